@@ -11,12 +11,13 @@ echo "============================================"
 
 echo "installing missing packages"
 if [ -f /etc/alpine-release ]; then
-    apk add autoconf automake libtool bison
+    apk add autoconf automake libtool bison openssl-dev openssl-libs-static
     git clone https://github.com/VirusTotal/yara.git
     cd yara.git
     git checkout v4.2.1
     ./bootstrap.sh
-    ./configure --enable-static
+    # pe, elf and math are built by default, hash requires crypto support
+    ./configure --enable-static --enable-dotnet --with-crypto
     make && make install
     cd ..
 
@@ -0,0 +1,56 @@
+/*
+ * C API of dionysos.
+ *
+ * Link against the dionysos library (libdionysos_lib.so, dionysos_lib.dll or
+ * libdionysos_lib.dylib), which is built by 'cargo build --release'.
+ *
+ * All functions which return an int return 0 on success and -1 on failure.
+ * dionysos_last_error() returns the reason of the last failure of the calling
+ * thread.
+ */
+#ifndef DIONYSOS_H
+#define DIONYSOS_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct DionysosJob dionysos_job;
+
+/* called for every finding with the name of the file and the finding as JSON.
+ * Both strings are only valid during the call */
+typedef void (*dionysos_finding_fn)(void *ctx, const char *file, const char *finding_json);
+
+/* message of the last error of the calling thread, or NULL */
+const char *dionysos_last_error(void);
+
+/* creates a job which scans all files below 'path', or returns NULL */
+dionysos_job *dionysos_job_new(const char *path);
+
+/* adds scanners to the job. This must be done before the job is run */
+int dionysos_job_add_yara(const dionysos_job *job, const char *ruleset);
+int dionysos_job_add_hash(const dionysos_job *job, const char *hash);
+int dionysos_job_add_hash_file(const dionysos_job *job, const char *hash_file);
+int dionysos_job_add_filename(const dionysos_job *job, const char *pattern);
+int dionysos_job_set_threads(const dionysos_job *job, size_t threads);
+
+/* includes the matched strings of yara rules in the findings, if print_strings is not 0 */
+int dionysos_job_set_print_strings(const dionysos_job *job, int print_strings);
+
+/* runs the job and blocks until it has finished or has been cancelled. A job can
+ * only be run once */
+int dionysos_job_run(const dionysos_job *job, dionysos_finding_fn callback, void *ctx);
+
+/* cancels a running job. This can be called from any thread */
+void dionysos_job_cancel(const dionysos_job *job);
+
+/* releases a job, which must not be running anymore */
+void dionysos_job_free(dionysos_job *job);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,28 @@
+/*
+ * Interface of dionysos scanner plugins.
+ *
+ * A plugin is a dynamic library (*.so, *.dll or *.dylib), which is loaded from
+ * the directory given with '--plugin-dir'. dionysos_plugin_scan_file() is
+ * called concurrently by all worker threads, so it must be thread-safe.
+ */
+#ifndef DIONYSOS_PLUGIN_H
+#define DIONYSOS_PLUGIN_H
+
+#include <stdint.h>
+
+#define DIONYSOS_PLUGIN_ABI_VERSION 1
+
+/* reports a finding. 'details' may be NULL. Both strings are copied by dionysos */
+typedef void (*dionysos_report_fn)(void *ctx, const char *rule, const char *details);
+
+/* must return DIONYSOS_PLUGIN_ABI_VERSION */
+uint32_t dionysos_plugin_abi_version(void);
+
+/* name of the plugin, which is displayed in all findings */
+const char *dionysos_plugin_name(void);
+
+/* scans the file at 'path', and calls 'report(ctx, ...)' for every finding.
+ * Returns 0 on success, or any other value if the file could not be scanned */
+int dionysos_plugin_scan_file(const char *path, dionysos_report_fn report, void *ctx);
+
+#endif
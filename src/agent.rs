@@ -0,0 +1,149 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
+
+use crate::controller::read_pem;
+use crate::dionysos::TlsOptions;
+use crate::grpc::proto::agent_report::Report;
+use crate::grpc::proto::controller_client::ControllerClient;
+use crate::grpc::proto::{AgentInfo, AgentReport, Finding, JobState, Progress, Task};
+use crate::scan_job::ScanJob;
+use crate::string_options::StringOptions;
+
+/// delay before the agent reconnects to the controller
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+fn client_tls(tls: &TlsOptions) -> Result<ClientTlsConfig> {
+    Ok(ClientTlsConfig::new()
+        .identity(Identity::from_pem(read_pem(&tls.cert)?, read_pem(&tls.key)?))
+        .ca_certificate(Certificate::from_pem(read_pem(&tls.ca)?)))
+}
+
+fn hostname() -> String {
+    hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "unknown".to_owned())
+}
+
+/// connects to the controller at `url` (e.g. `https://controller:7778`), and runs all
+/// tasks which it receives. The agent reconnects if the connection is lost, and
+/// runs until it is interrupted
+pub fn run(url: &str, tls: &TlsOptions) -> Result<()> {
+    let endpoint = Channel::from_shared(url.to_owned())
+        .map_err(|why| anyhow!("invalid controller URL '{}': {}", url, why))?
+        .tls_config(client_tls(tls)?)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        loop {
+            match endpoint.connect().await {
+                Ok(channel) => match work(channel).await {
+                    Ok(()) => log::info!("the controller has closed the connection"),
+                    Err(why) => log::error!("lost the connection to the controller: {}", why),
+                },
+                Err(why) => log::error!("unable to connect to '{}': {}", url, why),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    })
+}
+
+/// registers at the controller and runs its tasks, until the connection is closed
+async fn work(channel: Channel) -> Result<()> {
+    let mut client = ControllerClient::new(channel);
+    let hostname = hostname();
+    let mut tasks = client
+        .register(AgentInfo {
+            hostname: hostname.clone(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            os: std::env::consts::OS.to_owned(),
+        })
+        .await?
+        .into_inner();
+    log::info!("registered at the controller");
+
+    while let Some(task) = tasks.message().await? {
+        log::info!("received task {}", task.id);
+        let (tx, rx) = mpsc::channel(64);
+        let mut report_client = client.clone();
+        let report = report_client.report(ReceiverStream::new(rx));
+        let hostname = hostname.clone();
+        let scan = tokio::task::spawn_blocking(move || run_task(task, &hostname, tx));
+        let (report, scan) = tokio::join!(report, scan);
+        report?;
+        scan?;
+    }
+    Ok(())
+}
+
+/// scans all paths of the task, and sends its findings and summary to `tx`
+fn run_task(task: Task, hostname: &str, tx: mpsc::Sender<AgentReport>) {
+    let send = |report: Report| {
+        let _ = tx.blocking_send(AgentReport {
+            task_id: task.id,
+            hostname: hostname.to_owned(),
+            report: Some(report),
+        });
+    };
+
+    let mut progress = Progress {
+        state: JobState::Finished as i32,
+        files: 0,
+        findings: 0,
+        errors: 0,
+        error: None,
+    };
+    let workdir = std::env::temp_dir().join(format!("dionysos-task-{}", task.id));
+    let result = std::fs::create_dir_all(&workdir)
+        .map_err(anyhow::Error::from)
+        .and_then(|()| {
+            let options = StringOptions::default();
+            for path in task.paths.iter() {
+                let summary = scan_job(&task, path, &workdir)?.run(|result| {
+                    for finding in result.findings() {
+                        send(Report::Finding(Finding {
+                            file: result.filename().to_owned(),
+                            json: finding.to_json(&options).to_string(),
+                        }));
+                    }
+                })?;
+                progress.files += summary.files() as u64;
+                progress.findings += summary.findings() as u64;
+                progress.errors += summary.errors() as u64;
+            }
+            Ok(())
+        });
+    let _ = std::fs::remove_dir_all(&workdir);
+
+    if let Err(why) = result {
+        log::error!("task {} failed: {}", task.id, why);
+        progress.state = JobState::Failed as i32;
+        progress.error = Some(why.to_string());
+    } else {
+        log::info!("task {} has finished", task.id);
+    }
+    send(Report::Done(progress));
+}
+
+/// creates the scan job for `path`. The yara ruleset of the task is stored in `workdir`
+fn scan_job(task: &Task, path: &str, workdir: &Path) -> Result<ScanJob> {
+    let mut job = ScanJob::new(path);
+    if !task.yara.is_empty() {
+        let name = Path::new(&task.yara_name)
+            .file_name()
+            .ok_or_else(|| anyhow!("the yara ruleset has no name"))?;
+        let ruleset = workdir.join(name);
+        std::fs::write(&ruleset, &task.yara)?;
+        job = job.with_yara(&ruleset)?;
+    }
+    if !task.hashes.is_empty() {
+        job = job.with_hashes(&task.hashes)?;
+    }
+    if !task.filenames.is_empty() {
+        job = job.with_filenames(&task.filenames)?;
+    }
+    Ok(job)
+}
@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::scanner_result::ScannerResult;
+
+/// names of yara metadata which contain MITRE ATT&CK technique IDs
+pub(crate) const ATTACK_METADATA: &[&str] = &["attack", "mitre_attack", "mitre_technique"];
+
+/// parses a MITRE ATT&CK technique ID, like `T1055`, `T1055.012` or the tag
+/// `attack.t1055.012` of Sigma rules, and returns it in its canonical form
+pub(crate) fn parse_technique(value: &str) -> Option<String> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("attack.")
+        .or_else(|| value.strip_prefix("attack_"))
+        .unwrap_or(value);
+    let id = value
+        .strip_prefix('T')
+        .or_else(|| value.strip_prefix('t'))?;
+    let (technique, subtechnique) = match id.split_once(|c| c == '.' || c == '_') {
+        Some((technique, subtechnique)) => (technique, Some(subtechnique)),
+        None => (id, None),
+    };
+    let is_digits = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+    match subtechnique {
+        None if is_digits(technique, 4) => Some(format!("T{}", technique)),
+        Some(sub) if is_digits(technique, 4) && is_digits(sub, 3) => {
+            Some(format!("T{}.{}", technique, sub))
+        }
+        _ => None,
+    }
+}
+
+/// all technique IDs in `values`, which may contain multiple IDs separated by commas or
+/// whitespace, e.g. the value of an `attack` metadata of a yara rule
+pub(crate) fn parse_techniques<'a, I: IntoIterator<Item = &'a str>>(values: I) -> Vec<String> {
+    let mut techniques: Vec<String> = values
+        .into_iter()
+        .flat_map(|v| v.split(|c: char| c == ',' || c == ';' || c.is_whitespace()))
+        .filter_map(parse_technique)
+        .collect();
+    techniques.sort();
+    techniques.dedup();
+    techniques
+}
+
+/// the ATT&CK techniques of all findings of a scan, which are shown in the summary
+#[derive(Default)]
+pub(crate) struct AttackCoverage {
+    /// the number of findings and the files of every technique
+    techniques: BTreeMap<String, (usize, HashSet<String>)>,
+}
+
+impl AttackCoverage {
+    pub fn add(&mut self, result: &ScannerResult) {
+        for finding in result.findings() {
+            for technique in finding.attack_techniques() {
+                let (findings, files) = self.techniques.entry(technique).or_default();
+                *findings += 1;
+                files.insert(result.filename().to_owned());
+            }
+        }
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        if self.techniques.is_empty() {
+            return Vec::new();
+        }
+        let mut lines = vec![format!(
+            "ATT&CK coverage: {} technique(s)",
+            self.techniques.len()
+        )];
+        lines.extend(
+            self.techniques
+                .iter()
+                .map(|(technique, (findings, files))| {
+                    format!(
+                        "  {:<10} {} finding(s) in {} file(s)",
+                        technique,
+                        findings,
+                        files.len()
+                    )
+                }),
+        );
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_technique, parse_techniques};
+
+    #[test]
+    fn test_parse_technique() {
+        assert_eq!(parse_technique("T1055"), Some("T1055".to_owned()));
+        assert_eq!(
+            parse_technique("attack.t1055.012"),
+            Some("T1055.012".to_owned())
+        );
+        assert_eq!(
+            parse_technique("attack_t1036_005"),
+            Some("T1036.005".to_owned())
+        );
+        assert_eq!(parse_technique("attack.execution"), None);
+        assert_eq!(parse_technique("T105"), None);
+        assert_eq!(
+            parse_techniques(["T1059.001, T1027", "t1027"]),
+            vec!["T1027".to_owned(), "T1059.001".to_owned()]
+        );
+    }
+}
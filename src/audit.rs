@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde_json::{json, Value};
+use walkdir::WalkDir;
+
+use crate::file_hash::sha256_of;
+use crate::scanner_result::ScannerResult;
+
+/// options whose values must not appear in the manifest
+const SECRET_OPTIONS: &[&str] = &["--yara-zip-password"];
+
+/// an input of the scan (like the yara ruleset or a hash list), which is
+/// recorded with its SHA-256 hash
+struct AuditInput {
+    kind: &'static str,
+    path: PathBuf,
+    sha256: String,
+}
+
+/// writes an audit manifest of a scan run, which documents how the results have
+/// been produced. The manifest consists of JSON lines:
+///
+///  - a `start` record with the version of dionysos, the start time, the host,
+///    the command line and the hashes of all inputs (rules, hash lists, IoCs)
+///  - a `file` record for every file, which has been scanned, skipped or which
+///    could not be scanned completely, together with the reason
+///  - an `end` record with the end time, some statistics and the exit code
+///
+/// After the scan, the SHA-256 hash of the manifest is written to `<manifest>.sha256`,
+/// which can be signed to protect the manifest against modifications.
+pub struct AuditManifest {
+    destination: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+    inputs: Vec<AuditInput>,
+    scanned_files: AtomicUsize,
+    skipped_files: AtomicUsize,
+    failed_files: AtomicUsize,
+}
+
+impl AuditManifest {
+    pub fn new<P: AsRef<Path>>(destination: P) -> Result<Self> {
+        let destination = destination.as_ref().to_owned();
+        let writer = File::create(&destination).map_err(|why| {
+            anyhow!("unable to create the audit manifest '{}': {}", destination.display(), why)
+        })?;
+        Ok(Self {
+            destination,
+            writer: Mutex::new(BufWriter::new(writer)),
+            inputs: Vec::new(),
+            scanned_files: AtomicUsize::new(0),
+            skipped_files: AtomicUsize::new(0),
+            failed_files: AtomicUsize::new(0),
+        })
+    }
+
+    /// records the hash of an input file. If `path` is a directory, every file
+    /// in this directory is recorded
+    pub fn with_input<P: AsRef<Path>>(mut self, kind: &'static str, path: P) -> Result<Self> {
+        let mut files: Vec<PathBuf> = WalkDir::new(path.as_ref())
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .collect();
+        files.sort();
+        for path in files {
+            let sha256 = sha256_of(&path)
+                .map_err(|why| anyhow!("unable to hash '{}': {}", path.display(), why))?;
+            self.inputs.push(AuditInput { kind, path, sha256 });
+        }
+        Ok(self)
+    }
+
+    /// writes the `start` record
+    pub fn start(self) -> Result<Self> {
+        let inputs: Vec<Value> = self
+            .inputs
+            .iter()
+            .map(|input| {
+                json!({
+                    "type": input.kind,
+                    "path": input.path.display().to_string(),
+                    "sha256": input.sha256
+                })
+            })
+            .collect();
+        self.write_record(json!({
+            "record": "start",
+            "version": env!("CARGO_PKG_VERSION"),
+            "started": Utc::now().to_rfc3339(),
+            "host": hostname::get().map(|h| h.to_string_lossy().to_string()).ok(),
+            "user": std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok(),
+            "os": std::env::consts::OS,
+            "command_line": command_line(),
+            "inputs": inputs
+        }))?;
+        Ok(self)
+    }
+
+    /// records the outcome of the scan of a file
+    pub fn record_file(&self, result: &ScannerResult) {
+        let record = if let Some(reason) = result.skip_reason() {
+            self.skipped_files.fetch_add(1, Ordering::Relaxed);
+            json!({"record": "file", "path": result.filename(), "status": "skipped", "reason": reason})
+        } else if result.has_errors() {
+            self.failed_files.fetch_add(1, Ordering::Relaxed);
+            json!({
+                "record": "file",
+                "path": result.filename(),
+                "status": "failed",
+                "reason": result.errors().join("; "),
+                "findings": result.findings().count()
+            })
+        } else {
+            self.scanned_files.fetch_add(1, Ordering::Relaxed);
+            json!({
+                "record": "file",
+                "path": result.filename(),
+                "status": "scanned",
+                "findings": result.findings().count()
+            })
+        };
+        self.log_error(self.write_record(record));
+    }
+
+    /// records a file which has not been scanned at all
+    pub fn record_skipped(&self, path: &str, reason: &str) {
+        self.skipped_files.fetch_add(1, Ordering::Relaxed);
+        self.log_error(self.write_record(json!({
+            "record": "file",
+            "path": path,
+            "status": "skipped",
+            "reason": reason
+        })));
+    }
+
+    /// writes the `end` record and the hash of the manifest
+    pub fn finish(&self, findings: usize, errors: usize, exit_code: i32) -> Result<()> {
+        self.write_record(json!({
+            "record": "end",
+            "finished": Utc::now().to_rfc3339(),
+            "files_scanned": self.scanned_files.load(Ordering::Relaxed),
+            "files_skipped": self.skipped_files.load(Ordering::Relaxed),
+            "files_failed": self.failed_files.load(Ordering::Relaxed),
+            "findings": findings,
+            "errors": errors,
+            "exit_code": exit_code
+        }))?;
+        self.writer.lock().unwrap().flush()?;
+
+        let sha256 = sha256_of(&self.destination)?;
+        let mut checksum_file = self.destination.clone().into_os_string();
+        checksum_file.push(".sha256");
+        let filename = self
+            .destination
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        std::fs::write(&checksum_file, format!("{}  {}\n", sha256, filename))?;
+        log::info!(
+            "wrote the audit manifest to '{}' (SHA-256: {})",
+            self.destination.display(),
+            sha256
+        );
+        Ok(())
+    }
+
+    fn write_record(&self, record: Value) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        serde_json::to_writer(&mut *writer, &record)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    fn log_error(&self, result: Result<()>) {
+        if let Err(why) = result {
+            log::error!(
+                "unable to write to the audit manifest '{}': {}",
+                self.destination.display(),
+                why
+            );
+        }
+    }
+}
+
+/// the command line of this process, without the values of secret options
+fn command_line() -> Vec<String> {
+    let mut args = Vec::new();
+    let mut hide_next = false;
+    for arg in std::env::args_os().map(|a| a.to_string_lossy().to_string()) {
+        if hide_next {
+            args.push("***".to_owned());
+            hide_next = false;
+        } else if let Some(option) = SECRET_OPTIONS.iter().find(|o| arg.starts_with(*o)) {
+            if arg.len() == option.len() {
+                hide_next = true;
+                args.push(arg);
+            } else {
+                args.push(format!("{}=***", option));
+            }
+        } else {
+            args.push(arg);
+        }
+    }
+    args
+}
@@ -0,0 +1,454 @@
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+
+use crate::pe_file::PeFile;
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const BMP_STRING: u8 = 0x1e;
+const CONTEXT_0: u8 = 0xa0;
+
+/// 1.2.840.113549.1.7.2
+const OID_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+
+/// 1.3.6.1.4.1.311.2.1.4, the content of Authenticode signatures
+const OID_SPC_INDIRECT_DATA: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x01, 0x04];
+
+/// 1.3.6.1.4.1.311.10.1, the content of catalog files
+const OID_CERTIFICATE_TRUST_LIST: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x0a, 0x01];
+
+/// 2.5.4.3
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+
+const DIGEST_ALGORITHMS: &[(&[u8], DigestAlgorithm)] = &[
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x05],
+        DigestAlgorithm::Md5,
+    ),
+    (&[0x2b, 0x0e, 0x03, 0x02, 0x1a], DigestAlgorithm::Sha1),
+    (
+        &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01],
+        DigestAlgorithm::Sha256,
+    ),
+    (
+        &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02],
+        DigestAlgorithm::Sha384,
+    ),
+    (
+        &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03],
+        DigestAlgorithm::Sha512,
+    ),
+];
+
+/// a minimal DER reader, which supports just what is needed to read Authenticode
+/// signatures and the signers of catalog files
+struct Der<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// reads the next element, and returns its tag and its content
+    fn next(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.data.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (length, rest) = if first & 0x80 == 0 {
+            (first as usize, rest)
+        } else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return None;
+            }
+            let length = rest[..count]
+                .iter()
+                .fold(0, |length, byte| length << 8 | *byte as usize);
+            (length, &rest[count..])
+        };
+        let content = rest.get(..length)?;
+        self.data = &rest[length..];
+        Some((tag, content))
+    }
+
+    /// reads the next element, which must have the tag `tag`
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.next()? {
+            (found, content) if found == tag => Some(content),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum DigestAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    fn from_oid(oid: &[u8]) -> Option<Self> {
+        DIGEST_ALGORITHMS
+            .iter()
+            .find(|(known, _)| *known == oid)
+            .map(|(_, algorithm)| *algorithm)
+    }
+
+    /// the Authenticode hash of `pe`, using this algorithm
+    pub fn digest_of(&self, pe: &PeFile) -> Option<Vec<u8>> {
+        match self {
+            Self::Md5 => pe.authenticode_digest::<Md5>(),
+            Self::Sha1 => pe.authenticode_digest::<Sha1>(),
+            Self::Sha256 => pe.authenticode_digest::<Sha256>(),
+            Self::Sha384 => pe.authenticode_digest::<Sha384>(),
+            Self::Sha512 => pe.authenticode_digest::<Sha512>(),
+        }
+    }
+}
+
+/// the PKCS#7 signed data of an Authenticode signature or of a catalog file. The
+/// signature itself is not verified, this requires the certificate store of Windows
+pub(crate) struct SignedData<'a> {
+    content_type: &'a [u8],
+    content: &'a [u8],
+    certificates: &'a [u8],
+
+    /// the serial number of the certificate of the (first) signer
+    signer_serial: Option<&'a [u8]>,
+}
+
+impl<'a> SignedData<'a> {
+    /// parses a DER encoded `ContentInfo`, which must contain `SignedData`. Returns
+    /// `None` if `data` is malformed
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let mut content_info = Der::new(Der::new(data).expect(SEQUENCE)?);
+        if content_info.expect(OBJECT_IDENTIFIER)? != OID_SIGNED_DATA {
+            return None;
+        }
+        let mut signed_data = Der::new(Der::new(content_info.expect(CONTEXT_0)?).expect(SEQUENCE)?);
+        signed_data.expect(INTEGER)?;
+        signed_data.expect(SET)?;
+
+        let mut encapsulated = Der::new(signed_data.expect(SEQUENCE)?);
+        let content_type = encapsulated.expect(OBJECT_IDENTIFIER)?;
+        let content = encapsulated.expect(CONTEXT_0).unwrap_or_default();
+
+        let mut certificates = &[][..];
+        let mut signer_infos = &[][..];
+        while let Some((tag, value)) = signed_data.next() {
+            match tag {
+                CONTEXT_0 => certificates = value,
+                SET => signer_infos = value,
+                _ => (),
+            }
+        }
+
+        let signer_serial = (|| {
+            let mut signer_info = Der::new(Der::new(signer_infos).expect(SEQUENCE)?);
+            signer_info.expect(INTEGER)?;
+            let mut issuer_and_serial = Der::new(signer_info.expect(SEQUENCE)?);
+            issuer_and_serial.expect(SEQUENCE)?;
+            issuer_and_serial.expect(INTEGER)
+        })();
+
+        Some(Self {
+            content_type,
+            content,
+            certificates,
+            signer_serial,
+        })
+    }
+
+    /// the digest algorithm and the Authenticode hash of the signed file, if this is
+    /// an Authenticode signature
+    pub fn file_digest(&self) -> Option<(DigestAlgorithm, &'a [u8])> {
+        if self.content_type != OID_SPC_INDIRECT_DATA {
+            return None;
+        }
+        indirect_data_digest(self.content)
+    }
+
+    /// the Authenticode hashes of the files in a catalog file, which are stored as
+    /// `SpcIndirectData` attributes of the subjects of its certificate trust list
+    pub fn catalog_members(&self) -> Vec<&'a [u8]> {
+        if self.content_type != OID_CERTIFICATE_TRUST_LIST {
+            return Vec::new();
+        }
+        // the subjects are the third sequence, after the subject usage and the
+        // subject algorithm
+        let subjects = Der::new(self.content)
+            .expect(SEQUENCE)
+            .and_then(|list| {
+                let mut list = Der::new(list);
+                std::iter::from_fn(|| list.next())
+                    .filter(|(tag, _)| *tag == SEQUENCE)
+                    .nth(2)
+            })
+            .map_or(&[][..], |(_, subjects)| subjects);
+
+        let mut members = Vec::new();
+        let mut subjects = Der::new(subjects);
+        while let Some((SEQUENCE, subject)) = subjects.next() {
+            let mut subject = Der::new(subject);
+            let attributes = subject
+                .expect(OCTET_STRING)
+                .and_then(|_| subject.expect(SET))
+                .unwrap_or_default();
+            let mut attributes = Der::new(attributes);
+            while let Some((SEQUENCE, attribute)) = attributes.next() {
+                let mut attribute = Der::new(attribute);
+                if attribute.expect(OBJECT_IDENTIFIER) != Some(OID_SPC_INDIRECT_DATA) {
+                    continue;
+                }
+                if let Some((_, digest)) = attribute.expect(SET).and_then(indirect_data_digest) {
+                    members.push(digest);
+                }
+            }
+        }
+        members
+    }
+
+    /// the common name of the signer, taken from the subject of its certificate
+    pub fn signer_name(&self) -> Option<String> {
+        let serial = self.signer_serial?;
+        let mut certificates = Der::new(self.certificates);
+        while let Some((SEQUENCE, certificate)) = certificates.next() {
+            let mut tbs_certificate = Der::new(Der::new(certificate).expect(SEQUENCE)?);
+            let mut field = tbs_certificate.next()?;
+            if field.0 == CONTEXT_0 {
+                field = tbs_certificate.next()?;
+            }
+            if field != (INTEGER, serial) {
+                continue;
+            }
+            tbs_certificate.expect(SEQUENCE)?;
+            tbs_certificate.expect(SEQUENCE)?;
+            tbs_certificate.expect(SEQUENCE)?;
+            return common_name(tbs_certificate.expect(SEQUENCE)?);
+        }
+        None
+    }
+}
+
+/// the digest algorithm and the digest of a DER encoded `SpcIndirectDataContent`
+fn indirect_data_digest(data: &[u8]) -> Option<(DigestAlgorithm, &[u8])> {
+    let mut indirect_data = Der::new(Der::new(data).expect(SEQUENCE)?);
+    indirect_data.expect(SEQUENCE)?;
+    let mut digest_info = Der::new(indirect_data.expect(SEQUENCE)?);
+    let mut algorithm = Der::new(digest_info.expect(SEQUENCE)?);
+    let algorithm = DigestAlgorithm::from_oid(algorithm.expect(OBJECT_IDENTIFIER)?)?;
+    Some((algorithm, digest_info.expect(OCTET_STRING)?))
+}
+
+/// the first common name in a distinguished name
+fn common_name(name: &[u8]) -> Option<String> {
+    let mut relative_names = Der::new(name);
+    while let Some((SET, relative_name)) = relative_names.next() {
+        let mut attributes = Der::new(relative_name);
+        while let Some((SEQUENCE, attribute)) = attributes.next() {
+            let mut attribute = Der::new(attribute);
+            if attribute.expect(OBJECT_IDENTIFIER)? != OID_COMMON_NAME {
+                continue;
+            }
+            return match attribute.next()? {
+                (BMP_STRING, value) => Some(String::from_utf16_lossy(
+                    &value
+                        .chunks_exact(2)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect::<Vec<_>>(),
+                )),
+                (_, value) => Some(String::from_utf8_lossy(value).to_string()),
+            };
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DigestAlgorithm, SignedData, OID_CERTIFICATE_TRUST_LIST, OID_SIGNED_DATA,
+        OID_SPC_INDIRECT_DATA,
+    };
+
+    fn der(tag: u8, parts: &[&[u8]]) -> Vec<u8> {
+        let content = parts.concat();
+        let mut element = vec![tag];
+        if content.len() < 0x80 {
+            element.push(content.len() as u8);
+        } else {
+            element.push(0x82);
+            element.extend_from_slice(&(content.len() as u16).to_be_bytes());
+        }
+        element.extend(content);
+        element
+    }
+
+    #[test]
+    fn test_signed_data() {
+        let serial = der(0x02, &[&[0x13, 0x37]]);
+        let sha256 = der(
+            0x30,
+            &[&der(
+                0x06,
+                &[&[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]],
+            )],
+        );
+        let name = |common_name: &str| {
+            der(
+                0x30,
+                &[&der(
+                    0x31,
+                    &[&der(
+                        0x30,
+                        &[
+                            &der(0x06, &[&[0x55, 0x04, 0x03]]),
+                            &der(0x0c, &[common_name.as_bytes()]),
+                        ],
+                    )],
+                )],
+            )
+        };
+        let certificate = |serial: &[u8], subject: &str| {
+            der(
+                0x30,
+                &[&der(
+                    0x30,
+                    &[
+                        &der(0xa0, &[&der(0x02, &[&[2]])]),
+                        serial,
+                        &sha256,
+                        &name("Issuing CA"),
+                        &der(0x30, &[]),
+                        &name(subject),
+                    ],
+                )],
+            )
+        };
+        let indirect_data = der(
+            0x30,
+            &[
+                &der(0x30, &[]),
+                &der(0x30, &[&sha256, &der(0x04, &[&[0xab; 32]])]),
+            ],
+        );
+        let signer_info = der(
+            0x30,
+            &[
+                &der(0x02, &[&[1]]),
+                &der(0x30, &[&name("Issuing CA"), &serial]),
+            ],
+        );
+        let signed_data = der(
+            0x30,
+            &[
+                &der(0x06, &[OID_SIGNED_DATA]),
+                &der(
+                    0xa0,
+                    &[&der(
+                        0x30,
+                        &[
+                            &der(0x02, &[&[1]]),
+                            &der(0x31, &[&sha256]),
+                            &der(
+                                0x30,
+                                &[
+                                    &der(0x06, &[OID_SPC_INDIRECT_DATA]),
+                                    &der(0xa0, &[&indirect_data]),
+                                ],
+                            ),
+                            &der(
+                                0xa0,
+                                &[
+                                    &certificate(&der(0x02, &[&[0x42]]), "Issuing CA"),
+                                    &certificate(&serial, "Contoso Ltd"),
+                                ],
+                            ),
+                            &der(0x31, &[&signer_info]),
+                        ],
+                    )],
+                ),
+            ],
+        );
+
+        let signed_data = SignedData::parse(&signed_data).unwrap();
+        assert_eq!(signed_data.signer_name().as_deref(), Some("Contoso Ltd"));
+        assert_eq!(
+            signed_data.file_digest(),
+            Some((DigestAlgorithm::Sha256, &[0xab; 32][..]))
+        );
+        assert!(SignedData::parse(&[0x30, 0x05, 0x06]).is_none());
+    }
+
+    #[test]
+    fn test_catalog_members() {
+        let sha1 = der(0x30, &[&der(0x06, &[&[0x2b, 0x0e, 0x03, 0x02, 0x1a]])]);
+        let member = |digest: &[u8]| {
+            let indirect_data = der(
+                0x30,
+                &[&der(0x30, &[]), &der(0x30, &[&sha1, &der(0x04, &[digest])])],
+            );
+            der(
+                0x30,
+                &[
+                    &der(0x04, &[b"tag"]),
+                    &der(
+                        0x31,
+                        &[&der(
+                            0x30,
+                            &[
+                                &der(0x06, &[OID_SPC_INDIRECT_DATA]),
+                                &der(0x31, &[&indirect_data]),
+                            ],
+                        )],
+                    ),
+                ],
+            )
+        };
+        let trust_list = der(
+            0x30,
+            &[
+                &der(0x30, &[&der(0x06, &[&[0x2b, 0x06, 0x01]])]),
+                &der(0x04, &[&[0x01]]),
+                &der(0x17, &[b"230101000000Z"]),
+                &sha1,
+                &der(0x30, &[&member(&[0x11; 20]), &member(&[0x22; 20])]),
+            ],
+        );
+        let catalog = der(
+            0x30,
+            &[
+                &der(0x06, &[OID_SIGNED_DATA]),
+                &der(
+                    0xa0,
+                    &[&der(
+                        0x30,
+                        &[
+                            &der(0x02, &[&[1]]),
+                            &der(0x31, &[&sha1]),
+                            &der(
+                                0x30,
+                                &[
+                                    &der(0x06, &[OID_CERTIFICATE_TRUST_LIST]),
+                                    &der(0xa0, &[&trust_list]),
+                                ],
+                            ),
+                        ],
+                    )],
+                ),
+            ],
+        );
+
+        let catalog = SignedData::parse(&catalog).unwrap();
+        assert_eq!(catalog.catalog_members(), vec![&[0x11; 20][..], &[0x22; 20][..]]);
+        assert!(catalog.file_digest().is_none());
+    }
+}
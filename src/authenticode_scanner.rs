@@ -0,0 +1,699 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use memmap::MmapOptions;
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::authenticode::{DigestAlgorithm, SignedData};
+use crate::csv_line::CsvLine;
+use crate::directory_cache::DirectoryCache;
+use crate::filescanner::FileScanner;
+use crate::path_string::path_to_string;
+use crate::pe_file::PeFile;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+use crate::system_location::{locate, Location};
+
+/// only Windows can detect revoked, expired and untrusted certificates
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum State {
+    /// the file has no embedded signature, and is not contained in a catalog
+    Unsigned,
+
+    /// the signature is valid
+    Signed,
+
+    /// the signature matches the file, but its certificates could not be verified, e.g.
+    /// on other platforms than Windows, or if their revocation could not be checked
+    Unverified(String),
+
+    /// the file has been modified after it has been signed, or the signature is broken
+    Invalid(String),
+
+    /// the certificate of the signer, or of a CA, has been revoked
+    Revoked,
+
+    /// the certificate of the signer has expired, and the signature has no timestamp
+    Expired,
+
+    /// the certificate chain does not end at a trusted root, or has been distrusted
+    Untrusted,
+}
+
+impl State {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Unsigned => "unsigned",
+            Self::Signed => "signed",
+            Self::Unverified(_) => "unverified",
+            Self::Invalid(_) => "invalid",
+            Self::Revoked => "revoked",
+            Self::Expired => "expired",
+            Self::Untrusted => "untrusted",
+        }
+    }
+}
+
+struct Signature {
+    state: State,
+    signer: Option<String>,
+
+    /// the catalog file which contains the hash of the file, if it is not signed itself
+    catalog: Option<String>,
+}
+
+impl Signature {
+    fn new(state: State, signer: Option<String>) -> Self {
+        Self {
+            state,
+            signer,
+            catalog: None,
+        }
+    }
+}
+
+/// checks the embedded signature of `pe` by comparing the signed digest with the
+/// Authenticode hash of the file. The certificates are not verified, so that a matching
+/// signature is `State::Unverified`
+fn check_embedded(pe: &PeFile) -> Signature {
+    let signature = match pe.signature() {
+        Some(signature) => signature,
+        None => return Signature::new(State::Unsigned, None),
+    };
+    let signed_data = match SignedData::parse(signature) {
+        Some(signed_data) => signed_data,
+        None => return Signature::new(State::Invalid("malformed signature".to_owned()), None),
+    };
+    let state = match signed_data.file_digest() {
+        Some((algorithm, digest)) if algorithm.digest_of(pe).as_deref() == Some(digest) => {
+            State::Unverified("the certificates have not been verified".to_owned())
+        }
+        Some(_) => State::Invalid("the file has been modified after it has been signed".to_owned()),
+        None => State::Invalid("the signature contains no digest of the file".to_owned()),
+    };
+    Signature::new(state, signed_data.signer_name())
+}
+
+/// verifies the certificates of the embedded signature
+#[cfg(windows)]
+fn verify(path: &Path, embedded: Signature) -> Signature {
+    match embedded.state {
+        State::Unverified(_) => Signature {
+            state: wintrust::verify_file(path),
+            ..embedded
+        },
+        _ => embedded,
+    }
+}
+
+/// returns a finding for all signatures which are not valid. Unsigned files, and files
+/// whose embedded signature could not be verified, are only reported if
+/// `report_unsigned` is set
+fn finding(
+    found_in_file: String,
+    signature: Signature,
+    report_unsigned: bool,
+) -> Option<AuthenticodeFinding> {
+    match signature.state {
+        State::Signed => None,
+        State::Unsigned if !report_unsigned => None,
+        State::Unverified(_) if !report_unsigned || signature.catalog.is_some() => None,
+        _ => Some(AuthenticodeFinding {
+            found_in_file,
+            signature,
+        }),
+    }
+}
+
+/// the Authenticode hashes of the files in the catalogs of a Windows installation
+#[derive(Default)]
+struct Catalogs {
+    /// the paths of the catalogs, with their signers
+    catalogs: Vec<(String, Option<String>)>,
+
+    /// the hashes of the files, with the index of the catalog which contains them
+    members: HashMap<Vec<u8>, usize>,
+}
+
+impl Catalogs {
+    /// reads the catalogs of the Windows installation on the filesystem `root`, which
+    /// are stored in `Windows\System32\CatRoot\{GUID}\*.cat`
+    fn load(root: &Path) -> Self {
+        let mut catalogs = Self::default();
+        let catroot = root.join("Windows").join("System32").join("CatRoot");
+        let entries = |directory: &Path| {
+            std::fs::read_dir(directory)
+                .into_iter()
+                .flatten()
+                .flatten()
+        };
+        for directory in entries(&catroot) {
+            for catalog in entries(&directory.path()) {
+                let path = catalog.path();
+                if path
+                    .extension()
+                    .map_or(true, |extension| !extension.eq_ignore_ascii_case("cat"))
+                {
+                    continue;
+                }
+                match std::fs::read(&path) {
+                    Ok(data) => catalogs.add(&path, &data),
+                    Err(why) => log::warn!("unable to read catalog '{}': {}", path.display(), why),
+                }
+            }
+        }
+        log::info!(
+            "read {} files in {} catalogs from '{}'",
+            catalogs.members.len(),
+            catalogs.catalogs.len(),
+            catroot.display()
+        );
+        catalogs
+    }
+
+    fn add(&mut self, path: &Path, data: &[u8]) {
+        let catalog = match SignedData::parse(data) {
+            Some(catalog) => catalog,
+            None => {
+                log::warn!("'{}' is no valid catalog", path.display());
+                return;
+            }
+        };
+        let index = self.catalogs.len();
+        self.catalogs
+            .push((path_to_string(path), catalog.signer_name()));
+        for member in catalog.catalog_members() {
+            self.members.insert(member.to_vec(), index);
+        }
+    }
+
+    /// the catalog which contains `pe`, and its signer. Neither the signature of the
+    /// catalog nor its certificates are verified
+    fn find(&self, pe: &PeFile) -> Option<Signature> {
+        let index = [DigestAlgorithm::Sha256, DigestAlgorithm::Sha1]
+            .iter()
+            .filter_map(|algorithm| algorithm.digest_of(pe))
+            .find_map(|digest| self.members.get(&digest))?;
+        let (catalog, signer) = self.catalogs[*index].clone();
+        Some(Signature {
+            state: State::Unverified("the catalog has not been verified".to_owned()),
+            signer,
+            catalog: Some(catalog),
+        })
+    }
+}
+
+/// checks the Authenticode signatures of PE files, and reports files with invalid,
+/// revoked, expired or untrusted signatures. Unsigned files are reported if they are
+/// stored in a directory with programs of Windows (like `C:\Windows\System32` or
+/// `C:\Program Files`), and are not contained in a catalog of the Windows installation
+/// which they belong to. This also works for mounted images.
+///
+/// Only Windows can verify certificates, and only the catalogs of the running system.
+/// Otherwise, the scanner only checks if the embedded signature or a catalog matches the
+/// file, and reports such files in directories with programs of Windows as unverified
+#[derive(Default)]
+pub struct AuthenticodeScanner {
+    /// the root of the filesystem of directories with programs of Windows, or `None`
+    /// for other directories
+    checked_directories: DirectoryCache<Option<PathBuf>>,
+
+    /// the catalogs of the Windows installations, by the root of their filesystems
+    catalogs: Mutex<HashMap<PathBuf, Arc<Catalogs>>>,
+}
+
+impl AuthenticodeScanner {
+    /// the root of the filesystem, if `directory` contains programs of Windows
+    fn system_root_of(&self, directory: &Path) -> Option<PathBuf> {
+        self.checked_directories
+            .get_or_insert_with(directory, || match locate(directory) {
+                (Location::WindowsSystem, root) => root.map(Path::to_path_buf),
+                _ => None,
+            })
+            .0
+    }
+
+    /// searches the unsigned file `pe` in the catalogs of the Windows installation on
+    /// the filesystem `root`. The catalogs of the running system are verified on
+    /// Windows, those of other installations (e.g. of a mounted image) are read from
+    /// the filesystem
+    #[cfg_attr(not(windows), allow(unused_variables))]
+    fn find_in_catalogs(&self, path: &Path, pe: &PeFile, root: &Path) -> Option<Signature> {
+        #[cfg(windows)]
+        if wintrust::is_running_system(root) {
+            return wintrust::verify_catalog(path);
+        }
+        let catalogs = Arc::clone(
+            self.catalogs
+                .lock()
+                .unwrap()
+                .entry(root.to_path_buf())
+                .or_insert_with(|| Arc::new(Catalogs::load(root))),
+        );
+        catalogs.find(pe)
+    }
+
+    fn check_file(&self, path: &Path) -> Result<Option<AuthenticodeFinding>> {
+        let file = File::open(path)?;
+        let mut magic = [0; 2];
+        if (&file).read_exact(&mut magic).is_err() || &magic != b"MZ" {
+            return Ok(None);
+        }
+        let data = unsafe { MmapOptions::new().map(&file)? };
+        let pe = match PeFile::parse(&data) {
+            Ok(Some(pe)) => pe,
+            Ok(None) => return Ok(None),
+            Err(why) => {
+                log::debug!("unable to parse '{}' as PE file: {}", path.display(), why);
+                return Ok(None);
+            }
+        };
+
+        let signature = check_embedded(&pe);
+        #[cfg(windows)]
+        let signature = verify(path, signature);
+
+        let root = path
+            .parent()
+            .and_then(|directory| self.system_root_of(directory));
+        let signature = match (&signature.state, &root) {
+            (State::Unsigned, Some(root)) => self
+                .find_in_catalogs(path, &pe, root)
+                .unwrap_or(signature),
+            _ => signature,
+        };
+        Ok(finding(path_to_string(path), signature, root.is_some()))
+    }
+}
+
+impl Display for AuthenticodeScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "AuthenticodeScanner")
+    }
+}
+
+impl FileScanner for AuthenticodeScanner {
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match self.check_file(entry.path()) {
+            Ok(None) => vec![],
+            Ok(Some(finding)) => vec![Ok(Box::new(finding) as Box<dyn ScannerFinding>)],
+            Err(why) => vec![Err(anyhow!(
+                "unable to check the signature of '{}': {}",
+                entry.path().display(),
+                why
+            ))],
+        }
+    }
+
+    /// files in archives cannot be found in catalogs, so only their embedded
+    /// signatures are checked, and unsigned or unverified files are not reported
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match PeFile::parse(data) {
+            Ok(Some(pe)) => finding(name.to_owned(), check_embedded(&pe), false)
+                .map(|finding| Ok(Box::new(finding) as Box<dyn ScannerFinding>))
+                .into_iter()
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// unsigned files are only reported in system directories
+    fn depends_on_path(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(windows)]
+mod wintrust {
+    use std::ffi::{c_void, OsStr};
+    use std::fs::File;
+    use std::mem::size_of;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::path::{Path, PathBuf};
+
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::{
+        CERT_E_CHAINING, CERT_E_EXPIRED, CERT_E_REVOCATION_FAILURE, CERT_E_REVOKED,
+        CERT_E_UNTRUSTEDROOT, CRYPT_E_REVOCATION_OFFLINE, HANDLE, HWND, TRUST_E_BAD_DIGEST,
+        TRUST_E_EXPLICIT_DISTRUST, TRUST_E_NOSIGNATURE,
+    };
+    use windows::Win32::Security::Cryptography::Catalog::{
+        CryptCATAdminAcquireContext2, CryptCATAdminCalcHashFromFileHandle2,
+        CryptCATAdminEnumCatalogFromHash, CryptCATAdminReleaseCatalogContext,
+        CryptCATAdminReleaseContext, CryptCATCatalogInfoFromContext, CATALOG_INFO,
+    };
+    use windows::Win32::Security::WinTrust::{
+        WinVerifyTrust, WINTRUST_ACTION_GENERIC_VERIFY_V2, WINTRUST_CATALOG_INFO, WINTRUST_DATA,
+        WINTRUST_DATA_0, WINTRUST_FILE_INFO, WTD_CACHE_ONLY_URL_RETRIEVAL, WTD_CHOICE_CATALOG,
+        WTD_CHOICE_FILE, WTD_REVOKE_WHOLECHAIN, WTD_STATEACTION_CLOSE, WTD_STATEACTION_VERIFY,
+        WTD_UI_NONE,
+    };
+
+    use super::{Signature, State};
+    use crate::authenticode::{DigestAlgorithm, SignedData};
+
+    fn wide(value: &OsStr) -> Vec<u16> {
+        value.encode_wide().chain(Some(0)).collect()
+    }
+
+    fn state_of(status: i32) -> State {
+        if status == 0 {
+            State::Signed
+        } else if status == TRUST_E_NOSIGNATURE.0 {
+            State::Unsigned
+        } else if status == CERT_E_REVOKED.0 {
+            State::Revoked
+        } else if status == CERT_E_EXPIRED.0 {
+            State::Expired
+        } else if [
+            CERT_E_UNTRUSTEDROOT,
+            CERT_E_CHAINING,
+            TRUST_E_EXPLICIT_DISTRUST,
+        ]
+        .iter()
+        .any(|error| error.0 == status)
+        {
+            State::Untrusted
+        } else if [CRYPT_E_REVOCATION_OFFLINE, CERT_E_REVOCATION_FAILURE]
+            .iter()
+            .any(|error| error.0 == status)
+        {
+            // revocation lists are only read from the cache, which is often empty
+            log::debug!("unable to check the revocation of a certificate");
+            State::Unverified("the revocation of the certificates could not be checked".to_owned())
+        } else if status == TRUST_E_BAD_DIGEST.0 {
+            State::Invalid("the file has been modified after it has been signed".to_owned())
+        } else {
+            State::Invalid(format!("verification failed with error 0x{:08x}", status))
+        }
+    }
+
+    /// runs WinVerifyTrust, without any user interaction or network access
+    fn win_verify_trust(data: &mut WINTRUST_DATA) -> State {
+        let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+        data.cbStruct = size_of::<WINTRUST_DATA>() as u32;
+        data.dwUIChoice = WTD_UI_NONE;
+        data.fdwRevocationChecks = WTD_REVOKE_WHOLECHAIN;
+        data.dwProvFlags = WTD_CACHE_ONLY_URL_RETRIEVAL;
+        data.dwStateAction = WTD_STATEACTION_VERIFY;
+        let status = unsafe {
+            WinVerifyTrust(
+                HWND(0),
+                &mut action,
+                data as *mut WINTRUST_DATA as *mut c_void,
+            )
+        };
+        data.dwStateAction = WTD_STATEACTION_CLOSE;
+        unsafe {
+            WinVerifyTrust(
+                HWND(0),
+                &mut action,
+                data as *mut WINTRUST_DATA as *mut c_void,
+            )
+        };
+        state_of(status)
+    }
+
+    pub(super) fn verify_file(path: &Path) -> State {
+        let name = wide(path.as_os_str());
+        let mut file_info = WINTRUST_FILE_INFO {
+            cbStruct: size_of::<WINTRUST_FILE_INFO>() as u32,
+            pcwszFilePath: PCWSTR(name.as_ptr()),
+            ..Default::default()
+        };
+        let mut data = WINTRUST_DATA {
+            dwUnionChoice: WTD_CHOICE_FILE,
+            Anonymous: WINTRUST_DATA_0 {
+                pFile: &mut file_info,
+            },
+            ..Default::default()
+        };
+        win_verify_trust(&mut data)
+    }
+
+    /// checks if `root` is the root of the filesystem of the running Windows, whose
+    /// catalogs are known to the system
+    pub(super) fn is_running_system(root: &Path) -> bool {
+        let system_root = match std::env::var_os("SystemRoot") {
+            Some(system_root) => PathBuf::from(system_root),
+            None => return false,
+        };
+        match (system_root.parent(), root.canonicalize()) {
+            (Some(system_drive), Ok(root)) => system_drive
+                .canonicalize()
+                .map_or(false, |system_drive| system_drive == root),
+            _ => false,
+        }
+    }
+
+    /// searches the hash of `path` in the catalogs of the system, and verifies the
+    /// catalog which contains it. Returns `None` if no catalog contains the file
+    pub(super) fn verify_catalog(path: &Path) -> Option<Signature> {
+        let file = File::open(path).ok()?;
+        let handle = HANDLE(file.as_raw_handle() as isize);
+        for algorithm in [w!("SHA256"), w!("SHA1")] {
+            let mut admin = 0;
+            if !unsafe { CryptCATAdminAcquireContext2(&mut admin, None, algorithm, None, 0) }
+                .as_bool()
+            {
+                continue;
+            }
+            let signature = find_in_catalogs(admin, handle, path);
+            unsafe { CryptCATAdminReleaseContext(admin, 0) };
+            if signature.is_some() {
+                return signature;
+            }
+        }
+        None
+    }
+
+    fn find_in_catalogs(admin: isize, file: HANDLE, path: &Path) -> Option<Signature> {
+        let mut length = 0;
+        unsafe { CryptCATAdminCalcHashFromFileHandle2(admin, file, &mut length, None, 0) };
+        let mut hash = vec![0u8; length as usize];
+        if !unsafe {
+            CryptCATAdminCalcHashFromFileHandle2(
+                admin,
+                file,
+                &mut length,
+                Some(hash.as_mut_ptr()),
+                0,
+            )
+        }
+        .as_bool()
+        {
+            return None;
+        }
+
+        let catalog = unsafe { CryptCATAdminEnumCatalogFromHash(admin, &hash, 0, None) };
+        if catalog == 0 {
+            return None;
+        }
+        let mut catalog_info = CATALOG_INFO {
+            cbStruct: size_of::<CATALOG_INFO>() as u32,
+            ..Default::default()
+        };
+        let signature = unsafe { CryptCATCatalogInfoFromContext(catalog, &mut catalog_info, 0) }
+            .as_bool()
+            .then(|| verify_member(admin, &catalog_info, &mut hash, file, path));
+        unsafe { CryptCATAdminReleaseCatalogContext(admin, catalog, 0) };
+        signature
+    }
+
+    fn verify_member(
+        admin: isize,
+        catalog_info: &CATALOG_INFO,
+        hash: &mut [u8],
+        file: HANDLE,
+        path: &Path,
+    ) -> Signature {
+        let catalog_file = &catalog_info.wszCatalogFile;
+        let end = catalog_file
+            .iter()
+            .position(|c| *c == 0)
+            .unwrap_or(catalog_file.len());
+        let catalog_file = String::from_utf16_lossy(&catalog_file[..end]);
+
+        let member_tag = wide(OsStr::new(&hex::encode_upper(&hash[..])));
+        let name = wide(path.as_os_str());
+        let mut catalog = WINTRUST_CATALOG_INFO {
+            cbStruct: size_of::<WINTRUST_CATALOG_INFO>() as u32,
+            pcwszCatalogFilePath: PCWSTR(catalog_info.wszCatalogFile.as_ptr()),
+            pcwszMemberTag: PCWSTR(member_tag.as_ptr()),
+            pcwszMemberFilePath: PCWSTR(name.as_ptr()),
+            hMemberFile: file,
+            pbCalculatedFileHash: hash.as_mut_ptr(),
+            cbCalculatedFileHash: hash.len() as u32,
+            hCatAdmin: admin,
+            ..Default::default()
+        };
+        let mut data = WINTRUST_DATA {
+            dwUnionChoice: WTD_CHOICE_CATALOG,
+            Anonymous: WINTRUST_DATA_0 {
+                pCatalog: &mut catalog,
+            },
+            ..Default::default()
+        };
+        let state = win_verify_trust(&mut data);
+        let signer = std::fs::read(&catalog_file)
+            .ok()
+            .and_then(|data| SignedData::parse(&data).and_then(|s| s.signer_name()));
+        Signature {
+            state,
+            signer,
+            catalog: Some(catalog_file),
+        }
+    }
+}
+
+struct AuthenticodeFinding {
+    found_in_file: String,
+    signature: Signature,
+}
+
+impl AuthenticodeFinding {
+    fn details(&self) -> String {
+        let mut details = match &self.signature.state {
+            State::Invalid(reason) => format!("invalid signature: {}", reason),
+            State::Unverified(reason) => format!("unverified signature: {}", reason),
+            State::Unsigned => "unsigned file in a system directory".to_owned(),
+            state => format!("{} signature", state.name()),
+        };
+        if let Some(signer) = &self.signature.signer {
+            details += &format!(", signed by {}", signer);
+        }
+        if let Some(catalog) = &self.signature.catalog {
+            details += &format!(", in catalog {}", catalog);
+        }
+        details
+    }
+}
+
+impl Display for AuthenticodeFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "Authenticode: {found_in_file} ({details})")
+    }
+}
+
+impl ScannerFinding for AuthenticodeFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "Authenticode",
+            self.signature.state.name(),
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        let reason = match &self.signature.state {
+            State::Invalid(reason) | State::Unverified(reason) => Some(reason),
+            _ => None,
+        };
+        json!({
+            "01_scanner": "authenticode",
+            "02_suspicious_file": self.found_in_file(),
+            "03_state": self.signature.state.name(),
+            "04_signer": self.signature.signer,
+            "05_catalog": self.signature.catalog,
+            "06_reason": reason
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        match self.signature.state {
+            State::Invalid(_) | State::Revoked => Severity::High,
+            State::Expired | State::Unverified(_) => Severity::Low,
+            _ => Severity::Medium,
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "authenticode".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    fn rule(&self) -> Option<&str> {
+        Some(self.signature.state.name())
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        match self.signature.state {
+            // Masquerading: Match Legitimate Name or Location
+            State::Unsigned => vec!["T1036.005".to_owned()],
+            // Subvert Trust Controls: Code Signing
+            _ => vec!["T1553.002".to_owned()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{finding, Signature, State};
+
+    fn unverified() -> State {
+        State::Unverified("the certificates have not been verified".to_owned())
+    }
+
+    #[test]
+    fn test_finding() {
+        let signer = Some("Contoso Ltd".to_owned());
+        assert!(finding(
+            String::new(),
+            Signature::new(State::Signed, signer.clone()),
+            true
+        )
+        .is_none());
+        assert!(finding(String::new(), Signature::new(State::Unsigned, None), false).is_none());
+
+        let unsigned = finding(String::new(), Signature::new(State::Unsigned, None), true).unwrap();
+        assert_eq!(unsigned.details(), "unsigned file in a system directory");
+
+        let revoked =
+            finding(String::new(), Signature::new(State::Revoked, signer), false).unwrap();
+        assert_eq!(
+            revoked.details(),
+            "revoked signature, signed by Contoso Ltd"
+        );
+    }
+
+    #[test]
+    fn test_unverified() {
+        let signer = Some("Microsoft Windows".to_owned());
+        assert!(finding(
+            String::new(),
+            Signature::new(unverified(), signer.clone()),
+            false
+        )
+        .is_none());
+
+        // a self-signed file with a well-known signer name is not accepted as signed
+        let unverified_file =
+            finding(String::new(), Signature::new(unverified(), signer), true).unwrap();
+        assert_eq!(
+            unverified_file.details(),
+            "unverified signature: the certificates have not been verified, \
+             signed by Microsoft Windows"
+        );
+
+        // files in a catalog of the installation are not reported
+        let mut in_catalog = Signature::new(unverified(), None);
+        in_catalog.catalog = Some("nt5.cat".to_owned());
+        assert!(finding(String::new(), in_catalog, true).is_none());
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::inventory::inventory_entry;
+use crate::path_string::{extended_length_path, path_to_string};
+use crate::scanner_result::ScannerResult;
+
+/// a row of the manifest of the collected files
+#[derive(Serialize)]
+struct ManifestEntry {
+    path: String,
+    collected_as: String,
+    findings: usize,
+    size: u64,
+    md5: String,
+    sha1: String,
+    sha256: String,
+    modified: String,
+    accessed: String,
+    created: String,
+}
+
+/// copies every file with findings into an evidence directory (`--collect`):
+///
+///  - the files are stored below `files/`, with their full original path (e.g.
+///    `files/C/Users/user/evil.exe` or `files/home/user/evil.elf`)
+///  - `manifest.csv` contains the original path, the hashes, the size and the
+///    timestamps of every collected file
+///  - if a password is given, every file is stored in a zip archive, which is
+///    encrypted with that password, so that antivirus software does not remove or
+///    quarantine the samples
+///
+/// The files are copied in a separate thread, so that copying large files does not
+/// block the output of the results
+pub(crate) struct Collector {
+    dir: PathBuf,
+    files: mpsc::Sender<(PathBuf, usize)>,
+    collected: HashSet<String>,
+    thread: thread::JoinHandle<Collection>,
+}
+
+impl Collector {
+    pub fn new<P: AsRef<Path>>(dir: P, password: Option<String>) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        std::fs::create_dir_all(dir.join("files"))
+            .map_err(|why| anyhow!("unable to create '{}': {}", dir.display(), why))?;
+        let manifest = dir.join("manifest.csv");
+        let manifest = csv::Writer::from_path(&manifest)
+            .map_err(|why| anyhow!("unable to create '{}': {}", manifest.display(), why))?;
+        let mut collection = Collection {
+            dir: dir.clone(),
+            password,
+            manifest,
+            collected_files: 0,
+            failed_files: 0,
+        };
+
+        let (files, rx) = mpsc::channel::<(PathBuf, usize)>();
+        let thread = thread::spawn(move || {
+            for (source, findings) in rx {
+                collection.add_file(&source, findings);
+            }
+            collection
+        });
+        Ok(Self {
+            dir,
+            files,
+            collected: HashSet::new(),
+            thread,
+        })
+    }
+
+    /// collects the file of `result`. Findings in archive members or event log
+    /// records have no file of their own, and are not collected
+    pub fn add_result(&mut self, result: &ScannerResult) {
+        if let Some(source) = result.path() {
+            self.add_file(source, result.findings().count());
+        }
+    }
+
+    /// collects the file `source`, which has `findings` findings. A file is collected
+    /// only once, even if it is found again by `--watch`
+    pub fn add_file(&mut self, source: &Path, findings: usize) {
+        if !self.collected.insert(path_to_string(source)) {
+            return;
+        }
+        if self.files.send((source.to_owned(), findings)).is_err() {
+            log::error!("unable to collect '{}'", path_to_string(source));
+        }
+    }
+
+    /// waits until all files have been collected, and returns the lines which are
+    /// displayed in the summary at the end of a scan
+    pub fn finish(self) -> Vec<String> {
+        drop(self.files);
+        let collection = match self.thread.join() {
+            Ok(collection) => collection,
+            Err(_) => {
+                let error = format!("unable to collect the files in '{}'", self.dir.display());
+                return vec![error];
+            }
+        };
+        let mut lines = vec![format!(
+            "collected {} file(s) in '{}'",
+            collection.collected_files,
+            self.dir.display()
+        )];
+        if collection.failed_files > 0 {
+            lines.push(format!(
+                "unable to collect {} file(s), see the log for details",
+                collection.failed_files
+            ));
+        }
+        lines
+    }
+}
+
+/// the evidence directory, which is filled by the thread of a [`Collector`]
+struct Collection {
+    dir: PathBuf,
+    password: Option<String>,
+    manifest: csv::Writer<File>,
+    collected_files: usize,
+    failed_files: usize,
+}
+
+impl Collection {
+    fn add_file(&mut self, source: &Path, findings: usize) {
+        if !source.is_file() {
+            return;
+        }
+        match self.collect(source, findings) {
+            Ok(()) => self.collected_files += 1,
+            Err(why) => {
+                self.failed_files += 1;
+                log::error!("unable to collect '{}': {}", path_to_string(source), why);
+            }
+        }
+    }
+
+    fn collect(&mut self, source: &Path, findings: usize) -> Result<()> {
+        let entry = inventory_entry(source, None)?;
+        let mut collected_as = Path::new("files").join(evidence_path(source));
+        if self.password.is_some() {
+            let mut name = collected_as.into_os_string();
+            name.push(".zip");
+            collected_as = PathBuf::from(name);
+        }
+
+        let destination = extended_length_path(&self.dir.join(&collected_as));
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match &self.password {
+            None => {
+                std::fs::copy(source, &destination)?;
+            }
+            Some(password) => write_zip(source, &destination, password)?,
+        }
+
+        self.manifest.serialize(ManifestEntry {
+            path: entry.path,
+            collected_as: path_to_string(&collected_as),
+            findings,
+            size: entry.size,
+            md5: entry.md5,
+            sha1: entry.sha1,
+            sha256: entry.sha256,
+            modified: entry.modified,
+            accessed: entry.accessed,
+            created: entry.created,
+        })?;
+        self.manifest.flush()?;
+        Ok(())
+    }
+}
+
+/// stores `source` in a zip archive, which is encrypted with `password`. ZipCrypto is
+/// used instead of AES, because every archive tool can extract it, and it is only
+/// meant to hide the samples from antivirus software
+fn write_zip(source: &Path, destination: &Path, password: &str) -> Result<()> {
+    let name = source
+        .file_name()
+        .map(|name| path_to_string(Path::new(name)))
+        .unwrap_or_default();
+    let mut reader = BufReader::new(File::open(source)?);
+    let size = source.metadata()?.len();
+
+    let mut zip = zip::ZipWriter::new(File::create(destination)?);
+    let options = zip::write::SimpleFileOptions::default()
+        .large_file(size >= u32::MAX as u64)
+        .with_deprecated_encryption(password.as_bytes());
+    zip.start_file(name, options)?;
+    std::io::copy(&mut reader, &mut zip)?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// the path of `source` below the evidence directory, which is its absolute path
+/// without the root. The drive letter (or server and share) of Windows paths is
+/// kept as the first directory
+fn evidence_path(source: &Path) -> PathBuf {
+    let absolute = match source.is_absolute() {
+        true => source.to_owned(),
+        false => std::env::current_dir()
+            .map(|current_dir| current_dir.join(source))
+            .unwrap_or_else(|_| source.to_owned()),
+    };
+
+    let mut result = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            #[cfg(windows)]
+            Component::Prefix(prefix) => {
+                use std::path::Prefix;
+                match prefix.kind() {
+                    Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                        result.push((letter as char).to_string())
+                    }
+                    Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                        result.push(server);
+                        result.push(share);
+                    }
+                    _ => (),
+                }
+            }
+            #[cfg(not(windows))]
+            Component::Prefix(_) => (),
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::Normal(name) => result.push(name),
+            Component::RootDir | Component::CurDir => (),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+
+    use sha2::{Digest, Sha256};
+
+    use super::{evidence_path, Collector};
+
+    /// collects `sample.exe` twice (and a missing file) into an evidence directory, and
+    /// returns the path and the content of the collected file, the manifest and the summary
+    fn collect(name: &str, password: Option<&str>) -> (PathBuf, Vec<u8>, String, Vec<String>) {
+        let dir = std::env::temp_dir().join(format!("dionysos-{}-{}", name, std::process::id()));
+        let sample = dir.join("sample.exe");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&sample, b"MZevil").unwrap();
+
+        let evidence = dir.join("evidence");
+        let mut collector = Collector::new(&evidence, password.map(str::to_owned)).unwrap();
+        collector.add_file(&sample, 2);
+        collector.add_file(&sample, 2);
+        collector.add_file(&dir.join("missing.exe"), 1);
+        let summary = collector.finish();
+
+        let mut collected = evidence.join("files").join(evidence_path(&sample));
+        if password.is_some() {
+            collected.set_extension("exe.zip");
+        }
+        let data = std::fs::read(&collected).unwrap();
+        let manifest = std::fs::read_to_string(evidence.join("manifest.csv")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        (collected, data, manifest, summary)
+    }
+
+    #[test]
+    fn test_collect() {
+        let (_, data, manifest, summary) = collect("collect", None);
+        assert_eq!(data, b"MZevil");
+        let lines: Vec<&str> = manifest.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("path,collected_as,findings,size,md5"));
+        assert!(lines[1].contains(",2,6,"));
+        assert!(lines[1].contains(&hex::encode(Sha256::digest(b"MZevil"))));
+        assert_eq!(summary.len(), 1);
+        assert!(summary[0].starts_with("collected 1 file(s)"));
+    }
+
+    #[test]
+    fn test_collect_encrypted() {
+        let (collected, data, manifest, _) = collect("collect-encrypted", Some("infected"));
+        assert!(manifest.contains(&*collected.file_name().unwrap().to_string_lossy()));
+
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        assert!(zip.by_index(0).is_err());
+        let mut sample = Vec::new();
+        zip.by_index_decrypt(0, b"infected")
+            .unwrap()
+            .read_to_end(&mut sample)
+            .unwrap();
+        assert_eq!(sample, b"MZevil");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_evidence_path() {
+        assert_eq!(
+            evidence_path(Path::new("/home/user/../user/evil.elf")),
+            Path::new("home/user/evil.elf")
+        );
+        assert!(evidence_path(Path::new("src/lib.rs")).ends_with("src/lib.rs"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_evidence_path() {
+        assert_eq!(
+            evidence_path(Path::new("\\\\?\\C:\\Users\\user\\evil.exe")),
+            Path::new("C\\Users\\user\\evil.exe")
+        );
+        assert_eq!(
+            evidence_path(Path::new("\\\\server\\share\\evil.exe")),
+            Path::new("server\\share\\evil.exe")
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use walkdir::DirEntry;
+
+use crate::path_string::path_to_string;
+
+const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+const FILE_ATTRIBUTE_COMPRESSED: u32 = 0x800;
+const IO_REPARSE_TAG_WOF: u32 = 0x8000_0017;
+
+/// the number of bytes which are read to check if a compressed file can be decompressed
+const PROBE_SIZE: usize = 64 * 1024;
+
+/// the compression of a file by the file system. Reading such a file returns its
+/// decompressed content, as long as the file system supports the compression: NTFS
+/// compression is supported by Windows and by ntfs-3g, but WOF compression (which is
+/// used by `compact.exe /EXE` and by Windows itself for its system files) requires the
+/// WOF driver on Windows, or the system compression plugin of ntfs-3g
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Compression {
+    Ntfs,
+    Wof,
+}
+
+const COMPRESSIONS: [Compression; 2] = [Compression::Ntfs, Compression::Wof];
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Ntfs => write!(f, "NTFS-compressed"),
+            Compression::Wof => write!(f, "WOF-compressed"),
+        }
+    }
+}
+
+impl Compression {
+    /// the compression of `entry`, or `None` if it is not compressed. On Unix, the
+    /// compression is only known for files of NTFS volumes which are mounted with
+    /// ntfs-3g, which exposes the attributes of the files as extended attributes
+    pub fn of(entry: &DirEntry) -> Option<Self> {
+        if !entry.file_type().is_file() {
+            return None;
+        }
+        let (attributes, reparse_tag) = ntfs_attributes(entry)?;
+        if attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0 && reparse_tag == Some(IO_REPARSE_TAG_WOF)
+        {
+            Some(Compression::Wof)
+        } else if attributes & FILE_ATTRIBUTE_COMPRESSED != 0 {
+            Some(Compression::Ntfs)
+        } else {
+            None
+        }
+    }
+}
+
+/// the NTFS attributes of `entry` and, if it is a reparse point, its reparse tag
+#[cfg(windows)]
+pub(crate) fn ntfs_attributes(entry: &DirEntry) -> Option<(u32, Option<u32>)> {
+    use std::os::windows::fs::MetadataExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        FileAttributeTagInfo, GetFileInformationByHandleEx, FILE_ATTRIBUTE_TAG_INFO,
+    };
+
+    let attributes = entry.metadata().ok()?.file_attributes();
+    if attributes & FILE_ATTRIBUTE_REPARSE_POINT == 0 {
+        return Some((attributes, None));
+    }
+
+    // WOF reparse points are no name surrogates, so that opening the file opens the
+    // file itself instead of the target of the reparse point
+    let file = File::open(entry.path()).ok()?;
+    let mut info = FILE_ATTRIBUTE_TAG_INFO::default();
+    let success = unsafe {
+        GetFileInformationByHandleEx(
+            HANDLE(file.as_raw_handle() as isize),
+            FileAttributeTagInfo,
+            &mut info as *mut FILE_ATTRIBUTE_TAG_INFO as *mut std::ffi::c_void,
+            std::mem::size_of::<FILE_ATTRIBUTE_TAG_INFO>() as u32,
+        )
+    };
+    match success.as_bool() {
+        true => Some((attributes, Some(info.ReparseTag))),
+        false => Some((attributes, None)),
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn ntfs_attributes(entry: &DirEntry) -> Option<(u32, Option<u32>)> {
+    let le_u32 = |data: Vec<u8>| {
+        data.get(..4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    let attributes = xattr::get(entry.path(), "system.ntfs_attrib")
+        .ok()
+        .flatten()
+        .and_then(le_u32)?;
+    if attributes & FILE_ATTRIBUTE_REPARSE_POINT == 0 {
+        return Some((attributes, None));
+    }
+    let reparse_tag = xattr::get(entry.path(), "system.ntfs_reparse_data")
+        .ok()
+        .flatten()
+        .and_then(le_u32);
+    Some((attributes, reparse_tag))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn ntfs_attributes(_entry: &DirEntry) -> Option<(u32, Option<u32>)> {
+    None
+}
+
+/// counts the compressed files which have been scanned, and the files which could not
+/// be decompressed. Compressed files are scanned as every other file, because the file
+/// system decompresses them when they are read. However, if it does not support their
+/// compression, reading them fails, or returns nothing. Instead of passing such a file
+/// to the scanners, which would not find anything, it is reported as an error
+#[derive(Default)]
+pub(crate) struct CompressedFiles {
+    counts: [AtomicUsize; COMPRESSIONS.len()],
+    failed_files: Mutex<Vec<String>>,
+}
+
+impl CompressedFiles {
+    /// checks if `entry` is compressed and, if so, if its decompressed content can be read
+    pub fn check(&self, entry: &DirEntry) -> Result<()> {
+        let compression = match Compression::of(entry) {
+            Some(compression) => compression,
+            None => return Ok(()),
+        };
+        let index = COMPRESSIONS.iter().position(|c| *c == compression).unwrap();
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+
+        match probe(entry) {
+            Ok(()) => {
+                log::debug!("'{}' is {}", entry.path().display(), compression);
+                Ok(())
+            }
+            Err(why) => {
+                let filename = path_to_string(entry.path());
+                log::error!(
+                    "unable to decompress the {} file '{}': {}",
+                    compression,
+                    filename,
+                    why
+                );
+                self.failed_files.lock().unwrap().push(filename);
+                Err(anyhow!(
+                    "unable to decompress the {} file: {}",
+                    compression,
+                    why
+                ))
+            }
+        }
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        let counts: Vec<String> = COMPRESSIONS
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(kind, count)| (kind, count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .map(|(kind, count)| format!("{} {} file(s)", count, kind))
+            .collect();
+        let mut lines = Vec::new();
+        if !counts.is_empty() {
+            lines.push(format!("compressed files: {}", counts.join(", ")));
+        }
+        let failed_files = self.failed_files.lock().unwrap();
+        if !failed_files.is_empty() {
+            lines.push(format!(
+                "unable to decompress {} file(s):",
+                failed_files.len()
+            ));
+            lines.extend(failed_files.iter().map(|f| format!("  {}", f)));
+        }
+        lines
+    }
+}
+
+/// reads the beginning of `entry`, which fails if the file system cannot decompress
+/// it. Some implementations return less data than the size of the file instead
+fn probe(entry: &DirEntry) -> Result<()> {
+    let size = entry.metadata()?.len();
+    let mut buffer = vec![0; PROBE_SIZE.min(size as usize)];
+    let mut file = File::open(entry.path())?;
+    let mut read = 0;
+    while read < buffer.len() {
+        match file.read(&mut buffer[read..])? {
+            0 => break,
+            bytes => read += bytes,
+        }
+    }
+    match read == buffer.len() {
+        true => Ok(()),
+        false => Err(anyhow!(
+            "read {} of {} bytes of the decompressed content",
+            read,
+            buffer.len()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use walkdir::WalkDir;
+
+    use super::{CompressedFiles, Compression};
+
+    #[test]
+    fn test_uncompressed_file() {
+        let entry = WalkDir::new("src/lib.rs")
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(Compression::of(&entry), None);
+
+        let compressed_files = CompressedFiles::default();
+        assert!(compressed_files.check(&entry).is_ok());
+        assert!(compressed_files.summary().is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use clap::Command;
+use serde_json::{Map, Value};
+
+/// reads a configuration file (TOML or YAML, detected by the extension) and
+/// converts it into command line arguments. The keys of the configuration file
+/// are the long names of the command line options, e.g.
+///
+/// ```toml
+/// path = "/mnt/evidence"
+/// yara = "/opt/rules"
+/// threads = 8
+/// evtx = true
+/// filename = ["^evil\\.exe$", "^mimikatz"]
+/// ```
+///
+/// Flags which can be specified multiple times (like `verbose`) accept a number.
+pub fn config_args(path: &Path, command: &Command) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|why| anyhow!("unable to read the configuration file '{}': {}", path.display(), why))?;
+
+    let config: Map<String, Value> = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&content)?,
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content)?,
+        _ => {
+            return Err(anyhow!(
+                "unknown format of the configuration file '{}', use a '.toml' or '.yaml' file",
+                path.display()
+            ))
+        }
+    };
+
+    to_args(config, command)
+        .map_err(|why| anyhow!("invalid configuration file '{}': {}", path.display(), why))
+}
+
+fn to_args(config: Map<String, Value>, command: &Command) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for (key, value) in config.into_iter() {
+        let name = key.replace('_', "-");
+        if name == "config" {
+            return Err(anyhow!("configuration files cannot include other configuration files"));
+        }
+
+        let arg = command
+            .get_arguments()
+            .find(|a| a.get_long() == Some(&name[..]))
+            .ok_or_else(|| anyhow!("unknown option '{}'", key))?;
+        let option = format!("--{}", name);
+
+        if arg.is_takes_value_set() {
+            let values = match value {
+                Value::Array(values) => values,
+                value => vec![value],
+            };
+            for value in values {
+                args.push(option.clone());
+                args.push(match value {
+                    Value::String(s) => s,
+                    Value::Number(n) => n.to_string(),
+                    Value::Bool(b) => b.to_string(),
+                    _ => return Err(anyhow!("invalid value for option '{}'", key)),
+                });
+            }
+        } else {
+            let occurrences = match value {
+                Value::Bool(b) => b as u64,
+                Value::Number(n) => n
+                    .as_u64()
+                    .ok_or_else(|| anyhow!("invalid value for flag '{}'", key))?,
+                _ => return Err(anyhow!("flag '{}' expects a boolean or a number", key)),
+            };
+            for _ in 0..occurrences {
+                args.push(option.clone());
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+    use serde_json::{Map, Value};
+
+    use super::to_args;
+    use crate::dionysos::Cli;
+
+    fn args_of(config: &str) -> anyhow::Result<Vec<String>> {
+        let config: Map<String, Value> = toml::from_str(config).unwrap();
+        to_args(config, &Cli::command())
+    }
+
+    #[test]
+    fn test_to_args() {
+        assert_eq!(args_of("path = '/mnt/evidence'").unwrap(), vec!["--path", "/mnt/evidence"]);
+        assert_eq!(args_of("threads = 8").unwrap(), vec!["--threads", "8"]);
+        assert_eq!(args_of("scan_compressed = true").unwrap(), vec!["--scan-compressed"]);
+        assert!(args_of("scan_compressed = false").unwrap().is_empty());
+        assert_eq!(args_of("verbose = 2").unwrap(), vec!["--verbose", "--verbose"]);
+        assert_eq!(
+            args_of("filename = ['^evil', '^mimikatz']").unwrap(),
+            vec!["--filename", "^evil", "--filename", "^mimikatz"]
+        );
+        assert!(args_of("no_such_option = 1").is_err());
+        assert!(args_of("config = 'other.toml'").is_err());
+    }
+
+    #[test]
+    fn test_args_override_config() {
+        // the options of the configuration file precede the options of the command line
+        let mut args = vec!["dionysos".to_owned()];
+        args.extend(args_of("path = '/mnt/evidence'\nthreads = 8").unwrap());
+        args.extend(["--threads", "2"].iter().map(|a| a.to_string()));
+
+        let matches = Cli::command().try_get_matches_from(args).unwrap();
+        assert_eq!(matches.value_of("threads"), Some("2"));
+        assert_eq!(matches.value_of("path"), Some("/mnt/evidence"));
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::dionysos::TlsOptions;
+use crate::grpc::proto::agent_report::Report;
+use crate::grpc::proto::controller_server::{Controller, ControllerServer};
+use crate::grpc::proto::{AgentInfo, AgentReport, JobState, ReportAck, Task};
+
+/// identifies an agent by the SHA-256 fingerprint of its client certificate, because
+/// the hostname which it reports cannot be trusted
+fn agent_identity<T>(request: &Request<T>) -> Result<String, Status> {
+    let certs = request
+        .peer_certs()
+        .ok_or_else(|| Status::unauthenticated("the agent has no client certificate"))?;
+    let cert = certs
+        .first()
+        .ok_or_else(|| Status::unauthenticated("the agent has no client certificate"))?;
+    Ok(hex::encode(Sha256::digest(cert.get_ref())))
+}
+
+struct ControllerService {
+    /// the task which is sent to every agent
+    task: Task,
+
+    /// the connected agents. The stream of tasks of an agent is kept open as long
+    /// as its sender is stored here
+    agents: Mutex<HashMap<String, mpsc::Sender<Result<Task, Status>>>>,
+
+    /// the state of the task of every agent which has reported its completion.
+    /// Agents which have finished the task do not receive it again when they
+    /// reconnect, but agents whose task has failed or has been cancelled do
+    states: Mutex<HashMap<String, JobState>>,
+}
+
+impl ControllerService {
+    fn new(task: Task) -> Self {
+        Self {
+            task,
+            agents: Mutex::new(HashMap::new()),
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// checks if the task must be sent to `agent`
+    fn needs_task(&self, agent: &str) -> bool {
+        self.states.lock().unwrap().get(agent) != Some(&JobState::Finished)
+    }
+
+    /// records the state of the task of `agent`. The task stream of an agent which
+    /// has finished the task is closed
+    fn complete_task(&self, agent: &str, state: JobState) {
+        self.states.lock().unwrap().insert(agent.to_owned(), state);
+        if state == JobState::Finished {
+            self.agents.lock().unwrap().remove(agent);
+        }
+    }
+
+    /// the states of the tasks of all agents, which are logged when the controller stops
+    fn summary(&self) -> Vec<String> {
+        let connected = self.agents.lock().unwrap().len();
+        let states = self.states.lock().unwrap();
+        let mut summary = Vec::new();
+        let finished = states.values().filter(|s| **s == JobState::Finished).count();
+        summary.push(format!("{} agent(s) have finished the task", finished));
+        let mut failed: Vec<_> = states
+            .iter()
+            .filter(|(_, state)| **state != JobState::Finished)
+            .map(|(agent, _)| agent.as_str())
+            .collect();
+        if !failed.is_empty() {
+            failed.sort_unstable();
+            summary.push(format!(
+                "the task of {} agent(s) has failed or has been cancelled: {}",
+                failed.len(),
+                failed.join(", ")
+            ));
+        }
+        if connected > 0 {
+            summary.push(format!("{} agent(s) are still connected", connected));
+        }
+        summary
+    }
+}
+
+#[tonic::async_trait]
+impl Controller for ControllerService {
+    type RegisterStream = ReceiverStream<Result<Task, Status>>;
+
+    async fn register(
+        &self,
+        request: Request<AgentInfo>,
+    ) -> Result<Response<Self::RegisterStream>, Status> {
+        let identity = agent_identity(&request)?;
+        let agent = request.into_inner();
+        log::info!(
+            "agent '{}' (dionysos {} on {}, certificate {}) has connected",
+            agent.hostname,
+            agent.version,
+            agent.os,
+            identity
+        );
+
+        let (tx, rx) = mpsc::channel(4);
+        if self.needs_task(&identity) {
+            tx.send(Ok(self.task.clone()))
+                .await
+                .map_err(|why| Status::internal(why.to_string()))?;
+        }
+        if self.agents.lock().unwrap().insert(identity, tx).is_some() {
+            log::info!("agent '{}' has replaced its previous connection", agent.hostname);
+        }
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn report(
+        &self,
+        request: Request<Streaming<AgentReport>>,
+    ) -> Result<Response<ReportAck>, Status> {
+        let identity = agent_identity(&request)?;
+        let mut reports = request.into_inner();
+        while let Some(report) = reports.message().await? {
+            match report.report {
+                Some(Report::Finding(finding)) => {
+                    let finding: Value = serde_json::from_str(&finding.json)
+                        .unwrap_or(Value::String(finding.json));
+                    let line = json!({
+                        "agent": report.hostname,
+                        "certificate": identity,
+                        "task": report.task_id,
+                        "finding": finding
+                    });
+                    let mut stdout = std::io::stdout().lock();
+                    writeln!(stdout, "{}", line)
+                        .and_then(|()| stdout.flush())
+                        .map_err(|why| Status::internal(why.to_string()))?;
+                }
+                Some(Report::Done(progress)) => {
+                    let state = match JobState::from_i32(progress.state) {
+                        Some(JobState::Finished) => JobState::Finished,
+                        Some(JobState::Cancelled) => JobState::Cancelled,
+                        _ => JobState::Failed,
+                    };
+                    let description = match state {
+                        JobState::Finished => "finished",
+                        JobState::Cancelled => "been cancelled",
+                        _ => "failed",
+                    };
+                    let message = format!(
+                        "agent '{}' has {} task {}: {} file(s), {} finding(s), {} error(s){}",
+                        report.hostname,
+                        description,
+                        report.task_id,
+                        progress.files,
+                        progress.findings,
+                        progress.errors,
+                        progress.error.map(|e| format!(" ({})", e)).unwrap_or_default()
+                    );
+                    match state {
+                        JobState::Finished => log::info!("{}", message),
+                        _ => log::error!("{}", message),
+                    }
+                    self.complete_task(&identity, state);
+                }
+                None => (),
+            }
+        }
+        Ok(Response::new(ReportAck {}))
+    }
+}
+
+/// reads a certificate or key
+pub(crate) fn read_pem(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).map_err(|why| anyhow!("unable to read '{}': {}", path.display(), why))
+}
+
+fn server_tls(tls: &TlsOptions) -> Result<ServerTlsConfig> {
+    Ok(ServerTlsConfig::new()
+        .identity(Identity::from_pem(read_pem(&tls.cert)?, read_pem(&tls.key)?))
+        .client_ca_root(Certificate::from_pem(read_pem(&tls.ca)?)))
+}
+
+/// sends `task` to every agent which connects, and writes their findings to stdout
+/// (one JSON object per line), until the controller is interrupted with Ctrl-C.
+/// Agents must authenticate with a certificate which is signed by the CA
+pub fn serve(addr: SocketAddr, tls: &TlsOptions, task: Task) -> Result<()> {
+    let service = Arc::new(ControllerService::new(task));
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        log::info!("waiting for agents on {}", addr);
+        tonic::transport::Server::builder()
+            .tls_config(server_tls(tls)?)?
+            .add_service(ControllerServer::from_arc(Arc::clone(&service)))
+            .serve_with_shutdown(addr, async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await?;
+        Ok::<(), anyhow::Error>(())
+    })?;
+    for line in service.summary() {
+        log::info!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::{Code, Request};
+
+    use super::{agent_identity, ControllerService};
+    use crate::grpc::proto::{JobState, Task};
+
+    #[test]
+    fn test_identity_requires_certificate() {
+        let status = agent_identity(&Request::new(())).unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_finished_task_is_not_sent_again() {
+        let service = ControllerService::new(Task::default());
+        assert!(service.needs_task("a"));
+        service.complete_task("a", JobState::Finished);
+        assert!(!service.needs_task("a"));
+        assert!(service.needs_task("b"));
+    }
+
+    #[test]
+    fn test_failed_task_is_sent_again() {
+        let service = ControllerService::new(Task::default());
+        service.complete_task("a", JobState::Failed);
+        service.complete_task("b", JobState::Cancelled);
+        assert!(service.needs_task("a"));
+        assert!(service.needs_task("b"));
+
+        let summary = service.summary();
+        assert!(summary[0].starts_with("0 agent(s)"));
+        assert!(summary[1].ends_with("a, b"));
+    }
+
+    #[test]
+    fn test_finished_agent_is_disconnected() {
+        let service = ControllerService::new(Task::default());
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        service.agents.lock().unwrap().insert("a".to_owned(), tx);
+        service.complete_task("a", JobState::Failed);
+        assert_eq!(service.agents.lock().unwrap().len(), 1);
+        service.complete_task("a", JobState::Finished);
+        assert!(service.agents.lock().unwrap().is_empty());
+    }
+}
@@ -2,7 +2,8 @@ use serde::Serialize;
 
 const CSV_SEP: char = ',';
 
-#[derive(PartialEq, Eq, Hash, Serialize)]
+/// a line of the `csv` output, see [`crate::ScannerFinding::format_csv`]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize)]
 pub struct CsvLine {
     scanner_name: String,
     rule_name: String,
@@ -19,4 +20,24 @@ impl CsvLine {
             details
         }
     }
+
+    /// the same line, with `annotation` appended to its details
+    pub(crate) fn with_annotation(&self, annotation: &str) -> Self {
+        let details = match self.details.is_empty() {
+            true => annotation.to_owned(),
+            false => format!("{} ({})", self.details, annotation),
+        };
+        Self {
+            details,
+            ..self.clone()
+        }
+    }
+
+    /// the same line, for another file
+    pub(crate) fn for_file(&self, found_in_file: &str) -> Self {
+        Self {
+            found_in_file: found_in_file.to_owned(),
+            ..self.clone()
+        }
+    }
 }
@@ -0,0 +1,583 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use serde_json::Value;
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::filescanner::FileScanner;
+use crate::hash_list::CryptoHash;
+use crate::interrupt::Interrupt;
+use crate::scanner_result::{ScannerFinding, ScannerResult};
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+/// files with identical content, of which only one is scanned (`--dedup`). File
+/// servers often contain thousands of copies of the same installer, which would
+/// otherwise be scanned again and again.
+///
+/// All files are enumerated before the scan starts. Files whose size is unique are
+/// scanned without being hashed; the others are hashed with BLAKE3, and only the first
+/// file of every group with the same hash is scanned. Its findings and errors are
+/// reported for every file of the group. Findings of scanners which depend on the path
+/// of a file (like the filename scanner) are not copied: if such scanners are enabled,
+/// the other files of the group are scanned by them, and the results are merged
+pub(crate) struct Duplicates {
+    /// the duplicates of every scanned file, by the path of the scanned file
+    duplicates: RwLock<HashMap<PathBuf, Vec<PathBuf>>>,
+
+    /// the duplicates, which are scanned only by the scanners which depend on their path
+    duplicate_files: RwLock<HashSet<PathBuf>>,
+
+    /// results of duplicates which are waiting for the findings of their scanned file,
+    /// or findings of scanned files which are waiting for the results of their duplicates
+    pending: Mutex<HashMap<PathBuf, Pending>>,
+
+    /// at least one scanner depends on the path of the files
+    scan_duplicates: bool,
+    duplicate_count: AtomicUsize,
+    threads: usize,
+}
+
+enum Pending {
+    Result(ScannerResult),
+    Attribution(Arc<Attribution>),
+}
+
+impl Duplicates {
+    pub fn new(threads: usize, scanners: &[Box<dyn FileScanner>]) -> Self {
+        Self {
+            duplicates: RwLock::new(HashMap::new()),
+            duplicate_files: RwLock::new(HashSet::new()),
+            pending: Mutex::new(HashMap::new()),
+            scan_duplicates: scanners.iter().any(|scanner| scanner.depends_on_path()),
+            duplicate_count: AtomicUsize::new(0),
+            threads: threads.max(1),
+        }
+    }
+
+    /// the scanners of the scan, of which the scanners that depend only on the content of
+    /// files do not scan the duplicates. This is only needed if duplicates are scanned
+    /// at all, i.e. if at least one scanner depends on the path of the files
+    pub fn wrap_scanners(
+        self: &Arc<Self>,
+        scanners: Arc<Vec<Box<dyn FileScanner>>>,
+    ) -> Arc<Vec<Box<dyn FileScanner>>> {
+        if !self.scan_duplicates {
+            return scanners;
+        }
+        let wrapped: Vec<Box<dyn FileScanner>> = (0..scanners.len())
+            .map(|index| {
+                Box::new(DeduplicatedScanner {
+                    scanners: Arc::clone(&scanners),
+                    index,
+                    duplicates: Arc::clone(self),
+                }) as Box<dyn FileScanner>
+            })
+            .collect();
+        Arc::new(wrapped)
+    }
+
+    /// returns the errors of the enumeration and one file of every group of files with
+    /// identical content. The other files of the group are recorded as its duplicates;
+    /// they are only returned if they must be scanned by scanners which depend on their
+    /// path
+    pub fn deduplicate(
+        &self,
+        entries: impl Iterator<Item = walkdir::Result<DirEntry>>,
+        interrupt: &Interrupt,
+    ) -> Vec<walkdir::Result<DirEntry>> {
+        let mut unique = Vec::new();
+        let mut by_size: HashMap<u64, Vec<DirEntry>> = HashMap::new();
+        for entry in entries {
+            if interrupt.is_interrupted() {
+                break;
+            }
+            match entry {
+                Ok(entry) => match entry.metadata() {
+                    Ok(metadata) => by_size.entry(metadata.len()).or_default().push(entry),
+                    Err(_) => unique.push(Ok(entry)),
+                },
+                Err(why) => unique.push(Err(why)),
+            }
+        }
+
+        let mut candidates = Vec::new();
+        for (_, files) in by_size {
+            match files.len() {
+                1 => unique.extend(files.into_iter().map(Ok)),
+                _ => candidates.extend(files),
+            }
+        }
+        log::info!("hashing {} file(s) to find duplicates", candidates.len());
+
+        let hashes = self.hash_files(&candidates);
+        let mut by_hash: HashMap<[u8; 32], Vec<DirEntry>> = HashMap::new();
+        for (entry, hash) in candidates.into_iter().zip(hashes) {
+            match hash {
+                Some(hash) => by_hash.entry(hash).or_default().push(entry),
+                // the file is scanned, so that the error is reported by the scanners
+                None => unique.push(Ok(entry)),
+            }
+        }
+
+        let mut duplicates = self.duplicates.write().unwrap();
+        let mut duplicate_files = self.duplicate_files.write().unwrap();
+        for (_, mut files) in by_hash {
+            let representative = files.remove(0);
+            if !files.is_empty() {
+                self.duplicate_count.fetch_add(files.len(), Ordering::Relaxed);
+                duplicates.insert(
+                    representative.path().to_owned(),
+                    files.iter().map(|f| f.path().to_owned()).collect(),
+                );
+            }
+            unique.push(Ok(representative));
+            if self.scan_duplicates {
+                duplicate_files.extend(files.iter().map(|f| f.path().to_owned()));
+                unique.extend(files.into_iter().map(Ok));
+            }
+        }
+        unique
+    }
+
+    fn hash_files(&self, entries: &[DirEntry]) -> Vec<Option<[u8; 32]>> {
+        if entries.is_empty() {
+            return Vec::new();
+        }
+        let chunk_size = entries.len().div_ceil(self.threads);
+        thread::scope(|scope| {
+            let threads: Vec<_> = entries
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().map(hash_file).collect::<Vec<_>>()))
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect()
+        })
+    }
+
+    /// checks if `path` is a duplicate, which is only scanned by the scanners which
+    /// depend on its path
+    fn is_duplicate(&self, path: &Path) -> bool {
+        self.scan_duplicates && self.duplicate_files.read().unwrap().contains(path)
+    }
+
+    /// the result of a scanned file, followed by the results of those of its duplicates
+    /// which are complete: the findings of the scanned file are added to the results of
+    /// the scanners which depend on the path of the duplicates, no matter which of them
+    /// arrives first
+    pub fn attribute(&self, mut result: ScannerResult) -> Vec<ScannerResult> {
+        let path = match result.path() {
+            Some(path) => path.to_owned(),
+            None => return vec![result],
+        };
+        if self.is_duplicate(&path) {
+            let mut pending = self.pending.lock().unwrap();
+            return match pending.remove(&path) {
+                Some(Pending::Attribution(attribution)) => {
+                    attribution.apply(&mut result);
+                    vec![result]
+                }
+                _ => {
+                    pending.insert(path, Pending::Result(result));
+                    Vec::new()
+                }
+            };
+        }
+
+        let duplicates = match self.duplicates.read().unwrap().get(&path) {
+            Some(duplicates) => duplicates.clone(),
+            None => return vec![result],
+        };
+        let original = result.filename().to_owned();
+        let (content_findings, path_findings) = result.take_findings_by_path();
+        let findings: Vec<Arc<dyn ScannerFinding>> =
+            content_findings.into_iter().map(Arc::from).collect();
+        for finding in &findings {
+            result.add_finding(Box::new(DuplicateFinding {
+                finding: Arc::clone(finding),
+                filename: original.clone(),
+                duplicate_of: None,
+            }));
+        }
+        for finding in path_findings {
+            result.add_path_dependent_finding(finding);
+        }
+        let attribution = Arc::new(Attribution {
+            errors: result.errors().to_vec(),
+            skip_reason: result.skip_reason().map(|reason| reason.to_owned()),
+            hashes: result.hashes().map(|hashes| hashes.to_vec()),
+            original,
+            findings,
+        });
+
+        let mut results = Vec::with_capacity(duplicates.len() + 1);
+        results.push(result);
+        let mut pending = self.pending.lock().unwrap();
+        for path in duplicates {
+            if !self.scan_duplicates {
+                let mut duplicate = ScannerResult::from(path.as_path());
+                attribution.apply(&mut duplicate);
+                results.push(duplicate);
+                continue;
+            }
+            match pending.remove(&path) {
+                Some(Pending::Result(mut duplicate)) => {
+                    attribution.apply(&mut duplicate);
+                    results.push(duplicate);
+                }
+                _ => {
+                    pending.insert(path, Pending::Attribution(Arc::clone(&attribution)));
+                }
+            }
+        }
+        results
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        match (self.duplicate_count.load(Ordering::Relaxed), self.scan_duplicates) {
+            (0, _) => Vec::new(),
+            (count, false) => vec![format!(
+                "{} file(s) have not been scanned, because they are identical to a scanned file",
+                count
+            )],
+            (count, true) => vec![format!(
+                "{} file(s) have only been scanned by the scanners which depend on their path, \
+                 because they are identical to a scanned file",
+                count
+            )],
+        }
+    }
+}
+
+/// the findings, errors and skip reason of a scanned file, which are copied to its
+/// duplicates
+struct Attribution {
+    original: String,
+    findings: Vec<Arc<dyn ScannerFinding>>,
+    errors: Vec<String>,
+    skip_reason: Option<String>,
+
+    /// the duplicates have the same content, so their hashes need not be computed again
+    hashes: Option<Vec<CryptoHash>>,
+}
+
+impl Attribution {
+    fn apply(&self, duplicate: &mut ScannerResult) {
+        let filename = duplicate.filename().to_owned();
+        for finding in &self.findings {
+            duplicate.add_finding(Box::new(DuplicateFinding {
+                finding: Arc::clone(finding),
+                filename: filename.clone(),
+                duplicate_of: Some(self.original.clone()),
+            }));
+        }
+        for error in &self.errors {
+            duplicate.add_error(error.clone());
+        }
+        if let Some(reason) = &self.skip_reason {
+            duplicate.skip(reason);
+        }
+        if let (Some(hashes), None) = (&self.hashes, duplicate.hashes()) {
+            duplicate.set_hashes(hashes.clone());
+        }
+    }
+}
+
+/// a scanner of a scan with `--dedup`. Scanners which depend only on the content of files
+/// do not scan duplicates, because the findings of the scanned file are copied to them
+struct DeduplicatedScanner {
+    scanners: Arc<Vec<Box<dyn FileScanner>>>,
+    index: usize,
+    duplicates: Arc<Duplicates>,
+}
+
+impl DeduplicatedScanner {
+    fn scanner(&self) -> &dyn FileScanner {
+        self.scanners[self.index].as_ref()
+    }
+}
+
+impl Display for DeduplicatedScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.scanner())
+    }
+}
+
+impl FileScanner for DeduplicatedScanner {
+    fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let scanner = self.scanner();
+        if !scanner.depends_on_path() && self.duplicates.is_duplicate(file.path()) {
+            return Vec::new();
+        }
+        scanner.scan_file(file)
+    }
+
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        self.scanner().scan_buffer(name, data)
+    }
+
+    fn depends_on_path(&self) -> bool {
+        self.scanner().depends_on_path()
+    }
+
+    fn reload(&self) -> anyhow::Result<()> {
+        self.scanner().reload()
+    }
+
+    fn summary(&self) -> Vec<String> {
+        self.scanner().summary()
+    }
+}
+
+fn hash_file(entry: &DirEntry) -> Option<[u8; 32]> {
+    let mut hasher = blake3::Hasher::new();
+    match File::open(entry.path()).and_then(|mut file| std::io::copy(&mut file, &mut hasher)) {
+        Ok(_) => Some(*hasher.finalize().as_bytes()),
+        Err(why) => {
+            log::warn!("unable to hash '{}': {}", entry.path().display(), why);
+            None
+        }
+    }
+}
+
+/// a finding of a scanned file, which is reported for one of its duplicates
+struct DuplicateFinding {
+    finding: Arc<dyn ScannerFinding>,
+    filename: String,
+
+    /// the file which has been scanned, or `None` if this is the scanned file
+    duplicate_of: Option<String>,
+}
+
+impl Display for DuplicateFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.duplicate_of {
+            None => write!(f, "{}", self.finding),
+            Some(original) => write!(
+                f,
+                "{} (found in '{}', a duplicate of '{}')",
+                self.finding, self.filename, original
+            ),
+        }
+    }
+}
+
+impl ScannerFinding for DuplicateFinding {
+    fn format_readable(&self, options: &StringOptions) -> String {
+        let readable = self.finding.format_readable(options);
+        match &self.duplicate_of {
+            None => readable,
+            Some(original) => format!(
+                "{}\n  found in '{}', a duplicate of '{}'\n",
+                readable.trim_end(),
+                self.filename,
+                original
+            ),
+        }
+    }
+
+    fn format_csv(&self, options: &StringOptions) -> HashSet<CsvLine> {
+        let lines = self.finding.format_csv(options);
+        match &self.duplicate_of {
+            None => lines,
+            Some(_) => lines.iter().map(|line| line.for_file(&self.filename)).collect(),
+        }
+    }
+
+    fn to_json(&self, options: &StringOptions) -> Value {
+        let mut json = self.finding.to_json(options);
+        if let (Some(original), Value::Object(values)) = (&self.duplicate_of, &mut json) {
+            values.insert("02_suspicious_file".to_owned(), Value::from(self.filename.clone()));
+            values.insert("99_duplicate_of".to_owned(), Value::from(original.clone()));
+        }
+        json
+    }
+
+    fn severity(&self) -> Severity {
+        self.finding.severity()
+    }
+
+    fn scanner(&self) -> String {
+        self.finding.scanner()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.filename
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fmt::Display;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use maplit::hashset;
+    use serde_json::json;
+    use walkdir::DirEntry;
+
+    use crate::csv_line::CsvLine;
+    use crate::filescanner::FileScanner;
+    use crate::interrupt::Interrupt;
+    use crate::scan_job::handle_file;
+    use crate::scanner_result::{ScannerFinding, ScannerResult};
+    use crate::string_options::StringOptions;
+
+    use super::Duplicates;
+
+    /// reports every file, either because of its content or because of its name
+    struct TestScanner {
+        depends_on_path: bool,
+    }
+
+    struct TestFinding {
+        scanner: &'static str,
+        file: String,
+    }
+
+    impl Display for TestScanner {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "TestScanner")
+        }
+    }
+
+    impl FileScanner for TestScanner {
+        fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+            let finding = TestFinding {
+                scanner: if self.depends_on_path { "name" } else { "content" },
+                file: file.path().display().to_string(),
+            };
+            vec![Ok(Box::new(finding) as Box<dyn ScannerFinding>)]
+        }
+
+        fn depends_on_path(&self) -> bool {
+            self.depends_on_path
+        }
+    }
+
+    impl Display for TestFinding {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            writeln!(f, "{}: {}", self.scanner, self.file)
+        }
+    }
+
+    impl ScannerFinding for TestFinding {
+        fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+            hashset![CsvLine::new(self.scanner, "", &self.file, String::new())]
+        }
+
+        fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+            json!({"01_scanner": self.scanner, "02_suspicious_file": self.file})
+        }
+
+        fn found_in_file(&self) -> &str {
+            &self.file
+        }
+    }
+
+    /// creates the files `a.exe` and `b.exe` with the same content, and `c.exe` and
+    /// `d.txt`, and returns the files which are scanned
+    fn deduplicate(duplicates: &Duplicates, dir: &Path) -> Vec<PathBuf> {
+        std::fs::create_dir_all(dir).unwrap();
+        for (name, content) in [("a.exe", "MZ1"), ("b.exe", "MZ1"), ("c.exe", "MZ2"), ("d.txt", "x")] {
+            std::fs::File::create(dir.join(name))
+                .unwrap()
+                .write_all(content.as_bytes())
+                .unwrap();
+        }
+        let entries = walkdir::WalkDir::new(dir)
+            .into_iter()
+            .filter(|e| e.as_ref().map_or(true, |e| e.file_type().is_file()));
+        duplicates
+            .deduplicate(entries, &Interrupt::default())
+            .iter()
+            .map(|e| e.as_ref().unwrap().path().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let dir = std::env::temp_dir().join(format!("dionysos-dedup-{}", std::process::id()));
+        let duplicates = Duplicates::new(2, &[]);
+        let scanned = deduplicate(&duplicates, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(scanned.len(), 3);
+        let representative = scanned
+            .iter()
+            .find(|p| p.ends_with("a.exe") || p.ends_with("b.exe"))
+            .unwrap();
+        let results = duplicates.attribute(ScannerResult::from(representative.as_path()));
+        assert_eq!(results.len(), 2);
+        assert_ne!(results[0].filename(), results[1].filename());
+        assert!(results[1].path().is_some());
+        assert_eq!(duplicates.summary().len(), 1);
+    }
+
+    #[test]
+    fn test_path_dependent_scanners() {
+        let dir = std::env::temp_dir().join(format!("dionysos-dedup-path-{}", std::process::id()));
+        let scanners: Vec<Box<dyn FileScanner>> = vec![
+            Box::new(TestScanner {
+                depends_on_path: false,
+            }),
+            Box::new(TestScanner {
+                depends_on_path: true,
+            }),
+        ];
+        let duplicates = Arc::new(Duplicates::new(2, &scanners));
+        let scanners = duplicates.wrap_scanners(Arc::new(scanners));
+
+        // the duplicate is scanned as well, but only by the scanner which depends on its path
+        let scanned = deduplicate(&duplicates, &dir);
+        assert_eq!(scanned.len(), 4);
+        let results: Vec<ScannerResult> = scanned
+            .iter()
+            .filter(|path| path.ends_with("a.exe") || path.ends_with("b.exe"))
+            .map(|path| {
+                let entry = walkdir::WalkDir::new(path).into_iter().next().unwrap().unwrap();
+                handle_file(&scanners, &None, &entry, &Default::default(), None, None)
+            })
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let counts: HashSet<usize> = results.iter().map(|r| r.findings().count()).collect();
+        assert_eq!(counts, hashset![1, 2]);
+
+        // the result of the duplicate is completed by the findings of the scanned file
+        let (representative, duplicate): (Vec<_>, Vec<_>) =
+            results.into_iter().partition(|r| r.findings().count() == 2);
+        let duplicate = duplicate.into_iter().next().unwrap();
+        let duplicate_name = duplicate.filename().to_owned();
+        assert!(duplicates.attribute(duplicate).is_empty());
+        let results = duplicates.attribute(representative.into_iter().next().unwrap());
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].filename(), duplicate_name);
+
+        // every file has its own finding of the scanner which depends on the path
+        let options = StringOptions::default();
+        let mut scanners: Vec<String> = results[1]
+            .findings()
+            .map(|f| f.to_json(&options)["01_scanner"].as_str().unwrap().to_owned())
+            .collect();
+        scanners.sort();
+        assert_eq!(scanners, vec!["content", "name"]);
+        for finding in results[1].findings() {
+            assert_eq!(finding.found_in_file(), duplicate_name);
+            assert_eq!(finding.to_json(&options)["02_suspicious_file"], duplicate_name.as_str());
+        }
+        let readable: Vec<String> =
+            results[1].findings().map(|f| f.format_readable(&options)).collect();
+        assert!(readable.iter().any(|r| r.contains(", a duplicate of '")));
+    }
+}
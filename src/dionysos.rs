@@ -1,38 +1,122 @@
 use anyhow::{anyhow, Result};
-use clap::{ArgEnum, Parser};
+#[cfg(any(feature = "scan_evtx", feature = "scan_reg"))]
+use chrono::{DateTime, Utc};
+use clap::{ArgEnum, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use simplelog::{
     ColorChoice, Config, ConfigBuilder, LevelFilter, TermLogger, TerminalMode, WriteLogger,
 };
+use std::ffi::OsString;
 use std::fs::OpenOptions;
+#[cfg(feature = "tui")]
+use std::io::IsTerminal;
 use std::io::Write;
-use std::path::PathBuf;
+#[cfg(any(feature = "grpc", feature = "http"))]
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use walkdir::WalkDir;
 
+use crate::attack::AttackCoverage;
+use crate::audit::AuditManifest;
+use crate::collect::Collector;
+use crate::dedup::Duplicates;
+use crate::estimate::Estimate;
+use crate::result_diff::{self, Change, ResultSet};
+use crate::report::{Report, ReportFormat};
+use crate::output_route::{OutputRoutes, Route};
+use crate::threat_intel::ThreatIntel;
+use crate::findings_policy::FindingsPolicy;
+#[cfg(feature = "hash_lookup")]
+use crate::hash_lookup::{HashLookup, HashReport};
+use crate::targets::{TargetMode, Targets};
+use crate::export::{ExportLayout, Exporter};
+use crate::config_file::config_args;
 use crate::filename_scanner::FilenameScanner;
 use crate::filescanner::*;
+#[cfg(feature = "grpc")]
+use crate::grpc;
+#[cfg(feature = "grpc")]
+use crate::{agent, controller};
+#[cfg(feature = "http")]
+use crate::http_server;
+use crate::hash_list::{CryptoHash, HashAlgorithms, HashList};
+#[cfg(feature = "fuzzy_hash")]
+use crate::fuzzy_hash_scanner::{self, FuzzyHashScanner};
 use crate::hash_scanner::HashScanner;
+use crate::tlsh_scanner::{self, TlshScanner};
+use crate::entropy_scanner::{self, EntropyScanner};
+use crate::mail;
+use crate::extension_scanner::ExtensionScanner;
+use crate::interrupt::{Interrupt, EXIT_ABORTED};
+use crate::inventory::InventoryScanner;
+use crate::known_good::{KnownGoodFilter, KnownGoodMode};
+use crate::path_string::{extended_length_path, path_to_string};
 use crate::levenshtein_scanner::LevenshteinScanner;
+#[cfg(windows)]
+use crate::live_eventlog;
+#[cfg(windows)]
+use crate::service;
+use crate::masquerading_scanner::MasqueradingScanner;
+use crate::permission_scanner::PermissionScanner;
+use crate::authenticode_scanner::AuthenticodeScanner;
+use crate::pe_anomaly_scanner::PeAnomalyScanner;
+use crate::elf_anomaly_scanner::ElfAnomalyScanner;
+use crate::macho_anomaly_scanner::MachOAnomalyScanner;
+use crate::executable_scanner::ExecutableScanner;
+use crate::ransomware_scanner::RansomwareScanner;
+use crate::plugin::PluginScanner;
+use crate::progress_json::{ProgressEvents, ProgressReporter};
+#[cfg(feature = "scan_reg")]
+use crate::registry_ioc::RegistryIocScanner;
+use crate::compressed_files::{CompressedFiles, Compression};
+use crate::file_timeout::{FileHandler, FileTimeout};
+use crate::disk_image::DiskImageProvider;
+use crate::file_provider::{FileListProvider, FileProvider, ProvidedFile};
+use crate::s3::S3Provider;
+use crate::scan_job::{panic_message, ScanHandle, ScanJob, WorkerHooks};
+use crate::csv_line::CsvLine;
+use crate::scan_errors::{ScanErrorKind, ScanErrors};
+use crate::special_files::{SpecialFile, SpecialFiles};
+use crate::worker_watchdog::{WorkerActivity, WorkerWatchdog};
+use crate::scanner_kind::ScannerKind;
+#[cfg(any(feature = "grpc", feature = "http"))]
+use crate::scan_service::{JobRegistry, ScanRequest};
 use crate::scanner_result::{ScannerResult, ScannerFinding};
-use crate::yara::YaraScanner;
-
-/// this needs to be a global variable,
-/// because it is read by serialization code, which has no state by default
-static mut DISPLAY_STRINGS: bool = false;
-
-pub(crate) fn display_strings() -> bool {
-    unsafe { DISPLAY_STRINGS }
-}
-
-pub(crate) fn skip_display_strings() -> bool {
-    !display_strings()
-}
-
-fn set_display_strings(val: bool) {
-    unsafe { DISPLAY_STRINGS = val }
+use crate::severity::Severity;
+use crate::string_options::{StringEncoding, StringOptions};
+use crate::systemd;
+use crate::telemetry;
+#[cfg(feature = "otel")]
+use crate::telemetry::Telemetry;
+use crate::throttle::{Throttle, ThrottleSchedule};
+#[cfg(any(feature = "scan_evtx", feature = "scan_reg"))]
+use crate::time_window::{parse_timestamp, TimeWindow};
+use crate::update::{self, Feed};
+#[cfg(feature = "tui")]
+use crate::tui::TuiState;
+use crate::yara::{ntfs_volumes, YaraRuleset, YaraScanner};
+#[cfg(feature = "scan_evtx")]
+use crate::yara::EvtxFilter;
+
+#[cfg(feature = "yara_x")]
+use crate::yara::YaraXScanner;
+
+/// the formats which can be selected with `--format`: the output formats, and the
+/// formats of reports, which are only supported by the `report` subcommand
+#[derive(ArgEnum, Clone, Copy)]
+pub(crate) enum Format {
+    Csv,
+    Txt,
+    Json,
+    Ndjson,
+    Html,
+    Xlsx,
+    Md,
 }
 
 #[derive(ArgEnum, Clone)]
@@ -40,26 +124,33 @@ pub(crate) enum OutputFormat {
     Csv,
     Txt,
     Json,
+    Ndjson,
 }
 
 impl OutputFormat {
     pub fn into_options<W: Write>(self, destination: W) -> OutputMethods<W> {
+        let line_delimited = matches!(self, OutputFormat::Ndjson);
         let destination = match self {
             OutputFormat::Csv => OutputDestination::Csv(csv::Writer::from_writer(destination)),
             OutputFormat::Txt => OutputDestination::Txt(destination),
-            OutputFormat::Json => OutputDestination::Json(destination),
+            OutputFormat::Json | OutputFormat::Ndjson => OutputDestination::Json(destination),
         };
-        let print_strings = false;
         OutputMethods {
             destination,
-            print_strings,
+            line_delimited,
+            string_options: StringOptions::default(),
+            threat_intel: None,
         }
     }
 }
 
 pub(crate) struct OutputMethods<W: Write> {
     destination: OutputDestination<W>,
-    print_strings: bool,
+
+    /// writes every JSON object to a line of its own (`ndjson`)
+    line_delimited: bool,
+    string_options: StringOptions,
+    threat_intel: Option<Arc<ThreatIntel>>,
 }
 
 pub(crate) enum OutputDestination<W: Write> {
@@ -72,210 +163,1210 @@ impl<W> OutputMethods<W>
 where
     W: Write,
 {
-    pub fn with_print_strings(mut self, print_strings: bool) -> Self {
-        self.print_strings = print_strings;
+    pub fn with_string_options(mut self, string_options: StringOptions) -> Self {
+        self.string_options = string_options;
         self
     }
 
-    pub fn print_strings(&self) -> bool {
-        self.print_strings
+    /// annotates every finding with what is known about it in `threat_intel`
+    pub fn with_threat_intel(mut self, threat_intel: Option<Arc<ThreatIntel>>) -> Self {
+        self.threat_intel = threat_intel;
+        self
     }
 
     pub fn destination(&self) -> &OutputDestination<W> {
         &self.destination
     }
 
-    pub fn print_result(&mut self, result: &ScannerResult) {
-        for finding in result.findings() {
+    /// writes the findings of `result`. Errors of the destination (e.g. a broken pipe
+    /// or a full disk) are returned, so that the scan can be stopped
+    pub fn print_result(&mut self, result: &ScannerResult) -> Result<()> {
+        self.print_findings(result, |_| true)
+    }
+
+    /// like [`OutputMethods::print_result`], but writes only the findings which are
+    /// accepted by `filter`
+    pub fn print_findings<F>(&mut self, result: &ScannerResult, filter: F) -> Result<()>
+    where
+        F: Fn(&dyn ScannerFinding) -> bool,
+    {
+        if !result.findings().any(|finding| filter(finding.as_ref())) {
+            return Ok(());
+        }
+        let hashes = match &self.threat_intel {
+            Some(threat_intel) => threat_intel.hashes_of(result),
+            None => Vec::new(),
+        };
+        for finding in result.findings().filter(|finding| filter(finding.as_ref())) {
+            // additional information about the finding, as key in the `json` output, label
+            // in the `txt` output, description and value in the `json` output
+            let mut annotations: Vec<(&str, &str, String, serde_json::Value)> = Vec::new();
+            if let Some(attribution) = self
+                .threat_intel
+                .as_ref()
+                .and_then(|threat_intel| threat_intel.attribution(finding.as_ref(), &hashes))
+            {
+                annotations.push((
+                    "90_threat_intel",
+                    "threat intel",
+                    attribution.describe(),
+                    attribution.to_json(),
+                ));
+            }
+            let techniques = finding.attack_techniques();
+            if !techniques.is_empty() {
+                annotations.push((
+                    "92_attack",
+                    "ATT&CK",
+                    techniques.join(", "),
+                    serde_json::json!(techniques),
+                ));
+            }
+
+            match self.destination {
+                OutputDestination::Csv(ref mut wtr) => {
+                    let annotation = annotations
+                        .iter()
+                        .map(|(_, label, description, _)| format!("{}: {}", label, description))
+                        .collect::<Vec<_>>()
+                        .join("; ");
+                    for csv in finding.format_csv(&self.string_options) {
+                        match annotation.is_empty() {
+                            true => wtr.serialize(csv)?,
+                            false => wtr.serialize(csv.with_annotation(&annotation))?,
+                        }
+                    }
+                }
+                OutputDestination::Txt(ref mut wtr) => {
+                    write!(wtr, "{}", finding.format_readable(&self.string_options))?;
+                    for (_, label, description, _) in annotations {
+                        writeln!(wtr, "  {}: {}", label, description)?;
+                    }
+                }
+                OutputDestination::Json(ref mut wtr) => {
+                    let mut json = finding.to_json(&self.string_options);
+                    if let Some(object) = json.as_object_mut() {
+                        object.insert(
+                            "89_severity".to_owned(),
+                            finding.severity().to_string().into(),
+                        );
+                        for (key, _, _, value) in annotations {
+                            object.insert(key.to_owned(), value);
+                        }
+                    }
+                    write_json(wtr, &json, self.line_delimited)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// writes the errors which occurred during the scan of a file, so that files which
+    /// have not been scanned completely can be found in the output:
+    /// `error` lines in the `txt` output, `Error` lines in the `csv` output, and
+    /// objects with `"01_scanner": "error"` in the `json` output
+    pub fn print_errors(&mut self, result: &ScannerResult) -> Result<()> {
+        for error in result.errors() {
             match self.destination {
                 OutputDestination::Csv(ref mut wtr) => {
-                    let _ = finding.format_csv().into_iter().map(|csv| wtr.serialize(csv));
-                },
+                    let kind = ScanErrorKind::of(error).to_string();
+                    wtr.serialize(CsvLine::new("Error", &kind, result.filename(), error.clone()))?;
+                }
                 OutputDestination::Txt(ref mut wtr) => {
-                    let _ = write!(wtr, "{}", finding);
-                },
+                    writeln!(wtr, "error while scanning '{}': {}", result.filename(), error)?;
+                }
                 OutputDestination::Json(ref mut wtr) => {
-                    let _ = serde_json::to_writer(wtr, &finding.to_json());
+                    let error = serde_json::json!({
+                        "01_scanner": "error",
+                        "02_suspicious_file": result.filename(),
+                        "03_kind": ScanErrorKind::of(error).to_string(),
+                        "04_error": error
+                    });
+                    write_json(wtr, &error, self.line_delimited)?;
                 }
             }
         }
+        Ok(())
+    }
+
+    /// writes what VirusTotal and MalwareBazaar know about the file `filename`, which
+    /// has findings: a `hash lookup` line in the `txt` output, a `HashLookup` line in
+    /// the `csv` output and an object with `"01_scanner": "hash_lookup"` in the `json`
+    /// output
+    #[cfg(feature = "hash_lookup")]
+    pub fn print_hash_report(&mut self, filename: &str, report: &HashReport) -> Result<()> {
+        match self.destination {
+            OutputDestination::Csv(ref mut wtr) => {
+                let line = CsvLine::new("HashLookup", report.sha256(), filename, report.describe());
+                wtr.serialize(line)?;
+            }
+            OutputDestination::Txt(ref mut wtr) => {
+                writeln!(wtr, "hash lookup of '{}': {}", filename, report.describe())?;
+            }
+            OutputDestination::Json(ref mut wtr) => {
+                let report = serde_json::json!({
+                    "01_scanner": "hash_lookup",
+                    "02_suspicious_file": filename,
+                    "91_hash_lookup": report.to_json()
+                });
+                write_json(wtr, &report, self.line_delimited)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match self.destination {
+            OutputDestination::Csv(ref mut wtr) => wtr.flush(),
+            OutputDestination::Txt(ref mut wtr) => wtr.flush(),
+            OutputDestination::Json(ref mut wtr) => wtr.flush(),
+        }
+    }
+}
+
+/// writes `value`, and terminates it with a newline if the output is `line_delimited`
+fn write_json<W: Write>(
+    wtr: &mut W,
+    value: &serde_json::Value,
+    line_delimited: bool,
+) -> Result<()> {
+    serde_json::to_writer(&mut *wtr, value)?;
+    if line_delimited {
+        writeln!(wtr)?;
+    }
+    Ok(())
+}
+
+#[derive(ArgEnum, Clone)]
+pub(crate) enum YaraEngine {
+    Libyara,
+
+    #[cfg(feature = "yara_x")]
+    YaraX,
+}
+
+/// exit code if dionysos could not complete its task
+pub const EXIT_FATAL: i32 = 3;
+
+/// outcome of a run, which determines the exit code
+pub enum ScanStatus {
+    /// no findings and no errors
+    Clean,
+
+    /// findings have been reported (and no errors occurred)
+    Findings,
+
+    /// errors occurred, so that some files might not have been scanned completely
+    Errors,
+
+    /// the scan has been interrupted by SIGINT or SIGTERM
+    Interrupted,
+}
+
+impl ScanStatus {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ScanStatus::Clean => 0,
+            ScanStatus::Findings => 1,
+            ScanStatus::Errors => 2,
+            ScanStatus::Interrupted => EXIT_ABORTED,
+        }
     }
 }
 
+/// if no subcommand is given, `scan` is used
+#[derive(Subcommand, Clone)]
+pub(crate) enum Command {
+    /// scan the path with all scanners which are enabled by the options (default)
+    Scan,
+
+    /// write a manifest of every file below the path, containing its hashes, size and
+    /// timestamps, to FILE (in CSV format), without running any scanner. This is the same
+    /// as '--inventory', and can be used as a baseline for later scans
+    Baseline {
+        #[clap(value_name("FILE"))]
+        destination: String,
+    },
+
+    /// compare the findings of two scans (written with '--format json' or '--format ndjson',
+    /// or imported into SQLite) and report new, resolved and changed findings in the selected
+    /// output format. The exit code is 1 if there are new or changed findings
+    Diff {
+        #[clap(value_name("OLD_RESULTS"))]
+        old: PathBuf,
+
+        #[clap(value_name("NEW_RESULTS"))]
+        new: PathBuf,
+    },
+
+    /// render the findings of a scan (written with '--format json' or '--format ndjson', or
+    /// imported into SQLite) as a report, in which duplicate findings are merged, and the
+    /// findings are grouped by file and sorted by their severity
+    Report {
+        #[clap(value_name("RESULTS"))]
+        results: PathBuf,
+
+        /// write the report to FILE instead of stdout
+        #[clap(long("output"), value_name("FILE"))]
+        output: Option<PathBuf>,
+    },
+
+    /// print a completion script for SHELL to stdout, e.g.
+    /// 'dionysos completions bash > /etc/bash_completion.d/dionysos'
+    Completions {
+        #[clap(arg_enum, value_name("SHELL"))]
+        shell: Shell,
+    },
+
+    /// compile the yara ruleset and parse all hash lists, filename patterns and IoC files
+    /// which are specified by the options, without scanning anything. Every error is
+    /// reported with its file and line
+    Validate,
+
+    /// download all files given with '--feed' (e.g. yara rulesets or hash lists), and
+    /// replace the dionysos binary by the latest release, if '--binary' is given
+    Update {
+        /// replace the dionysos binary by the latest release, whose signature is verified
+        #[clap(long("binary"), requires("public_key"))]
+        binary: bool,
+
+        /// minisign public key, which is used to verify the signature of the release
+        #[clap(
+            long("public-key"),
+            env("DIONYSOS_UPDATE_PUBLIC_KEY"),
+            value_name("KEY")
+        )]
+        public_key: Option<String>,
+    },
+
+    /// run a server, which scans paths on this host on behalf of its clients, until it is
+    /// interrupted with Ctrl-C. Requests which do not specify any scanner use the yara
+    /// ruleset, hashes and filename patterns given by the options. This requires the
+    /// feature 'grpc' or 'http'
+    Serve(ServeOptions),
+
+    /// send a scan task to every agent which connects to ADDR, and write their findings
+    /// to stdout, until the controller is interrupted with Ctrl-C. The task consists of
+    /// the path, the yara ruleset (a single file or zip file), the hashes given with
+    /// '--file-hash' and the filename patterns
+    #[cfg(feature = "grpc")]
+    Controller {
+        #[clap(long("listen"), value_name("ADDR"))]
+        listen: SocketAddr,
+
+        #[clap(flatten)]
+        tls: TlsOptions,
+    },
+
+    /// connect to the controller at URL (e.g. 'https://controller:7778'), run its scan
+    /// tasks and report their findings back to the controller
+    #[cfg(feature = "grpc")]
+    Agent {
+        #[clap(long("controller"), value_name("URL"))]
+        controller: String,
+
+        #[clap(flatten)]
+        tls: TlsOptions,
+    },
+
+    /// run dionysos as a Windows service, which is controlled by the service control
+    /// manager and writes its log messages to the event log
+    #[cfg(windows)]
+    Service {
+        #[clap(subcommand)]
+        action: ServiceAction,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+#[cfg(windows)]
+pub(crate) enum ServiceAction {
+    /// install the service, which starts automatically and runs with the options given
+    /// on this command line (e.g. '--watch' or '--config'). Paths must be absolute
+    Install {
+        /// append the findings to FILE
+        #[clap(long("output"), value_name("FILE"))]
+        output: PathBuf,
+    },
+
+    /// stop and remove the service
+    Uninstall,
+
+    /// run the scan as a service. This is done by the service control manager
+    #[clap(hide(true))]
+    Run {
+        #[clap(long("output"), value_name("FILE"))]
+        output: PathBuf,
+    },
+}
+
+/// certificates for the mutual TLS authentication between the controller and its agents
+#[derive(Args, Clone)]
+#[cfg(feature = "grpc")]
+pub(crate) struct TlsOptions {
+    /// certificate of this host (PEM)
+    #[clap(long("tls-cert"), value_name("FILE"))]
+    pub(crate) cert: PathBuf,
+
+    /// private key of the certificate (PEM)
+    #[clap(long("tls-key"), value_name("FILE"))]
+    pub(crate) key: PathBuf,
+
+    /// CA certificate (PEM), which must have signed the certificate of the peer
+    #[clap(long("tls-ca"), value_name("FILE"))]
+    pub(crate) ca: PathBuf,
+}
+
+#[derive(Args, Clone)]
+pub(crate) struct ServeOptions {
+    /// address of the gRPC API, e.g. '127.0.0.1:7777' (see proto/dionysos.proto)
+    #[clap(long("grpc"), value_name("ADDR"))]
+    #[cfg(feature = "grpc")]
+    grpc: Option<SocketAddr>,
+
+    /// address of the REST API, e.g. '127.0.0.1:8080'
+    #[clap(long("http"), value_name("ADDR"))]
+    #[cfg(feature = "http")]
+    http: Option<SocketAddr>,
+}
+
 #[derive(Parser, Clone)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, args_override_self = true)]
 pub(crate) struct Cli {
-    #[clap(flatten)]
-    verbose: clap_verbosity_flag::Verbosity,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// more log messages on stderr, can be given several times
+    #[clap(global(true), short('v'), long("verbose"), parse(from_occurrences))]
+    verbose: u8,
+
+    /// read options from a configuration file (TOML or YAML), whose keys are the long
+    /// names of the options. Options given on the command line override the values
+    /// of the configuration file
+    #[clap(global(true), long("config"), value_name("FILE"), display_order(5))]
+    config: Option<PathBuf>,
 
     /// path which must be scanned
-    #[clap(short('P'), long("path"), display_order(10))]
+    #[clap(global(true), short('P'), long("path"), display_order(10))]
     path: Option<String>,
 
-    /// output format
-    #[clap(short('f'),long("format"), arg_enum, default_value_t=OutputFormat::Txt, display_order(20))]
-    pub(crate) output_format: OutputFormat,
+    /// only scan the artifacts (event logs, registry hives, browser data, ...) of the target
+    /// definitions in PATH (a KAPE target file, or a directory of them), instead of all files.
+    /// The paths of the targets are relative to '--path'
+    #[clap(global(true), long("targets"), value_name("PATH"), display_order(11))]
+    targets: Option<PathBuf>,
+
+    /// scan the files of '--targets', or copy them to '--collect', or both
+    #[clap(global(true), long("target-mode"), arg_enum, default_value_t=TargetMode::Scan, display_order(12))]
+    target_mode: TargetMode,
+
+    /// scan the files which are listed in FILE, one path per line, instead of the files
+    /// below '--path'. Empty lines and lines starting with '#' are ignored
+    #[clap(global(true), long("file-list"), value_name("FILE"), conflicts_with_all(&["targets", "disk_image", "s3", "dry_run", "estimate"]), display_order(13))]
+    file_list: Option<PathBuf>,
+
+    /// scan the files of the NTFS volumes in IMAGE (a disk image or a raw device), which
+    /// are read from the MFT without mounting the volumes. Compressed and encrypted files
+    /// are reported as errors
+    #[clap(global(true), long("disk-image"), value_name("IMAGE"), conflicts_with_all(&["targets", "s3", "dry_run", "estimate"]), display_order(14))]
+    disk_image: Option<PathBuf>,
+
+    /// scan the objects of an S3 bucket whose keys start with PREFIX, given as
+    /// 's3://BUCKET/PREFIX'. The credentials are read from AWS_ACCESS_KEY_ID,
+    /// AWS_SECRET_ACCESS_KEY and AWS_SESSION_TOKEN, the region from AWS_REGION, and
+    /// other S3 compatible storages are selected with AWS_ENDPOINT_URL
+    #[clap(global(true), long("s3"), value_name("URL"), conflicts_with_all(&["targets", "dry_run", "estimate"]), display_order(15))]
+    s3: Option<String>,
+
+    /// output format [default: txt]. The report subcommand writes 'html', 'xlsx' or 'md',
+    /// and takes the format from the extension of the output file by default
+    #[clap(global(true), short('f'),long("format"), arg_enum, value_name("FORMAT"), display_order(20))]
+    format: Option<Format>,
+
+    /// only write findings to the console: the banner, progress bars and the summary are
+    /// suppressed, and stdout contains nothing but the results in the selected output
+    /// format. Log messages are still written to stderr (or to the log file)
+    #[clap(global(true), short('q'), long("quiet"), display_order(25))]
+    quiet: bool,
+
+    /// write the findings of some scanners to another sink than stdout, given as
+    /// SCANNERS=SINK, e.g. 'hash,filename=https://siem.example.com/hook' or
+    /// 'yara=/secure/yara.zip'. SINK is a webhook URL, to which every finding is posted, a file,
+    /// to which the findings are appended, or '-' for stdout. This parameter can be specified
+    /// multiple times
+    #[clap(global(true), long("route"), value_name("SCANNERS=SINK"), parse(try_from_str = Route::parse), display_order(21))]
+    route: Vec<Route>,
+
+    /// encrypt the findings of routes to '.zip' files with PASSWORD (AES-256)
+    #[clap(global(true), long("route-password"), env("DIONYSOS_ROUTE_PASSWORD"), value_name("PASSWORD"), display_order(22))]
+    route_password: Option<String>,
+
+    /// suppress, downgrade or escalate findings according to the rules of a YAML policy
+    /// file, which match the rule name, the path, the hash or the severity of findings
+    #[clap(global(true), long("findings-policy"), value_name("FILE"), display_order(26))]
+    findings_policy: Option<PathBuf>,
+
+    /// annotate findings with the malware family, actor and campaign from a local threat
+    /// intelligence database (CSV or JSON), which maps hashes and rule names to them
+    #[clap(global(true), long("threat-intel"), value_name("FILE"), display_order(27))]
+    threat_intel: Vec<PathBuf>,
+
+    /// look up the SHA256 hashes of files with findings at VirusTotal with this API key. This
+    /// requires network access, and sends the hashes (but not the files) to VirusTotal
+    #[clap(global(true), long("virustotal-key"), env("DIONYSOS_VIRUSTOTAL_KEY"), value_name("KEY"), display_order(28))]
+    #[cfg(feature = "hash_lookup")]
+    virustotal_key: Option<String>,
+
+    /// look up the SHA256 hashes of files with findings at MalwareBazaar with this API key. This
+    /// requires network access, and sends the hashes (but not the files) to MalwareBazaar
+    #[clap(global(true), long("malwarebazaar-key"), env("DIONYSOS_MALWAREBAZAAR_KEY"), value_name("KEY"), display_order(28))]
+    #[cfg(feature = "hash_lookup")]
+    malwarebazaar_key: Option<String>,
+
+    /// maximum number of hash lookups per minute and service. The public VirusTotal API allows 4
+    #[clap(global(true), long("lookup-rate"), value_name("PER_MINUTE"), default_value_t = 4, display_order(29))]
+    #[cfg(feature = "hash_lookup")]
+    lookup_rate: u32,
+
+    /// maximum number of requests which are sent to the services. The public VirusTotal API allows
+    /// 500 lookups per day
+    #[clap(global(true), long("lookup-limit"), value_name("REQUESTS"), default_value_t = 500, display_order(29))]
+    #[cfg(feature = "hash_lookup")]
+    lookup_limit: usize,
+
+    /// only findings with at least this severity lead to exit code 1. Findings of yara
+    /// rules get their severity from the 'severity' or 'score' metadata of the rule
+    #[clap(global(true), long("min-severity"), arg_enum, default_value_t=Severity::Low, display_order(30))]
+    min_severity: Severity,
+
+    /// enable a scanner, even if none of its options is given. Scanners which need
+    /// a configuration (like the yara ruleset) cannot be enabled without it.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("enable"), arg_enum, value_name("SCANNER"), display_order(40))]
+    enable: Vec<ScannerKind>,
+
+    /// disable a scanner, even if its options are given (e.g. in a configuration file).
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("disable"), arg_enum, value_name("SCANNER"), display_order(41))]
+    disable: Vec<ScannerKind>,
+
+    /// list all scanners, and whether they would run with the given options and why
+    #[clap(global(true), long("list-scanners"), display_order(42))]
+    list_scanners: bool,
 
     /// use yara scanner with the specified ruleset. This can be a
     /// single file, a zip file or a directory containing lots of
     /// yara files. Yara files must end with 'yar' or 'yara', and zip
     /// files must end with 'zip'
-    #[clap(short('Y'), long("yara"), display_order(100))]
+    #[clap(global(true), short('Y'), long("yara"), display_order(100))]
     yara: Option<String>,
 
+    /// password of the zip file which contains the yara ruleset
+    #[clap(
+        global(true),
+        long("yara-zip-password"),
+        env("DIONYSOS_YARA_ZIP_PASSWORD"),
+        hide_env_values(true),
+        display_order(104)
+    )]
+    yara_zip_password: Option<String>,
+
+    /// engine which is used to evaluate the yara rules
+    #[clap(global(true), long("engine"), arg_enum, default_value_t=YaraEngine::Libyara, display_order(105))]
+    yara_engine: YaraEngine,
+
     /// timeout for the yara scanner, in seconds
-    #[clap(long("yara-timeout"), default_value_t = 240, display_order(110))]
+    #[clap(global(true), long("yara-timeout"), default_value_t = 240, display_order(110))]
     yara_timeout: u16,
 
+    /// abort the scan of a file if all scanners together need more than SECONDS
+    /// for it. The file is reported as an error
+    #[clap(global(true), long("file-timeout"), value_name("SECONDS"), display_order(111))]
+    file_timeout: Option<u64>,
+
+    /// log a warning if a worker has been scanning the same file for more than SECONDS,
+    /// together with the scanner which is running
+    #[clap(global(true), long("stuck-threshold"), value_name("SECONDS"), default_value_t = 600, display_order(112))]
+    stuck_threshold: u64,
+
+    /// skip files which have been scanned for more than the time given by
+    /// '--stuck-threshold'. They are reported as errors
+    #[clap(global(true), long("skip-stuck-files"), display_order(113))]
+    skip_stuck_files: bool,
+
+    /// fail if the yara ruleset produces any compiler warnings. All warnings
+    /// are listed with their source file and line
+    #[clap(global(true), long("yara-strict"), display_order(115))]
+    yara_strict: bool,
+
+    /// display per-rule statistics (matched files, matches and average scan time
+    /// of the matched files) at the end of the scan
+    #[clap(global(true), long("rule-stats"), display_order(116))]
+    rule_statistics: bool,
+
     /// print matching strings (only used by yara currently)
-    #[clap(short('s'), long("print-strings"), display_order(120))]
+    #[clap(global(true), short('s'), long("print-strings"), display_order(120))]
     pub(crate) print_strings: bool,
 
+    /// number of bytes before and after every string match which are displayed as
+    /// hexdump (only used together with --print-strings)
+    #[clap(global(true), long("string-context"), default_value_t = 16, display_order(125))]
+    string_context: usize,
+
+    /// maximum number of bytes which are displayed per matching string
+    #[clap(global(true), long("max-string-length"), display_order(126))]
+    max_string_length: Option<usize>,
+
+    /// maximum number of matching strings which are displayed per finding
+    #[clap(global(true), long("max-strings"), display_order(127))]
+    max_strings: Option<usize>,
+
+    /// encoding of matching strings
+    #[clap(global(true), long("string-encoding"), arg_enum, default_value_t=StringEncoding::Escaped, display_order(128))]
+    string_encoding: StringEncoding,
+
+    /// scan the events of this channel of the live event log (e.g. 'Security') with yara.
+    /// Events are rendered as XML before they are scanned. This parameter can be
+    /// specified multiple times
+    #[clap(global(true), long("live-evtx"), value_name("CHANNEL"), display_order(129))]
+    #[cfg(windows)]
+    live_evtx: Vec<String>,
+
     /// also do YARA scan in Windows EVTX records (exported as JSON)
-    #[clap(long("evtx"), display_order(130))]
+    #[clap(global(true), long("evtx"), display_order(130))]
     #[cfg(feature = "scan_evtx")]
     pub(crate) yara_scan_evtx: bool,
 
+    /// only scan evtx records with this event id. This parameter can be specified multiple times
+    #[clap(global(true), long("evtx-event-id"), value_name("EVENT_ID"), display_order(131))]
+    #[cfg(feature = "scan_evtx")]
+    evtx_event_id: Vec<u64>,
+
+    /// only scan evtx records of this channel, e.g. 'Security'. This parameter can be
+    /// specified multiple times
+    #[clap(global(true), long("evtx-channel"), value_name("CHANNEL"), display_order(132))]
+    #[cfg(feature = "scan_evtx")]
+    evtx_channel: Vec<String>,
+
+    /// only scan evtx records which have been written at or after TIMESTAMP
+    /// (RFC 3339 or YYYY-MM-DD)
+    #[clap(global(true), long("evtx-after"), value_name("TIMESTAMP"), parse(try_from_str = parse_timestamp), display_order(133))]
+    #[cfg(feature = "scan_evtx")]
+    evtx_after: Option<DateTime<Utc>>,
+
+    /// only scan evtx records which have been written at or before TIMESTAMP
+    /// (RFC 3339 or YYYY-MM-DD)
+    #[clap(global(true), long("evtx-before"), value_name("TIMESTAMP"), parse(try_from_str = parse_timestamp), display_order(134))]
+    #[cfg(feature = "scan_evtx")]
+    evtx_before: Option<DateTime<Utc>>,
+
     /// also do YARA scan in Windows registry hive files
-    #[clap(long("reg"), display_order(130))]
+    #[clap(global(true), long("reg"), display_order(135))]
     #[cfg(feature = "scan_reg")]
     pub(crate) yara_scan_reg: bool,
 
-    /// allow yara to scan compressed files. Currently, xz, bz2 and gz are supported
-    #[clap(short('C'), long("scan-compressed"), display_order(140))]
+    /// only scan the values of registry keys which have been written at or after TIMESTAMP
+    /// (RFC 3339 or YYYY-MM-DD)
+    #[clap(global(true), long("reg-after"), value_name("TIMESTAMP"), parse(try_from_str = parse_timestamp), display_order(136))]
+    #[cfg(feature = "scan_reg")]
+    reg_after: Option<DateTime<Utc>>,
+
+    /// only scan the values of registry keys which have been written at or before TIMESTAMP
+    /// (RFC 3339 or YYYY-MM-DD)
+    #[clap(global(true), long("reg-before"), value_name("TIMESTAMP"), parse(try_from_str = parse_timestamp), display_order(137))]
+    #[cfg(feature = "scan_reg")]
+    reg_before: Option<DateTime<Utc>>,
+
+    /// report registry keys and values which match the IoCs in FILE. FILE is a CSV file with
+    /// the columns 'name', 'key', 'value_name' and 'value_data'. This parameter can be
+    /// specified multiple times
+    #[clap(global(true), long("reg-ioc"), value_name("FILE"), display_order(138))]
+    #[cfg(feature = "scan_reg")]
+    reg_ioc: Vec<PathBuf>,
+
+    /// also do YARA scan in the records of ESE databases, like WebCacheV01.dat, SRUDB.dat
+    /// or Windows.edb. Every record is serialized as JSON before it is scanned
+    #[clap(global(true), long("ese"), display_order(139))]
+    #[cfg(feature = "scan_ese")]
+    yara_scan_ese: bool,
+
+    /// scan evtx files, registry hives and ESE databases as raw files, instead of parsing them. Such files
+    /// are otherwise detected by their signature, regardless of their name
+    #[clap(global(true), long("force-raw"), display_order(140))]
+    force_raw: bool,
+
+    /// allow yara to scan compressed files and the payloads of installers. Currently, xz, bz2,
+    /// gz, zip, 7z, split archives (.001, .002, ..., .part1.rar, ...), MSI, CAB and
+    /// self-extracting installers (ZIP, CAB and NSIS) are supported
+    #[clap(global(true), short('C'), long("scan-compressed"), display_order(141))]
     scan_compressed: bool,
 
+    /// file with passwords (one per line), which are tried to decrypt encrypted zip and 7z
+    /// archives, in addition to 'infected', 'malware' and 'virus'
+    #[clap(global(true), long("archive-passwords"), value_name("FILE"), display_order(142))]
+    archive_passwords: Option<PathBuf>,
+
+    /// scan only the unallocated clusters of disk images and raw devices with NTFS volumes,
+    /// and the PE, ZIP and PDF files which are carved from them. Raw devices are otherwise skipped
+    #[clap(global(true), long("unallocated"), display_order(143))]
+    unallocated: bool,
+
+    /// decode mails (.eml and Outlook .msg), and scan their bodies and attachments with all
+    /// scanners which can scan data in memory
+    #[clap(global(true), long("scan-mails"), display_order(144))]
+    scan_mails: bool,
+
+    /// maximum size (in MiB) of mails whose bodies and attachments are scanned
+    #[clap(global(true), long("max-mail-size"), value_name("MIB"), default_value_t = mail::DEFAULT_MAX_SIZE, display_order(145))]
+    max_mail_size: u64,
+
     /// maximum size (in MiB) of decompression buffer (per thread), which is used to scan compressed files
     #[clap(
+        global(true),
         long("decompression-buffer"),
         default_value_t = 128,
         display_order(150)
     )]
     decompression_buffer_size: usize,
 
-    /// Hash of file to match against. Use any of MD5, SHA1 or SHA256.
-    /// This parameter can be specified multiple times
-    #[clap(short('H'), long("file-hash"), display_order(200))]
+    /// Hash of file to match against. Use any of MD5, SHA1, SHA256, SHA384 or SHA512,
+    /// which are detected by their length, or prefix the hash with its type, e.g.
+    /// 'blake3:<hash>'. Import hashes and Authenticode hashes of PE files can be specified
+    /// as 'imphash:<hash>' and 'authenticode:<hash>'. This parameter can be specified multiple times
+    #[clap(global(true), short('H'), long("file-hash"), display_order(200))]
     file_hash: Vec<String>,
 
+    /// file containing hashes to match against, e.g. a plain list with one hash
+    /// per line, a CSV file or a Loki IoC file. Lines starting with '#' are ignored.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("file-hash-list"), display_order(201))]
+    file_hash_list: Vec<String>,
+
+    /// directory containing hash sets to match against. Every *.txt and *.csv file
+    /// in this directory is read like a file passed to '--file-hash-list', and findings
+    /// are tagged with the name of the file which contained the hash.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("hash-dir"), display_order(202))]
+    hash_dir: Vec<String>,
+
+    /// only hash the first MIB mebibytes of larger files, which speeds up the triage of
+    /// large files like VM images. Findings based on such a partial hash are marked in
+    /// the output. This also applies to '--inventory'
+    #[clap(global(true), long("partial-hash"), value_name("MIB"), display_order(203))]
+    partial_hash: Option<u64>,
+
+    /// import hash of PE files to match against. Unlike '--file-hash', the hash does not
+    /// need the 'imphash:' prefix. This parameter can be specified multiple times
+    #[clap(global(true), long("imphash"), value_name("HASH"), display_order(204))]
+    imphash: Vec<String>,
+
+    /// file containing hashes of known-good files, e.g. NSRLFile.txt of the NSRL RDS.
+    /// Findings of known-good files are not reported. This parameter can be specified
+    /// multiple times
+    #[clap(global(true), long("known-good"), display_order(205))]
+    known_good: Vec<String>,
+
+    /// how known-good files are handled. 'skip' hashes every file before scanning it, which
+    /// is faster if expensive scanners (like yara) are used
+    #[clap(global(true), long("known-good-mode"), arg_enum, default_value_t=KnownGoodMode::Suppress, display_order(206))]
+    known_good_mode: KnownGoodMode,
+
+    /// hash of a file whose findings will not be reported, e.g. because it
+    /// is known to trigger false positives. This parameter can be specified multiple times
+    #[clap(global(true), long("ignore-hash"), display_order(207))]
+    ignore_hash: Vec<String>,
+
+    /// file containing hashes of files whose findings will not be reported.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("ignore-hash-list"), display_order(208))]
+    ignore_hash_list: Vec<String>,
+
     /// regular expression to match against the basename of files.
     /// This parameter can be specified multiple times
-    #[clap(short('F'), long("filename"), display_order(210))]
+    #[clap(global(true), short('F'), long("filename"), display_order(210))]
     filenames: Vec<String>,
 
+    /// ssdeep hash of a known file, e.g. of a malware sample. Files which are similar
+    /// to it are reported, even if their cryptographic hashes differ.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("fuzzy-hash"), value_name("SSDEEP"), display_order(211))]
+    #[cfg(feature = "fuzzy_hash")]
+    fuzzy_hash: Vec<String>,
+
+    /// file containing ssdeep hashes, with one hash per line, or the output of 'ssdeep'.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("fuzzy-hash-list"), value_name("FILE"), display_order(212))]
+    #[cfg(feature = "fuzzy_hash")]
+    fuzzy_hash_list: Vec<String>,
+
+    /// minimum similarity score (between 0 and 100) of files which are reported by the
+    /// ssdeep scanner
+    #[clap(global(true), long("fuzzy-threshold"), value_name("SCORE"), default_value_t = fuzzy_hash_scanner::DEFAULT_THRESHOLD, display_order(213))]
+    #[cfg(feature = "fuzzy_hash")]
+    fuzzy_threshold: u32,
+
+    /// TLSH digest of a known file, e.g. of a malware sample. Files which are similar
+    /// to it are reported. This parameter can be specified multiple times
+    #[clap(global(true), long("tlsh"), value_name("DIGEST"), display_order(214))]
+    tlsh: Vec<String>,
+
+    /// file containing TLSH digests, with one digest per line, optionally followed by a
+    /// comma and the name of the known file. This parameter can be specified multiple times
+    #[clap(global(true), long("tlsh-list"), value_name("FILE"), display_order(215))]
+    tlsh_list: Vec<String>,
+
+    /// maximum distance of the TLSH digests of files which are reported by the TLSH
+    /// scanner. A distance of 0 means that the files are (almost) identical
+    #[clap(global(true), long("tlsh-distance"), value_name("DISTANCE"), default_value_t = tlsh_scanner::DEFAULT_DISTANCE, display_order(216))]
+    tlsh_distance: u32,
+
+    /// report files with a high entropy, which are probably packed or encrypted. Of PE
+    /// and ELF files, only the sections which contain code are checked
+    #[clap(global(true), long("entropy"), display_order(217))]
+    entropy: bool,
+
+    /// entropy (in bits per byte, between 0 and 8) above which files and sections are
+    /// reported by the entropy scanner
+    #[clap(global(true), long("entropy-threshold"), value_name("BITS"), parse(try_from_str = entropy_scanner::parse_threshold), default_value_t = entropy_scanner::DEFAULT_THRESHOLD, display_order(218))]
+    entropy_threshold: f64,
+
+    /// report files whose content does not match their extension, like an executable
+    /// which is named invoice.pdf
+    #[clap(global(true), long("extension-mismatch"), display_order(219))]
+    extension_mismatch: bool,
+
     /// run the Levenshtein scanner
-    #[clap(long("levenshtein"), display_order(220))]
+    #[clap(global(true), long("levenshtein"), display_order(220))]
     levenshtein: bool,
 
+    /// filename to be protected by the Levenshtein scanner, instead of the builtin list
+    /// of well-known filenames. Use 'name:distance' to override the maximum distance for
+    /// this filename. This implies '--levenshtein'.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("levenshtein-target"), display_order(221))]
+    levenshtein_target: Vec<String>,
+
+    /// file containing filenames to be protected by the Levenshtein scanner (one per line),
+    /// instead of the builtin list of well-known filenames. This implies '--levenshtein'.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("levenshtein-targets-file"), display_order(222))]
+    levenshtein_targets_file: Vec<String>,
+
+    /// maximum Levenshtein distance of a filename to a protected filename. Short filenames
+    /// should use a distance of 1, because larger distances cause many false positives
+    #[clap(global(true), long("levenshtein-distance"), value_name("DISTANCE"), default_value_t = 1, display_order(223))]
+    levenshtein_distance: usize,
+
+    /// path of a legitimate file which must not be reported by the Levenshtein scanner.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("levenshtein-allow"), value_name("PATH"), display_order(224))]
+    levenshtein_allow: Vec<String>,
+
+    /// report files which have the name of a system binary (like svchost.exe), but reside
+    /// outside of its expected directory and differ from the original system binary
+    #[clap(global(true), long("masquerading"), display_order(225))]
+    masquerading: bool,
+
+    /// report directories with signs of ransomware: files which have been renamed to an
+    /// unusual extension, files whose content looks encrypted, and ransom notes
+    #[clap(global(true), long("ransomware"), display_order(226))]
+    ransomware: bool,
+
+    /// report files and directories in system locations which are writable by everyone
+    /// (by their mode or ACL), and executable or setuid files of root in temp directories
+    #[clap(global(true), long("permissions"), display_order(227))]
+    permissions: bool,
+
+    /// load scanner plugins (dynamic libraries, see plugins/dionysos_plugin.h) from DIR.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("plugin-dir"), value_name("DIR"), display_order(228))]
+    plugin_dir: Vec<PathBuf>,
+
+    /// report PE files whose Authenticode signature is invalid, revoked, expired or
+    /// untrusted, and unsigned or unverified PE files in system directories
+    #[clap(global(true), long("authenticode"), display_order(229))]
+    authenticode: bool,
+
+    /// report PE files with writable and executable sections, sections of known packers,
+    /// large overlays, TLS callbacks or unusual entry points
+    #[clap(global(true), long("pe-anomalies"), display_order(231))]
+    pe_anomalies: bool,
+
+    /// report ELF files with injected segments, unusual program interpreters or executable
+    /// stacks, and stripped executables in temp directories
+    #[clap(global(true), long("elf-anomalies"), display_order(232))]
+    elf_anomalies: bool,
+
+    /// report Mach-O files with ad-hoc or invalid code signatures, kernel extensions which
+    /// are not signed by Apple or a Developer ID, and libraries which are loaded from unusual
+    /// paths
+    #[clap(global(true), long("macho-anomalies"), display_order(233))]
+    macho_anomalies: bool,
+
+    /// write a manifest of every scanned file, containing its hashes, size and timestamps,
+    /// to FILE (in CSV format). This can be used as a baseline for later scans
+    #[clap(global(true), long("inventory"), value_name("FILE"), display_order(230))]
+    inventory: Option<String>,
+
+    /// write an audit manifest of the scan to FILE, which contains the version of dionysos,
+    /// the command line, the hashes of the ruleset and of all IoC files, the host, the start
+    /// and end time, and every scanned or skipped file (with the reason). The SHA-256 hash
+    /// of the manifest is written to FILE.sha256
+    #[clap(global(true), long("audit"), value_name("FILE"), display_order(235))]
+    audit: Option<PathBuf>,
+
+    /// additionally write all findings and the metadata of their files to '--export-dir',
+    /// in the layout of a Velociraptor offline collection or of the output of a KAPE module
+    #[clap(global(true), long("export"), arg_enum, requires("export_dir"), display_order(237))]
+    export: Option<ExportLayout>,
+
+    /// directory which is written by '--export'
+    #[clap(global(true), long("export-dir"), value_name("DIR"), display_order(238))]
+    export_dir: Option<PathBuf>,
+
+    /// copy every file with findings to DIR, together with a manifest of their original
+    /// paths, hashes and timestamps. DIR is not scanned
+    #[clap(global(true), long("collect"), value_name("DIR"), display_order(239))]
+    collect: Option<PathBuf>,
+
+    /// store every collected file in a zip archive, which is encrypted with PASSWORD,
+    /// so that antivirus software does not remove the samples
+    #[clap(global(true), long("collect-password"), value_name("PASSWORD"), requires("collect"), display_order(240))]
+    collect_password: Option<String>,
+
     /// use the specified NUMBER of threads
-    #[clap(short('p'), long("threads"), default_value_t = num_cpus::get(), display_order(300))]
+    #[clap(global(true), short('p'), long("threads"), default_value_t = num_cpus::get(), display_order(300))]
     threads: usize,
 
+    /// slow down the scan according to a SCHEDULE of local times and the share of the time
+    /// in which the workers may scan, e.g. '22:00-06:00=100%,20%' to scan at full speed at
+    /// night and 20% of the time otherwise. A budget of 0% pauses the scan
+    #[clap(global(true), long("throttle"), value_name("SCHEDULE"), parse(try_from_str = ThrottleSchedule::parse), display_order(305))]
+    throttle: Option<ThrottleSchedule>,
+
     /// display a progress bar (requires counting the number of files to be scanned before a progress bar can be displayed)
-    #[clap(long("progress"), display_order(310))]
+    #[clap(global(true), long("progress"), display_order(310))]
     pub(crate) display_progress: bool,
 
+    /// write progress events (discovered and completed files, the current file, the number
+    /// of findings and the ETA) as JSON to DEST every second, one object per line. DEST is
+    /// a file or named pipe, or '-' for stderr
+    #[clap(global(true), long("progress-json"), value_name("DEST"), display_order(311))]
+    progress_json: Option<PathBuf>,
+
+    /// display an interactive view of the scan on stderr, which shows the file every worker is
+    /// scanning, the throughput and all findings (including their matching strings). The scan
+    /// can be paused, and the results of a file which takes too long can be discarded.
+    /// stdout must be redirected, and log messages are only written to the '--log-file'
+    #[clap(global(true), long("tui"), display_order(315))]
+    #[cfg(feature = "tui")]
+    tui: bool,
+
+    /// keep running and rescan the path every SECONDS seconds. Changes of the
+    /// yara ruleset are detected and loaded without a restart
+    #[clap(global(true), long("watch"), value_name("SECONDS"), display_order(320))]
+    watch: Option<u64>,
+
+    /// if the scan is interrupted (by Ctrl-C or SIGTERM), wait up to SECONDS seconds for
+    /// files which are currently being scanned. Their results are written before dionysos
+    /// exits. Interrupting dionysos a second time exits immediately
+    #[clap(global(true), long("grace-period"), value_name("SECONDS"), default_value_t = 10, display_order(325))]
+    grace_period: u64,
+
+    /// only list the files which would be scanned, and the scanners which would run on
+    /// them, without reading their content. The list is written in the selected output
+    /// format. '--known-good' is not applied, because it requires hashing the files
+    #[clap(global(true), long("dry-run"), display_order(330))]
+    dry_run: bool,
+
+    /// walk the target, and report the number and size of the files which would be
+    /// scanned, by extension and size. The duration of the scan is predicted by scanning
+    /// a small sample of the files with the selected scanners
+    #[clap(global(true), long("estimate"), display_order(333))]
+    estimate: bool,
+
+    /// also scan files whose content is not stored locally, like OneDrive placeholders.
+    /// Reading them downloads or recalls their content. Sockets, FIFOs and device
+    /// nodes are never scanned
+    #[clap(global(true), long("scan-placeholders"), display_order(331))]
+    scan_placeholders: bool,
+
+    /// scan only one of all files with identical content, and report its findings for
+    /// every copy. All files are enumerated, and files of the same size are hashed
+    /// (with BLAKE3), before the scan starts
+    #[clap(global(true), long("dedup"), display_order(332))]
+    dedup: bool,
+
+    /// download FILE from URL when running 'dionysos update', given as FILE=URL. This
+    /// can be used to keep yara rulesets, hash lists and IoC files up to date.
+    /// This parameter can be specified multiple times
+    #[clap(global(true), long("feed"), value_name("FILE=URL"), parse(try_from_str = Feed::parse), display_order(340))]
+    feeds: Vec<Feed>,
+
     /// path of the file to write error logs to. Error logs will always be appended
     /// Be aware that this are not the results (e.g. matching yara rules) of this program.
-    #[clap(short('L'), long("log-file"), display_order(520))]
+    #[clap(global(true), short('L'), long("log-file"), display_order(520))]
     log_file: Option<String>,
+
+    /// export traces of the scan (the enumeration of files, and the time which every
+    /// scanner spends on every file) to the OpenTelemetry collector at URL, using OTLP
+    /// over gRPC (e.g. 'http://localhost:4317')
+    #[clap(global(true), long("otlp-endpoint"), value_name("URL"), display_order(530))]
+    #[cfg(feature = "otel")]
+    otlp_endpoint: Option<String>,
+}
+
+impl Cli {
+    /// errors are logged by default, and every `--verbose` adds the next level
+    fn log_level(&self) -> LevelFilter {
+        match self.verbose {
+            0 => LevelFilter::Error,
+            1 => LevelFilter::Warn,
+            2 => LevelFilter::Info,
+            3 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+
+    /// the format of the findings, and of the output of all subcommands except `report`
+    fn output_format(&self) -> Result<OutputFormat> {
+        match self.format {
+            None | Some(Format::Txt) => Ok(OutputFormat::Txt),
+            Some(Format::Csv) => Ok(OutputFormat::Csv),
+            Some(Format::Json) => Ok(OutputFormat::Json),
+            Some(Format::Ndjson) => Ok(OutputFormat::Ndjson),
+            Some(Format::Html | Format::Xlsx | Format::Md) => Err(anyhow!(
+                "the formats 'html', 'xlsx' and 'md' are only supported by the report subcommand"
+            )),
+        }
+    }
+
+    /// the format of a report, or `None` if it is taken from the output file
+    fn report_format(&self) -> Result<Option<ReportFormat>> {
+        match self.format {
+            None => Ok(None),
+            Some(Format::Html) => Ok(Some(ReportFormat::Html)),
+            Some(Format::Xlsx) => Ok(Some(ReportFormat::Xlsx)),
+            Some(Format::Md) => Ok(Some(ReportFormat::Md)),
+            Some(Format::Csv | Format::Txt | Format::Json | Format::Ndjson) => Err(anyhow!(
+                "reports can only be written as 'html', 'xlsx' or 'md'"
+            )),
+        }
+    }
+
+    fn string_options(&self) -> StringOptions {
+        StringOptions::default()
+            .with_print_strings(self.print_strings)
+            .with_max_length(self.max_string_length)
+            .with_max_strings(self.max_strings)
+            .with_encoding(self.string_encoding)
+    }
 }
 
 pub struct Dionysos {
     path: PathBuf,
+    targets: Option<Targets>,
     loglevel: LevelFilter,
     yara_rules: Option<PathBuf>,
     filenames: Vec<regex::Regex>,
     cli: Cli,
+
+    /// number of errors which occurred during the scan
+    errors: Arc<AtomicUsize>,
+
+    /// special files which have been skipped during the walk
+    special_files: SpecialFiles,
+
+    /// compressed files which have been scanned, or could not be decompressed
+    compressed_files: Arc<CompressedFiles>,
+
+    audit: Option<Arc<AuditManifest>>,
+
+    /// is triggered by the service control manager, if dionysos runs as Windows service.
+    /// Otherwise, SIGINT and SIGTERM are handled
+    interrupt: Option<Arc<Interrupt>>,
+
+    /// aborts the running scans when the grace period of an interrupt has elapsed
+    handle: ScanHandle,
 }
 
-fn handle_file(
-    scanners: &Arc<Vec<Box<dyn FileScanner>>>,
-    entry: &walkdir::DirEntry,
-) -> ScannerResult {
-    let mut result = ScannerResult::from(entry.path());
-    for scanner in scanners.iter() {
-        log::trace!(
-            "starting {} on {}",
-            scanner,
-            entry.file_name().to_string_lossy()
-        );
-        let begin = Instant::now();
+/// displays what a worker is doing, either as spinner or in the TUI, and throttles it
+struct WorkerStatus {
+    spinner: Option<ProgressBar>,
 
-        for res in scanner.scan_file(entry).into_iter() {
-            match res {
-                Err(why) => {
-                    log::error!("{}", why);
-                }
+    /// the progress bar which is shared by all workers
+    progress: Option<Arc<ProgressBar>>,
+
+    progress_events: Option<Arc<ProgressEvents>>,
+
+    #[cfg(feature = "tui")]
+    tui: Option<(Arc<TuiState>, usize)>,
+
+    activity: Arc<WorkerActivity>,
+    interrupt: Arc<Interrupt>,
+    throttle: Option<Arc<Throttle>>,
+}
+
+impl WorkerHooks for WorkerStatus {
+    fn activity(&self) -> Option<Arc<WorkerActivity>> {
+        Some(Arc::clone(&self.activity))
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.interrupt.is_interrupted()
+    }
 
-                Ok(res) => {
-                    log::trace!(
-                        "new finding from {} for {}",
-                        scanner,
-                        entry.path().display()
-                    );
-                    result.add_finding(res);
+    /// waits while the scan is paused
+    fn start_file(&self, file: &ProvidedFile) {
+        let name = file.name();
+        #[cfg(feature = "tui")]
+        if let Some((tui, id)) = &self.tui {
+            while tui.is_paused() {
+                thread::sleep(Duration::from_millis(100));
+            }
+            tui.start_file(*id, name.clone());
+        }
+        if let Some(s) = &self.spinner {
+            match file {
+                ProvidedFile::Entry(entry) => {
+                    s.set_message(entry.file_name().to_string_lossy().to_string())
                 }
+                ProvidedFile::Buffer { name, .. } => s.set_message(name.clone()),
             }
         }
+        if let Some(p) = &self.progress {
+            p.inc(1);
+        }
+        if let Some(events) = &self.progress_events {
+            events.start_file(Path::new(&name));
+        }
+    }
 
-        log::trace!(
-            "finished {} on {} in {}s",
-            scanner,
-            entry.file_name().to_string_lossy(),
-            Instant::now().duration_since(begin).as_secs_f64()
-        );
+    /// returns `false` if the user asked to skip the file, so that its results
+    /// must be discarded
+    fn finish_file(&self, elapsed: Duration) -> bool {
+        if let Some(throttle) = &self.throttle {
+            throttle.pause(elapsed, &self.interrupt);
+        }
+        #[cfg(feature = "tui")]
+        if let Some((tui, id)) = &self.tui {
+            return tui.finish_file(*id);
+        }
+        true
+    }
+
+    fn finish(&self) {
+        if let Some(s) = &self.spinner {
+            s.finish_and_clear();
+        }
     }
-    result
 }
 
-fn worker(
-    rx: spmc::Receiver<walkdir::DirEntry>,
-    tx: mpsc::Sender<ScannerResult>,
-    scanners: Arc<Vec<Box<dyn FileScanner>>>,
-    mystatus: Option<ProgressBar>,
-    progress: Option<Arc<ProgressBar>>,
-) {
-    let rx_ref = &rx;
-    let tx_ref = &tx;
-    loop {
-        match rx_ref.try_recv() {
-            Ok(entry) => {
-                if let Some(s) = &mystatus {
-                    s.set_message(entry.file_name().to_string_lossy().to_string());
-                }
-                if let Some(p) = &progress {
-                    p.inc(1);
-                }
+/// checks if `entry` is a block device with an NTFS volume, whose unallocated space is
+/// scanned with `--unallocated`. Other devices (like `/dev/zero` or `/dev/tty`) are never
+/// read, because reading them would not end or would block
+fn is_ntfs_device(entry: &walkdir::DirEntry) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if !entry.file_type().is_block_device() {
+            return false;
+        }
+    }
+    !ntfs_volumes(entry.path()).is_empty()
+}
 
-                let result = handle_file(&scanners, &entry);
+/// checks if `entry` is the directory `dir`, which must be canonical. Only directories
+/// with the same name are canonicalized, to keep the walk fast
+fn is_same_dir(entry: &walkdir::DirEntry, dir: &Path) -> bool {
+    entry.file_type().is_dir()
+        && entry.file_name() == dir.file_name().unwrap_or_default()
+        && entry.path().canonicalize().map_or(false, |path| path == dir)
+}
 
-                if let Err(why) = tx_ref.send(result) {
-                    log::error!(
-                        "error while sending a scanner result from the worker: {}",
-                        why
-                    );
-                    if let Some(s) = mystatus {
-                        s.finish_and_clear();
-                    }
-                    drop(rx);
-                    drop(tx);
-                    return;
-                }
-            }
-            Err(mpsc::TryRecvError::Empty) => {
-                thread::sleep(Duration::from_millis(100));
-                continue;
-            }
-            Err(mpsc::TryRecvError::Disconnected) => {
-                if let Some(s) = &mystatus {
-                    s.finish_and_clear();
-                }
-                drop(rx);
-                drop(tx);
-                return;
+/// checks if the findings of a file must be ignored because of its hash
+fn is_ignored(ignored_hashes: &Option<HashList>, result: &ScannerResult) -> bool {
+    let hashes = match ignored_hashes {
+        Some(hashes) => hashes,
+        None => return false,
+    };
+    match hashes.matching_hashes_of_result(result) {
+        Ok(matching_hashes) => {
+            if let Some(hash) = matching_hashes.first() {
+                log::info!(
+                    "ignoring the findings of '{}' because of its hash {}",
+                    result.filename(),
+                    hash
+                );
             }
+            !matching_hashes.is_empty()
+        }
+        Err(why) => {
+            log::warn!("unable to hash '{}': {}", result.filename(), why);
+            false
+        }
+    }
+}
+
+/// interval (in seconds) in which the ruleset is checked for changes
+const RULESET_POLL_INTERVAL: u64 = 5;
+
+/// the scanners which are used by `dionysos serve` for requests which do not specify any
+#[cfg(any(feature = "grpc", feature = "http"))]
+fn server_defaults(cli: &Cli) -> ScanRequest {
+    ScanRequest {
+        path: String::new(),
+        yara: cli.yara.clone(),
+        hashes: cli.file_hash.clone(),
+        hash_lists: cli.file_hash_list.clone(),
+        filenames: cli.filenames.clone(),
+        known_good: cli.known_good.clone(),
+        threads: cli.threads,
+        ..ScanRequest::default()
+    }
+}
+
+fn reload_scanners(scanners: &[Box<dyn FileScanner>]) {
+    for scanner in scanners.iter() {
+        if let Err(why) = scanner.reload() {
+            log::error!(
+                "unable to reload {}, keeping the previous configuration: {}",
+                scanner,
+                why
+            );
+        }
+    }
+}
+
+/// reloads the rulesets of all scanners when SIGHUP is received
+fn reload_on_sighup(reload: Arc<AtomicBool>, scanners: Arc<Vec<Box<dyn FileScanner>>>) {
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        if reload.swap(false, Ordering::Relaxed) {
+            log::info!("received SIGHUP, reloading the rulesets");
+            systemd::notify_reloading();
+            reload_scanners(&scanners);
+            systemd::notify_ready();
+        }
+    }
+}
+
+fn watch_ruleset(ruleset: YaraRuleset, scanners: Arc<Vec<Box<dyn FileScanner>>>) {
+    let mut last_modified = ruleset.last_modified();
+    loop {
+        thread::sleep(Duration::from_secs(RULESET_POLL_INTERVAL));
+
+        let modified = ruleset.last_modified();
+        if modified == last_modified {
+            continue;
         }
+        last_modified = modified;
+
+        log::info!(
+            "the yara ruleset in '{}' has changed",
+            ruleset.path().display()
+        );
+        reload_scanners(&scanners);
     }
 }
 
@@ -284,83 +1375,1253 @@ impl Dionysos {
         Self::parse_options()
     }
 
-    pub fn run(&self) -> Result<()> {
+    /// runs the scan with `interrupt`, instead of handling SIGINT and SIGTERM
+    #[cfg(windows)]
+    pub(crate) fn with_interrupt(mut self, interrupt: Arc<Interrupt>) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    pub fn run(&self) -> Result<ScanStatus> {
+        // the service control manager runs the scan in another thread, which is
+        // started by the dispatcher
+        #[cfg(windows)]
+        if self.interrupt.is_none()
+            && matches!(
+                self.cli.command,
+                Some(Command::Service { action: ServiceAction::Run { .. } })
+            )
+        {
+            service::dispatch()?;
+            return Ok(ScanStatus::Clean);
+        }
+
         self.init_logging()?;
 
         log::info!("running dionysos version {}", env!("CARGO_PKG_VERSION"));
 
-        let scanners = self.init_scanners()?;
+        if self.cli.list_scanners {
+            self.list_scanners();
+            return Ok(ScanStatus::Clean);
+        }
+
+        if !self.cli.target_mode.scans()
+            && !self.cli.dry_run
+            && !self.cli.estimate
+            && matches!(self.cli.command, None | Some(Command::Scan))
+        {
+            return self.collect_targets_only();
+        }
+
+        let scanners = match &self.cli.command {
+            None | Some(Command::Scan) => self.init_scanners()?,
+            Some(Command::Baseline { destination }) if self.cli.dry_run || self.cli.estimate => {
+                log::info!("not writing the inventory '{}' without a scan", destination);
+                Arc::new(Vec::new())
+            }
+            Some(Command::Baseline { destination }) => {
+                let inventory_scanner =
+                    InventoryScanner::new(destination)?.with_partial_hash(self.cli.partial_hash);
+                let scanners: Vec<Box<dyn FileScanner>> = vec![Box::new(inventory_scanner)];
+                Arc::new(scanners)
+            }
+            Some(Command::Validate) => return self.validate(),
+            Some(Command::Diff { old, new }) => return self.diff(old, new),
+            Some(Command::Report { results, output }) => {
+                return self.report(results, self.cli.report_format()?, output.as_deref())
+            }
+            Some(Command::Update { binary, public_key }) => {
+                return self.update(*binary, public_key.as_deref())
+            }
+            Some(Command::Serve(options)) => return self.serve(options),
+            #[cfg(feature = "grpc")]
+            Some(Command::Controller { listen, tls }) => {
+                controller::serve(*listen, tls, self.controller_task()?)?;
+                return Ok(ScanStatus::Clean);
+            }
+            #[cfg(feature = "grpc")]
+            Some(Command::Agent { controller, tls }) => {
+                agent::run(controller, tls)?;
+                return Ok(ScanStatus::Clean);
+            }
+            Some(Command::Completions { shell }) => {
+                clap_complete::generate(*shell, &mut Cli::command(), "dionysos", &mut std::io::stdout());
+                return Ok(ScanStatus::Clean);
+            }
+            #[cfg(windows)]
+            Some(Command::Service { action }) => match action {
+                ServiceAction::Install { .. } => {
+                    service::install()?;
+                    return Ok(ScanStatus::Clean);
+                }
+                ServiceAction::Uninstall => {
+                    service::uninstall()?;
+                    return Ok(ScanStatus::Clean);
+                }
+                ServiceAction::Run { .. } => self.init_scanners()?,
+            },
+        };
+        if self.cli.estimate {
+            return self.estimate(&scanners);
+        }
+        if self.cli.dry_run {
+            return self.dry_run(&scanners);
+        }
+
+        let duplicates = match self.cli.dedup {
+            true => Some(Arc::new(Duplicates::new(self.cli.threads, &scanners))),
+            false => None,
+        };
+        let scanners = match &duplicates {
+            Some(duplicates) => duplicates.wrap_scanners(scanners),
+            None => scanners,
+        };
+
+        let known_good = self.init_known_good()?;
         let (m_progress, progress) = self.create_progress()?;
+        let interrupt = match &self.interrupt {
+            Some(interrupt) => Arc::clone(interrupt),
+            None => Interrupt::install()?,
+        };
+        let destination = self.output_destination()?;
+        let progress_reporter = self
+            .cli
+            .progress_json
+            .as_deref()
+            .map(ProgressReporter::start)
+            .transpose()?;
+        let progress_events = progress_reporter.as_ref().map(|r| Arc::clone(r.events()));
+
+        #[cfg(feature = "otel")]
+        let _telemetry = self
+            .cli
+            .otlp_endpoint
+            .as_deref()
+            .map(Telemetry::init)
+            .transpose()?;
+        let _scan_span =
+            telemetry::span("scan", || vec![("path", self.path.display().to_string())]);
 
         let spinner_style =
             ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {wide_msg}")?;
 
         let max_workers = self.cli.threads;
-        let mut workers = Vec::new();
 
-        let (mut tx_in, rx_in) = spmc::channel();
-        let (tx_out, rx_out) = mpsc::channel();
+        #[cfg(feature = "tui")]
+        let tui = match self.cli.tui {
+            false => None,
+            true => Some(Arc::new(TuiState::new(max_workers))),
+        };
+        #[cfg(feature = "tui")]
+        let tui_thread = tui.as_ref().map(|tui| tui.spawn()).transpose()?;
+
+        let file_timeout = self
+            .cli
+            .file_timeout
+            .map(|secs| Arc::new(FileTimeout::new(Duration::from_secs(secs))));
+
+        let throttle = self
+            .cli
+            .throttle
+            .clone()
+            .map(|schedule| Arc::new(Throttle::new(schedule)));
+
+        let mut worker_watchdog = WorkerWatchdog::new(
+            Duration::from_secs(self.cli.stuck_threshold),
+            self.cli.skip_stuck_files,
+        );
+
+        let mut hooks: Vec<Box<dyn WorkerHooks>> = Vec::new();
         for _id in 0..max_workers {
-            log::trace!("creating worker #{}", _id);
-            let pb = match &m_progress {
-                None => None,
-                Some(m_progress) => {
+            hooks.push(Box::new(WorkerStatus {
+                spinner: m_progress.as_ref().map(|m_progress| {
                     let pb = m_progress.add(ProgressBar::new_spinner());
                     pb.set_style(spinner_style.clone());
-                    Some(pb)
-                }
-            };
-
-            let scanner = Arc::clone(&scanners);
-            let rx = rx_in.clone();
-            let tx = tx_out.clone();
-            let global_progress = progress.as_ref().map(Arc::clone);
-            let worker = thread::spawn(move || worker(rx, tx, scanner, pb, global_progress));
-            workers.push(worker);
+                    pb
+                }),
+                progress: progress.as_ref().map(Arc::clone),
+                progress_events: progress_events.as_ref().map(Arc::clone),
+                #[cfg(feature = "tui")]
+                tui: tui.as_ref().map(|tui| (Arc::clone(tui), _id)),
+                activity: worker_watchdog.add_worker(),
+                interrupt: Arc::clone(&interrupt),
+                throttle: throttle.as_ref().map(Arc::clone),
+            }));
         }
-        drop(tx_out);
+
+        // the hashes which are looked up by the writer are computed by the workers
+        let ignored_hashes = self.init_ignored_hashes()?;
+        let threat_intel = self.init_threat_intel()?;
+        let findings_policy = self.init_findings_policy()?;
+        let mut hash_algorithms = HashAlgorithms::default();
+        if let Some(ignored_hashes) = &ignored_hashes {
+            hash_algorithms = hash_algorithms.union(ignored_hashes.algorithms());
+        }
+        if let Some(threat_intel) = &threat_intel {
+            hash_algorithms = hash_algorithms.union(threat_intel.hash_algorithms());
+        }
+        if let Some(findings_policy) = &findings_policy {
+            hash_algorithms = hash_algorithms.union(findings_policy.hash_algorithms());
+        }
+
+        let (tx_out, rx_out) = mpsc::channel();
+        let mut job = ScanJob::new(&self.path)
+            .with_scanners(Arc::clone(&scanners))
+            .with_known_good_filter(known_good.as_ref().map(Arc::clone))
+            .with_threads(max_workers)
+            .with_timeout(file_timeout.as_ref().map(Arc::clone))
+            .with_compressed_files(Arc::clone(&self.compressed_files))
+            .with_errors(Arc::clone(&self.errors))
+            .with_handle(self.handle.clone())
+            .with_hooks(hooks)
+            .with_hash_algorithms(hash_algorithms);
+        if self.cli.scan_mails {
+            job = job.with_mail_parts(self.cli.max_mail_size * 1024 * 1024);
+        }
+        let mut workers = job.start(tx_out.clone());
+        let (worker_watchdog, worker_watchdog_thread) = worker_watchdog.start();
+        #[cfg(windows)]
+        let live_tx = tx_out.clone();
+        let walk_tx = tx_out.clone();
+        drop(tx_out);
 
         let cli = self.cli.clone();
+        #[cfg(feature = "tui")]
+        let writer_tui = tui.as_ref().map(Arc::clone);
+        let audit = self.audit.as_ref().map(Arc::clone);
+        let writer_progress_events = progress_events.as_ref().map(Arc::clone);
+        let mut exporter = match (self.cli.export, &self.cli.export_dir) {
+            (Some(layout), Some(dir)) => {
+                Some(Exporter::new(layout, dir, self.cli.string_options())?)
+            }
+            _ => None,
+        };
+        let mut collector = match &self.cli.collect {
+            Some(dir) => Some(Collector::new(dir, self.cli.collect_password.clone())?),
+            None => None,
+        };
+        if let (true, Some(collector)) = (self.cli.target_mode.collects(), &mut collector) {
+            self.collect_targets(collector, &interrupt);
+        }
+        let writer_duplicates = duplicates.as_ref().map(Arc::clone);
+        let writer_interrupt = Arc::clone(&interrupt);
+        let writer_handle = self.handle.clone();
+        let grace_period = Duration::from_secs(self.cli.grace_period);
+        let writer_policy = findings_policy.as_ref().map(Arc::clone);
+        #[cfg(feature = "hash_lookup")]
+        let mut hash_lookup = HashLookup::new(
+            self.cli.virustotal_key.clone(),
+            self.cli.malwarebazaar_key.clone(),
+            self.cli.lookup_rate,
+            self.cli.lookup_limit,
+        );
+        let string_options = self.cli.string_options();
+        let output_methods = move |destination: Box<dyn Write + Send>, format: OutputFormat| {
+            format
+                .into_options(destination)
+                .with_string_options(string_options.clone())
+                .with_threat_intel(threat_intel.as_ref().map(Arc::clone))
+        };
+        let output_format = self.cli.output_format()?;
+        let mut routes = OutputRoutes::open(
+            &self.cli.route,
+            output_format.clone(),
+            self.cli.route_password.as_deref(),
+            &output_methods,
+        )?;
         let writer_thread = thread::spawn(move || {
-            let mut output_options = cli.output_format.into_options(std::io::stdout());
+            let mut output_options = output_methods(destination, output_format);
+            let mut ignored_files = 0;
+            let mut relevant_findings = 0;
+            let mut attack_coverage = AttackCoverage::default();
+            let mut scan_errors = ScanErrors::default();
+            let mut output_error = None;
+            // the files with findings, which are looked up after the scan
+            #[cfg(feature = "hash_lookup")]
+            let mut lookup_files = Vec::new();
+            // the writer receives a message at least every 500ms, as long as the scan is running
+            let watchdog = systemd::Watchdog::from_env();
             loop {
-                match rx_out.recv() {
-                    Err(mpsc::RecvError) => {
+                watchdog.ping();
+                match rx_out.recv_timeout(Duration::from_millis(500)) {
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
                         drop(rx_out);
                         break;
                     }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        if writer_interrupt.grace_period_elapsed(grace_period) {
+                            log::warn!("the grace period has elapsed, cancelling the running scans");
+                            writer_handle.cancel();
+                            break;
+                        }
+                    }
                     Ok(result) => {
-                        if result.has_findings() {
-                            output_options.print_result(&result);
+                        let previous_findings = relevant_findings;
+                        let results = match &writer_duplicates {
+                            Some(duplicates) => duplicates.attribute(result),
+                            None => vec![result],
+                        };
+                        for mut result in results {
+                            if let Some(policy) = &writer_policy {
+                                policy.apply(&mut result);
+                            }
+                            if let Some(audit) = &audit {
+                                audit.record_file(&result);
+                            }
+                            if result.has_errors() {
+                                scan_errors.add(result.filename(), result.errors());
+                                if let Err(why) = output_options.print_errors(&result) {
+                                    output_error = Some(why);
+                                }
+                            }
+                            if result.has_findings() {
+                                if is_ignored(&ignored_hashes, &result) {
+                                    ignored_files += 1;
+                                } else {
+                                    relevant_findings += result
+                                        .findings()
+                                        .filter(|f| f.severity() >= cli.min_severity)
+                                        .count();
+                                    if let Err(why) = output_options
+                                        .print_findings(&result, |f| routes.is_default(f))
+                                        .and_then(|_| routes.print_result(&result))
+                                    {
+                                        output_error = Some(why);
+                                    }
+                                    attack_coverage.add(&result);
+                                    #[cfg(feature = "hash_lookup")]
+                                    if let (Some(_), Some(path)) = (&hash_lookup, result.path()) {
+                                        lookup_files
+                                            .push((result.filename().to_owned(), path.to_owned()));
+                                    }
+                                    if let Some(exporter) = &mut exporter {
+                                        if let Err(why) = exporter.add_result(&result) {
+                                            log::error!("unable to export the results: {}", why);
+                                        }
+                                    }
+                                    if let Some(collector) = &mut collector {
+                                        collector.add_result(&result);
+                                    }
+
+                                    #[cfg(feature = "tui")]
+                                    if let Some(tui) = &writer_tui {
+                                        tui.add_findings(&result, &cli.string_options());
+                                    }
+                                }
+                            }
+                        }
+                        if let Some(events) = &writer_progress_events {
+                            events.complete_file(relevant_findings - previous_findings);
+                        }
+
+                        // there is no point in scanning files whose results are lost
+                        if let Some(why) = &output_error {
+                            log::error!("unable to write the results, stopping the scan: {}", why);
+                            writer_interrupt.stop();
+                            break;
+                        }
+                    }
+                }
+            }
+            #[cfg(feature = "hash_lookup")]
+            if let Some(hash_lookup) = &mut hash_lookup {
+                let mut looked_up = std::collections::HashSet::new();
+                for (filename, path) in lookup_files {
+                    if writer_interrupt.is_interrupted() || output_error.is_some() {
+                        break;
+                    }
+                    if hash_lookup.is_exhausted() {
+                        log::warn!(
+                            "the limit of hash lookups has been reached, \
+                             not looking up the remaining files"
+                        );
+                        break;
+                    }
+                    if !looked_up.insert(path.clone()) {
+                        continue;
+                    }
+                    watchdog.ping();
+                    if let Some(report) = hash_lookup.lookup(&path) {
+                        if let Err(why) = output_options.print_hash_report(&filename, &report) {
+                            output_error = Some(why);
                         }
                     }
                 }
             }
+            let flushed = output_options.flush().map_err(anyhow::Error::from);
+            if let Err(why) = flushed.and_then(|_| routes.finish()) {
+                log::error!("unable to write the results: {}", why);
+                output_error.get_or_insert(why);
+            }
+            if let Some(Err(why)) = exporter.map(|exporter| exporter.finish()) {
+                log::error!("unable to export the results: {}", why);
+            }
+            let collected = collector.map(Collector::finish).unwrap_or_default();
+            (
+                ignored_files,
+                relevant_findings,
+                scan_errors,
+                output_error,
+                collected,
+                attack_coverage
+                    .summary()
+                    .into_iter()
+                    .chain(routes.summary())
+                    .collect::<Vec<_>>(),
+            )
         });
 
-        for entry in WalkDir::new(&self.path)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
+        if let (Some(_), Some(ruleset)) = (self.cli.watch, self.yara_ruleset()) {
+            let scanners = Arc::clone(&scanners);
+            thread::spawn(move || watch_ruleset(ruleset, scanners));
+        }
+        if self.cli.watch.is_some() {
+            let reload = systemd::reload_requested()?;
+            let scanners = Arc::clone(&scanners);
+            thread::spawn(move || reload_on_sighup(reload, scanners));
+        }
+        systemd::notify_ready();
+
+        #[cfg(windows)]
         {
-            log::info!("scanning '{}'", entry.path().display());
+            self.scan_live_eventlog(&scanners, &live_tx);
+            drop(live_tx);
+        }
+
+        let mut provider = self.provider()?;
+        let walk_span = telemetry::span("walk", Vec::new);
+        while !interrupt.is_interrupted() {
+            if let Some(events) = &progress_events {
+                events.set_enumerating(true);
+            }
+            let files: Box<dyn Iterator<Item = Result<ProvidedFile, ScannerResult>> + '_> =
+                match &mut provider {
+                    Some(provider) => Box::new(
+                        provider
+                            .files()
+                            .map(|file| file.map_err(|why| self.provider_error(why))),
+                    ),
+                    None => {
+                        let entries: Box<dyn Iterator<Item = walkdir::Result<_>> + '_> =
+                            match &duplicates {
+                                Some(duplicates) => Box::new(
+                                    duplicates.deduplicate(self.walk(), &interrupt).into_iter(),
+                                ),
+                                None => self.walk(),
+                            };
+                        Box::new(entries.map(|entry| {
+                            entry
+                                .map(ProvidedFile::Entry)
+                                .map_err(|why| self.walk_error(why))
+                        }))
+                    }
+                };
+            for file in files {
+                if interrupt.is_interrupted() {
+                    break;
+                }
+                let file = match file {
+                    Ok(file) => file,
+                    Err(error) => {
+                        // the error is written by the writer, like the errors of the scanners
+                        let _ = walk_tx.send(error);
+                        continue;
+                    }
+                };
+                log::info!("scanning '{}'", file.name());
+
+                if let Some(events) = &progress_events {
+                    events.discover_file();
+                }
+                workers.scan(file)?;
+            }
+            if let Some(events) = &progress_events {
+                events.set_enumerating(false);
+            }
 
-            tx_in.send(entry)?;
+            match self.cli.watch {
+                None => break,
+                Some(interval) => {
+                    log::info!("all files have been enumerated, next scan starts in {}s", interval);
+                    interrupt.sleep(Duration::from_secs(interval));
+                }
+            }
+        }
+        drop(walk_tx);
+        drop(walk_span);
+
+        // a panic of a scanner is caught by `handle_file`, so that a worker only
+        // fails because of a bug in dionysos itself
+        let failed_workers = workers.join();
+        self.errors.fetch_add(failed_workers, Ordering::Relaxed);
+        worker_watchdog.finish();
+        let _ = worker_watchdog_thread.join();
+        let (
+            ignored_files,
+            relevant_findings,
+            scan_errors,
+            output_error,
+            collected,
+            output_summary,
+        ) = match writer_thread.join() {
+            Ok(result) => result,
+            Err(payload) => {
+                log::error!("the output has failed: {}", panic_message(&*payload));
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                Default::default()
+            }
+        };
+        if output_error.is_some() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(progress_reporter) = progress_reporter {
+            progress_reporter.finish();
         }
-        drop(tx_in);
 
-        let _ = workers.into_iter().map(|w| w.join());
-        let _ = writer_thread.join();
+        #[cfg(feature = "tui")]
+        if let (Some(tui), Some(tui_thread)) = (tui, tui_thread) {
+            tui.close();
+            let _ = tui_thread.join();
+        }
 
         if let Some(mp) = m_progress {
             mp.clear()?;
         }
 
-        Ok(())
+        let mut summary = Vec::new();
+        if let Some(known_good) = &known_good {
+            summary.extend(known_good.summary());
+        }
+        summary.extend(self.special_files.summary());
+        summary.extend(self.compressed_files.summary());
+        if let Some(duplicates) = &duplicates {
+            summary.extend(duplicates.summary());
+        }
+        if let Some(findings_policy) = &findings_policy {
+            summary.extend(findings_policy.summary());
+        }
+        if let Some(throttle) = &throttle {
+            summary.extend(throttle.summary());
+        }
+        if ignored_files > 0 {
+            summary.push(format!(
+                "ignored the findings of {} file(s) because of their hash",
+                ignored_files
+            ));
+        }
+        if relevant_findings > 0 {
+            summary.push(format!(
+                "found {} finding(s) with a severity of at least {}",
+                relevant_findings, self.cli.min_severity
+            ));
+        }
+        summary.extend(output_summary);
+        let errors = self.errors.load(Ordering::Relaxed);
+        if errors > 0 {
+            summary.push(format!("{} error(s) occurred during the scan", errors));
+        }
+        summary.extend(scan_errors.summary());
+        summary.extend(collected);
+        if let Some(file_timeout) = &file_timeout {
+            summary.extend(file_timeout.summary());
+        }
+        summary.extend(worker_watchdog.summary());
+        if failed_workers > 0 {
+            summary.push(format!(
+                "{} of {} worker(s) failed, so that the results are incomplete",
+                failed_workers, max_workers
+            ));
+        }
+        if let Some(why) = &output_error {
+            summary.push(format!(
+                "the scan has been stopped, because the results could not be written: {}",
+                why
+            ));
+        } else if interrupt.is_interrupted() {
+            summary.push(
+                "the scan has been interrupted, so that the results are incomplete".to_owned(),
+            );
+        }
+        self.print_summary(&scanners, summary);
+
+        let status = if output_error.is_some() {
+            ScanStatus::Errors
+        } else if interrupt.is_interrupted() {
+            ScanStatus::Interrupted
+        } else if errors > 0 {
+            ScanStatus::Errors
+        } else if relevant_findings > 0 {
+            ScanStatus::Findings
+        } else {
+            ScanStatus::Clean
+        };
+        if let Some(audit) = &self.audit {
+            audit.finish(relevant_findings, errors, status.exit_code())?;
+        }
+        Ok(status)
+    }
+
+    /// scans all events of the channels given with `--live-evtx`. Every event is
+    /// reported as `eventlog:<channel>#<record id>`
+    #[cfg(windows)]
+    fn scan_live_eventlog(
+        &self,
+        scanners: &Arc<Vec<Box<dyn FileScanner>>>,
+        tx: &mpsc::Sender<ScannerResult>,
+    ) {
+        for channel in self.cli.live_evtx.iter() {
+            log::info!("scanning the live event log channel '{}'", channel);
+            let res = live_eventlog::read_channel(channel, |xml| {
+                let name = format!(
+                    "eventlog:{}#{}",
+                    channel,
+                    live_eventlog::record_id(xml).unwrap_or("-")
+                );
+                let mut result = ScannerResult::from(name.clone());
+                for scanner in scanners.iter() {
+                    for res in scanner.scan_buffer(&name, xml.as_bytes()) {
+                        match res {
+                            Err(why) => {
+                                log::error!("{}", why);
+                                self.errors.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Ok(finding) => result.add_finding(finding),
+                        }
+                    }
+                }
+                if result.has_findings() {
+                    let _ = tx.send(result);
+                }
+            });
+            match res {
+                Ok(events) => log::info!("scanned {} event(s) of '{}'", events, channel),
+                Err(why) => {
+                    log::error!("{}", why);
+                    self.errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// compares the findings of the results `old` and `new`, and writes the new, resolved
+    /// and changed findings in the selected output format
+    fn diff(&self, old: &Path, new: &Path) -> Result<ScanStatus> {
+        let load = |path: &Path| {
+            ResultSet::load(path)
+                .map_err(|why| anyhow!("unable to read '{}': {}", path.display(), why))
+        };
+        let diffs = result_diff::diff(load(old)?, load(new)?);
+        let count = |change| diffs.iter().filter(|d| d.change == change).count();
+
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        match self.cli.output_format()? {
+            OutputFormat::Csv => {
+                let mut wtr = csv::Writer::from_writer(stdout);
+                wtr.write_record(["change", "scanner", "indicator", "file"])?;
+                for diff in diffs.iter() {
+                    wtr.write_record([
+                        diff.change.to_string().as_str(),
+                        diff.scanner(),
+                        diff.indicator().as_str(),
+                        diff.file(),
+                    ])?;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::Txt => {
+                for diff in diffs.iter() {
+                    writeln!(
+                        stdout,
+                        "{:<9} {:<12} {} ({})",
+                        diff.change,
+                        diff.scanner(),
+                        diff.file(),
+                        diff.indicator()
+                    )?;
+                }
+                if !self.cli.quiet {
+                    writeln!(
+                        stdout,
+                        "{} new, {} resolved and {} changed finding(s)",
+                        count(Change::New),
+                        count(Change::Resolved),
+                        count(Change::Changed)
+                    )?;
+                }
+            }
+            format @ (OutputFormat::Json | OutputFormat::Ndjson) => {
+                let line_delimited = matches!(format, OutputFormat::Ndjson);
+                for diff in diffs.iter() {
+                    write_json(&mut stdout, &diff.to_json(), line_delimited)?;
+                }
+            }
+        }
+
+        match count(Change::New) + count(Change::Changed) {
+            0 => Ok(ScanStatus::Clean),
+            _ => Ok(ScanStatus::Findings),
+        }
+    }
+
+    fn report(
+        &self,
+        results: &Path,
+        format: Option<ReportFormat>,
+        output: Option<&Path>,
+    ) -> Result<ScanStatus> {
+        let report = Report::load(results)
+            .map_err(|why| anyhow!("unable to read '{}': {}", results.display(), why))?;
+        let format = format
+            .or_else(|| output.and_then(ReportFormat::of))
+            .unwrap_or(ReportFormat::Html);
+        match output {
+            Some(output) => {
+                let mut file = std::io::BufWriter::new(std::fs::File::create(output).map_err(
+                    |why| anyhow!("unable to create '{}': {}", output.display(), why),
+                )?);
+                report.write(format, &mut file)?;
+                file.flush()?;
+            }
+            None => {
+                let stdout = std::io::stdout();
+                report.write(format, &mut stdout.lock())?;
+            }
+        }
+        Ok(ScanStatus::Clean)
+    }
+
+    /// checks the yara ruleset and all IoC files, and reports every error
+    fn validate(&self) -> Result<ScanStatus> {
+        let mut errors = Vec::new();
+        let quiet = self.cli.quiet;
+        let mut check = |what: &str, result: Result<()>| match result {
+            Ok(()) if quiet => (),
+            Ok(()) => println!("OK     {}", what),
+            Err(why) => {
+                if !quiet {
+                    println!("ERROR  {}", what);
+                }
+                errors.push(why.to_string());
+            }
+        };
+
+        if let Some(ruleset) = self.yara_ruleset() {
+            let result = match self.cli.yara_engine {
+                YaraEngine::Libyara => YaraScanner::new(&ruleset).map(|_| ()),
+                #[cfg(feature = "yara_x")]
+                YaraEngine::YaraX => YaraXScanner::new(&ruleset).map(|_| ()),
+            };
+            check(&format!("yara ruleset '{}'", ruleset.path().display()), result);
+        }
+
+        for pattern in self.cli.filenames.iter() {
+            check(
+                &format!("filename pattern '{}'", pattern),
+                Self::filename_pattern(pattern).map(|_| ()),
+            );
+        }
+
+        for hash in self.cli.file_hash.iter().chain(self.cli.ignore_hash.iter()) {
+            check(&format!("hash '{}'", hash), CryptoHash::parse(hash).map(|_| ()));
+        }
+        for imphash in self.cli.imphash.iter() {
+            check(
+                &format!("import hash '{}'", imphash),
+                CryptoHash::parse_imphash(imphash).map(|_| ()),
+            );
+        }
+
+        let mut hash_sets: Vec<PathBuf> = self
+            .cli
+            .file_hash_list
+            .iter()
+            .chain(self.cli.ignore_hash_list.iter())
+            .chain(self.cli.known_good.iter())
+            .map(PathBuf::from)
+            .collect();
+        for hash_dir in self.cli.hash_dir.iter() {
+            match HashList::hash_sets_in_directory(Path::new(hash_dir)) {
+                Ok(files) => hash_sets.extend(files),
+                Err(why) => check(
+                    &format!("hash directory '{}'", hash_dir),
+                    Err(anyhow!("unable to read '{}': {}", hash_dir, why)),
+                ),
+            }
+        }
+        for hash_set in hash_sets.iter() {
+            let result = HashList::invalid_lines(hash_set)
+                .map_err(|why| anyhow!("unable to read '{}': {}", hash_set.display(), why))
+                .and_then(|lines| {
+                    if lines.is_empty() {
+                        return Ok(());
+                    }
+                    Err(anyhow!(
+                        "{}",
+                        lines
+                            .iter()
+                            .map(|l| format!("{}:{}: found no valid hash", hash_set.display(), l))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    ))
+                });
+            check(&format!("hash set '{}'", hash_set.display()), result);
+        }
+
+        #[cfg(feature = "fuzzy_hash")]
+        for hash in self.cli.fuzzy_hash.iter() {
+            check(
+                &format!("ssdeep hash '{}'", hash),
+                fuzzy_hash_scanner::parse_ssdeep(hash).map(|_| ()),
+            );
+        }
+        #[cfg(feature = "fuzzy_hash")]
+        for hash_list in self.cli.fuzzy_hash_list.iter() {
+            check(
+                &format!("ssdeep hash list '{}'", hash_list),
+                FuzzyHashScanner::default().with_hash_file(hash_list).map(|_| ()),
+            );
+        }
+        for digest in self.cli.tlsh.iter() {
+            check(
+                &format!("TLSH digest '{}'", digest),
+                tlsh_scanner::parse_tlsh(digest).map(|_| ()),
+            );
+        }
+        for digest_list in self.cli.tlsh_list.iter() {
+            check(
+                &format!("TLSH digest list '{}'", digest_list),
+                TlshScanner::default().with_digest_file(digest_list).map(|_| ()),
+            );
+        }
+
+        for path in self.cli.threat_intel.iter() {
+            check(
+                &format!("threat intelligence database '{}'", path.display()),
+                ThreatIntel::default().add_file(path).map(|_| ()),
+            );
+        }
+
+        if let Some(path) = &self.cli.findings_policy {
+            check(
+                &format!("findings policy '{}'", path.display()),
+                FindingsPolicy::from_file(path).map(|_| ()),
+            );
+        }
+
+        if !self.cli.levenshtein_target.is_empty() {
+            check(
+                "levenshtein targets",
+                LevenshteinScanner::new(&self.cli.levenshtein_target).map(|_| ()),
+            );
+        }
+        for targets_file in self.cli.levenshtein_targets_file.iter() {
+            check(
+                &format!("levenshtein targets file '{}'", targets_file),
+                LevenshteinScanner::default()
+                    .with_targets_file(targets_file)
+                    .map(|_| ()),
+            );
+        }
+
+        #[cfg(feature = "scan_reg")]
+        for ioc_file in self.cli.reg_ioc.iter() {
+            check(
+                &format!("registry IoC file '{}'", ioc_file.display()),
+                RegistryIocScanner::default()
+                    .with_ioc_file(ioc_file)
+                    .map(|_| ()),
+            );
+        }
+
+        for plugin_dir in self.cli.plugin_dir.iter() {
+            check(
+                &format!("plugin directory '{}'", plugin_dir.display()),
+                PluginScanner::load_directory(plugin_dir).map(|_| ()),
+            );
+        }
+
+        if errors.is_empty() {
+            return Ok(ScanStatus::Clean);
+        }
+        for error in errors.iter() {
+            eprintln!("{}", error);
+        }
+        eprintln!("validation failed with {} error(s)", errors.len());
+        Ok(ScanStatus::Errors)
+    }
+
+    /// downloads all feeds, and replaces the binary if requested
+    fn update(&self, binary: bool, public_key: Option<&str>) -> Result<ScanStatus> {
+        if self.cli.feeds.is_empty() && !binary {
+            return Err(anyhow!("nothing to update, use '--feed' or '--binary'"));
+        }
+
+        let mut errors = 0;
+        for feed in self.cli.feeds.iter() {
+            match feed.update(self.cli.yara_zip_password.as_deref()) {
+                Ok(bytes) => println!(
+                    "updated '{}' from '{}' ({} bytes)",
+                    feed.file().display(),
+                    feed.url(),
+                    bytes
+                ),
+                Err(why) => {
+                    eprintln!("unable to update '{}': {}", feed.file().display(), why);
+                    errors += 1;
+                }
+            }
+        }
+
+        if let (true, Some(public_key)) = (binary, public_key) {
+            match update::update_binary(public_key) {
+                Ok(Some(version)) => println!("updated dionysos to version {}", version),
+                Ok(None) => println!("dionysos {} is up to date", env!("CARGO_PKG_VERSION")),
+                Err(why) => {
+                    eprintln!("unable to update dionysos: {}", why);
+                    errors += 1;
+                }
+            }
+        }
+
+        Ok(if errors > 0 {
+            ScanStatus::Errors
+        } else {
+            ScanStatus::Clean
+        })
+    }
+
+    #[cfg(not(any(feature = "grpc", feature = "http")))]
+    fn serve(&self, _options: &ServeOptions) -> Result<ScanStatus> {
+        Err(anyhow!("dionysos has been built without a server (feature 'grpc' or 'http')"))
+    }
+
+    /// runs the servers which are requested by `options`, until dionysos is interrupted
+    #[cfg(any(feature = "grpc", feature = "http"))]
+    fn serve(&self, options: &ServeOptions) -> Result<ScanStatus> {
+        let jobs = Arc::new(JobRegistry::new(server_defaults(&self.cli)));
+
+        // sockets which are passed by systemd replace the addresses, in the order
+        // of '--grpc' and '--http'
+        let mut sockets = systemd::listen_sockets()?.into_iter();
+        let mut listen = |addr: SocketAddr| match sockets.next() {
+            Some(socket) => Ok(socket),
+            None => TcpListener::bind(addr)
+                .map_err(|why| anyhow!("unable to listen on {}: {}", addr, why)),
+        };
+        #[cfg(feature = "grpc")]
+        let grpc = options.grpc.map(&mut listen).transpose()?;
+        #[cfg(feature = "http")]
+        let http = options.http.map(&mut listen).transpose()?;
+
+        let reload = systemd::reload_requested()?;
+        let watched_jobs = Arc::clone(&jobs);
+        let config = self.cli.config.clone();
+        thread::spawn(move || {
+            let watchdog = systemd::Watchdog::from_env();
+            loop {
+                watchdog.ping();
+                if reload.swap(false, Ordering::Relaxed) {
+                    systemd::notify_reloading();
+                    match &config {
+                        None => log::info!("there is no configuration file to reload"),
+                        Some(config) => match Self::read_config(config) {
+                            Ok(cli) => {
+                                watched_jobs.set_defaults(server_defaults(&cli));
+                                log::info!("reloaded the configuration '{}'", config.display());
+                            }
+                            Err(why) => log::error!(
+                                "unable to reload the configuration, keeping the previous one: {}",
+                                why
+                            ),
+                        },
+                    }
+                    systemd::notify_ready();
+                }
+                thread::sleep(Duration::from_secs(1));
+            }
+        });
+
+        #[cfg(feature = "http")]
+        let http = http.map(|listener| {
+            let jobs = Arc::clone(&jobs);
+            thread::spawn(move || http_server::serve(listener, jobs))
+        });
+        #[cfg(not(feature = "http"))]
+        let http: Option<thread::JoinHandle<Result<()>>> = None;
+        systemd::notify_ready();
+
+        #[cfg(feature = "grpc")]
+        if let Some(listener) = grpc {
+            // the gRPC server terminates on Ctrl-C, which also terminates the HTTP server
+            grpc::serve(listener, Arc::clone(&jobs))?;
+            jobs.cancel_all();
+            return Ok(ScanStatus::Clean);
+        }
+
+        match http {
+            Some(server) => {
+                server
+                    .join()
+                    .map_err(|_| anyhow!("the HTTP server terminated unexpectedly"))??;
+                Ok(ScanStatus::Clean)
+            }
+            None => Err(anyhow!("no server has been requested, use '--grpc' or '--http'")),
+        }
+    }
+
+    /// the task which the controller sends to its agents
+    #[cfg(feature = "grpc")]
+    fn controller_task(&self) -> Result<grpc::proto::Task> {
+        let (yara, yara_name) = match &self.yara_rules {
+            Some(rules) if rules.is_dir() => {
+                return Err(anyhow!(
+                    "the yara ruleset must be a single file or zip file to be sent to agents"
+                ))
+            }
+            Some(rules) => (
+                std::fs::read(rules)?,
+                rules.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            ),
+            None => (Vec::new(), String::new()),
+        };
+        if yara.is_empty() && self.cli.file_hash.is_empty() && self.cli.filenames.is_empty() {
+            return Err(anyhow!(
+                "no scanner has been requested, use '--yara', '--file-hash' or '--filename'"
+            ));
+        }
+        Ok(grpc::proto::Task {
+            id: 1,
+            paths: vec![self.path.display().to_string()],
+            yara,
+            yara_name,
+            hashes: self.cli.file_hash.clone(),
+            filenames: self.cli.filenames.clone(),
+        })
+    }
+
+    /// all files which must be scanned, and the errors of the enumeration
+    fn walk(&self) -> Box<dyn Iterator<Item = walkdir::Result<walkdir::DirEntry>> + '_> {
+        if let Some(targets) = &self.targets {
+            let files = targets.files(&self.path, |path, why| {
+                log::error!("unable to enumerate files in '{}': {}", path.display(), why);
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            });
+            return Box::new(files.filter(|e| e.as_ref().map_or(true, |e| self.is_scannable(e))));
+        }
+
+        // the collected files would be found again, and would be collected again
+        let collect_dir = self.cli.collect.as_ref().and_then(|dir| dir.canonicalize().ok());
+        Box::new(
+            WalkDir::new(extended_length_path(&self.path))
+                .into_iter()
+                .filter_entry(move |e| match &collect_dir {
+                    Some(collect_dir) => !is_same_dir(e, collect_dir),
+                    None => true,
+                })
+                .filter(|e| e.as_ref().map_or(true, |e| self.is_scannable(e))),
+        )
+    }
+
+    /// all files which must be scanned. Errors are logged and counted
+    fn files(&self) -> impl Iterator<Item = walkdir::DirEntry> + '_ {
+        self.walk().filter_map(|e| match e {
+            Ok(entry) => Some(entry),
+            Err(why) => {
+                self.walk_error(why);
+                None
+            }
+        })
+    }
+
+    /// the source of the files which are scanned instead of the files below '--path', if
+    /// one has been selected
+    fn provider(&self) -> Result<Option<Box<dyn FileProvider>>> {
+        if let Some(list) = &self.cli.file_list {
+            return Ok(Some(Box::new(FileListProvider::new(list))));
+        }
+        if let Some(image) = &self.cli.disk_image {
+            return Ok(Some(Box::new(DiskImageProvider::new(image))));
+        }
+        match &self.cli.s3 {
+            Some(url) => Ok(Some(Box::new(S3Provider::new(url)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// logs and counts an error of the file provider, and returns it as the result of the
+    /// source of the files
+    fn provider_error(&self, why: anyhow::Error) -> ScannerResult {
+        log::error!("{}", why);
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let source = match (&self.cli.file_list, &self.cli.disk_image, &self.cli.s3) {
+            (Some(list), _, _) => path_to_string(list),
+            (_, Some(image), _) => path_to_string(image),
+            (_, _, Some(url)) => url.clone(),
+            _ => path_to_string(&self.path),
+        };
+        let mut result = ScannerResult::from(source);
+        result.add_error(why.to_string());
+        result
+    }
+
+    /// logs and counts an error of the enumeration, and returns it as the result of the
+    /// file or directory which could not be read
+    fn walk_error(&self, why: walkdir::Error) -> ScannerResult {
+        log::error!("unable to enumerate files: {}", why);
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let mut result = ScannerResult::from(why.path().unwrap_or(self.path.as_path()));
+        result.add_error(format!("unable to enumerate files: {}", why));
+        result
+    }
+
+    /// checks if `entry` is a regular file, and counts the special files which are
+    /// skipped, because scanners would block or fail on them
+    fn is_scannable(&self, entry: &walkdir::DirEntry) -> bool {
+        match SpecialFile::of(entry) {
+            None => entry.file_type().is_file(),
+            Some(SpecialFile::Placeholder) if self.cli.scan_placeholders => true,
+            // WOF-compressed files on volumes which are mounted with ntfs-3g occupy no
+            // blocks, because their content is stored in an alternate data stream
+            Some(SpecialFile::Placeholder) if Compression::of(entry).is_some() => true,
+            Some(SpecialFile::Device) if self.cli.unallocated && is_ntfs_device(entry) => true,
+            Some(kind) => {
+                log::info!("skipping the {} '{}'", kind, entry.path().display());
+                self.special_files.record(kind);
+                if let Some(audit) = &self.audit {
+                    audit.record_skipped(&path_to_string(entry.path()), &kind.to_string());
+                }
+                false
+            }
+        }
+    }
+
+    fn dry_run(&self, scanners: &Arc<Vec<Box<dyn FileScanner>>>) -> Result<ScanStatus> {
+        let scanner_names: Vec<String> = scanners.iter().map(|s| s.to_string()).collect();
+        let mut files = 0;
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        match self.cli.output_format()? {
+            OutputFormat::Csv => {
+                let mut wtr = csv::Writer::from_writer(stdout);
+                wtr.write_record(["file", "scanners"])?;
+                for entry in self.files() {
+                    wtr.write_record([&path_to_string(entry.path()), &scanner_names.join(" ")])?;
+                    files += 1;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::Txt => {
+                for entry in self.files() {
+                    writeln!(stdout, "{}: {}", path_to_string(entry.path()), scanner_names.join(", "))?;
+                    files += 1;
+                }
+            }
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                for entry in self.files() {
+                    let line = serde_json::json!({
+                        "file": path_to_string(entry.path()),
+                        "scanners": scanner_names
+                    });
+                    writeln!(stdout, "{}", line)?;
+                    files += 1;
+                }
+            }
+        }
+        log::info!("{} file(s) would be scanned", files);
+        Ok(ScanStatus::Clean)
+    }
+
+    /// counts the files which would be scanned, and predicts the duration of the scan
+    fn estimate(&self, scanners: &Arc<Vec<Box<dyn FileScanner>>>) -> Result<ScanStatus> {
+        let mut estimate = Estimate::default();
+        for entry in self.files() {
+            estimate.add(entry);
+        }
+        log::info!("scanning a sample of the files to predict the duration of the scan");
+        estimate.calibrate(
+            FileHandler::new(Arc::clone(scanners), None, Arc::new(AtomicUsize::new(0))),
+            self.cli.file_timeout.map(Duration::from_secs),
+        );
+        let stdout = std::io::stdout();
+        estimate.write(&self.cli.output_format()?, self.cli.threads, &mut stdout.lock())?;
+        Ok(ScanStatus::Clean)
+    }
+
+    /// copies all files of '--targets' to the evidence directory
+    fn collect_targets(&self, collector: &mut Collector, interrupt: &Interrupt) {
+        for entry in self.files() {
+            if interrupt.is_interrupted() {
+                break;
+            }
+            collector.add_file(entry.path(), 0);
+        }
+    }
+
+    /// collects the files of '--targets' without scanning them
+    fn collect_targets_only(&self) -> Result<ScanStatus> {
+        let interrupt = match &self.interrupt {
+            Some(interrupt) => Arc::clone(interrupt),
+            None => Interrupt::install()?,
+        };
+        let dir = self.cli.collect.as_ref().expect("'--collect' is required to collect targets");
+        let mut collector = Collector::new(dir, self.cli.collect_password.clone())?;
+        self.collect_targets(&mut collector, &interrupt);
+
+        let mut summary = collector.finish();
+        let errors = self.errors.load(Ordering::Relaxed);
+        if errors > 0 {
+            summary.push(format!("{} error(s) occurred during the collection", errors));
+        }
+        summary.extend(self.special_files.summary());
+        self.print_summary(&Arc::new(Vec::new()), summary);
+
+        Ok(if interrupt.is_interrupted() {
+            ScanStatus::Interrupted
+        } else if errors > 0 {
+            ScanStatus::Errors
+        } else {
+            ScanStatus::Clean
+        })
+    }
+
+    fn print_summary(&self, scanners: &Arc<Vec<Box<dyn FileScanner>>>, summary: Vec<String>) {
+        let mut lines: Vec<String> = scanners.iter().flat_map(|s| s.summary()).collect();
+        lines.extend(summary);
+        if lines.is_empty() {
+            return;
+        }
+
+        if self.cli.quiet {
+            for line in lines {
+                log::info!("{}", line);
+            }
+            return;
+        }
+
+        eprintln!("scan summary:");
+        for line in lines {
+            eprintln!("{}", line);
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    fn tui_enabled(&self) -> bool {
+        self.cli.tui
+    }
+
+    #[cfg(not(feature = "tui"))]
+    fn tui_enabled(&self) -> bool {
+        false
     }
 
     fn create_progress(&self) -> Result<(Option<MultiProgress>, Option<Arc<ProgressBar>>)> {
-        let m_progress = match self.cli.display_progress {
+        if self.cli.display_progress && self.cli.quiet {
+            log::info!("not displaying a progress bar, because of '--quiet'");
+        }
+        let display_progress =
+            self.cli.display_progress && !self.cli.quiet && !self.tui_enabled();
+        let m_progress = match display_progress {
             false => None,
             true => {
                 let m_progress = MultiProgress::new();
@@ -384,44 +2645,413 @@ impl Dionysos {
         Ok((m_progress, progress))
     }
 
-    fn init_scanners(&self) -> Result<Arc<Vec<Box<dyn FileScanner>>>> {
-        let mut scanners: Vec<Box<dyn FileScanner>> = Vec::new();
+    fn init_ignored_hashes(&self) -> Result<Option<HashList>> {
+        if self.cli.ignore_hash.is_empty() && self.cli.ignore_hash_list.is_empty() {
+            return Ok(None);
+        }
 
-        if let Some(ref yara_rules) = self.yara_rules {
-            let yara_scanner = YaraScanner::new(yara_rules)?
-                .with_scan_compressed(self.cli.scan_compressed)
-                .with_buffer_size(self.cli.decompression_buffer_size)
-                .with_timeout(self.cli.yara_timeout);
+        let mut hashes = HashList::default();
+        for hash in self.cli.ignore_hash.iter() {
+            hashes.insert(CryptoHash::parse(hash)?);
+        }
+        for hash_file in self.cli.ignore_hash_list.iter() {
+            hashes.add_file(hash_file)?;
+        }
+        Ok(Some(hashes))
+    }
 
-            #[cfg(feature = "scan_evtx")]
-            let yara_scanner = yara_scanner.with_scan_evtx(self.cli.yara_scan_evtx);
+    /// the passwords of '--archive-passwords'
+    fn archive_passwords(&self) -> Result<Vec<String>> {
+        let file = match &self.cli.archive_passwords {
+            Some(file) => file,
+            None => return Ok(Vec::new()),
+        };
+        if !self.cli.scan_compressed {
+            log::warn!(
+                "'--archive-passwords' is ignored, because archives are only scanned with '-C'"
+            );
+        }
+        let passwords = std::fs::read_to_string(file)
+            .map_err(|why| anyhow!("unable to read '{}': {}", file.display(), why))?;
+        Ok(passwords
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_owned())
+            .collect())
+    }
+
+    fn init_known_good(&self) -> Result<Option<Arc<KnownGoodFilter>>> {
+        if self.cli.known_good.is_empty() {
+            return Ok(None);
+        }
+
+        let mut known_good = KnownGoodFilter::new(self.cli.known_good_mode);
+        for hash_file in self.cli.known_good.iter() {
+            known_good = known_good.with_hash_file(hash_file)?;
+        }
+        Ok(Some(Arc::new(known_good)))
+    }
+
+    fn yara_ruleset(&self) -> Option<YaraRuleset> {
+        self.yara_rules.as_ref().map(|yara_rules| {
+            YaraRuleset::new(yara_rules)
+                .with_strict(self.cli.yara_strict)
+                .with_zip_password(self.cli.yara_zip_password.clone())
+        })
+    }
 
+    /// the option which activates a scanner, if it has been given
+    fn configured_by(&self, kind: ScannerKind) -> Option<&'static str> {
+        match kind {
+            ScannerKind::Yara => self.yara_rules.is_some().then_some("--yara"),
+            ScannerKind::Filename => (!self.filenames.is_empty()).then_some("--filename"),
+            ScannerKind::Levenshtein => {
+                if !self.cli.levenshtein_target.is_empty() {
+                    Some("--levenshtein-target")
+                } else if !self.cli.levenshtein_targets_file.is_empty() {
+                    Some("--levenshtein-targets-file")
+                } else {
+                    self.cli.levenshtein.then_some("--levenshtein")
+                }
+            }
+            ScannerKind::Hash => {
+                if !self.cli.file_hash.is_empty() {
+                    Some("--file-hash")
+                } else if !self.cli.imphash.is_empty() {
+                    Some("--imphash")
+                } else if !self.cli.file_hash_list.is_empty() {
+                    Some("--file-hash-list")
+                } else {
+                    (!self.cli.hash_dir.is_empty()).then_some("--hash-dir")
+                }
+            }
+            #[cfg(feature = "fuzzy_hash")]
+            ScannerKind::FuzzyHash => {
+                if !self.cli.fuzzy_hash.is_empty() {
+                    Some("--fuzzy-hash")
+                } else {
+                    (!self.cli.fuzzy_hash_list.is_empty()).then_some("--fuzzy-hash-list")
+                }
+            }
+            ScannerKind::Tlsh => {
+                if !self.cli.tlsh.is_empty() {
+                    Some("--tlsh")
+                } else {
+                    (!self.cli.tlsh_list.is_empty()).then_some("--tlsh-list")
+                }
+            }
+            ScannerKind::Entropy => self.cli.entropy.then_some("--entropy"),
+            ScannerKind::ExtensionMismatch => {
+                self.cli.extension_mismatch.then_some("--extension-mismatch")
+            }
+            ScannerKind::Masquerading => self.cli.masquerading.then_some("--masquerading"),
+            ScannerKind::Ransomware => self.cli.ransomware.then_some("--ransomware"),
+            ScannerKind::Permissions => self.cli.permissions.then_some("--permissions"),
+            ScannerKind::Authenticode => self.cli.authenticode.then_some("--authenticode"),
+            ScannerKind::PeAnomalies => self.cli.pe_anomalies.then_some("--pe-anomalies"),
+            ScannerKind::ElfAnomalies => self.cli.elf_anomalies.then_some("--elf-anomalies"),
+            ScannerKind::MachoAnomalies => {
+                self.cli.macho_anomalies.then_some("--macho-anomalies")
+            }
             #[cfg(feature = "scan_reg")]
-            let yara_scanner = yara_scanner.with_scan_reg(self.cli.yara_scan_reg);
+            ScannerKind::RegistryIoc => (!self.cli.reg_ioc.is_empty()).then_some("--reg-ioc"),
+            ScannerKind::Inventory => self.cli.inventory.is_some().then_some("--inventory"),
+            ScannerKind::Plugins => (!self.cli.plugin_dir.is_empty()).then_some("--plugin-dir"),
+        }
+    }
+
+    /// checks if a scanner will be run, and returns the reason
+    fn scanner_state(&self, kind: ScannerKind) -> (bool, String) {
+        if self.cli.disable.contains(&kind) {
+            return (false, "disabled by --disable".to_owned());
+        }
+        if let Some(option) = self.configured_by(kind) {
+            return (true, format!("enabled by {}", option));
+        }
+        if self.cli.enable.contains(&kind) {
+            return (true, "enabled by --enable".to_owned());
+        }
+        match kind.required_options() {
+            Some(options) => (false, format!("requires {}", options)),
+            None => (false, format!("not enabled, use --enable {}", kind)),
+        }
+    }
+
+    fn is_enabled(&self, kind: ScannerKind) -> bool {
+        self.scanner_state(kind).0
+    }
+
+    fn list_scanners(&self) {
+        for kind in ScannerKind::value_variants() {
+            let (enabled, reason) = self.scanner_state(*kind);
+            println!(
+                "{:<20}{:<10}{}",
+                kind.to_string(),
+                if enabled { "active" } else { "inactive" },
+                kind.description()
+            );
+            println!("{:30}{}", "", reason);
+        }
+    }
+
+    /// the options which have been given, but are only supported by libyara
+    #[cfg(feature = "yara_x")]
+    fn unsupported_by_yara_x(&self) -> Vec<&'static str> {
+        let options = [
+            (self.cli.scan_compressed, "--scan-compressed"),
+            (self.cli.archive_passwords.is_some(), "--archive-passwords"),
+            (self.cli.unallocated, "--unallocated"),
+            (self.cli.force_raw, "--force-raw"),
+            #[cfg(feature = "scan_evtx")]
+            (self.cli.yara_scan_evtx, "--evtx"),
+            #[cfg(feature = "scan_evtx")]
+            (!self.cli.evtx_event_id.is_empty(), "--evtx-event-id"),
+            #[cfg(feature = "scan_evtx")]
+            (!self.cli.evtx_channel.is_empty(), "--evtx-channel"),
+            #[cfg(feature = "scan_evtx")]
+            (self.cli.evtx_after.is_some(), "--evtx-after"),
+            #[cfg(feature = "scan_evtx")]
+            (self.cli.evtx_before.is_some(), "--evtx-before"),
+            #[cfg(feature = "scan_reg")]
+            (self.cli.yara_scan_reg, "--reg"),
+            #[cfg(feature = "scan_reg")]
+            (self.cli.reg_after.is_some(), "--reg-after"),
+            #[cfg(feature = "scan_reg")]
+            (self.cli.reg_before.is_some(), "--reg-before"),
+            #[cfg(feature = "scan_ese")]
+            (self.cli.yara_scan_ese, "--ese"),
+        ];
+        options
+            .iter()
+            .filter_map(|&(given, option)| given.then_some(option))
+            .collect()
+    }
+
+    fn init_scanners(&self) -> Result<Arc<Vec<Box<dyn FileScanner>>>> {
+        let mut scanners: Vec<Box<dyn FileScanner>> = Vec::new();
 
-            scanners.push(Box::new(yara_scanner));
+        for kind in self.cli.enable.iter() {
+            if let (None, Some(options)) = (self.configured_by(*kind), kind.required_options()) {
+                return Err(anyhow!("the {} scanner cannot be enabled without {}", kind, options));
+            }
+        }
+
+        if let Some(ruleset) = self.yara_ruleset().filter(|_| self.is_enabled(ScannerKind::Yara)) {
+            match self.cli.yara_engine {
+                YaraEngine::Libyara => {
+                    let yara_scanner = YaraScanner::new(&ruleset)?
+                        .with_scan_handle(self.handle.clone())
+                        .with_scan_compressed(self.cli.scan_compressed)
+                        .with_archive_passwords(self.archive_passwords()?)
+                        .with_buffer_size(self.cli.decompression_buffer_size)
+                        .with_timeout(self.cli.yara_timeout)
+                        .with_rule_statistics(self.cli.rule_statistics)
+                        .with_force_raw(self.cli.force_raw)
+                        .with_scan_unallocated(self.cli.unallocated)
+                        .with_string_context(if self.cli.print_strings {
+                            self.cli.string_context
+                        } else {
+                            0
+                        });
+
+                    #[cfg(feature = "scan_evtx")]
+                    let yara_scanner = yara_scanner
+                        .with_scan_evtx(self.cli.yara_scan_evtx)
+                        .with_evtx_filter(EvtxFilter::new(
+                            self.cli.evtx_event_id.clone(),
+                            &self.cli.evtx_channel,
+                            TimeWindow::new(self.cli.evtx_after, self.cli.evtx_before),
+                        ));
+
+                    #[cfg(feature = "scan_reg")]
+                    let yara_scanner = yara_scanner
+                        .with_scan_reg(self.cli.yara_scan_reg)
+                        .with_registry_window(TimeWindow::new(self.cli.reg_after, self.cli.reg_before));
+
+                    #[cfg(feature = "scan_ese")]
+                    let yara_scanner = yara_scanner.with_scan_ese(self.cli.yara_scan_ese);
+
+                    scanners.push(Box::new(yara_scanner));
+                }
+
+                #[cfg(feature = "yara_x")]
+                YaraEngine::YaraX => {
+                    for option in self.unsupported_by_yara_x() {
+                        log::warn!("the YARA-X engine does not support {}, ignoring it", option);
+                    }
+                    let yara_scanner = YaraXScanner::new(&ruleset)?
+                        .with_scan_handle(self.handle.clone())
+                        .with_timeout(self.cli.yara_timeout)
+                        .with_rule_statistics(self.cli.rule_statistics)
+                        .with_string_context(if self.cli.print_strings {
+                            self.cli.string_context
+                        } else {
+                            0
+                        });
+                    scanners.push(Box::new(yara_scanner));
+                }
+            }
         };
 
-        if !self.filenames.is_empty() {
+        if self.is_enabled(ScannerKind::Filename) {
             let filename_scanner = FilenameScanner::new(self.filenames.clone());
             scanners.push(Box::new(filename_scanner));
         }
 
-        if self.cli.levenshtein {
-            let levenshtein_scanner = LevenshteinScanner::default();
+        if self.is_enabled(ScannerKind::Levenshtein) {
+            let mut levenshtein_scanner = if self.cli.levenshtein_target.is_empty()
+                && self.cli.levenshtein_targets_file.is_empty()
+            {
+                LevenshteinScanner::default()
+            } else {
+                LevenshteinScanner::new(&self.cli.levenshtein_target)?
+            }
+            .with_max_distance(self.cli.levenshtein_distance)
+            .with_allowed_paths(&self.cli.levenshtein_allow);
+            for targets_file in self.cli.levenshtein_targets_file.iter() {
+                levenshtein_scanner = levenshtein_scanner.with_targets_file(targets_file)?;
+            }
             scanners.push(Box::new(levenshtein_scanner));
         }
 
-        if !self.cli.file_hash.is_empty() {
-            let hash_scanner = HashScanner::default().with_hashes(&self.cli.file_hash)?;
+        if self.is_enabled(ScannerKind::Hash) {
+            let mut hash_scanner = HashScanner::default()
+                .with_hashes(&self.cli.file_hash)?
+                .with_imphashes(&self.cli.imphash)?
+                .with_partial_hash(self.cli.partial_hash);
+            for hash_file in self.cli.file_hash_list.iter() {
+                hash_scanner = hash_scanner.with_hash_file(hash_file)?;
+            }
+            for hash_dir in self.cli.hash_dir.iter() {
+                hash_scanner = hash_scanner.with_hash_dir(hash_dir)?;
+            }
             scanners.push(Box::new(hash_scanner));
         }
 
+        #[cfg(feature = "fuzzy_hash")]
+        if self.is_enabled(ScannerKind::FuzzyHash) {
+            let mut fuzzy_hash_scanner = FuzzyHashScanner::default()
+                .with_hashes(&self.cli.fuzzy_hash)?
+                .with_threshold(self.cli.fuzzy_threshold);
+            for hash_file in self.cli.fuzzy_hash_list.iter() {
+                fuzzy_hash_scanner = fuzzy_hash_scanner.with_hash_file(hash_file)?;
+            }
+            scanners.push(Box::new(fuzzy_hash_scanner));
+        }
+
+        if self.is_enabled(ScannerKind::Tlsh) {
+            let mut tlsh_scanner = TlshScanner::default()
+                .with_digests(&self.cli.tlsh)?
+                .with_distance(self.cli.tlsh_distance);
+            for digest_file in self.cli.tlsh_list.iter() {
+                tlsh_scanner = tlsh_scanner.with_digest_file(digest_file)?;
+            }
+            scanners.push(Box::new(tlsh_scanner));
+        }
+
+        if self.is_enabled(ScannerKind::ExtensionMismatch) {
+            scanners.push(Box::new(ExtensionScanner::default()));
+        }
+
+        if self.is_enabled(ScannerKind::Masquerading) {
+            scanners.push(Box::new(MasqueradingScanner::default()));
+        }
+
+        if self.is_enabled(ScannerKind::Ransomware) {
+            scanners.push(Box::new(RansomwareScanner::default()));
+        }
+
+        if self.is_enabled(ScannerKind::Permissions) {
+            scanners.push(Box::new(PermissionScanner::default()));
+        }
+
+        if self.is_enabled(ScannerKind::Authenticode) {
+            scanners.push(Box::new(AuthenticodeScanner::default()));
+        }
+
+        // these checks share the parsing of executables, so that every file is
+        // parsed only once
+        let mut executable_scanner = ExecutableScanner::default();
+        if self.is_enabled(ScannerKind::Entropy) {
+            executable_scanner = executable_scanner.with_check(Box::new(
+                EntropyScanner::default().with_threshold(self.cli.entropy_threshold),
+            ));
+        }
+        if self.is_enabled(ScannerKind::PeAnomalies) {
+            executable_scanner =
+                executable_scanner.with_check(Box::new(PeAnomalyScanner::default()));
+        }
+        if self.is_enabled(ScannerKind::ElfAnomalies) {
+            executable_scanner =
+                executable_scanner.with_check(Box::new(ElfAnomalyScanner::default()));
+        }
+        if self.is_enabled(ScannerKind::MachoAnomalies) {
+            executable_scanner =
+                executable_scanner.with_check(Box::new(MachOAnomalyScanner::default()));
+        }
+        if !executable_scanner.is_empty() {
+            scanners.push(Box::new(executable_scanner));
+        }
+
+        #[cfg(feature = "scan_reg")]
+        if self.is_enabled(ScannerKind::RegistryIoc) {
+            let mut registry_ioc_scanner = RegistryIocScanner::default();
+            for ioc_file in self.cli.reg_ioc.iter() {
+                registry_ioc_scanner = registry_ioc_scanner.with_ioc_file(ioc_file)?;
+            }
+            scanners.push(Box::new(registry_ioc_scanner));
+        }
+
+        if self.is_enabled(ScannerKind::Plugins) {
+            for plugin_dir in self.cli.plugin_dir.iter() {
+                for plugin in PluginScanner::load_directory(plugin_dir)? {
+                    log::info!("loaded {} from '{}'", plugin, plugin.path().display());
+                    scanners.push(Box::new(plugin));
+                }
+            }
+        }
+
+        let inventory = self.cli.inventory.as_ref();
+        if let Some(inventory) = inventory.filter(|_| self.is_enabled(ScannerKind::Inventory)) {
+            if self.cli.dry_run || self.cli.estimate {
+                log::info!("not writing the inventory '{}' without a scan", inventory);
+            } else {
+                let inventory_scanner =
+                    InventoryScanner::new(inventory)?.with_partial_hash(self.cli.partial_hash);
+                scanners.push(Box::new(inventory_scanner));
+            }
+        }
+
         Ok(Arc::new(scanners))
     }
 
+    /// the findings are written to stdout, unless dionysos runs as Windows service
+    fn output_destination(&self) -> Result<Box<dyn Write + Send>> {
+        #[cfg(windows)]
+        if let Some(Command::Service { action: ServiceAction::Run { output } }) = &self.cli.command {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(output)
+                .map_err(|why| anyhow!("unable to open '{}': {}", output.display(), why))?;
+            return Ok(Box::new(file));
+        }
+        Ok(Box::new(std::io::stdout()))
+    }
+
     fn init_logging(&self) -> Result<()> {
         match &self.cli.log_file {
+            // log messages would garble the TUI
+            None if self.tui_enabled() => Ok(()),
+
+            // a service has no console
+            #[cfg(windows)]
+            None if self.interrupt.is_some() => eventlog::init(
+                service::SERVICE_NAME,
+                self.loglevel.to_level().unwrap_or(log::Level::Error),
+            )
+            .map_err(|why| anyhow!("unable to write to the event log: {}", why)),
             None => match TermLogger::init(
                 self.loglevel,
                 Config::default(),
@@ -454,8 +3084,52 @@ impl Dionysos {
         }
     }
 
+    /// like [`Cli::parse_from`], but uses [`EXIT_FATAL`] for invalid command lines,
+    /// to distinguish them from scans which completed with errors
+    fn parse_cli<I, T>(args: I) -> Cli
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        match Cli::try_parse_from(args) {
+            Ok(cli) => cli,
+            Err(why) if why.use_stderr() => {
+                let _ = why.print();
+                std::process::exit(EXIT_FATAL)
+            }
+            Err(why) => why.exit(),
+        }
+    }
+
+    fn filename_pattern(pattern: &str) -> Result<regex::Regex> {
+        regex::Regex::new(pattern)
+            .map_err(|why| anyhow!("invalid filename pattern '{}': {}", pattern, why))
+    }
+
+    /// the command line, whose options are preceded by the options of the
+    /// configuration file. This way, options from the command line override the
+    /// values of the configuration file
+    fn args_with_config(config: &Path) -> Result<Vec<OsString>> {
+        let mut args: Vec<OsString> = std::env::args_os().collect();
+        let cli_args = args.split_off(1);
+        args.extend(config_args(config, &Cli::command())?.into_iter().map(OsString::from));
+        args.extend(cli_args);
+        Ok(args)
+    }
+
+    /// re-reads the configuration file, e.g. after SIGHUP. In contrast to
+    /// [`Dionysos::parse_options`], invalid options do not terminate dionysos
+    #[cfg(any(feature = "grpc", feature = "http"))]
+    fn read_config(config: &Path) -> Result<Cli> {
+        Ok(Cli::try_parse_from(Self::args_with_config(config)?)?)
+    }
+
     fn parse_options() -> Result<Self> {
-        let cli = Cli::parse();
+        let mut cli = Self::parse_cli(std::env::args_os());
+
+        if let Some(config) = &cli.config {
+            cli = Self::parse_cli(Self::args_with_config(config)?);
+        }
 
         let path = match &cli.path {
             Some(path) => PathBuf::from(&path),
@@ -478,18 +3152,151 @@ impl Dionysos {
             }
         };
 
-        let filenames: Vec<regex::Regex> = cli
-            .filenames
-            .iter()
-            .map(|f| regex::Regex::new(f).unwrap())
-            .collect();
+        #[cfg(feature = "tui")]
+        if cli.tui && std::io::stdout().is_terminal() {
+            return Err(anyhow!("'--tui' requires stdout to be redirected to a file"));
+        }
+
+        // invalid patterns are reported by the validation
+        let filenames = match cli.command {
+            Some(Command::Validate) => Vec::new(),
+            _ => cli
+                .filenames
+                .iter()
+                .map(|f| Self::filename_pattern(f))
+                .collect::<Result<Vec<_>>>()?,
+        };
+
+        let targets = cli.targets.as_ref().map(Targets::from_path).transpose()?;
+        if cli.target_mode != TargetMode::Scan && targets.is_none() {
+            return Err(anyhow!("'--target-mode' requires '--targets'"));
+        }
+        if cli.target_mode.collects() && cli.collect.is_none() {
+            return Err(anyhow!("collecting the targets requires '--collect'"));
+        }
 
-        Ok(Self {
+        let mut dionysos = Self {
             path,
-            loglevel: cli.verbose.log_level_filter(),
+            targets,
+            loglevel: cli.log_level(),
             yara_rules,
             filenames,
             cli,
-        })
+            errors: Arc::new(AtomicUsize::new(0)),
+            special_files: SpecialFiles::default(),
+            compressed_files: Arc::new(CompressedFiles::default()),
+            audit: None,
+            interrupt: None,
+            handle: ScanHandle::default(),
+        };
+        dionysos.audit = dionysos.init_audit()?;
+        Ok(dionysos)
+    }
+
+    /// reads the threat intelligence databases, if they are given
+    fn init_threat_intel(&self) -> Result<Option<Arc<ThreatIntel>>> {
+        if self.cli.threat_intel.is_empty() {
+            return Ok(None);
+        }
+        let mut threat_intel = ThreatIntel::default();
+        for path in self.cli.threat_intel.iter() {
+            let entries = threat_intel
+                .add_file(path)
+                .map_err(|why| anyhow!("unable to read '{}': {}", path.display(), why))?;
+            log::info!(
+                "read {} entries from the threat intelligence database '{}'",
+                entries,
+                path.display()
+            );
+        }
+        Ok(Some(Arc::new(threat_intel)))
+    }
+
+    /// reads the findings policy, if it is given
+    fn init_findings_policy(&self) -> Result<Option<Arc<FindingsPolicy>>> {
+        let path = match &self.cli.findings_policy {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let policy = FindingsPolicy::from_file(path).map_err(|why| {
+            anyhow!("unable to read the findings policy '{}': {}", path.display(), why)
+        })?;
+        log::info!(
+            "read {} rule(s) from the findings policy '{}'",
+            policy.rule_count(),
+            path.display()
+        );
+        Ok(Some(Arc::new(policy)))
+    }
+
+    /// creates the audit manifest, if it is requested for a scan
+    fn init_audit(&self) -> Result<Option<Arc<AuditManifest>>> {
+        let destination = match &self.cli.audit {
+            Some(destination) => destination,
+            None => return Ok(None),
+        };
+        if self.cli.dry_run
+            || matches!(
+                self.cli.command,
+                Some(Command::Validate)
+                    | Some(Command::Diff { .. })
+                    | Some(Command::Report { .. })
+                    | Some(Command::Completions { .. })
+                    | Some(Command::Update { .. })
+                    | Some(Command::Serve(_))
+            )
+        {
+            return Ok(None);
+        }
+        #[cfg(feature = "grpc")]
+        if matches!(
+            self.cli.command,
+            Some(Command::Controller { .. }) | Some(Command::Agent { .. })
+        ) {
+            return Ok(None);
+        }
+        #[cfg(windows)]
+        if matches!(
+            self.cli.command,
+            Some(Command::Service { action: ServiceAction::Install { .. } })
+                | Some(Command::Service { action: ServiceAction::Uninstall })
+        ) {
+            return Ok(None);
+        }
+
+        let mut audit = AuditManifest::new(destination)?;
+        if let Some(config) = &self.cli.config {
+            audit = audit.with_input("configuration", config)?;
+        }
+        if let Some(yara_rules) = &self.yara_rules {
+            audit = audit.with_input("yara ruleset", yara_rules)?;
+        }
+        for hash_list in self.cli.file_hash_list.iter().chain(self.cli.hash_dir.iter()) {
+            audit = audit.with_input("hash list", hash_list)?;
+        }
+        #[cfg(feature = "fuzzy_hash")]
+        for hash_list in self.cli.fuzzy_hash_list.iter() {
+            audit = audit.with_input("ssdeep hash list", hash_list)?;
+        }
+        for digest_list in self.cli.tlsh_list.iter() {
+            audit = audit.with_input("TLSH digest list", digest_list)?;
+        }
+        for hash_list in self.cli.known_good.iter() {
+            audit = audit.with_input("known-good hash list", hash_list)?;
+        }
+        for hash_list in self.cli.ignore_hash_list.iter() {
+            audit = audit.with_input("ignored hash list", hash_list)?;
+        }
+        for targets_file in self.cli.levenshtein_targets_file.iter() {
+            audit = audit.with_input("levenshtein targets", targets_file)?;
+        }
+        #[cfg(feature = "scan_reg")]
+        for ioc_file in self.cli.reg_ioc.iter() {
+            audit = audit.with_input("registry IoCs", ioc_file)?;
+        }
+        for plugin_dir in self.cli.plugin_dir.iter() {
+            audit = audit.with_input("plugin", plugin_dir)?;
+        }
+        Ok(Some(Arc::new(audit.start()?)))
     }
 }
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// the number of directories which are cached by default
+const CAPACITY: usize = 10_000;
+
+/// what scanners have found out about the directories of the scanned files, e.g. their
+/// location. The files of a directory are usually scanned one after another, so only
+/// the most recently used directories are kept: if there are more than `capacity`,
+/// the least recently used half of them is dropped
+pub(crate) struct DirectoryCache<V> {
+    capacity: usize,
+    directories: Mutex<CachedDirectories<V>>,
+}
+
+struct CachedDirectories<V> {
+    /// the value of every directory, and when it has been used for the last time
+    values: HashMap<PathBuf, (V, u64)>,
+    uses: u64,
+}
+
+impl<V> Default for DirectoryCache<V> {
+    fn default() -> Self {
+        Self::with_capacity(CAPACITY)
+    }
+}
+
+impl<V: Clone> DirectoryCache<V> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            directories: Mutex::new(CachedDirectories {
+                values: HashMap::new(),
+                uses: 0,
+            }),
+        }
+    }
+
+    /// the value of `directory`, which is computed with `value` if it is not cached,
+    /// and if it has been computed by this call
+    pub fn get_or_insert_with<F>(&self, directory: &Path, value: F) -> (V, bool)
+    where
+        F: FnOnce() -> V,
+    {
+        let mut directories = self.directories.lock().unwrap();
+        directories.uses += 1;
+        let uses = directories.uses;
+        if let Some((value, last_used)) = directories.values.get_mut(directory) {
+            *last_used = uses;
+            return (value.clone(), false);
+        }
+
+        if directories.values.len() >= self.capacity {
+            let mut last_uses: Vec<u64> = directories.values.values().map(|(_, u)| *u).collect();
+            last_uses.sort_unstable();
+            let oldest_kept = last_uses[last_uses.len() / 2];
+            directories
+                .values
+                .retain(|_, (_, last_used)| *last_used >= oldest_kept);
+        }
+        let value = value();
+        directories
+            .values
+            .insert(directory.to_path_buf(), (value.clone(), uses));
+        (value, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::DirectoryCache;
+
+    #[test]
+    fn test_directory_cache() {
+        let cache = DirectoryCache::with_capacity(4);
+        assert_eq!(cache.get_or_insert_with(Path::new("/a"), || 1), (1, true));
+        assert_eq!(cache.get_or_insert_with(Path::new("/a"), || 2), (1, false));
+        for (index, directory) in ["/b", "/c", "/d"].iter().enumerate() {
+            cache.get_or_insert_with(Path::new(directory), || index);
+        }
+        // "/a" is used again, so that "/b" and "/c" are dropped instead
+        cache.get_or_insert_with(Path::new("/a"), || 3);
+        cache.get_or_insert_with(Path::new("/e"), || 4);
+
+        assert_eq!(cache.directories.lock().unwrap().values.len(), 3);
+        assert_eq!(cache.get_or_insert_with(Path::new("/a"), || 5), (1, false));
+        assert_eq!(cache.get_or_insert_with(Path::new("/d"), || 5), (2, false));
+        assert_eq!(cache.get_or_insert_with(Path::new("/b"), || 5), (5, true));
+    }
+}
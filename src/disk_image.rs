@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::file_provider::{FileProvider, ProvidedFile};
+use crate::yara::{
+    apply_fixups, attributes, data_runs, decode_runs, ntfs_volumes, read_at, NtfsVolume,
+};
+
+/// files which are larger are not read from disk images, unless the limit is changed
+pub(crate) const DEFAULT_MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+/// the MFT records below this number belong to the metadata files of NTFS, which are
+/// not scanned
+const FIRST_USER_RECORD: u64 = 24;
+const ROOT_RECORD: u64 = 5;
+
+/// references to MFT records contain the sequence number of the record in their upper
+/// 16 bits
+const RECORD_NUMBER_MASK: u64 = 0x0000_ffff_ffff_ffff;
+const RECORD_IN_USE: u16 = 0x0001;
+const RECORD_DIRECTORY: u16 = 0x0002;
+
+const ATTRIBUTE_FILE_NAME: u32 = 0x30;
+const ATTRIBUTE_DATA: u32 = 0x80;
+const ATTRIBUTE_COMPRESSED: u16 = 0x00ff;
+const ATTRIBUTE_ENCRYPTED: u16 = 0x4000;
+const NAMESPACE_DOS: u8 = 2;
+
+/// paths are resolved up to this depth, so that loops in a damaged MFT terminate
+const MAX_DEPTH: usize = 256;
+
+/// the files of the NTFS volumes in a disk image (or a raw device), which are read
+/// from the MFT without mounting the volumes. Their names are `<image>:<path>`, or
+/// `<image>:volume<n>:<path>` if the image contains several volumes.
+///
+/// Only the unnamed data stream of files is read. Compressed and encrypted files, and
+/// files whose attributes do not fit into a single MFT record, are reported as errors
+pub struct DiskImageProvider {
+    image: PathBuf,
+    max_size: u64,
+}
+
+impl DiskImageProvider {
+    pub fn new<P: AsRef<Path>>(image: P) -> Self {
+        Self {
+            image: image.as_ref().to_owned(),
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// files which are larger than `max_size` bytes are reported as errors instead of
+    /// being read into memory
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    fn volume_files(
+        &self,
+        volume: Option<usize>,
+        offset: u64,
+    ) -> Box<dyn Iterator<Item = Result<ProvidedFile>> + '_> {
+        let prefix = match volume {
+            Some(volume) => format!("{}:volume{}", self.image.display(), volume),
+            None => self.image.display().to_string(),
+        };
+        let (mut mft, index) = match Mft::open(&self.image, offset)
+            .and_then(|mut mft| mft.index().map(|index| (mft, index)))
+        {
+            Ok(mft) => mft,
+            Err(why) => {
+                let why = anyhow!("unable to read the MFT of '{}': {}", prefix, why);
+                return Box::new(std::iter::once(Err(why)));
+            }
+        };
+        let max_size = self.max_size;
+        let Index { names, files } = index;
+        Box::new(files.into_iter().map(move |record| {
+            let name = format!("{}:{}", prefix, path(&names, record));
+            match mft.data(record, max_size) {
+                Ok(data) => Ok(ProvidedFile::Buffer { name, data }),
+                Err(why) => Err(anyhow!("unable to read '{}': {}", name, why)),
+            }
+        }))
+    }
+}
+
+impl FileProvider for DiskImageProvider {
+    fn files(&mut self) -> Box<dyn Iterator<Item = Result<ProvidedFile>> + '_> {
+        let volumes = ntfs_volumes(&self.image);
+        if volumes.is_empty() {
+            let why = anyhow!("'{}' contains no NTFS volume", self.image.display());
+            return Box::new(std::iter::once(Err(why)));
+        }
+        let several = volumes.len() > 1;
+        let provider = &*self;
+        Box::new(
+            volumes
+                .into_iter()
+                .enumerate()
+                .flat_map(move |(n, offset)| {
+                    provider.volume_files(several.then_some(n + 1), offset)
+                }),
+        )
+    }
+}
+
+/// the names and parent directories of all records of an MFT, and the records of the
+/// files which are scanned
+#[derive(Default)]
+struct Index {
+    names: HashMap<u64, (u64, String)>,
+    files: Vec<u64>,
+}
+
+/// the MFT of a volume, whose location is read from its first record
+struct Mft {
+    image: File,
+    volume: NtfsVolume,
+    runs: Vec<(Option<u64>, u64)>,
+}
+
+impl Mft {
+    fn open(image: &Path, offset: u64) -> Result<Self> {
+        let mut image = File::open(image)?;
+        let volume = NtfsVolume::open(&mut image, offset)?;
+        let mut record = vec![0u8; volume.record_size as usize];
+        read_at(&mut image, volume.mft_offset, &mut record)?;
+        apply_fixups(&mut record)?;
+        let runs = data_runs(&record)?;
+        Ok(Self {
+            image,
+            volume,
+            runs,
+        })
+    }
+
+    /// the number of records, which cannot exceed the size of the volume
+    fn records(&self) -> u64 {
+        let clusters = self
+            .runs
+            .iter()
+            .fold(0u64, |clusters, (_, count)| clusters.saturating_add(*count))
+            .min(self.volume.clusters);
+        clusters * self.volume.cluster_size / self.volume.record_size
+    }
+
+    fn record(&mut self, number: u64) -> Result<Vec<u8>> {
+        let mut record = vec![0u8; self.volume.record_size as usize];
+        let start = number * self.volume.record_size;
+        read_runs(
+            &mut self.image,
+            &self.volume,
+            &self.runs,
+            start,
+            &mut record,
+        )?;
+        apply_fixups(&mut record)?;
+        Ok(record)
+    }
+
+    fn index(&mut self) -> Result<Index> {
+        let mut index = Index::default();
+        for number in 0..self.records() {
+            let record = match self.record(number) {
+                Ok(record) => record,
+                Err(why) => {
+                    log::debug!("skipping MFT record {}: {}", number, why);
+                    continue;
+                }
+            };
+            let flags = u16::from_le_bytes([record[0x16], record[0x17]]);
+            let base_record = u64::from_le_bytes(record[0x20..0x28].try_into()?);
+            if flags & RECORD_IN_USE == 0 || base_record != 0 {
+                continue;
+            }
+            if let Some(name) = file_name(&record) {
+                index.names.insert(number, name);
+                if flags & RECORD_DIRECTORY == 0 && number >= FIRST_USER_RECORD {
+                    index.files.push(number);
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// the content of the unnamed data stream of the file in record `number`
+    fn data(&mut self, number: u64, max_size: u64) -> Result<Vec<u8>> {
+        let record = self.record(number)?;
+        let (_, attribute) = attributes(&record)
+            .find(|(kind, attribute)| *kind == ATTRIBUTE_DATA && attribute[9] == 0)
+            .ok_or_else(|| anyhow!("the data is not stored in the MFT record of the file"))?;
+        if attribute[8] == 0 {
+            return resident_value(attribute)
+                .map(|value| value.to_vec())
+                .ok_or_else(|| anyhow!("invalid data attribute"));
+        }
+
+        let flags = u16::from_le_bytes([attribute[0x0c], attribute[0x0d]]);
+        if flags & ATTRIBUTE_COMPRESSED != 0 {
+            return Err(anyhow!("compressed files are not supported"));
+        }
+        if flags & ATTRIBUTE_ENCRYPTED != 0 {
+            return Err(anyhow!("encrypted files are not supported"));
+        }
+        let runs = decode_runs(attribute)?;
+        let size = u64::from_le_bytes(attribute[0x30..0x38].try_into()?);
+        if size > max_size {
+            return Err(anyhow!("the file is larger than {} bytes", max_size));
+        }
+        let mut data = vec![0u8; size as usize];
+        read_runs(&mut self.image, &self.volume, &runs, 0, &mut data)?;
+        Ok(data)
+    }
+}
+
+/// reads the bytes from `start` on of the data which is stored in `runs`
+fn read_runs(
+    image: &mut File,
+    volume: &NtfsVolume,
+    runs: &[(Option<u64>, u64)],
+    start: u64,
+    buffer: &mut [u8],
+) -> Result<()> {
+    let mut position = 0u64;
+    let mut filled = 0usize;
+    for (cluster, count) in runs {
+        if filled == buffer.len() {
+            break;
+        }
+        let length = count.saturating_mul(volume.cluster_size);
+        let offset = start + filled as u64;
+        if offset < position.saturating_add(length) {
+            let skipped = offset - position;
+            let size = (length - skipped).min((buffer.len() - filled) as u64) as usize;
+            match cluster {
+                Some(cluster)
+                    if *cluster < volume.clusters && *count <= volume.clusters - cluster =>
+                {
+                    let offset = volume.offset + cluster * volume.cluster_size + skipped;
+                    read_at(image, offset, &mut buffer[filled..filled + size])?
+                }
+                Some(_) => return Err(anyhow!("the data is outside of the volume")),
+                // sparse runs are not allocated and read as zeros
+                None => (),
+            }
+            filled += size;
+        }
+        position = position.saturating_add(length);
+    }
+    match filled == buffer.len() {
+        true => Ok(()),
+        false => Err(anyhow!("the data runs are incomplete")),
+    }
+}
+
+fn resident_value(attribute: &[u8]) -> Option<&[u8]> {
+    let length = u32::from_le_bytes(attribute[0x10..0x14].try_into().ok()?) as usize;
+    let offset = u16::from_le_bytes([attribute[0x14], attribute[0x15]]) as usize;
+    attribute.get(offset..offset.checked_add(length)?)
+}
+
+/// the parent directory and the name of the file in `record`. Long names are preferred
+/// to their DOS aliases
+fn file_name(record: &[u8]) -> Option<(u64, String)> {
+    attributes(record)
+        .filter(|(kind, attribute)| *kind == ATTRIBUTE_FILE_NAME && attribute[8] == 0)
+        .filter_map(|(_, attribute)| {
+            let value = resident_value(attribute)?;
+            let length = *value.get(0x40)? as usize;
+            let name: Vec<u16> = value
+                .get(0x42..0x42 + 2 * length)?
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            let parent = u64::from_le_bytes(value[..8].try_into().ok()?) & RECORD_NUMBER_MASK;
+            Some((value[0x41], parent, String::from_utf16_lossy(&name)))
+        })
+        .min_by_key(|(namespace, _, _)| *namespace == NAMESPACE_DOS)
+        .map(|(_, parent, name)| (parent, name))
+}
+
+/// the path of `record` in its volume. Files in directories which are not known any
+/// more are placed below `$Orphan`
+fn path(names: &HashMap<u64, (u64, String)>, record: u64) -> String {
+    let mut components = Vec::new();
+    let mut current = record;
+    while current != ROOT_RECORD {
+        match names.get(&current) {
+            Some((parent, name)) if components.len() < MAX_DEPTH => {
+                components.push(name.as_str());
+                current = *parent;
+            }
+            _ => {
+                components.push("$Orphan");
+                break;
+            }
+        }
+    }
+    components.reverse();
+    components.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiskImageProvider, FileProvider, ProvidedFile};
+
+    const RECORD_SIZE: usize = 1024;
+    const CLUSTER_SIZE: usize = 512;
+
+    fn record(flags: u16, attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut record = vec![0u8; RECORD_SIZE];
+        record[..4].copy_from_slice(b"FILE");
+        // an update sequence without fixups
+        record[4] = 0x30;
+        record[6] = 1;
+        record[0x14] = 0x38;
+        record[0x16..0x18].copy_from_slice(&flags.to_le_bytes());
+        let mut position = 0x38;
+        for attribute in attributes {
+            record[position..position + attribute.len()].copy_from_slice(attribute);
+            position += attribute.len();
+        }
+        record[position..position + 4].copy_from_slice(&0xffff_ffffu32.to_le_bytes());
+        record
+    }
+
+    fn resident(kind: u32, value: &[u8]) -> Vec<u8> {
+        let length = (0x18 + value.len() + 7) & !7;
+        let mut attribute = vec![0u8; length];
+        attribute[..4].copy_from_slice(&kind.to_le_bytes());
+        attribute[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        attribute[0x10..0x14].copy_from_slice(&(value.len() as u32).to_le_bytes());
+        attribute[0x14] = 0x18;
+        attribute[0x18..0x18 + value.len()].copy_from_slice(value);
+        attribute
+    }
+
+    fn non_resident(size: u64, runs: &[u8], flags: u16) -> Vec<u8> {
+        let length = (0x40 + runs.len() + 1 + 7) & !7;
+        let mut attribute = vec![0u8; length];
+        attribute[..4].copy_from_slice(&0x80u32.to_le_bytes());
+        attribute[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        attribute[8] = 1;
+        attribute[0x0c..0x0e].copy_from_slice(&flags.to_le_bytes());
+        attribute[0x20] = 0x40;
+        attribute[0x30..0x38].copy_from_slice(&size.to_le_bytes());
+        attribute[0x40..0x40 + runs.len()].copy_from_slice(runs);
+        attribute
+    }
+
+    fn file_name(parent: u64, name: &str) -> Vec<u8> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let mut value = vec![0u8; 0x42 + 2 * name.len()];
+        // the sequence number of the parent is ignored
+        value[..8].copy_from_slice(&(parent | (3 << 48)).to_le_bytes());
+        value[0x40] = name.len() as u8;
+        value[0x41] = 1;
+        for (i, c) in name.iter().enumerate() {
+            value[0x42 + 2 * i..0x44 + 2 * i].copy_from_slice(&c.to_le_bytes());
+        }
+        resident(0x30, &value)
+    }
+
+    /// a volume of 256 clusters, whose MFT of 30 records starts at cluster 4
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 256 * CLUSTER_SIZE];
+        image[3..11].copy_from_slice(b"NTFS    ");
+        image[0x0b..0x0d].copy_from_slice(&512u16.to_le_bytes());
+        image[0x0d] = 1;
+        image[0x28..0x30].copy_from_slice(&256u64.to_le_bytes());
+        image[0x30..0x38].copy_from_slice(&4u64.to_le_bytes());
+        image[0x40] = -10i8 as u8;
+
+        let records = [
+            (0, record(1, &[non_resident(30 * 1024, &[0x11, 60, 4], 0)])),
+            (5, record(3, &[file_name(5, ".")])),
+            (6, record(1, &[non_resident(32, &[0x11, 1, 100], 0)])),
+            (24, record(3, &[file_name(5, "docs")])),
+            (
+                25,
+                record(1, &[file_name(24, "a.txt"), resident(0x80, b"hello")]),
+            ),
+            (
+                26,
+                record(
+                    1,
+                    &[
+                        file_name(5, "b.bin"),
+                        non_resident(1500, &[0x01, 2, 0x11, 2, 120], 0),
+                    ],
+                ),
+            ),
+            (
+                27,
+                record(
+                    1,
+                    &[file_name(5, "c.bin"), non_resident(512, &[0x11, 1, 122], 1)],
+                ),
+            ),
+            (
+                28,
+                record(1, &[file_name(99, "lost.txt"), resident(0x80, b"lost")]),
+            ),
+            (
+                29,
+                record(0, &[file_name(5, "deleted.txt"), resident(0x80, b"gone")]),
+            ),
+        ];
+        for (number, record) in records {
+            let offset = 4 * CLUSTER_SIZE + number * RECORD_SIZE;
+            image[offset..offset + RECORD_SIZE].copy_from_slice(&record);
+        }
+        image[120 * CLUSTER_SIZE..122 * CLUSTER_SIZE].fill(b'b');
+        image
+    }
+
+    #[test]
+    fn test_disk_image() {
+        let path = std::env::temp_dir().join(format!("dionysos-disk-image-{}", std::process::id()));
+        std::fs::write(&path, image()).unwrap();
+        let mut provider = DiskImageProvider::new(&path);
+        let files: Vec<_> = provider.files().collect();
+        std::fs::remove_file(&path).unwrap();
+
+        let file = |index: usize| match &files[index] {
+            Ok(ProvidedFile::Buffer { name, data }) => (name.clone(), data.clone()),
+            _ => panic!("file {} has not been read", index),
+        };
+        let prefix = path.display().to_string();
+        assert_eq!(files.len(), 4);
+        assert_eq!(
+            file(0),
+            (format!("{}:docs/a.txt", prefix), b"hello".to_vec())
+        );
+
+        let mut data = vec![0u8; 1024];
+        data.resize(1500, b'b');
+        assert_eq!(file(1), (format!("{}:b.bin", prefix), data));
+        assert!(files[2]
+            .as_ref()
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("compressed"));
+        assert_eq!(
+            file(3),
+            (format!("{}:$Orphan/lost.txt", prefix), b"lost".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_max_size() {
+        let path = std::env::temp_dir().join(format!("dionysos-max-size-{}", std::process::id()));
+        std::fs::write(&path, image()).unwrap();
+        let mut provider = DiskImageProvider::new(&path).with_max_size(1024);
+        let files: Vec<_> = provider.files().collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(&files[0], Ok(ProvidedFile::Buffer { .. })));
+        assert!(files[1]
+            .as_ref()
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("larger"));
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+
+use goblin::elf::program_header::{pt_to_str, ProgramHeader, PF_R, PF_W, PF_X, PT_LOAD, PT_NOTE};
+use goblin::elf::section_header::SHT_NOBITS;
+use maplit::hashset;
+use serde_json::json;
+
+use crate::csv_line::CsvLine;
+use crate::directory_cache::DirectoryCache;
+use crate::elf_file::ElfFile;
+use crate::executable_scanner::{Executable, ExecutableCheck};
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+use crate::system_location::{location_of, Location};
+
+/// directories which contain the dynamic loaders of Linux, the BSDs, Android and of
+/// distributions with their own store, like NixOS and Guix
+const INTERPRETER_DIRECTORIES: &[&str] = &[
+    "/lib/",
+    "/lib32/",
+    "/lib64/",
+    "/libx32/",
+    "/usr/lib/",
+    "/usr/lib32/",
+    "/usr/lib64/",
+    "/libexec/",
+    "/usr/libexec/",
+    "/system/bin/",
+    "/apex/",
+    "/nix/store/",
+    "/gnu/store/",
+];
+
+/// prefixes of the filenames of dynamic loaders, like `ld-linux-x86-64.so.2`,
+/// `ld-musl-x86_64.so.1`, `ld-elf.so.1` or `linker64`
+const INTERPRETER_NAMES: &[&str] = &["ld-", "ld.so", "ld64.so", "linker"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Anomaly {
+    /// an executable without symbol table in a temp directory
+    StrippedInTemp,
+
+    /// a program interpreter which is not a dynamic loader of the system
+    UnusualInterpreter,
+
+    /// a `PT_GNU_STACK` segment which requests an executable stack
+    ExecutableStack,
+
+    /// a `PT_LOAD` or `PT_NOTE` segment which contains no section, like the code
+    /// which is added by a PT_NOTE to PT_LOAD infection
+    InjectedSegment,
+}
+
+impl Anomaly {
+    fn name(&self) -> &'static str {
+        match self {
+            Anomaly::StrippedInTemp => "stripped-in-temp",
+            Anomaly::UnusualInterpreter => "unusual-interpreter",
+            Anomaly::ExecutableStack => "executable-stack",
+            Anomaly::InjectedSegment => "injected-segment",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Anomaly::StrippedInTemp => "stripped executable in a temp directory",
+            Anomaly::UnusualInterpreter => "unusual program interpreter",
+            Anomaly::ExecutableStack => "executable stack",
+            Anomaly::InjectedSegment => "segments without sections",
+        }
+    }
+}
+
+fn is_usual_interpreter(interpreter: &str) -> bool {
+    let (directory, name) = match interpreter.rsplit_once('/') {
+        Some((directory, name)) => (format!("{}/", directory), name),
+        None => return false,
+    };
+    !interpreter.contains("/../")
+        && INTERPRETER_DIRECTORIES
+            .iter()
+            .any(|prefix| directory.starts_with(prefix))
+        && INTERPRETER_NAMES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// the flags of a segment, like `r-x`
+fn flags_string(header: &ProgramHeader) -> String {
+    [(PF_R, 'r'), (PF_W, 'w'), (PF_X, 'x')]
+        .iter()
+        .map(|(flag, c)| if header.p_flags & flag != 0 { *c } else { '-' })
+        .collect()
+}
+
+/// the `PT_LOAD` and `PT_NOTE` segments of `elf` which contain data, but no section.
+/// The segments of files without section headers cannot be checked
+fn injected_segments(elf: &ElfFile) -> Vec<String> {
+    let sections: Vec<_> = elf
+        .section_headers()
+        .iter()
+        .filter(|header| header.sh_type != SHT_NOBITS && header.sh_size > 0)
+        .filter_map(|header| header.file_range())
+        .collect();
+    if sections.is_empty() {
+        return Vec::new();
+    }
+
+    let entry_point = elf.entry_point();
+    elf.program_headers()
+        .iter()
+        .filter(|header| matches!(header.p_type, PT_LOAD | PT_NOTE) && header.p_filesz > 0)
+        .filter(|header| {
+            let segment = header.file_range();
+            !sections
+                .iter()
+                .any(|section| section.start < segment.end && segment.start < section.end)
+        })
+        .map(|header| {
+            let contains_entry_point = entry_point != 0
+                && (header.p_vaddr..header.p_vaddr.saturating_add(header.p_memsz))
+                    .contains(&entry_point);
+            format!(
+                "{} at {:#x} ({} bytes, {}){}",
+                pt_to_str(header.p_type),
+                header.p_offset,
+                header.p_filesz,
+                flags_string(header),
+                if contains_entry_point {
+                    ", contains the entry point"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect()
+}
+
+/// the anomalies of `elf`, and the details of every anomaly
+fn anomalies(elf: &ElfFile, is_in_temp: bool) -> Vec<(Anomaly, String)> {
+    let mut anomalies = Vec::new();
+
+    if is_in_temp && !elf.has_symbol_table() {
+        anomalies.push((Anomaly::StrippedInTemp, "no symbol table".to_owned()));
+    }
+
+    if let Some(interpreter) = elf.interpreter() {
+        if !is_usual_interpreter(interpreter) {
+            anomalies.push((Anomaly::UnusualInterpreter, interpreter.to_owned()));
+        }
+    }
+
+    if elf.has_executable_stack() {
+        anomalies.push((
+            Anomaly::ExecutableStack,
+            "PT_GNU_STACK is executable".to_owned(),
+        ));
+    }
+
+    let injected_segments = injected_segments(elf);
+    if !injected_segments.is_empty() {
+        anomalies.push((Anomaly::InjectedSegment, injected_segments.join(", ")));
+    }
+
+    anomalies
+}
+
+/// reports ELF files with characteristics which are typical for malware or for
+/// infected executables: stripped executables in temp directories, unusual program
+/// interpreters, executable stacks and segments which have been injected into a file
+#[derive(Default)]
+pub struct ElfAnomalyScanner {
+    /// if directories are temp directories
+    checked_directories: DirectoryCache<bool>,
+}
+
+impl ElfAnomalyScanner {
+    fn is_temp_directory(&self, directory: &Path) -> bool {
+        self.checked_directories
+            .get_or_insert_with(directory, || location_of(directory) == Location::Temp)
+            .0
+    }
+}
+
+impl Display for ElfAnomalyScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ElfAnomalyScanner")
+    }
+}
+
+impl ExecutableCheck for ElfAnomalyScanner {
+    /// data without a path (e.g. files in archives) is not in a temp directory, so
+    /// stripped files are not reported
+    fn check(
+        &self,
+        found_in_file: &str,
+        path: Option<&Path>,
+        _data: &[u8],
+        executable: Option<&Executable>,
+    ) -> Vec<Box<dyn ScannerFinding>> {
+        let elf = match executable {
+            Some(Executable::Elf(elf)) => elf,
+            _ => return Vec::new(),
+        };
+        let is_in_temp = path
+            .and_then(Path::parent)
+            .map_or(false, |directory| self.is_temp_directory(directory));
+        anomalies(elf, is_in_temp)
+            .into_iter()
+            .map(|(anomaly, details)| {
+                Box::new(ElfAnomalyFinding {
+                    found_in_file: found_in_file.to_owned(),
+                    anomaly,
+                    details,
+                }) as Box<dyn ScannerFinding>
+            })
+            .collect()
+    }
+
+    /// executables are also reported because of their location
+    fn depends_on_path(&self) -> bool {
+        true
+    }
+}
+
+struct ElfAnomalyFinding {
+    found_in_file: String,
+    anomaly: Anomaly,
+    details: String,
+}
+
+impl ElfAnomalyFinding {
+    fn details(&self) -> String {
+        format!("{}: {}", self.anomaly.description(), self.details)
+    }
+}
+
+impl Display for ElfAnomalyFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "ELF anomaly: {found_in_file} ({details})")
+    }
+}
+
+impl ScannerFinding for ElfAnomalyFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "ElfAnomaly",
+            self.anomaly.name(),
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "elf_anomalies",
+            "02_suspicious_file": self.found_in_file(),
+            "03_anomaly": self.anomaly.name(),
+            "04_details": self.details
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        match self.anomaly {
+            Anomaly::InjectedSegment => Severity::High,
+            Anomaly::UnusualInterpreter | Anomaly::ExecutableStack => Severity::Medium,
+            Anomaly::StrippedInTemp => Severity::Low,
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "elf_anomalies".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    fn rule(&self) -> Option<&str> {
+        Some(self.anomaly.name())
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        match self.anomaly {
+            // Compromise Host Software Binary
+            Anomaly::InjectedSegment => vec!["T1554".to_owned()],
+            // Hijack Execution Flow
+            Anomaly::UnusualInterpreter => vec!["T1574".to_owned()],
+            Anomaly::ExecutableStack | Anomaly::StrippedInTemp => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{anomalies, injected_segments, is_usual_interpreter, Anomaly};
+    use crate::elf_file::ElfFile;
+    use crate::test_executables::infected_elf;
+
+    #[test]
+    fn test_injected_segments() {
+        let data = infected_elf(&[0x90; 0x100], true);
+        let elf = ElfFile::parse(&data).unwrap().unwrap();
+        assert_eq!(
+            injected_segments(&elf),
+            vec!["PT_LOAD at 0x300 (128 bytes, r-x), contains the entry point"]
+        );
+        let anomalies = anomalies(&elf, false);
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].0, Anomaly::InjectedSegment);
+
+        // without section headers, the segments cannot be checked
+        let data = infected_elf(&[0x90; 0x100], false);
+        let elf = ElfFile::parse(&data).unwrap().unwrap();
+        assert!(injected_segments(&elf).is_empty());
+    }
+
+    #[test]
+    fn test_interpreter() {
+        assert!(is_usual_interpreter("/lib64/ld-linux-x86-64.so.2"));
+        assert!(is_usual_interpreter("/lib/ld-musl-x86_64.so.1"));
+        assert!(is_usual_interpreter("/libexec/ld-elf.so.1"));
+        assert!(is_usual_interpreter("/system/bin/linker64"));
+        assert!(is_usual_interpreter(
+            "/nix/store/0k5jlj8y4n4rwvx5ryfyf3bb6ykxsyvd-glibc-2.37/lib/ld-linux-x86-64.so.2"
+        ));
+        assert!(!is_usual_interpreter("/tmp/ld-linux-x86-64.so.2"));
+        assert!(!is_usual_interpreter("/lib64/../tmp/ld-linux.so.2"));
+        assert!(!is_usual_interpreter("/lib64/libevil.so"));
+        assert!(!is_usual_interpreter("ld-linux.so.2"));
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use goblin::elf::program_header::{ProgramHeader, PF_X, PT_GNU_STACK};
+use goblin::elf::section_header::{SectionHeader, SHF_EXECINSTR, SHT_SYMTAB};
+use goblin::elf::Elf;
+
+/// a parsed ELF file, which is shared by all scanners which need to
+/// understand the structure of unix executables
+pub struct ElfFile<'a> {
+    elf: Elf<'a>,
+    data: &'a [u8],
+}
+
+impl<'a> ElfFile<'a> {
+    /// parses `data` as ELF file. Returns `None` if `data` does not look like an
+    /// ELF file at all, and an error if it does but cannot be parsed.
+    pub fn parse(data: &'a [u8]) -> Result<Option<Self>> {
+        if !data.starts_with(b"\x7fELF") {
+            return Ok(None);
+        }
+        let elf = Elf::parse(data)?;
+        Ok(Some(Self { elf, data }))
+    }
+
+    /// the names and the content of all sections which contain code
+    pub fn executable_sections(&self) -> Vec<(String, &'a [u8])> {
+        self.elf
+            .section_headers
+            .iter()
+            .filter(|header| header.sh_flags & SHF_EXECINSTR as u64 != 0)
+            .filter_map(|header| {
+                let content = self.data.get(header.file_range()?)?;
+                let name = self
+                    .elf
+                    .shdr_strtab
+                    .get_at(header.sh_name)
+                    .unwrap_or_default();
+                Some((name.to_owned(), content))
+            })
+            .collect()
+    }
+
+    /// the virtual address of the entry point, which is 0 for most libraries
+    pub fn entry_point(&self) -> u64 {
+        self.elf.header.e_entry
+    }
+
+    /// the program interpreter (the dynamic loader) of dynamically linked executables
+    pub fn interpreter(&self) -> Option<&'a str> {
+        self.elf.interpreter
+    }
+
+    /// checks if the file contains a symbol table, which is removed by `strip`
+    pub fn has_symbol_table(&self) -> bool {
+        self.elf
+            .section_headers
+            .iter()
+            .any(|header| header.sh_type == SHT_SYMTAB)
+    }
+
+    /// checks if the `PT_GNU_STACK` segment requests an executable stack
+    pub fn has_executable_stack(&self) -> bool {
+        self.elf
+            .program_headers
+            .iter()
+            .any(|header| header.p_type == PT_GNU_STACK && header.p_flags & PF_X != 0)
+    }
+
+    /// the program headers, which describe the segments which are loaded
+    pub fn program_headers(&self) -> &[ProgramHeader] {
+        &self.elf.program_headers
+    }
+
+    /// the section headers, which are missing in some stripped files
+    pub fn section_headers(&self) -> &[SectionHeader] {
+        &self.elf.section_headers
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use serde_json::json;
+
+use crate::csv_line::CsvLine;
+use crate::executable_scanner::{Executable, ExecutableCheck};
+use crate::extension_scanner::is_compressed;
+use crate::scanner_result::ScannerFinding;
+use crate::string_options::StringOptions;
+
+/// the default entropy (in bits per byte) above which files and sections are reported
+pub(crate) const DEFAULT_THRESHOLD: f64 = 7.2;
+
+/// the entropy of smaller files and sections is not meaningful
+const MIN_SIZE: usize = 1024;
+
+/// parses the value of `--entropy-threshold`, which must be between 0 and 8 bits per byte
+pub(crate) fn parse_threshold(value: &str) -> Result<f64> {
+    match value.parse::<f64>() {
+        Ok(threshold) if (0.0..=8.0).contains(&threshold) => Ok(threshold),
+        _ => Err(anyhow!(
+            "invalid entropy '{}', use a number between 0 and 8",
+            value
+        )),
+    }
+}
+
+/// the number of occurrences of every byte value
+struct ByteCounts {
+    counts: [u64; 256],
+    total: u64,
+}
+
+impl Default for ByteCounts {
+    fn default() -> Self {
+        Self {
+            counts: [0; 256],
+            total: 0,
+        }
+    }
+}
+
+impl ByteCounts {
+    fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.counts[*byte as usize] += 1;
+        }
+        self.total += data.len() as u64;
+    }
+
+    fn entropy(&self) -> f64 {
+        let total = self.total as f64;
+        self.counts
+            .iter()
+            .filter(|count| **count > 0)
+            .map(|count| {
+                let p = *count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+}
+
+/// the Shannon entropy of `data`, in bits per byte
+pub(crate) fn entropy(data: &[u8]) -> f64 {
+    let mut counts = ByteCounts::default();
+    counts.update(data);
+    counts.entropy()
+}
+
+/// the format and the executable sections of `executable`, if it is a PE or ELF file
+fn executable_sections<'a>(
+    executable: Option<&Executable<'a>>,
+) -> Option<(&'static str, Vec<(String, &'a [u8])>)> {
+    match executable? {
+        Executable::Pe(pe) => Some(("PE", pe.executable_sections())),
+        Executable::Elf(elf) => Some(("ELF", elf.executable_sections())),
+        Executable::MachO(_) => None,
+    }
+}
+
+/// reports files with a high entropy, which are probably packed or encrypted.
+/// Of PE and ELF files, only the sections which contain code are checked, because
+/// resources and overlays (e.g. of installers) are often compressed. Other files are
+/// checked as a whole, unless they have a format which is compressed by design (like
+/// zip archives or images)
+pub struct EntropyScanner {
+    threshold: f64,
+}
+
+impl Default for EntropyScanner {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl EntropyScanner {
+    /// only report files and sections whose entropy is above `threshold`
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    fn check_entropy(
+        &self,
+        found_in_file: &str,
+        data: &[u8],
+        executable: Option<&Executable>,
+    ) -> Option<EntropyFinding> {
+        if data.len() < MIN_SIZE {
+            return None;
+        }
+
+        if let Some((format, sections)) = executable_sections(executable) {
+            if !sections.is_empty() {
+                let sections: Vec<_> = sections
+                    .into_iter()
+                    .filter(|(_, content)| content.len() >= MIN_SIZE)
+                    .map(|(name, content)| SectionEntropy {
+                        name,
+                        size: content.len(),
+                        entropy: entropy(content),
+                    })
+                    .filter(|section| section.entropy > self.threshold)
+                    .collect();
+                return (!sections.is_empty()).then(|| EntropyFinding {
+                    found_in_file: found_in_file.to_owned(),
+                    format: Some(format),
+                    entropy: None,
+                    sections,
+                });
+            }
+        }
+
+        // the entropy of formats which are compressed by design is always high
+        if is_compressed(data) {
+            return None;
+        }
+        let entropy = entropy(data);
+        (entropy > self.threshold).then(|| EntropyFinding {
+            found_in_file: found_in_file.to_owned(),
+            format: None,
+            entropy: Some(entropy),
+            sections: Vec::new(),
+        })
+    }
+}
+
+impl Display for EntropyScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "EntropyScanner")
+    }
+}
+
+impl ExecutableCheck for EntropyScanner {
+    fn check(
+        &self,
+        found_in_file: &str,
+        _path: Option<&Path>,
+        data: &[u8],
+        executable: Option<&Executable>,
+    ) -> Vec<Box<dyn ScannerFinding>> {
+        self.check_entropy(found_in_file, data, executable)
+            .map(|finding| Box::new(finding) as Box<dyn ScannerFinding>)
+            .into_iter()
+            .collect()
+    }
+}
+
+struct SectionEntropy {
+    name: String,
+    size: usize,
+    entropy: f64,
+}
+
+struct EntropyFinding {
+    found_in_file: String,
+
+    /// the format of executables, whose sections are checked
+    format: Option<&'static str>,
+
+    /// the entropy of the whole file, if it is not an executable
+    entropy: Option<f64>,
+
+    /// the sections of an executable whose entropy is too high
+    sections: Vec<SectionEntropy>,
+}
+
+impl EntropyFinding {
+    fn details(&self) -> String {
+        match (self.entropy, self.format) {
+            (Some(entropy), _) => format!("entropy {:.2}", entropy),
+            (None, format) => format!(
+                "{} sections with high entropy: {}",
+                format.unwrap_or_default(),
+                self.sections
+                    .iter()
+                    .map(|section| format!("{} ({:.2})", section.name, section.entropy))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl Display for EntropyFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "high entropy: {found_in_file} ({details})")
+    }
+}
+
+impl ScannerFinding for EntropyFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "Entropy",
+            self.rule().unwrap_or_default(),
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        let sections: Vec<_> = self
+            .sections
+            .iter()
+            .map(|section| {
+                json!({
+                    "name": section.name,
+                    "size": section.size,
+                    "entropy": section.entropy
+                })
+            })
+            .collect();
+        json!({
+            "01_scanner": "entropy",
+            "02_suspicious_file": self.found_in_file(),
+            "03_format": self.format,
+            "04_entropy": self.entropy,
+            "05_sections": sections
+        })
+    }
+
+    fn scanner(&self) -> String {
+        "entropy".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    fn rule(&self) -> Option<&str> {
+        match self.format {
+            Some(_) => Some("high-entropy-sections"),
+            None => Some("high-entropy-file"),
+        }
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        match self.format {
+            // Obfuscated Files or Information: Software Packing
+            Some(_) => vec!["T1027.002".to_owned()],
+            // Obfuscated Files or Information
+            None => vec!["T1027".to_owned()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entropy, parse_threshold, EntropyFinding, EntropyScanner, MIN_SIZE};
+    use crate::executable_scanner::Executable;
+    use crate::test_executables::infected_elf;
+
+    fn check(scanner: &EntropyScanner, data: &[u8]) -> Option<EntropyFinding> {
+        scanner.check_entropy("test", data, Executable::parse("test", data).as_ref())
+    }
+
+    /// data with an entropy of almost 8 bits per byte
+    fn random(len: usize) -> Vec<u8> {
+        (0..len as u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[0x41; 100]), 0.0);
+        assert_eq!(entropy(&(0..=255).collect::<Vec<u8>>()), 8.0);
+
+        let random = random(4 * MIN_SIZE);
+        let scanner = EntropyScanner::default();
+        let finding = check(&scanner, &random).unwrap();
+        assert!(finding.entropy.unwrap() > 7.9);
+        assert!(check(&scanner, &random[..100]).is_none());
+    }
+
+    #[test]
+    fn test_compressed_formats() {
+        let scanner = EntropyScanner::default();
+        for signature in [
+            &b"PK\x03\x04"[..],
+            b"MSWIM\x00\x00\x00",
+            b"\x00\x00\x00\x20ftyp",
+        ] {
+            let mut data = signature.to_vec();
+            data.extend(random(4 * MIN_SIZE));
+            assert!(check(&scanner, &data).is_none());
+        }
+    }
+
+    #[test]
+    fn test_sections() {
+        let scanner = EntropyScanner::default();
+        let packed = infected_elf(&random(4 * MIN_SIZE), true);
+        let finding = check(&scanner, &packed).unwrap();
+        assert_eq!(finding.format, Some("ELF"));
+        assert_eq!(finding.sections.len(), 1);
+        assert_eq!(finding.sections[0].size, 4 * MIN_SIZE);
+        assert!(finding.entropy.is_none());
+
+        // only the sections with code are checked, not the whole file
+        let mut unpacked = infected_elf(&[0x90; 4 * MIN_SIZE], true);
+        unpacked.extend(random(16 * MIN_SIZE));
+        assert!(check(&scanner, &unpacked).is_none());
+    }
+
+    #[test]
+    fn test_parse_threshold() {
+        assert_eq!(parse_threshold("7.5").unwrap(), 7.5);
+        assert_eq!(parse_threshold("8").unwrap(), 8.0);
+        for value in ["-1", "8.1", "NaN", "high"] {
+            assert!(parse_threshold(value).is_err(), "{}", value);
+        }
+    }
+}
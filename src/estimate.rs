@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use indicatif::{HumanBytes, HumanDuration};
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::dionysos::OutputFormat;
+use crate::file_timeout::{FileHandler, FileTimeout};
+
+/// the maximum number of files which are scanned to calibrate the prediction
+const SAMPLE_SIZE: usize = 100;
+
+/// the calibration stops after this time, even if not all files of the sample have
+/// been scanned
+const CALIBRATION_TIME: Duration = Duration::from_secs(30);
+
+/// opening a file and running the scanners on it takes about as long as reading this
+/// number of bytes, so that many small files are not predicted to be scanned instantly
+const FILE_OVERHEAD: u64 = 64 * 1024;
+
+/// the upper bounds of the size buckets, and their names
+const SIZE_BUCKETS: &[(u64, &str)] = &[
+    (4 * 1024, "< 4 KiB"),
+    (1024 * 1024, "< 1 MiB"),
+    (16 * 1024 * 1024, "< 16 MiB"),
+    (256 * 1024 * 1024, "< 256 MiB"),
+    (u64::MAX, ">= 256 MiB"),
+];
+
+/// the number of extensions which are listed, the others are summarized
+const TOP_EXTENSIONS: usize = 15;
+
+/// the number of files and their size
+#[derive(Default, Clone, Copy)]
+struct Count {
+    files: u64,
+    bytes: u64,
+}
+
+impl Count {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// the result of scanning the sample
+struct Calibration {
+    files: u64,
+    bytes: u64,
+    elapsed: Duration,
+}
+
+/// the files which would be scanned (`--estimate`), and how long scanning them would
+/// take. The prediction is based on scanning a sample of the files with the selected
+/// scanners, so that responders can decide between a quick triage and a full scan
+/// before committing hours
+pub(crate) struct Estimate {
+    total: Count,
+    extensions: HashMap<String, Count>,
+    sizes: [Count; SIZE_BUCKETS.len()],
+
+    /// files which are evenly distributed over the walk, every `sample_step`th file
+    sample: Vec<DirEntry>,
+    sample_step: u64,
+
+    calibration: Option<Calibration>,
+}
+
+impl Default for Estimate {
+    fn default() -> Self {
+        Self {
+            total: Count::default(),
+            extensions: HashMap::new(),
+            sizes: [Count::default(); SIZE_BUCKETS.len()],
+            sample: Vec::new(),
+            sample_step: 1,
+            calibration: None,
+        }
+    }
+}
+
+impl Estimate {
+    pub fn add(&mut self, entry: DirEntry) {
+        let bytes = entry.metadata().map_or(0, |metadata| metadata.len());
+        let extension = entry
+            .path()
+            .extension()
+            .map_or_else(String::new, |e| e.to_string_lossy().to_lowercase());
+        self.extensions.entry(extension).or_default().add(bytes);
+        if let Some(bucket) = SIZE_BUCKETS.iter().position(|(limit, _)| bytes < *limit) {
+            self.sizes[bucket].add(bytes);
+        }
+
+        // if the sample is full, every second file of it is dropped, so that the
+        // sample is still evenly distributed, without knowing the number of files
+        if self.total.files % self.sample_step == 0 {
+            self.sample.push(entry);
+            if self.sample.len() > SAMPLE_SIZE {
+                let mut index = 0;
+                self.sample.retain(|_| {
+                    index += 1;
+                    index % 2 == 1
+                });
+                self.sample_step *= 2;
+            }
+        }
+        self.total.add(bytes);
+    }
+
+    /// scans the sample with `file_handler`, and measures the time it takes. The scan
+    /// of a single file is aborted after `file_timeout` (`--file-timeout`), but not
+    /// later than the end of the calibration
+    pub fn calibrate(&mut self, file_handler: FileHandler, file_timeout: Option<Duration>) {
+        let file_timeout = file_timeout.map_or(CALIBRATION_TIME, |t| t.min(CALIBRATION_TIME));
+        let mut file_handler =
+            file_handler.with_timeout(Some(Arc::new(FileTimeout::new(file_timeout))));
+        let started = Instant::now();
+        let mut calibration = Calibration {
+            files: 0,
+            bytes: 0,
+            elapsed: Duration::default(),
+        };
+        // if the calibration is stopped early, the scanned files are still spread
+        // over the whole walk, and not taken from the first directories only
+        for entry in spread_order(self.sample.len()).map(|index| &self.sample[index]) {
+            if started.elapsed() > CALIBRATION_TIME {
+                log::info!("the calibration takes too long, using a smaller sample");
+                break;
+            }
+            file_handler.handle_file(entry);
+            calibration.files += 1;
+            calibration.bytes += entry.metadata().map_or(0, |metadata| metadata.len());
+        }
+        calibration.elapsed = started.elapsed();
+        self.calibration = Some(calibration);
+    }
+
+    /// the predicted duration of scanning all files with `threads` workers, assuming
+    /// that the workers do not slow down each other
+    fn predicted_duration(&self, threads: usize) -> Option<Duration> {
+        let calibration = self.calibration.as_ref().filter(|c| c.files > 0)?;
+        let work = |files: u64, bytes: u64| (bytes + files * FILE_OVERHEAD) as f64;
+        let secs = calibration.elapsed.as_secs_f64() * work(self.total.files, self.total.bytes)
+            / work(calibration.files, calibration.bytes)
+            / threads.max(1) as f64;
+        Some(Duration::from_secs_f64(secs))
+    }
+
+    /// the most frequent extensions, and the count of all other extensions
+    fn top_extensions(&self) -> Vec<(String, Count)> {
+        let mut extensions: Vec<(String, Count)> = self
+            .extensions
+            .iter()
+            .map(|(extension, count)| (extension.clone(), *count))
+            .collect();
+        extensions.sort_by(|(a_ext, a), (b_ext, b)| (b.files, a_ext).cmp(&(a.files, b_ext)));
+        if extensions.len() > TOP_EXTENSIONS {
+            let mut others = Count::default();
+            for (_, count) in extensions.drain(TOP_EXTENSIONS..) {
+                others.files += count.files;
+                others.bytes += count.bytes;
+            }
+            extensions.push(("(others)".to_owned(), others));
+        }
+        for (extension, _) in extensions.iter_mut().filter(|(e, _)| e.is_empty()) {
+            *extension = "(none)".to_owned();
+        }
+        extensions
+    }
+
+    pub fn write<W: Write>(
+        &self,
+        format: &OutputFormat,
+        threads: usize,
+        wtr: &mut W,
+    ) -> Result<()> {
+        let predicted = self.predicted_duration(threads);
+        match format {
+            OutputFormat::Txt => {
+                writeln!(
+                    wtr,
+                    "{} file(s), {}",
+                    self.total.files,
+                    HumanBytes(self.total.bytes)
+                )?;
+                writeln!(wtr, "\nby extension:")?;
+                for (extension, count) in self.top_extensions() {
+                    writeln!(
+                        wtr,
+                        "  {:<12} {:>10} {:>12}",
+                        extension,
+                        count.files,
+                        HumanBytes(count.bytes).to_string()
+                    )?;
+                }
+                writeln!(wtr, "\nby size:")?;
+                for ((_, name), count) in SIZE_BUCKETS.iter().zip(self.sizes.iter()) {
+                    writeln!(
+                        wtr,
+                        "  {:<12} {:>10} {:>12}",
+                        name,
+                        count.files,
+                        HumanBytes(count.bytes).to_string()
+                    )?;
+                }
+                if let Some(calibration) = &self.calibration {
+                    writeln!(
+                        wtr,
+                        "\ncalibration: scanned {} file(s) ({}) in {:.1}s",
+                        calibration.files,
+                        HumanBytes(calibration.bytes),
+                        calibration.elapsed.as_secs_f64()
+                    )?;
+                }
+                if let Some(predicted) = predicted {
+                    writeln!(
+                        wtr,
+                        "predicted duration: {} with {} thread(s)",
+                        HumanDuration(predicted),
+                        threads
+                    )?;
+                }
+            }
+            OutputFormat::Csv => {
+                let mut wtr = csv::Writer::from_writer(wtr);
+                wtr.write_record(["category", "name", "files", "bytes", "seconds"])?;
+                let mut record = |category: &str, name: &str, count: Count, secs: Option<f64>| {
+                    wtr.write_record([
+                        category,
+                        name,
+                        &count.files.to_string(),
+                        &count.bytes.to_string(),
+                        &secs.map_or_else(String::new, |secs| format!("{:.1}", secs)),
+                    ])
+                };
+                record("total", "", self.total, predicted.map(|p| p.as_secs_f64()))?;
+                for (extension, count) in self.top_extensions() {
+                    record("extension", &extension, count, None)?;
+                }
+                for ((_, name), count) in SIZE_BUCKETS.iter().zip(self.sizes.iter()) {
+                    record("size", name, *count, None)?;
+                }
+                if let Some(calibration) = &self.calibration {
+                    let count = Count {
+                        files: calibration.files,
+                        bytes: calibration.bytes,
+                    };
+                    record(
+                        "calibration",
+                        "",
+                        count,
+                        Some(calibration.elapsed.as_secs_f64()),
+                    )?;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                let counts = |counts: Vec<(String, Count)>, key: &str| {
+                    counts
+                        .into_iter()
+                        .map(|(name, count)| {
+                            json!({key: name, "files": count.files, "bytes": count.bytes})
+                        })
+                        .collect::<Vec<_>>()
+                };
+                let sizes = SIZE_BUCKETS
+                    .iter()
+                    .zip(self.sizes.iter())
+                    .map(|((_, name), count)| (name.to_string(), *count))
+                    .collect();
+                let estimate = json!({
+                    "files": self.total.files,
+                    "bytes": self.total.bytes,
+                    "extensions": counts(self.top_extensions(), "extension"),
+                    "sizes": counts(sizes, "size"),
+                    "calibration": self.calibration.as_ref().map(|c| json!({
+                        "files": c.files,
+                        "bytes": c.bytes,
+                        "seconds": c.elapsed.as_secs_f64()
+                    })),
+                    "threads": threads,
+                    "predicted_seconds": predicted.map(|p| p.as_secs())
+                });
+                writeln!(wtr, "{}", estimate)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the indices `0..len` in an order in which every prefix is spread over the whole
+/// range: the first index, then the middle, then the quarters, and so on
+fn spread_order(len: usize) -> impl Iterator<Item = usize> {
+    let first_step = len.next_power_of_two();
+    let mut step = first_step;
+    let mut order = Vec::with_capacity(len);
+    while step > 0 {
+        // the indices of the larger steps have already been visited
+        order.extend(
+            (0..len)
+                .step_by(step)
+                .filter(|index| step == first_step || index % (2 * step) != 0),
+        );
+        step /= 2;
+    }
+    order.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{spread_order, Calibration, Estimate, SAMPLE_SIZE};
+
+    #[test]
+    fn test_estimate() {
+        let dir = std::env::temp_dir().join(format!("dionysos-estimate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for index in 0..(3 * SAMPLE_SIZE) {
+            let extension = if index % 3 == 0 { "exe" } else { "txt" };
+            std::fs::write(dir.join(format!("{}.{}", index, extension)), b"x").unwrap();
+        }
+
+        let mut estimate = Estimate::default();
+        for entry in walkdir::WalkDir::new(&dir).min_depth(1) {
+            estimate.add(entry.unwrap());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(estimate.total.files, 3 * SAMPLE_SIZE as u64);
+        assert_eq!(estimate.total.bytes, 3 * SAMPLE_SIZE as u64);
+        assert_eq!(estimate.sizes[0].files, 3 * SAMPLE_SIZE as u64);
+        assert_eq!(estimate.extensions["exe"].files, SAMPLE_SIZE as u64);
+        assert!(estimate.sample.len() <= SAMPLE_SIZE);
+        assert!(estimate.sample.len() > SAMPLE_SIZE / 2);
+        assert_eq!(estimate.sample_step, 4);
+
+        // the sample has a tenth of the work, and two threads share the work
+        estimate.calibration = Some(Calibration {
+            files: 30,
+            bytes: 30,
+            elapsed: Duration::from_secs(2),
+        });
+        let predicted = estimate.predicted_duration(2).unwrap();
+        assert_eq!(predicted.as_secs(), 10);
+    }
+
+    #[test]
+    fn test_spread_order() {
+        assert_eq!(
+            spread_order(10).collect::<Vec<_>>(),
+            vec![0, 8, 4, 2, 6, 1, 3, 5, 7, 9]
+        );
+        assert_eq!(spread_order(1).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(spread_order(0).count(), 0);
+
+        let mut order: Vec<_> = spread_order(SAMPLE_SIZE).collect();
+        // the first few files are taken from the whole sample
+        assert!(order[..4].iter().all(|index| *index % 32 == 0));
+        assert!(order[..4].iter().any(|index| *index >= SAMPLE_SIZE / 2));
+        order.sort_unstable();
+        assert_eq!(order, (0..SAMPLE_SIZE).collect::<Vec<_>>());
+    }
+}
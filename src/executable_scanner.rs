@@ -0,0 +1,216 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use memmap::MmapOptions;
+use walkdir::DirEntry;
+
+use crate::elf_file::ElfFile;
+use crate::filescanner::FileScanner;
+use crate::macho_file::MachOFile;
+use crate::path_string::path_to_string;
+use crate::pe_file::PeFile;
+use crate::scanner_result::ScannerFinding;
+
+/// a parsed PE, ELF or Mach-O file
+pub(crate) enum Executable<'a> {
+    Pe(PeFile<'a>),
+    Elf(ElfFile<'a>),
+
+    /// one file for every architecture of a fat binary
+    MachO(Vec<MachOFile<'a>>),
+}
+
+impl<'a> Executable<'a> {
+    /// parses `data` as executable. Returns `None` if `data` is no executable, or
+    /// if it cannot be parsed; goblin rejects many of them
+    pub(crate) fn parse(found_in_file: &str, data: &'a [u8]) -> Option<Self> {
+        match Self::try_parse(data) {
+            Ok(executable) => executable,
+            Err(why) => {
+                log::debug!("unable to parse '{}' as executable: {}", found_in_file, why);
+                None
+            }
+        }
+    }
+
+    /// the parsers check the magic numbers first, so that at most one of them fails
+    fn try_parse(data: &'a [u8]) -> Result<Option<Self>> {
+        if let Some(pe) = PeFile::parse(data)? {
+            return Ok(Some(Self::Pe(pe)));
+        }
+        if let Some(elf) = ElfFile::parse(data)? {
+            return Ok(Some(Self::Elf(elf)));
+        }
+        let files = MachOFile::parse(data)?;
+        Ok((!files.is_empty()).then_some(Self::MachO(files)))
+    }
+}
+
+/// a check which needs to understand the structure of executables, such as the
+/// anomaly checks of PE, ELF and Mach-O files. Checks are run by the
+/// [`ExecutableScanner`], which parses every file only once.
+///
+/// The rules of the findings are the names of the anomalies, so that the
+/// findings policy can match them
+pub(crate) trait ExecutableCheck: Display + Sync + Send {
+    /// checks the content of a single file. `executable` is `None` if the file is
+    /// no executable, and `path` is `None` if the data does not reside in a file
+    fn check(
+        &self,
+        found_in_file: &str,
+        path: Option<&Path>,
+        data: &[u8],
+        executable: Option<&Executable>,
+    ) -> Vec<Box<dyn ScannerFinding>>;
+
+    /// see [`FileScanner::depends_on_path`]
+    fn depends_on_path(&self) -> bool {
+        false
+    }
+}
+
+/// maps every file into memory, parses it once and runs all enabled checks
+/// on it
+#[derive(Default)]
+pub struct ExecutableScanner {
+    checks: Vec<Box<dyn ExecutableCheck>>,
+}
+
+impl ExecutableScanner {
+    pub(crate) fn with_check(mut self, check: Box<dyn ExecutableCheck>) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.checks.is_empty()
+    }
+
+    fn check(
+        &self,
+        found_in_file: &str,
+        path: Option<&Path>,
+        data: &[u8],
+    ) -> Vec<Box<dyn ScannerFinding>> {
+        let executable = Executable::parse(found_in_file, data);
+        self.checks
+            .iter()
+            .flat_map(|check| check.check(found_in_file, path, data, executable.as_ref()))
+            .collect()
+    }
+
+    fn check_file(&self, path: &Path) -> Result<Vec<Box<dyn ScannerFinding>>> {
+        let file = File::open(path)?;
+
+        // empty files cannot be mapped into memory
+        if file.metadata()?.len() == 0 {
+            return Ok(Vec::new());
+        }
+        let data = unsafe { MmapOptions::new().map(&file)? };
+        Ok(self.check(&path_to_string(path), Some(path), &data))
+    }
+}
+
+impl Display for ExecutableScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let checks: Vec<_> = self.checks.iter().map(ToString::to_string).collect();
+        write!(f, "ExecutableScanner({})", checks.join(", "))
+    }
+}
+
+impl FileScanner for ExecutableScanner {
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match self.check_file(entry.path()) {
+            Ok(findings) => findings.into_iter().map(Ok).collect(),
+            Err(why) => vec![Err(anyhow!(
+                "unable to check the executable '{}': {}",
+                entry.path().display(),
+                why
+            ))],
+        }
+    }
+
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        self.check(name, None, data).into_iter().map(Ok).collect()
+    }
+
+    fn depends_on_path(&self) -> bool {
+        self.checks.iter().any(|check| check.depends_on_path())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Display;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    use walkdir::WalkDir;
+
+    use super::{Executable, ExecutableCheck, ExecutableScanner};
+    use crate::filescanner::FileScanner;
+    use crate::scanner_result::ScannerFinding;
+    use crate::test_executables::infected_elf;
+
+    /// records the formats of all checked files
+    #[derive(Clone, Default)]
+    struct Formats(Arc<Mutex<Vec<Option<&'static str>>>>);
+
+    impl Display for Formats {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "Formats")
+        }
+    }
+
+    impl ExecutableCheck for Formats {
+        fn check(
+            &self,
+            _found_in_file: &str,
+            _path: Option<&Path>,
+            _data: &[u8],
+            executable: Option<&Executable>,
+        ) -> Vec<Box<dyn ScannerFinding>> {
+            let format = executable.map(|executable| match executable {
+                Executable::Pe(_) => "PE",
+                Executable::Elf(_) => "ELF",
+                Executable::MachO(_) => "Mach-O",
+            });
+            self.0.lock().unwrap().push(format);
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let elf = infected_elf(&[0x90; 0x100], true);
+        assert!(matches!(
+            Executable::parse("elf", &elf),
+            Some(Executable::Elf(_))
+        ));
+        assert!(Executable::parse("text", b"hello world").is_none());
+        assert!(Executable::parse("broken", b"\x7fELF").is_none());
+    }
+
+    #[test]
+    fn test_scan_file() {
+        let path = std::env::temp_dir().join(format!("dionysos-executable-{}", std::process::id()));
+        std::fs::write(&path, infected_elf(&[0x90; 0x100], true)).unwrap();
+        let entry = WalkDir::new(&path).into_iter().next().unwrap().unwrap();
+
+        let formats = Formats::default();
+        let scanner = ExecutableScanner::default().with_check(Box::new(formats.clone()));
+        assert!(!scanner.is_empty());
+        assert!(!scanner.depends_on_path());
+        assert!(scanner.scan_file(&entry).is_empty());
+        assert!(scanner.scan_buffer("text", b"hello world").is_empty());
+
+        // empty files are not checked at all
+        std::fs::write(&path, b"").unwrap();
+        assert!(scanner.scan_file(&entry).is_empty());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(scanner.scan_file(&entry).len(), 1);
+        assert_eq!(*formats.0.lock().unwrap(), vec![Some("ELF"), None]);
+    }
+}
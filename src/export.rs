@@ -0,0 +1,263 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::ArgEnum;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::scanner_result::ScannerResult;
+use crate::string_options::StringOptions;
+
+/// name of the Velociraptor artifact, whose results are exported
+const VELOCIRAPTOR_ARTIFACT: &str = "Custom.Dionysos.Findings";
+
+/// layout of the directory, which is written by `--export`
+#[derive(ArgEnum, Clone, Copy)]
+pub enum ExportLayout {
+    /// the layout of an offline collection, which can be imported by a Velociraptor server
+    Velociraptor,
+
+    /// the output layout of KAPE modules
+    Kape,
+}
+
+/// a row of the KAPE module output
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct KapeRow<'a> {
+    hostname: &'a str,
+    scanner: &'a str,
+    full_path: &'a str,
+    size: Option<u64>,
+    last_modified: Option<String>,
+    severity: String,
+    finding: String,
+}
+
+enum ExportWriter {
+    Velociraptor(BufWriter<File>),
+    Kape(csv::Writer<File>),
+}
+
+/// writes all findings and the metadata of their files in a layout, which can be
+/// consumed by existing collection pipelines:
+///
+///  - `velociraptor`: `results/Custom.Dionysos.Findings.json` (one JSON object per line)
+///    and `client_info.json`, like an offline collection. The definition of the artifact
+///    is written to `Custom.Dionysos.Findings.yaml`, and must be added to the server
+///    before the collection is imported
+///  - `kape`: `FileSystem/<timestamp>_Dionysos_Findings.csv`, like the output of a
+///    KAPE module
+pub struct Exporter {
+    dir: PathBuf,
+    hostname: String,
+    string_options: StringOptions,
+    writer: ExportWriter,
+}
+
+impl Exporter {
+    pub fn new<P: AsRef<Path>>(
+        layout: ExportLayout,
+        dir: P,
+        string_options: StringOptions,
+    ) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        let create = |path: PathBuf| -> Result<File> {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            File::create(&path)
+                .map_err(|why| anyhow!("unable to create '{}': {}", path.display(), why))
+        };
+
+        let writer = match layout {
+            ExportLayout::Velociraptor => {
+                let results = dir
+                    .join("results")
+                    .join(format!("{}.json", VELOCIRAPTOR_ARTIFACT));
+                ExportWriter::Velociraptor(BufWriter::new(create(results)?))
+            }
+            ExportLayout::Kape => {
+                let results = dir.join("FileSystem").join(format!(
+                    "{}_Dionysos_Findings.csv",
+                    Utc::now().format("%Y%m%d%H%M%S")
+                ));
+                ExportWriter::Kape(csv::Writer::from_writer(create(results)?))
+            }
+        };
+
+        Ok(Self {
+            dir,
+            hostname: hostname::get()
+                .map(|h| h.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            string_options,
+            writer,
+        })
+    }
+
+    /// exports all findings of `result`
+    pub fn add_result(&mut self, result: &ScannerResult) -> Result<()> {
+        let metadata = result.path().and_then(|path| std::fs::metadata(path).ok());
+        let size = metadata.as_ref().map(|m| m.len());
+        let last_modified = metadata
+            .and_then(|m| m.modified().ok())
+            .map(|t| DateTime::<Utc>::from(t).to_rfc3339());
+
+        for finding in result.findings() {
+            let details = finding.to_json(&self.string_options);
+            match &mut self.writer {
+                ExportWriter::Velociraptor(writer) => {
+                    let mut row = json!({
+                        "Hostname": self.hostname,
+                        "FullPath": result.filename(),
+                        "Size": size,
+                        "LastModified": last_modified,
+                    });
+                    if let (Some(row), Value::Object(details)) = (row.as_object_mut(), details) {
+                        row.extend(details);
+                    }
+                    serde_json::to_writer(&mut *writer, &row)?;
+                    writeln!(writer)?;
+                }
+                ExportWriter::Kape(writer) => {
+                    writer.serialize(KapeRow {
+                        hostname: &self.hostname,
+                        scanner: details["01_scanner"].as_str().unwrap_or_default(),
+                        full_path: result.filename(),
+                        size,
+                        last_modified: last_modified.clone(),
+                        severity: finding.severity().to_string(),
+                        finding: finding
+                            .format_readable(&self.string_options)
+                            .trim_end()
+                            .to_owned(),
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// flushes the results and writes the metadata of the collection
+    pub fn finish(mut self) -> Result<()> {
+        match &mut self.writer {
+            ExportWriter::Velociraptor(writer) => {
+                writer.flush()?;
+                let client_info = json!({
+                    "Hostname": self.hostname,
+                    "OS": std::env::consts::OS,
+                    "Architecture": std::env::consts::ARCH,
+                    "Collector": format!("dionysos {}", env!("CARGO_PKG_VERSION")),
+                });
+                std::fs::write(
+                    self.dir.join("client_info.json"),
+                    serde_json::to_string_pretty(&client_info)?,
+                )?;
+                std::fs::write(
+                    self.dir.join(format!("{}.yaml", VELOCIRAPTOR_ARTIFACT)),
+                    velociraptor_artifact(),
+                )?;
+            }
+            ExportWriter::Kape(writer) => writer.flush()?,
+        }
+        log::info!("exported the findings to '{}'", self.dir.display());
+        Ok(())
+    }
+}
+
+fn velociraptor_artifact() -> String {
+    format!(
+        "name: {}\n\
+         description: |\n  \
+           Findings of dionysos, which have been imported from an offline collection.\n\
+         type: CLIENT\n\
+         sources:\n  \
+           - query: SELECT * FROM scope()\n",
+        VELOCIRAPTOR_ARTIFACT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::Value;
+
+    use super::{ExportLayout, Exporter, VELOCIRAPTOR_ARTIFACT};
+    use crate::scanner_result::ScannerResult;
+    use crate::string_options::StringOptions;
+    use crate::ScanJob;
+
+    /// a result with a finding of the filename scanner
+    fn result() -> ScannerResult {
+        ScanJob::new("unused")
+            .with_filenames(&[r"\.exe$".to_owned()])
+            .unwrap()
+            .scan_buffer("evil.exe", b"")
+    }
+
+    fn export(layout: ExportLayout, dir: &Path) {
+        let mut exporter = Exporter::new(layout, dir, StringOptions::default()).unwrap();
+        exporter.add_result(&result()).unwrap();
+        exporter.finish().unwrap();
+    }
+
+    #[test]
+    fn test_velociraptor() {
+        let dir = std::env::temp_dir().join(format!("dionysos-velo-{}", std::process::id()));
+        export(ExportLayout::Velociraptor, &dir);
+
+        let results = dir
+            .join("results")
+            .join(format!("{}.json", VELOCIRAPTOR_ARTIFACT));
+        let results = std::fs::read_to_string(results).unwrap();
+        let client_info = std::fs::read_to_string(dir.join("client_info.json")).unwrap();
+        let artifact =
+            std::fs::read_to_string(dir.join(format!("{}.yaml", VELOCIRAPTOR_ARTIFACT))).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let rows: Vec<Value> = results
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["FullPath"], "evil.exe");
+        assert_eq!(rows[0]["01_scanner"], "filename");
+        assert!(rows[0]["Size"].is_null());
+
+        let client_info: Value = serde_json::from_str(&client_info).unwrap();
+        assert_eq!(client_info["OS"], std::env::consts::OS);
+        assert!(artifact.starts_with(&format!("name: {}\n", VELOCIRAPTOR_ARTIFACT)));
+    }
+
+    #[test]
+    fn test_kape() {
+        let dir = std::env::temp_dir().join(format!("dionysos-kape-{}", std::process::id()));
+        export(ExportLayout::Kape, &dir);
+
+        let files: Vec<_> = std::fs::read_dir(dir.join("FileSystem"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        let name = files[0].file_name().unwrap().to_string_lossy().to_string();
+        let mut reader = csv::Reader::from_path(&files[0]).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(name.ends_with("_Dionysos_Findings.csv"), "{}", name);
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>().join(","),
+            "Hostname,Scanner,FullPath,Size,LastModified,Severity,Finding"
+        );
+        assert_eq!(rows.len(), 1);
+        assert_eq!(&rows[0][1], "filename");
+        assert_eq!(&rows[0][2], "evil.exe");
+        assert_eq!(&rows[0][3], "");
+    }
+}
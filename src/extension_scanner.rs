@@ -0,0 +1,632 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::filescanner::FileScanner;
+use crate::path_string::path_to_string;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+/// the number of bytes at the beginning of a file which are needed to detect its type
+const HEADER_SIZE: usize = 4096;
+
+/// extensions which are used for all kinds of data, and therefore claim no type
+const AMBIGUOUS_EXTENSIONS: &[&str] = &["dat", "bin", "log", "tmp", "bak", "old", "data"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Category {
+    Executable,
+    Archive,
+    Document,
+    Image,
+    Media,
+    Other,
+}
+
+struct FileType {
+    name: &'static str,
+    category: Category,
+
+    /// offsets and contents of the signatures of this type. Types without signature
+    /// cannot be detected, but can be claimed by an extension
+    signatures: &'static [(usize, &'static [u8])],
+
+    /// the extensions (in lowercase) which files of this type may have
+    extensions: &'static [&'static str],
+
+    /// if the content of this type is compressed by design, so that its entropy is
+    /// always high
+    compressed: bool,
+}
+
+/// PE files are detected by their PE header, because `MZ` alone is too short
+static PE: FileType = FileType {
+    name: "PE executable",
+    category: Category::Executable,
+    signatures: &[],
+    extensions: &[
+        "exe", "dll", "sys", "drv", "ocx", "cpl", "scr", "efi", "mui", "mun", "ax", "acm", "tsp",
+        "winmd", "node", "pyd", "com", "msstyles", "ime", "rll", "fon", "xll", "wll",
+    ],
+    compressed: false,
+};
+
+/// archives which are used as containers by other formats, like Office documents
+const ZIP_EXTENSIONS: &[&str] = &[
+    "zip",
+    "zipx",
+    "jar",
+    "war",
+    "ear",
+    "aar",
+    "apk",
+    "aab",
+    "xapk",
+    "ipa",
+    "docx",
+    "docm",
+    "dotx",
+    "dotm",
+    "xlsx",
+    "xlsm",
+    "xlsb",
+    "xltx",
+    "xltm",
+    "xlam",
+    "pptx",
+    "pptm",
+    "potx",
+    "potm",
+    "ppsx",
+    "ppsm",
+    "ppam",
+    "sldx",
+    "thmx",
+    "vsdx",
+    "vsdm",
+    "vssx",
+    "vstx",
+    "odt",
+    "ods",
+    "odp",
+    "odg",
+    "ott",
+    "epub",
+    "xpi",
+    "nupkg",
+    "vsix",
+    "whl",
+    "egg",
+    "appx",
+    "appxbundle",
+    "msix",
+    "msixbundle",
+    "kmz",
+    "3mf",
+    "xps",
+    "oxps",
+    "cbz",
+    "idml",
+    "sketch",
+    "crx",
+];
+
+static FILE_TYPES: &[FileType] = &[
+    FileType {
+        name: "ELF executable",
+        category: Category::Executable,
+        signatures: &[(0, b"\x7fELF")],
+        extensions: &["so", "elf", "o", "ko", "out", "axf", "prx", "run", "node"],
+        compressed: false,
+    },
+    FileType {
+        name: "Mach-O executable",
+        category: Category::Executable,
+        signatures: &[
+            (0, b"\xfe\xed\xfa\xce"),
+            (0, b"\xfe\xed\xfa\xcf"),
+            (0, b"\xce\xfa\xed\xfe"),
+            (0, b"\xcf\xfa\xed\xfe"),
+        ],
+        extensions: &["dylib", "bundle", "o", "so", "kext", "node"],
+        compressed: false,
+    },
+    FileType {
+        name: "ZIP archive",
+        category: Category::Archive,
+        signatures: &[(0, b"PK\x03\x04"), (0, b"PK\x05\x06")],
+        extensions: ZIP_EXTENSIONS,
+        compressed: true,
+    },
+    FileType {
+        name: "OLE compound file",
+        category: Category::Document,
+        signatures: &[(0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1")],
+        // encrypted Office Open XML documents are OLE compound files as well
+        extensions: &[
+            "doc", "dot", "xls", "xlt", "xla", "ppt", "pot", "pps", "ppa", "msi", "msp", "msm",
+            "mst", "msg", "pub", "vsd", "vss", "vst", "mpp", "db", "wps", "docx", "docm", "xlsx",
+            "xlsm", "xlsb", "pptx", "pptm",
+        ],
+        compressed: true,
+    },
+    FileType {
+        name: "PDF document",
+        category: Category::Document,
+        signatures: &[(0, b"%PDF")],
+        extensions: &["pdf", "ai", "fdf"],
+        compressed: true,
+    },
+    FileType {
+        name: "RTF document",
+        category: Category::Document,
+        signatures: &[(0, b"{\\rtf")],
+        extensions: &["rtf", "doc", "wri"],
+        compressed: false,
+    },
+    FileType {
+        name: "gzip archive",
+        category: Category::Archive,
+        signatures: &[(0, b"\x1f\x8b\x08")],
+        extensions: &["gz", "tgz", "gzip", "svgz", "emz", "wmz"],
+        compressed: true,
+    },
+    FileType {
+        name: "bzip2 archive",
+        category: Category::Archive,
+        signatures: &[(0, b"BZh")],
+        extensions: &["bz2", "tbz", "tbz2", "bzip2"],
+        compressed: true,
+    },
+    FileType {
+        name: "xz archive",
+        category: Category::Archive,
+        signatures: &[(0, b"\xfd7zXZ\x00")],
+        extensions: &["xz", "txz"],
+        compressed: true,
+    },
+    FileType {
+        name: "zstd archive",
+        category: Category::Archive,
+        signatures: &[(0, b"\x28\xb5\x2f\xfd")],
+        extensions: &["zst", "tzst"],
+        compressed: true,
+    },
+    FileType {
+        name: "7-Zip archive",
+        category: Category::Archive,
+        signatures: &[(0, b"7z\xbc\xaf\x27\x1c")],
+        extensions: &["7z"],
+        compressed: true,
+    },
+    FileType {
+        name: "RAR archive",
+        category: Category::Archive,
+        signatures: &[(0, b"Rar!\x1a\x07")],
+        extensions: &["rar"],
+        compressed: true,
+    },
+    FileType {
+        name: "cabinet archive",
+        category: Category::Archive,
+        signatures: &[(0, b"MSCF\x00\x00\x00\x00")],
+        extensions: &["cab", "msu"],
+        compressed: true,
+    },
+    FileType {
+        name: "WIM image",
+        category: Category::Archive,
+        signatures: &[(0, b"MSWIM\x00\x00\x00")],
+        extensions: &["wim", "esd", "swm"],
+        compressed: true,
+    },
+    FileType {
+        name: "JPEG image",
+        category: Category::Image,
+        signatures: &[(0, b"\xff\xd8\xff")],
+        extensions: &["jpg", "jpeg", "jpe", "jfif", "jif"],
+        compressed: true,
+    },
+    FileType {
+        name: "PNG image",
+        category: Category::Image,
+        signatures: &[(0, b"\x89PNG\r\n\x1a\n")],
+        extensions: &["png", "apng"],
+        compressed: true,
+    },
+    FileType {
+        name: "GIF image",
+        category: Category::Image,
+        signatures: &[(0, b"GIF87a"), (0, b"GIF89a")],
+        extensions: &["gif"],
+        compressed: true,
+    },
+    FileType {
+        name: "TIFF image",
+        category: Category::Image,
+        signatures: &[(0, b"II*\x00"), (0, b"MM\x00*")],
+        extensions: &["tif", "tiff", "dng", "cr2", "nef", "arw"],
+        compressed: false,
+    },
+    FileType {
+        name: "image",
+        category: Category::Image,
+        signatures: &[],
+        extensions: &[
+            "bmp", "dib", "ico", "cur", "webp", "svg", "heic", "heif", "avif",
+        ],
+        compressed: false,
+    },
+    FileType {
+        name: "RIFF container",
+        category: Category::Media,
+        signatures: &[(0, b"RIFF")],
+        extensions: &["wav", "avi", "webp", "ani", "rmi", "cdr"],
+        compressed: true,
+    },
+    FileType {
+        name: "ISO media",
+        category: Category::Media,
+        signatures: &[(4, b"ftyp")],
+        extensions: &[
+            "mp4", "m4a", "m4v", "m4b", "m4p", "mov", "3gp", "3g2", "heic", "heif", "avif", "f4v",
+        ],
+        compressed: true,
+    },
+    FileType {
+        name: "Matroska media",
+        category: Category::Media,
+        signatures: &[(0, b"\x1a\x45\xdf\xa3")],
+        extensions: &["mkv", "webm", "mka", "mks"],
+        compressed: true,
+    },
+    FileType {
+        name: "Ogg media",
+        category: Category::Media,
+        signatures: &[(0, b"OggS")],
+        extensions: &["ogg", "oga", "ogv", "opus", "spx"],
+        compressed: true,
+    },
+    FileType {
+        name: "MP3 audio",
+        category: Category::Media,
+        signatures: &[(0, b"ID3")],
+        extensions: &["mp3"],
+        compressed: true,
+    },
+    FileType {
+        name: "FLAC audio",
+        category: Category::Media,
+        signatures: &[(0, b"fLaC")],
+        extensions: &["flac"],
+        compressed: true,
+    },
+    FileType {
+        name: "media",
+        category: Category::Media,
+        signatures: &[],
+        extensions: &["mpg", "mpeg", "wmv", "wma", "aac", "flv"],
+        compressed: false,
+    },
+    FileType {
+        name: "SQLite database",
+        category: Category::Other,
+        signatures: &[(0, b"SQLite format 3\x00")],
+        extensions: &["sqlite", "sqlite3", "db", "db3", "sqlitedb"],
+        compressed: false,
+    },
+    FileType {
+        name: "Windows shortcut",
+        category: Category::Other,
+        signatures: &[(0, b"L\x00\x00\x00\x01\x14\x02\x00")],
+        extensions: &["lnk"],
+        compressed: false,
+    },
+    FileType {
+        name: "Windows event log",
+        category: Category::Other,
+        signatures: &[(0, b"ElfFile\x00")],
+        extensions: &["evtx"],
+        compressed: false,
+    },
+    FileType {
+        name: "registry hive",
+        category: Category::Other,
+        signatures: &[(0, b"regf")],
+        extensions: &["hve", "hiv", "log1", "log2", "sav"],
+        compressed: false,
+    },
+    FileType {
+        name: "Outlook data file",
+        category: Category::Other,
+        signatures: &[(0, b"!BDN")],
+        extensions: &["pst", "ost"],
+        compressed: false,
+    },
+    FileType {
+        name: "WebAssembly module",
+        category: Category::Executable,
+        signatures: &[(0, b"\x00asm")],
+        extensions: &["wasm"],
+        compressed: false,
+    },
+    FileType {
+        name: "WOFF2 font",
+        category: Category::Other,
+        signatures: &[(0, b"wOF2")],
+        extensions: &["woff2"],
+        compressed: true,
+    },
+    FileType {
+        name: "text",
+        category: Category::Document,
+        signatures: &[],
+        extensions: &[
+            "txt", "csv", "tsv", "ini", "cfg", "conf", "md", "json", "xml", "html", "htm",
+            "hta", "css", "js", "vbs", "vbe", "wsf", "ps1", "psm1", "bat", "cmd", "sh", "py",
+            "yml", "yaml", "eml",
+        ],
+        compressed: false,
+    },
+];
+
+/// checks if `header` starts with a DOS header, which points to a PE header
+fn is_pe(header: &[u8]) -> bool {
+    if !header.starts_with(b"MZ") {
+        return false;
+    }
+    let pe_offset = match header.get(0x3c..0x40) {
+        Some(offset) => u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize,
+        None => return false,
+    };
+    header
+        .get(pe_offset..)
+        .map_or(false, |pe| pe.starts_with(b"PE\x00\x00"))
+}
+
+/// the type of a file, detected by the first bytes of its content
+fn detect(header: &[u8]) -> Option<&'static FileType> {
+    if is_pe(header) {
+        return Some(&PE);
+    }
+    FILE_TYPES.iter().find(|file_type| {
+        file_type.signatures.iter().any(|(offset, signature)| {
+            header
+                .get(*offset..)
+                .map_or(false, |header| header.starts_with(signature))
+        })
+    })
+}
+
+/// checks if `header` is the beginning of a file whose format is compressed by design,
+/// like an archive, an image or a media file
+pub(crate) fn is_compressed(header: &[u8]) -> bool {
+    detect(header).map_or(false, |file_type| file_type.compressed)
+}
+
+/// the type which files with `extension` are expected to have
+fn claimed(extension: &str) -> Option<&'static FileType> {
+    if AMBIGUOUS_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+    std::iter::once(&PE)
+        .chain(FILE_TYPES.iter())
+        .find(|file_type| file_type.extensions.contains(&extension))
+}
+
+/// checks if the content of the file `name` does not match its extension. Files
+/// with unknown extensions and contents are not reported, and neither are images
+/// or media files with the extension of another image or media format, which is
+/// common and harmless
+fn mismatch(name: &str, header: &[u8]) -> Option<ExtensionFinding> {
+    let extension = Path::new(name)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    let detected = detect(header)?;
+    if detected.extensions.contains(&extension.as_str()) {
+        return None;
+    }
+    let claimed = claimed(&extension)?;
+    if claimed.category == detected.category
+        && matches!(detected.category, Category::Image | Category::Media)
+    {
+        return None;
+    }
+    Some(ExtensionFinding {
+        found_in_file: name.to_owned(),
+        detected,
+        extension,
+        claimed,
+    })
+}
+
+/// reports files whose content does not match their extension, like an executable
+/// which is named `invoice.pdf`, or a zip archive which is named `holiday.jpg`
+#[derive(Default)]
+pub struct ExtensionScanner {}
+
+impl Display for ExtensionScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ExtensionScanner")
+    }
+}
+
+impl ExtensionScanner {
+    fn check_file(&self, path: &Path) -> Result<Option<ExtensionFinding>> {
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        File::open(path)?
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut header)?;
+        Ok(mismatch(&path_to_string(path), &header))
+    }
+}
+
+impl FileScanner for ExtensionScanner {
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match self.check_file(entry.path()) {
+            Ok(None) => vec![],
+            Ok(Some(finding)) => vec![Ok(Box::new(finding) as Box<dyn ScannerFinding>)],
+            Err(why) => vec![Err(anyhow!(
+                "unable to detect the type of '{}': {}",
+                entry.path().display(),
+                why
+            ))],
+        }
+    }
+
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let header = &data[..data.len().min(HEADER_SIZE)];
+        mismatch(name, header)
+            .map(|finding| Ok(Box::new(finding) as Box<dyn ScannerFinding>))
+            .into_iter()
+            .collect()
+    }
+
+    /// the extension of the file is compared with its content
+    fn depends_on_path(&self) -> bool {
+        true
+    }
+}
+
+struct ExtensionFinding {
+    found_in_file: String,
+
+    /// the type which has been detected by the content of the file
+    detected: &'static FileType,
+
+    /// the extension of the file, in lowercase
+    extension: String,
+
+    /// the type which files with this extension are expected to have
+    claimed: &'static FileType,
+}
+
+impl ExtensionFinding {
+    fn details(&self) -> String {
+        format!(
+            "{} with the extension '.{}' of {}",
+            self.detected.name, self.extension, self.claimed.name
+        )
+    }
+}
+
+impl Display for ExtensionFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "extension mismatch: {found_in_file} ({details})")
+    }
+}
+
+impl ScannerFinding for ExtensionFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "ExtensionMismatch",
+            self.detected.name,
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "extension_mismatch",
+            "02_suspicious_file": self.found_in_file(),
+            "03_detected_type": self.detected.name,
+            "04_extension": self.extension,
+            "05_claimed_type": self.claimed.name
+        })
+    }
+
+    /// an executable with the extension of another executable format, like an ELF file
+    /// named `.dll`, is no attempt to hide an executable
+    fn severity(&self) -> Severity {
+        match self.detected.category {
+            Category::Executable if self.claimed.category == Category::Executable => {
+                Severity::Medium
+            }
+            Category::Executable => Severity::High,
+            Category::Archive | Category::Document => Severity::Medium,
+            _ => Severity::Low,
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "extension_mismatch".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        // Masquerading: Masquerade File Type
+        vec!["T1036.008".to_owned()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mismatch;
+    use crate::scanner_result::ScannerFinding;
+    use crate::severity::Severity;
+
+    #[test]
+    fn test_mismatch() {
+        let mut pe = b"MZ".to_vec();
+        pe.resize(0x3c, 0);
+        pe.extend_from_slice(&0x40u32.to_le_bytes());
+        pe.extend_from_slice(b"PE\x00\x00");
+
+        let finding = mismatch("C:\\Users\\bob\\invoice.pdf", &pe).unwrap();
+        assert_eq!(finding.detected.name, "PE executable");
+        assert_eq!(finding.extension, "pdf");
+        assert_eq!(finding.claimed.name, "PDF document");
+        assert!(mismatch("setup.exe", &pe).is_none());
+        assert!(mismatch("payload.tmp", &pe).is_none());
+
+        let zip = b"PK\x03\x04\x14\x00";
+        assert_eq!(
+            mismatch("holiday.JPG", zip).unwrap().claimed.name,
+            "JPEG image"
+        );
+        assert!(mismatch("report.docx", zip).is_none());
+
+        // images with the extension of another image format are common
+        assert!(mismatch("photo.png", b"\xff\xd8\xff\xe0").is_none());
+        assert!(mismatch("notes.txt", b"MZ is not a PE file").is_none());
+    }
+
+    #[test]
+    fn test_ambiguous_extensions() {
+        let elf = b"\x7fELF\x02\x01\x01";
+        assert!(mismatch("addon.node", elf).is_none());
+        assert!(mismatch("addon.node", b"\xcf\xfa\xed\xfe").is_none());
+        for name in ["settings.dat", "firmware.bin", "setup.log"] {
+            assert!(mismatch(name, b"PK\x03\x04\x14\x00").is_none());
+            assert!(mismatch(name, b"SQLite format 3\x00").is_none());
+            assert!(mismatch(name, elf).is_none());
+        }
+    }
+
+    #[test]
+    fn test_severity() {
+        let elf = b"\x7fELF\x02\x01\x01";
+        assert_eq!(mismatch("invoice.pdf", elf).unwrap().severity(), Severity::High);
+        assert_eq!(mismatch("library.dll", elf).unwrap().severity(), Severity::Medium);
+        assert_eq!(
+            mismatch("holiday.jpg", b"PK\x03\x04").unwrap().severity(),
+            Severity::Medium
+        );
+    }
+}
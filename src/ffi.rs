@@ -0,0 +1,288 @@
+//! C API of dionysos, see `include/dionysos.h`. All functions return 0 on success
+//! and -1 on failure; the reason of the last failure of the calling thread can be
+//! read with `dionysos_last_error`.
+
+use std::cell::RefCell;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+
+use crate::filename_scanner::FilenameScanner;
+use crate::filescanner::FileScanner;
+use crate::hash_scanner::HashScanner;
+use crate::scan_job::{ScanHandle, ScanJob};
+use crate::string_options::StringOptions;
+use crate::yara::{YaraRuleset, YaraScanner};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// called for every finding with the name of the file and the finding as JSON
+pub type DionysosFindingFn =
+    extern "C" fn(ctx: *mut c_void, file: *const c_char, finding_json: *const c_char);
+
+/// a scan job, which is shared between the thread running the scan and threads
+/// which cancel it
+pub struct DionysosJob {
+    /// `None` after the job has been started
+    job: Mutex<Option<ScanJob>>,
+    handle: ScanHandle,
+
+    /// how matched strings are rendered in the findings of this job
+    string_options: Mutex<StringOptions>,
+}
+
+fn set_last_error(why: anyhow::Error) {
+    let message = CString::new(why.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn status(result: Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(why) => {
+            set_last_error(why);
+            -1
+        }
+    }
+}
+
+/// # Safety
+/// `s` must be null or point to a nul-terminated string
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str> {
+    if s.is_null() {
+        return Err(anyhow!("unexpected null pointer"));
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+/// # Safety
+/// `job` must be null or have been returned by `dionysos_job_new`
+unsafe fn to_job<'a>(job: *const DionysosJob) -> Result<&'a DionysosJob> {
+    job.as_ref().ok_or_else(|| anyhow!("unexpected null pointer"))
+}
+
+/// replaces the job by the result of `f`, which configures it. `f` cannot fail,
+/// because the job would be lost otherwise
+fn configure<F>(job: &DionysosJob, f: F) -> Result<()>
+where
+    F: FnOnce(ScanJob) -> ScanJob,
+{
+    let mut guard = job.job.lock().unwrap();
+    let scan_job = guard
+        .take()
+        .ok_or_else(|| anyhow!("the job has already been started"))?;
+    *guard = Some(f(scan_job));
+    Ok(())
+}
+
+/// adds `scanner` to the job. Scanners are created before the job is configured,
+/// so that invalid arguments are rejected without losing the job
+fn add_scanner<S: FileScanner + 'static>(job: &DionysosJob, scanner: Result<S>) -> Result<()> {
+    let scanner = scanner?;
+    configure(job, |j| j.with_scanner(scanner))
+}
+
+/// returns the message of the last error of the calling thread, or null. The
+/// message is valid until the next call of a dionysos function in this thread
+#[no_mangle]
+pub extern "C" fn dionysos_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+/// creates a job which scans all files below `path`, or returns null
+///
+/// # Safety
+/// `path` must be a nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_new(path: *const c_char) -> *mut DionysosJob {
+    match to_str(path) {
+        Ok(path) => {
+            let job = ScanJob::new(path);
+            Box::into_raw(Box::new(DionysosJob {
+                handle: job.handle(),
+                job: Mutex::new(Some(job)),
+                string_options: Mutex::new(StringOptions::default()),
+            }))
+        }
+        Err(why) => {
+            set_last_error(why);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `job` must have been returned by `dionysos_job_new`, and `ruleset` must be a
+/// nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_add_yara(
+    job: *const DionysosJob,
+    ruleset: *const c_char,
+) -> c_int {
+    status(to_job(job).and_then(|job| {
+        let ruleset = YaraRuleset::new(to_str(ruleset)?);
+        let scanner = YaraScanner::new(&ruleset);
+        add_scanner(job, scanner.map(|s| s.with_scan_handle(job.handle.clone())))
+    }))
+}
+
+/// # Safety
+/// `job` must have been returned by `dionysos_job_new`, and `hash` must be a
+/// nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_add_hash(
+    job: *const DionysosJob,
+    hash: *const c_char,
+) -> c_int {
+    status(to_job(job).and_then(|job| {
+        let hash = to_str(hash)?.to_owned();
+        add_scanner(job, HashScanner::default().with_hashes(&[hash]))
+    }))
+}
+
+/// # Safety
+/// `job` must have been returned by `dionysos_job_new`, and `hash_file` must be a
+/// nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_add_hash_file(
+    job: *const DionysosJob,
+    hash_file: *const c_char,
+) -> c_int {
+    status(to_job(job).and_then(|job| {
+        let hash_file = to_str(hash_file)?;
+        add_scanner(job, HashScanner::default().with_hash_file(hash_file))
+    }))
+}
+
+/// # Safety
+/// `job` must have been returned by `dionysos_job_new`, and `pattern` must be a
+/// nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_add_filename(
+    job: *const DionysosJob,
+    pattern: *const c_char,
+) -> c_int {
+    status(to_job(job).and_then(|job| {
+        let pattern = to_str(pattern)?.to_owned();
+        add_scanner(job, FilenameScanner::from_patterns(&[pattern]))
+    }))
+}
+
+/// # Safety
+/// `job` must have been returned by `dionysos_job_new`
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_set_threads(
+    job: *const DionysosJob,
+    threads: usize,
+) -> c_int {
+    status(to_job(job).and_then(|job| configure(job, |j| j.with_threads(threads))))
+}
+
+/// includes the matched strings of yara rules in the findings, if `print_strings`
+/// is not 0
+///
+/// # Safety
+/// `job` must have been returned by `dionysos_job_new`
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_set_print_strings(
+    job: *const DionysosJob,
+    print_strings: c_int,
+) -> c_int {
+    status(to_job(job).map(|job| {
+        let mut options = job.string_options.lock().unwrap();
+        *options = options.clone().with_print_strings(print_strings != 0);
+    }))
+}
+
+/// runs the job and blocks until it has finished or has been cancelled. `callback`
+/// is called (from the calling thread) for every finding. A job can only be run once.
+///
+/// # Safety
+/// `job` must have been returned by `dionysos_job_new`
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_run(
+    job: *const DionysosJob,
+    callback: DionysosFindingFn,
+    ctx: *mut c_void,
+) -> c_int {
+    status(to_job(job).and_then(|job| {
+        let scan_job = job
+            .job
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow!("the job has already been started"))?;
+        let options = job.string_options.lock().unwrap().clone();
+        scan_job.run(|result| {
+            let file = CString::new(result.filename()).unwrap_or_default();
+            for finding in result.findings() {
+                let json = finding.to_json(&options).to_string();
+                let json = CString::new(json).unwrap_or_default();
+                callback(ctx, file.as_ptr(), json.as_ptr());
+            }
+        })?;
+        Ok(())
+    }))
+}
+
+/// cancels a running job. This can be called from any thread
+///
+/// # Safety
+/// `job` must have been returned by `dionysos_job_new`
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_cancel(job: *const DionysosJob) {
+    if let Some(job) = job.as_ref() {
+        job.handle.cancel();
+    }
+}
+
+/// releases a job, which must not be running anymore
+///
+/// # Safety
+/// `job` must be null or have been returned by `dionysos_job_new`, and must not be
+/// used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn dionysos_job_free(job: *mut DionysosJob) {
+    if !job.is_null() {
+        drop(Box::from_raw(job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+
+    use super::{
+        dionysos_job_add_filename, dionysos_job_add_hash, dionysos_job_free, dionysos_job_new,
+        dionysos_last_error,
+    };
+
+    #[test]
+    fn test_invalid_argument() {
+        let path = CString::new(".").unwrap();
+        let invalid_hash = CString::new("no hash").unwrap();
+        let valid_hash = CString::new("d41d8cd98f00b204e9800998ecf8427e").unwrap();
+        let invalid_pattern = CString::new("(").unwrap();
+        unsafe {
+            let job = dionysos_job_new(path.as_ptr());
+            assert!(!job.is_null());
+
+            assert_eq!(dionysos_job_add_hash(job, invalid_hash.as_ptr()), -1);
+            let error = CStr::from_ptr(dionysos_last_error()).to_str().unwrap();
+            assert!(!error.contains("already been started"), "{}", error);
+
+            // the job is still usable after an argument has been rejected
+            assert_eq!(dionysos_job_add_hash(job, valid_hash.as_ptr()), 0);
+            assert_eq!(dionysos_job_add_filename(job, invalid_pattern.as_ptr()), -1);
+            assert_eq!(dionysos_job_add_hash(job, valid_hash.as_ptr()), 0);
+            dionysos_job_free(job);
+        }
+    }
+}
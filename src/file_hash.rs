@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+/// size of the buffer which is used to read the files
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// the SHA-256 hash of the file at `path`, as hex string. The file is read in
+/// chunks, so that large files do not have to fit into memory
+pub(crate) fn sha256_of(path: &Path) -> Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut sha256 = Sha256::new();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let bytes = reader.read(&mut buffer)?;
+        if bytes == 0 {
+            break;
+        }
+        sha256.update(&buffer[..bytes]);
+    }
+    Ok(hex::encode(sha256.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::{sha256_of, READ_BUFFER_SIZE};
+
+    #[test]
+    fn test_sha256_of() {
+        let path = std::env::temp_dir().join(format!("dionysos-hash-{}", std::process::id()));
+
+        std::fs::write(&path, b"").unwrap();
+        assert_eq!(
+            sha256_of(&path).unwrap(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        // more than one chunk
+        let data = vec![b'a'; READ_BUFFER_SIZE * 2 + 1];
+        std::fs::write(&path, &data).unwrap();
+        let hash = sha256_of(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(hash, hex::encode(Sha256::digest(&data)));
+
+        assert!(sha256_of(&path).is_err());
+    }
+}
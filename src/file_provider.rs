@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use walkdir::WalkDir;
+
+use crate::path_string::{extended_length_path, path_to_string};
+
+/// a file which is passed by a [`FileProvider`] to the scanners
+pub enum ProvidedFile {
+    /// a file in the filesystem, which is scanned by all scanners
+    Entry(walkdir::DirEntry),
+
+    /// data which does not reside in the filesystem (e.g. a member of an archive or an
+    /// object in a bucket). It is scanned with [`crate::FileScanner::scan_buffer`], and
+    /// `name` is used as its filename
+    Buffer { name: String, data: Vec<u8> },
+}
+
+impl ProvidedFile {
+    /// the name which is used as filename of the results
+    pub fn name(&self) -> String {
+        match self {
+            ProvidedFile::Entry(entry) => path_to_string(entry.path()),
+            ProvidedFile::Buffer { name, .. } => name.clone(),
+        }
+    }
+}
+
+/// the source of the files which are scanned by a [`crate::ScanJob`]. By default, all
+/// files below the path of the job are scanned ([`WalkDirProvider`]). Other sources,
+/// such as disk images or cloud storage, can be scanned by implementing this trait
+/// and passing it to [`crate::ScanJob::with_provider`].
+///
+/// The files are enumerated in a separate thread, while they are being scanned.
+pub trait FileProvider: Send {
+    /// enumerates all files. Errors (e.g. directories which cannot be read) are
+    /// logged and counted, and the enumeration continues with the next item
+    fn files(&mut self) -> Box<dyn Iterator<Item = Result<ProvidedFile>> + '_>;
+}
+
+/// all files below a directory
+pub struct WalkDirProvider {
+    path: PathBuf,
+}
+
+impl WalkDirProvider {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+        }
+    }
+}
+
+impl FileProvider for WalkDirProvider {
+    fn files(&mut self) -> Box<dyn Iterator<Item = Result<ProvidedFile>> + '_> {
+        Box::new(WalkDir::new(extended_length_path(&self.path)).into_iter().filter_map(|entry| match entry {
+            Ok(entry) if entry.file_type().is_file() => Some(Ok(ProvidedFile::Entry(entry))),
+            Ok(_) => None,
+            Err(why) => Some(Err(anyhow!("unable to enumerate files: {}", why))),
+        }))
+    }
+}
+
+/// the files which are listed in a text file, one path per line. Empty lines and
+/// lines starting with `#` are ignored
+pub struct FileListProvider {
+    list: PathBuf,
+}
+
+impl FileListProvider {
+    pub fn new<P: AsRef<Path>>(list: P) -> Self {
+        Self {
+            list: list.as_ref().to_owned(),
+        }
+    }
+
+    fn entry(path: &str) -> Result<ProvidedFile> {
+        let entry = WalkDir::new(extended_length_path(Path::new(path)))
+            .max_depth(0)
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("unable to read '{}'", path))?
+            .map_err(|why| anyhow!("unable to read '{}': {}", path, why))?;
+        match entry.file_type().is_file() {
+            true => Ok(ProvidedFile::Entry(entry)),
+            false => Err(anyhow!("'{}' is no file", path)),
+        }
+    }
+}
+
+impl FileProvider for FileListProvider {
+    fn files(&mut self) -> Box<dyn Iterator<Item = Result<ProvidedFile>> + '_> {
+        let list = match File::open(&self.list) {
+            Ok(list) => BufReader::new(list),
+            Err(why) => {
+                let why = anyhow!("unable to read '{}': {}", self.list.display(), why);
+                return Box::new(std::iter::once(Err(why)));
+            }
+        };
+        Box::new(list.lines().filter_map(|line| match line {
+            Err(why) => Some(Err(why.into())),
+            Ok(line) => {
+                let line = line.trim();
+                match line.is_empty() || line.starts_with('#') {
+                    true => None,
+                    false => Some(Self::entry(line)),
+                }
+            }
+        }))
+    }
+}
+
+/// the members of a zip archive, which are scanned without extracting them. Their
+/// names are `<archive>:<member>`
+pub struct ZipArchiveProvider {
+    archive: PathBuf,
+}
+
+impl ZipArchiveProvider {
+    pub fn new<P: AsRef<Path>>(archive: P) -> Self {
+        Self {
+            archive: archive.as_ref().to_owned(),
+        }
+    }
+
+    fn open(&self) -> Result<zip::ZipArchive<File>> {
+        let archive = File::open(&self.archive)?;
+        Ok(zip::ZipArchive::new(archive)?)
+    }
+}
+
+impl FileProvider for ZipArchiveProvider {
+    fn files(&mut self) -> Box<dyn Iterator<Item = Result<ProvidedFile>> + '_> {
+        let mut zip = match self.open() {
+            Ok(zip) => zip,
+            Err(why) => {
+                let why = anyhow!("unable to open '{}': {}", self.archive.display(), why);
+                return Box::new(std::iter::once(Err(why)));
+            }
+        };
+        let archive = self.archive.display().to_string();
+        Box::new((0..zip.len()).filter_map(move |index| {
+            let mut member = match zip.by_index(index) {
+                Ok(member) if member.is_file() => member,
+                Ok(_) => return None,
+                Err(why) => return Some(Err(anyhow!("unable to read '{}': {}", archive, why))),
+            };
+            let name = format!("{}:{}", archive, member.name());
+            let mut data = Vec::new();
+            Some(match member.read_to_end(&mut data) {
+                Ok(_) => Ok(ProvidedFile::Buffer { name, data }),
+                Err(why) => Err(anyhow!("unable to read '{}': {}", name, why)),
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{FileListProvider, FileProvider, ProvidedFile};
+
+    #[test]
+    fn test_file_list() {
+        let dir = std::env::temp_dir().join(format!("dionysos-file-list-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let list = dir.join("files.txt");
+        let mut file = std::fs::File::create(&list).unwrap();
+        writeln!(file, "# files to scan\nsrc/lib.rs\n\nsrc/does-not-exist.rs\nsrc").unwrap();
+        drop(file);
+
+        let mut provider = FileListProvider::new(&list);
+        let files: Vec<_> = provider.files().collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(files.len(), 3);
+        assert!(matches!(&files[0], Ok(ProvidedFile::Entry(e)) if e.path().ends_with("lib.rs")));
+        assert!(files[1].is_err());
+        assert!(files[2].is_err());
+    }
+}
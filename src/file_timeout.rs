@@ -0,0 +1,280 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use walkdir::DirEntry;
+
+use crate::compressed_files::CompressedFiles;
+use crate::filescanner::FileScanner;
+use crate::known_good::KnownGoodFilter;
+use crate::path_string::path_to_string;
+use crate::scan_job::handle_file;
+use crate::scanner_result::ScannerResult;
+use crate::telemetry;
+use crate::worker_watchdog::WorkerActivity;
+
+/// interval in which a worker checks if the scan of its file must be aborted
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// the maximum time which all scanners together may spend on a single file, and
+/// the files whose scans have been aborted because of it
+pub(crate) struct FileTimeout {
+    timeout: Duration,
+    timed_out_files: Mutex<Vec<String>>,
+}
+
+impl FileTimeout {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            timed_out_files: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        let timed_out_files = self.timed_out_files.lock().unwrap();
+        if timed_out_files.is_empty() {
+            return Vec::new();
+        }
+        let mut lines = vec![format!(
+            "the scan of {} file(s) has been aborted after {}s:",
+            timed_out_files.len(),
+            self.timeout.as_secs()
+        )];
+        lines.extend(timed_out_files.iter().map(|f| format!("  {}", f)));
+        lines
+    }
+}
+
+/// a thread which scans the files of a worker, so that the worker can give up
+/// waiting for a file
+struct Helper {
+    files: mpsc::Sender<DirEntry>,
+    results: mpsc::Receiver<ScannerResult>,
+}
+
+/// scans the files of a worker with all scanners (see [`handle_file`]). If a
+/// [`FileTimeout`] is set, or if stuck files can be skipped by the
+/// [`crate::worker_watchdog::WorkerWatchdog`], the files are scanned in a helper thread.
+/// Threads cannot be killed, so the helper of a file which is aborted is abandoned: it
+/// exits as soon as its scan is finished, and the next file is scanned by a new one
+pub(crate) struct FileHandler {
+    scanners: Arc<Vec<Box<dyn FileScanner>>>,
+    known_good: Option<Arc<KnownGoodFilter>>,
+    errors: Arc<AtomicUsize>,
+    timeout: Option<Arc<FileTimeout>>,
+    activity: Option<Arc<WorkerActivity>>,
+    compressed_files: Option<Arc<CompressedFiles>>,
+    max_mail_size: Option<u64>,
+    helper: Option<Helper>,
+}
+
+impl FileHandler {
+    pub fn new(
+        scanners: Arc<Vec<Box<dyn FileScanner>>>,
+        known_good: Option<Arc<KnownGoodFilter>>,
+        errors: Arc<AtomicUsize>,
+    ) -> Self {
+        Self {
+            scanners,
+            known_good,
+            errors,
+            timeout: None,
+            activity: None,
+            compressed_files: None,
+            max_mail_size: None,
+            helper: None,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Arc<FileTimeout>>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// reports the file and the scanner which are running to `activity`
+    pub fn with_activity(mut self, activity: Option<Arc<WorkerActivity>>) -> Self {
+        self.activity = activity;
+        self
+    }
+
+    /// checks if compressed files can be decompressed before they are scanned
+    pub fn with_compressed_files(mut self, compressed_files: Option<Arc<CompressedFiles>>) -> Self {
+        self.compressed_files = compressed_files;
+        self
+    }
+
+    /// scans the parts of mails of at most `max_mail_size` bytes as well
+    pub fn with_max_mail_size(mut self, max_mail_size: Option<u64>) -> Self {
+        self.max_mail_size = max_mail_size;
+        self
+    }
+
+    pub fn handle_file(&mut self, entry: &DirEntry) -> ScannerResult {
+        if let Some(activity) = &self.activity {
+            activity.start_file(entry.path());
+        }
+        let result = match self.compressed_files.as_ref().map(|c| c.check(entry)) {
+            Some(Err(why)) => self.aborted(entry, why.to_string()),
+            _ => self.scan(entry),
+        };
+        if let Some(activity) = &self.activity {
+            activity.finish_file();
+        }
+        result
+    }
+
+    fn scan(&mut self, entry: &DirEntry) -> ScannerResult {
+        let skippable = self.activity.as_ref().map_or(false, |a| a.is_skippable());
+        if self.timeout.is_none() && !skippable {
+            return self.scan_directly(entry);
+        }
+
+        let helper = match self.helper.take() {
+            Some(helper) => helper,
+            None => self.spawn_helper(),
+        };
+        if helper.files.send(entry.clone()).is_err() {
+            return self.scan_directly(entry);
+        }
+        let deadline = self.timeout.as_ref().map(|t| Instant::now() + t.timeout);
+        loop {
+            match helper.results.recv_timeout(POLL_INTERVAL) {
+                Ok(result) => {
+                    self.helper = Some(helper);
+                    return result;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return self.scan_directly(entry),
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+            }
+
+            if let (Some(timeout), Some(deadline)) = (&self.timeout, deadline) {
+                if Instant::now() >= deadline {
+                    let filename = path_to_string(entry.path());
+                    log::error!(
+                        "the scan of '{}' has been aborted after {}s",
+                        filename,
+                        timeout.timeout.as_secs()
+                    );
+                    timeout.timed_out_files.lock().unwrap().push(filename);
+                    return self.aborted(
+                        entry,
+                        format!(
+                            "the scan has been aborted after {}s (--file-timeout)",
+                            timeout.timeout.as_secs()
+                        ),
+                    );
+                }
+            }
+            if self.activity.as_ref().map_or(false, |a| a.skip_requested()) {
+                return self.aborted(
+                    entry,
+                    "the scan has been skipped, because it was stuck (--skip-stuck-files)"
+                        .to_owned(),
+                );
+            }
+        }
+    }
+
+    fn scan_directly(&self, entry: &DirEntry) -> ScannerResult {
+        handle_file(
+            &self.scanners,
+            &self.known_good,
+            entry,
+            &self.errors,
+            self.activity.as_deref(),
+            self.max_mail_size,
+        )
+    }
+
+    /// the result of a file whose scan has been abandoned, or which cannot be scanned
+    fn aborted(&self, entry: &DirEntry, reason: String) -> ScannerResult {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let mut result = ScannerResult::from(entry.path());
+        result.add_error(reason);
+        result
+    }
+
+    fn spawn_helper(&self) -> Helper {
+        let (files_tx, files_rx) = mpsc::channel::<DirEntry>();
+        let (results_tx, results_rx) = mpsc::channel();
+        let scanners = Arc::clone(&self.scanners);
+        let known_good = self.known_good.as_ref().map(Arc::clone);
+        let errors = Arc::clone(&self.errors);
+        let activity = self.activity.as_ref().map(Arc::clone);
+        let max_mail_size = self.max_mail_size;
+        let trace_context = telemetry::current();
+        thread::spawn(move || {
+            let _trace = telemetry::attach(trace_context);
+            for entry in files_rx {
+                let result = handle_file(
+                    &scanners,
+                    &known_good,
+                    &entry,
+                    &errors,
+                    activity.as_deref(),
+                    max_mail_size,
+                );
+                if results_tx.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+        Helper {
+            files: files_tx,
+            results: results_rx,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Display;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use walkdir::{DirEntry, WalkDir};
+
+    use super::{FileHandler, FileTimeout};
+    use crate::filescanner::FileScanner;
+    use crate::scanner_result::ScannerFinding;
+
+    /// takes longer for `lib.rs` than the timeout
+    struct SlowScanner;
+
+    impl Display for SlowScanner {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "SlowScanner")
+        }
+    }
+
+    impl FileScanner for SlowScanner {
+        fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+            if file.file_name() == "lib.rs" {
+                std::thread::sleep(Duration::from_secs(2));
+            }
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_file_timeout() {
+        let scanners: Vec<Box<dyn FileScanner>> = vec![Box::new(SlowScanner)];
+        let errors = Arc::new(AtomicUsize::new(0));
+        let timeout = Arc::new(FileTimeout::new(Duration::from_millis(200)));
+        let mut handler = FileHandler::new(Arc::new(scanners), None, Arc::clone(&errors))
+            .with_timeout(Some(Arc::clone(&timeout)));
+
+        let entry = |path| WalkDir::new(path).into_iter().next().unwrap().unwrap();
+        let result = handler.handle_file(&entry("src/lib.rs"));
+        assert!(result.has_errors());
+        let result = handler.handle_file(&entry("src/main.rs"));
+        assert!(!result.has_errors());
+
+        assert_eq!(errors.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert_eq!(timeout.summary().len(), 2);
+    }
+}
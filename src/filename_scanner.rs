@@ -1,12 +1,15 @@
 use std::collections::HashSet;
 use std::fmt::Display;
 
+use anyhow::{anyhow, Result};
 use maplit::hashset;
 use serde_json::json;
 use walkdir::DirEntry;
 
 use crate::filescanner::*;
 use crate::csv_line::CsvLine;
+use crate::path_string::path_to_string;
+use crate::string_options::StringOptions;
 use crate::scanner_result::ScannerFinding;
 
 pub struct FilenameScanner {
@@ -19,6 +22,18 @@ impl FilenameScanner {
             patterns,
         }
     }
+
+    /// compiles `patterns` to regular expressions
+    pub(crate) fn from_patterns(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|p| {
+                regex::Regex::new(p)
+                    .map_err(|why| anyhow!("invalid filename pattern '{}': {}", p, why))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(patterns))
+    }
 }
 
 impl Display for FilenameScanner {
@@ -31,16 +46,16 @@ impl FileScanner for FilenameScanner
 {
     fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
         let file = file.path();
-        let filename = file.to_str().unwrap();
+        let filename = file.to_string_lossy();
         let mut results = Vec::new();
         for pattern in self.patterns.iter() {
-            if pattern.is_match(filename) {
+            if pattern.is_match(&filename) {
                 results.push(
                     Ok(
                         Box::new(
                             FilenameFinding{
                                 pattern: pattern.clone(),
-                                found_in_file: filename.to_string()
+                                found_in_file: path_to_string(file)
                             }
                         ) as Box<dyn ScannerFinding>
                     )
@@ -49,6 +64,24 @@ impl FileScanner for FilenameScanner
         }
         results
     }
+
+    fn scan_buffer(&self, name: &str, _data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        self.patterns
+            .iter()
+            .filter(|pattern| pattern.is_match(name))
+            .map(|pattern| {
+                Ok(Box::new(FilenameFinding {
+                    pattern: pattern.clone(),
+                    found_in_file: name.to_owned(),
+                }) as Box<dyn ScannerFinding>)
+            })
+            .collect()
+    }
+
+    /// the patterns are matched against the path of the file
+    fn depends_on_path(&self) -> bool {
+        true
+    }
 }
 
 struct FilenameFinding {
@@ -66,11 +99,11 @@ impl Display for FilenameFinding {
 
 impl ScannerFinding for FilenameFinding {
 
-    fn format_csv(&self) -> HashSet<CsvLine> {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
         let file = self.found_in_file();
         hashset![CsvLine::new("Filename", &format!("{}", self.pattern), file, String::new())]
     }
-    fn to_json(&self) -> serde_json::Value {
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
         let file = self.found_in_file();
         json!({
             "01_scanner": "filename",
@@ -79,6 +112,10 @@ impl ScannerFinding for FilenameFinding {
         })
     }
 
+    fn scanner(&self) -> String {
+        "filename".to_owned()
+    }
+
     fn found_in_file(&self) -> &str {
         &self.found_in_file[..]
     }
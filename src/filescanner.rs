@@ -2,11 +2,43 @@ use std::fmt::Display;
 use walkdir::DirEntry;
 use crate::scanner_result::*;
 
+/// a scanner, which checks every file for a certain kind of indicators.
+///
+/// Scanners are shared by all worker threads, so that `scan_file` is called
+/// concurrently for different files. The name which is displayed in the log
+/// and in the summary is taken from the `Display` implementation.
+///
+/// Custom scanners can be registered with [`crate::ScanJob::with_scanner`].
 pub trait FileScanner: Display + Sync + Send
 {
+    /// scans a single file, and returns all findings. Errors (e.g. if the file
+    /// cannot be read) are returned as well, so that other findings of the same
+    /// file are not lost. They are logged and counted by the engine.
     fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>>;
-}
 
-pub trait CloneScanner {
-    fn clone_scanner(&self) -> Self;
-}
\ No newline at end of file
+    /// scans data which does not reside in a file, such as a rendered event of the
+    /// live event log. `name` is used as the location of findings. Scanners which
+    /// only work on files do not need to implement this.
+    fn scan_buffer(&self, _name: &str, _data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        Vec::new()
+    }
+
+    /// checks if the findings depend on the path of a file (e.g. on its name, its
+    /// location or its owner), and not only on its content. With `--dedup`, files with
+    /// identical content are scanned only once, and their findings are copied to the
+    /// other files; scanners which depend on the path scan every file anyway
+    fn depends_on_path(&self) -> bool {
+        false
+    }
+
+    /// reloads the configuration of the scanner (e.g. a ruleset), if supported.
+    /// Scans which are currently running must not be affected.
+    fn reload(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    fn summary(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
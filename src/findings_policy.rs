@@ -0,0 +1,488 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, Result};
+use glob::{MatchOptions, Pattern};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::csv_line::CsvLine;
+use crate::hash_list::{CryptoHash, HashAlgorithms, HashList};
+use crate::scanner_result::{ScannerFinding, ScannerResult};
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+const PATH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Windows paths are case insensitive, and are matched with `/` as separator
+const WINDOWS_PATH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    ..PATH_OPTIONS
+};
+
+/// checks if `path` is a Windows path, like `C:\Windows` or `\\server\share`
+fn is_windows_path(path: &str) -> bool {
+    match path.as_bytes() {
+        [drive, b':', ..] if drive.is_ascii_alphabetic() => true,
+        _ => path.contains('\\'),
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    /// drops the finding
+    Suppress,
+
+    /// lowers the severity of the finding
+    Downgrade,
+
+    /// raises the severity of the finding
+    Escalate,
+}
+
+impl Action {
+    fn past_tense(&self) -> &'static str {
+        match self {
+            Action::Suppress => "suppressed",
+            Action::Downgrade => "downgraded",
+            Action::Escalate => "escalated",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PolicyFile {
+    rules: Vec<RuleDefinition>,
+}
+
+/// a rule, as it is written in the policy file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDefinition {
+    name: Option<String>,
+    action: Action,
+    to: Option<String>,
+    rule: Option<String>,
+    path: Option<String>,
+    hash: Option<String>,
+    severity: Option<String>,
+}
+
+struct PolicyRule {
+    name: String,
+    action: Action,
+
+    /// the new severity of downgraded or escalated findings, or `None` to change
+    /// their severity by one level
+    to: Option<Severity>,
+
+    rule: Option<Pattern>,
+    path: Option<Pattern>,
+    hash: Option<CryptoHash>,
+    severity: Option<Severity>,
+
+    /// the number of findings to which this rule has been applied
+    applied: AtomicUsize,
+}
+
+impl PolicyRule {
+    fn parse(index: usize, definition: RuleDefinition) -> Result<Self> {
+        let name = definition
+            .name
+            .unwrap_or_else(|| format!("rule #{}", index + 1));
+        let severity = |value: Option<String>| {
+            value
+                .map(|value| {
+                    Severity::parse(&value)
+                        .ok_or_else(|| anyhow!("{}: invalid severity '{}'", name, value))
+                })
+                .transpose()
+        };
+        let pattern = |value: Option<String>| {
+            value
+                .map(|value| {
+                    Pattern::new(&value)
+                        .map_err(|why| anyhow!("{}: invalid pattern '{}': {}", name, value, why))
+                })
+                .transpose()
+        };
+        let path = definition.path.map(|path| {
+            if is_windows_path(&path) {
+                path.replace('\\', "/")
+            } else {
+                path
+            }
+        });
+        let rule = Self {
+            action: definition.action,
+            to: severity(definition.to)?,
+            rule: pattern(definition.rule)?,
+            path: pattern(path)?,
+            hash: definition
+                .hash
+                .map(|hash| CryptoHash::parse(&hash).map_err(|why| anyhow!("{}: {}", name, why)))
+                .transpose()?,
+            severity: severity(definition.severity)?,
+            applied: AtomicUsize::new(0),
+            name,
+        };
+
+        if rule.rule.is_none()
+            && rule.path.is_none()
+            && rule.hash.is_none()
+            && rule.severity.is_none()
+        {
+            return Err(anyhow!(
+                "{}: a rule needs at least one of 'rule', 'path', 'hash' or 'severity'",
+                rule.name
+            ));
+        }
+        if rule.to.is_some() && rule.action == Action::Suppress {
+            return Err(anyhow!(
+                "{}: suppressed findings have no severity",
+                rule.name
+            ));
+        }
+        Ok(rule)
+    }
+
+    /// checks if this rule matches `finding`, whose file has the `path` and the `hashes`
+    fn matches(&self, finding: &dyn ScannerFinding, path: &str, hashes: &[CryptoHash]) -> bool {
+        let rule_matches = match (&self.rule, finding.rule()) {
+            (None, _) => true,
+            (Some(pattern), Some(rule)) => pattern.matches(rule),
+            (Some(_), None) => false,
+        };
+        let path_matches = match &self.path {
+            None => true,
+            Some(pattern) if is_windows_path(path) => {
+                pattern.matches_with(&path.replace('\\', "/"), WINDOWS_PATH_OPTIONS)
+            }
+            Some(pattern) => pattern.matches_with(path, PATH_OPTIONS),
+        };
+        rule_matches
+            && path_matches
+            && self
+                .hash
+                .as_ref()
+                .map_or(true, |hash| hashes.contains(hash))
+            && self
+                .severity
+                .map_or(true, |severity| finding.severity() == severity)
+    }
+
+    /// the severity of a finding after this rule has been applied
+    fn new_severity(&self, severity: Severity) -> Severity {
+        match (self.action, self.to) {
+            (Action::Downgrade, Some(to)) => severity.min(to),
+            (Action::Escalate, Some(to)) => severity.max(to),
+            (Action::Downgrade, None) => match severity {
+                Severity::Critical => Severity::High,
+                Severity::High => Severity::Medium,
+                _ => Severity::Low,
+            },
+            (Action::Escalate, None) => match severity {
+                Severity::Low => Severity::Medium,
+                Severity::Medium => Severity::High,
+                _ => Severity::Critical,
+            },
+            (Action::Suppress, _) => severity,
+        }
+    }
+}
+
+/// rules which suppress, downgrade or escalate findings (`--findings-policy`), so that
+/// known false positives of an environment can be silenced without changing the rulesets.
+/// The policy is applied by the writer, before findings are counted and written; the
+/// first rule which matches a finding is applied to it
+pub(crate) struct FindingsPolicy {
+    rules: Vec<PolicyRule>,
+
+    /// all hashes of the rules, so that files are only hashed if necessary
+    hashes: HashList,
+}
+
+impl FindingsPolicy {
+    /// reads a policy file in YAML, like
+    ///
+    /// ```yaml
+    /// rules:
+    ///   - name: backup software
+    ///     action: suppress
+    ///     rule: "Mimikatz_*"
+    ///     path: "/opt/backup/**"
+    ///   - action: downgrade
+    ///     to: low
+    ///     hash: 44d88612fea8a8f36de82e1278abb02f
+    /// ```
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let policy: PolicyFile = serde_yaml::from_str(content)?;
+        let rules = policy
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(index, definition)| PolicyRule::parse(index, definition))
+            .collect::<Result<Vec<_>>>()?;
+        let mut hashes = HashList::default();
+        for hash in rules.iter().filter_map(|rule| rule.hash.as_ref()) {
+            hashes.insert(hash.clone());
+        }
+        Ok(Self { rules, hashes })
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// the hash algorithms which are needed to match the hashes of the rules
+    pub fn hash_algorithms(&self) -> HashAlgorithms {
+        self.hashes.algorithms()
+    }
+
+    /// applies the policy to all findings of `result`
+    pub fn apply(&self, result: &mut ScannerResult) {
+        if !result.has_findings() {
+            return;
+        }
+        let hashes = match self.rules.iter().any(|rule| rule.hash.is_some()) {
+            false => Vec::new(),
+            true => self
+                .hashes
+                .matching_hashes_of_result(result)
+                .unwrap_or_else(|why| {
+                    log::debug!("unable to hash '{}': {}", result.filename(), why);
+                    Vec::new()
+                }),
+        };
+        // the path is matched unescaped, because rules are written for the real paths
+        let path = match result.path() {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None => result.filename().to_owned(),
+        };
+
+        for finding in result.take_findings() {
+            let rule = match self
+                .rules
+                .iter()
+                .find(|rule| rule.matches(finding.as_ref(), &path, &hashes))
+            {
+                Some(rule) => rule,
+                None => {
+                    result.add_finding(finding);
+                    continue;
+                }
+            };
+            rule.applied.fetch_add(1, Ordering::Relaxed);
+            log::trace!(
+                "'{}' has {} a finding in '{}'",
+                rule.name,
+                rule.action.past_tense(),
+                finding.found_in_file()
+            );
+            if rule.action != Action::Suppress {
+                let severity = rule.new_severity(finding.severity());
+                result.add_finding(Box::new(PolicyFinding {
+                    rule: rule.name.clone(),
+                    action: rule.action,
+                    severity,
+                    finding,
+                }));
+            }
+        }
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .filter_map(|rule| match rule.applied.load(Ordering::Relaxed) {
+                0 => None,
+                count => Some(format!(
+                    "the policy rule '{}' has {} {} finding(s)",
+                    rule.name,
+                    rule.action.past_tense(),
+                    count
+                )),
+            })
+            .collect()
+    }
+}
+
+/// a finding whose severity has been changed by the findings policy
+struct PolicyFinding {
+    finding: Box<dyn ScannerFinding>,
+    rule: String,
+    action: Action,
+    severity: Severity,
+}
+
+impl PolicyFinding {
+    fn describe(&self) -> String {
+        format!(
+            "{} from {} to {} by '{}'",
+            self.action.past_tense(),
+            self.finding.severity(),
+            self.severity,
+            self.rule
+        )
+    }
+}
+
+impl Display for PolicyFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.finding)
+    }
+}
+
+impl ScannerFinding for PolicyFinding {
+    fn format_readable(&self, options: &StringOptions) -> String {
+        format!(
+            "{}\n  policy: {}\n",
+            self.finding.format_readable(options).trim_end(),
+            self.describe()
+        )
+    }
+
+    fn format_csv(&self, options: &StringOptions) -> HashSet<CsvLine> {
+        let annotation = format!("policy: {}", self.describe());
+        self.finding
+            .format_csv(options)
+            .iter()
+            .map(|line| line.with_annotation(&annotation))
+            .collect()
+    }
+
+    fn to_json(&self, options: &StringOptions) -> Value {
+        let mut json = self.finding.to_json(options);
+        if let Value::Object(values) = &mut json {
+            values.insert(
+                "93_policy".to_owned(),
+                json!({
+                    "rule": self.rule,
+                    "action": self.action.past_tense(),
+                    "original_severity": self.finding.severity().to_string(),
+                    "severity": self.severity.to_string()
+                }),
+            );
+        }
+        json
+    }
+
+    fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn scanner(&self) -> String {
+        self.finding.scanner()
+    }
+
+    fn found_in_file(&self) -> &str {
+        self.finding.found_in_file()
+    }
+
+    fn rule(&self) -> Option<&str> {
+        self.finding.rule()
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        self.finding.attack_techniques()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scanner_result::ScannerResult;
+    use crate::severity::Severity;
+    use crate::ScanJob;
+
+    use super::FindingsPolicy;
+
+    /// a result with a finding of the filename scanner
+    fn result(name: &str) -> ScannerResult {
+        ScanJob::new("unused")
+            .with_filenames(&[r"(?i)\.exe$".to_owned()])
+            .unwrap()
+            .scan_buffer(name, b"")
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = FindingsPolicy::parse(
+            "rules:\n\
+             - name: backups\n  action: suppress\n  rule: \"Mimikatz_*\"\n  path: \"/opt/backup/**\"\n\
+             - action: downgrade\n  severity: critical\n\
+             - action: escalate\n  to: critical\n  rule: Webshell\n",
+        )
+        .unwrap();
+        assert_eq!(policy.rule_count(), 3);
+        assert_eq!(policy.rules[0].name, "backups");
+        assert_eq!(policy.rules[1].name, "rule #2");
+        assert_eq!(
+            policy.rules[1].new_severity(Severity::Critical),
+            Severity::High
+        );
+        assert_eq!(
+            policy.rules[2].new_severity(Severity::Low),
+            Severity::Critical
+        );
+
+        assert!(FindingsPolicy::parse("rules:\n- action: suppress\n").is_err());
+        assert!(FindingsPolicy::parse("rules:\n- action: ignore\n  rule: x\n").is_err());
+        assert!(
+            FindingsPolicy::parse("rules:\n- action: suppress\n  to: low\n  rule: x\n").is_err()
+        );
+    }
+
+    #[test]
+    fn test_windows_paths() {
+        let policy = FindingsPolicy::parse(
+            "rules:\n- action: suppress\n  path: 'C:\\Users\\*\\AppData\\**'\n",
+        )
+        .unwrap();
+        for (name, suppressed) in [
+            (r"c:\users\bob\appdata\local\temp\EVIL.EXE", true),
+            ("C:/Users/bob/AppData/evil.exe", true),
+            (r"D:\Users\bob\AppData\evil.exe", false),
+            (r"C:\Users\AppData\evil.exe", false),
+        ] {
+            let mut result = result(name);
+            assert!(result.has_findings());
+            policy.apply(&mut result);
+            assert_eq!(!result.has_findings(), suppressed, "{}", name);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_escaped_path() {
+        let dir = std::env::temp_dir().join(format!("dionysos-policy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a\nb.exe");
+        std::fs::write(&path, b"MZ").unwrap();
+
+        // the rule matches the real path, not the escaped filename of the output
+        let policy = FindingsPolicy::parse(&format!(
+            "rules:\n- action: suppress\n  path: '{}/a?b.exe'\n",
+            dir.display()
+        ))
+        .unwrap();
+        let finding = result("a.exe").take_findings().remove(0);
+        let mut scanned = ScannerResult::from(path.as_path());
+        scanned.add_finding(finding);
+        policy.apply(&mut scanned);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(!scanned.has_findings());
+    }
+}
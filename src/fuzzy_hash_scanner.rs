@@ -0,0 +1,301 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::filescanner::FileScanner;
+use crate::path_string::path_to_string;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+/// the default minimum similarity score (between 0 and 100) of a finding
+pub(crate) const DEFAULT_THRESHOLD: u32 = 70;
+
+/// findings with at least this score are almost identical to the known file
+const HIGH_SCORE: u32 = 90;
+
+/// checks if `value` looks like an ssdeep hash, like `96:s4Ud1Lj96tHHlZDrwciQmA:...`,
+/// and removes the quotes which ssdeep writes around it
+pub(crate) fn parse_ssdeep(value: &str) -> Result<String> {
+    let hash = value.trim().trim_matches('"');
+    let mut parts = hash.splitn(3, ':');
+    match (
+        parts.next().map(str::parse::<u64>),
+        parts.next(),
+        parts.next(),
+    ) {
+        (Some(Ok(_)), Some(_), Some(_)) => Ok(hash.to_owned()),
+        _ => Err(anyhow!(
+            "invalid ssdeep hash '{}', expected BLOCKSIZE:HASH:HASH",
+            value
+        )),
+    }
+}
+
+/// an ssdeep hash of a known file
+struct KnownHash {
+    hash: String,
+
+    /// the name of the known file, or of the list which contained the hash
+    source: Option<String>,
+}
+
+/// reports files which are similar to known files, by comparing their ssdeep hashes.
+/// Unlike cryptographic hashes, fuzzy hashes also find variants of known malware,
+/// which differ only in a few bytes
+pub struct FuzzyHashScanner {
+    hashes: Vec<KnownHash>,
+
+    /// the minimum similarity score of a finding
+    threshold: u32,
+}
+
+impl Default for FuzzyHashScanner {
+    fn default() -> Self {
+        Self {
+            hashes: Vec::new(),
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl FuzzyHashScanner {
+    pub fn with_hashes(mut self, hashes: &[String]) -> Result<Self> {
+        for hash in hashes.iter() {
+            self.hashes.push(KnownHash {
+                hash: parse_ssdeep(hash)?,
+                source: None,
+            });
+        }
+        Ok(self)
+    }
+
+    /// reads hashes from a file, see [`FuzzyHashScanner::parse_hash_file`]
+    pub fn with_hash_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|why| anyhow!("unable to read '{}': {}", path.display(), why))?;
+        let list_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+        for (hash, name) in Self::parse_hash_file(&content)
+            .map_err(|why| anyhow!("invalid ssdeep list '{}': {}", path.display(), why))?
+        {
+            self.hashes.push(KnownHash {
+                hash,
+                source: name.or_else(|| list_name.clone()),
+            });
+        }
+        Ok(self)
+    }
+
+    /// only report files whose similarity score is at least `threshold`
+    pub fn with_threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// parses a list of ssdeep hashes, with one hash per line, or the output of
+    /// `ssdeep`, which contains the hash and the name of the file. Empty lines, lines
+    /// starting with '#' and the header of `ssdeep` are ignored
+    pub(crate) fn parse_hash_file(content: &str) -> Result<Vec<(String, Option<String>)>> {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter(|line| !line.starts_with("ssdeep,"))
+            .map(|line| match line.split_once(',') {
+                Some((hash, name)) => {
+                    let name = name.trim().trim_matches('"');
+                    Ok((parse_ssdeep(hash)?, Some(name.to_owned())))
+                }
+                None => Ok((parse_ssdeep(line)?, None)),
+            })
+            .collect()
+    }
+
+    /// compares the ssdeep hash of a scanned file with all known hashes. Hashes which
+    /// cannot be compared (e.g. because they are malformed) are not similar
+    fn findings(&self, name: String, file_hash: String) -> Vec<Result<Box<dyn ScannerFinding>>> {
+        self.hashes
+            .iter()
+            .filter_map(|known| {
+                let score = ssdeep::compare(&file_hash, &known.hash).unwrap_or(0);
+                (u32::from(score) >= self.threshold).then(|| {
+                    Ok(Box::new(FuzzyHashFinding {
+                        found_in_file: name.clone(),
+                        hash: known.hash.clone(),
+                        file_hash: file_hash.clone(),
+                        score,
+                        source: known.source.clone(),
+                    }) as Box<dyn ScannerFinding>)
+                })
+            })
+            .collect()
+    }
+}
+
+impl Display for FuzzyHashScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "FuzzyHashScanner")
+    }
+}
+
+impl FileScanner for FuzzyHashScanner {
+    /// the file is read by libfuzzy in chunks, so that it is never held in memory
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match ssdeep::hash_from_file(entry.path()) {
+            Ok(hash) => self.findings(path_to_string(entry.path()), hash),
+            Err(why) => vec![Err(anyhow!(
+                "unable to compute the ssdeep hash of '{}': {}",
+                entry.path().display(),
+                why
+            ))],
+        }
+    }
+
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match ssdeep::hash(data) {
+            Ok(hash) => self.findings(name.to_owned(), hash),
+            Err(why) => vec![Err(anyhow!(
+                "unable to compute the ssdeep hash of '{}': {}",
+                name,
+                why
+            ))],
+        }
+    }
+}
+
+struct FuzzyHashFinding {
+    found_in_file: String,
+
+    /// the known hash
+    hash: String,
+
+    /// the hash of the scanned file
+    file_hash: String,
+
+    /// the similarity score, between 0 and 100
+    score: u8,
+
+    /// the name of the known file, or of the list which contained the hash
+    source: Option<String>,
+}
+
+impl FuzzyHashFinding {
+    fn details(&self) -> String {
+        match &self.source {
+            Some(source) => format!("{}% similar to {}", self.score, source),
+            None => format!("{}% similar", self.score),
+        }
+    }
+}
+
+impl Display for FuzzyHashFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let hash = &self.hash;
+        let details = self.details();
+        writeln!(
+            f,
+            "file {found_in_file} is similar to the ssdeep hash {hash} ({details})"
+        )
+    }
+}
+
+impl ScannerFinding for FuzzyHashFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "FuzzyHash",
+            &self.hash,
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "fuzzy_hash",
+            "02_suspicious_file": self.found_in_file(),
+            "03_hash": self.hash,
+            "04_file_hash": self.file_hash,
+            "05_score": self.score,
+            "06_source": self.source
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        if u32::from(self.score) >= HIGH_SCORE {
+            Severity::High
+        } else {
+            Severity::Medium
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "fuzzy_hash".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ssdeep, FuzzyHashScanner};
+    use crate::filescanner::FileScanner;
+
+    #[test]
+    fn test_parse_hash_file() {
+        let hashes = FuzzyHashScanner::parse_hash_file(
+            "ssdeep,1.1--blocksize:hash:hash,filename\n\
+             96:s4Ud1Lj96tHHlZDrwciQmA5kBR:s4Ud1Lj96tHHlAkBR,\"/samples/dropper.exe\"\n\
+             # a comment\n\
+             \n\
+             3:hMCEpFZBn:huN\n",
+        )
+        .unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(
+            hashes[0].0,
+            "96:s4Ud1Lj96tHHlZDrwciQmA5kBR:s4Ud1Lj96tHHlAkBR"
+        );
+        assert_eq!(hashes[0].1.as_deref(), Some("/samples/dropper.exe"));
+        assert_eq!(hashes[1], ("3:hMCEpFZBn:huN".to_owned(), None));
+
+        assert_eq!(parse_ssdeep("\"3:abc:def\"").unwrap(), "3:abc:def");
+        assert!(parse_ssdeep("abc:def").is_err());
+        assert!(parse_ssdeep("3:abc").is_err());
+        assert!(FuzzyHashScanner::parse_hash_file("d41d8cd98f00b204e9800998ecf8427e").is_err());
+    }
+
+    #[test]
+    fn test_similar_files() {
+        // pseudo-random data, so that ssdeep finds enough context to split it
+        let mut state = 0x1234_5678u32;
+        let sample: Vec<u8> = (0..16 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let mut variant = sample.clone();
+        variant[8000..8016].copy_from_slice(b"another C2 host!");
+        let other: Vec<u8> = sample.iter().rev().copied().collect();
+
+        let scanner = FuzzyHashScanner::default()
+            .with_hashes(&[ssdeep::hash(&sample).unwrap()])
+            .unwrap();
+        let findings = scanner.scan_buffer("variant.exe", &variant);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].as_ref().unwrap().found_in_file(), "variant.exe");
+        assert!(scanner.scan_buffer("other.exe", &other).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use std::net::TcpListener;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+
+use crate::scan_service::{self, Job, JobRegistry, JobStatus};
+
+pub(crate) mod proto {
+    tonic::include_proto!("dionysos");
+}
+
+use proto::scanner_server::{Scanner, ScannerServer};
+use proto::{Finding, JobId, JobState, Progress, ScanRequest};
+
+impl From<ScanRequest> for scan_service::ScanRequest {
+    fn from(request: ScanRequest) -> Self {
+        Self {
+            path: request.path,
+            yara: request.yara,
+            hashes: request.hashes,
+            hash_lists: request.hash_lists,
+            filenames: request.filenames,
+            known_good: request.known_good,
+            threads: request.threads as usize,
+            print_strings: request.print_strings,
+            max_strings: request.max_strings.map(|n| n as usize),
+            max_string_length: request.max_string_length.map(|n| n as usize),
+        }
+    }
+}
+
+impl From<&JobStatus> for Progress {
+    fn from(status: &JobStatus) -> Self {
+        let state = match status.state() {
+            scan_service::JobState::Running => JobState::Running,
+            scan_service::JobState::Finished => JobState::Finished,
+            scan_service::JobState::Cancelled => JobState::Cancelled,
+            scan_service::JobState::Failed => JobState::Failed,
+        };
+        Self {
+            state: state as i32,
+            files: status.files(),
+            findings: status.findings(),
+            errors: status.errors(),
+            error: status.error().map(|e| e.to_owned()),
+        }
+    }
+}
+
+struct ScanService {
+    jobs: Arc<JobRegistry>,
+}
+
+impl ScanService {
+    fn job(&self, id: &JobId) -> Result<Arc<Job>, Status> {
+        self.jobs
+            .job(id.id)
+            .ok_or_else(|| Status::not_found(format!("there is no job with id {}", id.id)))
+    }
+}
+
+#[tonic::async_trait]
+impl Scanner for ScanService {
+    type StreamFindingsStream = ReceiverStream<Result<Finding, Status>>;
+
+    async fn start_scan(&self, request: Request<ScanRequest>) -> Result<Response<JobId>, Status> {
+        let request = scan_service::ScanRequest::from(request.into_inner());
+        let jobs = Arc::clone(&self.jobs);
+        // compiling the yara ruleset may take a while
+        let id = tokio::task::spawn_blocking(move || jobs.start(request))
+            .await
+            .map_err(|why| Status::internal(why.to_string()))?
+            .map_err(|why| Status::invalid_argument(why.to_string()))?;
+        Ok(Response::new(JobId { id }))
+    }
+
+    async fn stream_findings(
+        &self,
+        request: Request<JobId>,
+    ) -> Result<Response<Self::StreamFindingsStream>, Status> {
+        let job = self.job(request.get_ref())?;
+        let (tx, rx) = mpsc::channel(64);
+        tokio::task::spawn_blocking(move || {
+            let options = job.string_options().clone();
+            let mut next = 0;
+            loop {
+                let status = job.wait_for_results(next);
+                let findings: Vec<Finding> = status.results()[next..]
+                    .iter()
+                    .flat_map(|result| {
+                        result.findings().map(|finding| Finding {
+                            file: result.filename().to_owned(),
+                            json: finding.to_json(&options).to_string(),
+                        })
+                    })
+                    .collect();
+                next = status.results().len();
+                let running = status.state() == scan_service::JobState::Running;
+                drop(status);
+
+                for finding in findings {
+                    if tx.blocking_send(Ok(finding)).is_err() {
+                        return;
+                    }
+                }
+                if !running {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_progress(&self, request: Request<JobId>) -> Result<Response<Progress>, Status> {
+        let job = self.job(request.get_ref())?;
+        let progress = Progress::from(&*job.status());
+        Ok(Response::new(progress))
+    }
+
+    async fn cancel_scan(&self, request: Request<JobId>) -> Result<Response<Progress>, Status> {
+        let job = self.job(request.get_ref())?;
+        log::info!("cancelling scan job {}", request.get_ref().id);
+        job.cancel();
+        let progress = Progress::from(&*job.status());
+        Ok(Response::new(progress))
+    }
+}
+
+/// runs the gRPC server until it is interrupted with Ctrl-C
+pub fn serve(listener: TcpListener, jobs: Arc<JobRegistry>) -> Result<()> {
+    log::info!("listening for gRPC requests on {}", listener.local_addr()?);
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        tonic::transport::Server::builder()
+            .add_service(ScannerServer::new(ScanService { jobs }))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await?;
+        Ok::<(), anyhow::Error>(())
+    })
+}
@@ -0,0 +1,643 @@
+use anyhow::{anyhow, Result};
+use md5::{Digest, Md5};
+use memmap::MmapOptions;
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+use std::convert::TryInto;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::fs::File;
+
+use crate::pe_file::PeFile;
+use crate::scanner_result::ScannerResult;
+
+const MD5_SIZE: usize = 128 / 8;
+const SHA1_SIZE: usize = 160 / 8;
+const SHA256_SIZE: usize = 256 / 8;
+const SHA384_SIZE: usize = 384 / 8;
+const SHA512_SIZE: usize = 512 / 8;
+const BLAKE3_SIZE: usize = 256 / 8;
+const IMPHASH_SIZE: usize = 128 / 8;
+const AUTHENTICODE_SIZE: usize = 256 / 8;
+
+#[derive(Eq, Clone)]
+pub enum CryptoHash {
+    MD5([u8; MD5_SIZE]),
+    SHA1([u8; SHA1_SIZE]),
+    SHA256([u8; SHA256_SIZE]),
+    SHA384([u8; SHA384_SIZE]),
+    SHA512([u8; SHA512_SIZE]),
+    BLAKE3([u8; BLAKE3_SIZE]),
+
+    /// import hash of a PE file
+    IMPHASH([u8; IMPHASH_SIZE]),
+
+    /// SHA256 Authenticode hash of a PE file
+    AUTHENTICODE([u8; AUTHENTICODE_SIZE]),
+}
+
+impl PartialEq for CryptoHash {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::MD5(l0), Self::MD5(r0)) => l0 == r0,
+            (Self::SHA1(l0), Self::SHA1(r0)) => l0 == r0,
+            (Self::SHA256(l0), Self::SHA256(r0)) => l0 == r0,
+            (Self::SHA384(l0), Self::SHA384(r0)) => l0 == r0,
+            (Self::SHA512(l0), Self::SHA512(r0)) => l0 == r0,
+            (Self::BLAKE3(l0), Self::BLAKE3(r0)) => l0 == r0,
+            (Self::IMPHASH(l0), Self::IMPHASH(r0)) => l0 == r0,
+            (Self::AUTHENTICODE(l0), Self::AUTHENTICODE(r0)) => l0 == r0,
+            (_, _) => false,
+        }
+    }
+}
+
+impl Hash for CryptoHash {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match self {
+            CryptoHash::MD5(h) => h.hash(state),
+            CryptoHash::SHA1(h) => h.hash(state),
+            CryptoHash::SHA256(h) => h.hash(state),
+            CryptoHash::SHA384(h) => h.hash(state),
+            CryptoHash::SHA512(h) => h.hash(state),
+            CryptoHash::BLAKE3(h) => h.hash(state),
+            CryptoHash::IMPHASH(h) => h.hash(state),
+            CryptoHash::AUTHENTICODE(h) => h.hash(state),
+        }
+    }
+}
+
+impl Display for CryptoHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CryptoHash::MD5(h) => write!(f, "MD5:{}", hex::encode(h)),
+            CryptoHash::SHA1(h) => write!(f, "SHA1:{}", hex::encode(h)),
+            CryptoHash::SHA256(h) => write!(f, "SHA256:{}", hex::encode(h)),
+            CryptoHash::SHA384(h) => write!(f, "SHA384:{}", hex::encode(h)),
+            CryptoHash::SHA512(h) => write!(f, "SHA512:{}", hex::encode(h)),
+            CryptoHash::BLAKE3(h) => write!(f, "BLAKE3:{}", hex::encode(h)),
+            CryptoHash::IMPHASH(h) => write!(f, "IMPHASH:{}", hex::encode(h)),
+            CryptoHash::AUTHENTICODE(h) => write!(f, "AUTHENTICODE:{}", hex::encode(h)),
+        }
+    }
+}
+
+impl CryptoHash {
+    /// parses a hex encoded hash. The algorithm is detected by the length of the
+    /// hash, or by an optional prefix like `sha256:` (which is the same format as used
+    /// by [`Display`]). BLAKE3 hashes, import hashes and Authenticode hashes have the same
+    /// length as SHA256 and MD5 hashes, so they always need the `blake3:`, `imphash:` or
+    /// `authenticode:` prefix.
+    pub fn parse(hash: &str) -> Result<Self> {
+        let (prefix, value) = match hash.split_once(':') {
+            Some((prefix, value)) => (Some(prefix.to_lowercase()), value),
+            None => (None, hash),
+        };
+        let bytes = hex::decode(value)?;
+        match (prefix.as_deref(), bytes.len()) {
+            (None | Some("md5"), MD5_SIZE) => Ok(CryptoHash::MD5(bytes.try_into().unwrap())),
+            (None | Some("sha1"), SHA1_SIZE) => Ok(CryptoHash::SHA1(bytes.try_into().unwrap())),
+            (None | Some("sha256"), SHA256_SIZE) => Ok(CryptoHash::SHA256(bytes.try_into().unwrap())),
+            (None | Some("sha384"), SHA384_SIZE) => Ok(CryptoHash::SHA384(bytes.try_into().unwrap())),
+            (None | Some("sha512"), SHA512_SIZE) => Ok(CryptoHash::SHA512(bytes.try_into().unwrap())),
+            (Some("blake3"), BLAKE3_SIZE) => Ok(CryptoHash::BLAKE3(bytes.try_into().unwrap())),
+            (Some("imphash"), IMPHASH_SIZE) => Ok(CryptoHash::IMPHASH(bytes.try_into().unwrap())),
+            (Some("authenticode" | "authentihash"), AUTHENTICODE_SIZE) => {
+                Ok(CryptoHash::AUTHENTICODE(bytes.try_into().unwrap()))
+            }
+            (Some(prefix), _) => Err(anyhow!("invalid hash type or length of '{}' ({})", hash, prefix)),
+            (None, _) => Err(anyhow!("invalid hash length of '{}'", hash)),
+        }
+    }
+
+    /// parses an import hash, which may be given without the `imphash:` prefix,
+    /// because reports usually list import hashes in a separate column
+    pub fn parse_imphash(hash: &str) -> Result<Self> {
+        let hash = hash.trim();
+        match hash.split_once(':') {
+            Some(_) => match Self::parse(hash)? {
+                imphash @ CryptoHash::IMPHASH(_) => Ok(imphash),
+                _ => Err(anyhow!("'{}' is not an import hash", hash)),
+            },
+            None => Self::parse(&format!("imphash:{}", hash)),
+        }
+    }
+}
+
+/// marks a hash which has not been read from a file
+const NO_SOURCE: u32 = u32::MAX;
+
+/// a sorted array of hashes of the same type. This needs much less memory than
+/// a hash set (there is no per-entry overhead besides the index of the source),
+/// and can be searched using a binary search.
+#[derive(Default)]
+struct SortedHashes<const N: usize> {
+    entries: Vec<([u8; N], u32)>,
+}
+
+impl<const N: usize> SortedHashes<N> {
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn search(&self, hash: &[u8; N]) -> std::result::Result<usize, usize> {
+        self.entries.binary_search_by(|(entry, _)| entry.cmp(hash))
+    }
+
+    /// inserts a single hash, keeping the array sorted
+    fn insert(&mut self, hash: [u8; N], source: u32) {
+        if let Err(index) = self.search(&hash) {
+            self.entries.insert(index, (hash, source));
+        }
+    }
+
+    /// appends a hash without keeping the array sorted. [`SortedHashes::sort`] must
+    /// be called after all hashes have been appended
+    fn push(&mut self, hash: [u8; N], source: u32) {
+        self.entries.push((hash, source));
+    }
+
+    /// sorts the array and removes duplicates. If a hash occurs more than once,
+    /// the source of its first occurrence is kept
+    fn sort(&mut self) {
+        self.entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.entries.dedup_by(|(a, _), (b, _)| a == b);
+        self.entries.shrink_to_fit();
+    }
+
+    fn source_of(&self, hash: &[u8; N]) -> Option<u32> {
+        self.search(hash).ok().map(|index| self.entries[index].1)
+    }
+}
+
+/// the hash algorithms which are needed to check a file against one or more
+/// hash sets. The scan workers compute the hashes of a file only once, and the
+/// hash sets look them up afterwards
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HashAlgorithms {
+    md5: bool,
+    sha1: bool,
+    sha256: bool,
+    sha384: bool,
+    sha512: bool,
+    blake3: bool,
+    imphash: bool,
+    authenticode: bool,
+}
+
+impl HashAlgorithms {
+    /// the algorithms which are needed by `self` or by `other`
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            md5: self.md5 || other.md5,
+            sha1: self.sha1 || other.sha1,
+            sha256: self.sha256 || other.sha256,
+            sha384: self.sha384 || other.sha384,
+            sha512: self.sha512 || other.sha512,
+            blake3: self.blake3 || other.blake3,
+            imphash: self.imphash || other.imphash,
+            authenticode: self.authenticode || other.authenticode,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// computes the hashes of `slice` with all selected algorithms
+    pub fn compute_hashes<S: AsRef<[u8]>>(&self, slice: S) -> Vec<CryptoHash> {
+        let mut hashes = Vec::new();
+
+        if self.md5 {
+            let mut hasher = Md5::new();
+            hasher.update(&slice);
+            let result = hasher.finalize();
+            let crypto_hash = CryptoHash::MD5(result.try_into().unwrap());
+            hashes.push(crypto_hash);
+        }
+
+        if self.sha1 {
+            let mut hasher = Sha1::new();
+            hasher.update(&slice);
+            let result = hasher.finalize();
+            let crypto_hash = CryptoHash::SHA1(result.try_into().unwrap());
+            hashes.push(crypto_hash);
+        }
+
+        if self.sha256 {
+            let mut hasher = Sha256::new();
+            hasher.update(&slice);
+            let result = hasher.finalize();
+            let crypto_hash = CryptoHash::SHA256(result.try_into().unwrap());
+            hashes.push(crypto_hash);
+        }
+
+        if self.sha384 {
+            let mut hasher = Sha384::new();
+            hasher.update(&slice);
+            let result = hasher.finalize();
+            let crypto_hash = CryptoHash::SHA384(result.try_into().unwrap());
+            hashes.push(crypto_hash);
+        }
+
+        if self.sha512 {
+            let mut hasher = Sha512::new();
+            hasher.update(&slice);
+            let result = hasher.finalize();
+            let crypto_hash = CryptoHash::SHA512(result.try_into().unwrap());
+            hashes.push(crypto_hash);
+        }
+
+        if self.blake3 {
+            let result = blake3::hash(slice.as_ref());
+            let crypto_hash = CryptoHash::BLAKE3(*result.as_bytes());
+            hashes.push(crypto_hash);
+        }
+
+        if self.imphash || self.authenticode {
+            match PeFile::parse(slice.as_ref()) {
+                Ok(Some(pe)) => {
+                    if self.imphash {
+                        if let Some(imphash) = pe.imphash() {
+                            hashes.push(CryptoHash::IMPHASH(imphash));
+                        }
+                    }
+                    if self.authenticode {
+                        if let Some(authenticode) = pe.authenticode_hash() {
+                            hashes.push(CryptoHash::AUTHENTICODE(authenticode));
+                        }
+                    }
+                }
+                Ok(None) => (),
+                Err(why) => log::debug!("unable to parse PE file: {}", why),
+            }
+        }
+
+        hashes
+    }
+
+    /// computes the hashes of the file at `path` with all selected algorithms
+    pub fn hashes_of_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<CryptoHash>> {
+        const EMPTY_SLICE: [u8; 0] = [];
+
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Ok(self.compute_hashes(EMPTY_SLICE));
+        }
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+        Ok(self.compute_hashes(&mmap[..]))
+    }
+}
+
+/// a set of hashes of different types, which knows which hash algorithms
+/// must be used to check if a file is contained in the set. Every hash
+/// which has been read from a file remembers the name of that file.
+///
+/// Hashes are stored in sorted arrays, so that large hash sets like the full
+/// NSRL RDS can be loaded without exhausting the memory.
+#[derive(Default)]
+pub struct HashList {
+    md5: SortedHashes<MD5_SIZE>,
+    sha1: SortedHashes<SHA1_SIZE>,
+    sha256: SortedHashes<SHA256_SIZE>,
+    sha384: SortedHashes<SHA384_SIZE>,
+    sha512: SortedHashes<SHA512_SIZE>,
+    blake3: SortedHashes<BLAKE3_SIZE>,
+    imphash: SortedHashes<IMPHASH_SIZE>,
+    authenticode: SortedHashes<AUTHENTICODE_SIZE>,
+
+    /// names of the files the hashes have been read from
+    sources: Vec<String>,
+}
+
+impl HashList {
+    pub fn insert(&mut self, crypto_hash: CryptoHash) {
+        match crypto_hash {
+            CryptoHash::MD5(h) => self.md5.insert(h, NO_SOURCE),
+            CryptoHash::SHA1(h) => self.sha1.insert(h, NO_SOURCE),
+            CryptoHash::SHA256(h) => self.sha256.insert(h, NO_SOURCE),
+            CryptoHash::SHA384(h) => self.sha384.insert(h, NO_SOURCE),
+            CryptoHash::SHA512(h) => self.sha512.insert(h, NO_SOURCE),
+            CryptoHash::BLAKE3(h) => self.blake3.insert(h, NO_SOURCE),
+            CryptoHash::IMPHASH(h) => self.imphash.insert(h, NO_SOURCE),
+            CryptoHash::AUTHENTICODE(h) => self.authenticode.insert(h, NO_SOURCE),
+        }
+    }
+
+    /// inserts many hashes at once, which is much faster than [`HashList::insert`]
+    /// for every single hash, because the arrays are sorted only once
+    pub(crate) fn extend<I: IntoIterator<Item = CryptoHash>>(&mut self, hashes: I) {
+        for hash in hashes {
+            self.push(hash, NO_SOURCE);
+        }
+        self.sort();
+    }
+
+    fn push(&mut self, crypto_hash: CryptoHash, source: u32) {
+        match crypto_hash {
+            CryptoHash::MD5(h) => self.md5.push(h, source),
+            CryptoHash::SHA1(h) => self.sha1.push(h, source),
+            CryptoHash::SHA256(h) => self.sha256.push(h, source),
+            CryptoHash::SHA384(h) => self.sha384.push(h, source),
+            CryptoHash::SHA512(h) => self.sha512.push(h, source),
+            CryptoHash::BLAKE3(h) => self.blake3.push(h, source),
+            CryptoHash::IMPHASH(h) => self.imphash.push(h, source),
+            CryptoHash::AUTHENTICODE(h) => self.authenticode.push(h, source),
+        }
+    }
+
+    fn sort(&mut self) {
+        self.md5.sort();
+        self.sha1.sort();
+        self.sha256.sort();
+        self.sha384.sort();
+        self.sha512.sort();
+        self.blake3.sort();
+        self.imphash.sort();
+        self.authenticode.sort();
+    }
+
+    fn lookup(&self, crypto_hash: &CryptoHash) -> Option<u32> {
+        match crypto_hash {
+            CryptoHash::MD5(h) => self.md5.source_of(h),
+            CryptoHash::SHA1(h) => self.sha1.source_of(h),
+            CryptoHash::SHA256(h) => self.sha256.source_of(h),
+            CryptoHash::SHA384(h) => self.sha384.source_of(h),
+            CryptoHash::SHA512(h) => self.sha512.source_of(h),
+            CryptoHash::BLAKE3(h) => self.blake3.source_of(h),
+            CryptoHash::IMPHASH(h) => self.imphash.source_of(h),
+            CryptoHash::AUTHENTICODE(h) => self.authenticode.source_of(h),
+        }
+    }
+
+    pub fn contains(&self, crypto_hash: &CryptoHash) -> bool {
+        self.lookup(crypto_hash).is_some()
+    }
+
+    /// returns the name of the file from which `crypto_hash` has been read
+    pub fn source_of(&self, crypto_hash: &CryptoHash) -> Option<&str> {
+        self.lookup(crypto_hash)
+            .and_then(|source| self.sources.get(source as usize))
+            .map(String::as_str)
+    }
+
+    /// reads hashes from a file. Every line may contain any number of hashes
+    /// (see [`CryptoHash::parse`]), separated by commas, semicolons, tabs, pipes or spaces.
+    /// This covers plain hash lists, CSV files (including the NSRL RDS format)
+    /// and Loki-style IoC files (`hash;description`). Lines starting with `#`,
+    /// `//` or `;` are ignored. Returns the number of hashes read.
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        let source = self.sources.len() as u32;
+        self.sources.push(
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.display().to_string()),
+        );
+
+        let mut count = 0;
+        for (line_no, line) in reader.lines().enumerate() {
+            let line = line?;
+            let hashes = Self::parse_line(&line);
+            if hashes.is_empty() && !Self::is_comment(&line) {
+                log::debug!(
+                    "found no hash in {}:{}, ignoring this line",
+                    path.display(),
+                    line_no + 1
+                );
+            }
+            for hash in hashes {
+                self.push(hash, source);
+                count += 1;
+            }
+        }
+        self.sort();
+
+        if count == 0 {
+            log::warn!("found no hashes in '{}'", path.display());
+        } else {
+            log::info!("loaded {} hashes from '{}'", count, path.display());
+        }
+        Ok(count)
+    }
+
+    /// all hash sets (files ending with `.txt` or `.csv`) in a directory, sorted by name
+    pub fn hash_sets_in_directory(path: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let file = entry?.path();
+            let is_hash_set = file
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .map_or(false, |e| e == "txt" || e == "csv");
+            if file.is_file() && is_hash_set {
+                files.push(file);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// returns the numbers of all lines of a hash set which are neither a comment
+    /// nor contain a hash. The first line of a CSV file is considered to be a header
+    pub fn invalid_lines<P: AsRef<Path>>(path: P) -> Result<Vec<usize>> {
+        let path = path.as_ref();
+        let has_header = path
+            .extension()
+            .map_or(false, |e| e.to_string_lossy().to_lowercase() == "csv");
+        let reader = BufReader::new(File::open(path)?);
+        let mut invalid_lines = Vec::new();
+        for (line_no, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line_no == 0 && has_header {
+                continue;
+            }
+            if Self::parse_line(&line).is_empty() && !Self::is_comment(&line) {
+                invalid_lines.push(line_no + 1);
+            }
+        }
+        Ok(invalid_lines)
+    }
+
+    /// reads all hash sets (files ending with `.txt` or `.csv`) in a directory,
+    /// like Loki does with its `iocs` folder. Returns the number of hashes read.
+    pub fn add_directory<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let files = Self::hash_sets_in_directory(path.as_ref())?;
+        if files.is_empty() {
+            log::warn!("found no hash sets in '{}'", path.as_ref().display());
+        }
+
+        let mut count = 0;
+        for file in files {
+            count += self.add_file(file)?;
+        }
+        Ok(count)
+    }
+
+    fn is_comment(line: &str) -> bool {
+        let line = line.trim_start();
+        line.is_empty() || line.starts_with('#') || line.starts_with("//") || line.starts_with(';')
+    }
+
+    fn parse_line(line: &str) -> Vec<CryptoHash> {
+        if Self::is_comment(line) {
+            return Vec::new();
+        }
+
+        line.split(|c: char| matches!(c, ',' | ';' | '\t' | '|') || c.is_whitespace())
+            .map(|field| field.trim_matches(|c| c == '"' || c == '\''))
+            .filter_map(|field| CryptoHash::parse(field).ok())
+            .collect()
+    }
+
+    /// the hash algorithms which are needed to check against this list
+    pub fn algorithms(&self) -> HashAlgorithms {
+        HashAlgorithms {
+            md5: !self.md5.is_empty(),
+            sha1: !self.sha1.is_empty(),
+            sha256: !self.sha256.is_empty(),
+            sha384: !self.sha384.is_empty(),
+            sha512: !self.sha512.is_empty(),
+            blake3: !self.blake3.is_empty(),
+            imphash: !self.imphash.is_empty(),
+            authenticode: !self.authenticode.is_empty(),
+        }
+    }
+
+    /// computes all hashes of `slice` which are needed to check against this list
+    pub fn compute_hashes<S: AsRef<[u8]>>(&self, slice: S) -> Vec<CryptoHash> {
+        self.algorithms().compute_hashes(slice)
+    }
+
+    /// computes the hashes of `data` and returns all of them which are contained
+    /// in this list
+    pub fn matching_hashes_of_slice(&self, data: &[u8]) -> Vec<CryptoHash> {
+        self.compute_hashes(data)
+            .into_iter()
+            .filter(|h| self.contains(h))
+            .collect()
+    }
+
+    /// computes the hashes of the file at `path` and returns all of them
+    /// which are contained in this list
+    pub fn matching_hashes_of_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<CryptoHash>> {
+        Ok(self.matching_hashes_of_file_limited(path, None)?.0)
+    }
+
+    /// the hashes of the file of `result` which are contained in this list. The hashes
+    /// which have been computed by the scan worker are used, if there are any;
+    /// otherwise the file is hashed again
+    pub(crate) fn matching_hashes_of_result(
+        &self,
+        result: &ScannerResult,
+    ) -> Result<Vec<CryptoHash>> {
+        match (result.hashes(), result.path()) {
+            (Some(hashes), _) => {
+                let matching_hashes = hashes.iter().filter(|h| self.contains(h));
+                Ok(matching_hashes.cloned().collect())
+            }
+            (None, Some(path)) => self.matching_hashes_of_file(path),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    /// like [`HashList::matching_hashes_of_file`], but only the first `limit` bytes
+    /// of the file are hashed. The second value returned is `true` if the file
+    /// was larger than `limit`, which means that only a partial hash has been computed.
+    pub fn matching_hashes_of_file_limited<P: AsRef<Path>>(
+        &self,
+        path: P,
+        limit: Option<u64>,
+    ) -> Result<(Vec<CryptoHash>, bool)> {
+        const EMPTY_SLICE: [u8; 0] = [];
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let partial = limit.map_or(false, |limit| len > limit);
+        let hashes = if len == 0 {
+            self.compute_hashes(EMPTY_SLICE)
+        } else {
+            let mmap = unsafe { MmapOptions::new().map(&file)? };
+            let end = limit.map_or(len, |limit| limit.min(len)) as usize;
+            self.compute_hashes(&mmap[..end])
+        };
+        Ok((hashes.into_iter().filter(|h| self.contains(h)).collect(), partial))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CryptoHash, HashList};
+
+    #[test]
+    fn test_parse_line() {
+        assert!(HashList::parse_line("# e4d7f1b4ed2e42d15898f4b27b019da4").is_empty());
+        assert!(HashList::parse_line("md5,sha1,filename").is_empty());
+
+        let hashes = HashList::parse_line("e4d7f1b4ed2e42d15898f4b27b019da4;hello world sample");
+        assert_eq!(hashes.len(), 1);
+        assert!(matches!(hashes[0], CryptoHash::MD5(_)));
+
+        let hashes = HashList::parse_line(
+            "\"e4d7f1b4ed2e42d15898f4b27b019da4\",\"b7e23ec29af22b0b4e41da31e868d57226121c84\",sample1.txt",
+        );
+        assert_eq!(hashes.len(), 2);
+        assert!(matches!(hashes[1], CryptoHash::SHA1(_)));
+    }
+
+    #[test]
+    fn test_sorted_hashes() {
+        let mut hashes = HashList::default();
+        for (source, hash) in [(0, [3u8; 16]), (0, [1u8; 16]), (1, [3u8; 16])] {
+            hashes.push(CryptoHash::MD5(hash), source);
+        }
+        hashes.sort();
+        hashes.insert(CryptoHash::MD5([2u8; 16]));
+        hashes.sources = vec!["first.txt".to_owned(), "second.txt".to_owned()];
+
+        assert_eq!(hashes.md5.entries.len(), 3);
+        assert!(hashes.contains(&CryptoHash::MD5([2u8; 16])));
+        assert!(!hashes.contains(&CryptoHash::SHA1([2u8; 20])));
+        assert_eq!(hashes.source_of(&CryptoHash::MD5([3u8; 16])), Some("first.txt"));
+        assert_eq!(hashes.source_of(&CryptoHash::MD5([2u8; 16])), None);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut hashes = HashList::default();
+        hashes.insert(CryptoHash::MD5([2u8; 16]));
+        hashes.extend([3u8, 1, 2, 3].iter().map(|&b| CryptoHash::MD5([b; 16])));
+
+        let entries: Vec<_> = hashes.md5.entries.iter().map(|(hash, _)| hash[0]).collect();
+        assert_eq!(entries, vec![1, 2, 3]);
+        assert!(hashes.contains(&CryptoHash::MD5([1u8; 16])));
+    }
+
+    #[test]
+    fn test_parse_prefixed() {
+        let blake3 = "blake3:ece8e0ff8ec1aa2d2a4cff2e8e4d1b7bfb1e0f6e9a4b9bd1e84ebe9c8f1e8a3d";
+        assert!(matches!(CryptoHash::parse(blake3), Ok(CryptoHash::BLAKE3(_))));
+        assert!(matches!(
+            CryptoHash::parse(&blake3.replace("blake3", "SHA256")),
+            Ok(CryptoHash::SHA256(_))
+        ));
+        assert!(CryptoHash::parse("sha1:e4d7f1b4ed2e42d15898f4b27b019da4").is_err());
+        assert!(matches!(
+            CryptoHash::parse("imphash:e4d7f1b4ed2e42d15898f4b27b019da4"),
+            Ok(CryptoHash::IMPHASH(_))
+        ));
+        assert!(
+            CryptoHash::parse_imphash("E4D7F1B4ED2E42D15898F4B27B019DA4").unwrap()
+                == CryptoHash::parse("imphash:e4d7f1b4ed2e42d15898f4b27b019da4").unwrap()
+        );
+        assert!(CryptoHash::parse_imphash("md5:e4d7f1b4ed2e42d15898f4b27b019da4").is_err());
+
+        let sha512 = "a".repeat(128);
+        assert!(matches!(CryptoHash::parse(&sha512), Ok(CryptoHash::SHA512(_))));
+        let sha384 = "b".repeat(96);
+        assert!(matches!(CryptoHash::parse(&sha384), Ok(CryptoHash::SHA384(_))));
+    }
+}
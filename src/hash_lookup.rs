@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+use crate::file_hash::sha256_of;
+
+const VIRUSTOTAL_URL: &str = "https://www.virustotal.com/api/v3/files/";
+const MALWAREBAZAAR_URL: &str = "https://mb-api.abuse.ch/api/v1/";
+const USER_AGENT: &str = concat!("dionysos/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Service {
+    VirusTotal,
+    MalwareBazaar,
+}
+
+impl std::fmt::Display for Service {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Service::VirusTotal => write!(f, "VirusTotal"),
+            Service::MalwareBazaar => write!(f, "MalwareBazaar"),
+        }
+    }
+}
+
+/// what an online service knows about a hash
+#[derive(Clone, Debug)]
+pub(crate) struct ServiceReport {
+    service: Service,
+
+    /// `false` if the service has never seen the hash
+    known: bool,
+
+    /// the number of engines which detect the file, and the number of all engines
+    detections: Option<(u64, u64)>,
+    family: Option<String>,
+}
+
+impl ServiceReport {
+    fn describe(&self) -> String {
+        if !self.known {
+            return format!("{} unknown", self.service);
+        }
+        let mut description = self.service.to_string();
+        if let Some((detected, engines)) = self.detections {
+            description.push_str(&format!(" {}/{}", detected, engines));
+        }
+        if let Some(family) = &self.family {
+            description.push_str(&format!(" ({})", family));
+        }
+        description
+    }
+}
+
+/// the reports of all services about the SHA256 hash of a file
+#[derive(Clone, Debug)]
+pub(crate) struct HashReport {
+    sha256: String,
+    reports: Vec<ServiceReport>,
+}
+
+impl HashReport {
+    /// a single line, like `VirusTotal 45/70 (trojan.emotet), MalwareBazaar (Emotet)`
+    pub fn describe(&self) -> String {
+        self.reports
+            .iter()
+            .map(|r| r.describe())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut report = json!({ "sha256": self.sha256 });
+        for r in self.reports.iter() {
+            report[r.service.to_string().to_lowercase()] = json!({
+                "known": r.known,
+                "detected": r.detections.map(|(detected, _)| detected),
+                "engines": r.detections.map(|(_, engines)| engines),
+                "family": r.family
+            });
+        }
+        report
+    }
+}
+
+/// ensures that requests to a service are at least `interval` apart
+struct RateLimit {
+    interval: Duration,
+    last_request: Option<Instant>,
+}
+
+impl RateLimit {
+    fn wait(&mut self) {
+        if let Some(last_request) = self.last_request {
+            let elapsed = last_request.elapsed();
+            if elapsed < self.interval {
+                std::thread::sleep(self.interval - elapsed);
+            }
+        }
+        self.last_request = Some(Instant::now());
+    }
+}
+
+/// looks up the hashes of files with findings at VirusTotal and MalwareBazaar. This
+/// is the only part of dionysos which sends information about the scanned files to
+/// third parties: only the SHA256 hashes are sent, never the files themselves.
+///
+/// The public APIs of both services are strictly rate limited, so that requests are
+/// delayed to stay within `per_minute` requests per service, and no more than `limit`
+/// requests are sent. Every hash is only looked up once. The lookups are done after
+/// the scan, so that the delays do not hold up the output of the findings
+pub(crate) struct HashLookup {
+    services: Vec<(Service, String)>,
+    agent: ureq::Agent,
+    rate_limits: Vec<RateLimit>,
+
+    /// the maximum number of requests, and the number of requests which have been sent
+    limit: usize,
+    requests: usize,
+
+    cache: HashMap<String, HashReport>,
+}
+
+impl HashLookup {
+    pub fn new(
+        virustotal_key: Option<String>,
+        malwarebazaar_key: Option<String>,
+        per_minute: u32,
+        limit: usize,
+    ) -> Option<Self> {
+        let services: Vec<(Service, String)> = vec![
+            (Service::VirusTotal, virustotal_key),
+            (Service::MalwareBazaar, malwarebazaar_key),
+        ]
+        .into_iter()
+        .filter_map(|(service, key)| key.map(|key| (service, key)))
+        .collect();
+        if services.is_empty() {
+            return None;
+        }
+        let interval = Duration::from_secs(60) / per_minute.max(1);
+        Some(Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .user_agent(USER_AGENT)
+                .build(),
+            rate_limits: services
+                .iter()
+                .map(|_| RateLimit {
+                    interval,
+                    last_request: None,
+                })
+                .collect(),
+            services,
+            limit,
+            requests: 0,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// checks if no more requests may be sent
+    pub fn is_exhausted(&self) -> bool {
+        self.requests >= self.limit
+    }
+
+    /// the reports about the file at `path`, or `None` if it could not be looked up
+    pub fn lookup(&mut self, path: &Path) -> Option<HashReport> {
+        let sha256 = match sha256_of(path) {
+            Ok(sha256) => sha256,
+            Err(why) => {
+                log::debug!("unable to hash '{}': {}", path.display(), why);
+                return None;
+            }
+        };
+        if let Some(report) = self.cache.get(&sha256) {
+            return Some(report.clone());
+        }
+
+        let mut reports = Vec::new();
+        for (index, (service, key)) in self.services.iter().enumerate() {
+            if self.requests >= self.limit {
+                log::warn!(
+                    "not looking up '{}' at {}, because the limit of {} requests has been reached",
+                    path.display(),
+                    service,
+                    self.limit
+                );
+                continue;
+            }
+            self.requests += 1;
+            self.rate_limits[index].wait();
+            log::info!(
+                "looking up '{}' ({}) at {}",
+                path.display(),
+                sha256,
+                service
+            );
+            let report = match service {
+                Service::VirusTotal => query_virustotal(&self.agent, key, &sha256),
+                Service::MalwareBazaar => query_malwarebazaar(&self.agent, key, &sha256),
+            };
+            match report {
+                Ok(report) => reports.push(report),
+                Err(why) => log::warn!("unable to look up {} at {}: {}", sha256, service, why),
+            }
+        }
+        if reports.is_empty() {
+            return None;
+        }
+        let report = HashReport { sha256, reports };
+        self.cache.insert(report.sha256.clone(), report.clone());
+        Some(report)
+    }
+}
+
+fn query_virustotal(agent: &ureq::Agent, key: &str, sha256: &str) -> Result<ServiceReport> {
+    let response = agent
+        .get(&format!("{}{}", VIRUSTOTAL_URL, sha256))
+        .set("x-apikey", key)
+        .call();
+    let response: Value = match response {
+        Ok(response) => serde_json::from_reader(response.into_reader())?,
+        Err(ureq::Error::Status(404, _)) => {
+            return Ok(ServiceReport {
+                service: Service::VirusTotal,
+                known: false,
+                detections: None,
+                family: None,
+            })
+        }
+        Err(why) => return Err(anyhow!("{}", why)),
+    };
+    Ok(parse_virustotal(&response))
+}
+
+fn parse_virustotal(response: &Value) -> ServiceReport {
+    let attributes = &response["data"]["attributes"];
+    let stats = &attributes["last_analysis_stats"];
+    let detections = stats.as_object().map(|stats| {
+        let count = |key: &str| stats.get(key).and_then(Value::as_u64).unwrap_or(0);
+        (
+            count("malicious"),
+            count("malicious") + count("suspicious") + count("undetected") + count("harmless"),
+        )
+    });
+    ServiceReport {
+        service: Service::VirusTotal,
+        known: true,
+        detections,
+        family: attributes["popular_threat_classification"]["suggested_threat_label"]
+            .as_str()
+            .map(|label| label.to_owned()),
+    }
+}
+
+fn query_malwarebazaar(agent: &ureq::Agent, key: &str, sha256: &str) -> Result<ServiceReport> {
+    let response = agent
+        .post(MALWAREBAZAAR_URL)
+        .set("Auth-Key", key)
+        .send_form(&[("query", "get_info"), ("hash", sha256)])
+        .map_err(|why| anyhow!("{}", why))?;
+    let response: Value = serde_json::from_reader(response.into_reader())?;
+    parse_malwarebazaar(&response)
+}
+
+fn parse_malwarebazaar(response: &Value) -> Result<ServiceReport> {
+    let known = match response["query_status"].as_str() {
+        Some("ok") => true,
+        Some("hash_not_found") => false,
+        Some(status) => return Err(anyhow!("query failed with status '{}'", status)),
+        None => return Err(anyhow!("invalid response")),
+    };
+    Ok(ServiceReport {
+        service: Service::MalwareBazaar,
+        known,
+        detections: None,
+        family: response["data"][0]["signature"]
+            .as_str()
+            .map(|signature| signature.to_owned()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{parse_malwarebazaar, parse_virustotal, HashLookup};
+
+    #[test]
+    fn test_parse_responses() {
+        let virustotal = parse_virustotal(&json!({"data": {"attributes": {
+            "last_analysis_stats": {"malicious": 45, "suspicious": 1, "undetected": 20, "harmless": 0},
+            "popular_threat_classification": {"suggested_threat_label": "trojan.emotet"}
+        }}}));
+        assert_eq!(virustotal.describe(), "VirusTotal 45/66 (trojan.emotet)");
+
+        let malwarebazaar = parse_malwarebazaar(&json!({
+            "query_status": "ok",
+            "data": [{"signature": "Emotet"}]
+        }))
+        .unwrap();
+        assert_eq!(malwarebazaar.describe(), "MalwareBazaar (Emotet)");
+
+        let unknown = parse_malwarebazaar(&json!({"query_status": "hash_not_found"})).unwrap();
+        assert_eq!(unknown.describe(), "MalwareBazaar unknown");
+        assert!(parse_malwarebazaar(&json!({"query_status": "wrong_auth_key"})).is_err());
+    }
+
+    #[test]
+    fn test_lookup_limit() {
+        assert!(HashLookup::new(None, None, 4, 500).is_none());
+
+        let path = std::env::temp_dir().join(format!("dionysos-lookup-{}", std::process::id()));
+        std::fs::write(&path, b"sample").unwrap();
+        let mut hash_lookup = HashLookup::new(Some("key".to_owned()), None, 4, 0).unwrap();
+        assert!(hash_lookup.is_exhausted());
+        // no request is sent once the limit has been reached
+        assert!(hash_lookup.lookup(&path).is_none());
+        assert_eq!(hash_lookup.requests, 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,136 +1,60 @@
 use anyhow::{anyhow, Result};
 use maplit::hashset;
-use md5::{Digest, Md5};
-use memmap::MmapOptions;
 use serde_json::json;
-use sha1::Sha1;
-use sha2::Sha256;
-use std::convert::TryInto;
+use std::collections::HashSet;
 use std::fmt::Display;
-use std::hash::Hash;
-use std::{collections::HashSet, fs::File};
+use std::path::Path;
 use walkdir::DirEntry;
 
 use crate::csv_line::CsvLine;
+use crate::path_string::path_to_string;
+use crate::hash_list::{CryptoHash, HashList};
+use crate::string_options::StringOptions;
 use crate::filescanner::FileScanner;
 use crate::scanner_result::ScannerFinding;
-
-const MD5_SIZE: usize = 128 / 8;
-const SHA1_SIZE: usize = 160 / 8;
-const SHA256_SIZE: usize = 256 / 8;
-
-#[derive(Eq, Clone)]
-pub enum CryptoHash {
-    MD5([u8; MD5_SIZE]),
-    SHA1([u8; SHA1_SIZE]),
-    SHA256([u8; SHA256_SIZE]),
-}
-
-impl PartialEq for CryptoHash {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::MD5(l0), Self::MD5(r0)) => l0 == r0,
-            (Self::SHA1(l0), Self::SHA1(r0)) => l0 == r0,
-            (Self::SHA256(l0), Self::SHA256(r0)) => l0 == r0,
-            (_, _) => false,
-        }
-    }
-}
-
-impl Hash for CryptoHash {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        match self {
-            CryptoHash::MD5(h) => h.hash(state),
-            CryptoHash::SHA1(h) => h.hash(state),
-            CryptoHash::SHA256(h) => h.hash(state),
-        }
-    }
-}
-
-impl Display for CryptoHash {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CryptoHash::MD5(h) => write!(f, "MD5:{}", hex::encode(h)),
-            CryptoHash::SHA1(h) => write!(f, "SHA1:{}", hex::encode(h)),
-            CryptoHash::SHA256(h) => write!(f, "SHA256:{}", hex::encode(h)),
-        }
-    }
-}
+use crate::severity::Severity;
 
 #[derive(Default)]
 pub struct HashScanner {
-    hashes: HashSet<CryptoHash>,
+    hashes: HashList,
 
-    has_md5_hashes: bool,
-    has_sha1_hashes: bool,
-    has_sha256_hashes: bool,
+    /// only hash the first MiB of a file
+    partial_hash: Option<u64>,
 }
 
 impl HashScanner {
     pub fn with_hashes(mut self, hashes: &[String]) -> Result<Self> {
         for hash in hashes.iter() {
-            let crypto_hash = Self::parse_hash(hash)?;
-            match &crypto_hash {
-                CryptoHash::MD5(_) => self.has_md5_hashes = true,
-                CryptoHash::SHA1(_) => self.has_sha1_hashes = true,
-                CryptoHash::SHA256(_) => self.has_sha256_hashes = true,
-            }
-            self.hashes.insert(Self::parse_hash(hash)?);
+            self.hashes.insert(CryptoHash::parse(hash)?);
         }
         Ok(self)
     }
 
-    fn parse_hash(hash: &str) -> Result<CryptoHash> {
-        let bytes = hex::decode(hash)?;
-        match bytes.len() {
-            MD5_SIZE => Ok(CryptoHash::MD5(bytes.try_into().unwrap())),
-            SHA1_SIZE => Ok(CryptoHash::SHA1(bytes.try_into().unwrap())),
-            SHA256_SIZE => Ok(CryptoHash::SHA256(bytes.try_into().unwrap())),
-            _ => Err(anyhow!("invalid hash length of '{}'", hash)),
+    /// import hashes of PE files, with or without the `imphash:` prefix
+    pub fn with_imphashes(mut self, imphashes: &[String]) -> Result<Self> {
+        for imphash in imphashes.iter() {
+            self.hashes.insert(CryptoHash::parse_imphash(imphash)?);
         }
+        Ok(self)
     }
 
-    fn scan_slice<S: AsRef<[u8]>>(
-        &self,
-        slice: S,
-        entry: &DirEntry,
-    ) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
-        let mut hashes = Vec::new();
-
-        if self.has_md5_hashes {
-            let mut hasher = Md5::new();
-            hasher.update(&slice);
-            let result = hasher.finalize();
-            let crypto_hash = CryptoHash::MD5(result.try_into().unwrap());
-            hashes.push(crypto_hash);
-        }
-
-        if self.has_sha1_hashes {
-            let mut hasher = Sha1::new();
-            hasher.update(&slice);
-            let result = hasher.finalize();
-            let crypto_hash = CryptoHash::SHA1(result.try_into().unwrap());
-            hashes.push(crypto_hash);
-        }
+    /// reads hashes from a file, see [`HashList::add_file`] for the supported formats
+    pub fn with_hash_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        self.hashes.add_file(path)?;
+        Ok(self)
+    }
 
-        if self.has_sha256_hashes {
-            let mut hasher = Sha256::new();
-            hasher.update(&slice);
-            let result = hasher.finalize();
-            let crypto_hash = CryptoHash::SHA256(result.try_into().unwrap());
-            hashes.push(crypto_hash);
-        }
+    /// only hash the first `partial_hash` MiB of larger files. Findings in those
+    /// files are marked as being based on a partial hash
+    pub fn with_partial_hash(mut self, partial_hash: Option<u64>) -> Self {
+        self.partial_hash = partial_hash;
+        self
+    }
 
-        let mut results = Vec::new();
-        for h in &hashes {
-            if self.hashes.contains(h) {
-                results.push(Ok(Box::new(HashScannerFinding {
-                    hash: h.clone(),
-                    found_in_file: entry.file_name().to_str().unwrap().to_owned(),
-                }) as Box<dyn ScannerFinding>));
-            }
-        }
-        results
+    /// reads all hash sets in a directory, see [`HashList::add_directory`]
+    pub fn with_hash_dir<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        self.hashes.add_directory(path)?;
+        Ok(self)
     }
 }
 
@@ -142,55 +66,107 @@ impl Display for HashScanner {
 
 impl FileScanner for HashScanner {
     fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
-        const EMPTY_SLICE: [u8; 0] = [];
-
-        match entry.metadata() {
-            Err(why) => return vec![Err(anyhow!("unable to obtain metadata for file '{}'", why))],
-            Ok(metadata) => {
-                if metadata.len() == 0 {
-                    self.scan_slice(&EMPTY_SLICE, entry)
-                } else {
-                    let file = File::open(entry.path()).unwrap();
-                    let mmap = unsafe { MmapOptions::new().map(&file).unwrap() };
-                    self.scan_slice(&mmap, entry)
-                }
-            }
+        let limit = self.partial_hash.map(|mib| mib * 1024 * 1024);
+        match self.hashes.matching_hashes_of_file_limited(entry.path(), limit) {
+            Err(why) => vec![Err(anyhow!(
+                "unable to hash file '{}': {}",
+                entry.path().display(),
+                why
+            ))],
+            Ok((hashes, partial)) => hashes
+                .into_iter()
+                .map(|hash| {
+                    Ok(Box::new(HashScannerFinding {
+                        source: self.hashes.source_of(&hash).map(str::to_owned),
+                        hash,
+                        partial_hash: if partial { self.partial_hash } else { None },
+                        found_in_file: path_to_string(Path::new(entry.file_name())),
+                    }) as Box<dyn ScannerFinding>)
+                })
+                .collect(),
         }
     }
+
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let limit = self.partial_hash.map(|mib| (mib * 1024 * 1024) as usize);
+        let partial = limit.map_or(false, |limit| data.len() > limit);
+        let end = limit.map_or(data.len(), |limit| limit.min(data.len()));
+        self.hashes
+            .matching_hashes_of_slice(&data[..end])
+            .into_iter()
+            .map(|hash| {
+                Ok(Box::new(HashScannerFinding {
+                    source: self.hashes.source_of(&hash).map(str::to_owned),
+                    hash,
+                    partial_hash: if partial { self.partial_hash } else { None },
+                    found_in_file: name.to_owned(),
+                }) as Box<dyn ScannerFinding>)
+            })
+            .collect()
+    }
 }
 
 struct HashScannerFinding {
     hash: CryptoHash,
     found_in_file: String,
+
+    /// name of the hash set which contained the hash
+    source: Option<String>,
+
+    /// size (in MiB) of the part of the file which has been hashed,
+    /// if the file has not been hashed completely
+    partial_hash: Option<u64>,
+}
+
+impl HashScannerFinding {
+    fn hash_description(&self) -> String {
+        match self.partial_hash {
+            Some(mib) => format!("{} (partial hash of the first {} MiB)", self.hash, mib),
+            None => self.hash.to_string(),
+        }
+    }
 }
 
 impl Display for HashScannerFinding {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let found_in_file = self.found_in_file();
-        let hash = &self.hash;
-        writeln!(f, "file {found_in_file} has the hash value {hash}")
+        let hash = self.hash_description();
+        match &self.source {
+            Some(source) => writeln!(f, "file {found_in_file} has the hash value {hash} (from {source})"),
+            None => writeln!(f, "file {found_in_file} has the hash value {hash}"),
+        }
     }
 }
 
 impl ScannerFinding for HashScannerFinding {
-    fn format_csv(&self) -> HashSet<CsvLine> {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
         let file = self.found_in_file();
         hashset![CsvLine::new(
             "Hash",
-            &format!("{}", self.hash),
+            &self.hash_description(),
             file,
-            String::new()
+            self.source.clone().unwrap_or_default()
         )]
     }
-    fn to_json(&self) -> serde_json::Value {
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
         let file = self.found_in_file();
         json!({
             "01_scanner": "hash",
             "02_suspicious_file": file,
-            "03_hash": format!("{}", self.hash)
+            "03_hash": format!("{}", self.hash),
+            "04_source": self.source,
+            "05_partial_hash_mib": self.partial_hash
         })
     }
 
+    fn severity(&self) -> Severity {
+        Severity::High
+    }
+
+    fn scanner(&self) -> String {
+        "hash".to_owned()
+    }
+
     fn found_in_file(&self) -> &str {
         &self.found_in_file[..]
     }
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use clap::ArgEnum;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::dionysos::OutputFormat;
+use crate::scan_service::{Job, JobRegistry, ScanRequest};
+
+/// uploads which are larger are rejected
+const MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// the number of requests which are handled at the same time. Further requests wait
+/// until a thread is available
+const HTTP_THREADS: usize = 8;
+
+static UPLOADS: AtomicU64 = AtomicU64::new(0);
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: u16, value: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "error": message }))
+    }
+}
+
+/// runs the HTTP server. The requests are handled by a fixed number of threads
+pub fn serve(listener: TcpListener, jobs: Arc<JobRegistry>) -> Result<()> {
+    serve_with_threads(listener, jobs, HTTP_THREADS)
+}
+
+fn serve_with_threads(listener: TcpListener, jobs: Arc<JobRegistry>, threads: usize) -> Result<()> {
+    log::info!("listening for HTTP requests on {}", listener.local_addr()?);
+    let server = Arc::new(
+        Server::from_listener(listener, None)
+            .map_err(|why| anyhow!("unable to start the HTTP server: {}", why))?,
+    );
+    let workers: Vec<_> = (0..threads.max(1))
+        .map(|_| {
+            let server = Arc::clone(&server);
+            let jobs = Arc::clone(&jobs);
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &jobs);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+fn handle(mut request: Request, jobs: &JobRegistry) {
+    log::debug!("{} {}", request.method(), request.url());
+    let reply = route(&mut request, jobs);
+    let content_type = Header::from_bytes("Content-Type", reply.content_type)
+        .expect("the content type is a valid header");
+    let response = Response::from_data(reply.body)
+        .with_status_code(reply.status)
+        .with_header(content_type);
+    if let Err(why) = request.respond(response) {
+        log::warn!("unable to send the response: {}", why);
+    }
+}
+
+fn route(request: &mut Request, jobs: &JobRegistry) -> Reply {
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((&url[..], ""));
+    let query: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let method = request.method().clone();
+    match (method, &segments[..]) {
+        (Method::Post, ["scans"]) => start_scan(request, jobs),
+        (Method::Post, ["uploads"]) => upload(request, &query, jobs),
+        (Method::Get, ["scans", id]) => with_job(jobs, id, |job| {
+            Reply::json(200, job.status().to_json())
+        }),
+        (Method::Get, ["scans", id, "results"]) => with_job(jobs, id, |job| {
+            results(job, query.get("format").map(String::as_str).unwrap_or("json"))
+        }),
+        (Method::Delete, ["scans", id]) => with_job(jobs, id, |job| {
+            job.cancel();
+            Reply::json(200, job.status().to_json())
+        }),
+        _ => Reply::error(404, "not found"),
+    }
+}
+
+fn with_job<F>(jobs: &JobRegistry, id: &str, f: F) -> Reply
+where
+    F: FnOnce(&Job) -> Reply,
+{
+    match id.parse().ok().and_then(|id| jobs.job(id)) {
+        Some(job) => f(&job),
+        None => Reply::error(404, &format!("there is no job with id '{}'", id)),
+    }
+}
+
+fn start_scan(request: &mut Request, jobs: &JobRegistry) -> Reply {
+    let scan_request: ScanRequest = match serde_json::from_reader(request.as_reader()) {
+        Ok(scan_request) => scan_request,
+        Err(why) => return Reply::error(400, &format!("invalid scan request: {}", why)),
+    };
+    match jobs.start(scan_request) {
+        Ok(id) => Reply::json(201, json!({ "id": id })),
+        Err(why) => Reply::error(400, &why.to_string()),
+    }
+}
+
+/// stores the body of the request in a temporary directory and scans it with the
+/// scanners of the server. The file is removed after the scan
+fn upload(request: &mut Request, query: &HashMap<String, String>, jobs: &JobRegistry) -> Reply {
+    let name = match query.get("name").and_then(|name| Path::new(name).file_name()) {
+        Some(name) => name.to_owned(),
+        None => return Reply::error(400, "the name of the file is missing"),
+    };
+
+    let dir = std::env::temp_dir().join(format!(
+        "dionysos-upload-{}-{}",
+        std::process::id(),
+        UPLOADS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = dir.join(name);
+    let result = store_upload(request, &dir, &file).and_then(|()| {
+        let scan_request = ScanRequest {
+            path: file.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        jobs.start_with_cleanup(scan_request, Some(dir.clone()))
+    });
+
+    match result {
+        Ok(id) => Reply::json(201, json!({ "id": id })),
+        Err(why) => {
+            let _ = std::fs::remove_dir_all(&dir);
+            Reply::error(400, &why.to_string())
+        }
+    }
+}
+
+fn store_upload(request: &mut Request, dir: &Path, file: &Path) -> Result<()> {
+    std::fs::create_dir(dir)?;
+    let mut body = request.as_reader().take(MAX_UPLOAD_SIZE + 1);
+    let size = std::io::copy(&mut body, &mut File::create(file)?)?;
+    if size > MAX_UPLOAD_SIZE {
+        return Err(anyhow!("the file is larger than {} bytes", MAX_UPLOAD_SIZE));
+    }
+    Ok(())
+}
+
+/// writes all findings of the job in `format` (`csv`, `txt`, `json` or `ndjson`)
+fn results(job: &Job, format: &str) -> Reply {
+    let (format, content_type) = match OutputFormat::from_str(format, true) {
+        Ok(format @ OutputFormat::Csv) => (format, "text/csv"),
+        Ok(format @ OutputFormat::Txt) => (format, "text/plain; charset=utf-8"),
+        Ok(format @ OutputFormat::Json) => (format, "application/json"),
+        Ok(format @ OutputFormat::Ndjson) => (format, "application/x-ndjson"),
+        Err(_) => return Reply::error(400, &format!("unknown format '{}'", format)),
+    };
+
+    let mut body = Vec::new();
+    let mut output = format
+        .into_options(&mut body)
+        .with_string_options(job.string_options().clone());
+    for result in job.status().results() {
+        if let Err(why) = output.print_result(result) {
+            return Reply::error(500, &why.to_string());
+        }
+    }
+    if let Err(why) = output.flush() {
+        return Reply::error(500, &why.to_string());
+    }
+    drop(output);
+
+    Reply {
+        status: 200,
+        content_type,
+        body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    use serde_json::Value;
+
+    use super::serve_with_threads;
+    use crate::scan_service::{JobRegistry, ScanRequest};
+
+    /// starts a server with `threads` threads, and returns its URL
+    fn start(threads: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let jobs = Arc::new(JobRegistry::new(ScanRequest::default()));
+        std::thread::spawn(move || serve_with_threads(listener, jobs, threads));
+        url
+    }
+
+    fn get(url: &str) -> (u16, Value) {
+        let response = match ureq::get(url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(why) => panic!("{}", why),
+        };
+        (response.status(), response.into_json().unwrap())
+    }
+
+    #[test]
+    fn test_scan() {
+        let url = start(2);
+        let response = ureq::post(&format!("{}/scans", url))
+            .send_string(r#"{"path": "src", "filenames": ["^lib\\.rs$"], "threads": 1}"#)
+            .unwrap();
+        assert_eq!(response.status(), 201);
+        let reply: Value = response.into_json().unwrap();
+        let id = reply["id"].as_u64().unwrap();
+
+        let (status, job) = get(&format!("{}/scans/{}", url, id));
+        assert_eq!(status, 200);
+        assert!(job["state"].is_string());
+
+        let (status, error) = get(&format!("{}/scans/{}", url, id + 1));
+        assert_eq!(status, 404);
+        assert!(error["error"].as_str().unwrap().contains("no job"));
+    }
+
+    #[test]
+    fn test_bounded_threads() {
+        // more concurrent requests than threads are answered one after another
+        let url = start(1);
+        let clients: Vec<_> = (0..4)
+            .map(|_| {
+                let url = url.clone();
+                std::thread::spawn(move || get(&format!("{}/unknown", url)).0)
+            })
+            .collect();
+        for client in clients {
+            assert_eq!(client.join().unwrap(), 404);
+        }
+    }
+}
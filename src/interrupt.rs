@@ -0,0 +1,76 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+/// exit code if the user aborts dionysos immediately
+pub const EXIT_ABORTED: i32 = 130;
+
+/// tracks if the scan has been interrupted by SIGINT (Ctrl-C) or SIGTERM.
+///
+/// After the first signal, no more files are scanned, but files which are
+/// currently being scanned may complete within a grace period. A second
+/// signal terminates dionysos immediately.
+#[derive(Default)]
+pub struct Interrupt {
+    since: Mutex<Option<Instant>>,
+}
+
+impl Interrupt {
+    /// installs the signal handler. This must be called only once
+    pub fn install() -> Result<Arc<Self>> {
+        let interrupt = Arc::new(Self::default());
+        let handler = Arc::clone(&interrupt);
+        ctrlc::set_handler(move || handler.interrupt())?;
+        Ok(interrupt)
+    }
+
+    /// creates an interrupt without signal handler, which is triggered by
+    /// [`Interrupt::interrupt`], e.g. when a Windows service is stopped
+    #[cfg(windows)]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub(crate) fn interrupt(&self) {
+        let mut since = self.since.lock().unwrap();
+        if since.is_some() {
+            eprintln!("aborting immediately");
+            std::process::exit(EXIT_ABORTED);
+        }
+        log::warn!(
+            "the scan has been interrupted, waiting for the running scans to complete \
+             (interrupt again to abort immediately)"
+        );
+        *since = Some(Instant::now());
+    }
+
+    /// stops the scan like the first signal, e.g. because its results cannot be
+    /// written anymore
+    pub(crate) fn stop(&self) {
+        let mut since = self.since.lock().unwrap();
+        if since.is_none() {
+            *since = Some(Instant::now());
+        }
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.since.lock().unwrap().is_some()
+    }
+
+    /// returns `true` if the scan has been interrupted more than `grace_period` ago
+    pub fn grace_period_elapsed(&self, grace_period: Duration) -> bool {
+        match *self.since.lock().unwrap() {
+            None => false,
+            Some(since) => since.elapsed() >= grace_period,
+        }
+    }
+
+    /// sleeps for `duration`, unless the scan is interrupted before
+    pub fn sleep(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        while !self.is_interrupted() && Instant::now() < until {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
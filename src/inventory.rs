@@ -0,0 +1,164 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use md5::{Digest, Md5};
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::Sha256;
+use walkdir::DirEntry;
+
+use crate::filescanner::FileScanner;
+use crate::path_string::path_to_string;
+use crate::scanner_result::ScannerFinding;
+
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Serialize)]
+pub(crate) struct InventoryEntry {
+    pub path: String,
+    pub size: u64,
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+
+    /// `true` if only the first part of the file has been hashed
+    pub partial_hash: bool,
+    pub modified: String,
+    pub accessed: String,
+    pub created: String,
+}
+
+/// writes a manifest of every scanned file, containing its hashes, size and
+/// timestamps, to a CSV file. This scanner never produces findings.
+pub struct InventoryScanner {
+    destination: PathBuf,
+    writer: Mutex<csv::Writer<File>>,
+    files: AtomicUsize,
+
+    /// only hash the first bytes of a file
+    hash_limit: Option<u64>,
+}
+
+impl InventoryScanner {
+    pub fn new<P: AsRef<Path>>(destination: P) -> Result<Self> {
+        let destination = destination.as_ref().to_owned();
+        let writer = csv::Writer::from_path(&destination)?;
+        Ok(Self {
+            destination,
+            writer: Mutex::new(writer),
+            files: AtomicUsize::new(0),
+            hash_limit: None,
+        })
+    }
+
+    /// only hash the first `partial_hash` MiB of larger files. Those files
+    /// are marked in the inventory
+    pub fn with_partial_hash(mut self, partial_hash: Option<u64>) -> Self {
+        self.hash_limit = partial_hash.map(|mib| mib * 1024 * 1024);
+        self
+    }
+
+    fn write_entry(&self, entry: InventoryEntry) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.serialize(entry)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn inventory_entry(&self, path: &Path) -> Result<InventoryEntry> {
+        inventory_entry(path, self.hash_limit)
+    }
+}
+
+/// hashes the file at `path` (only its first `hash_limit` bytes, if given) and reads
+/// its size and timestamps
+pub(crate) fn inventory_entry(path: &Path, hash_limit: Option<u64>) -> Result<InventoryEntry> {
+    let metadata = path.metadata()?;
+    let limit = hash_limit.unwrap_or(u64::MAX);
+    let mut reader = BufReader::new(File::open(path)?).take(limit);
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+
+    let mut md5 = Md5::new();
+    let mut sha1 = Sha1::new();
+    let mut sha256 = Sha256::new();
+    loop {
+        let bytes = reader.read(&mut buffer)?;
+        if bytes == 0 {
+            break;
+        }
+        md5.update(&buffer[..bytes]);
+        sha1.update(&buffer[..bytes]);
+        sha256.update(&buffer[..bytes]);
+    }
+
+    Ok(InventoryEntry {
+        path: path_to_string(path),
+        size: metadata.len(),
+        md5: hex::encode(md5.finalize()),
+        sha1: hex::encode(sha1.finalize()),
+        sha256: hex::encode(sha256.finalize()),
+        partial_hash: metadata.len() > limit,
+        modified: format_timestamp(metadata.modified()),
+        accessed: format_timestamp(metadata.accessed()),
+        created: format_timestamp(metadata.created()),
+    })
+}
+
+/// timestamps which are not supported by the platform or filesystem are left empty
+fn format_timestamp(timestamp: std::io::Result<SystemTime>) -> String {
+    match timestamp {
+        Ok(timestamp) => DateTime::<Utc>::from(timestamp).to_rfc3339(),
+        Err(_) => String::new(),
+    }
+}
+
+impl Display for InventoryScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "InventoryScanner")
+    }
+}
+
+impl FileScanner for InventoryScanner {
+    fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let entry = match self.inventory_entry(file.path()) {
+            Ok(entry) => entry,
+            Err(why) => {
+                return vec![Err(anyhow!(
+                    "unable to add '{}' to the inventory: {}",
+                    file.path().display(),
+                    why
+                ))]
+            }
+        };
+
+        if let Err(why) = self.write_entry(entry) {
+            return vec![Err(anyhow!(
+                "unable to write to the inventory '{}': {}",
+                self.destination.display(),
+                why
+            ))];
+        }
+        self.files.fetch_add(1, Ordering::Relaxed);
+        Vec::new()
+    }
+
+    fn summary(&self) -> Vec<String> {
+        vec![format!(
+            "wrote {} file(s) to the inventory '{}'",
+            self.files.load(Ordering::Relaxed),
+            self.destination.display()
+        )]
+    }
+
+    /// every file is added to the inventory, including its path
+    fn depends_on_path(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Result;
+use clap::ArgEnum;
+
+use crate::hash_list::HashList;
+
+#[derive(ArgEnum, Clone, Copy)]
+pub enum KnownGoodMode {
+    /// scan all files, but drop the findings of known-good files
+    Suppress,
+
+    /// hash every file before scanning it, and do not scan known-good files at all
+    Skip,
+}
+
+/// an allowlist of hashes of known-good files, e.g. the NSRL RDS
+pub struct KnownGoodFilter {
+    hashes: HashList,
+    mode: KnownGoodMode,
+    suppressed: AtomicUsize,
+}
+
+impl KnownGoodFilter {
+    pub fn new(mode: KnownGoodMode) -> Self {
+        Self {
+            hashes: HashList::default(),
+            mode,
+            suppressed: AtomicUsize::new(0),
+        }
+    }
+
+    /// reads hashes from a file, see [`HashList::add_file`] for the supported formats
+    pub fn with_hash_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        self.hashes.add_file(path)?;
+        Ok(self)
+    }
+
+    pub fn mode(&self) -> KnownGoodMode {
+        self.mode
+    }
+
+    /// checks if the file at `path` is known to be good, and counts it if so.
+    /// Files which cannot be hashed are never known-good.
+    pub fn is_known_good(&self, path: &Path) -> bool {
+        match self.hashes.matching_hashes_of_file(path) {
+            Err(why) => {
+                log::warn!(
+                    "unable to check if '{}' is known-good: {}",
+                    path.display(),
+                    why
+                );
+                false
+            }
+            Ok(hashes) => {
+                if hashes.is_empty() {
+                    false
+                } else {
+                    log::trace!("'{}' is known-good", path.display());
+                    self.suppressed.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+            }
+        }
+    }
+
+    /// like [`KnownGoodFilter::is_known_good`], but checks data in memory
+    pub fn is_known_good_buffer(&self, name: &str, data: &[u8]) -> bool {
+        if self.hashes.matching_hashes_of_slice(data).is_empty() {
+            false
+        } else {
+            log::trace!("'{}' is known-good", name);
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        let suppressed = self.suppressed.load(Ordering::Relaxed);
+        match self.mode {
+            KnownGoodMode::Suppress => vec![format!(
+                "suppressed the findings of {} known-good file(s)",
+                suppressed
+            )],
+            KnownGoodMode::Skip => vec![format!("skipped {} known-good file(s)", suppressed)],
+        }
+    }
+}
@@ -1,15 +1,158 @@
+use anyhow::{anyhow, Result};
 use maplit::hashset;
 use serde_json::json;
 use walkdir::DirEntry;
 
 use crate::filescanner::*;
 use crate::csv_line::CsvLine;
+use crate::path_string::{path_to_string, user_friendly_path};
+use crate::string_options::StringOptions;
 use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
 use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 use std::path::Path;
+
+/// a filename which is protected by the Levenshtein scanner
+struct LevenshteinTarget {
+    name: Vec<char>,
+
+    /// overrides the maximum distance of the scanner for this target
+    max_distance: Option<usize>,
+}
+
+impl LevenshteinTarget {
+    /// parses a target in the form `name` or `name:distance`
+    fn parse(target: &str) -> Result<Self> {
+        match target.rsplit_once(':') {
+            Some((name, distance)) => Ok(Self {
+                name: name.chars().collect(),
+                max_distance: Some(distance.parse().map_err(|why| {
+                    anyhow!("invalid distance in Levenshtein target '{}': {}", target, why)
+                })?),
+            }),
+            None => Ok(Self {
+                name: target.chars().collect(),
+                max_distance: None,
+            }),
+        }
+    }
+}
+
+/// describes how a suspicious filename differs from the name of a protected file
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Transformation {
+    /// the names differ only in the case of some characters, e.g. `svcHost.exe`
+    MixedCase,
+
+    /// some characters have been replaced by similar looking characters,
+    /// e.g. `rund1l32.exe` or `expl0rer.exe`
+    Substitution,
+
+    /// two adjacent characters have been swapped, e.g. `scvhost.exe`
+    Transposition,
+
+    /// the names have a small Levenshtein distance
+    EditDistance,
+}
+
+impl Display for Transformation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transformation::MixedCase => write!(f, "mixed case"),
+            Transformation::Substitution => write!(f, "character substitution"),
+            Transformation::Transposition => write!(f, "transposition"),
+            Transformation::EditDistance => write!(f, "edit distance"),
+        }
+    }
+}
+
+/// maps characters which are often used to imitate other characters to the
+/// character they imitate
+fn normalize_char(c: char) -> char {
+    match c.to_ascii_lowercase() {
+        '0' => 'o',
+        '1' | 'i' | '!' | '|' => 'l',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        '8' => 'b',
+        c => c,
+    }
+}
+
+fn is_mixed_case(name: &[char]) -> bool {
+    name.iter().any(|c| c.is_uppercase()) && name.iter().any(|c| c.is_lowercase())
+}
+
+fn is_transposition(a: &[char], b: &[char]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let differences: Vec<usize> = (0..a.len()).filter(|&i| a[i] != b[i]).collect();
+    matches!(differences[..], [i, j] if j == i + 1 && a[i] == b[j] && a[j] == b[i])
+}
+
+/// checks how `name` differs from `target`. Known tricks (mixed case, substitution
+/// of similar looking characters and transposition) are always reported, other
+/// differences only if the Levenshtein distance is not more than `max_distance`.
+/// Returns the transformation and the Levenshtein distance of the lowercase names.
+fn classify(name: &[char], target: &[char], max_distance: usize) -> Option<(Transformation, usize)> {
+    if name == target {
+        return None;
+    }
+
+    let lower_name: Vec<char> = name.iter().flat_map(|c| c.to_lowercase()).collect();
+    let lower_target: Vec<char> = target.iter().flat_map(|c| c.to_lowercase()).collect();
+    if lower_name == lower_target {
+        return is_mixed_case(name).then_some((Transformation::MixedCase, 0));
+    }
+
+    let distance = || levenshtein_distance(&lower_name, &lower_target, usize::MAX).unwrap_or(0);
+    if lower_name.len() == lower_target.len()
+        && lower_name
+            .iter()
+            .zip(lower_target.iter())
+            .all(|(a, b)| normalize_char(*a) == normalize_char(*b))
+    {
+        return Some((Transformation::Substitution, distance()));
+    }
+
+    if is_transposition(&lower_name, &lower_target) {
+        return Some((Transformation::Transposition, distance()));
+    }
+
+    levenshtein_distance(&lower_name, &lower_target, max_distance)
+        .map(|d| (Transformation::EditDistance, d))
+}
+
+/// checks if a file named `file_name` exists in the directory of `file`. On
+/// case-insensitive file systems, a name which differs only in case from the name of
+/// `file` resolves to `file` itself, so that the exact name is looked up in the
+/// directory listing for mixed case names
+fn original_exists(file: &Path, file_name: &str, transformation: Transformation) -> bool {
+    if transformation != Transformation::MixedCase {
+        return file.with_file_name(file_name).exists();
+    }
+    file.parent()
+        .and_then(|directory| std::fs::read_dir(directory).ok())
+        .map_or(false, |mut entries| {
+            entries.any(|entry| {
+                entry.map_or(false, |entry| entry.file_name() == OsStr::new(file_name))
+            })
+        })
+}
+
 pub struct LevenshteinScanner {
-    wellknown_files: Vec<Vec<char>>
+    wellknown_files: Vec<LevenshteinTarget>,
+    max_distance: usize,
+
+    /// paths of legitimate files which must not be reported (in lowercase)
+    allowed_paths: HashSet<String>,
 }
 
 impl Default for LevenshteinScanner {
@@ -24,9 +167,17 @@ impl Default for LevenshteinScanner {
             "firefox.exe",
             "winlogon.exe"
         ];
-        let wellknown_files = WELLKNOWN_FILES.iter().map(|s| s.chars().collect()).collect();
+        let wellknown_files = WELLKNOWN_FILES
+            .iter()
+            .map(|s| LevenshteinTarget {
+                name: s.chars().collect(),
+                max_distance: None,
+            })
+            .collect();
         Self {
-            wellknown_files
+            wellknown_files,
+            max_distance: 1,
+            allowed_paths: HashSet::new(),
         }
     }
 }
@@ -35,6 +186,11 @@ impl FileScanner for LevenshteinScanner {
     fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
         self.intern_scan_file(file.path())
     }
+
+    /// the name of the file is compared with the names of system files
+    fn depends_on_path(&self) -> bool {
+        true
+    }
 }
 
 impl Display for LevenshteinScanner {
@@ -44,20 +200,80 @@ impl Display for LevenshteinScanner {
 }
 
 impl LevenshteinScanner {
+    /// creates a scanner which protects `targets` instead of the builtin list of
+    /// well-known filenames. Every target can override the maximum distance
+    /// by using the form `name:distance`
+    pub fn new(targets: &[String]) -> Result<Self> {
+        Ok(Self {
+            wellknown_files: targets
+                .iter()
+                .map(|t| LevenshteinTarget::parse(t))
+                .collect::<Result<Vec<_>>>()?,
+            max_distance: 1,
+            allowed_paths: HashSet::new(),
+        })
+    }
+
+    /// reads additional filenames to protect from a file, which contains one filename
+    /// per line (optionally in the form `name:distance`). Empty lines and lines
+    /// starting with '#' are ignored
+    pub fn with_targets_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        for (line_no, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.wellknown_files.push(
+                LevenshteinTarget::parse(line)
+                    .map_err(|why| anyhow!("{}:{}: {}", path.display(), line_no + 1, why))?,
+            );
+        }
+        Ok(self)
+    }
+
+    /// sets the maximum Levenshtein distance for all targets which
+    /// do not specify their own distance
+    pub fn with_max_distance(mut self, max_distance: usize) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    /// never report the files at `paths`, because they are known to be legitimate.
+    /// Paths are compared case-insensitively
+    pub fn with_allowed_paths(mut self, paths: &[String]) -> Self {
+        self.allowed_paths.extend(paths.iter().map(|p| p.to_lowercase()));
+        self
+    }
+
     fn intern_scan_file(&self, file: &Path) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {        
+        if self.allowed_paths.contains(&user_friendly_path(file).display().to_string().to_lowercase()) {
+            return vec![];
+        }
+
         match file.file_name() {
             None => vec![],
             Some(file_name) => match file_name.to_str() {
                 Some(os_fn) => {
-                    let res:  Vec<anyhow::Result<Box<dyn ScannerFinding>>> = self.wellknown_files
+                    let os_fn: Vec<char> = os_fn.chars().collect();
+                    self.wellknown_files
                         .iter()
-                        .filter(|l| has_levenshtein_distance_one(&os_fn.chars().collect(), l))
-                        .map(|l| Ok(Box::new(LevenshteinScannerFinding{file_name: l.iter().collect(),  found_in_file: file.display().to_string()}) as Box<dyn ScannerFinding>))
-                        .collect();
-                    if file_name == "expl0rer.exe" {
-                        assert_eq!(res.len(), 1);
-                    }
-                    res
+                        .filter_map(|l| {
+                            let max_distance = l.max_distance.unwrap_or(self.max_distance);
+                            classify(&os_fn, &l.name, max_distance).map(|(t, d)| (l, t, d))
+                        })
+                        .map(|(l, transformation, distance)| {
+                            let file_name: String = l.name.iter().collect();
+
+                            // a similar file next to the original file is much more suspicious
+                            let original_in_same_directory =
+                                original_exists(file, &file_name, transformation);
+
+                            Ok(Box::new(LevenshteinScannerFinding{file_name, transformation, distance, original_in_same_directory, found_in_file: path_to_string(file)}) as Box<dyn ScannerFinding>)
+                        })
+                        .collect()
                 }
                 None => vec![]
             }
@@ -68,6 +284,9 @@ impl LevenshteinScanner {
 
 struct LevenshteinScannerFinding {
     file_name: String,
+    transformation: Transformation,
+    distance: usize,
+    original_in_same_directory: bool,
     found_in_file: String,
 }
 
@@ -75,28 +294,65 @@ impl Display for LevenshteinScannerFinding {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let found_in_file = self.found_in_file();
         let filename = &self.file_name;
-        writeln!(f, "the name of the file {found_in_file} is very similar to {filename}")
+        let distance = self.distance;
+        let transformation = self.transformation;
+        if self.original_in_same_directory {
+            writeln!(f, "the name of the file {found_in_file} is very similar to {filename} ({transformation}, distance {distance}), which exists in the same directory")
+        } else {
+            writeln!(f, "the name of the file {found_in_file} is very similar to {filename} ({transformation}, distance {distance})")
+        }
     }
 }
 
 impl ScannerFinding for LevenshteinScannerFinding {
-    fn format_csv(&self) -> HashSet<CsvLine> {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
         let file = self.found_in_file();
-        hashset![CsvLine::new("Levenshtein", &self.file_name, file, String::new())]
+        let details = if self.original_in_same_directory {
+            format!("{}, distance {}, original file exists in the same directory", self.transformation, self.distance)
+        } else {
+            format!("{}, distance {}", self.transformation, self.distance)
+        };
+        hashset![CsvLine::new("Levenshtein", &self.file_name, file, details)]
     }
 
-    fn to_json(&self) -> serde_json::Value {
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
         let file = self.found_in_file();
         json!({
             "01_scanner": "levenshtein",
             "02_suspicious_file": file,
-            "03_original_name": self.file_name
+            "03_original_name": self.file_name,
+            "04_distance": self.distance,
+            "05_original_in_same_directory": self.original_in_same_directory,
+            "06_transformation": self.transformation.to_string()
         })
     }
 
+    /// names with imitated characters are a deliberate deception, while mixed case
+    /// and small edit distances also happen by chance. A finding is more severe if
+    /// the original file exists in the same directory
+    fn severity(&self) -> Severity {
+        match (self.transformation, self.original_in_same_directory) {
+            (Transformation::Substitution | Transformation::Transposition, true) => Severity::High,
+            (Transformation::Substitution | Transformation::Transposition, false) => {
+                Severity::Medium
+            }
+            (Transformation::MixedCase | Transformation::EditDistance, true) => Severity::Medium,
+            (Transformation::MixedCase | Transformation::EditDistance, false) => Severity::Low,
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "levenshtein".to_owned()
+    }
+
     fn found_in_file(&self) -> &str {
         &self.found_in_file[..]
     }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        // Masquerading
+        vec!["T1036".to_owned()]
+    }
 }
 
 /**
@@ -109,34 +365,31 @@ impl ScannerFinding for LevenshteinScannerFinding {
  *
  * Copyright (c) 2016 Titus Wormer <tituswormer@gmail.com>
  */
-pub fn has_levenshtein_distance_one(a: &Vec<char>, b: &Vec<char>) -> bool {
+/// computes the Levenshtein distance of `a` and `b`, and returns it if the names
+/// are not equal and their distance is not more than `max_distance`
+pub fn levenshtein_distance(a: &[char], b: &[char], max_distance: usize) -> Option<usize> {
     let mut result = 0;
-    let dist = 1;
 
     /* Shortcut optimizations / degenerate cases. */
     if a == b {
-        return false;
+        return None;
     }
 
     let length_a = a.len();
     let length_b = b.len();
 
-    if length_a == 0 {
-        return length_b == dist;
+    // if both string lengths differ more than `max_distance`, their
+    // Levenshtein distance must be more than `max_distance`
+    if length_a.abs_diff(length_b) > max_distance {
+        return None;
     }
 
-    if length_b == 0 {
-        return length_a == dist;
+    if length_a == 0 {
+        return Some(length_b);
     }
 
-    // if both string lengths differ more than 1, their
-    // Levenshtein distance must be more than 1
-    if length_a > length_b {
-        if length_a - length_b > 1 {
-            return false;
-        }
-    } else if length_b - length_a > 1 {
-        return false;
+    if length_b == 0 {
+        return Some(length_a);
     }
 
     /* Initialize the vector.
@@ -177,14 +430,18 @@ pub fn has_levenshtein_distance_one(a: &Vec<char>, b: &Vec<char>) -> bool {
         }
     }
 
-    result == dist
+    (result <= max_distance).then_some(result)
 }
 
 
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
-    use super::LevenshteinScanner;
+
+    use super::{classify, LevenshteinScanner, Transformation};
+    use crate::scanner_result::ScannerFinding;
+    use crate::severity::Severity;
+    use crate::string_options::StringOptions;
 
     #[test]
     fn test_equal() {
@@ -195,42 +452,110 @@ mod tests {
         assert!(results.is_empty(), "invalid result for {}", filename);
     }
 
+    /// the transformations of all findings of `sample`, which is placed in the
+    /// directory of the crate
+    fn transformations(scanner: &LevenshteinScanner, sample: &str) -> Vec<String> {
+        let filename = env!("CARGO_MANIFEST_DIR").to_owned() + "/" + sample;
+        scanner
+            .intern_scan_file(&PathBuf::from(&filename))
+            .into_iter()
+            .map(|result| {
+                let finding = result.expect("error in scan_result");
+                assert_eq!(finding.found_in_file(), filename);
+                let json = finding.to_json(&StringOptions::default());
+                json["06_transformation"].as_str().unwrap().to_owned()
+            })
+            .collect()
+    }
+
     #[test]
     fn test_distance_one() {
-        let samples = vec![
-            "expl0rer.exe",
-            "explor3r.exe",
-            "3xplorer.exe"
+        let samples = [
+            ("expl0rer.exe", "character substitution"),
+            ("explor3r.exe", "character substitution"),
+            ("3xplorer.exe", "character substitution"),
+            ("explorrer.exe", "edit distance"),
         ];
         let scanner = LevenshteinScanner::default();
-        for sample_fn in samples {
-            let filename = env!("CARGO_MANIFEST_DIR").to_owned() + sample_fn;
-            let sample = PathBuf::from(&filename);
-            let results = scanner.intern_scan_file(&sample);
-            match results.last() {
-                None => assert!(results.is_empty(), "invalid result for {}", filename),
-                Some(result) => match result {
-                    Err(why) => panic!("error in scan_result: {:?}", why),
-                    Ok(_) => ()
-                }
-            }
+        for (sample, expected) in samples {
+            assert_eq!(transformations(&scanner, sample), vec![expected], "{}", sample);
         }
     }
 
+    #[test]
+    fn test_custom_targets() {
+        let scanner = LevenshteinScanner::new(&["myapp.exe".to_owned()]).unwrap();
+        assert_eq!(scanner.intern_scan_file(&PathBuf::from("/tmp/myapq.exe")).len(), 1);
+        assert!(scanner.intern_scan_file(&PathBuf::from("/tmp/expl0rer.exe")).is_empty());
+    }
+
+    #[test]
+    fn test_per_target_distance() {
+        let scanner = LevenshteinScanner::new(&["lsass.exe".to_owned(), "winlogon.exe:2".to_owned()])
+            .unwrap();
+        assert_eq!(scanner.intern_scan_file(&PathBuf::from("/tmp/w1nl0gon.exe")).len(), 1);
+        assert!(scanner.intern_scan_file(&PathBuf::from("/tmp/lxaxs.exe")).is_empty());
+    }
 
     #[test]
-    fn test_distance_more_than_one() {
-        let samples = vec![
-            "3xpl0rer.exe",
-            "expl0r3r.exe",
-            "3xpl0rer.exe"
+    fn test_transformations() {
+        let target: Vec<char> = "rundll32.exe".chars().collect();
+        let samples = [
+            ("rund1l32.exe", Some(Transformation::Substitution)),
+            ("rurdll32.exe", Some(Transformation::EditDistance)),
+            ("rnudll32.exe", Some(Transformation::Transposition)),
+            ("RunDll32.exe", Some(Transformation::MixedCase)),
+            ("RUNDLL32.EXE", None),
+            ("rundll32.exe", None),
         ];
+        for (name, expected) in samples {
+            let name: Vec<char> = name.chars().collect();
+            assert_eq!(classify(&name, &target, 1).map(|(t, _)| t), expected);
+        }
+    }
+
+    #[test]
+    fn test_distance_more_than_one() {
         let scanner = LevenshteinScanner::default();
-        for sample_fn in samples {
-            let filename = env!("CARGO_MANIFEST_DIR").to_owned() + sample_fn;
-            let sample = PathBuf::from(&filename);
-            let results = scanner.intern_scan_file(&sample);
-            assert!(results.is_empty(), "invalid result for {}", filename);
+        for sample in ["exxplorrer.exe", "explorer2.exe.bak", "xplrer.exe"] {
+            assert!(transformations(&scanner, sample).is_empty(), "{}", sample);
         }
+
+        // substitutions are reported regardless of the distance
+        assert_eq!(
+            transformations(&scanner, "3xpl0r3r.exe"),
+            vec!["character substitution"]
+        );
+    }
+
+    #[test]
+    fn test_original_in_same_directory() {
+        let dir = std::env::temp_dir().join(format!("dionysos-levenshtein-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let scanner = LevenshteinScanner::default();
+        let findings = |name: &str| {
+            let file = dir.join(name);
+            std::fs::write(&file, b"").unwrap();
+            scanner
+                .intern_scan_file(&file)
+                .into_iter()
+                .map(|result| {
+                    let finding = result.unwrap();
+                    let json = finding.to_json(&StringOptions::default());
+                    (json["05_original_in_same_directory"].clone(), finding.severity())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // on case-insensitive file systems, the name would resolve to the file itself
+        let mixed_case = findings("RunDll32.exe");
+        let substitution = findings("rund1l32.exe");
+        std::fs::write(dir.join("rundll32.exe"), b"").unwrap();
+        let next_to_original = findings("rund1l32.exe");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mixed_case, vec![(false.into(), Severity::Low)]);
+        assert_eq!(substitution, vec![(false.into(), Severity::Medium)]);
+        assert_eq!(next_to_original, vec![(true.into(), Severity::High)]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,126 @@
+//! dionysos scans files for indicators of compromise, e.g. with yara rules,
+//! hashes or filename patterns. Besides the command line tool, the scanning
+//! engine can be embedded into other applications using [`ScanJob`]:
+//!
+//! ```no_run
+//! use dionysos_lib::ScanJob;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let summary = ScanJob::new("/mnt/evidence")
+//!     .with_yara("/opt/rules")?
+//!     .with_hashes(&["d41d8cd98f00b204e9800998ecf8427e".to_owned()])?
+//!     .run(|result| {
+//!         for finding in result.findings() {
+//!             println!("{}: {}", result.filename(), finding);
+//!         }
+//!     })?;
+//! println!("scanned {} file(s)", summary.files());
+//! # Ok(())
+//! # }
+//! ```
+
+mod filescanner;
+mod dionysos;
+mod scan_job;
+mod file_timeout;
+mod special_files;
+mod compressed_files;
+mod scan_errors;
+mod path_string;
+mod file_hash;
+mod worker_watchdog;
+mod file_provider;
+mod disk_image;
+mod s3;
+mod yara;
+mod filename_scanner;
+mod scanner_result;
+mod scanner_kind;
+mod levenshtein_scanner;
+mod masquerading_scanner;
+mod ransomware_scanner;
+mod permission_scanner;
+mod directory_cache;
+mod system_location;
+#[cfg(test)]
+mod test_executables;
+mod authenticode_scanner;
+mod executable_scanner;
+mod entropy_scanner;
+mod extension_scanner;
+mod pe_anomaly_scanner;
+mod elf_anomaly_scanner;
+mod macho_anomaly_scanner;
+#[cfg(feature = "scan_reg")]
+mod registry_ioc;
+mod hash_scanner;
+#[cfg(feature = "fuzzy_hash")]
+mod fuzzy_hash_scanner;
+mod tlsh_scanner;
+mod hash_list;
+mod inventory;
+mod plugin;
+mod progress_json;
+mod pe_file;
+mod ordlookup;
+mod authenticode;
+mod elf_file;
+mod macho_file;
+mod known_good;
+mod csv_line;
+mod string_options;
+mod severity;
+mod attack;
+mod time_window;
+mod throttle;
+mod config_file;
+mod audit;
+mod export;
+mod collect;
+mod targets;
+mod dedup;
+mod estimate;
+mod threat_intel;
+mod findings_policy;
+#[cfg(feature = "hash_lookup")]
+mod hash_lookup;
+mod result_diff;
+mod report;
+mod output_route;
+mod mail;
+mod interrupt;
+mod systemd;
+mod telemetry;
+mod update;
+mod ffi;
+#[cfg(windows)]
+mod live_eventlog;
+#[cfg(windows)]
+mod service;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(any(feature = "grpc", feature = "http"))]
+mod scan_service;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "grpc")]
+mod controller;
+#[cfg(feature = "grpc")]
+mod agent;
+#[cfg(feature = "http")]
+mod http_server;
+
+pub use crate::dionysos::{Dionysos, ScanStatus, EXIT_FATAL};
+pub use csv_line::CsvLine;
+pub use disk_image::DiskImageProvider;
+pub use file_provider::{
+    FileListProvider, FileProvider, ProvidedFile, WalkDirProvider, ZipArchiveProvider,
+};
+pub use filescanner::FileScanner;
+pub use scan_job::{ScanCancelled, ScanHandle, ScanJob, ScanResults, ScanSummary};
+pub use path_string::path_to_string;
+pub use s3::S3Provider;
+pub use scanner_result::{ScannerFinding, ScannerResult};
+pub use severity::Severity;
+pub use string_options::{StringEncoding, StringOptions};
+pub use walkdir::DirEntry;
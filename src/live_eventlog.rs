@@ -0,0 +1,102 @@
+use anyhow::{anyhow, Result};
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{GetLastError, ERROR_INSUFFICIENT_BUFFER, ERROR_NO_MORE_ITEMS};
+use windows::Win32::System::EventLog::{
+    EvtClose, EvtNext, EvtQuery, EvtQueryChannelPath, EvtQueryForwardDirection, EvtRender,
+    EvtRenderEventXml, EVT_HANDLE,
+};
+
+/// number of events which are fetched at once
+const BATCH_SIZE: usize = 64;
+
+/// closes an event log handle when it is dropped
+struct EventHandle(EVT_HANDLE);
+
+impl Drop for EventHandle {
+    fn drop(&mut self) {
+        unsafe {
+            EvtClose(self.0);
+        }
+    }
+}
+
+/// reads all events of a channel of the live event log (e.g. `Security`) using
+/// the Windows Event Log API, and calls `handler` with the XML representation
+/// of every event
+pub fn read_channel<F>(channel: &str, mut handler: F) -> Result<usize>
+where
+    F: FnMut(&str),
+{
+    let query = unsafe {
+        EvtQuery(
+            EVT_HANDLE::default(),
+            &HSTRING::from(channel),
+            &HSTRING::from("*"),
+            EvtQueryChannelPath.0 | EvtQueryForwardDirection.0,
+        )
+    }
+    .map_err(|why| anyhow!("unable to query the event log channel '{}': {}", channel, why))?;
+    let query = EventHandle(query);
+
+    let mut count = 0;
+    let mut events = [0isize; BATCH_SIZE];
+    loop {
+        let mut returned = 0;
+        if !unsafe { EvtNext(query.0, &mut events, u32::MAX, 0, &mut returned) }.as_bool() {
+            match unsafe { GetLastError() } {
+                ERROR_NO_MORE_ITEMS => break,
+                error => {
+                    return Err(anyhow!(
+                        "unable to read events from '{}': error {}",
+                        channel,
+                        error.0
+                    ))
+                }
+            }
+        }
+
+        for event in events[..returned as usize].iter() {
+            let event = EventHandle(EVT_HANDLE(*event));
+            match render_xml(&event) {
+                Ok(xml) => handler(&xml),
+                Err(why) => log::warn!("unable to render an event of '{}': {}", channel, why),
+            }
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn render_xml(event: &EventHandle) -> Result<String> {
+    let mut buffer: Vec<u16> = Vec::new();
+    let mut used = 0;
+    let mut property_count = 0;
+    loop {
+        let success = unsafe {
+            EvtRender(
+                EVT_HANDLE::default(),
+                event.0,
+                EvtRenderEventXml.0,
+                (buffer.len() * 2) as u32,
+                Some(buffer.as_mut_ptr() as *mut _),
+                &mut used,
+                &mut property_count,
+            )
+        };
+        if success.as_bool() {
+            let chars = (used as usize / 2).saturating_sub(1);
+            return Ok(String::from_utf16_lossy(&buffer[..chars]));
+        }
+        match unsafe { GetLastError() } {
+            ERROR_INSUFFICIENT_BUFFER => buffer.resize(used as usize / 2 + 1, 0),
+            error => return Err(anyhow!("EvtRender failed with error {}", error.0)),
+        }
+    }
+}
+
+/// extracts the record id of an event from its XML representation
+pub fn record_id(xml: &str) -> Option<&str> {
+    let start = xml.find("<EventRecordID>")? + "<EventRecordID>".len();
+    let end = start + xml[start..].find('<')?;
+    Some(&xml[start..end])
+}
@@ -0,0 +1,512 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+
+use maplit::hashset;
+use serde_json::json;
+
+use crate::authenticode::SignedData;
+use crate::csv_line::CsvLine;
+use crate::executable_scanner::{Executable, ExecutableCheck};
+use crate::macho_file::MachOFile;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade_0cc0;
+const CSMAGIC_CODEDIRECTORY: u32 = 0xfade_0c02;
+const CSMAGIC_BLOBWRAPPER: u32 = 0xfade_0b01;
+const CSSLOT_CODEDIRECTORY: u32 = 0;
+const CSSLOT_SIGNATURESLOT: u32 = 0x10000;
+const CS_ADHOC: u32 = 0x0000_0002;
+const CS_LINKER_SIGNED: u32 = 0x0002_0000;
+
+/// the first version of the code directory which contains a team identifier
+const CS_SUPPORTSTEAMID: u32 = 0x20200;
+
+/// the number of blobs in a code signature which are read at most
+const MAX_BLOBS: usize = 64;
+
+/// the common names of the certificates which Apple signs its own code with
+const APPLE_SIGNERS: &[&str] = &["Software Signing", "Apple Mac OS Application Signing"];
+
+/// the dynamic loader of macOS, which is the only valid `LC_LOAD_DYLINKER`
+const DYLD: &str = "/usr/lib/dyld";
+
+/// directories which are writable by users, and from which no library should be
+/// loaded by an installed program
+const SUSPICIOUS_DIRECTORIES: &[&str] = &[
+    "/tmp/",
+    "/private/tmp/",
+    "/var/tmp/",
+    "/private/var/tmp/",
+    "/var/folders/",
+    "/private/var/folders/",
+    "/Users/",
+    "/Volumes/",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    /// there is no `LC_CODE_SIGNATURE` command
+    Unsigned,
+
+    /// the code signature is malformed
+    Invalid,
+
+    /// the code directory is not signed by a certificate
+    AdHoc,
+
+    /// an ad-hoc signature, which has been created by the linker. The linker signs
+    /// every binary for Apple silicon like this
+    LinkerSigned,
+
+    /// the code directory is signed by a certificate. The signature itself is not
+    /// verified, this requires the certificates of Apple
+    Signed,
+}
+
+impl State {
+    fn name(&self) -> &'static str {
+        match self {
+            State::Unsigned => "unsigned",
+            State::Invalid => "invalid",
+            State::AdHoc => "ad-hoc signed",
+            State::LinkerSigned => "ad-hoc signed by the linker",
+            State::Signed => "signed",
+        }
+    }
+}
+
+/// the code signature of a Mach-O file
+struct Signature {
+    state: State,
+
+    /// the identifier in the code directory, like `com.apple.ls`
+    identifier: Option<String>,
+
+    /// the team identifier in the code directory, like `EQHXZ8M8AV`
+    team: Option<String>,
+
+    /// the common name of the signer, like `Developer ID Application: Contoso Ltd`
+    signer: Option<String>,
+}
+
+impl Signature {
+    fn with_state(state: State) -> Self {
+        Self {
+            state,
+            identifier: None,
+            team: None,
+            signer: None,
+        }
+    }
+
+    /// parses the superblob of an `LC_CODE_SIGNATURE` command
+    fn parse(blob: Option<&[u8]>) -> Self {
+        match blob {
+            Some(blob) => {
+                Self::parse_superblob(blob).unwrap_or_else(|| Self::with_state(State::Invalid))
+            }
+            None => Self::with_state(State::Unsigned),
+        }
+    }
+
+    fn parse_superblob(blob: &[u8]) -> Option<Self> {
+        if read_u32(blob, 0)? != CSMAGIC_EMBEDDED_SIGNATURE {
+            return None;
+        }
+        let count = read_u32(blob, 8)? as usize;
+        let mut code_directory = None;
+        let mut cms = None;
+        for index in 0..count.min(MAX_BLOBS) {
+            let slot = read_u32(blob, 12 + index * 8)?;
+            let offset = read_u32(blob, 16 + index * 8)? as usize;
+            let content = blob.get(offset..)?;
+            let magic = read_u32(content, 0)?;
+            let content = content.get(..read_u32(content, 4)? as usize)?;
+            match (slot, magic) {
+                (CSSLOT_CODEDIRECTORY, CSMAGIC_CODEDIRECTORY) => code_directory = Some(content),
+                (CSSLOT_SIGNATURESLOT, CSMAGIC_BLOBWRAPPER) => cms = content.get(8..),
+                _ => (),
+            }
+        }
+
+        let code_directory = code_directory?;
+        let version = read_u32(code_directory, 8)?;
+        let flags = read_u32(code_directory, 12)?;
+        let identifier = read_string(code_directory, read_u32(code_directory, 20)? as usize);
+        let team = match read_u32(code_directory, 48) {
+            Some(offset) if version >= CS_SUPPORTSTEAMID && offset != 0 => {
+                read_string(code_directory, offset as usize)
+            }
+            _ => None,
+        };
+        let cms = cms.filter(|cms| !cms.is_empty());
+        let state = if flags & CS_LINKER_SIGNED != 0 {
+            State::LinkerSigned
+        } else if flags & CS_ADHOC != 0 || cms.is_none() {
+            State::AdHoc
+        } else {
+            State::Signed
+        };
+        Some(Self {
+            state,
+            identifier,
+            team,
+            signer: cms
+                .and_then(SignedData::parse)
+                .and_then(|signed_data| signed_data.signer_name()),
+        })
+    }
+
+    /// checks if the signature may sign a kernel extension: it must be signed by a
+    /// certificate of Apple, or by a "Developer ID" certificate of a team. The
+    /// certificates are not verified, but a kext which is signed with a self-made
+    /// certificate is not accepted
+    fn may_sign_kext(&self) -> bool {
+        let signer = match (&self.state, &self.signer) {
+            (State::Signed, Some(signer)) => signer,
+            _ => return false,
+        };
+        APPLE_SIGNERS.contains(&signer.as_str())
+            || (self.team.is_some() && signer.starts_with("Developer ID"))
+    }
+
+    fn details(&self) -> String {
+        let mut details = vec![self.state.name().to_owned()];
+        if let Some(identifier) = &self.identifier {
+            details.push(format!("identifier {}", identifier));
+        }
+        if let Some(team) = &self.team {
+            details.push(format!("team {}", team));
+        }
+        if let Some(signer) = &self.signer {
+            details.push(format!("signed by {}", signer));
+        }
+        details.join(", ")
+    }
+}
+
+/// reads a big endian `u32`, which is used by code signatures
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// reads a string which ends with a NUL byte
+fn read_string(data: &[u8], offset: usize) -> Option<String> {
+    let data = data.get(offset..)?;
+    let length = data.iter().position(|c| *c == 0)?;
+    Some(String::from_utf8_lossy(&data[..length]).to_string())
+}
+
+/// checks if the path of the load command `command` is unusual: the dynamic loader
+/// must be dyld, libraries must not be loaded from directories which are writable by
+/// users or from hidden directories, and relative paths must be relative to
+/// `@executable_path`, `@loader_path` or `@rpath`
+fn is_unusual_path(command: &str, path: &str) -> bool {
+    match command {
+        "LC_DYLD_ENVIRONMENT" => true,
+        "LC_LOAD_DYLINKER" => path != DYLD,
+        _ => {
+            !(path.starts_with('/') || path.starts_with('@'))
+                || SUSPICIOUS_DIRECTORIES
+                    .iter()
+                    .any(|directory| path.starts_with(directory))
+                || path.split('/').any(|component| {
+                    component.starts_with('.') && component != "." && component != ".."
+                })
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Anomaly {
+    /// an ad-hoc signature, which is not signed by a certificate
+    AdHocSignature { linker_signed: bool },
+
+    /// a malformed code signature
+    InvalidSignature,
+
+    /// a kernel extension which is not signed by a certificate of Apple or by a
+    /// "Developer ID" certificate
+    UnsignedKext,
+
+    /// a load command with an unusual path
+    UnusualLoadCommand,
+}
+
+impl Anomaly {
+    fn name(&self) -> &'static str {
+        match self {
+            Anomaly::AdHocSignature { .. } => "adhoc-signature",
+            Anomaly::InvalidSignature => "invalid-signature",
+            Anomaly::UnsignedKext => "unsigned-kext",
+            Anomaly::UnusualLoadCommand => "unusual-load-command",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Anomaly::AdHocSignature { .. } => "ad-hoc code signature",
+            Anomaly::InvalidSignature => "invalid code signature",
+            Anomaly::UnsignedKext => "kernel extension without valid signature",
+            Anomaly::UnusualLoadCommand => "unusual load commands",
+        }
+    }
+}
+
+/// the anomalies of one architecture of a Mach-O file, and the details of every anomaly
+fn anomalies(macho: &MachOFile) -> Vec<(Anomaly, String)> {
+    let mut anomalies = Vec::new();
+
+    let signature = Signature::parse(macho.code_signature());
+    match signature.state {
+        State::Invalid => anomalies.push((Anomaly::InvalidSignature, signature.details())),
+        _ if macho.is_kext() && !signature.may_sign_kext() => {
+            anomalies.push((Anomaly::UnsignedKext, signature.details()))
+        }
+        State::Signed | State::Unsigned => (),
+        State::AdHoc | State::LinkerSigned => anomalies.push((
+            Anomaly::AdHocSignature {
+                linker_signed: signature.state == State::LinkerSigned,
+            },
+            signature.details(),
+        )),
+    }
+
+    let unusual_paths: Vec<_> = macho
+        .paths()
+        .into_iter()
+        .filter(|(command, path)| is_unusual_path(command, path))
+        .map(|(command, path)| format!("{} {}", command, path))
+        .collect();
+    if !unusual_paths.is_empty() {
+        anomalies.push((Anomaly::UnusualLoadCommand, unusual_paths.join(", ")));
+    }
+
+    anomalies
+}
+
+/// reports Mach-O files with ad-hoc or invalid code signatures, kernel extensions
+/// which are not signed by Apple or a "Developer ID" certificate, and load commands
+/// with unusual paths.
+/// Code signatures are not verified, this is only possible on macOS
+#[derive(Default)]
+pub struct MachOAnomalyScanner {}
+
+impl Display for MachOAnomalyScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "MachOAnomalyScanner")
+    }
+}
+
+impl ExecutableCheck for MachOAnomalyScanner {
+    fn check(
+        &self,
+        found_in_file: &str,
+        _path: Option<&Path>,
+        _data: &[u8],
+        executable: Option<&Executable>,
+    ) -> Vec<Box<dyn ScannerFinding>> {
+        let files = match executable {
+            Some(Executable::MachO(files)) => files,
+            _ => return Vec::new(),
+        };
+
+        // the architectures of fat binaries usually have the same anomalies
+        let mut findings: Vec<MachOAnomalyFinding> = Vec::new();
+        for macho in files.iter() {
+            for (anomaly, details) in anomalies(macho) {
+                match findings
+                    .iter_mut()
+                    .find(|finding| finding.anomaly == anomaly && finding.details == details)
+                {
+                    Some(finding) => finding.architectures.push(macho.architecture()),
+                    None => findings.push(MachOAnomalyFinding {
+                        found_in_file: found_in_file.to_owned(),
+                        anomaly,
+                        details,
+                        architectures: vec![macho.architecture()],
+                    }),
+                }
+            }
+        }
+        findings
+            .into_iter()
+            .map(|finding| Box::new(finding) as Box<dyn ScannerFinding>)
+            .collect()
+    }
+}
+
+struct MachOAnomalyFinding {
+    found_in_file: String,
+    anomaly: Anomaly,
+    details: String,
+
+    /// the architectures which have this anomaly
+    architectures: Vec<&'static str>,
+}
+
+impl MachOAnomalyFinding {
+    fn details(&self) -> String {
+        format!(
+            "{}: {} ({})",
+            self.anomaly.description(),
+            self.details,
+            self.architectures.join(", ")
+        )
+    }
+}
+
+impl Display for MachOAnomalyFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "Mach-O anomaly: {found_in_file} ({details})")
+    }
+}
+
+impl ScannerFinding for MachOAnomalyFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "MachOAnomaly",
+            self.anomaly.name(),
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "macho_anomalies",
+            "02_suspicious_file": self.found_in_file(),
+            "03_anomaly": self.anomaly.name(),
+            "04_details": self.details,
+            "05_architectures": self.architectures
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        match self.anomaly {
+            Anomaly::InvalidSignature | Anomaly::UnsignedKext => Severity::High,
+            Anomaly::AdHocSignature {
+                linker_signed: false,
+            }
+            | Anomaly::UnusualLoadCommand => Severity::Medium,
+            Anomaly::AdHocSignature {
+                linker_signed: true,
+            } => Severity::Low,
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "macho_anomalies".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    fn rule(&self) -> Option<&str> {
+        Some(self.anomaly.name())
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        match self.anomaly {
+            // Subvert Trust Controls: Code Signing
+            Anomaly::AdHocSignature { .. } | Anomaly::InvalidSignature => {
+                vec!["T1553.002".to_owned()]
+            }
+            // Boot or Logon Autostart Execution: Kernel Modules and Extensions
+            Anomaly::UnsignedKext => vec!["T1547.006".to_owned()],
+            // Hijack Execution Flow: Dylib Hijacking
+            Anomaly::UnusualLoadCommand => vec!["T1574.004".to_owned()],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_unusual_path, Signature, State, CSMAGIC_CODEDIRECTORY, CS_ADHOC};
+
+    #[test]
+    fn test_signature() {
+        let mut code_directory = Vec::new();
+        code_directory.extend(CSMAGIC_CODEDIRECTORY.to_be_bytes());
+        code_directory.extend(76u32.to_be_bytes());
+        code_directory.extend(0x20400u32.to_be_bytes());
+        code_directory.extend(CS_ADHOC.to_be_bytes());
+        code_directory.extend(0u32.to_be_bytes());
+        code_directory.extend(52u32.to_be_bytes());
+        code_directory.extend([0; 24]);
+        code_directory.extend(0u32.to_be_bytes());
+        code_directory.extend(b"com.example.tool\0\0\0\0\0\0\0\0");
+
+        let mut superblob = Vec::new();
+        superblob.extend(0xfade_0cc0u32.to_be_bytes());
+        superblob.extend((20 + code_directory.len() as u32).to_be_bytes());
+        superblob.extend(1u32.to_be_bytes());
+        superblob.extend(0u32.to_be_bytes());
+        superblob.extend(20u32.to_be_bytes());
+        superblob.extend(&code_directory);
+
+        let signature = Signature::parse(Some(&superblob[..]));
+        assert_eq!(signature.state, State::AdHoc);
+        assert_eq!(signature.identifier.as_deref(), Some("com.example.tool"));
+        assert_eq!(signature.team, None);
+        assert_eq!(Signature::parse(None).state, State::Unsigned);
+        assert_eq!(
+            Signature::parse(Some(&superblob[..30])).state,
+            State::Invalid
+        );
+    }
+
+    #[test]
+    fn test_kext_signer() {
+        let signature = |state, team: Option<&str>, signer: Option<&str>| Signature {
+            state,
+            identifier: None,
+            team: team.map(str::to_owned),
+            signer: signer.map(str::to_owned),
+        };
+        let developer_id = "Developer ID Application: Contoso Ltd (EQHXZ8M8AV)";
+        assert!(signature(State::Signed, Some("EQHXZ8M8AV"), Some(developer_id)).may_sign_kext());
+        assert!(signature(State::Signed, None, Some("Software Signing")).may_sign_kext());
+
+        // a self-made certificate, or a Developer ID certificate without a team
+        assert!(!signature(State::Signed, Some("EQHXZ8M8AV"), Some("Contoso")).may_sign_kext());
+        assert!(!signature(State::Signed, None, Some(developer_id)).may_sign_kext());
+        assert!(!signature(State::Signed, Some("EQHXZ8M8AV"), None).may_sign_kext());
+        assert!(!signature(State::AdHoc, Some("EQHXZ8M8AV"), Some(developer_id)).may_sign_kext());
+    }
+
+    #[test]
+    fn test_unusual_path() {
+        assert!(!is_unusual_path("LC_LOAD_DYLINKER", "/usr/lib/dyld"));
+        assert!(!is_unusual_path(
+            "LC_LOAD_DYLIB",
+            "/usr/lib/libSystem.B.dylib"
+        ));
+        assert!(!is_unusual_path(
+            "LC_LOAD_DYLIB",
+            "@rpath/Sparkle.framework/Sparkle"
+        ));
+        assert!(!is_unusual_path("LC_RPATH", "@loader_path/../Frameworks"));
+        assert!(is_unusual_path("LC_LOAD_DYLINKER", "/tmp/dyld"));
+        assert!(is_unusual_path(
+            "LC_LOAD_WEAK_DYLIB",
+            "/private/tmp/libhook.dylib"
+        ));
+        assert!(is_unusual_path(
+            "LC_LOAD_DYLIB",
+            "/Library/.hidden/libhook.dylib"
+        ));
+        assert!(is_unusual_path("LC_LOAD_DYLIB", "libhook.dylib"));
+        assert!(is_unusual_path(
+            "LC_DYLD_ENVIRONMENT",
+            "DYLD_INSERT_LIBRARIES=/usr/lib/x"
+        ));
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{anyhow, Result};
+use goblin::mach::cputype::get_arch_name_from_types;
+use goblin::mach::header::MH_KEXT_BUNDLE;
+use goblin::mach::load_command::{CommandVariant, LoadCommand};
+use goblin::mach::{Mach, MachO};
+
+/// fat binaries with more architectures are probably Java class files, which have
+/// the same magic
+const MAX_ARCHITECTURES: u32 = 20;
+
+/// a parsed Mach-O file, which is shared by all scanners which need to
+/// understand the structure of macOS executables. Fat (universal) binaries consist
+/// of one Mach-O file per architecture
+pub struct MachOFile<'a> {
+    macho: MachO<'a>,
+
+    /// the data of this architecture, to which all offsets are relative
+    data: &'a [u8],
+}
+
+impl<'a> MachOFile<'a> {
+    /// parses `data` as Mach-O file, and returns one file for every architecture.
+    /// Returns an empty list if `data` does not look like a Mach-O file at all, and an
+    /// error if it does but cannot be parsed.
+    pub fn parse(data: &'a [u8]) -> Result<Vec<Self>> {
+        let magic = match data.get(0..4) {
+            Some(magic) => u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]),
+            None => return Ok(Vec::new()),
+        };
+        match magic {
+            0xfeed_face | 0xfeed_facf | 0xcefa_edfe | 0xcffa_edfe => (),
+            0xcafe_babe => {
+                let architectures = data
+                    .get(4..8)
+                    .map(|count| u32::from_be_bytes([count[0], count[1], count[2], count[3]]));
+                if !matches!(architectures, Some(1..=MAX_ARCHITECTURES)) {
+                    return Ok(Vec::new());
+                }
+            }
+            _ => return Ok(Vec::new()),
+        }
+
+        match Mach::parse(data)? {
+            Mach::Binary(macho) => Ok(vec![Self { macho, data }]),
+            Mach::Fat(fat) => {
+                let mut files = Vec::new();
+                for arch in fat.iter_arches() {
+                    let arch = arch?;
+                    let start = arch.offset as usize;
+                    let data = data
+                        .get(start..start.saturating_add(arch.size as usize))
+                        .ok_or_else(|| anyhow!("an architecture exceeds the end of the file"))?;
+                    files.push(Self {
+                        macho: MachO::parse(data, 0)?,
+                        data,
+                    });
+                }
+                Ok(files)
+            }
+        }
+    }
+
+    /// the name of the architecture, like `x86_64` or `arm64`
+    pub fn architecture(&self) -> &'static str {
+        get_arch_name_from_types(self.macho.header.cputype, self.macho.header.cpusubtype)
+            .unwrap_or("unknown")
+    }
+
+    /// checks if this is a kernel extension
+    pub fn is_kext(&self) -> bool {
+        self.macho.header.filetype == MH_KEXT_BUNDLE
+    }
+
+    /// the string at `offset` in `command`, which ends at the first NUL byte
+    fn string_of(&self, command: &LoadCommand, offset: u32, size: u32) -> String {
+        let start = command.offset.saturating_add(offset as usize);
+        let end = command.offset.saturating_add(size as usize);
+        let string = self.data.get(start..end).unwrap_or_default();
+        let length = string.iter().position(|c| *c == 0).unwrap_or(string.len());
+        String::from_utf8_lossy(&string[..length]).to_string()
+    }
+
+    /// the load commands which refer to a path, like `LC_LOAD_DYLIB`, `LC_RPATH` or
+    /// `LC_LOAD_DYLINKER`, and their paths
+    pub fn paths(&self) -> Vec<(&'static str, String)> {
+        self.macho
+            .load_commands
+            .iter()
+            .filter_map(|command| {
+                let (name, offset, size) = match &command.command {
+                    CommandVariant::LoadDylib(c) => ("LC_LOAD_DYLIB", c.dylib.name, c.cmdsize),
+                    CommandVariant::LoadWeakDylib(c) => {
+                        ("LC_LOAD_WEAK_DYLIB", c.dylib.name, c.cmdsize)
+                    }
+                    CommandVariant::ReexportDylib(c) => {
+                        ("LC_REEXPORT_DYLIB", c.dylib.name, c.cmdsize)
+                    }
+                    CommandVariant::LoadUpwardDylib(c) => {
+                        ("LC_LOAD_UPWARD_DYLIB", c.dylib.name, c.cmdsize)
+                    }
+                    CommandVariant::LazyLoadDylib(c) => {
+                        ("LC_LAZY_LOAD_DYLIB", c.dylib.name, c.cmdsize)
+                    }
+                    CommandVariant::LoadDylinker(c) => ("LC_LOAD_DYLINKER", c.name, c.cmdsize),
+                    CommandVariant::DyldEnvironment(c) => {
+                        ("LC_DYLD_ENVIRONMENT", c.name, c.cmdsize)
+                    }
+                    CommandVariant::Rpath(c) => ("LC_RPATH", c.path, c.cmdsize),
+                    _ => return None,
+                };
+                Some((name, self.string_of(command, offset, size)))
+            })
+            .collect()
+    }
+
+    /// the code signature, which is a superblob of the code directory, the
+    /// requirements and the CMS signature. Returns `None` if the file has no
+    /// `LC_CODE_SIGNATURE` command, and an empty slice if the command is invalid
+    pub fn code_signature(&self) -> Option<&'a [u8]> {
+        self.macho
+            .load_commands
+            .iter()
+            .find_map(|command| match &command.command {
+                CommandVariant::CodeSignature(c) => {
+                    let start = c.dataoff as usize;
+                    let end = start.saturating_add(c.datasize as usize);
+                    Some(self.data.get(start..end).unwrap_or_default())
+                }
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MachOFile;
+
+    /// a 64 bit Mach-O header without load commands
+    fn thin(cputype: u32, cpusubtype: u32, filetype: u32) -> Vec<u8> {
+        let mut header = Vec::new();
+        for field in [0xfeed_facf, cputype, cpusubtype, filetype, 0, 0, 0, 0] {
+            header.extend(u32::to_le_bytes(field));
+        }
+        header
+    }
+
+    #[test]
+    fn test_fat_binary() {
+        let x86_64 = thin(0x0100_0007, 3, 2);
+        let arm64 = thin(0x0100_000c, 0, 0xb);
+
+        let mut fat = Vec::new();
+        for field in [0xcafe_babe, 2] {
+            fat.extend(u32::to_be_bytes(field));
+        }
+        let mut offset = 8 + 2 * 20;
+        for (cputype, cpusubtype, data) in [(0x0100_0007, 3, &x86_64), (0x0100_000c, 0, &arm64)] {
+            for field in [cputype, cpusubtype, offset, data.len() as u32, 0] {
+                fat.extend(u32::to_be_bytes(field));
+            }
+            offset += data.len() as u32;
+        }
+        fat.extend(&x86_64);
+        fat.extend(&arm64);
+
+        let files = MachOFile::parse(&fat).unwrap();
+        let architectures: Vec<_> = files.iter().map(|file| file.architecture()).collect();
+        assert_eq!(architectures, vec!["x86_64", "arm64"]);
+        assert!(!files[0].is_kext());
+        assert!(files[1].is_kext());
+        assert!(files[0].code_signature().is_none());
+
+        // an architecture beyond the end of the file
+        assert!(MachOFile::parse(&fat[..fat.len() - 1]).is_err());
+
+        // Java class files have the same magic, but a large version instead of the
+        // number of architectures
+        let class_file = [0xca, 0xfe, 0xba, 0xbe, 0x00, 0x00, 0x00, 0x34];
+        assert!(MachOFile::parse(&class_file).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use mail_parser::{Message, MessageParser, MimeHeaders, PartType};
+use memmap::MmapOptions;
+
+/// nested messages are extracted up to this depth
+const MAX_DEPTH: usize = 3;
+
+/// the default of the maximum size of mails whose parts are scanned, in MiB
+pub(crate) const DEFAULT_MAX_SIZE: u64 = 64;
+
+/// a decoded part of a mail, e.g. its body or an attachment
+pub(crate) struct MailPart {
+    /// the name of the part, which is appended to the filename of the mail, e.g.
+    /// `invoice.pdf` or `forwarded.eml:invoice.pdf` for parts of attached mails
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// the decoded parts of `file`, if it is a mail (`.eml` or Outlook `.msg`) of at most
+/// `max_size` bytes. Otherwise, `None` is returned. Attachments are otherwise only seen
+/// as base64 (or compressed streams of the compound file), so that scanners would not
+/// find anything in them. Files which cannot be parsed (e.g. `.msg` files which are no
+/// Outlook messages) are not considered to be mails
+pub(crate) fn mail_parts(file: &Path, max_size: u64) -> Option<Vec<MailPart>> {
+    let extension = file.extension()?.to_string_lossy().to_lowercase();
+    if extension != "eml" && extension != "msg" {
+        return None;
+    }
+    match read_mail_parts(file, &extension, max_size) {
+        Ok(parts) => parts,
+        Err(why) => {
+            log::debug!("'{}' is not scanned as mail: {}", file.display(), why);
+            None
+        }
+    }
+}
+
+fn read_mail_parts(file: &Path, extension: &str, max_size: u64) -> Result<Option<Vec<MailPart>>> {
+    let mail = File::open(file)?;
+    let size = mail.metadata()?.len();
+    if size > max_size {
+        log::warn!(
+            "the parts of '{}' are not scanned, because it is larger than {} bytes",
+            file.display(),
+            max_size
+        );
+        return Ok(None);
+    }
+    match extension {
+        "eml" if size == 0 => Ok(None),
+        "eml" => {
+            let mmap = unsafe { MmapOptions::new().map(&mail)? };
+            eml_parts(&mmap[..]).map(Some)
+        }
+        _ => msg_parts(mail).map(Some),
+    }
+}
+
+fn eml_parts(data: &[u8]) -> Result<Vec<MailPart>> {
+    let message = MessageParser::default()
+        .parse(data)
+        .ok_or_else(|| anyhow!("unable to parse the mail"))?;
+    let mut parts = Vec::new();
+    add_eml_parts(&mut parts, &message, "", 0);
+    Ok(parts)
+}
+
+fn add_eml_parts(parts: &mut Vec<MailPart>, message: &Message, parent: &str, depth: usize) {
+    let name_of = |name: String| match parent.is_empty() {
+        true => name,
+        false => format!("{}:{}", parent, name),
+    };
+    for (index, part) in message.parts.iter().enumerate() {
+        let name = part
+            .attachment_name()
+            .map(|name| name.to_owned())
+            .unwrap_or_else(|| match &part.body {
+                PartType::Text(_) => format!("part[{}].txt", index),
+                PartType::Html(_) => format!("part[{}].html", index),
+                PartType::Message(_) => format!("part[{}].eml", index),
+                _ => format!("part[{}]", index),
+            });
+        match &part.body {
+            PartType::Multipart(_) => (),
+            PartType::Text(text) | PartType::Html(text) => parts.push(MailPart {
+                name: name_of(name),
+                data: text.as_bytes().to_vec(),
+            }),
+            PartType::Binary(data) | PartType::InlineBinary(data) => parts.push(MailPart {
+                name: name_of(name),
+                data: data.to_vec(),
+            }),
+            PartType::Message(nested) => {
+                let name = name_of(name);
+                parts.push(MailPart {
+                    name: name.clone(),
+                    data: nested.raw_message().to_vec(),
+                });
+                if depth + 1 < MAX_DEPTH {
+                    add_eml_parts(parts, nested, &name, depth + 1);
+                }
+            }
+        }
+    }
+}
+
+/// the names of the streams in Outlook messages, see MS-OXMSG
+const MSG_ATTACHMENT_PREFIX: &str = "__attach_version1.0_#";
+const MSG_TEXT_BODY: &str = "__substg1.0_1000001F";
+const MSG_HTML_BODY: &str = "__substg1.0_10130102";
+const MSG_ATTACHMENT_DATA: &str = "__substg1.0_37010102";
+const MSG_ATTACHMENT_LONG_FILENAME: &str = "__substg1.0_3707001F";
+const MSG_ATTACHMENT_FILENAME: &str = "__substg1.0_3704001F";
+
+/// the bodies and attachments of an Outlook message, which is a compound file.
+/// Attached messages are stored as storages instead of streams, and are not extracted
+fn msg_parts<R: Read + Seek>(reader: R) -> Result<Vec<MailPart>> {
+    let mut msg = cfb::CompoundFile::open(reader)?;
+    let mut attachments: Vec<PathBuf> = msg
+        .read_root_storage()
+        .filter(|entry| entry.is_storage() && entry.name().starts_with(MSG_ATTACHMENT_PREFIX))
+        .map(|entry| entry.path().to_owned())
+        .collect();
+    attachments.sort();
+
+    let mut read_stream = |path: &Path| -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        msg.open_stream(path).ok()?.read_to_end(&mut data).ok()?;
+        Some(data)
+    };
+
+    let mut parts = Vec::new();
+    if let Some(body) = read_stream(&Path::new("/").join(MSG_TEXT_BODY)) {
+        parts.push(MailPart {
+            name: "body.txt".to_owned(),
+            data: utf16_to_string(&body).into_bytes(),
+        });
+    }
+    if let Some(body) = read_stream(&Path::new("/").join(MSG_HTML_BODY)) {
+        parts.push(MailPart {
+            name: "body.html".to_owned(),
+            data: body,
+        });
+    }
+    for (index, attachment) in attachments.iter().enumerate() {
+        let data = match read_stream(&attachment.join(MSG_ATTACHMENT_DATA)) {
+            Some(data) => data,
+            None => continue,
+        };
+        let name = read_stream(&attachment.join(MSG_ATTACHMENT_LONG_FILENAME))
+            .or_else(|| read_stream(&attachment.join(MSG_ATTACHMENT_FILENAME)))
+            .map(|name| utf16_to_string(&name))
+            .unwrap_or_else(|| format!("attachment[{}]", index));
+        parts.push(MailPart { name, data });
+    }
+    Ok(parts)
+}
+
+fn utf16_to_string(data: &[u8]) -> String {
+    let chars: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&chars)
+        .trim_end_matches('\0')
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use std::path::Path;
+
+    use super::{eml_parts, mail_parts, msg_parts};
+
+    const EML: &str = concat!(
+        "From: attacker@example.com\r\n",
+        "To: victim@example.com\r\n",
+        "Subject: invoice\r\n",
+        "MIME-Version: 1.0\r\n",
+        "Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+        "\r\n",
+        "--b\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "please see the attachment\r\n",
+        "--b\r\n",
+        "Content-Type: application/octet-stream; name=\"invoice.exe\"\r\n",
+        "Content-Disposition: attachment; filename=\"invoice.exe\"\r\n",
+        "Content-Transfer-Encoding: base64\r\n",
+        "\r\n",
+        "TVpldmls\r\n",
+        "--b--\r\n"
+    );
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    /// an Outlook message with a text body and an attachment
+    fn msg() -> Vec<u8> {
+        let mut msg = cfb::CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        msg.create_storage("/__attach_version1.0_#00000000")
+            .unwrap();
+        for (path, data) in [
+            ("/__substg1.0_1000001F", utf16("please see the attachment")),
+            (
+                "/__attach_version1.0_#00000000/__substg1.0_37010102",
+                b"MZevil".to_vec(),
+            ),
+            (
+                "/__attach_version1.0_#00000000/__substg1.0_3707001F",
+                utf16("invoice.exe"),
+            ),
+        ] {
+            msg.create_stream(path).unwrap().write_all(&data).unwrap();
+        }
+        msg.flush().unwrap();
+        msg.into_inner().into_inner()
+    }
+
+    #[test]
+    fn test_eml_attachment() {
+        let parts = eml_parts(EML.as_bytes()).unwrap();
+        let attachment = parts.iter().find(|p| p.name == "invoice.exe").unwrap();
+        assert_eq!(attachment.data, b"MZevil");
+        assert!(parts.iter().any(|p| p.data.starts_with(b"please see")));
+    }
+
+    #[test]
+    fn test_msg_attachment() {
+        let parts = msg_parts(Cursor::new(msg())).unwrap();
+        let names: Vec<&str> = parts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["body.txt", "invoice.exe"]);
+        assert_eq!(parts[0].data, b"please see the attachment");
+        assert_eq!(parts[1].data, b"MZevil");
+    }
+
+    #[test]
+    fn test_mail_files() {
+        let dir = std::env::temp_dir().join(format!("dionysos-mail-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, data: &[u8]| {
+            std::fs::write(dir.join(name), data).unwrap();
+            dir.join(name)
+        };
+        let eml = write("invoice.eml", EML.as_bytes());
+        let msg = write("invoice.MSG", &msg());
+        // e.g. a .msg file of another application
+        let other_msg = write("other.msg", b"not an Outlook message");
+        let text = write("invoice.txt", EML.as_bytes());
+
+        let count = |file: &Path, max_size: u64| mail_parts(file, max_size).map(|p| p.len());
+        let results = (
+            count(&eml, 1024),
+            count(&msg, 1 << 20),
+            count(&other_msg, 1024),
+            count(&text, 1024),
+            count(&eml, 16),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(results, (Some(2), Some(2), None, None, None));
+    }
+}
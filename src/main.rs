@@ -1,17 +1,12 @@
-use anyhow::Result;
+use dionysos_lib::{Dionysos, EXIT_FATAL};
 
-mod filescanner;
-mod dionysos;
-mod yara;
-mod filename_scanner;
-mod scanner_result;
-mod levenshtein_scanner;
-mod hash_scanner;
-mod csv_line;
-
-use dionysos::*;
-
-fn main() -> Result <()> {
-    let app: Dionysos = Dionysos::new()?;
-    app.run()
+fn main() {
+    let status = Dionysos::new().and_then(|app| app.run());
+    std::process::exit(match status {
+        Ok(status) => status.exit_code(),
+        Err(why) => {
+            eprintln!("Error: {:?}", why);
+            EXIT_FATAL
+        }
+    })
 }
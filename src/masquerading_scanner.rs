@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::file_hash::sha256_of;
+use crate::path_string::path_to_string;
+use crate::filescanner::FileScanner;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+const SYSTEM32: &[&str] = &["Windows/System32"];
+const SYSTEM32_AND_WOW64: &[&str] = &["Windows/System32", "Windows/SysWOW64"];
+
+/// system binaries and the directories they are expected to reside in
+static SYSTEM_BINARIES: &[(&str, &[&str])] = &[
+    ("svchost.exe", SYSTEM32_AND_WOW64),
+    ("lsass.exe", SYSTEM32),
+    ("csrss.exe", SYSTEM32),
+    ("winlogon.exe", SYSTEM32),
+    ("wininit.exe", SYSTEM32),
+    ("smss.exe", SYSTEM32),
+    ("services.exe", SYSTEM32),
+    ("spoolsv.exe", SYSTEM32),
+    ("taskhostw.exe", SYSTEM32),
+    ("conhost.exe", SYSTEM32),
+    ("rundll32.exe", SYSTEM32_AND_WOW64),
+    ("dllhost.exe", SYSTEM32_AND_WOW64),
+    ("cmd.exe", SYSTEM32_AND_WOW64),
+    ("explorer.exe", &["Windows", "Windows/SysWOW64"]),
+];
+
+/// directories which contain legitimate copies of system binaries
+static COMPONENT_STORES: &[&str] = &["windows/winsxs/", "windows/servicing/"];
+
+/// reports files which have the name of a system binary, but reside outside of the
+/// directory where this system binary is expected. If the original binary can be
+/// found, only files whose hash differs from the hash of the original are reported.
+#[derive(Default)]
+pub struct MasqueradingScanner {}
+
+impl Display for MasqueradingScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "MasqueradingScanner")
+    }
+}
+
+impl FileScanner for MasqueradingScanner {
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match self.intern_scan_file(entry.path()) {
+            Ok(None) => vec![],
+            Ok(Some(finding)) => vec![Ok(Box::new(finding) as Box<dyn ScannerFinding>)],
+            Err(why) => vec![Err(anyhow!(
+                "unable to check '{}' for masquerading: {}",
+                entry.path().display(),
+                why
+            ))],
+        }
+    }
+
+    /// the file is compared with the system file of the same name
+    fn depends_on_path(&self) -> bool {
+        true
+    }
+}
+
+impl MasqueradingScanner {
+    fn intern_scan_file(&self, file: &Path) -> Result<Option<MasqueradingFinding>> {
+        let file_name = match file.file_name() {
+            Some(file_name) => file_name.to_string_lossy().to_lowercase(),
+            None => return Ok(None),
+        };
+
+        let expected_directories = match SYSTEM_BINARIES.iter().find(|(name, _)| *name == file_name) {
+            Some((_, directories)) => *directories,
+            None => return Ok(None),
+        };
+
+        let path = normalize(file);
+        let parent = path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or_default();
+        if expected_directories
+            .iter()
+            .any(|d| parent.ends_with(&d.to_lowercase()))
+            || COMPONENT_STORES.iter().any(|d| path.contains(d))
+        {
+            return Ok(None);
+        }
+
+        let original = Self::find_original(file, &file_name, expected_directories);
+        if let Some(original) = &original {
+            if sha256_of(file)? == sha256_of(original)? {
+                log::info!(
+                    "'{}' is a copy of '{}'",
+                    file.display(),
+                    original.display()
+                );
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(MasqueradingFinding {
+            found_in_file: path_to_string(file),
+            expected_location: expected_directories.join(", "),
+            original: original.map(|o| path_to_string(&o)),
+        }))
+    }
+
+    /// searches the original system binary, relative to all parents of `file`.
+    /// This also works if the scanned filesystem is mounted somewhere
+    fn find_original(file: &Path, file_name: &str, directories: &[&str]) -> Option<PathBuf> {
+        for ancestor in file.ancestors().skip(1) {
+            for directory in directories.iter() {
+                for candidate in [directory.to_string(), directory.to_lowercase()] {
+                    let candidate = ancestor.join(candidate).join(file_name);
+                    if candidate.is_file() {
+                        return Some(candidate);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// converts a path to lowercase, using `/` as separator
+fn normalize(path: &Path) -> String {
+    path.display().to_string().replace('\\', "/").to_lowercase()
+}
+
+struct MasqueradingFinding {
+    found_in_file: String,
+    expected_location: String,
+
+    /// the original system binary, if it has been found
+    original: Option<String>,
+}
+
+impl MasqueradingFinding {
+    fn details(&self) -> String {
+        match &self.original {
+            Some(original) => format!("hash differs from the original '{}'", original),
+            None => format!("expected in {}, but the original has not been found", self.expected_location),
+        }
+    }
+}
+
+impl Display for MasqueradingFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "system binary masquerading: {found_in_file} ({details})")
+    }
+}
+
+impl ScannerFinding for MasqueradingFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        let file = self.found_in_file();
+        hashset![CsvLine::new(
+            "Masquerading",
+            "system binary masquerading",
+            file,
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        let file = self.found_in_file();
+        json!({
+            "01_scanner": "masquerading",
+            "02_suspicious_file": file,
+            "03_expected_location": self.expected_location,
+            "04_original": self.original
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::High
+    }
+
+    fn scanner(&self) -> String {
+        "masquerading".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        // Masquerading: Match Legitimate Name or Location
+        vec!["T1036.005".to_owned()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::MasqueradingScanner;
+
+    fn write(root: &Path, path: &str, content: &[u8]) -> PathBuf {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_expected_location() {
+        let root = std::env::temp_dir().join(format!("dionysos-masq-loc-{}", std::process::id()));
+        let scanner = MasqueradingScanner::default();
+
+        for path in [
+            "Windows/System32/svchost.exe",
+            "Windows/SysWOW64/svchost.exe",
+            "Windows/WinSxS/amd64_svchost/svchost.exe",
+            "Users/Public/notepad.exe",
+        ] {
+            let file = write(&root, path, b"MZ");
+            let result = scanner.intern_scan_file(&file).unwrap();
+            assert!(result.is_none(), "{}", path);
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_copy_of_original() {
+        let root = std::env::temp_dir().join(format!("dionysos-masq-copy-{}", std::process::id()));
+        let scanner = MasqueradingScanner::default();
+
+        write(&root, "Windows/System32/lsass.exe", b"MZoriginal");
+        let copy = write(&root, "Users/Public/lsass.exe", b"MZoriginal");
+        let fake = write(&root, "Users/Public/Downloads/LSASS.EXE", b"MZevil");
+
+        let copy_result = scanner.intern_scan_file(&copy).unwrap();
+        let fake_result = scanner.intern_scan_file(&fake).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(copy_result.is_none());
+        let finding = fake_result.unwrap();
+        assert_eq!(finding.expected_location, "Windows/System32");
+        assert!(finding.original.unwrap().ends_with("lsass.exe"));
+    }
+
+    #[test]
+    fn test_missing_original() {
+        let root = std::env::temp_dir().join(format!("dionysos-masq-miss-{}", std::process::id()));
+        let scanner = MasqueradingScanner::default();
+
+        let fake = write(&root, "Temp/svchost.exe", b"MZevil");
+        let result = scanner.intern_scan_file(&fake).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let finding = result.unwrap();
+        assert_eq!(
+            finding.expected_location,
+            "Windows/System32, Windows/SysWOW64"
+        );
+        assert!(finding.original.is_none());
+    }
+}
@@ -0,0 +1,462 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::dionysos::{OutputFormat, OutputMethods};
+use crate::scanner_result::{ScannerFinding, ScannerResult};
+
+/// the names of the scanners, as they are written to `01_scanner` in the `json` output
+const SCANNERS: &[&str] = &[
+    "yara",
+    "filename",
+    "levenshtein",
+    "hash",
+    "fuzzy_hash",
+    "tlsh",
+    "entropy",
+    "extension_mismatch",
+    "masquerading",
+    "ransomware",
+    "permissions",
+    "authenticode",
+    "pe_anomalies",
+    "elf_anomalies",
+    "macho_anomalies",
+    "registry_ioc",
+    "plugin",
+];
+
+const USER_AGENT: &str = concat!("dionysos/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// the number of findings which wait to be posted to a webhook. If the webhook is
+/// slower than the scan, writing further findings blocks
+const WEBHOOK_QUEUE: usize = 1024;
+
+/// where the findings of a route are written
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Sink {
+    /// the output of all findings which are not routed (`-`)
+    Default,
+
+    /// a file, to which the findings are appended in the selected output format. Files
+    /// with the extension `.zip` are zip archives encrypted with `--route-password`,
+    /// which are overwritten
+    File(PathBuf),
+
+    /// a URL, to which every finding is posted as JSON object, e.g. of a SIEM
+    Webhook(String),
+}
+
+impl std::fmt::Display for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sink::Default => write!(f, "the default output"),
+            Sink::File(path) => write!(f, "'{}'", path.display()),
+            Sink::Webhook(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// sends the findings of some scanners to another sink than the default output
+/// (`--route`), e.g. hash findings to a SIEM, or the strings of yara findings only
+/// to an encrypted file
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Route {
+    scanners: Vec<String>,
+    sink: Sink,
+}
+
+impl Route {
+    /// parses a route like `hash,filename=https://siem.example.com/hook`
+    pub fn parse(value: &str) -> Result<Self> {
+        let (scanners, sink) = value
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid route '{}', expected SCANNERS=SINK", value))?;
+        let scanners: Vec<String> = scanners
+            .split(',')
+            .map(|scanner| scanner.trim().to_lowercase())
+            .collect();
+        if let Some(scanner) = scanners.iter().find(|s| !SCANNERS.contains(&s.as_str())) {
+            return Err(anyhow!(
+                "unknown scanner '{}' in route '{}', expected one of {}",
+                scanner,
+                value,
+                SCANNERS.join(", ")
+            ));
+        }
+        let sink = match sink.trim() {
+            "" => return Err(anyhow!("the route '{}' has no sink", value)),
+            "-" => Sink::Default,
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                Sink::Webhook(url.to_owned())
+            }
+            path => Sink::File(PathBuf::from(path)),
+        };
+        Ok(Self { scanners, sink })
+    }
+
+    fn matches(&self, scanner: &str) -> bool {
+        self.scanners.iter().any(|s| s == scanner)
+    }
+}
+
+/// what must be completed after the last finding has been written to a sink
+enum Completion {
+    /// the encrypted archive, which is shared with the output of the route
+    Archive(PathBuf, Arc<Mutex<Option<zip::ZipWriter<File>>>>),
+
+    /// the thread which posts the findings to a webhook
+    Webhook(JoinHandle<()>),
+}
+
+struct RouteOutput {
+    route: Route,
+    output: Option<OutputMethods<Box<dyn Write + Send>>>,
+    completion: Option<Completion>,
+}
+
+/// the outputs of all routes
+pub(crate) struct OutputRoutes {
+    routes: Vec<RouteOutput>,
+    failed_requests: Arc<AtomicUsize>,
+}
+
+impl OutputRoutes {
+    /// opens the sinks of all `routes`. `output` creates the output of a sink, in the
+    /// given format, so that the findings of all sinks are annotated in the same way
+    pub fn open<F>(
+        routes: &[Route],
+        format: OutputFormat,
+        password: Option<&str>,
+        output: F,
+    ) -> Result<Self>
+    where
+        F: Fn(Box<dyn Write + Send>, OutputFormat) -> OutputMethods<Box<dyn Write + Send>>,
+    {
+        let failed_requests = Arc::new(AtomicUsize::new(0));
+        let mut outputs = Vec::new();
+        for route in routes {
+            let mut completion = None;
+            let sink_output = match &route.sink {
+                Sink::Default => None,
+                Sink::File(path) => {
+                    let is_zip = path
+                        .extension()
+                        .map_or(false, |e| e.eq_ignore_ascii_case("zip"));
+                    let destination: Box<dyn Write + Send> = match (is_zip, password) {
+                        (false, _) => Box::new(
+                            OpenOptions::new()
+                                .create(true)
+                                .append(true)
+                                .open(path)
+                                .map_err(|why| {
+                                    anyhow!("unable to open '{}': {}", path.display(), why)
+                                })?,
+                        ),
+                        (true, Some(password)) => {
+                            let zip = encrypted_file(path, &format, password)?;
+                            let archive = Arc::new(Mutex::new(Some(zip)));
+                            completion =
+                                Some(Completion::Archive(path.clone(), Arc::clone(&archive)));
+                            Box::new(EncryptedFile(archive))
+                        }
+                        (true, None) => {
+                            return Err(anyhow!(
+                                "the route to '{}' needs a password ('--route-password')",
+                                path.display()
+                            ))
+                        }
+                    };
+                    Some(output(destination, format.clone()))
+                }
+                Sink::Webhook(url) => {
+                    let (lines, rx) = mpsc::sync_channel(WEBHOOK_QUEUE);
+                    let url = url.clone();
+                    let failed_requests = Arc::clone(&failed_requests);
+                    completion = Some(Completion::Webhook(thread::spawn(move || {
+                        post_lines(&url, rx, &failed_requests)
+                    })));
+                    let webhook = Webhook {
+                        line: Vec::new(),
+                        lines,
+                    };
+                    Some(output(Box::new(webhook), OutputFormat::Ndjson))
+                }
+            };
+            log::info!(
+                "writing the findings of {} to {}",
+                route.scanners.join(", "),
+                route.sink
+            );
+            outputs.push(RouteOutput {
+                route: route.clone(),
+                output: sink_output,
+                completion,
+            });
+        }
+        Ok(Self {
+            routes: outputs,
+            failed_requests,
+        })
+    }
+
+    /// checks if `finding` belongs to the default output, because it is not routed
+    /// at all, or routed to `-`
+    pub fn is_default(&self, finding: &dyn ScannerFinding) -> bool {
+        if self.routes.is_empty() {
+            return true;
+        }
+        let scanner = finding.scanner();
+        let mut routes = self
+            .routes
+            .iter()
+            .filter(|r| r.route.matches(&scanner))
+            .peekable();
+        routes.peek().is_none() || routes.any(|r| r.route.sink == Sink::Default)
+    }
+
+    /// writes the findings of `result` to the sinks of their routes
+    pub fn print_result(&mut self, result: &ScannerResult) -> Result<()> {
+        for RouteOutput { route, output, .. } in self.routes.iter_mut() {
+            if let Some(output) = output {
+                output.print_findings(result, |finding| route.matches(&finding.scanner()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// writes all findings which have not been written yet, completes the encrypted
+    /// archives and waits until all findings have been posted to their webhooks. No
+    /// findings can be written afterwards
+    pub fn finish(&mut self) -> Result<()> {
+        for route in self.routes.iter_mut() {
+            if let Some(mut output) = route.output.take() {
+                output.flush()?;
+            }
+            match route.completion.take() {
+                None => (),
+                Some(Completion::Archive(path, archive)) => {
+                    if let Some(mut zip) = archive.lock().unwrap().take() {
+                        zip.finish().map_err(|why| {
+                            anyhow!("unable to complete '{}': {}", path.display(), why)
+                        })?;
+                    }
+                }
+                Some(Completion::Webhook(thread)) => {
+                    if thread.join().is_err() {
+                        return Err(anyhow!("the webhook of {} has panicked", route.route.sink));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        match self.failed_requests.load(Ordering::Relaxed) {
+            0 => Vec::new(),
+            failed => vec![format!(
+                "{} finding(s) could not be sent to their webhook",
+                failed
+            )],
+        }
+    }
+}
+
+/// creates a zip archive, which contains a single file with the findings, encrypted
+/// with AES-256. The archive must be completed with [`OutputRoutes::finish`]
+fn encrypted_file(
+    path: &Path,
+    format: &OutputFormat,
+    password: &str,
+) -> Result<zip::ZipWriter<File>> {
+    let file = File::create(path)
+        .map_err(|why| anyhow!("unable to create '{}': {}", path.display(), why))?;
+    let name = match format {
+        OutputFormat::Csv => "findings.csv",
+        OutputFormat::Txt => "findings.txt",
+        OutputFormat::Json => "findings.json",
+        OutputFormat::Ndjson => "findings.ndjson",
+    };
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .large_file(true)
+        .with_aes_encryption(zip::AesMode::Aes256, password);
+    zip.start_file(name, options)?;
+    Ok(zip)
+}
+
+/// writes to the encrypted archive of a route, until it is completed
+struct EncryptedFile(Arc<Mutex<Option<zip::ZipWriter<File>>>>);
+
+impl Write for EncryptedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(zip) => zip.write(buf),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "the archive has already been completed",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(zip) => zip.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// passes every line which is written to it to the thread which posts it to the
+/// webhook, so that slow requests do not hold up the output of the findings
+struct Webhook {
+    line: Vec<u8>,
+    lines: SyncSender<Vec<u8>>,
+}
+
+impl Write for Webhook {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        while let Some(end) = self.line.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = self.line.drain(..=end).collect();
+            line.truncate(end);
+            self.lines.send(line).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, "the webhook has stopped")
+            })?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// posts every line of `lines` to `url`. Failed requests are logged and counted, but
+/// do not stop the scan
+fn post_lines(url: &str, lines: Receiver<Vec<u8>>, failed_requests: &AtomicUsize) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .user_agent(USER_AGENT)
+        .build();
+    for line in lines {
+        let response = agent
+            .post(url)
+            .set("Content-Type", "application/json")
+            .send_bytes(&line);
+        if let Err(why) = response {
+            log::warn!("unable to send a finding to {}: {}", url, why);
+            failed_requests.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    use super::{OutputRoutes, Route, Sink};
+    use crate::dionysos::OutputFormat;
+    use crate::scanner_result::{ScannerFinding, ScannerResult};
+    use crate::ScanJob;
+
+    /// a result with a finding of the filename scanner
+    fn result() -> ScannerResult {
+        ScanJob::new("unused")
+            .with_filenames(&[r"\.exe$".to_owned()])
+            .unwrap()
+            .scan_buffer("evil.exe", b"")
+    }
+
+    fn open(route: &str, password: Option<&str>) -> anyhow::Result<OutputRoutes> {
+        let routes = [Route::parse(route)?];
+        OutputRoutes::open(
+            &routes,
+            OutputFormat::Json,
+            password,
+            |destination, format| format.into_options(destination),
+        )
+    }
+
+    #[test]
+    fn test_parse_route() {
+        let route = Route::parse("hash, filename=https://siem.example.com/hook?token=x").unwrap();
+        assert_eq!(route.scanners, vec!["hash", "filename"]);
+        assert_eq!(
+            route.sink,
+            Sink::Webhook("https://siem.example.com/hook?token=x".to_owned())
+        );
+        assert!(route.matches("hash"));
+        assert!(!route.matches("yara"));
+
+        let route = Route::parse("yara=/var/log/dionysos/yara.zip").unwrap();
+        assert_eq!(
+            route.sink,
+            Sink::File(PathBuf::from("/var/log/dionysos/yara.zip"))
+        );
+        assert_eq!(Route::parse("yara=-").unwrap().sink, Sink::Default);
+
+        assert!(Route::parse("yara").is_err());
+        assert!(Route::parse("antivirus=-").is_err());
+        assert!(Route::parse("yara=").is_err());
+    }
+
+    #[test]
+    fn test_scanner_of_finding() {
+        let result = result();
+        let finding = result.findings().next().unwrap();
+        assert_eq!(finding.scanner(), "filename");
+
+        let routes = open("hash=-", None).unwrap();
+        assert!(routes.is_default(finding.as_ref()));
+        let routes = open("filename=http://127.0.0.1:1/", None).unwrap();
+        assert!(!routes.is_default(finding.as_ref()));
+    }
+
+    #[test]
+    fn test_encrypted_file() {
+        let path = std::env::temp_dir().join(format!("dionysos-route-{}.zip", std::process::id()));
+        let route = format!("filename={}", path.display());
+        assert!(open(&route, None).is_err());
+
+        let mut routes = open(&route, Some("secret")).unwrap();
+        routes.print_result(&result()).unwrap();
+        routes.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut findings = String::new();
+        archive
+            .by_index_decrypt(0, b"secret")
+            .unwrap()
+            .read_to_string(&mut findings)
+            .unwrap();
+        drop(archive);
+        std::fs::remove_file(&path).unwrap();
+        assert!(findings.contains("evil.exe"));
+    }
+
+    #[test]
+    fn test_failed_webhook() {
+        // nothing listens on port 1, so that the request fails
+        let mut routes = open("filename=http://127.0.0.1:1/", None).unwrap();
+        routes.print_result(&result()).unwrap();
+        routes.finish().unwrap();
+        assert_eq!(
+            routes.summary(),
+            vec!["1 finding(s) could not be sent to their webhook"]
+        );
+    }
+}
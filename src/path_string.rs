@@ -0,0 +1,204 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// converts `path` into its absolute, extended-length form (`\\?\C:\...` or
+/// `\\?\UNC\server\share\...`) on Windows, so that files below it can be opened
+/// even if their paths are longer than `MAX_PATH`. `.` and `..` are resolved, because
+/// Windows does not resolve them in extended-length paths. On other platforms, `path`
+/// is returned unchanged
+pub(crate) fn extended_length_path(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        use std::ffi::OsString;
+        use std::path::{Component, Prefix};
+
+        let absolute = match path.is_absolute() {
+            true => path.to_owned(),
+            false => match std::env::current_dir() {
+                Ok(current_dir) => current_dir.join(path),
+                Err(_) => return path.to_owned(),
+            },
+        };
+        let mut components = absolute.components();
+        let mut result = match components.next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::Disk(letter) => PathBuf::from(format!("\\\\?\\{}:\\", letter as char)),
+                Prefix::UNC(server, share) => {
+                    let mut unc = OsString::from("\\\\?\\UNC\\");
+                    unc.push(server);
+                    unc.push("\\");
+                    unc.push(share);
+                    unc.push("\\");
+                    PathBuf::from(unc)
+                }
+                // already in extended-length form, or a device
+                _ => return absolute,
+            },
+            _ => return absolute,
+        };
+        for component in components {
+            match component {
+                Component::ParentDir => {
+                    result.pop();
+                }
+                Component::Normal(name) => result.push(name),
+                Component::Prefix(_) | Component::RootDir | Component::CurDir => (),
+            }
+        }
+        result
+    }
+
+    #[cfg(not(windows))]
+    path.to_owned()
+}
+
+/// removes the extended-length prefix of a path, which has been added by
+/// [`extended_length_path`], so that users see the usual form of the path
+pub(crate) fn user_friendly_path(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    {
+        use std::ffi::OsString;
+        use std::path::{Component, Prefix};
+
+        let mut components = path.components();
+        let prefix = match components.next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::VerbatimDisk(letter) => OsString::from(format!("{}:", letter as char)),
+                Prefix::VerbatimUNC(server, share) => {
+                    let mut unc = OsString::from("\\\\");
+                    unc.push(server);
+                    unc.push("\\");
+                    unc.push(share);
+                    unc
+                }
+                _ => return Cow::Borrowed(path),
+            },
+            _ => return Cow::Borrowed(path),
+        };
+        let mut result = PathBuf::from(prefix);
+        result.push("\\");
+        for component in components {
+            if let Component::Normal(name) = component {
+                result.push(name);
+            }
+        }
+        Cow::Owned(result)
+    }
+
+    #[cfg(not(windows))]
+    Cow::Borrowed(path)
+}
+
+/// converts `path` into a string, which is used as the filename of findings and
+/// results. Attackers often use filenames which are not valid Unicode, or which contain
+/// line breaks, to hide files from tools. Therefore, the conversion is lossless and does
+/// not break the line-based outputs:
+///
+/// - bytes which are not valid UTF-8 (on Unix) are written as `\xNN`
+/// - unpaired UTF-16 surrogates (on Windows) are written as `\u{dNNN}`
+/// - control characters (e.g. line breaks) are written as `\u{N}`
+/// - on Unix, where a backslash is a valid character of a filename, it is written as
+///   `\\`
+///
+/// Valid filenames without control characters (or backslashes, on Unix) are not changed.
+/// Extended-length paths (`\\?\C:\...`) are written in their usual form (`C:\...`)
+pub fn path_to_string(path: &Path) -> String {
+    let path = user_friendly_path(path);
+    let mut result = String::new();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let mut bytes = path.as_os_str().as_bytes();
+        while !bytes.is_empty() {
+            match std::str::from_utf8(bytes) {
+                Ok(valid) => {
+                    push_escaped(&mut result, valid);
+                    break;
+                }
+                Err(why) => {
+                    let (valid, rest) = bytes.split_at(why.valid_up_to());
+                    // SAFETY: `valid_up_to` is the length of the valid prefix
+                    push_escaped(&mut result, unsafe { std::str::from_utf8_unchecked(valid) });
+                    let invalid = why.error_len().unwrap_or(rest.len());
+                    for byte in &rest[..invalid] {
+                        let _ = write!(result, "\\x{:02x}", byte);
+                    }
+                    bytes = &rest[invalid..];
+                }
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        for c in char::decode_utf16(path.as_os_str().encode_wide()) {
+            match c {
+                Ok(c) => push_escaped_char(&mut result, c),
+                Err(why) => {
+                    let _ = write!(result, "\\u{{{:x}}}", why.unpaired_surrogate());
+                }
+            }
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    push_escaped(&mut result, &path.to_string_lossy());
+
+    result
+}
+
+fn push_escaped(result: &mut String, value: &str) {
+    for c in value.chars() {
+        push_escaped_char(result, c);
+    }
+}
+
+fn push_escaped_char(result: &mut String, c: char) {
+    match c {
+        '\\' if cfg!(unix) => result.push_str("\\\\"),
+        c if c.is_control() => {
+            let _ = write!(result, "\\u{{{:x}}}", c as u32);
+        }
+        c => result.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::path_to_string;
+
+    #[test]
+    fn test_valid_path() {
+        assert_eq!(path_to_string(Path::new("/tmp/Über.exe")), "/tmp/Über.exe");
+        assert_eq!(path_to_string(Path::new("/tmp/a\nb")), "/tmp/a\\u{a}b");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_paths() {
+        use super::extended_length_path;
+
+        let path = extended_length_path(Path::new("C:\\Users\\.\\user\\..\\Public"));
+        assert_eq!(path, Path::new("\\\\?\\C:\\Users\\Public"));
+        assert_eq!(path_to_string(&path), "C:\\Users\\Public");
+
+        let path = extended_length_path(Path::new("\\\\server\\share\\file.exe"));
+        assert_eq!(path, Path::new("\\\\?\\UNC\\server\\share\\file.exe"));
+        assert_eq!(path_to_string(&path), "\\\\server\\share\\file.exe");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_invalid_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/tmp/a\xff\xfeb\\c.exe"));
+        assert_eq!(path_to_string(path), "/tmp/a\\xff\\xfeb\\\\c.exe");
+    }
+}
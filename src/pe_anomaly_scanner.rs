@@ -0,0 +1,365 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+
+use goblin::pe::section_table::{SectionTable, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_WRITE};
+use maplit::hashset;
+use serde_json::json;
+
+use crate::csv_line::CsvLine;
+use crate::executable_scanner::{Executable, ExecutableCheck};
+use crate::pe_file::PeFile;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+/// overlays which are smaller than this are not reported
+const MIN_OVERLAY_SIZE: usize = 64 * 1024;
+
+/// names of sections which are created by packers and protectors, and the name of
+/// the packer
+static PACKER_SECTIONS: &[(&str, &str)] = &[
+    ("UPX0", "UPX"),
+    ("UPX1", "UPX"),
+    ("UPX2", "UPX"),
+    (".aspack", "ASPack"),
+    (".adata", "ASPack"),
+    (".petite", "Petite"),
+    (".nsp0", "NsPack"),
+    (".nsp1", "NsPack"),
+    (".nsp2", "NsPack"),
+    (".MPRESS1", "MPRESS"),
+    (".MPRESS2", "MPRESS"),
+    (".themida", "Themida"),
+    (".winlice", "WinLicense"),
+    (".vmp0", "VMProtect"),
+    (".vmp1", "VMProtect"),
+    (".vmp2", "VMProtect"),
+    (".enigma1", "Enigma"),
+    (".enigma2", "Enigma"),
+    ("PEC2", "PECompact"),
+    ("pec1", "PECompact"),
+    ("pec2", "PECompact"),
+    (".packed", "RLPack"),
+    (".RLPack", "RLPack"),
+    ("MEW", "MEW"),
+    (".perplex", "Perplex"),
+    ("kkrunchy", "kkrunchy"),
+    (".yP", "Y0da Protector"),
+    (".y0da", "Y0da Protector"),
+    (".spack", "Simple Pack"),
+    (".boom", "The Boomerang"),
+    ("ExeS", "EXE Stealth"),
+    (".ccg", "CCG"),
+    (".taz", "PESpin"),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Anomaly {
+    /// a section which is both writable and executable
+    WritableExecutable,
+
+    /// a section whose name is known from packers
+    PackerSection,
+
+    /// a section whose name is empty or contains unprintable characters
+    AbnormalSectionName,
+
+    /// an overlay which is larger than the sections
+    LargeOverlay,
+
+    /// TLS callbacks, which are executed before the entry point
+    TlsCallbacks,
+
+    /// an entry point in a section without raw data, which is typical for packers
+    /// which unpack the code into this section
+    EntryPointInEmptySection,
+
+    /// an entry point which lies outside of all sections
+    EntryPointOutsideSections,
+}
+
+impl Anomaly {
+    fn name(&self) -> &'static str {
+        match self {
+            Anomaly::WritableExecutable => "writable-executable-section",
+            Anomaly::PackerSection => "packer-section",
+            Anomaly::AbnormalSectionName => "abnormal-section-name",
+            Anomaly::LargeOverlay => "large-overlay",
+            Anomaly::TlsCallbacks => "tls-callbacks",
+            Anomaly::EntryPointInEmptySection => "entry-point-in-empty-section",
+            Anomaly::EntryPointOutsideSections => "entry-point-outside-sections",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Anomaly::WritableExecutable => "writable and executable sections",
+            Anomaly::PackerSection => "sections of a packer",
+            Anomaly::AbnormalSectionName => "sections with abnormal names",
+            Anomaly::LargeOverlay => "overlay which is larger than the sections",
+            Anomaly::TlsCallbacks => "TLS callbacks",
+            Anomaly::EntryPointInEmptySection => "entry point in a section without raw data",
+            Anomaly::EntryPointOutsideSections => "entry point outside of all sections",
+        }
+    }
+}
+
+/// the name of a section, without the padding
+fn raw_name(section: &SectionTable) -> &[u8] {
+    let length = section
+        .name
+        .iter()
+        .rposition(|c| *c != 0)
+        .map_or(0, |last| last + 1);
+    &section.name[..length]
+}
+
+/// the name of a section, with unprintable characters escaped
+fn section_name(section: &SectionTable) -> String {
+    raw_name(section)
+        .iter()
+        .flat_map(|c| std::ascii::escape_default(*c))
+        .map(char::from)
+        .collect()
+}
+
+fn is_abnormal_name(section: &SectionTable) -> bool {
+    let name = raw_name(section);
+    name.is_empty() || name.iter().any(|c| !(0x20..0x7f).contains(c))
+}
+
+/// the anomalies of `pe`, and the details of every anomaly
+fn anomalies(pe: &PeFile) -> Vec<(Anomaly, String)> {
+    let mut anomalies = Vec::new();
+    let sections = pe.sections();
+
+    let writable_executable: Vec<_> = sections
+        .iter()
+        .filter(|section| {
+            let flags = IMAGE_SCN_MEM_WRITE | IMAGE_SCN_MEM_EXECUTE;
+            section.characteristics & flags == flags
+        })
+        .map(section_name)
+        .collect();
+    if !writable_executable.is_empty() {
+        anomalies.push((Anomaly::WritableExecutable, writable_executable.join(", ")));
+    }
+
+    let packer_sections: Vec<_> = sections
+        .iter()
+        .filter_map(|section| {
+            let name = section_name(section);
+            PACKER_SECTIONS
+                .iter()
+                .find(|(packer_section, _)| *packer_section == name)
+                .map(|(_, packer)| format!("{} ({})", name, packer))
+        })
+        .collect();
+    if !packer_sections.is_empty() {
+        anomalies.push((Anomaly::PackerSection, packer_sections.join(", ")));
+    }
+
+    let abnormal_names: Vec<_> = sections
+        .iter()
+        .filter(|section| is_abnormal_name(section))
+        .map(|section| format!("'{}'", section_name(section)))
+        .collect();
+    if !abnormal_names.is_empty() {
+        anomalies.push((Anomaly::AbnormalSectionName, abnormal_names.join(", ")));
+    }
+
+    let overlay_size: usize = pe.overlay().iter().map(|part| part.len()).sum();
+    let sections_size: usize = sections
+        .iter()
+        .map(|section| section.size_of_raw_data as usize)
+        .sum();
+    if overlay_size >= MIN_OVERLAY_SIZE && overlay_size > sections_size {
+        anomalies.push((
+            Anomaly::LargeOverlay,
+            format!(
+                "{} bytes, the sections have {} bytes",
+                overlay_size, sections_size
+            ),
+        ));
+    }
+
+    let tls_callbacks = pe.tls_callbacks();
+    if !tls_callbacks.is_empty() {
+        let callbacks: Vec<_> = tls_callbacks
+            .iter()
+            .map(|address| format!("{:#x}", address))
+            .collect();
+        anomalies.push((Anomaly::TlsCallbacks, callbacks.join(", ")));
+    }
+
+    let entry_point = pe.entry_point();
+    if entry_point != 0 {
+        match pe.section_of(entry_point) {
+            Some(section) if section.size_of_raw_data == 0 => anomalies.push((
+                Anomaly::EntryPointInEmptySection,
+                format!("{:#x} in {}", entry_point, section_name(section)),
+            )),
+            Some(_) => (),
+            None => anomalies.push((
+                Anomaly::EntryPointOutsideSections,
+                format!("{:#x}", entry_point),
+            )),
+        }
+    }
+
+    anomalies
+}
+
+/// reports PE files with characteristics which are typical for packed or otherwise
+/// manipulated executables, like writable and executable sections, sections of known
+/// packers, large overlays, TLS callbacks and unusual entry points
+#[derive(Default)]
+pub struct PeAnomalyScanner {}
+
+impl Display for PeAnomalyScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PeAnomalyScanner")
+    }
+}
+
+impl ExecutableCheck for PeAnomalyScanner {
+    fn check(
+        &self,
+        found_in_file: &str,
+        _path: Option<&Path>,
+        _data: &[u8],
+        executable: Option<&Executable>,
+    ) -> Vec<Box<dyn ScannerFinding>> {
+        let pe = match executable {
+            Some(Executable::Pe(pe)) => pe,
+            _ => return Vec::new(),
+        };
+        anomalies(pe)
+            .into_iter()
+            .map(|(anomaly, details)| {
+                Box::new(PeAnomalyFinding {
+                    found_in_file: found_in_file.to_owned(),
+                    anomaly,
+                    details,
+                }) as Box<dyn ScannerFinding>
+            })
+            .collect()
+    }
+}
+
+struct PeAnomalyFinding {
+    found_in_file: String,
+    anomaly: Anomaly,
+    details: String,
+}
+
+impl PeAnomalyFinding {
+    fn details(&self) -> String {
+        format!("{}: {}", self.anomaly.description(), self.details)
+    }
+}
+
+impl Display for PeAnomalyFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "PE anomaly: {found_in_file} ({details})")
+    }
+}
+
+impl ScannerFinding for PeAnomalyFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "PeAnomaly",
+            self.anomaly.name(),
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "pe_anomalies",
+            "02_suspicious_file": self.found_in_file(),
+            "03_anomaly": self.anomaly.name(),
+            "04_details": self.details
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        match self.anomaly {
+            Anomaly::EntryPointInEmptySection | Anomaly::EntryPointOutsideSections => {
+                Severity::High
+            }
+            Anomaly::WritableExecutable | Anomaly::PackerSection => Severity::Medium,
+            Anomaly::AbnormalSectionName | Anomaly::LargeOverlay | Anomaly::TlsCallbacks => {
+                Severity::Low
+            }
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "pe_anomalies".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    fn rule(&self) -> Option<&str> {
+        Some(self.anomaly.name())
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        match self.anomaly {
+            // Obfuscated Files or Information: Software Packing
+            Anomaly::WritableExecutable
+            | Anomaly::PackerSection
+            | Anomaly::EntryPointInEmptySection
+            | Anomaly::EntryPointOutsideSections => vec!["T1027.002".to_owned()],
+            // Obfuscated Files or Information: Embedded Payloads
+            Anomaly::LargeOverlay => vec!["T1027.009".to_owned()],
+            Anomaly::AbnormalSectionName | Anomaly::TlsCallbacks => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use goblin::pe::section_table::SectionTable;
+
+    use super::{is_abnormal_name, section_name, PeAnomalyScanner};
+    use crate::executable_scanner::{Executable, ExecutableCheck};
+    use crate::test_executables::infected_elf;
+
+    fn section(name: &[u8]) -> SectionTable {
+        let mut section = SectionTable::default();
+        section.name[..name.len()].copy_from_slice(name);
+        section
+    }
+
+    #[test]
+    fn test_section_name() {
+        assert_eq!(section_name(&section(b".text")), ".text");
+        assert_eq!(section_name(&section(b"UPX0")), "UPX0");
+        assert_eq!(section_name(&section(b"a\x01b")), "a\\x01b");
+    }
+
+    #[test]
+    fn test_abnormal_name() {
+        assert!(!is_abnormal_name(&section(b".text")));
+        assert!(!is_abnormal_name(&section(b".MPRESS1")));
+        assert!(is_abnormal_name(&section(b"")));
+        assert!(is_abnormal_name(&section(b"\xe4\xbd\xa0")));
+    }
+
+    #[test]
+    fn test_other_formats() {
+        let elf = infected_elf(&[0x90; 0x100], true);
+        let executable = Executable::parse("elf", &elf);
+        let scanner = PeAnomalyScanner::default();
+        let findings = scanner.check("elf", None, &elf, executable.as_ref());
+        assert!(findings.is_empty());
+    }
+}
@@ -0,0 +1,380 @@
+use std::convert::TryInto;
+
+use anyhow::Result;
+use goblin::pe::header::SIZEOF_COFF_HEADER;
+use goblin::pe::section_table::{SectionTable, IMAGE_SCN_MEM_EXECUTE};
+use goblin::pe::PE;
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+use crate::ordlookup::ordinal_name;
+
+const CHECKSUM_OFFSET: usize = 64;
+const DATA_DIRECTORIES_OFFSET_32: usize = 96;
+const DATA_DIRECTORIES_OFFSET_64: usize = 112;
+const SIZEOF_DATA_DIRECTORY: usize = 8;
+const CERTIFICATE_TABLE_INDEX: usize = 4;
+const SIZEOF_WIN_CERTIFICATE_HEADER: usize = 8;
+const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 2;
+
+/// the offset of `AddressOfCallBacks` in the TLS directory of 32 bit and 64 bit files
+const TLS_CALLBACKS_OFFSET_32: usize = 12;
+const TLS_CALLBACKS_OFFSET_64: usize = 24;
+
+/// the number of TLS callbacks which are read at most
+const MAX_TLS_CALLBACKS: usize = 64;
+
+/// a parsed PE file, which is shared by all scanners which need to
+/// understand the structure of windows executables
+pub struct PeFile<'a> {
+    pe: PE<'a>,
+    data: &'a [u8],
+}
+
+impl<'a> PeFile<'a> {
+    /// parses `data` as PE file. Returns `None` if `data` does not look like a
+    /// PE file at all, and an error if it does but cannot be parsed.
+    pub fn parse(data: &'a [u8]) -> Result<Option<Self>> {
+        if !data.starts_with(b"MZ") {
+            return Ok(None);
+        }
+        let pe = PE::parse(data)?;
+        Ok(Some(Self { pe, data }))
+    }
+
+    /// computes the import hash, using the same algorithm as `pefile`. Like there,
+    /// imports by ordinal from `ws2_32.dll`, `wsock32.dll` and `oleaut32.dll` are
+    /// resolved to function names, and all others are represented as `ord<n>`
+    pub fn imphash(&self) -> Option<[u8; 16]> {
+        if self.pe.imports.is_empty() {
+            return None;
+        }
+
+        let imports: Vec<String> = self
+            .pe
+            .imports
+            .iter()
+            .map(|import| {
+                let dll = import.dll.to_lowercase();
+                let dll = match dll.rsplit_once('.') {
+                    Some((name, "dll" | "ocx" | "sys")) => name.to_owned(),
+                    _ => dll,
+                };
+                let function = if import.name.starts_with("ORDINAL ") {
+                    match ordinal_name(import.dll, import.ordinal) {
+                        Some(name) => name.to_lowercase(),
+                        None => format!("ord{}", import.ordinal),
+                    }
+                } else {
+                    import.name.to_lowercase()
+                };
+                format!("{}.{}", dll, function)
+            })
+            .collect();
+
+        let mut hasher = Md5::new();
+        hasher.update(imports.join(",").as_bytes());
+        Some(hasher.finalize().into())
+    }
+
+    /// the names and the raw data of all sections which contain code
+    pub fn executable_sections(&self) -> Vec<(String, &'a [u8])> {
+        self.pe
+            .sections
+            .iter()
+            .filter(|section| section.characteristics & IMAGE_SCN_MEM_EXECUTE != 0)
+            .map(|section| {
+                let start = (section.pointer_to_raw_data as usize).min(self.data.len());
+                let end = start
+                    .saturating_add(section.size_of_raw_data as usize)
+                    .min(self.data.len());
+                let name = section.name().unwrap_or_default().to_owned();
+                (name, &self.data[start..end])
+            })
+            .collect()
+    }
+
+    /// the section headers
+    pub fn sections(&self) -> &[SectionTable] {
+        &self.pe.sections
+    }
+
+    /// the address of the entry point, relative to the image base. DLLs may have no
+    /// entry point, in which case this is 0
+    pub fn entry_point(&self) -> usize {
+        self.pe.entry
+    }
+
+    /// the section which contains `rva`, an address relative to the image base
+    pub fn section_of(&self, rva: usize) -> Option<&SectionTable> {
+        self.pe.sections.iter().find(|section| {
+            let start = section.virtual_address as usize;
+            let size = section.virtual_size.max(section.size_of_raw_data) as usize;
+            (start..start.saturating_add(size)).contains(&rva)
+        })
+    }
+
+    /// the file offset of `rva`, if it lies in the raw data of a section
+    fn offset_of(&self, rva: usize) -> Option<usize> {
+        let section = self.section_of(rva)?;
+        let delta = rva - section.virtual_address as usize;
+        (delta < section.size_of_raw_data as usize)
+            .then(|| section.pointer_to_raw_data as usize + delta)
+    }
+
+    /// the data which is appended to the file after the last section and which is not
+    /// loaded into memory. The certificate table is not part of the overlay, but data
+    /// which has been appended after it is, because a payload can be hidden there
+    /// without invalidating the signature. So the overlay consists of up to two parts
+    pub fn overlay(&self) -> Vec<&'a [u8]> {
+        let end = self
+            .pe
+            .sections
+            .iter()
+            .filter(|section| section.size_of_raw_data > 0)
+            .map(|section| section.pointer_to_raw_data as usize + section.size_of_raw_data as usize)
+            .max()
+            .unwrap_or(self.data.len())
+            .min(self.data.len());
+
+        // the certificate table is usually appended to the end of the file
+        let certificate_table = self
+            .pe
+            .header
+            .optional_header
+            .and_then(|header| *header.data_directories.get_certificate_table())
+            .filter(|table| table.size > 0)
+            .map(|table| {
+                let start = table.virtual_address as usize;
+                start..start.saturating_add(table.size as usize)
+            })
+            .filter(|table| (end..self.data.len()).contains(&table.start));
+        let parts = match certificate_table {
+            Some(table) => vec![
+                &self.data[end..table.start],
+                self.data.get(table.end..).unwrap_or_default(),
+            ],
+            None => vec![&self.data[end..]],
+        };
+        parts.into_iter().filter(|part| !part.is_empty()).collect()
+    }
+
+    /// the addresses (relative to the image base) of the TLS callbacks, which are
+    /// executed before the entry point
+    pub fn tls_callbacks(&self) -> Vec<usize> {
+        let table = match self.pe.header.optional_header {
+            Some(header) => match header.data_directories.get_tls_table() {
+                Some(table) if table.size > 0 => *table,
+                _ => return Vec::new(),
+            },
+            None => return Vec::new(),
+        };
+        let (pointer_size, callbacks_offset) = if self.pe.is_64 {
+            (8, TLS_CALLBACKS_OFFSET_64)
+        } else {
+            (4, TLS_CALLBACKS_OFFSET_32)
+        };
+        let read_pointer = |offset: usize| -> Option<usize> {
+            let bytes = self.data.get(offset..offset + pointer_size)?;
+            let mut value = [0; 8];
+            value[..pointer_size].copy_from_slice(bytes);
+            Some(u64::from_le_bytes(value) as usize)
+        };
+        let to_rva = |address: usize| address.checked_sub(self.pe.image_base);
+
+        let callbacks = self
+            .offset_of(table.virtual_address as usize)
+            .and_then(|directory| read_pointer(directory + callbacks_offset))
+            .filter(|address| *address != 0)
+            .and_then(to_rva)
+            .and_then(|rva| self.offset_of(rva));
+        let mut result = Vec::new();
+        if let Some(mut offset) = callbacks {
+            while let Some(address) = read_pointer(offset).filter(|address| *address != 0) {
+                result.push(to_rva(address).unwrap_or(address));
+                if result.len() == MAX_TLS_CALLBACKS {
+                    break;
+                }
+                offset += pointer_size;
+            }
+        }
+        result
+    }
+
+    /// computes the SHA256 Authenticode hash, which is the hash of the file without
+    /// its checksum, the certificate table entry and the certificate table itself.
+    /// This hash is the same for signed and unsigned versions of a file.
+    pub fn authenticode_hash(&self) -> Option<[u8; 32]> {
+        self.authenticode_digest::<Sha256>()?.try_into().ok()
+    }
+
+    /// computes the Authenticode hash with the digest algorithm `D`, which is needed
+    /// to check a signature which does not use SHA256
+    pub fn authenticode_digest<D: Digest>(&self) -> Option<Vec<u8>> {
+        let optional_header = self.pe.header.optional_header?;
+        let optional_header_offset =
+            self.pe.header.dos_header.pe_pointer as usize + 4 + SIZEOF_COFF_HEADER;
+        let checksum_offset = optional_header_offset + CHECKSUM_OFFSET;
+        let data_directories_offset = optional_header_offset
+            + if self.pe.is_64 {
+                DATA_DIRECTORIES_OFFSET_64
+            } else {
+                DATA_DIRECTORIES_OFFSET_32
+            };
+
+        let mut hasher = D::new();
+        hasher.update(self.data.get(..checksum_offset)?);
+
+        let number_of_data_directories =
+            optional_header.windows_fields.number_of_rva_and_sizes as usize;
+        if number_of_data_directories > CERTIFICATE_TABLE_INDEX {
+            let certificate_entry_offset =
+                data_directories_offset + CERTIFICATE_TABLE_INDEX * SIZEOF_DATA_DIRECTORY;
+            let (certificate_start, certificate_end) =
+                match optional_header.data_directories.get_certificate_table() {
+                    Some(table) if table.size > 0 => (
+                        table.virtual_address as usize,
+                        table.virtual_address as usize + table.size as usize,
+                    ),
+                    _ => (self.data.len(), self.data.len()),
+                };
+
+            hasher.update(self.data.get(checksum_offset + 4..certificate_entry_offset)?);
+            hasher.update(
+                self.data
+                    .get(certificate_entry_offset + SIZEOF_DATA_DIRECTORY..certificate_start)?,
+            );
+            hasher.update(self.data.get(certificate_end..)?);
+        } else {
+            hasher.update(self.data.get(checksum_offset + 4..)?);
+        }
+
+        Some(hasher.finalize().to_vec())
+    }
+
+    /// the PKCS#7 signed data of the embedded Authenticode signature. Returns `None` if
+    /// the file has no certificate table, and an empty slice if the certificate table
+    /// is invalid or does not contain an Authenticode signature.
+    pub fn signature(&self) -> Option<&'a [u8]> {
+        let optional_header = self.pe.header.optional_header?;
+        let table = match optional_header.data_directories.get_certificate_table() {
+            Some(table) if table.size > 0 => table,
+            _ => return None,
+        };
+
+        // the certificate table is not mapped into memory, so its address is an offset
+        let start = table.virtual_address as usize;
+        let certificate = self
+            .data
+            .get(start..start.saturating_add(table.size as usize))
+            .and_then(|table| {
+                let length = u32::from_le_bytes(table.get(0..4)?.try_into().ok()?) as usize;
+                let certificate_type = u16::from_le_bytes(table.get(6..8)?.try_into().ok()?);
+                (certificate_type == WIN_CERT_TYPE_PKCS_SIGNED_DATA)
+                    .then(|| table.get(SIZEOF_WIN_CERTIFICATE_HEADER..length))
+                    .flatten()
+            });
+        Some(certificate.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PeFile;
+
+    const IMAGE_BASE: u32 = 0x40_0000;
+    const OPTIONAL_HEADER: usize = 0x58;
+    const DATA_DIRECTORIES: usize = OPTIONAL_HEADER + 96;
+    const CERTIFICATE_TABLE: usize = 0x500;
+
+    fn put(data: &mut [u8], offset: usize, value: &[u8]) {
+        data[offset..offset + value.len()].copy_from_slice(value);
+    }
+
+    fn put_data_directory(data: &mut [u8], index: usize, address: u32, size: u32) {
+        put(data, DATA_DIRECTORIES + 8 * index, &address.to_le_bytes());
+        put(data, DATA_DIRECTORIES + 8 * index + 4, &size.to_le_bytes());
+    }
+
+    /// a 32 bit PE file with a single section, which contains the TLS directory and two
+    /// TLS callbacks, followed by an overlay of 256 bytes. A signed file has a
+    /// certificate table after the overlay, and 128 more bytes after the certificate
+    fn executable(signed: bool) -> Vec<u8> {
+        let mut data = vec![0; if signed { 0x590 } else { 0x500 }];
+        put(&mut data, 0, b"MZ");
+        put(&mut data, 0x3c, &0x40u32.to_le_bytes());
+        put(&mut data, 0x40, b"PE\0\0");
+
+        // COFF header: i386, one section, 224 bytes of optional header, executable
+        for (offset, value) in [(0x44, 0x14c), (0x46, 1), (0x54, 224), (0x56, 0x102)] {
+            put(&mut data, offset, &(value as u16).to_le_bytes());
+        }
+
+        // optional header: magic, entry point, image base, alignments, sizes, subsystem
+        put(&mut data, OPTIONAL_HEADER, &0x10bu16.to_le_bytes());
+        for (offset, value) in [
+            (16, 0x1040),
+            (28, IMAGE_BASE),
+            (32, 0x1000),
+            (36, 0x200),
+            (56, 0x2000),
+            (60, 0x200),
+            (92, 16),
+        ] {
+            put(&mut data, OPTIONAL_HEADER + offset, &value.to_le_bytes());
+        }
+        put(&mut data, OPTIONAL_HEADER + 68, &2u16.to_le_bytes());
+
+        // the TLS directory at the start of the section
+        put_data_directory(&mut data, 9, 0x1000, 24);
+
+        // the section .text at 0x1000, whose raw data is at 0x200
+        put(&mut data, 0x138, b".text");
+        for (offset, value) in [(8, 0x100), (12, 0x1000), (16, 0x200), (20, 0x200)] {
+            put(&mut data, 0x138 + offset, &(value as u32).to_le_bytes());
+        }
+        put(&mut data, 0x138 + 36, &0x6000_0020u32.to_le_bytes());
+
+        // AddressOfCallBacks, and the callbacks which it points to
+        put(&mut data, 0x200 + 12, &(IMAGE_BASE + 0x1020).to_le_bytes());
+        put(&mut data, 0x220, &(IMAGE_BASE + 0x1040).to_le_bytes());
+        put(&mut data, 0x224, &(IMAGE_BASE + 0x1050).to_le_bytes());
+
+        for byte in &mut data[0x400..CERTIFICATE_TABLE] {
+            *byte = b'A';
+        }
+        if signed {
+            // the certificate table, with a PKCS#7 certificate of 8 bytes
+            put_data_directory(&mut data, 4, CERTIFICATE_TABLE as u32, 16);
+            put(&mut data, CERTIFICATE_TABLE, &16u32.to_le_bytes());
+            put(&mut data, CERTIFICATE_TABLE + 4, &0x0200u16.to_le_bytes());
+            put(&mut data, CERTIFICATE_TABLE + 6, &2u16.to_le_bytes());
+            for byte in &mut data[CERTIFICATE_TABLE + 16..] {
+                *byte = b'B';
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_overlay() {
+        let data = executable(false);
+        let pe = PeFile::parse(&data).unwrap().unwrap();
+        assert_eq!(pe.overlay(), vec![&[b'A'; 0x100][..]]);
+
+        // the data after the certificate table is part of the overlay, the table is not
+        let data = executable(true);
+        let pe = PeFile::parse(&data).unwrap().unwrap();
+        assert_eq!(pe.overlay(), vec![&[b'A'; 0x100][..], &[b'B'; 0x80][..]]);
+        let certificate = &data[CERTIFICATE_TABLE + 8..CERTIFICATE_TABLE + 16];
+        assert_eq!(pe.signature(), Some(certificate));
+    }
+
+    #[test]
+    fn test_tls_callbacks() {
+        let data = executable(false);
+        let pe = PeFile::parse(&data).unwrap().unwrap();
+        assert_eq!(pe.tls_callbacks(), vec![0x1040, 0x1050]);
+        assert_eq!(pe.entry_point(), 0x1040);
+    }
+}
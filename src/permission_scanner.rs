@@ -0,0 +1,532 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::directory_cache::DirectoryCache;
+use crate::filescanner::FileScanner;
+use crate::path_string::path_to_string;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+use crate::system_location::{is_writable_by_design, locate, Location};
+
+/// the SIDs of the groups which contain (almost) every user, and their SDDL aliases
+const BROAD_GROUPS: &[(&str, &str)] = &[
+    ("S-1-1-0", "WD"),
+    ("S-1-5-11", "AU"),
+    ("S-1-5-32-545", "BU"),
+];
+
+const SE_DACL_PRESENT: u16 = 0x0004;
+const ACCESS_ALLOWED_ACE_TYPE: u8 = 0;
+const INHERIT_ONLY_ACE: u8 = 0x08;
+const INHERITED_ACE: u8 = 0x10;
+
+const FILE_ALL_ACCESS: u32 = 0x001f_01ff;
+const FILE_WRITE_DATA: u32 = 0x0000_0002;
+const FILE_APPEND_DATA: u32 = 0x0000_0004;
+const WRITE_DAC: u32 = 0x0004_0000;
+const WRITE_OWNER: u32 = 0x0008_0000;
+const GENERIC_WRITE: u32 = 0x4000_0000;
+const GENERIC_ALL: u32 = 0x1000_0000;
+
+/// the rights which allow to replace or modify a program
+const WRITE_RIGHTS: u32 =
+    FILE_WRITE_DATA | FILE_APPEND_DATA | WRITE_DAC | WRITE_OWNER | GENERIC_WRITE | GENERIC_ALL;
+
+/// the anomalies of unix modes are only detected on unix
+#[cfg_attr(not(unix), allow(dead_code))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Anomaly {
+    /// a file or directory in a system location, which is writable by everyone
+    WorldWritable,
+
+    /// a file or directory in a system location, whose ACL allows everyone to modify it
+    WeakAcl,
+
+    /// a setuid or setgid file owned by root in a temp directory
+    RootOwnedSetuid,
+
+    /// an executable file owned by root in a temp directory
+    RootOwnedExecutable,
+}
+
+impl Anomaly {
+    fn name(&self) -> &'static str {
+        match self {
+            Anomaly::WorldWritable => "world-writable",
+            Anomaly::WeakAcl => "weak-acl",
+            Anomaly::RootOwnedSetuid => "root-owned-setuid",
+            Anomaly::RootOwnedExecutable => "root-owned-executable",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Anomaly::WorldWritable => "writable by everyone in a system location",
+            Anomaly::WeakAcl => "ACL allows everyone to modify it in a system location",
+            Anomaly::RootOwnedSetuid => "setuid or setgid file of root in a temp directory",
+            Anomaly::RootOwnedExecutable => "executable file of root in a temp directory",
+        }
+    }
+}
+
+/// the anomaly of a file or directory with the unix `mode`, which is owned by `uid`
+#[cfg(unix)]
+fn mode_anomaly(location: Location, mode: u32, uid: u32, is_directory: bool) -> Option<Anomaly> {
+    match location {
+        Location::UnixSystem if mode & 0o002 != 0 => Some(Anomaly::WorldWritable),
+        Location::Temp if uid == 0 && !is_directory => {
+            if mode & 0o6000 != 0 {
+                Some(Anomaly::RootOwnedSetuid)
+            } else if mode & 0o111 != 0 {
+                Some(Anomaly::RootOwnedExecutable)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// formats a unix mode like `ls -l` does, e.g. `rwsr-xr-x (4755)`
+#[cfg(unix)]
+fn mode_string(mode: u32) -> String {
+    let special = [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')];
+    let mut result = String::new();
+    for (index, (special_bit, special_char)) in special.iter().enumerate() {
+        let shift = 6 - 3 * index;
+        let bits = (mode >> shift) & 0o7;
+        result.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        result.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        result.push(match (bits & 0o1 != 0, mode & special_bit != 0) {
+            (true, true) => *special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    format!("{} ({:04o})", result, mode & 0o7777)
+}
+
+/// converts a binary SID to its string representation, like `S-1-5-32-545`
+fn sid_string(sid: &[u8]) -> Option<String> {
+    let revision = *sid.first()?;
+    let count = *sid.get(1)? as usize;
+    let authority = sid
+        .get(2..8)?
+        .iter()
+        .fold(0u64, |authority, b| (authority << 8) | *b as u64);
+    let mut result = format!("S-{}-{}", revision, authority);
+    for index in 0..count {
+        let b = sid.get(8 + 4 * index..12 + 4 * index)?;
+        let sub_authority = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        result.push_str(&format!("-{}", sub_authority));
+    }
+    Some(result)
+}
+
+/// formats an access allowed ACE in SDDL, like `(A;OICI;FA;;;WD)`, if it grants a
+/// group which contains every user the right to modify a file or directory.
+/// Inherited ACEs of files are ignored, because they are reported for the directory
+fn weak_ace(flags: u8, mask: u32, sid: &str, is_directory: bool) -> Option<String> {
+    if flags & INHERIT_ONLY_ACE != 0 || (flags & INHERITED_ACE != 0 && !is_directory) {
+        return None;
+    }
+    let (_, trustee) = BROAD_GROUPS.iter().find(|(group, _)| *group == sid)?;
+    if mask & WRITE_RIGHTS == 0 {
+        return None;
+    }
+
+    let ace_flags: String = [
+        (0x01, "OI"),
+        (0x02, "CI"),
+        (0x04, "NP"),
+        (INHERITED_ACE, "ID"),
+    ]
+    .iter()
+    .filter(|(flag, _)| flags & flag != 0)
+    .map(|(_, name)| *name)
+    .collect();
+    let rights = if mask & FILE_ALL_ACCESS == FILE_ALL_ACCESS {
+        "FA".to_owned()
+    } else if mask & GENERIC_ALL != 0 {
+        "GA".to_owned()
+    } else {
+        format!("0x{:x}", mask)
+    };
+    Some(format!("(A;{};{};;;{})", ace_flags, rights, trustee))
+}
+
+/// reports permissions which allow an attacker to replace programs or to escalate
+/// their privileges:
+///
+///  - files and directories in system locations (like `/usr` or `C:\Program Files`),
+///    which are writable by everyone (unix mode), or whose ACL grants Everyone,
+///    Authenticated Users or Users the right to modify them (Windows, or NTFS
+///    filesystems which are mounted with ntfs-3g)
+///  - executable or setuid files owned by root in temp directories (like `/tmp`)
+///
+/// Directories are checked when the first of their files is scanned, like the
+/// ransomware scanner does. Subdirectories of `System32` which are writable by design,
+/// like `Tasks` or `spool\PRINTERS`, are not checked
+#[derive(Default)]
+pub struct PermissionScanner {
+    checked_directories: DirectoryCache<Location>,
+}
+
+impl Display for PermissionScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PermissionScanner")
+    }
+}
+
+impl FileScanner for PermissionScanner {
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let directory = match entry.path().parent() {
+            Some(directory) => directory,
+            None => return vec![],
+        };
+        let (location, is_new) = self.location_of(directory);
+        if location == Location::Other {
+            return vec![];
+        }
+
+        let mut checks = vec![(entry.path(), false)];
+        if is_new {
+            checks.push((directory, true));
+        }
+        let mut results = Vec::new();
+        for (path, is_directory) in checks {
+            match check(path, location, is_directory) {
+                Ok(findings) => results.extend(
+                    findings
+                        .into_iter()
+                        .map(|finding| Ok(Box::new(finding) as Box<dyn ScannerFinding>)),
+                ),
+                Err(why) => results.push(Err(anyhow!(
+                    "unable to check the permissions of '{}': {}",
+                    path.display(),
+                    why
+                ))),
+            }
+        }
+        results
+    }
+
+    /// every copy of a file has its own permissions and location
+    fn depends_on_path(&self) -> bool {
+        true
+    }
+}
+
+impl PermissionScanner {
+    /// the location of `directory`, and if it is checked for the first time
+    fn location_of(&self, directory: &Path) -> (Location, bool) {
+        self.checked_directories
+            .get_or_insert_with(directory, || match locate(directory) {
+                (Location::WindowsSystem, Some(root)) if is_writable_by_design(directory, root) => {
+                    Location::Other
+                }
+                (location, _) => location,
+            })
+    }
+}
+
+fn check(path: &Path, location: Location, is_directory: bool) -> Result<Vec<PermissionFinding>> {
+    let finding = |anomaly, permissions, owner| PermissionFinding {
+        found_in_file: path_to_string(path),
+        anomaly,
+        is_directory,
+        permissions,
+        owner,
+    };
+    let mut findings = Vec::new();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = std::fs::metadata(path)?;
+        if let Some(anomaly) = mode_anomaly(location, metadata.mode(), metadata.uid(), is_directory)
+        {
+            findings.push(finding(
+                anomaly,
+                mode_string(metadata.mode()),
+                Some(metadata.uid()),
+            ));
+        }
+    }
+
+    if location == Location::WindowsSystem {
+        if let Some(descriptor) = security_descriptor(path)? {
+            for ace in weak_aces(&descriptor, is_directory) {
+                findings.push(finding(Anomaly::WeakAcl, ace, None));
+            }
+        }
+    }
+    Ok(findings)
+}
+
+/// the ACEs of a self-relative security descriptor, which allow everyone to modify a
+/// file or directory
+fn weak_aces(descriptor: &[u8], is_directory: bool) -> Vec<String> {
+    let le_u16 = |offset: usize| {
+        descriptor
+            .get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+    let le_u32 = |offset: usize| {
+        descriptor
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let (control, dacl) = match (le_u16(2), le_u32(16)) {
+        (Some(control), Some(dacl)) => (control, dacl as usize),
+        _ => return Vec::new(),
+    };
+    if control & SE_DACL_PRESENT == 0 || dacl == 0 {
+        // a NULL DACL grants full access to everyone
+        return vec!["D:NO_ACCESS_CONTROL".to_owned()];
+    }
+
+    let mut aces = Vec::new();
+    let mut offset = dacl + 8;
+    for _ in 0..le_u16(dacl + 4).unwrap_or_default() {
+        let (ace_type, flags, size) = match (
+            descriptor.get(offset),
+            descriptor.get(offset + 1),
+            le_u16(offset + 2),
+        ) {
+            (Some(ace_type), Some(flags), Some(size)) if size >= 8 => {
+                (*ace_type, *flags, size as usize)
+            }
+            _ => break,
+        };
+        if ace_type == ACCESS_ALLOWED_ACE_TYPE {
+            let sid = descriptor
+                .get(offset + 8..offset + size)
+                .and_then(sid_string);
+            if let Some(ace) = le_u32(offset + 4)
+                .zip(sid)
+                .and_then(|(mask, sid)| weak_ace(flags, mask, &sid, is_directory))
+            {
+                aces.push(ace);
+            }
+        }
+        offset += size;
+    }
+    aces
+}
+
+/// the security descriptor of `path`, which ntfs-3g provides as extended attribute
+#[cfg(unix)]
+fn security_descriptor(path: &Path) -> Result<Option<Vec<u8>>> {
+    Ok(xattr::get(path, "system.ntfs_acl").ok().flatten())
+}
+
+/// the security descriptor of `path`, with its DACL only
+#[cfg(windows)]
+fn security_descriptor(path: &Path) -> Result<Option<Vec<u8>>> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::GetLastError;
+    use windows::Win32::Security::{
+        GetFileSecurityW, DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR,
+    };
+
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut length = 0;
+    unsafe {
+        GetFileSecurityW(
+            PCWSTR(name.as_ptr()),
+            DACL_SECURITY_INFORMATION.0,
+            PSECURITY_DESCRIPTOR::default(),
+            0,
+            &mut length,
+        )
+    };
+    let mut descriptor = vec![0u8; length as usize];
+    let success = unsafe {
+        GetFileSecurityW(
+            PCWSTR(name.as_ptr()),
+            DACL_SECURITY_INFORMATION.0,
+            PSECURITY_DESCRIPTOR(descriptor.as_mut_ptr() as *mut std::ffi::c_void),
+            length,
+            &mut length,
+        )
+    };
+    match success.as_bool() {
+        true => Ok(Some(descriptor)),
+        false => Err(anyhow!("unable to read the ACL (error {})", unsafe {
+            GetLastError().0
+        })),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn security_descriptor(_path: &Path) -> Result<Option<Vec<u8>>> {
+    Ok(None)
+}
+
+struct PermissionFinding {
+    found_in_file: String,
+    anomaly: Anomaly,
+    is_directory: bool,
+
+    /// the unix mode, or the offending ACE in SDDL
+    permissions: String,
+
+    /// the uid of the owner, on unix
+    owner: Option<u32>,
+}
+
+impl PermissionFinding {
+    fn details(&self) -> String {
+        let kind = if self.is_directory {
+            "directory"
+        } else {
+            "file"
+        };
+        format!(
+            "{} {}: {}",
+            kind,
+            self.anomaly.description(),
+            self.permissions
+        )
+    }
+}
+
+impl Display for PermissionFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "permission anomaly: {found_in_file} ({details})")
+    }
+}
+
+impl ScannerFinding for PermissionFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "Permissions",
+            self.anomaly.name(),
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "permissions",
+            "02_suspicious_file": self.found_in_file(),
+            "03_anomaly": self.anomaly.name(),
+            "04_directory": self.is_directory,
+            "05_permissions": self.permissions,
+            "06_owner": self.owner
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        match self.anomaly {
+            Anomaly::RootOwnedExecutable => Severity::Medium,
+            _ => Severity::High,
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "permissions".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    /// the name of the anomaly, so that the findings policy can match it
+    fn rule(&self) -> Option<&str> {
+        Some(self.anomaly.name())
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        match self.anomaly {
+            // File and Directory Permissions Modification
+            Anomaly::WorldWritable | Anomaly::WeakAcl => vec!["T1222".to_owned()],
+            // Abuse Elevation Control Mechanism: Setuid and Setgid
+            Anomaly::RootOwnedSetuid => vec!["T1548.001".to_owned()],
+            Anomaly::RootOwnedExecutable => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sid_string, weak_aces};
+    use crate::system_location::Location;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode_anomalies() {
+        use super::{mode_anomaly, mode_string, Anomaly};
+
+        assert_eq!(
+            mode_anomaly(Location::UnixSystem, 0o100777, 0, false),
+            Some(Anomaly::WorldWritable)
+        );
+        assert_eq!(mode_anomaly(Location::Other, 0o100777, 0, false), None);
+        assert_eq!(
+            mode_anomaly(Location::Temp, 0o104755, 0, false),
+            Some(Anomaly::RootOwnedSetuid)
+        );
+        assert_eq!(mode_anomaly(Location::Temp, 0o100755, 1000, false), None);
+        assert_eq!(mode_anomaly(Location::Temp, 0o41777, 0, true), None);
+        assert_eq!(mode_string(0o104755), "rwsr-xr-x (4755)");
+        assert_eq!(mode_string(0o41776), "rwxrwxrwT (1776)");
+    }
+
+    const USERS: [u8; 16] = [1, 2, 0, 0, 0, 0, 0, 5, 32, 0, 0, 0, 0x21, 2, 0, 0];
+
+    #[test]
+    fn test_sid_string() {
+        assert_eq!(sid_string(&USERS).unwrap(), "S-1-5-32-545");
+        assert_eq!(sid_string(&USERS[..12]), None);
+    }
+
+    #[test]
+    fn test_weak_aces() {
+        // a descriptor whose DACL grants Everyone full control, and Users read access
+        let descriptor = |flags: u8| {
+            let mut descriptor = vec![
+                1, 0, 0x04, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0, 0,
+            ];
+            descriptor.extend([2, 0, 52, 0, 2, 0, 0, 0].iter());
+            descriptor.extend([0, flags, 20, 0, 0xff, 0x01, 0x1f, 0].iter());
+            descriptor.extend([1, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0].iter());
+            descriptor.extend([0, flags, 24, 0, 0xa9, 0, 0x12, 0].iter());
+            descriptor.extend(USERS.iter());
+            descriptor
+        };
+        assert_eq!(
+            weak_aces(&descriptor(0x03), false),
+            vec!["(A;OICI;FA;;;WD)"]
+        );
+        assert_eq!(
+            weak_aces(&descriptor(0x13), true),
+            vec!["(A;OICIID;FA;;;WD)"]
+        );
+        assert!(weak_aces(&descriptor(0x13), false).is_empty());
+        assert!(weak_aces(&descriptor(0x0b), true).is_empty());
+    }
+
+    #[test]
+    fn test_null_dacl() {
+        let null_dacl = [
+            1, 0, 0, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_eq!(weak_aces(&null_dacl, false), vec!["D:NO_ACCESS_CONTROL"]);
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+use std::ffi::{c_void, CStr, CString};
+use std::fmt::Display;
+use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use libloading::Library;
+use maplit::hashset;
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::path_string::path_to_string;
+use crate::filescanner::FileScanner;
+use crate::scanner_result::ScannerFinding;
+use crate::string_options::StringOptions;
+
+/// the path which is passed to plugins. On Unix, these are the raw bytes of the path,
+/// so that plugins can open files whose names are no valid UTF-8
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+/// version of the plugin ABI, which must be returned by `dionysos_plugin_abi_version`
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+type NameFn = unsafe extern "C" fn() -> *const c_char;
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type ReportFn = extern "C" fn(ctx: *mut c_void, rule: *const c_char, details: *const c_char);
+type ScanFileFn =
+    unsafe extern "C" fn(path: *const c_char, report: ReportFn, ctx: *mut c_void) -> c_int;
+
+/// a scanner which is loaded from a dynamic library at runtime. The library must
+/// export the following functions (see `plugins/dionysos_plugin.h`):
+///
+/// ```c
+/// uint32_t dionysos_plugin_abi_version(void);
+/// const char *dionysos_plugin_name(void);
+/// int dionysos_plugin_scan_file(const char *path, dionysos_report_fn report, void *ctx);
+/// ```
+///
+/// `dionysos_plugin_scan_file` is called concurrently by all workers, and must call
+/// `report(ctx, rule, details)` for every finding. It returns 0 on success.
+pub struct PluginScanner {
+    name: String,
+    path: PathBuf,
+    scan_file: ScanFileFn,
+
+    /// the library must not be unloaded while `scan_file` can be called
+    _library: Library,
+}
+
+impl PluginScanner {
+    pub fn load(path: &Path) -> Result<Self> {
+        let error =
+            |why: String| anyhow!("unable to load the plugin '{}': {}", path.display(), why);
+
+        // SAFETY: loading a library runs its initialization code, and the exported
+        // functions are trusted to follow the plugin ABI
+        unsafe {
+            let library = Library::new(path).map_err(|why| error(why.to_string()))?;
+            let abi_version = library
+                .get::<AbiVersionFn>(b"dionysos_plugin_abi_version\0")
+                .map_err(|why| error(why.to_string()))?;
+            let abi_version = abi_version();
+            if abi_version != PLUGIN_ABI_VERSION {
+                return Err(error(format!(
+                    "the plugin uses ABI version {}, but version {} is required",
+                    abi_version, PLUGIN_ABI_VERSION
+                )));
+            }
+
+            let name = library
+                .get::<NameFn>(b"dionysos_plugin_name\0")
+                .map_err(|why| error(why.to_string()))?;
+            let name = name();
+            if name.is_null() {
+                return Err(error("the plugin has no name".to_owned()));
+            }
+            let name = CStr::from_ptr(name).to_string_lossy().to_string();
+
+            let scan_file = *library
+                .get::<ScanFileFn>(b"dionysos_plugin_scan_file\0")
+                .map_err(|why| error(why.to_string()))?;
+
+            Ok(Self {
+                name,
+                path: path.to_owned(),
+                scan_file,
+                _library: library,
+            })
+        }
+    }
+
+    /// loads all dynamic libraries (`*.so`, `*.dll` or `*.dylib`, depending on the
+    /// platform) in `dir`
+    pub fn load_directory(dir: &Path) -> Result<Vec<Self>> {
+        let mut paths = Vec::new();
+        let entries = std::fs::read_dir(dir).map_err(|why| {
+            anyhow!("unable to read the plugin directory '{}': {}", dir.display(), why)
+        })?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(std::env::consts::DLL_EXTENSION) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths.iter().map(|path| Self::load(path)).collect()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// collects the findings which are reported by a plugin
+extern "C" fn report(ctx: *mut c_void, rule: *const c_char, details: *const c_char) {
+    let to_string = |s: *const c_char| {
+        if s.is_null() {
+            String::new()
+        } else {
+            // SAFETY: the plugin passes nul-terminated strings
+            unsafe { CStr::from_ptr(s) }.to_string_lossy().to_string()
+        }
+    };
+    // SAFETY: `ctx` is the vector which has been passed to `dionysos_plugin_scan_file`
+    let findings = unsafe { &mut *(ctx as *mut Vec<(String, String)>) };
+    findings.push((to_string(rule), to_string(details)));
+}
+
+impl Display for PluginScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PluginScanner({})", self.name)
+    }
+}
+
+impl FileScanner for PluginScanner {
+    fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let found_in_file = path_to_string(file.path());
+        let path = match CString::new(path_bytes(file.path())) {
+            Ok(path) => path,
+            Err(why) => return vec![Err(anyhow!("invalid path '{}': {}", found_in_file, why))],
+        };
+
+        let mut findings: Vec<(String, String)> = Vec::new();
+        // SAFETY: `findings` outlives the call, and is only accessed by `report`
+        let rc = unsafe {
+            (self.scan_file)(
+                path.as_ptr(),
+                report,
+                &mut findings as *mut Vec<(String, String)> as *mut c_void,
+            )
+        };
+
+        let mut results: Vec<anyhow::Result<Box<dyn ScannerFinding>>> = findings
+            .into_iter()
+            .map(|(rule, details)| {
+                Ok(Box::new(PluginFinding {
+                    plugin: self.name.clone(),
+                    rule,
+                    details,
+                    found_in_file: found_in_file.clone(),
+                }) as Box<dyn ScannerFinding>)
+            })
+            .collect();
+        if rc != 0 {
+            results.push(Err(anyhow!(
+                "the plugin '{}' failed to scan '{}' (error code {})",
+                self.name,
+                found_in_file,
+                rc
+            )));
+        }
+        results
+    }
+
+    /// plugins receive the path of the file, and may use it
+    fn depends_on_path(&self) -> bool {
+        true
+    }
+}
+
+struct PluginFinding {
+    plugin: String,
+    rule: String,
+    details: String,
+    found_in_file: String,
+}
+
+impl Display for PluginFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "plugin '{}' found '{}' in {}",
+            self.plugin,
+            self.rule,
+            self.found_in_file()
+        )?;
+        if !self.details.is_empty() {
+            write!(f, ": {}", self.details)?;
+        }
+        writeln!(f)
+    }
+}
+
+impl ScannerFinding for PluginFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            &format!("Plugin:{}", self.plugin),
+            &self.rule,
+            self.found_in_file(),
+            self.details.clone()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "plugin",
+            "02_suspicious_file": self.found_in_file(),
+            "03_plugin": self.plugin,
+            "04_rule": self.rule,
+            "05_details": self.details
+        })
+    }
+
+    fn scanner(&self) -> String {
+        "plugin".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+
+    fn rule(&self) -> Option<&str> {
+        Some(&self.rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_void, CStr};
+    use std::os::raw::{c_char, c_int};
+    use std::path::PathBuf;
+
+    use walkdir::WalkDir;
+
+    use super::{PluginScanner, ReportFn, ScanFileFn};
+    use crate::filescanner::FileScanner;
+    use crate::string_options::StringOptions;
+
+    /// reports every file, and fails for files whose name contains `fail`
+    unsafe extern "C" fn scan_file(
+        path: *const c_char,
+        report: ReportFn,
+        ctx: *mut c_void,
+    ) -> c_int {
+        let c_str = |s: &'static [u8]| s.as_ptr() as *const c_char;
+        let path = CStr::from_ptr(path).to_string_lossy();
+        report(ctx, c_str(b"test_rule\0"), std::ptr::null());
+        if path.contains("fail") {
+            return 3;
+        }
+        report(ctx, c_str(b"other_rule\0"), c_str(b"details\0"));
+        0
+    }
+
+    /// reports files which exist
+    #[cfg(target_os = "linux")]
+    unsafe extern "C" fn check_exists(
+        path: *const c_char,
+        report: ReportFn,
+        ctx: *mut c_void,
+    ) -> c_int {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = OsStr::from_bytes(CStr::from_ptr(path).to_bytes());
+        if std::path::Path::new(path).exists() {
+            report(ctx, b"exists\0".as_ptr() as *const c_char, std::ptr::null());
+        }
+        0
+    }
+
+    /// a plugin which is implemented by `scan_file`. The library is only loaded
+    /// because a plugin keeps its library
+    fn plugin(scan_file: ScanFileFn) -> PluginScanner {
+        #[cfg(unix)]
+        let library = libloading::os::unix::Library::this().into();
+        #[cfg(windows)]
+        let library = libloading::os::windows::Library::this().unwrap().into();
+        PluginScanner {
+            name: "test".to_owned(),
+            path: PathBuf::from("test"),
+            scan_file,
+            _library: library,
+        }
+    }
+
+    #[test]
+    fn test_scan_file() {
+        let dir = std::env::temp_dir().join(format!("dionysos-plugin-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let ok = dir.join("sample.exe");
+        let failing = dir.join("fail.exe");
+        std::fs::write(&ok, b"MZ").unwrap();
+        std::fs::write(&failing, b"MZ").unwrap();
+
+        let plugin = plugin(scan_file);
+        let entry = |path| WalkDir::new(path).into_iter().next().unwrap().unwrap();
+        let results = plugin.scan_file(&entry(&ok));
+        let failed = plugin.scan_file(&entry(&failing));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let json: Vec<_> = results
+            .into_iter()
+            .map(|r| r.unwrap().to_json(&StringOptions::default()))
+            .collect();
+        assert_eq!(json.len(), 2);
+        assert_eq!(json[0]["03_plugin"], "test");
+        assert_eq!(json[0]["04_rule"], "test_rule");
+        assert_eq!(json[0]["05_details"], "");
+        assert_eq!(json[1]["04_rule"], "other_rule");
+        assert_eq!(json[1]["05_details"], "details");
+
+        // the findings which have been reported before the error are kept
+        assert_eq!(failed.len(), 2);
+        assert!(failed[0].is_ok());
+        let error = failed[1].as_ref().unwrap_err().to_string();
+        assert!(error.contains("error code 3"), "{}", error);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let dir = std::env::temp_dir().join(format!("dionysos-plugins-{}", std::process::id()));
+        assert!(PluginScanner::load_directory(&dir).is_err());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("README.txt"), b"no plugin").unwrap();
+        assert!(PluginScanner::load_directory(&dir).unwrap().is_empty());
+
+        let library = dir.join(format!("invalid.{}", std::env::consts::DLL_EXTENSION));
+        std::fs::write(&library, b"no library").unwrap();
+        let result = PluginScanner::load_directory(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let error = result.err().unwrap().to_string();
+        assert!(error.contains("unable to load the plugin"), "{}", error);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_invalid_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = std::env::temp_dir().join(format!("dionysos-plugin-utf8-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(OsStr::from_bytes(b"a\xff\xfeb.exe"));
+        std::fs::write(&file, b"MZ").unwrap();
+
+        let entry = WalkDir::new(&file).into_iter().next().unwrap().unwrap();
+        let results = plugin(check_exists).scan_file(&entry);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let json: Vec<_> = results
+            .into_iter()
+            .map(|r| r.unwrap().to_json(&StringOptions::default()))
+            .collect();
+        assert_eq!(json.len(), 1);
+        assert_eq!(json[0]["04_rule"], "exists");
+    }
+}
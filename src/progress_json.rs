@@ -0,0 +1,169 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde_json::json;
+
+/// interval between two progress events
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// emits progress events as JSON, one object per line, so that other applications can
+/// display the progress of the scan:
+///
+/// ```json
+/// {"event":"progress","discovered":1200,"completed":800,"findings":2,"current_file":"/home/user/a.exe","elapsed":12,"eta":6}
+/// ```
+///
+/// `eta` (in seconds) is `null` until all files have been discovered. The last event
+/// is `finished`
+pub(crate) struct ProgressEvents {
+    discovered: AtomicUsize,
+    completed: AtomicUsize,
+    findings: AtomicUsize,
+    current_file: Mutex<String>,
+    enumerating: AtomicBool,
+    finished: AtomicBool,
+    started: Instant,
+}
+
+/// writes the events of [`ProgressEvents`] until it is finished
+pub(crate) struct ProgressReporter {
+    events: Arc<ProgressEvents>,
+    thread: JoinHandle<()>,
+}
+
+impl ProgressEvents {
+    fn new() -> Self {
+        Self {
+            discovered: AtomicUsize::new(0),
+            completed: AtomicUsize::new(0),
+            findings: AtomicUsize::new(0),
+            current_file: Mutex::new(String::new()),
+            enumerating: AtomicBool::new(true),
+            finished: AtomicBool::new(false),
+            started: Instant::now(),
+        }
+    }
+
+    /// a file has been found, which will be scanned
+    pub fn discover_file(&self) {
+        self.discovered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// all files have been found, so that the ETA can be estimated
+    pub fn set_enumerating(&self, enumerating: bool) {
+        self.enumerating.store(enumerating, Ordering::Relaxed);
+    }
+
+    /// a worker has started to scan `file`
+    pub fn start_file(&self, file: &Path) {
+        *self.current_file.lock().unwrap() = file.display().to_string();
+    }
+
+    /// the results of a file have been written
+    pub fn complete_file(&self, findings: usize) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        self.findings.fetch_add(findings, Ordering::Relaxed);
+    }
+
+    fn to_json(&self, event: &str) -> serde_json::Value {
+        let discovered = self.discovered.load(Ordering::Relaxed);
+        let completed = self.completed.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed();
+        let eta = match self.enumerating.load(Ordering::Relaxed) {
+            true => None,
+            false if completed == 0 => None,
+            false => Some(
+                elapsed.as_secs_f64() * discovered.saturating_sub(completed) as f64
+                    / completed as f64,
+            ),
+        };
+        json!({
+            "event": event,
+            "discovered": discovered,
+            "completed": completed,
+            "findings": self.findings.load(Ordering::Relaxed),
+            "current_file": *self.current_file.lock().unwrap(),
+            "elapsed": elapsed.as_secs(),
+            "eta": eta.map(|eta| eta.round() as u64),
+        })
+    }
+}
+
+impl ProgressReporter {
+    /// writes the events to stderr, if `destination` is `-`, or to the file or named
+    /// pipe `destination` otherwise
+    pub fn start(destination: &Path) -> Result<Self> {
+        let mut writer: Box<dyn Write + Send> = if destination == Path::new("-") {
+            Box::new(std::io::stderr())
+        } else {
+            Box::new(
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(destination)
+                    .map_err(|why| {
+                        anyhow!("unable to open '{}': {}", destination.display(), why)
+                    })?,
+            )
+        };
+
+        let events = Arc::new(ProgressEvents::new());
+        let thread_events = Arc::clone(&events);
+        let thread = thread::spawn(move || {
+            while !thread_events.finished.load(Ordering::Relaxed) {
+                if let Err(why) = writeln!(writer, "{}", thread_events.to_json("progress")) {
+                    log::warn!("unable to write the progress, stopping progress events: {}", why);
+                    return;
+                }
+                let next = Instant::now() + PROGRESS_INTERVAL;
+                while !thread_events.finished.load(Ordering::Relaxed) && Instant::now() < next {
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+            thread_events.set_enumerating(false);
+            if let Err(why) = writeln!(writer, "{}", thread_events.to_json("finished")) {
+                log::warn!("unable to write the progress: {}", why);
+            }
+        });
+        Ok(Self { events, thread })
+    }
+
+    pub fn events(&self) -> &Arc<ProgressEvents> {
+        &self.events
+    }
+
+    /// writes the final event
+    pub fn finish(self) {
+        self.events.finished.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProgressEvents;
+
+    #[test]
+    fn test_eta() {
+        let events = ProgressEvents::new();
+        for _ in 0..4 {
+            events.discover_file();
+        }
+        events.complete_file(1);
+        assert!(events.to_json("progress")["eta"].is_null());
+
+        events.set_enumerating(false);
+        let progress = events.to_json("progress");
+        assert_eq!(progress["discovered"], 4);
+        assert_eq!(progress["completed"], 1);
+        assert_eq!(progress["findings"], 1);
+        assert!(progress["eta"].is_u64());
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use regex::Regex;
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::entropy_scanner::entropy;
+use crate::filescanner::FileScanner;
+use crate::path_string::path_to_string;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+/// the minimum number of renamed or encrypted files of a directory which are reported
+const MIN_FILES: usize = 5;
+
+/// the maximum number of files of a directory whose content is checked
+const MAX_SAMPLED_FILES: usize = 200;
+
+/// the number of bytes at the beginning of a file which are checked
+const SAMPLE_SIZE: usize = 4096;
+
+/// the entropy of smaller files is not meaningful
+const MIN_SAMPLE_SIZE: usize = 512;
+
+/// the entropy (in bits per byte) above which content is considered as encrypted
+const ENTROPY_THRESHOLD: f64 = 7.5;
+
+/// file extensions and the signatures which files with these extensions start with.
+/// Text formats have no signature, but must not have a high entropy
+static STRUCTURED_FORMATS: &[(&str, Option<&[u8]>)] = &[
+    ("pdf", Some(b"%PDF")),
+    ("doc", Some(b"\xd0\xcf\x11\xe0")),
+    ("xls", Some(b"\xd0\xcf\x11\xe0")),
+    ("ppt", Some(b"\xd0\xcf\x11\xe0")),
+    ("msg", Some(b"\xd0\xcf\x11\xe0")),
+    ("docx", Some(b"PK")),
+    ("xlsx", Some(b"PK")),
+    ("pptx", Some(b"PK")),
+    ("odt", Some(b"PK")),
+    ("ods", Some(b"PK")),
+    ("zip", Some(b"PK")),
+    ("jar", Some(b"PK")),
+    ("rtf", Some(b"{\\rtf")),
+    ("png", Some(b"\x89PNG")),
+    ("jpg", Some(b"\xff\xd8\xff")),
+    ("jpeg", Some(b"\xff\xd8\xff")),
+    ("gif", Some(b"GIF8")),
+    ("exe", Some(b"MZ")),
+    ("dll", Some(b"MZ")),
+    ("7z", Some(b"7z\xbc\xaf")),
+    ("sqlite", Some(b"SQLite format 3")),
+    ("txt", None),
+    ("csv", None),
+    ("xml", None),
+    ("html", None),
+    ("htm", None),
+    ("json", None),
+    ("log", None),
+    ("ini", None),
+    ("ps1", None),
+    ("bat", None),
+];
+
+/// common file extensions, which are not considered as unusual. Media, archives and
+/// encrypted formats are included, because their content has a high entropy anyway
+static COMMON_EXTENSIONS: &[&str] = &[
+    "aac", "age", "apk", "asc", "avi", "avif", "bak", "bin", "bmp", "bz2", "cab", "cat", "cfg",
+    "class", "conf", "crt", "dat", "db", "deb", "dmg", "dylib", "eot", "epub", "flac", "flv",
+    "gpg", "gz", "h5", "heic", "iso", "kdbx", "key", "lnk", "lz4", "m4a", "m4v", "mkv", "mov",
+    "mp3", "mp4", "mpg", "msi", "mui", "npy", "npz", "o", "ogg", "onnx", "opus", "otf", "p12",
+    "pak", "parquet", "pdb", "pem", "pfx", "pgp", "pkl", "pt", "pyc", "qcow2", "rar", "rpm", "sig",
+    "so", "svg", "sys", "tar", "tgz", "tif", "tiff", "tmp", "ttf", "txz", "url", "vdi", "vhd",
+    "vhdx", "vmdk", "wav", "webm", "webp", "whl", "wim", "wma", "wmv", "woff", "woff2", "xz",
+    "zst",
+];
+
+/// the names of the notes which are dropped by ransomware
+fn ransom_note_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?i)^[!_\-\.\s]*(",
+            r"how[_\-\s]*to[_\-\s]*(decrypt|restore|recover|unlock|back)|",
+            r"(decrypt|decryption|restore|recover|recovery|unlock)[_\-\s]*(my[_\-\s]*)?(files|instructions?|info|data|note)|",
+            r"read[_\-\s]*(me|this)[_\-\s]*(now|first|to[_\-\s]*decrypt|!+)|",
+            r"ransom[_\-\s]*note|",
+            r"your[_\-\s]*files[_\-\s]*(are|were)[_\-\s]*encrypted|",
+            r"files[_\-\s]*encrypted",
+            r").*\.(txt|html?|hta|rtf|url|png|bmp)$"
+        ))
+        .unwrap()
+    })
+}
+
+/// reports directories with signs of ransomware, which are
+///
+///  - files which have been renamed to an unusual extension, like `report.docx.lockbit`
+///  - files whose content looks encrypted, although their extension refers to a
+///    structured format (like a PDF document which does not start with `%PDF`)
+///  - ransom notes, like `HOW_TO_DECRYPT.txt`
+///
+/// Every directory is checked only once, when the first of its files is scanned, and
+/// the finding refers to the directory instead of a single file
+#[derive(Default)]
+pub struct RansomwareScanner {
+    checked_directories: Mutex<HashSet<PathBuf>>,
+}
+
+impl Display for RansomwareScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RansomwareScanner")
+    }
+}
+
+impl FileScanner for RansomwareScanner {
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let directory = match entry.path().parent() {
+            Some(directory) => directory,
+            None => return vec![],
+        };
+        if !self
+            .checked_directories
+            .lock()
+            .unwrap()
+            .insert(directory.to_owned())
+        {
+            return vec![];
+        }
+
+        match check_directory(directory) {
+            Ok(None) => vec![],
+            Ok(Some(finding)) => vec![Ok(Box::new(finding) as Box<dyn ScannerFinding>)],
+            Err(why) => vec![Err(anyhow!(
+                "unable to check '{}' for ransomware: {}",
+                directory.display(),
+                why
+            ))],
+        }
+    }
+
+    /// the file is checked together with the other files of its directory
+    fn depends_on_path(&self) -> bool {
+        true
+    }
+}
+
+fn check_directory(directory: &Path) -> Result<Option<RansomwareFinding>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+
+    let ransom_notes: Vec<String> = files
+        .iter()
+        .filter_map(|file| file.file_name())
+        .map(|name| name.to_string_lossy())
+        .filter(|name| ransom_note_pattern().is_match(name))
+        .map(|name| name.into_owned())
+        .collect();
+
+    let mut by_extension: HashMap<String, Vec<&Path>> = HashMap::new();
+    for file in files.iter() {
+        if let Some(extension) = extension_of(file) {
+            by_extension.entry(extension).or_default().push(file);
+        }
+    }
+
+    // the most frequent extension which is unusual, and its files which have been renamed
+    let mut renamed: Option<(String, usize)> = None;
+    let mut sampled_files = 0;
+    let mut candidates: Vec<_> = by_extension
+        .iter()
+        .filter(|(extension, files)| files.len() >= MIN_FILES && !is_known_extension(extension))
+        .collect();
+    candidates.sort_by_key(|(_, files)| std::cmp::Reverse(files.len()));
+    if let Some((extension, candidates)) = candidates.first() {
+        let mut renamed_files = 0;
+        for file in candidates.iter() {
+            let has_inner_extension = file
+                .file_stem()
+                .map(Path::new)
+                .and_then(extension_of)
+                .map_or(false, |inner| is_known_extension(&inner));
+            if has_inner_extension {
+                renamed_files += 1;
+            } else if sampled_files < MAX_SAMPLED_FILES {
+                sampled_files += 1;
+                if looks_encrypted(file, None)? {
+                    renamed_files += 1;
+                }
+            }
+        }
+        if renamed_files >= MIN_FILES {
+            renamed = Some((extension.to_string(), renamed_files));
+        }
+    }
+
+    let mut encrypted_files = 0;
+    for (extension, files) in by_extension.iter() {
+        let signature = match STRUCTURED_FORMATS
+            .iter()
+            .find(|(e, _)| *e == extension.as_str())
+        {
+            Some((_, signature)) => *signature,
+            None => continue,
+        };
+        for file in files {
+            if sampled_files >= MAX_SAMPLED_FILES {
+                break;
+            }
+            sampled_files += 1;
+            if looks_encrypted(file, signature)? {
+                encrypted_files += 1;
+            }
+        }
+    }
+    if encrypted_files < MIN_FILES {
+        encrypted_files = 0;
+    }
+
+    if renamed.is_none() && encrypted_files == 0 && ransom_notes.is_empty() {
+        return Ok(None);
+    }
+    let (extension, renamed_files) = match renamed {
+        Some((extension, renamed_files)) => (Some(extension), renamed_files),
+        None => (None, 0),
+    };
+    Ok(Some(RansomwareFinding {
+        directory: path_to_string(directory),
+        files: files.len(),
+        renamed_files,
+        extension,
+        encrypted_files,
+        ransom_notes,
+    }))
+}
+
+fn extension_of(file: &Path) -> Option<String> {
+    file.extension().map(|e| e.to_string_lossy().to_lowercase())
+}
+
+fn is_known_extension(extension: &str) -> bool {
+    STRUCTURED_FORMATS.iter().any(|(e, _)| *e == extension)
+        || COMMON_EXTENSIONS.contains(&extension)
+}
+
+/// checks if the content of `file` does not start with `signature`, and has a high
+/// entropy. Files which are too small to tell are not considered as encrypted
+fn looks_encrypted(file: &Path, signature: Option<&[u8]>) -> Result<bool> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    File::open(file)?
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    if sample.len() < MIN_SAMPLE_SIZE {
+        return Ok(false);
+    }
+    if signature.map_or(false, |signature| sample.starts_with(signature)) {
+        return Ok(false);
+    }
+    Ok(entropy(&sample) > ENTROPY_THRESHOLD)
+}
+
+struct RansomwareFinding {
+    directory: String,
+    files: usize,
+    renamed_files: usize,
+    extension: Option<String>,
+    encrypted_files: usize,
+    ransom_notes: Vec<String>,
+}
+
+impl RansomwareFinding {
+    fn details(&self) -> String {
+        let mut details = Vec::new();
+        if let Some(extension) = &self.extension {
+            details.push(format!(
+                "{} of {} file(s) renamed to '.{}'",
+                self.renamed_files, self.files, extension
+            ));
+        }
+        if self.encrypted_files > 0 {
+            details.push(format!(
+                "{} file(s) with encrypted content",
+                self.encrypted_files
+            ));
+        }
+        if !self.ransom_notes.is_empty() {
+            details.push(format!("ransom note(s): {}", self.ransom_notes.join(", ")));
+        }
+        details.join("; ")
+    }
+
+    fn indicators(&self) -> usize {
+        [
+            self.extension.is_some(),
+            self.encrypted_files > 0,
+            !self.ransom_notes.is_empty(),
+        ]
+        .iter()
+        .filter(|i| **i)
+        .count()
+    }
+}
+
+impl Display for RansomwareFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let directory = self.found_in_file();
+        let details = self.details();
+        writeln!(f, "ransomware activity: {directory} ({details})")
+    }
+}
+
+impl ScannerFinding for RansomwareFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "Ransomware",
+            "ransomware activity",
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "ransomware",
+            "02_suspicious_file": self.found_in_file(),
+            "03_files": self.files,
+            "04_renamed_files": self.renamed_files,
+            "05_extension": self.extension,
+            "06_encrypted_files": self.encrypted_files,
+            "07_ransom_notes": self.ransom_notes
+        })
+    }
+
+    /// a single indicator may be a false positive, but all of them together are not
+    fn severity(&self) -> Severity {
+        match self.indicators() {
+            0 | 1 => Severity::Medium,
+            2 => Severity::High,
+            _ => Severity::Critical,
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "ransomware".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.directory[..]
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        // Data Encrypted for Impact
+        vec!["T1486".to_owned()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_directory, ransom_note_pattern};
+
+    #[test]
+    fn test_ransom_notes() {
+        for name in [
+            "HOW_TO_DECRYPT.txt",
+            "!!!READ_ME_NOW!!!.hta",
+            "Restore-My-Files.txt",
+        ] {
+            assert!(ransom_note_pattern().is_match(name), "{}", name);
+        }
+        for name in ["README.txt", "restore.log", "decrypt.exe"] {
+            assert!(!ransom_note_pattern().is_match(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_encrypted_directory() {
+        let dir = std::env::temp_dir().join(format!("dionysos-ransomware-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // xorshift, which is random enough to have a high entropy
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect()
+        };
+        for i in 0..6 {
+            std::fs::write(dir.join(format!("report{}.docx.lockbit", i)), random(2048)).unwrap();
+        }
+        std::fs::write(dir.join("HOW_TO_DECRYPT.txt"), "pay").unwrap();
+
+        let finding = check_directory(&dir).unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(finding.renamed_files, 6);
+        assert_eq!(finding.extension.as_deref(), Some("lockbit"));
+        assert_eq!(finding.ransom_notes, vec!["HOW_TO_DECRYPT.txt"]);
+        assert!(check_directory(std::path::Path::new("src"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_common_formats() {
+        let dir = std::env::temp_dir().join(format!("dionysos-media-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // compressed media and encrypted backups have a high entropy, but are not unusual
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut random = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect()
+        };
+        for extension in ["flac", "gpg", "mkv", "webp", "zst"] {
+            for i in 0..6 {
+                let name = format!("file{}.{}", i, extension);
+                std::fs::write(dir.join(name), random(2048)).unwrap();
+            }
+        }
+
+        let finding = check_directory(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(finding.is_none());
+    }
+}
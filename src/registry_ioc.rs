@@ -0,0 +1,334 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use nt_hive2::{Hive, HiveParseMode, KeyNode, RegistryValue};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use serde_json::json;
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::path_string::path_to_string;
+use crate::filescanner::FileScanner;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+const REG_SIGNATURE: &[u8; 4] = b"regf";
+
+/// a line of an IoC file, as it is read from disk
+#[derive(Deserialize)]
+struct RegistryIocRecord {
+    name: String,
+    key: String,
+    #[serde(default)]
+    value_name: String,
+    #[serde(default)]
+    value_data: String,
+}
+
+/// an indicator which matches keys or values inside a registry hive.
+///
+/// The key path is a pattern relative to the root of the hive (e.g.
+/// `Microsoft\Windows\CurrentVersion\Run`), where `*` matches a single
+/// key name and `**` matches any number of keys. Key paths are compared
+/// case-insensitively. If neither a value name nor a value data regex is
+/// given, the existence of the key is reported.
+struct RegistryIoc {
+    name: String,
+    key: Regex,
+    value_name: Option<Regex>,
+    value_data: Option<Regex>,
+}
+
+impl RegistryIoc {
+    fn parse(record: RegistryIocRecord) -> Result<Self> {
+        let optional_regex = |pattern: &str| -> Result<Option<Regex>> {
+            if pattern.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(Regex::new(pattern).map_err(|why| {
+                    anyhow!("invalid regex in IoC '{}': {}", record.name, why)
+                })?))
+            }
+        };
+
+        Ok(Self {
+            key: key_pattern(&record.key)
+                .map_err(|why| anyhow!("invalid key pattern in IoC '{}': {}", record.name, why))?,
+            value_name: optional_regex(&record.value_name)?,
+            value_data: optional_regex(&record.value_data)?,
+            name: record.name,
+        })
+    }
+
+    fn matches_key_only(&self) -> bool {
+        self.value_name.is_none() && self.value_data.is_none()
+    }
+
+    fn matches_value(&self, name: &str, data: &[String]) -> Option<String> {
+        if let Some(value_name) = &self.value_name {
+            if !value_name.is_match(name) {
+                return None;
+            }
+        }
+        match &self.value_data {
+            None => Some(data.join(", ")),
+            Some(value_data) => data.iter().find(|d| value_data.is_match(d)).cloned(),
+        }
+    }
+}
+
+/// converts a key path pattern into a case-insensitive regex
+fn key_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    let pattern = pattern.trim_matches('\\');
+    let mut regex = String::from("^");
+    let mut separator = false;
+    for component in pattern.split('\\') {
+        if component == "**" {
+            regex.push_str(if separator { "(?:\\\\[^\\\\]+)*" } else { "(?:[^\\\\]+\\\\)*" });
+            continue;
+        }
+        if separator {
+            regex.push_str("\\\\");
+        }
+        separator = true;
+        let parts: Vec<_> = component.split('*').map(regex::escape).collect();
+        regex.push_str(&parts.join("[^\\\\]*"));
+    }
+    regex.push('$');
+    RegexBuilder::new(&regex).case_insensitive(true).build()
+}
+
+/// evaluates registry IoCs against all registry hives which are found
+#[derive(Default)]
+pub struct RegistryIocScanner {
+    iocs: Vec<RegistryIoc>,
+}
+
+impl RegistryIocScanner {
+    /// reads IoCs from a CSV file with the columns `name`, `key`, `value_name`
+    /// and `value_data`. Lines starting with `#` are ignored.
+    pub fn with_ioc_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut reader = csv::ReaderBuilder::new()
+            .comment(Some(b'#'))
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|why| anyhow!("unable to read '{}': {}", path.display(), why))?;
+        let headers = reader.headers()?.clone();
+        for record in reader.records() {
+            let record =
+                record.map_err(|why| anyhow!("invalid IoC in '{}': {}", path.display(), why))?;
+            let line = record.position().map_or(0, |p| p.line());
+            let ioc = record
+                .deserialize(Some(&headers))
+                .map_err(anyhow::Error::from)
+                .and_then(RegistryIoc::parse)
+                .map_err(|why| anyhow!("{}:{}: {}", path.display(), line, why))?;
+            self.iocs.push(ioc);
+        }
+        log::info!("loaded {} registry IoC(s) from '{}'", self.iocs.len(), path.display());
+        Ok(self)
+    }
+
+    fn is_hive(path: &Path) -> bool {
+        let mut header = [0u8; 4];
+        File::open(path)
+            .and_then(|mut f| f.read_exact(&mut header))
+            .map(|_| &header == REG_SIGNATURE)
+            .unwrap_or(false)
+    }
+
+    fn scan_key(
+        &self,
+        hive: &mut Hive<File>,
+        key: &KeyNode,
+        path: String,
+        findings: &mut Vec<RegistryIocFinding>,
+        found_in_file: &str,
+    ) -> Result<()> {
+        let last_written = key.timestamp().to_rfc3339();
+        let key_path = path.trim_start_matches('\\');
+
+        for ioc in self.iocs.iter().filter(|ioc| ioc.key.is_match(key_path)) {
+            if ioc.matches_key_only() {
+                findings.push(RegistryIocFinding {
+                    ioc: ioc.name.clone(),
+                    key_path: key_path.to_owned(),
+                    value: None,
+                    last_written: last_written.clone(),
+                    found_in_file: found_in_file.to_owned(),
+                });
+                continue;
+            }
+
+            for value in key.values() {
+                if let Some(data) = ioc.matches_value(value.name(), &value_data(value.value())) {
+                    findings.push(RegistryIocFinding {
+                        ioc: ioc.name.clone(),
+                        key_path: key_path.to_owned(),
+                        value: Some((value.name().to_owned(), data)),
+                        last_written: last_written.clone(),
+                        found_in_file: found_in_file.to_owned(),
+                    });
+                }
+            }
+        }
+
+        for subkey in key.subkeys(hive)?.iter() {
+            let subkey_path = format!("{}\\{}", path, subkey.borrow().name());
+            self.scan_key(hive, &subkey.borrow(), subkey_path, findings, found_in_file)?;
+        }
+        Ok(())
+    }
+
+    fn intern_scan_file(&self, file: &Path) -> Result<Vec<RegistryIocFinding>> {
+        let mut findings = Vec::new();
+        if !Self::is_hive(file) {
+            return Ok(findings);
+        }
+
+        let mut hive = Hive::new(File::open(file)?, HiveParseMode::NormalWithBaseBlock)?;
+        if !hive.is_primary_file() {
+            log::trace!("'{}' is no primary hive file, skipping it", file.display());
+            return Ok(findings);
+        }
+
+        let root_key = hive.root_key_node()?;
+        self.scan_key(
+            &mut hive,
+            &root_key,
+            String::new(),
+            &mut findings,
+            &path_to_string(file),
+        )?;
+        Ok(findings)
+    }
+}
+
+/// textual representations of a value, which are matched against the value data regex
+fn value_data(value: &RegistryValue) -> Vec<String> {
+    match value {
+        RegistryValue::RegSZ(s) | RegistryValue::RegExpandSZ(s) => vec![s.clone()],
+        RegistryValue::RegMultiSZ(sl) => sl.clone(),
+        RegistryValue::RegDWord(v) => vec![v.to_string()],
+        RegistryValue::RegQWord(v) => vec![v.to_string()],
+        RegistryValue::RegBinary(b) => vec![hex::encode(b)],
+        _ => Vec::new(),
+    }
+}
+
+impl Display for RegistryIocScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RegistryIocScanner")
+    }
+}
+
+impl FileScanner for RegistryIocScanner {
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match self.intern_scan_file(entry.path()) {
+            Ok(findings) => findings
+                .into_iter()
+                .map(|f| Ok(Box::new(f) as Box<dyn ScannerFinding>))
+                .collect(),
+            Err(why) => vec![Err(anyhow!(
+                "unable to check '{}' for registry IoCs: {}",
+                entry.path().display(),
+                why
+            ))],
+        }
+    }
+}
+
+struct RegistryIocFinding {
+    ioc: String,
+    key_path: String,
+
+    /// name and data of the matching value, if the IoC refers to a value
+    value: Option<(String, String)>,
+
+    /// last write time of the key (RFC 3339)
+    last_written: String,
+    found_in_file: String,
+}
+
+impl RegistryIocFinding {
+    fn location(&self) -> String {
+        match &self.value {
+            Some((name, data)) => format!("{}\\@{} = '{}'", self.key_path, name, data),
+            None => self.key_path.clone(),
+        }
+    }
+}
+
+impl Display for RegistryIocFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "registry IoC '{}' in {}: {} (key last written at {})",
+            self.ioc,
+            self.found_in_file(),
+            self.location(),
+            self.last_written
+        )
+    }
+}
+
+impl ScannerFinding for RegistryIocFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "RegistryIoc",
+            &self.ioc,
+            self.found_in_file(),
+            self.location()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "registry_ioc",
+            "02_suspicious_file": self.found_in_file(),
+            "03_ioc": self.ioc,
+            "04_key_path": self.key_path,
+            "05_value_name": self.value.as_ref().map(|(name, _)| name),
+            "06_value_data": self.value.as_ref().map(|(_, data)| data),
+            "07_last_written": self.last_written
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::High
+    }
+
+    fn scanner(&self) -> String {
+        "registry_ioc".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::key_pattern;
+
+    #[test]
+    fn test_key_pattern() {
+        let run = key_pattern("Microsoft\\Windows\\CurrentVersion\\Run*").unwrap();
+        assert!(run.is_match("Microsoft\\Windows\\CurrentVersion\\Run"));
+        assert!(run.is_match("microsoft\\windows\\currentversion\\RunOnce"));
+        assert!(!run.is_match("Microsoft\\Windows\\CurrentVersion\\Run\\foo"));
+
+        let services = key_pattern("**\\Services\\*\\Parameters").unwrap();
+        assert!(services.is_match("ControlSet001\\Services\\foo\\Parameters"));
+        assert!(services.is_match("Services\\foo\\Parameters"));
+        assert!(!services.is_match("ControlSet001\\Services\\Parameters"));
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::result_diff::{self, ResultSet};
+use crate::severity::Severity;
+
+/// the keys which are shown in their own column, and not in the details of a finding
+const COLUMN_KEYS: &[&str] = &["01_scanner", "02_suspicious_file", "89_severity"];
+
+/// details longer than this are truncated, e.g. the strings of a yara rule
+const MAX_DETAILS_LENGTH: usize = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ReportFormat {
+    Html,
+    Xlsx,
+    Md,
+}
+
+impl ReportFormat {
+    /// the format which belongs to the extension of `path`, e.g. `report.html`
+    pub fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "html" | "htm" => Some(Self::Html),
+            "xlsx" => Some(Self::Xlsx),
+            "md" | "markdown" => Some(Self::Md),
+            _ => None,
+        }
+    }
+}
+
+/// a finding in the report
+struct Entry {
+    /// `None` for results which have been written by a version of dionysos which did
+    /// not include the severity
+    severity: Option<Severity>,
+    scanner: String,
+    file: String,
+    indicator: String,
+    details: String,
+
+    /// how often the finding has been reported, e.g. for results which have been
+    /// appended to the same file by multiple scans
+    occurrences: usize,
+}
+
+impl Entry {
+    fn from_findings(findings: Vec<Value>) -> Self {
+        let finding = &findings[0];
+        let text = |key: &str| finding[key].as_str().unwrap_or_default().to_owned();
+        let indicator = result_diff::indicator(finding);
+        let details = finding
+            .as_object()
+            .map(|values| {
+                values
+                    .iter()
+                    .filter(|(key, value)| {
+                        !COLUMN_KEYS.contains(&key.as_str())
+                            && !value.is_null()
+                            && value.as_str() != Some(indicator.as_str())
+                    })
+                    .map(|(key, value)| {
+                        let key = key.split_once('_').map_or(&key[..], |(_, key)| key);
+                        match value {
+                            Value::String(s) => format!("{}: {}", key, s),
+                            value => format!("{}: {}", key, value),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            })
+            .unwrap_or_default();
+        Self {
+            severity: finding["89_severity"].as_str().and_then(Severity::parse),
+            scanner: text("01_scanner"),
+            file: text("02_suspicious_file"),
+            details: truncate(details),
+            indicator,
+            occurrences: findings.len(),
+        }
+    }
+
+    fn severity(&self) -> String {
+        self.severity
+            .map_or_else(|| "unknown".to_owned(), |s| s.to_string())
+    }
+}
+
+fn truncate(mut details: String) -> String {
+    if details.len() > MAX_DETAILS_LENGTH {
+        let mut end = MAX_DETAILS_LENGTH;
+        while !details.is_char_boundary(end) {
+            end -= 1;
+        }
+        details.truncate(end);
+        details.push_str("...");
+    }
+    details
+}
+
+/// a human readable report of the findings of a scan, which have been stored with
+/// `--format json`. Findings which have been reported more than once are shown only
+/// once, and the findings are grouped by file. Files with the most severe findings
+/// come first
+pub(crate) struct Report {
+    source: String,
+    files: Vec<(String, Vec<Entry>)>,
+}
+
+impl Report {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut by_file: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+        for findings in ResultSet::load(path)?.into_findings() {
+            let entry = Entry::from_findings(findings);
+            by_file.entry(entry.file.clone()).or_default().push(entry);
+        }
+
+        let mut files: Vec<(String, Vec<Entry>)> = by_file.into_iter().collect();
+        for (_, entries) in files.iter_mut() {
+            entries.sort_by(|a, b| {
+                (b.severity, &a.scanner, &a.indicator).cmp(&(a.severity, &b.scanner, &b.indicator))
+            });
+        }
+        // the entries are sorted, so that the first one has the highest severity
+        files.sort_by(|(a_file, a), (b_file, b)| {
+            (b[0].severity, a_file).cmp(&(a[0].severity, b_file))
+        });
+        Ok(Self {
+            source: path.display().to_string(),
+            files,
+        })
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.files.iter().flat_map(|(_, entries)| entries.iter())
+    }
+
+    /// the number of findings of every severity, the most severe first
+    fn severity_counts(&self) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<Option<Severity>, usize> = BTreeMap::new();
+        for entry in self.entries() {
+            *counts.entry(entry.severity).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .rev()
+            .map(|(severity, count)| {
+                let severity = severity.map_or_else(|| "unknown".to_owned(), |s| s.to_string());
+                (severity, count)
+            })
+            .collect()
+    }
+
+    pub fn write<W: Write>(&self, format: ReportFormat, wtr: &mut W) -> Result<()> {
+        match format {
+            ReportFormat::Html => self.write_html(wtr),
+            ReportFormat::Md => self.write_markdown(wtr),
+            #[cfg(feature = "xlsx")]
+            ReportFormat::Xlsx => self.write_xlsx(wtr),
+            #[cfg(not(feature = "xlsx"))]
+            ReportFormat::Xlsx => Err(anyhow::anyhow!(
+                "dionysos has been built without support for Excel workbooks (feature 'xlsx')"
+            )),
+        }
+    }
+
+    fn title(&self) -> String {
+        format!(
+            "{} finding(s) in {} file(s)",
+            self.entries().count(),
+            self.files.len()
+        )
+    }
+
+    fn write_markdown<W: Write>(&self, wtr: &mut W) -> Result<()> {
+        let escape = |s: &str| s.replace('|', "\\|").replace('\n', " ");
+        writeln!(wtr, "# dionysos report\n")?;
+        writeln!(wtr, "{} in `{}`\n", self.title(), self.source)?;
+        writeln!(wtr, "| Severity | Findings |\n|-|-|")?;
+        for (severity, count) in self.severity_counts() {
+            writeln!(wtr, "| {} | {} |", severity, count)?;
+        }
+        for (file, entries) in self.files.iter() {
+            writeln!(wtr, "\n## `{}`\n", file.replace('`', "'"))?;
+            writeln!(
+                wtr,
+                "| Severity | Scanner | Indicator | Occurrences | Details |\n|-|-|-|-|-|"
+            )?;
+            for entry in entries {
+                writeln!(
+                    wtr,
+                    "| {} | {} | {} | {} | {} |",
+                    entry.severity(),
+                    escape(&entry.scanner),
+                    escape(&entry.indicator),
+                    entry.occurrences,
+                    escape(&entry.details)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_html<W: Write>(&self, wtr: &mut W) -> Result<()> {
+        writeln!(
+            wtr,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>dionysos report</title>\n<style>\n\
+             body {{ font-family: sans-serif; margin: 2em; }}\n\
+             table {{ border-collapse: collapse; margin-bottom: 2em; }}\n\
+             th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; \
+             vertical-align: top; }}\n\
+             th {{ background: #eee; }}\n\
+             td.details {{ font-family: monospace; word-break: break-all; }}\n\
+             .critical {{ background: #f4b6b6; }}\n\
+             .high {{ background: #f9d4a6; }}\n\
+             .medium {{ background: #fbeeb0; }}\n\
+             </style>\n</head>\n<body>"
+        )?;
+        writeln!(wtr, "<h1>dionysos report</h1>")?;
+        writeln!(
+            wtr,
+            "<p>{} in <code>{}</code></p>",
+            self.title(),
+            html_escape(&self.source)
+        )?;
+        writeln!(wtr, "<table>\n<tr><th>Severity</th><th>Findings</th></tr>")?;
+        for (severity, count) in self.severity_counts() {
+            writeln!(
+                wtr,
+                "<tr class=\"{0}\"><td>{0}</td><td>{1}</td></tr>",
+                severity, count
+            )?;
+        }
+        writeln!(wtr, "</table>")?;
+        for (file, entries) in self.files.iter() {
+            writeln!(wtr, "<h2><code>{}</code></h2>", html_escape(file))?;
+            writeln!(
+                wtr,
+                "<table>\n<tr><th>Severity</th><th>Scanner</th><th>Indicator</th>\
+                 <th>Occurrences</th><th>Details</th></tr>"
+            )?;
+            for entry in entries {
+                writeln!(
+                    wtr,
+                    "<tr class=\"{0}\"><td>{0}</td><td>{1}</td><td>{2}</td><td>{3}</td>\
+                     <td class=\"details\">{4}</td></tr>",
+                    entry.severity(),
+                    html_escape(&entry.scanner),
+                    html_escape(&entry.indicator),
+                    entry.occurrences,
+                    html_escape(&entry.details)
+                )?;
+            }
+            writeln!(wtr, "</table>")?;
+        }
+        writeln!(wtr, "</body>\n</html>")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "xlsx")]
+    fn write_xlsx<W: Write>(&self, wtr: &mut W) -> Result<()> {
+        use rust_xlsxwriter::{Format, Workbook};
+
+        let bold = Format::new().set_bold();
+        let mut workbook = Workbook::new();
+
+        let summary = workbook.add_worksheet();
+        summary.set_name("Summary")?;
+        summary.write_string_with_format(0, 0, "Severity", &bold)?;
+        summary.write_string_with_format(0, 1, "Findings", &bold)?;
+        for (row, (severity, count)) in self.severity_counts().into_iter().enumerate() {
+            summary.write_string(row as u32 + 1, 0, severity)?;
+            summary.write_number(row as u32 + 1, 1, count as f64)?;
+        }
+
+        let findings = workbook.add_worksheet();
+        findings.set_name("Findings")?;
+        let columns = [
+            ("Severity", 10.0),
+            ("Scanner", 14.0),
+            ("File", 60.0),
+            ("Indicator", 30.0),
+            ("Occurrences", 12.0),
+            ("Details", 100.0),
+        ];
+        for (col, (name, width)) in columns.iter().enumerate() {
+            findings.write_string_with_format(0, col as u16, *name, &bold)?;
+            findings.set_column_width(col as u16, *width)?;
+        }
+        let mut row = 0;
+        for entry in self.entries() {
+            row += 1;
+            findings.write_string(row, 0, entry.severity())?;
+            findings.write_string(row, 1, &entry.scanner)?;
+            findings.write_string(row, 2, &entry.file)?;
+            findings.write_string(row, 3, &entry.indicator)?;
+            findings.write_number(row, 4, entry.occurrences as f64)?;
+            findings.write_string(row, 5, &entry.details)?;
+        }
+        findings.autofilter(0, 0, row, columns.len() as u16 - 1)?;
+        findings.set_freeze_panes(1, 0)?;
+
+        wtr.write_all(&workbook.save_to_buffer()?)?;
+        Ok(())
+    }
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::severity::Severity;
+
+    use super::{truncate, Entry, MAX_DETAILS_LENGTH};
+
+    #[test]
+    fn test_entry() {
+        let finding = json!({
+            "01_scanner": "yara",
+            "02_suspicious_file": "/srv/www/shell.php",
+            "04_strings": ["eval("],
+            "09_rule": "Webshell_Generic",
+            "89_severity": "high"
+        });
+        let entry = Entry::from_findings(vec![finding.clone(), finding]);
+        assert_eq!(entry.severity, Some(Severity::High));
+        assert_eq!(entry.indicator, "Webshell_Generic");
+        assert_eq!(entry.details, "strings: [\"eval(\"]");
+        assert_eq!(entry.occurrences, 2);
+
+        let details = truncate("ä".repeat(MAX_DETAILS_LENGTH));
+        assert!(details.ends_with("..."));
+        assert!(details.len() <= MAX_DETAILS_LENGTH + 3);
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+const SQLITE_SIGNATURE: &[u8; 16] = b"SQLite format 3\0";
+
+/// the keys which identify a finding of a scanner in a file. Findings with the same
+/// identity, but different values of their other keys, have changed between two scans.
+/// Findings of scanners which are not listed here are identified by all of their keys
+const IDENTITY_KEYS: &[(&str, &[&str])] = &[
+    (
+        "yara",
+        &[
+            "09_rule",
+            "03_incomplete",
+            "05_contained_file",
+            "07_location",
+        ],
+    ),
+    ("hash", &["03_hash"]),
+    ("fuzzy_hash", &["03_hash"]),
+    ("tlsh", &["03_hash"]),
+    ("entropy", &[]),
+    ("extension_mismatch", &["03_detected_type"]),
+    ("filename", &["03_pattern"]),
+    ("levenshtein", &["03_original_name"]),
+    ("masquerading", &[]),
+    ("ransomware", &[]),
+    ("permissions", &["03_anomaly"]),
+    ("authenticode", &["03_state"]),
+    ("pe_anomalies", &["03_anomaly"]),
+    ("elf_anomalies", &["03_anomaly"]),
+    ("macho_anomalies", &["03_anomaly"]),
+    ("registry_ioc", &["03_ioc", "04_key_path"]),
+    ("plugin", &["03_plugin", "04_rule"]),
+];
+
+/// the keys which annotate a finding (`89_severity` to `93_policy`) do not belong to the
+/// finding itself: they are missing in the results of older versions, and some of them
+/// change over time, like the detections of a hash lookup. They are ignored when findings
+/// are compared
+const ANNOTATION_KEYS: std::ops::RangeInclusive<u32> = 89..=93;
+
+/// the keys which name the rule or indicator of a finding, in the order of their priority
+const INDICATOR_KEYS: &[&str] = &[
+    "09_rule",
+    "04_rule",
+    "03_pattern",
+    "03_hash",
+    "03_ioc",
+    "03_original_name",
+    "04_reason",
+    "03_anomaly",
+    "03_state",
+];
+
+/// the findings of a scan, as written by `--format json`, grouped by their identity
+#[derive(Default)]
+pub(crate) struct ResultSet {
+    findings: BTreeMap<String, Vec<Value>>,
+}
+
+impl ResultSet {
+    /// reads the findings of `path`, which is either the `json` or `ndjson` output of a
+    /// scan or a SQLite database whose tables contain the same columns, e.g. after
+    /// importing the `ndjson` output with `sqlite-utils insert --nl`. Errors of the scan
+    /// are ignored
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut signature = [0u8; SQLITE_SIGNATURE.len()];
+        let is_sqlite = File::open(path)?
+            .read_exact(&mut signature)
+            .map_or(false, |_| &signature == SQLITE_SIGNATURE);
+
+        let records = if is_sqlite {
+            Self::read_sqlite(path)?
+        } else {
+            Self::read_json(path)?
+        };
+        let mut result_set = Self::default();
+        for record in records {
+            if let Some(identity) = identity(&record) {
+                result_set
+                    .findings
+                    .entry(identity)
+                    .or_default()
+                    .push(Value::Object(record));
+            }
+        }
+        Ok(result_set)
+    }
+
+    /// all findings, grouped by their identity. Every group contains the same finding
+    /// once for every time it has been reported
+    pub fn into_findings(self) -> impl Iterator<Item = Vec<Value>> {
+        self.findings.into_values()
+    }
+
+    fn read_json(path: &Path) -> Result<Vec<Map<String, Value>>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut records = Vec::new();
+        for value in serde_json::Deserializer::from_reader(reader).into_iter::<Value>() {
+            match value {
+                Ok(Value::Object(record)) => records.push(record),
+                Ok(_) => return Err(anyhow!("{}: expected a JSON object", path.display())),
+                Err(why) => return Err(anyhow!("{}: {}", path.display(), why)),
+            }
+        }
+        Ok(records)
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn read_sqlite(path: &Path) -> Result<Vec<Map<String, Value>>> {
+        let connection = rusqlite::Connection::open_with_flags(
+            path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?;
+        let tables: Vec<String> = connection
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let mut records = Vec::new();
+        for table in tables {
+            let mut statement =
+                connection.prepare(&format!("SELECT * FROM \"{}\"", table.replace('"', "\"\"")))?;
+            let columns: Vec<String> = statement
+                .column_names()
+                .into_iter()
+                .map(|c| c.to_owned())
+                .collect();
+            if !columns.iter().any(|c| c == "01_scanner") {
+                log::info!("ignoring the table '{}', which contains no findings", table);
+                continue;
+            }
+            let mut rows = statement.query([])?;
+            while let Some(row) = rows.next()? {
+                let mut record = Map::new();
+                for (index, column) in columns.iter().enumerate() {
+                    record.insert(column.clone(), sqlite_value(row.get_ref(index)?));
+                }
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn read_sqlite(path: &Path) -> Result<Vec<Map<String, Value>>> {
+        Err(anyhow!(
+            "'{}' is a SQLite database, but dionysos has been built without support for SQLite \
+             (feature 'sqlite')",
+            path.display()
+        ))
+    }
+}
+
+/// the rule or indicator of a finding of the `json` output, e.g. the name of a yara
+/// rule or a hash
+pub(crate) fn indicator(finding: &Value) -> String {
+    INDICATOR_KEYS
+        .iter()
+        .find_map(|key| match finding.get(*key) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+/// converts a column of SQLite to JSON. Nested objects and arrays are stored as JSON text
+#[cfg(feature = "sqlite")]
+fn sqlite_value(value: rusqlite::types::ValueRef) -> Value {
+    use rusqlite::types::ValueRef;
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(text) => {
+            let text = String::from_utf8_lossy(text);
+            match serde_json::from_str(&text) {
+                Ok(value @ (Value::Object(_) | Value::Array(_))) => value,
+                _ => Value::String(text.into_owned()),
+            }
+        }
+        ValueRef::Blob(blob) => Value::String(hex::encode(blob)),
+    }
+}
+
+/// the identity of a finding, which consists of its scanner, its file and the
+/// `IDENTITY_KEYS` of its scanner, or `None` for errors and for objects which are no
+/// findings
+fn identity(record: &Map<String, Value>) -> Option<String> {
+    let scanner = record.get("01_scanner")?.as_str()?;
+    let file = record.get("02_suspicious_file")?.as_str()?;
+    if scanner == "error" {
+        return None;
+    }
+    let values: Vec<String> = match IDENTITY_KEYS.iter().find(|(s, _)| *s == scanner) {
+        Some((_, keys)) => keys
+            .iter()
+            .map(|key| record.get(*key).unwrap_or(&Value::Null).to_string())
+            .collect(),
+        None => vec![Value::Object(without_annotations(record)).to_string()],
+    };
+    Some(format!("{}\0{}\0{}", scanner, file, values.join("\0")))
+}
+
+/// `record` without the `ANNOTATION_KEYS`
+fn without_annotations(record: &Map<String, Value>) -> Map<String, Value> {
+    record
+        .iter()
+        .filter(|(key, _)| {
+            let number = key.split_once('_').and_then(|(number, _)| number.parse::<u32>().ok());
+            !matches!(number, Some(number) if ANNOTATION_KEYS.contains(&number))
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Change {
+    /// the finding has only been reported by the new scan
+    New,
+
+    /// the finding has only been reported by the old scan
+    Resolved,
+
+    /// the finding has been reported by both scans, but with different details, e.g.
+    /// other matches of a yara rule
+    Changed,
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::New => write!(f, "new"),
+            Change::Resolved => write!(f, "resolved"),
+            Change::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+/// the difference of a finding between two scans, with all its findings of both scans
+pub(crate) struct FindingDiff {
+    pub change: Change,
+    pub old: Vec<Value>,
+    pub new: Vec<Value>,
+}
+
+impl FindingDiff {
+    /// the most recent finding, which is used to describe this difference
+    pub fn finding(&self) -> &Value {
+        self.new.first().or_else(|| self.old.first()).unwrap()
+    }
+
+    pub fn scanner(&self) -> &str {
+        self.finding()["01_scanner"].as_str().unwrap_or_default()
+    }
+
+    pub fn file(&self) -> &str {
+        self.finding()["02_suspicious_file"]
+            .as_str()
+            .unwrap_or_default()
+    }
+
+    /// the rule or indicator of the finding, e.g. the name of a yara rule or a hash
+    pub fn indicator(&self) -> String {
+        indicator(self.finding())
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "01_change": self.change.to_string(),
+            "02_scanner": self.scanner(),
+            "03_suspicious_file": self.file(),
+            "04_old": self.old,
+            "05_new": self.new
+        })
+    }
+}
+
+/// compares the findings of two scans. Findings which are unchanged are omitted
+pub(crate) fn diff(old: ResultSet, mut new: ResultSet) -> Vec<FindingDiff> {
+    let mut diffs = Vec::new();
+    for (identity, old) in old.findings {
+        match new.findings.remove(&identity) {
+            None => diffs.push(FindingDiff {
+                change: Change::Resolved,
+                old,
+                new: Vec::new(),
+            }),
+            Some(new) if !same_findings(&old, &new) => diffs.push(FindingDiff {
+                change: Change::Changed,
+                old,
+                new,
+            }),
+            Some(_) => (),
+        }
+    }
+    diffs.extend(new.findings.into_values().map(|new| FindingDiff {
+        change: Change::New,
+        old: Vec::new(),
+        new,
+    }));
+    diffs.sort_by(|a, b| (a.file(), a.change as u8).cmp(&(b.file(), b.change as u8)));
+    diffs
+}
+
+/// findings are compared regardless of their order, which depends on the scan threads,
+/// and regardless of their annotations
+fn same_findings(old: &[Value], new: &[Value]) -> bool {
+    let sorted = |findings: &[Value]| {
+        let mut findings: Vec<String> = findings
+            .iter()
+            .map(|f| match f {
+                Value::Object(record) => Value::Object(without_annotations(record)).to_string(),
+                f => f.to_string(),
+            })
+            .collect();
+        findings.sort();
+        findings
+    };
+    sorted(old) == sorted(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{diff, identity, Change, ResultSet};
+
+    fn result_set(findings: &[Value]) -> ResultSet {
+        let mut result_set = ResultSet::default();
+        for finding in findings {
+            let record = finding.as_object().unwrap();
+            if let Some(identity) = identity(record) {
+                result_set
+                    .findings
+                    .entry(identity)
+                    .or_default()
+                    .push(finding.clone());
+            }
+        }
+        result_set
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = result_set(&[
+            json!({"01_scanner": "yara", "02_suspicious_file": "/a", "04_strings": [1], "09_rule": "r1"}),
+            json!({"01_scanner": "yara", "02_suspicious_file": "/b", "04_strings": [1], "09_rule": "r1"}),
+            json!({"01_scanner": "hash", "02_suspicious_file": "/c", "03_hash": "00"}),
+            json!({"01_scanner": "error", "02_suspicious_file": "/d", "04_error": "denied"}),
+        ]);
+        let new = result_set(&[
+            json!({"01_scanner": "yara", "02_suspicious_file": "/a", "04_strings": [2], "09_rule": "r1"}),
+            json!({"01_scanner": "hash", "02_suspicious_file": "/c", "03_hash": "00"}),
+            json!({"01_scanner": "filename", "02_suspicious_file": "/e", "03_pattern": "x"}),
+        ]);
+
+        let changes: Vec<(String, Change)> = diff(old, new)
+            .into_iter()
+            .map(|d| (d.file().to_owned(), d.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("/a".to_owned(), Change::Changed),
+                ("/b".to_owned(), Change::Resolved),
+                ("/e".to_owned(), Change::New),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        // results of older versions have no severity, and the detections of hash lookups
+        // change over time
+        let old = result_set(&[
+            json!({"01_scanner": "yara", "02_suspicious_file": "/a", "04_strings": [1], "09_rule": "r1"}),
+            json!({"01_scanner": "hash_lookup", "02_suspicious_file": "/a",
+                   "91_hash_lookup": {"virustotal": {"detected": 40}}}),
+        ]);
+        let new = result_set(&[
+            json!({"01_scanner": "yara", "02_suspicious_file": "/a", "04_strings": [1], "09_rule": "r1",
+                   "89_severity": "high", "90_threat_intel": [], "92_attack": ["T1505.003"]}),
+            json!({"01_scanner": "hash_lookup", "02_suspicious_file": "/a",
+                   "91_hash_lookup": {"virustotal": {"detected": 45}}}),
+        ]);
+        assert!(diff(old, new).is_empty());
+    }
+
+    #[test]
+    fn test_hash_identity() {
+        // a file which matches another hash of the IoC list is a new finding
+        let old = result_set(&[
+            json!({"01_scanner": "hash", "02_suspicious_file": "/c", "03_hash": "00"}),
+        ]);
+        let new = result_set(&[
+            json!({"01_scanner": "hash", "02_suspicious_file": "/c", "03_hash": "11"}),
+        ]);
+        let changes: Vec<Change> = diff(old, new).into_iter().map(|d| d.change).collect();
+        assert_eq!(changes, vec![Change::New, Change::Resolved]);
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt::Display;
+
+/// maximum number of files which are listed in the summary
+const MAX_LISTED_FILES: usize = 10;
+
+/// the cause of an error which occurred during the scan of a file. The errors of the
+/// scanners are plain messages, so that the cause is guessed from the message
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ScanErrorKind {
+    PermissionDenied,
+    ReadFailure,
+    ParseFailure,
+    Timeout,
+    Other,
+}
+
+const ERROR_KINDS: [ScanErrorKind; 5] = [
+    ScanErrorKind::PermissionDenied,
+    ScanErrorKind::ReadFailure,
+    ScanErrorKind::ParseFailure,
+    ScanErrorKind::Timeout,
+    ScanErrorKind::Other,
+];
+
+impl ScanErrorKind {
+    pub fn of(message: &str) -> Self {
+        let message = message.to_lowercase();
+        let contains_any = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+        // the error codes of EACCES and ERROR_ACCESS_DENIED
+        let access_denied = if cfg!(windows) { "os error 5)" } else { "os error 13)" };
+        if contains_any(&["permission denied", "access is denied", access_denied]) {
+            ScanErrorKind::PermissionDenied
+        } else if contains_any(&["--file-timeout", "timed out", "timeout"]) {
+            ScanErrorKind::Timeout
+        } else if contains_any(&["parse", "invalid", "malformed", "corrupt", "unable to decode"]) {
+            ScanErrorKind::ParseFailure
+        } else if contains_any(&["os error", "unable to read", "unable to open", "i/o", "end of file"]) {
+            ScanErrorKind::ReadFailure
+        } else {
+            ScanErrorKind::Other
+        }
+    }
+}
+
+impl Display for ScanErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanErrorKind::PermissionDenied => write!(f, "permission denied"),
+            ScanErrorKind::ReadFailure => write!(f, "read failure"),
+            ScanErrorKind::ParseFailure => write!(f, "parse failure"),
+            ScanErrorKind::Timeout => write!(f, "timeout"),
+            ScanErrorKind::Other => write!(f, "other error"),
+        }
+    }
+}
+
+/// the errors of all files, which are summarized at the end of the scan, so that
+/// gaps in the coverage of the scan are not overlooked
+#[derive(Default)]
+pub(crate) struct ScanErrors {
+    counts: [usize; ERROR_KINDS.len()],
+    files: Vec<String>,
+}
+
+impl ScanErrors {
+    /// records the errors of `filename`
+    pub fn add<S: AsRef<str>>(&mut self, filename: &str, errors: &[S]) {
+        if errors.is_empty() {
+            return;
+        }
+        for error in errors {
+            let kind = ScanErrorKind::of(error.as_ref());
+            let index = ERROR_KINDS.iter().position(|k| *k == kind).unwrap();
+            self.counts[index] += 1;
+        }
+        self.files.push(filename.to_owned());
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        if self.files.is_empty() {
+            return Vec::new();
+        }
+        let counts: Vec<String> = ERROR_KINDS
+            .iter()
+            .zip(self.counts.iter())
+            .filter(|(_, count)| **count > 0)
+            .map(|(kind, count)| format!("{}: {}", kind, count))
+            .collect();
+        let mut lines = vec![format!(
+            "{} file(s) have not been scanned completely ({}):",
+            self.files.len(),
+            counts.join(", ")
+        )];
+        lines.extend(self.files.iter().take(MAX_LISTED_FILES).map(|f| format!("  {}", f)));
+        if self.files.len() > MAX_LISTED_FILES {
+            lines.push(format!(
+                "  ... and {} more, see the errors in the output",
+                self.files.len() - MAX_LISTED_FILES
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ScanErrorKind, ScanErrors};
+
+    #[test]
+    fn test_error_kinds() {
+        assert_eq!(
+            ScanErrorKind::of("unable to open '/root/.ssh/id_rsa': Permission denied (os error 13)"),
+            ScanErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            ScanErrorKind::of("the scan has been aborted after 60s (--file-timeout)"),
+            ScanErrorKind::Timeout
+        );
+        assert_eq!(
+            ScanErrorKind::of("the yara scan of '/a' has been aborted after 240s (--yara-timeout)"),
+            ScanErrorKind::Timeout
+        );
+        assert_eq!(
+            ScanErrorKind::of("unable to parse the registry hive: invalid signature"),
+            ScanErrorKind::ParseFailure
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            ScanErrorKind::of("unable to read '/dev/sdb1': Input/output error (os error 5)"),
+            ScanErrorKind::ReadFailure
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut errors = ScanErrors::default();
+        errors.add::<String>("/tmp/clean", &[]);
+        assert!(errors.summary().is_empty());
+
+        errors.add("/tmp/a", &["Permission denied (os error 13)", "unable to read 'x'"]);
+        errors.add("/tmp/b", &["Permission denied (os error 13)"]);
+        let summary = errors.summary();
+        assert_eq!(
+            summary[0],
+            "2 file(s) have not been scanned completely (permission denied: 2, read failure: 1):"
+        );
+        assert_eq!(summary.len(), 3);
+    }
+}
@@ -0,0 +1,1075 @@
+use std::any::Any;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use crate::compressed_files::CompressedFiles;
+use crate::file_provider::{FileProvider, ProvidedFile, WalkDirProvider};
+use crate::file_timeout::{FileHandler, FileTimeout};
+use crate::filename_scanner::FilenameScanner;
+use crate::filescanner::FileScanner;
+use crate::hash_list::HashAlgorithms;
+use crate::hash_scanner::HashScanner;
+use crate::known_good::{KnownGoodFilter, KnownGoodMode};
+use crate::mail::mail_parts;
+use crate::scanner_result::{ScannerFinding, ScannerResult};
+use crate::telemetry;
+use crate::worker_watchdog::WorkerActivity;
+use crate::yara::{YaraRuleset, YaraScanner};
+
+/// runs `scan` in a panic boundary, so that a scanner which panics on a single file
+/// does not kill the worker. The panic is returned as an error of `scanner`
+fn isolate_panics<F>(scanner: &dyn FileScanner, name: &str, scan: F) -> Vec<Result<Box<dyn ScannerFinding>>>
+where
+    F: FnOnce() -> Vec<Result<Box<dyn ScannerFinding>>>,
+{
+    panic::catch_unwind(AssertUnwindSafe(scan)).unwrap_or_else(|payload| {
+        vec![Err(anyhow!(
+            "{} panicked while scanning '{}': {}",
+            scanner,
+            name,
+            panic_message(&*payload)
+        ))]
+    })
+}
+
+/// the message which has been passed to `panic!`
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// scans a file with all scanners, and collects their findings. Errors are logged
+/// and counted in `errors`. The running scanner is reported to `activity`. The parts
+/// of mails of at most `max_mail_size` bytes are scanned as well
+pub(crate) fn handle_file(
+    scanners: &Arc<Vec<Box<dyn FileScanner>>>,
+    known_good: &Option<Arc<KnownGoodFilter>>,
+    entry: &walkdir::DirEntry,
+    errors: &AtomicUsize,
+    activity: Option<&WorkerActivity>,
+    max_mail_size: Option<u64>,
+) -> ScannerResult {
+    let mut result = ScannerResult::from(entry.path());
+    let _span = telemetry::span("handle_file", || {
+        vec![("file", result.filename().to_owned())]
+    });
+
+    if let Some(known_good) = known_good {
+        if matches!(known_good.mode(), KnownGoodMode::Skip) && known_good.is_known_good(entry.path()) {
+            result.skip("known-good file");
+            return result;
+        }
+    }
+
+    'scanners: for scanner in scanners.iter() {
+        log::trace!(
+            "starting {} on {}",
+            scanner,
+            entry.file_name().to_string_lossy()
+        );
+        let begin = Instant::now();
+        let _span = telemetry::span("scan_file", || vec![("scanner", scanner.to_string())]);
+        if let Some(activity) = activity {
+            activity.start_scanner(entry.path(), scanner);
+        }
+
+        let findings = isolate_panics(scanner.as_ref(), result.filename(), || scanner.scan_file(entry));
+        for res in findings.into_iter() {
+            match res {
+                Err(why) if why.is::<ScanCancelled>() => {
+                    log::info!("the scan of '{}' has been cancelled", entry.path().display());
+                    result.skip("scan cancelled");
+                    break 'scanners;
+                }
+                Err(why) => {
+                    log::error!("{}", why);
+                    errors.fetch_add(1, Ordering::Relaxed);
+                    result.add_error(why.to_string());
+                }
+
+                Ok(res) => {
+                    log::trace!(
+                        "new finding from {} for {}",
+                        scanner,
+                        entry.path().display()
+                    );
+                    match scanner.depends_on_path() {
+                        true => result.add_path_dependent_finding(res),
+                        false => result.add_finding(res),
+                    }
+                }
+            }
+        }
+
+        log::trace!(
+            "finished {} on {} in {}s",
+            scanner,
+            entry.file_name().to_string_lossy(),
+            Instant::now().duration_since(begin).as_secs_f64()
+        );
+    }
+
+    if let Some(max_mail_size) = max_mail_size {
+        if result.skip_reason().is_none() {
+            scan_mail_parts(
+                scanners,
+                known_good,
+                entry,
+                max_mail_size,
+                &mut result,
+                errors,
+            );
+        }
+    }
+
+    if let Some(known_good) = known_good {
+        if matches!(known_good.mode(), KnownGoodMode::Suppress)
+            && result.has_findings()
+            && known_good.is_known_good(entry.path())
+        {
+            return ScannerResult::from(entry.path());
+        }
+    }
+    result
+}
+
+/// scans the bodies and attachments of mails (see [`crate::mail::mail_parts`]) with all
+/// scanners which support scanning data in memory. Their findings are added to the result
+/// of the mail, and are located in `<mail>:<part>`
+fn scan_mail_parts(
+    scanners: &Arc<Vec<Box<dyn FileScanner>>>,
+    known_good: &Option<Arc<KnownGoodFilter>>,
+    entry: &walkdir::DirEntry,
+    max_size: u64,
+    result: &mut ScannerResult,
+    errors: &AtomicUsize,
+) {
+    let parts = match mail_parts(entry.path(), max_size) {
+        Some(parts) => parts,
+        None => return,
+    };
+    for part in parts {
+        let name = format!("{}:{}", result.filename(), part.name);
+        log::info!("scanning '{}'", name);
+        let mut part_result = handle_buffer(scanners, known_good.as_deref(), &name, &part.data);
+        for finding in part_result.take_findings() {
+            result.add_finding(finding);
+        }
+        for error in part_result.errors() {
+            errors.fetch_add(1, Ordering::Relaxed);
+            result.add_error(error.clone());
+        }
+        if part_result.skip_reason() == Some("scan cancelled") {
+            result.skip("scan cancelled");
+            return;
+        }
+    }
+}
+
+/// scans data in memory with all scanners which support it (see
+/// [`FileScanner::scan_buffer`]). `name` is used as the filename of the result
+fn handle_buffer(
+    scanners: &[Box<dyn FileScanner>],
+    known_good: Option<&KnownGoodFilter>,
+    name: &str,
+    data: &[u8],
+) -> ScannerResult {
+    let mut result = ScannerResult::from(name.to_owned());
+    let _span = telemetry::span("handle_buffer", || vec![("name", name.to_owned())]);
+
+    if let Some(known_good) = known_good {
+        if matches!(known_good.mode(), KnownGoodMode::Skip) && known_good.is_known_good_buffer(name, data) {
+            result.skip("known-good file");
+            return result;
+        }
+    }
+
+    for scanner in scanners.iter() {
+        let _span = telemetry::span("scan_buffer", || vec![("scanner", scanner.to_string())]);
+        let findings = isolate_panics(scanner.as_ref(), name, || scanner.scan_buffer(name, data));
+        for res in findings.into_iter() {
+            match res {
+                Err(why) if why.is::<ScanCancelled>() => {
+                    result.skip("scan cancelled");
+                    return result;
+                }
+                Err(why) => {
+                    log::error!("{}", why);
+                    result.add_error(why.to_string());
+                }
+                Ok(res) => result.add_finding(res),
+            }
+        }
+    }
+
+    if let Some(known_good) = known_good {
+        if matches!(known_good.mode(), KnownGoodMode::Suppress)
+            && result.has_findings()
+            && known_good.is_known_good_buffer(name, data)
+        {
+            return ScannerResult::from(name.to_owned());
+        }
+    }
+    result
+}
+
+/// statistics of a finished [`ScanJob`]
+#[derive(Default)]
+pub struct ScanSummary {
+    files: usize,
+    findings: usize,
+    errors: usize,
+    skipped: usize,
+    cancelled: bool,
+}
+
+impl ScanSummary {
+    /// number of files which have been scanned
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    pub fn findings(&self) -> usize {
+        self.findings
+    }
+
+    /// number of errors, e.g. files which could not be read
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// number of files which have not been scanned completely, because the scan
+    /// has been cancelled. They are included in [`ScanSummary::files`]
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// `true` if the scan has been cancelled with [`ScanHandle::cancel`]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+}
+
+/// the error which scanners return if their scan has been aborted by
+/// [`ScanHandle::cancel`]. It is not counted as error, but the file is reported
+/// as skipped
+#[derive(Debug)]
+pub struct ScanCancelled;
+
+impl std::fmt::Display for ScanCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "the scan has been cancelled")
+    }
+}
+
+impl std::error::Error for ScanCancelled {}
+
+/// can be used to cancel a running [`ScanJob`] from another thread
+#[derive(Clone, Default)]
+pub struct ScanHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScanHandle {
+    /// cancels the scan: the enumeration of files stops, yara scans of archives, event
+    /// logs, registry hives and databases are aborted before their next entry, and
+    /// libyara aborts the scan of every other file when it evaluates its next rule.
+    /// Files which have already been enumerated are passed to the callback as skipped,
+    /// so that [`ScanJob::run`] returns as soon as the running scans have ended
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// returns [`ScanCancelled`] if the scan has been cancelled. Custom scanners
+    /// which take long can call this regularly, using the handle of their job
+    pub fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(ScanCancelled.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+/// the results of a [`ScanJob`] which has been started with [`ScanJob::run_iter`].
+/// The iterator blocks until the next file has been scanned, and ends when all files
+/// have been scanned. If it is dropped before, the scan is cancelled
+pub struct ScanResults {
+    results: mpsc::Receiver<ScannerResult>,
+    scan: thread::JoinHandle<Result<ScanSummary>>,
+    handle: ScanHandle,
+}
+
+impl ScanResults {
+    /// returns a handle, which can be used to cancel the scan
+    pub fn handle(&self) -> ScanHandle {
+        self.handle.clone()
+    }
+
+    /// waits until the scan has finished, and returns its statistics. Results which
+    /// have not been consumed yet are discarded
+    pub fn summary(self) -> Result<ScanSummary> {
+        drop(self.results);
+        self.scan
+            .join()
+            .map_err(|_| anyhow!("the scan has failed"))?
+    }
+}
+
+impl Iterator for ScanResults {
+    type Item = ScannerResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.results.recv().ok()
+    }
+}
+
+/// hooks of the command line into the workers of a [`ScanJob`], which display what
+/// the workers are doing and throttle them
+pub(crate) trait WorkerHooks: Send {
+    /// the activity of the worker, which is watched for stuck scans
+    fn activity(&self) -> Option<Arc<WorkerActivity>> {
+        None
+    }
+
+    /// files which are received after the scan has been interrupted are skipped
+    fn is_interrupted(&self) -> bool {
+        false
+    }
+
+    fn start_file(&self, _file: &ProvidedFile) {}
+
+    /// is called after a file has been scanned in `elapsed`. Returns `false` if the
+    /// results of the file must be discarded
+    fn finish_file(&self, _elapsed: Duration) -> bool {
+        true
+    }
+
+    /// is called when the worker exits
+    fn finish(&self) {}
+}
+
+/// the workers of a scan which has been started with [`ScanJob::start`]
+pub(crate) struct Workers {
+    files: spmc::Sender<ProvidedFile>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl Workers {
+    /// passes `file` to the next idle worker. Fails if all workers have exited
+    pub fn scan(&mut self, file: ProvidedFile) -> Result<()> {
+        self.files
+            .send(file)
+            .map_err(|_| anyhow!("all workers have exited"))
+    }
+
+    /// waits until all files have been scanned, and returns the number of workers
+    /// which have failed
+    pub fn join(self) -> usize {
+        drop(self.files);
+        let mut failed_workers = 0;
+        for worker in self.threads {
+            if let Err(payload) = worker.join() {
+                log::error!("a worker has failed: {}", panic_message(&*payload));
+                failed_workers += 1;
+            }
+        }
+        failed_workers
+    }
+}
+
+/// a scan of all files below a path, which is configured with the `with_*` methods
+/// and started with [`ScanJob::run`]
+pub struct ScanJob {
+    path: PathBuf,
+    provider: Option<Box<dyn FileProvider>>,
+    scanners: Arc<Vec<Box<dyn FileScanner>>>,
+    known_good: Option<Arc<KnownGoodFilter>>,
+    threads: usize,
+    file_timeout: Option<Arc<FileTimeout>>,
+    compressed_files: Option<Arc<CompressedFiles>>,
+    errors: Arc<AtomicUsize>,
+    hooks: Vec<Box<dyn WorkerHooks>>,
+    handle: ScanHandle,
+    hash_algorithms: HashAlgorithms,
+    max_mail_size: Option<u64>,
+}
+
+impl ScanJob {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            provider: None,
+            scanners: Arc::new(Vec::new()),
+            known_good: None,
+            threads: num_cpus::get(),
+            file_timeout: None,
+            compressed_files: None,
+            errors: Arc::new(AtomicUsize::new(0)),
+            hooks: Vec::new(),
+            handle: ScanHandle::default(),
+            hash_algorithms: HashAlgorithms::default(),
+            max_mail_size: None,
+        }
+    }
+
+    /// returns a handle, which can be used to cancel the scan while it is running
+    pub fn handle(&self) -> ScanHandle {
+        self.handle.clone()
+    }
+
+    /// scans the content of files with the yara rules in `ruleset`, which can be
+    /// a single file, a zip file or a directory
+    pub fn with_yara<P: AsRef<Path>>(mut self, ruleset: P) -> Result<Self> {
+        let ruleset = YaraRuleset::new(ruleset);
+        self.add_scanner(Box::new(
+            YaraScanner::new(&ruleset)?.with_scan_handle(self.handle.clone()),
+        ));
+        Ok(self)
+    }
+
+    /// like [`ScanJob::with_yara`], but uses `rules`, which have already been compiled
+    /// from `ruleset`
+    #[cfg(any(feature = "grpc", feature = "http"))]
+    pub(crate) fn with_yara_rules<P: AsRef<Path>>(
+        mut self,
+        ruleset: P,
+        rules: Arc<yara::Rules>,
+    ) -> Self {
+        let ruleset = YaraRuleset::new(ruleset);
+        self.add_scanner(Box::new(
+            YaraScanner::from_rules(&ruleset, rules).with_scan_handle(self.handle.clone()),
+        ));
+        self
+    }
+
+    /// reports files which have one of these hashes (MD5, SHA1, SHA256, ...)
+    pub fn with_hashes(mut self, hashes: &[String]) -> Result<Self> {
+        self.add_scanner(Box::new(HashScanner::default().with_hashes(hashes)?));
+        Ok(self)
+    }
+
+    /// reports files whose hashes are listed in `hash_file`
+    pub fn with_hash_file<P: AsRef<Path>>(mut self, hash_file: P) -> Result<Self> {
+        self.add_scanner(Box::new(HashScanner::default().with_hash_file(hash_file)?));
+        Ok(self)
+    }
+
+    /// reports files whose basename matches one of these regular expressions
+    pub fn with_filenames(mut self, patterns: &[String]) -> Result<Self> {
+        self.add_scanner(Box::new(FilenameScanner::from_patterns(patterns)?));
+        Ok(self)
+    }
+
+    /// adds a custom scanner, which is run on every file after the scanners
+    /// which have been added before
+    pub fn with_scanner<S: FileScanner + 'static>(mut self, scanner: S) -> Self {
+        self.add_scanner(Box::new(scanner));
+        self
+    }
+
+    /// the scanners are only shared by [`ScanJob::with_scanners`], which is not
+    /// combined with the other methods which add scanners
+    fn add_scanner(&mut self, scanner: Box<dyn FileScanner>) {
+        Arc::get_mut(&mut self.scanners)
+            .expect("no scanners can be added to shared scanners")
+            .push(scanner);
+    }
+
+    /// scans the files with `scanners`, which are shared with the caller (e.g. to
+    /// reload their rulesets while the scan is running)
+    pub(crate) fn with_scanners(mut self, scanners: Arc<Vec<Box<dyn FileScanner>>>) -> Self {
+        self.scanners = scanners;
+        self
+    }
+
+    /// drops the findings of files whose hashes are listed in `hash_file`
+    pub fn with_known_good<P: AsRef<Path>>(mut self, hash_file: P) -> Result<Self> {
+        let known_good = match self.known_good.take().map(Arc::try_unwrap) {
+            None => KnownGoodFilter::new(KnownGoodMode::Suppress),
+            Some(Ok(known_good)) => known_good,
+            Some(Err(_)) => return Err(anyhow!("the known-good filter is shared")),
+        };
+        self.known_good = Some(Arc::new(known_good.with_hash_file(hash_file)?));
+        Ok(self)
+    }
+
+    /// filters the files with `known_good`, which is shared with the caller
+    pub(crate) fn with_known_good_filter(
+        mut self,
+        known_good: Option<Arc<KnownGoodFilter>>,
+    ) -> Self {
+        self.known_good = known_good;
+        self
+    }
+
+    /// scans the files of `provider` instead of the files below the path of this job
+    pub fn with_provider<P: FileProvider + 'static>(mut self, provider: P) -> Self {
+        self.provider = Some(Box::new(provider));
+        self
+    }
+
+    /// number of files which are scanned in parallel
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// aborts the scan of a file if all scanners together need more than `timeout`
+    /// for it. The file is reported with an error, and the scan continues with the
+    /// next file. The scanner which exceeded the timeout cannot be stopped, so it
+    /// keeps running in the background until it has finished
+    pub fn with_file_timeout(mut self, timeout: Duration) -> Self {
+        self.file_timeout = Some(Arc::new(FileTimeout::new(timeout)));
+        self
+    }
+
+    /// like [`ScanJob::with_file_timeout`], but `file_timeout` is shared with the
+    /// caller, which reports the aborted files
+    pub(crate) fn with_timeout(mut self, file_timeout: Option<Arc<FileTimeout>>) -> Self {
+        self.file_timeout = file_timeout;
+        self
+    }
+
+    /// checks if compressed files can be decompressed before they are scanned
+    pub(crate) fn with_compressed_files(mut self, compressed_files: Arc<CompressedFiles>) -> Self {
+        self.compressed_files = Some(compressed_files);
+        self
+    }
+
+    /// counts the errors of the scan in `errors`
+    pub(crate) fn with_errors(mut self, errors: Arc<AtomicUsize>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// cancels the scan with `handle`, which has already been passed to the scanners
+    pub(crate) fn with_handle(mut self, handle: ScanHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+
+    /// the hooks of the workers, one per worker
+    pub(crate) fn with_hooks(mut self, hooks: Vec<Box<dyn WorkerHooks>>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// computes the hashes of every file with findings in the worker which has scanned
+    /// it, so that the hash sets of the writer (threat intelligence, ignored hashes) do
+    /// not read the file again
+    pub(crate) fn with_hash_algorithms(mut self, hash_algorithms: HashAlgorithms) -> Self {
+        self.hash_algorithms = hash_algorithms;
+        self
+    }
+
+    /// decodes mails (`.eml` and Outlook `.msg`) of at most `max_size` bytes, and scans
+    /// their bodies and attachments with all scanners which support scanning data in
+    /// memory. Their findings are added to the result of the mail
+    pub fn with_mail_parts(mut self, max_size: u64) -> Self {
+        self.max_mail_size = Some(max_size);
+        self
+    }
+
+    /// scans `data` (e.g. an email attachment or an upload which is held in memory)
+    /// with all scanners of this job, instead of the files below its path. `name` is
+    /// used as the filename of the result and of its findings, and is matched by the
+    /// filename patterns. Scanners which only work on files (e.g. custom scanners
+    /// which do not implement [`FileScanner::scan_buffer`]) are skipped. This can be
+    /// called any number of times, also from multiple threads
+    pub fn scan_buffer(&self, name: &str, data: &[u8]) -> ScannerResult {
+        handle_buffer(&self.scanners, self.known_good.as_deref(), name, data)
+    }
+
+    /// like [`ScanJob::scan_buffer`], but reads the data from `reader`. The data is
+    /// read into memory completely, because yara needs all of it at once
+    pub fn scan_reader<R: Read>(&self, name: &str, mut reader: R) -> Result<ScannerResult> {
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|why| anyhow!("unable to read '{}': {}", name, why))?;
+        Ok(self.scan_buffer(name, &data))
+    }
+
+    /// scans all files and calls `callback` for every scanned file, as soon as its
+    /// scan has finished. Files without findings are passed to `callback` as well.
+    pub fn run<F>(self, mut callback: F) -> Result<ScanSummary>
+    where
+        F: FnMut(&ScannerResult),
+    {
+        self.execute(|result| callback(&result))
+    }
+
+    /// scans all files and sends the result of every scanned file to `results`, as
+    /// soon as its scan has finished. This allows the results to be consumed in
+    /// another thread. The scan is cancelled if the receiver is dropped.
+    pub fn run_with_sender(self, results: mpsc::Sender<ScannerResult>) -> Result<ScanSummary> {
+        let handle = self.handle();
+        self.execute(|result| {
+            if results.send(result).is_err() {
+                handle.cancel();
+            }
+        })
+    }
+
+    /// starts the scan in a separate thread, and returns an iterator over the result
+    /// of every scanned file, as soon as its scan has finished:
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut results = dionysos_lib::ScanJob::new("/mnt/evidence").with_yara("/opt/rules")?.run_iter();
+    /// for result in results.by_ref().filter(|r| r.has_findings()) {
+    ///     println!("{}", result.filename());
+    /// }
+    /// println!("scanned {} file(s)", results.summary()?.files());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_iter(self) -> ScanResults {
+        let (tx, rx) = mpsc::channel();
+        let handle = self.handle();
+        ScanResults {
+            results: rx,
+            scan: thread::spawn(move || self.run_with_sender(tx)),
+            handle,
+        }
+    }
+
+    /// starts the workers, which send the result of every scanned file to `results`.
+    /// The files are passed to the workers with [`Workers::scan`]
+    pub(crate) fn start(mut self, results: mpsc::Sender<ScannerResult>) -> Workers {
+        let trace_context = telemetry::current();
+        let (files, rx_in) = spmc::channel();
+        let mut threads = Vec::new();
+        let mut worker_hooks = std::mem::take(&mut self.hooks).into_iter();
+        for id in 0..self.threads {
+            log::trace!("creating worker #{}", id);
+            let rx: spmc::Receiver<ProvidedFile> = rx_in.clone();
+            let tx = results.clone();
+            let hooks = worker_hooks.next().unwrap_or_else(|| Box::new(NoHooks));
+            let scanners = Arc::clone(&self.scanners);
+            let known_good = self.known_good.as_ref().map(Arc::clone);
+            let errors = Arc::clone(&self.errors);
+            let mut file_handler =
+                FileHandler::new(Arc::clone(&scanners), known_good.clone(), Arc::clone(&errors))
+                    .with_timeout(self.file_timeout.clone())
+                    .with_activity(hooks.activity())
+                    .with_compressed_files(self.compressed_files.clone())
+                    .with_max_mail_size(self.max_mail_size);
+            let handle = self.handle.clone();
+            let hash_algorithms = self.hash_algorithms;
+            let trace_context = trace_context.clone();
+            threads.push(thread::spawn(move || {
+                let _trace = telemetry::attach(trace_context);
+                // files which have been enumerated before the scan has been cancelled
+                // are drained, and reported as skipped
+                while let Ok(file) = rx.recv() {
+                    let skip_reason = if handle.is_cancelled() {
+                        Some("scan cancelled")
+                    } else if hooks.is_interrupted() {
+                        Some("scan interrupted")
+                    } else {
+                        None
+                    };
+                    if let Some(skip_reason) = skip_reason {
+                        let mut result = empty_result(&file);
+                        result.skip(skip_reason);
+                        if tx.send(result).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+
+                    hooks.start_file(&file);
+                    let started = Instant::now();
+                    let mut result = match &file {
+                        ProvidedFile::Entry(entry) => file_handler.handle_file(entry),
+                        ProvidedFile::Buffer { name, data } => {
+                            let result =
+                                handle_buffer(&scanners, known_good.as_deref(), name, data);
+                            errors.fetch_add(result.errors().len(), Ordering::Relaxed);
+                            result
+                        }
+                    };
+                    if !hooks.finish_file(started.elapsed()) {
+                        log::warn!("discarding the results of '{}'", file.name());
+                        result = empty_result(&file);
+                        result.skip("discarded by the user");
+                    }
+                    add_hashes(&mut result, hash_algorithms);
+                    if tx.send(result).is_err() {
+                        break;
+                    }
+                }
+                hooks.finish();
+            }));
+        }
+        Workers { files, threads }
+    }
+
+    /// like [`ScanJob::run`], but passes the ownership of the results to `consume`
+    pub(crate) fn execute<F>(mut self, mut consume: F) -> Result<ScanSummary>
+    where
+        F: FnMut(ScannerResult),
+    {
+        let _scan_span =
+            telemetry::span("scan", || vec![("path", self.path.display().to_string())]);
+
+        let mut provider = match self.provider.take() {
+            Some(provider) => provider,
+            None => Box::new(WalkDirProvider::new(&self.path)),
+        };
+        let errors = Arc::clone(&self.errors);
+        let handle = self.handle.clone();
+        let (tx_out, rx_out) = mpsc::channel();
+        let mut workers = self.start(tx_out);
+
+        // the files are enumerated in a separate thread, so that results can be
+        // passed to the callback while the enumeration is running
+        let walk_errors = Arc::clone(&errors);
+        let walk_handle = handle.clone();
+        let trace_context = telemetry::current();
+        let enumerator = thread::spawn(move || {
+            let _trace = telemetry::attach(trace_context);
+            let walk_span = telemetry::span("walk", Vec::new);
+            for file in provider.files() {
+                if walk_handle.is_cancelled() {
+                    break;
+                }
+                match file {
+                    Ok(file) => {
+                        if workers.scan(file).is_err() {
+                            break;
+                        }
+                    }
+                    Err(why) => {
+                        log::error!("{}", why);
+                        walk_errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+            drop(walk_span);
+            workers.join()
+        });
+
+        let mut summary = ScanSummary::default();
+        for result in rx_out {
+            summary.files += 1;
+            summary.findings += result.findings().count();
+            if result.skip_reason().is_some() {
+                summary.skipped += 1;
+            }
+            consume(result);
+        }
+
+        let failed_workers = enumerator
+            .join()
+            .map_err(|_| anyhow!("the enumeration of files failed"))?;
+        if failed_workers > 0 {
+            return Err(anyhow!("{} worker(s) failed", failed_workers));
+        }
+        summary.errors = errors.load(Ordering::Relaxed);
+        summary.cancelled = handle.is_cancelled();
+        Ok(summary)
+    }
+}
+
+/// adds the hashes of the file to `result`, if it has findings which are written to
+/// the output
+fn add_hashes(result: &mut ScannerResult, hash_algorithms: HashAlgorithms) {
+    if hash_algorithms.is_empty() || !result.has_findings() {
+        return;
+    }
+    if let Some(path) = result.path() {
+        match hash_algorithms.hashes_of_file(path) {
+            Ok(hashes) => result.set_hashes(hashes),
+            Err(why) => log::warn!("unable to hash '{}': {}", result.filename(), why),
+        }
+    }
+}
+
+/// the result of `file` without findings, which is reported if `file` is not scanned
+fn empty_result(file: &ProvidedFile) -> ScannerResult {
+    match file {
+        ProvidedFile::Entry(entry) => ScannerResult::from(entry.path()),
+        ProvidedFile::Buffer { name, .. } => ScannerResult::from(name.clone()),
+    }
+}
+
+/// the workers of the library have no hooks
+struct NoHooks;
+
+impl WorkerHooks for NoHooks {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fmt::Display;
+
+    use maplit::hashset;
+    use serde_json::json;
+    use walkdir::DirEntry;
+
+    use super::{ScanCancelled, ScanHandle, ScanJob};
+    use crate::file_provider::ZipArchiveProvider;
+    use crate::csv_line::CsvLine;
+    use crate::filescanner::FileScanner;
+    use crate::hash_list::{CryptoHash, HashList};
+    use crate::scanner_result::ScannerFinding;
+    use crate::string_options::StringOptions;
+
+    struct LibRsScanner;
+    struct LibRsFinding(String);
+
+    impl Display for LibRsScanner {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "LibRsScanner")
+        }
+    }
+
+    impl FileScanner for LibRsScanner {
+        fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+            if file.file_name() != "lib.rs" {
+                return Vec::new();
+            }
+            let finding = LibRsFinding(file.path().display().to_string());
+            vec![Ok(Box::new(finding) as Box<dyn ScannerFinding>)]
+        }
+    }
+
+    impl Display for LibRsFinding {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            writeln!(f, "found '{}'", self.0)
+        }
+    }
+
+    impl ScannerFinding for LibRsFinding {
+        fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+            hashset![CsvLine::new("LibRs", "lib.rs", &self.0, String::new())]
+        }
+
+        fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+            json!({"01_scanner": "lib_rs", "02_suspicious_file": self.0})
+        }
+
+        fn found_in_file(&self) -> &str {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn test_custom_scanner() {
+        let mut found = Vec::new();
+        let summary = ScanJob::new("src")
+            .with_scanner(LibRsScanner)
+            .with_threads(2)
+            .run(|result| {
+                if result.has_findings() {
+                    found.push(result.filename().to_owned());
+                }
+            })
+            .unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert!(found[0].ends_with("lib.rs"));
+        assert_eq!(summary.findings(), 1);
+        assert!(summary.files() > 1);
+        assert_eq!(summary.errors(), 0);
+    }
+
+    /// cancels the scan as soon as it sees the first file
+    struct CancellingScanner(ScanHandle);
+
+    impl Display for CancellingScanner {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "CancellingScanner")
+        }
+    }
+
+    impl FileScanner for CancellingScanner {
+        fn scan_file(&self, _file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+            self.0.cancel();
+            vec![Err(ScanCancelled.into())]
+        }
+    }
+
+    /// panics on `lib.rs`
+    struct PanickingScanner;
+
+    impl Display for PanickingScanner {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "PanickingScanner")
+        }
+    }
+
+    impl FileScanner for PanickingScanner {
+        fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+            if file.file_name() == "lib.rs" {
+                panic!("unexpected file format");
+            }
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_panicking_scanner() {
+        let mut errors = Vec::new();
+        let mut files = 0;
+        let summary = ScanJob::new("src")
+            .with_scanner(PanickingScanner)
+            .with_scanner(LibRsScanner)
+            .with_threads(2)
+            .run(|result| {
+                files += 1;
+                errors.extend(result.errors().iter().cloned());
+                if result.filename().ends_with("lib.rs") {
+                    assert!(result.has_findings());
+                }
+            })
+            .unwrap();
+
+        assert_eq!(summary.files(), files);
+        assert!(files > 1);
+        assert_eq!(summary.errors(), 1);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("PanickingScanner panicked while scanning"));
+        assert!(errors[0].ends_with("unexpected file format"));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let job = ScanJob::new("src").with_threads(2);
+        let handle = job.handle();
+        let mut skipped = 0;
+        let summary = job
+            .with_scanner(CancellingScanner(handle))
+            .run(|result| {
+                assert_eq!(result.skip_reason(), Some("scan cancelled"));
+                skipped += 1;
+            })
+            .unwrap();
+
+        assert!(summary.is_cancelled());
+        assert!(skipped > 0);
+        assert_eq!(summary.skipped(), skipped);
+        assert_eq!(summary.files(), skipped);
+        assert_eq!(summary.errors(), 0);
+    }
+
+    #[test]
+    fn test_scan_buffer() {
+        let job = ScanJob::new("unused")
+            .with_hashes(&["5d41402abc4b2a76b9719d911017c592".to_owned()])
+            .unwrap()
+            .with_filenames(&[r"\.pdf\.exe$".to_owned()])
+            .unwrap()
+            .with_scanner(LibRsScanner);
+
+        let result = job.scan_buffer("invoice.pdf.exe", b"hello");
+        assert_eq!(result.filename(), "invoice.pdf.exe");
+        assert_eq!(result.findings().count(), 2);
+
+        let result = job.scan_reader("invoice.pdf", &b"hello world"[..]).unwrap();
+        assert!(!result.has_findings());
+        assert!(!result.has_errors());
+    }
+
+    #[test]
+    fn test_zip_provider() {
+        use std::io::Write;
+
+        let archive = std::env::temp_dir().join(format!("dionysos-provider-{}.zip", std::process::id()));
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        zip.start_file("hello.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"hello").unwrap();
+        zip.start_file("world.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"world").unwrap();
+        zip.finish().unwrap();
+
+        let mut found = Vec::new();
+        let summary = ScanJob::new("unused")
+            .with_provider(ZipArchiveProvider::new(&archive))
+            .with_hashes(&["5d41402abc4b2a76b9719d911017c592".to_owned()])
+            .unwrap()
+            .run(|result| {
+                if result.has_findings() {
+                    found.push(result.filename().to_owned());
+                }
+            })
+            .unwrap();
+        std::fs::remove_file(&archive).unwrap();
+
+        assert_eq!(summary.files(), 2);
+        assert_eq!(found, vec![format!("{}:hello.txt", archive.display())]);
+    }
+
+    #[test]
+    fn test_run_iter() {
+        let mut results = ScanJob::new("src").with_scanner(LibRsScanner).run_iter();
+        let found: Vec<_> = results
+            .by_ref()
+            .filter(|result| result.has_findings())
+            .map(|result| result.filename().to_owned())
+            .collect();
+        let summary = results.summary().unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert!(found[0].ends_with("lib.rs"));
+        assert_eq!(summary.findings(), 1);
+        assert!(!summary.is_cancelled());
+    }
+
+    #[test]
+    fn test_run_with_sender() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let consumer = std::thread::spawn(move || {
+            rx.into_iter()
+                .filter(|result| result.has_findings())
+                .map(|result| result.filename().to_owned())
+                .collect::<Vec<_>>()
+        });
+        let summary = ScanJob::new("src")
+            .with_scanner(LibRsScanner)
+            .run_with_sender(tx)
+            .unwrap();
+
+        let found = consumer.join().unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].ends_with("lib.rs"));
+        assert_eq!(summary.findings(), 1);
+    }
+
+    #[test]
+    fn test_hash_algorithms() {
+        let mut hashes = HashList::default();
+        hashes.insert(CryptoHash::MD5([0u8; 16]));
+        let expected = hashes.compute_hashes(std::fs::read("src/lib.rs").unwrap());
+
+        let results: Vec<_> = ScanJob::new("src")
+            .with_scanner(LibRsScanner)
+            .with_hash_algorithms(hashes.algorithms())
+            .run_iter()
+            .collect();
+        for result in results {
+            match result.hashes() {
+                Some(hashes) => assert!(result.has_findings() && hashes == &expected[..]),
+                None => assert!(!result.has_findings()),
+            }
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::scan_job::{ScanHandle, ScanJob};
+use crate::scanner_result::ScannerResult;
+use crate::string_options::StringOptions;
+use crate::yara::{YaraRuleset, YaraScanner};
+
+/// finished jobs, and their results, are removed after this time
+const FINISHED_JOB_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// the oldest finished jobs are removed if there are more
+const MAX_FINISHED_JOBS: usize = 100;
+
+/// the least recently used compiled rulesets are removed if there are more
+const MAX_CACHED_RULESETS: usize = 8;
+
+/// a scan which is requested by a client of `dionysos serve`. All paths refer to
+/// files on the server
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ScanRequest {
+    pub path: String,
+    pub yara: Option<String>,
+    pub hashes: Vec<String>,
+    pub hash_lists: Vec<String>,
+    pub filenames: Vec<String>,
+    pub known_good: Vec<String>,
+    pub threads: usize,
+
+    /// how the matched strings of the results are displayed. Every job has its own
+    /// options, which do not affect the other jobs
+    pub print_strings: bool,
+    pub max_strings: Option<usize>,
+    pub max_string_length: Option<usize>,
+}
+
+impl ScanRequest {
+    fn has_scanners(&self) -> bool {
+        self.yara.is_some()
+            || !self.hashes.is_empty()
+            || !self.hash_lists.is_empty()
+            || !self.filenames.is_empty()
+    }
+
+    /// uses the scanners of `defaults`, if the request does not specify any scanner
+    fn or_scanners_of(mut self, defaults: &ScanRequest) -> Self {
+        if !self.has_scanners() {
+            self.yara = defaults.yara.clone();
+            self.hashes = defaults.hashes.clone();
+            self.hash_lists = defaults.hash_lists.clone();
+            self.filenames = defaults.filenames.clone();
+        }
+        if self.known_good.is_empty() {
+            self.known_good = defaults.known_good.clone();
+        }
+        self
+    }
+
+    fn string_options(&self) -> StringOptions {
+        StringOptions::default()
+            .with_print_strings(self.print_strings)
+            .with_max_strings(self.max_strings)
+            .with_max_length(self.max_string_length)
+    }
+
+    /// creates the scan job. Compiling the yara ruleset may take a while, unless it
+    /// is found in `rulesets`
+    fn scan_job(&self, rulesets: &RulesetCache) -> Result<ScanJob> {
+        if !Path::new(&self.path).exists() {
+            return Err(anyhow!("'{}' does not exist", self.path));
+        }
+        if !self.has_scanners() {
+            return Err(anyhow!("no scanner has been requested"));
+        }
+        let mut job = ScanJob::new(&self.path);
+        if let Some(yara) = &self.yara {
+            job = job.with_yara_rules(yara, rulesets.rules(yara)?);
+        }
+        if !self.hashes.is_empty() {
+            job = job.with_hashes(&self.hashes)?;
+        }
+        for hash_list in self.hash_lists.iter() {
+            job = job.with_hash_file(hash_list)?;
+        }
+        if !self.filenames.is_empty() {
+            job = job.with_filenames(&self.filenames)?;
+        }
+        for hash_list in self.known_good.iter() {
+            job = job.with_known_good(hash_list)?;
+        }
+        if self.threads > 0 {
+            job = job.with_threads(self.threads);
+        }
+        Ok(job)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Finished,
+    Cancelled,
+    Failed,
+}
+
+pub struct JobStatus {
+    state: JobState,
+
+    /// when the job has finished, was cancelled or has failed
+    finished_at: Option<Instant>,
+    files: u64,
+    errors: u64,
+    error: Option<String>,
+
+    /// all files with findings, so that clients can fetch them at any time
+    results: Vec<ScannerResult>,
+}
+
+impl JobStatus {
+    pub fn state(&self) -> JobState {
+        self.state
+    }
+
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    pub fn findings(&self) -> u64 {
+        self.results.iter().map(|r| r.findings().count() as u64).sum()
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// reason why the job failed
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn results(&self) -> &[ScannerResult] {
+        &self.results[..]
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "state": self.state,
+            "files": self.files,
+            "findings": self.findings(),
+            "errors": self.errors,
+            "error": self.error,
+        })
+    }
+}
+
+/// a scan job which has been started by a client
+pub struct Job {
+    handle: ScanHandle,
+    string_options: StringOptions,
+    status: Mutex<JobStatus>,
+    changed: Condvar,
+}
+
+impl Job {
+    fn new(handle: ScanHandle, string_options: StringOptions) -> Self {
+        Self {
+            handle,
+            string_options,
+            status: Mutex::new(JobStatus {
+                state: JobState::Running,
+                finished_at: None,
+                files: 0,
+                errors: 0,
+                error: None,
+                results: Vec::new(),
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn run(&self, scan_job: ScanJob) {
+        let result = scan_job.execute(|result| {
+            let mut status = self.status.lock().unwrap();
+            status.files += 1;
+            status.errors += result.errors().len() as u64;
+            if result.has_findings() {
+                status.results.push(result);
+            }
+            drop(status);
+            self.changed.notify_all();
+        });
+
+        let mut status = self.status.lock().unwrap();
+        status.finished_at = Some(Instant::now());
+        match result {
+            Ok(summary) => {
+                status.errors = summary.errors() as u64;
+                status.state = match self.handle.is_cancelled() {
+                    true => JobState::Cancelled,
+                    false => JobState::Finished,
+                };
+            }
+            Err(why) => {
+                status.state = JobState::Failed;
+                status.error = Some(why.to_string());
+            }
+        }
+        drop(status);
+        self.changed.notify_all();
+    }
+
+    /// stops the enumeration of files. Files which are currently being scanned are
+    /// completed
+    pub fn cancel(&self) {
+        self.handle.cancel();
+    }
+
+    /// how the matched strings of the results must be displayed
+    pub fn string_options(&self) -> &StringOptions {
+        &self.string_options
+    }
+
+    pub fn status(&self) -> MutexGuard<'_, JobStatus> {
+        self.status.lock().unwrap()
+    }
+
+    /// blocks until the job has more than `known_results` results, or has finished
+    pub fn wait_for_results(&self, known_results: usize) -> MutexGuard<'_, JobStatus> {
+        let status = self.status.lock().unwrap();
+        self.changed
+            .wait_while(status, |status| {
+                status.results.len() <= known_results && status.state == JobState::Running
+            })
+            .unwrap()
+    }
+}
+
+/// the compiled yara rulesets, so that every request does not compile them again.
+/// A ruleset is compiled again if one of its files has been modified
+#[derive(Default)]
+struct RulesetCache {
+    rulesets: Mutex<HashMap<PathBuf, CachedRuleset>>,
+}
+
+struct CachedRuleset {
+    last_modified: Option<SystemTime>,
+    last_used: Instant,
+    rules: Arc<yara::Rules>,
+}
+
+impl RulesetCache {
+    fn rules(&self, path: &str) -> Result<Arc<yara::Rules>> {
+        let ruleset = YaraRuleset::new(path);
+        let last_modified = ruleset.last_modified();
+        if let Some(cached) = self.rulesets.lock().unwrap().get_mut(ruleset.path()) {
+            if cached.last_modified == last_modified {
+                cached.last_used = Instant::now();
+                return Ok(Arc::clone(&cached.rules));
+            }
+        }
+
+        // the lock is not held while compiling, so that other requests are not blocked
+        log::info!("compiling the yara rules of '{}'", path);
+        let rules = Arc::new(YaraScanner::compile(&ruleset)?);
+        let mut rulesets = self.rulesets.lock().unwrap();
+        rulesets.insert(
+            ruleset.path().to_owned(),
+            CachedRuleset {
+                last_modified,
+                last_used: Instant::now(),
+                rules: Arc::clone(&rules),
+            },
+        );
+        while rulesets.len() > MAX_CACHED_RULESETS {
+            let oldest = rulesets
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                rulesets.remove(&oldest);
+            }
+        }
+        Ok(rules)
+    }
+}
+
+/// the jobs of `dionysos serve`, which are shared by all APIs
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<u64, Arc<Job>>>,
+    next_id: AtomicU64,
+    rulesets: RulesetCache,
+
+    /// how long finished jobs are kept, and how many of them
+    finished_job_ttl: Duration,
+    max_finished_jobs: usize,
+
+    /// scanners which are used for requests which do not specify any
+    defaults: RwLock<ScanRequest>,
+}
+
+impl JobRegistry {
+    pub fn new(defaults: ScanRequest) -> Self {
+        Self {
+            defaults: RwLock::new(defaults),
+            jobs: Mutex::default(),
+            next_id: AtomicU64::default(),
+            rulesets: RulesetCache::default(),
+            finished_job_ttl: FINISHED_JOB_TTL,
+            max_finished_jobs: MAX_FINISHED_JOBS,
+        }
+    }
+
+    pub fn with_finished_job_ttl(mut self, finished_job_ttl: Duration) -> Self {
+        self.finished_job_ttl = finished_job_ttl;
+        self
+    }
+
+    pub fn with_max_finished_jobs(mut self, max_finished_jobs: usize) -> Self {
+        self.max_finished_jobs = max_finished_jobs;
+        self
+    }
+
+    /// replaces the scanners which are used for requests which do not specify any
+    pub fn set_defaults(&self, defaults: ScanRequest) {
+        *self.defaults.write().unwrap() = defaults;
+    }
+
+    /// creates a job and runs it in a separate thread. Returns the id of the job
+    pub fn start(&self, request: ScanRequest) -> Result<u64> {
+        self.start_with_cleanup(request, None)
+    }
+
+    /// like [`JobRegistry::start`], but removes `cleanup` after the job has finished
+    pub fn start_with_cleanup(
+        &self,
+        request: ScanRequest,
+        cleanup: Option<PathBuf>,
+    ) -> Result<u64> {
+        let request = request.or_scanners_of(&self.defaults.read().unwrap());
+        let scan_job = request.scan_job(&self.rulesets)?;
+        let job = Arc::new(Job::new(scan_job.handle(), request.string_options()));
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut jobs = self.jobs.lock().unwrap();
+        self.remove_finished_jobs(&mut jobs);
+        jobs.insert(id, Arc::clone(&job));
+        drop(jobs);
+
+        log::info!("starting scan job {} of '{}'", id, request.path);
+        thread::spawn(move || {
+            job.run(scan_job);
+            log::info!("scan job {} has finished", id);
+            if let Some(cleanup) = cleanup {
+                if let Err(why) = std::fs::remove_dir_all(&cleanup) {
+                    log::warn!("unable to remove '{}': {}", cleanup.display(), why);
+                }
+            }
+        });
+        Ok(id)
+    }
+
+    pub fn job(&self, id: u64) -> Option<Arc<Job>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.remove_finished_jobs(&mut jobs);
+        jobs.get(&id).cloned()
+    }
+
+    /// removes the jobs which have finished before the TTL, and the oldest finished
+    /// jobs if there are too many. Clients which still hold a job can use it
+    fn remove_finished_jobs(&self, jobs: &mut HashMap<u64, Arc<Job>>) {
+        let mut finished: Vec<(u64, Instant)> = jobs
+            .iter()
+            .filter_map(|(id, job)| job.status().finished_at.map(|at| (*id, at)))
+            .collect();
+        finished.sort_by_key(|(_, finished_at)| std::cmp::Reverse(*finished_at));
+        for (index, (id, finished_at)) in finished.into_iter().enumerate() {
+            if index >= self.max_finished_jobs || finished_at.elapsed() > self.finished_job_ttl {
+                log::debug!("removing finished scan job {}", id);
+                jobs.remove(&id);
+            }
+        }
+    }
+
+    pub fn cancel_all(&self) {
+        for job in self.jobs.lock().unwrap().values() {
+            job.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{JobRegistry, JobState, RulesetCache, ScanRequest};
+
+    fn request() -> ScanRequest {
+        ScanRequest {
+            path: "src".to_owned(),
+            filenames: vec![r"^lib\.rs$".to_owned()],
+            threads: 1,
+            ..Default::default()
+        }
+    }
+
+    fn run(jobs: &JobRegistry) -> u64 {
+        let id = jobs.start(request()).unwrap();
+        let job = jobs.job(id).unwrap();
+        assert_eq!(job.wait_for_results(usize::MAX).state(), JobState::Finished);
+        id
+    }
+
+    #[test]
+    fn test_max_finished_jobs() {
+        let jobs = JobRegistry::new(ScanRequest::default()).with_max_finished_jobs(2);
+        let ids: Vec<u64> = (0..3).map(|_| run(&jobs)).collect();
+        assert_eq!(jobs.job(ids[2]).unwrap().status().findings(), 1);
+
+        // only the two most recently finished jobs are kept
+        assert!(jobs.job(ids[0]).is_none());
+        assert!(jobs.job(ids[1]).is_some());
+        assert!(jobs.job(ids[2]).is_some());
+    }
+
+    #[test]
+    fn test_finished_job_ttl() {
+        let jobs = JobRegistry::new(ScanRequest::default())
+            .with_finished_job_ttl(Duration::from_millis(50));
+        let id = run(&jobs);
+        assert!(jobs.job(id).is_some());
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(jobs.job(id).is_none());
+    }
+
+    #[test]
+    fn test_ruleset_cache() {
+        let dir = std::env::temp_dir().join(format!("dionysos-rulesets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first.yar");
+        let second = dir.join("second.yar");
+        std::fs::write(&first, "rule first { condition: true }").unwrap();
+        std::fs::write(&second, "rule second { condition: false }").unwrap();
+
+        let rulesets = RulesetCache::default();
+        let rules = rulesets.rules(&first.to_string_lossy()).unwrap();
+        let cached = rulesets.rules(&first.to_string_lossy()).unwrap();
+        let other = rulesets.rules(&second.to_string_lossy()).unwrap();
+        let invalid = rulesets.rules(&dir.join("missing.yar").to_string_lossy());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(Arc::ptr_eq(&rules, &cached));
+        assert!(!Arc::ptr_eq(&rules, &other));
+        assert!(invalid.is_err());
+        assert_eq!(rulesets.rulesets.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt::Display;
+
+use clap::ArgEnum;
+
+/// the scanners which can be enabled or disabled with `--enable` and `--disable`
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ScannerKind {
+    Yara,
+    Filename,
+    Levenshtein,
+    Hash,
+
+    #[cfg(feature = "fuzzy_hash")]
+    FuzzyHash,
+
+    Tlsh,
+    Entropy,
+    ExtensionMismatch,
+    Masquerading,
+    Ransomware,
+    Permissions,
+    Authenticode,
+    PeAnomalies,
+    ElfAnomalies,
+    MachoAnomalies,
+
+    #[cfg(feature = "scan_reg")]
+    RegistryIoc,
+
+    Inventory,
+    Plugins,
+}
+
+impl ScannerKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Yara => "scans the content of files with a yara ruleset",
+            Self::Filename => "matches the basenames of files against regular expressions",
+            Self::Levenshtein => "finds filenames which are similar to well-known filenames",
+            Self::Hash => "matches the hashes of files against a list of hashes",
+            #[cfg(feature = "fuzzy_hash")]
+            Self::FuzzyHash => "finds files which are similar to known files (ssdeep)",
+            Self::Tlsh => "finds files which are similar to known files (TLSH)",
+            Self::Entropy => "finds packed or encrypted files by their entropy",
+            Self::ExtensionMismatch => "finds files whose content does not match their extension",
+            Self::Masquerading => "finds copies of system binaries outside of their directory",
+            Self::Ransomware => "finds directories with encrypted files and ransom notes",
+            Self::Permissions => "finds writable system files and root-owned temp files",
+            Self::Authenticode => "finds PE files with invalid or missing Authenticode signatures",
+            Self::PeAnomalies => "finds packed or manipulated PE files by their structure",
+            Self::ElfAnomalies => "finds infected or suspicious ELF files by their structure",
+            Self::MachoAnomalies => "finds ad-hoc signed or suspicious Mach-O files",
+            #[cfg(feature = "scan_reg")]
+            Self::RegistryIoc => "matches keys and values of registry hives against IoCs",
+            Self::Inventory => "writes the hashes, size and timestamps of every file to a CSV file",
+            Self::Plugins => "runs the scanners which are loaded from plugins",
+        }
+    }
+
+    /// the options which must be given for this scanner, if it cannot run without them
+    pub fn required_options(&self) -> Option<&'static str> {
+        match self {
+            Self::Yara => Some("--yara"),
+            Self::Filename => Some("--filename"),
+            Self::Levenshtein => None,
+            Self::Hash => Some("--file-hash, --imphash, --file-hash-list or --hash-dir"),
+            #[cfg(feature = "fuzzy_hash")]
+            Self::FuzzyHash => Some("--fuzzy-hash or --fuzzy-hash-list"),
+            Self::Tlsh => Some("--tlsh or --tlsh-list"),
+            Self::Entropy => None,
+            Self::ExtensionMismatch => None,
+            Self::Masquerading => None,
+            Self::Ransomware => None,
+            Self::Permissions => None,
+            Self::Authenticode => None,
+            Self::PeAnomalies => None,
+            Self::ElfAnomalies => None,
+            Self::MachoAnomalies => None,
+            #[cfg(feature = "scan_reg")]
+            Self::RegistryIoc => Some("--reg-ioc"),
+            Self::Inventory => Some("--inventory"),
+            Self::Plugins => Some("--plugin-dir"),
+        }
+    }
+}
+
+impl Display for ScannerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => write!(f, "{}", value.get_name()),
+            None => Ok(()),
+        }
+    }
+}
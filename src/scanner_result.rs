@@ -1,21 +1,83 @@
 use std::collections::HashSet;
 use std::fmt::Display;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use serde_json::Value;
 
 use crate::csv_line::CsvLine;
+use crate::hash_list::CryptoHash;
+use crate::path_string::path_to_string;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
 use std::str;
 
+/// a finding of a [`crate::FileScanner`]. The `Display` implementation is used
+/// by the default implementation of `format_readable`.
 pub trait ScannerFinding: Send + Sync + Display {
-    fn format_csv(&self) -> HashSet<CsvLine>;
-    fn to_json(&self) -> Value;
+    /// human readable representation of this finding, which is used by the `txt` output
+    fn format_readable(&self, _options: &StringOptions) -> String {
+        self.to_string()
+    }
+
+    /// lines which are written by the `csv` output. Duplicate lines are dropped
+    fn format_csv(&self, options: &StringOptions) -> HashSet<CsvLine>;
+
+    /// representation of this finding, which is used by the `json` output. By convention,
+    /// the keys are prefixed with a number, which determines their order, e.g.
+    /// `01_scanner` and `02_suspicious_file`
+    fn to_json(&self, options: &StringOptions) -> Value;
+
+    /// severity of this finding, which is used to decide about the exit code
+    fn severity(&self) -> Severity {
+        Severity::Medium
+    }
+
+    /// name of the scanner which has produced this finding, as it is written to
+    /// `01_scanner` by the `json` output
+    fn scanner(&self) -> String {
+        self.to_json(&StringOptions::default())["01_scanner"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned()
+    }
 
+    /// name of the file (or other location) which contains this finding
     fn found_in_file(&self) -> &str;
+
+    /// name of the rule which has produced this finding, e.g. of a yara rule, which is
+    /// used to look up the finding in the threat intelligence database
+    fn rule(&self) -> Option<&str> {
+        None
+    }
+
+    /// MITRE ATT&CK technique IDs of this finding, like `T1055` or `T1036.005`
+    fn attack_techniques(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
 
+/// all findings of all scanners for a single file
 pub struct ScannerResult {
+    /// the name of the file, which is escaped by [`path_to_string`] for the output
     filename: String,
-    findings: Vec<Box<dyn ScannerFinding>>
+
+    /// the path of the file, or `None` if the data does not reside in the filesystem
+    /// (e.g. a member of an archive, or data in memory)
+    path: Option<PathBuf>,
+
+    findings: Vec<Box<dyn ScannerFinding>>,
+
+    /// for every finding, if it has been found by a scanner which depends on the path of
+    /// the file (see [`crate::FileScanner::depends_on_path`])
+    path_dependent: Vec<bool>,
+
+    /// errors which occurred while the file was scanned
+    errors: Vec<String>,
+
+    /// reason why the file has not been scanned
+    skip_reason: Option<String>,
+
+    /// the hashes of the file, if they have been computed by the scan worker
+    hashes: Option<Vec<CryptoHash>>,
 }
 
 impl ScannerResult {
@@ -23,8 +85,22 @@ impl ScannerResult {
         &self.filename[..]
     }
 
+    /// the path of the scanned file, which can be opened (unlike [`ScannerResult::filename`],
+    /// which is escaped), or `None` if the data does not reside in the filesystem
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
     pub fn add_finding(&mut self, finding: Box<dyn ScannerFinding>) {
         self.findings.push(finding);
+        self.path_dependent.push(false);
+    }
+
+    /// adds a finding of a scanner which depends on the path of the file, so that it is not
+    /// copied to other files with the same content
+    pub(crate) fn add_path_dependent_finding(&mut self, finding: Box<dyn ScannerFinding>) {
+        self.findings.push(finding);
+        self.path_dependent.push(true);
     }
 
     pub fn has_findings(&self) -> bool {
@@ -34,13 +110,71 @@ impl ScannerResult {
     pub fn findings(&self) -> std::slice::Iter<'_, std::boxed::Box<dyn ScannerFinding>> {
         self.findings.iter()
     }
+
+    pub(crate) fn take_findings(&mut self) -> Vec<Box<dyn ScannerFinding>> {
+        self.path_dependent.clear();
+        std::mem::take(&mut self.findings)
+    }
+
+    /// takes the findings which only depend on the content of the file, and the findings
+    /// which depend on its path
+    pub(crate) fn take_findings_by_path(
+        &mut self,
+    ) -> (Vec<Box<dyn ScannerFinding>>, Vec<Box<dyn ScannerFinding>>) {
+        let path_dependent = std::mem::take(&mut self.path_dependent);
+        let (path_findings, content_findings): (Vec<_>, Vec<_>) = self
+            .take_findings()
+            .into_iter()
+            .zip(path_dependent)
+            .partition(|(_, path_dependent)| *path_dependent);
+        (
+            content_findings.into_iter().map(|(f, _)| f).collect(),
+            path_findings.into_iter().map(|(f, _)| f).collect(),
+        )
+    }
+
+    pub fn add_error(&mut self, error: String) {
+        self.errors.push(error);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        ! self.errors.is_empty()
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors[..]
+    }
+
+    /// marks the file as not scanned
+    pub fn skip(&mut self, reason: &str) {
+        self.skip_reason = Some(reason.to_owned());
+    }
+
+    pub fn skip_reason(&self) -> Option<&str> {
+        self.skip_reason.as_deref()
+    }
+
+    pub(crate) fn set_hashes(&mut self, hashes: Vec<CryptoHash>) {
+        self.hashes = Some(hashes);
+    }
+
+    /// the hashes of the file, which have been computed by the scan worker, so that
+    /// they need not be computed again for every hash set
+    pub(crate) fn hashes(&self) -> Option<&[CryptoHash]> {
+        self.hashes.as_deref()
+    }
 }
 
 impl From<&Path> for ScannerResult {
     fn from(path: &Path) -> Self {
         Self {
-            filename: path.to_string_lossy().to_string(),
-            findings: Vec::new()
+            filename: path_to_string(path),
+            path: Some(path.to_owned()),
+            findings: Vec::new(),
+            path_dependent: Vec::new(),
+            errors: Vec::new(),
+            skip_reason: None,
+            hashes: None,
         }
     }
 }
@@ -49,11 +183,46 @@ impl From<String> for ScannerResult {
     fn from(filename: String) -> Self {
         Self {
             filename,
-            findings: Vec::new()
+            path: None,
+            findings: Vec::new(),
+            path_dependent: Vec::new(),
+            errors: Vec::new(),
+            skip_reason: None,
+            hashes: None,
         }
     }
 }
 
 pub fn escape(value: &str) -> String {
     str::replace(value, "\"", "\\\"")
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::ScannerResult;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_escaped_path() {
+        let dir = std::env::temp_dir().join(format!("dionysos-result-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a\nb\\c.exe");
+        std::fs::write(&path, b"MZ").unwrap();
+
+        let result = ScannerResult::from(path.as_path());
+        let is_file = result.path().map_or(false, Path::is_file);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.filename().ends_with("a\\u{a}b\\\\c.exe"));
+        assert!(is_file);
+    }
+
+    #[test]
+    fn test_buffer_has_no_path() {
+        let result = ScannerResult::from("archive.zip:member.exe".to_owned());
+        assert_eq!(result.filename(), "archive.zip:member.exe");
+        assert!(result.path().is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! runs dionysos as a native Windows service, which is controlled by the service
+//! control manager (SCM) and writes its log messages to the event log
+
+use std::ffi::{OsStr, OsString};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::dionysos::{Dionysos, EXIT_FATAL};
+use crate::interrupt::Interrupt;
+
+/// name of the service and of the event log source
+pub(crate) const SERVICE_NAME: &str = "dionysos";
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// installs dionysos as a service, which starts automatically. The service runs with
+/// the command line of this process, where `service install` is replaced by `service run`
+pub(crate) fn install() -> Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )?;
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from("dionysos"),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: launch_arguments(std::env::args_os().skip(1))?,
+        dependencies: Vec::new(),
+        // LocalSystem, which is able to read all files
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+    service.set_description(env!("CARGO_PKG_DESCRIPTION"))?;
+
+    eventlog::register(SERVICE_NAME)
+        .map_err(|why| anyhow!("unable to register the event log source: {}", why))?;
+    log::info!("installed the service '{}'", SERVICE_NAME);
+    Ok(())
+}
+
+/// stops and removes the service
+pub(crate) fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+    let service = manager.open_service(
+        SERVICE_NAME,
+        ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+    )?;
+    if service.query_status()?.current_state != ServiceState::Stopped {
+        service.stop()?;
+    }
+    service.delete()?;
+
+    if let Err(why) = eventlog::deregister(SERVICE_NAME) {
+        log::warn!("unable to remove the event log source: {}", why);
+    }
+    log::info!("removed the service '{}'", SERVICE_NAME);
+    Ok(())
+}
+
+/// connects to the SCM, which runs the scan in [`service_main`]. This blocks until
+/// the service has stopped
+pub(crate) fn dispatch() -> Result<()> {
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+        .map_err(|why| anyhow!("unable to connect to the service control manager: {}", why))
+}
+
+/// the arguments of the service, which are the arguments of `service install`
+fn launch_arguments<I: IntoIterator<Item = OsString>>(args: I) -> Result<Vec<OsString>> {
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    let install = args
+        .windows(2)
+        .position(|w| w[0] == OsStr::new("service") && w[1] == OsStr::new("install"))
+        .ok_or_else(|| anyhow!("unable to find 'service install' in the command line"))?;
+    args[install + 1] = OsString::from("run");
+    Ok(args)
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(why) = run_service() {
+        log::error!("the service has failed: {}", why);
+    }
+}
+
+fn run_service() -> Result<()> {
+    let interrupt = Interrupt::new();
+    let handler = Arc::clone(&interrupt);
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                handler.interrupt();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+    set_status(
+        &status_handle,
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        ServiceExitCode::Win32(0),
+    )?;
+
+    // the command line of the service is parsed again, because the dispatcher does not
+    // pass any data to this function
+    let status = Dionysos::new().and_then(|dionysos| dionysos.with_interrupt(interrupt).run());
+    let exit_code = match status {
+        Ok(_) => ServiceExitCode::Win32(0),
+        Err(why) => {
+            log::error!("{:?}", why);
+            ServiceExitCode::ServiceSpecific(EXIT_FATAL as u32)
+        }
+    };
+    set_status(
+        &status_handle,
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+        exit_code,
+    )
+}
+
+fn set_status(
+    handle: &ServiceStatusHandle,
+    state: ServiceState,
+    controls_accepted: ServiceControlAccept,
+    exit_code: ServiceExitCode,
+) -> Result<()> {
+    handle.set_service_status(ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::default(),
+        process_id: None,
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::launch_arguments;
+
+    #[test]
+    fn test_launch_arguments() {
+        let args = ["--watch", "3600", "service", "install", "--output", "C:\\findings.txt"];
+        let expected = ["--watch", "3600", "service", "run", "--output", "C:\\findings.txt"];
+        assert_eq!(
+            launch_arguments(args.iter().map(OsString::from)).unwrap(),
+            expected.iter().map(OsString::from).collect::<Vec<_>>()
+        );
+        assert!(launch_arguments(args[..2].iter().map(OsString::from)).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt::Display;
+
+use clap::ArgEnum;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Default for Severity {
+    fn default() -> Self {
+        Self::Medium
+    }
+}
+
+impl Severity {
+    /// parses the severity given in the metadata of a rule, e.g. `severity = "high"`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "low" | "info" | "informational" => Some(Self::Low),
+            "medium" | "moderate" => Some(Self::Medium),
+            "high" => Some(Self::High),
+            "critical" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+
+    /// converts a score, as it is used by Loki and THOR rules, into a severity
+    pub fn from_score(score: i64) -> Self {
+        match score {
+            i64::MIN..=39 => Self::Low,
+            40..=59 => Self::Medium,
+            60..=99 => Self::High,
+            _ => Self::Critical,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Low => write!(f, "low"),
+            Self::Medium => write!(f, "medium"),
+            Self::High => write!(f, "high"),
+            Self::Critical => write!(f, "critical"),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use walkdir::DirEntry;
+
+use crate::compressed_files::ntfs_attributes;
+
+const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+
+/// the reparse tag of cloud files (e.g. of OneDrive), whose bits 12 to 15 are flags
+const IO_REPARSE_TAG_CLOUD: u32 = 0x9000_001a;
+const IO_REPARSE_TAG_CLOUD_MASK: u32 = 0xffff_0fff;
+const IO_REPARSE_TAG_HSM: u32 = 0xc000_0004;
+const IO_REPARSE_TAG_HSM2: u32 = 0x8000_0006;
+
+/// files which are found during the walk, but are not scanned, because reading them
+/// would block, would have side effects or would not return the content of a file
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum SpecialFile {
+    Socket,
+    Fifo,
+    Device,
+
+    /// a file whose content is not stored locally, like a OneDrive placeholder or the
+    /// stub of a file which has been moved to an archive. Reading it would download or
+    /// recall the file
+    Placeholder,
+}
+
+const SPECIAL_FILES: [SpecialFile; 4] = [
+    SpecialFile::Socket,
+    SpecialFile::Fifo,
+    SpecialFile::Device,
+    SpecialFile::Placeholder,
+];
+
+impl Display for SpecialFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpecialFile::Socket => write!(f, "socket"),
+            SpecialFile::Fifo => write!(f, "FIFO"),
+            SpecialFile::Device => write!(f, "device node"),
+            SpecialFile::Placeholder => write!(f, "cloud placeholder"),
+        }
+    }
+}
+
+impl SpecialFile {
+    /// the kind of special file of `entry`, or `None` if it is a regular file or
+    /// a directory or a symlink, which are handled by the walk itself
+    pub fn of(entry: &DirEntry) -> Option<Self> {
+        let file_type = entry.file_type();
+        if file_type.is_dir() || file_type.is_symlink() {
+            return None;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_socket() {
+                return Some(SpecialFile::Socket);
+            } else if file_type.is_fifo() {
+                return Some(SpecialFile::Fifo);
+            } else if file_type.is_block_device() || file_type.is_char_device() {
+                return Some(SpecialFile::Device);
+            }
+        }
+
+        // files of iCloud Drive and other file providers whose content has been evicted
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::macos::fs::MetadataExt;
+            const SF_DATALESS: u32 = 0x4000_0000;
+            if let Ok(metadata) = entry.metadata() {
+                if metadata.st_flags() & SF_DATALESS != 0 {
+                    return Some(SpecialFile::Placeholder);
+                }
+            }
+        }
+
+        // the reparse tag can only be read by opening the file, which would already
+        // recall a file with FILE_ATTRIBUTE_RECALL_ON_OPEN
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::MetadataExt;
+            if let Ok(metadata) = entry.metadata() {
+                if is_placeholder(metadata.file_attributes(), None) {
+                    return Some(SpecialFile::Placeholder);
+                }
+            }
+        }
+
+        // on Unix, the attributes are only known for NTFS volumes which are mounted with
+        // ntfs-3g. The size of the allocated blocks is no hint, because small files are
+        // stored in the MFT record on NTFS, and occupy no blocks either
+        match ntfs_attributes(entry) {
+            Some((attributes, reparse_tag)) if is_placeholder(attributes, reparse_tag) => {
+                Some(SpecialFile::Placeholder)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// checks if NTFS attributes or the reparse tag belong to a file whose content is not
+/// stored locally, i.e. to a cloud file or to a stub of a hierarchical storage manager
+fn is_placeholder(attributes: u32, reparse_tag: Option<u32>) -> bool {
+    let recall_attributes = FILE_ATTRIBUTE_OFFLINE
+        | FILE_ATTRIBUTE_RECALL_ON_OPEN
+        | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS;
+    attributes & recall_attributes != 0
+        || matches!(
+            reparse_tag,
+            Some(tag) if tag & IO_REPARSE_TAG_CLOUD_MASK == IO_REPARSE_TAG_CLOUD
+                || tag == IO_REPARSE_TAG_HSM
+                || tag == IO_REPARSE_TAG_HSM2
+        )
+}
+
+/// counts the special files which have been skipped
+#[derive(Default)]
+pub(crate) struct SpecialFiles {
+    counts: [AtomicUsize; SPECIAL_FILES.len()],
+}
+
+impl SpecialFiles {
+    pub fn record(&self, kind: SpecialFile) {
+        let index = SPECIAL_FILES.iter().position(|k| *k == kind).unwrap();
+        self.counts[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        let counts: Vec<String> = SPECIAL_FILES
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(kind, count)| (kind, count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .map(|(kind, count)| format!("{} {}(s)", count, kind))
+            .collect();
+        match counts.is_empty() {
+            true => Vec::new(),
+            false => vec![format!("skipped special files: {}", counts.join(", "))],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_placeholder, SpecialFile, SpecialFiles};
+
+    #[test]
+    fn test_summary() {
+        let special_files = SpecialFiles::default();
+        assert!(special_files.summary().is_empty());
+
+        special_files.record(SpecialFile::Fifo);
+        special_files.record(SpecialFile::Placeholder);
+        special_files.record(SpecialFile::Fifo);
+        assert_eq!(
+            special_files.summary(),
+            vec!["skipped special files: 2 FIFO(s), 1 cloud placeholder(s)".to_owned()]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fifo() {
+        let fifo = std::env::temp_dir().join(format!("dionysos-fifo-{}", std::process::id()));
+        let status = std::process::Command::new("mkfifo").arg(&fifo).status().unwrap();
+        assert!(status.success());
+
+        let entry = walkdir::WalkDir::new(&fifo).into_iter().next().unwrap().unwrap();
+        let kind = SpecialFile::of(&entry);
+        std::fs::remove_file(&fifo).unwrap();
+        assert_eq!(kind, Some(SpecialFile::Fifo));
+    }
+
+    #[test]
+    fn test_placeholder_attributes() {
+        // a OneDrive file which is available online only, and a pinned one
+        assert!(is_placeholder(0x0040_0400, Some(0x9000_601a)));
+        assert!(is_placeholder(0x0000_0400, Some(0x9000_001a)));
+        assert!(is_placeholder(0x1000, None));
+        assert!(is_placeholder(0x400, Some(0xc000_0004)));
+        assert!(!is_placeholder(0x20, None));
+        // a symbolic link and a WOF-compressed file
+        assert!(!is_placeholder(0x400, Some(0xa000_000c)));
+        assert!(!is_placeholder(0x400, Some(0x8000_0017)));
+    }
+
+    #[test]
+    fn test_sparse_file() {
+        // a sparse file occupies no blocks, but it is stored locally
+        let file = std::env::temp_dir().join(format!("dionysos-sparse-{}", std::process::id()));
+        std::fs::File::create(&file).unwrap().set_len(1 << 20).unwrap();
+
+        let entry = walkdir::WalkDir::new(&file).into_iter().next().unwrap().unwrap();
+        let kind = SpecialFile::of(&entry);
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(kind, None);
+    }
+}
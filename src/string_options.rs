@@ -0,0 +1,101 @@
+use clap::ArgEnum;
+
+#[derive(ArgEnum, Clone, Copy)]
+pub enum StringEncoding {
+    /// printable ASCII characters are displayed as they are, all other bytes as `\xx`
+    Escaped,
+
+    /// all bytes are displayed as hex values
+    Hex,
+}
+
+impl Default for StringEncoding {
+    fn default() -> Self {
+        Self::Escaped
+    }
+}
+
+/// configures how matched strings are displayed by an output
+#[derive(Clone, Default)]
+pub struct StringOptions {
+    print_strings: bool,
+    max_length: Option<usize>,
+    max_strings: Option<usize>,
+    encoding: StringEncoding,
+}
+
+impl StringOptions {
+    pub fn with_print_strings(mut self, print_strings: bool) -> Self {
+        self.print_strings = print_strings;
+        self
+    }
+
+    pub fn with_max_length(mut self, max_length: Option<usize>) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    pub fn with_max_strings(mut self, max_strings: Option<usize>) -> Self {
+        self.max_strings = max_strings;
+        self
+    }
+
+    pub fn with_encoding(mut self, encoding: StringEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn print_strings(&self) -> bool {
+        self.print_strings
+    }
+
+    pub fn max_strings(&self) -> Option<usize> {
+        self.max_strings
+    }
+
+    /// renders `data` with the configured encoding, truncated to the maximum length
+    pub fn render(&self, data: &[u8]) -> String {
+        let (data, truncated) = match self.max_length {
+            Some(max_length) if data.len() > max_length => (&data[..max_length], true),
+            _ => (data, false),
+        };
+
+        let mut result = match self.encoding {
+            StringEncoding::Escaped => escape_vec(data),
+            StringEncoding::Hex => hex::encode(data),
+        };
+
+        if truncated {
+            result.push_str("...");
+        }
+        result
+    }
+}
+
+pub fn escape_vec(v: &[u8]) -> String {
+    v.iter()
+    .map(|b| {let c = char::from(*b); if c.is_ascii_graphic() {
+        c.to_string() } else {
+            format!("\\{:02x}", b)
+        }
+    }).collect::<Vec<String>>().join("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StringEncoding, StringOptions};
+
+    #[test]
+    fn test_render_escaped() {
+        let options = StringOptions::default();
+        assert_eq!(options.render(b"ab\x00c"), "ab\\00c");
+    }
+
+    #[test]
+    fn test_render_hex_truncated() {
+        let options = StringOptions::default()
+            .with_encoding(StringEncoding::Hex)
+            .with_max_length(Some(2));
+        assert_eq!(options.render(b"abc"), "6162...");
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+
+/// directories of a unix filesystem, relative to its root, which must not be writable
+/// by everyone
+const UNIX_SYSTEM_DIRECTORIES: &[&str] =
+    &["bin", "boot", "etc", "lib", "lib32", "lib64", "sbin", "usr"];
+
+/// directories of a unix filesystem, relative to its root, where every user can
+/// create files
+const UNIX_TEMP_DIRECTORIES: &[&str] = &["tmp", "var/tmp", "dev/shm"];
+
+/// directories of a Windows filesystem (in lowercase), relative to its root, which
+/// contain programs
+const WINDOWS_SYSTEM_DIRECTORIES: &[&str] = &[
+    "program files",
+    "program files (x86)",
+    "windows/system32",
+    "windows/syswow64",
+];
+
+/// directories below the Windows system directories (in lowercase), in which users can
+/// create files by design, e.g. scheduled tasks, color profiles or print jobs
+const WINDOWS_WRITABLE_DIRECTORIES: &[&str] = &[
+    "windows/system32/tasks",
+    "windows/system32/spool/drivers/color",
+    "windows/system32/spool/printers",
+    "windows/system32/microsoft/crypto/rsa/machinekeys",
+    "windows/system32/com/dmp",
+    "windows/system32/tracing",
+    "windows/syswow64/tasks",
+    "windows/syswow64/com/dmp",
+    "windows/syswow64/tracing",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Location {
+    /// a directory with programs, libraries or the system configuration of unix
+    UnixSystem,
+
+    /// a directory with programs of Windows
+    WindowsSystem,
+
+    /// a directory with temporary files of all users
+    Temp,
+
+    Other,
+}
+
+/// checks if `path` is `directory` or one of its subdirectories, using `/` as separator
+fn is_in(directory: &str, path: &str) -> bool {
+    path == directory
+        || path
+            .strip_prefix(directory)
+            .map_or(false, |rest| rest.starts_with('/'))
+}
+
+/// converts a path relative to the root of a filesystem to use `/` as separator
+fn relative_path(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// classifies a path relative to the root of a filesystem, using `/` as separator
+fn classify(relative: &str) -> Location {
+    let lowercase = relative.to_lowercase();
+    if UNIX_SYSTEM_DIRECTORIES.iter().any(|d| is_in(d, relative)) {
+        Location::UnixSystem
+    } else if WINDOWS_SYSTEM_DIRECTORIES
+        .iter()
+        .any(|d| is_in(d, &lowercase))
+    {
+        Location::WindowsSystem
+    } else if UNIX_TEMP_DIRECTORIES.iter().any(|d| is_in(d, relative)) {
+        Location::Temp
+    } else {
+        Location::Other
+    }
+}
+
+/// checks if `directory` is the root of a unix or Windows filesystem. This also works
+/// if the scanned filesystem is mounted somewhere, so that `/home/user/lib` is not
+/// mistaken for a system directory
+fn looks_like_root(directory: &Path) -> bool {
+    (directory.join("etc").is_dir() && directory.join("usr").is_dir())
+        || directory.join("Windows").join("System32").is_dir()
+}
+
+/// checks if `directory` below the Windows installation at `root` is writable by users
+/// by design, like `System32\Tasks`
+pub(crate) fn is_writable_by_design(directory: &Path, root: &Path) -> bool {
+    let relative = match directory.strip_prefix(root) {
+        Ok(relative) => relative_path(relative).to_lowercase(),
+        Err(_) => return false,
+    };
+    WINDOWS_WRITABLE_DIRECTORIES
+        .iter()
+        .any(|d| is_in(d, &relative))
+}
+
+/// the location of `directory`, which is searched relative to all of its parents
+pub(crate) fn location_of(directory: &Path) -> Location {
+    locate(directory).0
+}
+
+/// the location of `directory`, and the root of the filesystem in which it has been
+/// found, unless the location is `Location::Other`
+pub(crate) fn locate(directory: &Path) -> (Location, Option<&Path>) {
+    for ancestor in directory.ancestors() {
+        let relative = match directory.strip_prefix(ancestor) {
+            Ok(relative) => relative_path(relative),
+            Err(_) => continue,
+        };
+        let location = classify(&relative);
+        if location != Location::Other && looks_like_root(ancestor) {
+            return (location, Some(ancestor));
+        }
+    }
+    (Location::Other, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{classify, is_writable_by_design, Location};
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("usr/local/bin"), Location::UnixSystem);
+        assert_eq!(classify("Program Files/Vendor"), Location::WindowsSystem);
+        assert_eq!(classify("var/tmp"), Location::Temp);
+        assert_eq!(classify("usrlocal"), Location::Other);
+        assert_eq!(classify("home/user/lib"), Location::Other);
+    }
+
+    #[test]
+    fn test_writable_system_directories() {
+        let root = Path::new("/mnt/c");
+        for directory in [
+            "Windows/System32/Tasks",
+            "Windows/System32/Tasks/Microsoft",
+            "WINDOWS/system32/spool/PRINTERS",
+            "Windows/System32/spool/drivers/color",
+            "Windows/System32/Microsoft/Crypto/RSA/MachineKeys",
+            "Windows/SysWOW64/com/dmp",
+            "Windows/System32/Tracing",
+        ] {
+            assert!(
+                is_writable_by_design(&root.join(directory), root),
+                "{}",
+                directory
+            );
+        }
+        for directory in [
+            "Windows/System32",
+            "Windows/System32/drivers",
+            "Windows/System32/spool/drivers/x64",
+            "Windows/System32/TasksHost",
+        ] {
+            assert!(
+                !is_writable_by_design(&root.join(directory), root),
+                "{}",
+                directory
+            );
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! integration into systemd: readiness and watchdog notifications, socket activation
+//! and reloading on SIGHUP. If dionysos is not started by systemd (or not on Unix),
+//! all functions do nothing.
+
+use std::net::TcpListener;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+/// tells systemd that dionysos has started (for services with `Type=notify`)
+pub fn notify_ready() {
+    #[cfg(unix)]
+    if let Err(why) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+        log::warn!("unable to notify systemd: {}", why);
+    }
+}
+
+/// tells systemd that dionysos is reloading its configuration. `notify_ready` must
+/// be called after the configuration has been reloaded
+pub fn notify_reloading() {
+    #[cfg(unix)]
+    if let Err(why) = sd_notify::notify(false, &[sd_notify::NotifyState::Reloading]) {
+        log::warn!("unable to notify systemd: {}", why);
+    }
+}
+
+/// sockets which are passed by systemd (socket activation), in the order of the
+/// `ListenStream=` entries of the socket unit
+pub fn listen_sockets() -> Result<Vec<TcpListener>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::FromRawFd;
+        let fds = sd_notify::listen_fds()?;
+        // SAFETY: systemd passes these file descriptors to the process, and they
+        // are not used elsewhere
+        Ok(fds
+            .map(|fd| unsafe { TcpListener::from_raw_fd(fd) })
+            .collect())
+    }
+    #[cfg(not(unix))]
+    Ok(Vec::new())
+}
+
+/// sets the returned flag when SIGHUP is received, which requests to reload the
+/// configuration and the rulesets
+pub fn reload_requested() -> Result<Arc<AtomicBool>> {
+    let flag = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&flag))?;
+    Ok(flag)
+}
+
+/// sends keep-alive notifications to the systemd watchdog (`WatchdogSec=`)
+pub struct Watchdog {
+    /// `None` if the watchdog is not enabled
+    interval: Option<Duration>,
+    last_ping: Mutex<Instant>,
+}
+
+impl Watchdog {
+    /// reads the timeout of the watchdog, which is passed by systemd
+    pub fn from_env() -> Self {
+        #[cfg(unix)]
+        let interval = {
+            let mut usec = 0;
+            // systemd recommends to ping at half of the timeout
+            sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec / 2))
+        };
+        #[cfg(not(unix))]
+        let interval = None;
+
+        Self {
+            interval,
+            last_ping: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// notifies the watchdog, if this has not been done within the last interval.
+    /// This should be called regularly from a thread whose progress shows that
+    /// dionysos is working
+    pub fn ping(&self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let mut last_ping = self.last_ping.lock().unwrap();
+        if last_ping.elapsed() >= interval {
+            #[cfg(unix)]
+            if let Err(why) = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]) {
+                log::warn!("unable to notify the systemd watchdog: {}", why);
+            }
+            *last_ping = Instant::now();
+        }
+    }
+}
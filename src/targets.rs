@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Path, MAIN_SEPARATOR};
+
+use anyhow::{anyhow, Result};
+use clap::ArgEnum;
+use glob::MatchOptions;
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+use crate::path_string::extended_length_path;
+
+/// what is done with the files of the triage targets (`--targets`)
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TargetMode {
+    /// scan the files
+    Scan,
+
+    /// copy the files to '--collect', without scanning them
+    Collect,
+
+    /// copy the files to '--collect', and scan them
+    CollectAndScan,
+}
+
+impl TargetMode {
+    pub fn collects(&self) -> bool {
+        matches!(self, TargetMode::Collect | TargetMode::CollectAndScan)
+    }
+
+    pub fn scans(&self) -> bool {
+        matches!(self, TargetMode::Scan | TargetMode::CollectAndScan)
+    }
+}
+
+/// a target definition, in the format of KAPE (`.tkape`)
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TargetDefinition {
+    targets: Vec<Target>,
+}
+
+/// a set of artifacts, e.g. the event logs or the history of a browser
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Target {
+    name: String,
+
+    /// the directory of the artifacts, which may contain `*` and `%user%`
+    path: String,
+
+    /// the filenames of the artifacts, `*` by default
+    file_mask: Option<String>,
+
+    /// also search the subdirectories of `path`
+    #[serde(default)]
+    recursive: bool,
+}
+
+/// the artifact paths (browser data, event logs, registry hives, ...) which are
+/// scanned or collected instead of walking the whole disk. They are read from
+/// target definitions in the format of KAPE. The paths of the targets are relative
+/// to the scanned path, so that `C:\Windows\...` is searched in `/mnt/evidence/Windows/...`
+/// if `/mnt/evidence` is scanned. The following is supported:
+///
+///  - `Path`, `FileMask` and `Recursive`
+///  - `*` and `**` in `Path` and `FileMask`, and `%user%` for every user profile
+///
+/// Targets which refer to other target definitions are not supported, and are skipped
+pub(crate) struct Targets {
+    targets: Vec<Target>,
+}
+
+impl Targets {
+    /// reads a target definition, or all target definitions (`*.tkape`) in a directory
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut targets = Vec::new();
+        if path.is_dir() {
+            let mut files = Vec::new();
+            for entry in std::fs::read_dir(path)? {
+                let file = entry?.path();
+                if file.extension().map_or(false, |e| e.eq_ignore_ascii_case("tkape")) {
+                    files.push(file);
+                }
+            }
+            files.sort();
+            for file in files {
+                targets.extend(Self::read_definition(&file)?);
+            }
+        } else {
+            targets.extend(Self::read_definition(path)?);
+        }
+        if targets.is_empty() {
+            return Err(anyhow!("found no targets in '{}'", path.display()));
+        }
+        Ok(Self { targets })
+    }
+
+    fn read_definition(file: &Path) -> Result<Vec<Target>> {
+        let reader = File::open(file)
+            .map_err(|why| anyhow!("unable to read '{}': {}", file.display(), why))?;
+        let definition: TargetDefinition = serde_yaml::from_reader(reader)
+            .map_err(|why| anyhow!("invalid target definition '{}': {}", file.display(), why))?;
+        Ok(definition
+            .targets
+            .into_iter()
+            .filter(|target| {
+                let compound = target.path.to_lowercase().ends_with(".tkape");
+                if compound {
+                    log::warn!(
+                        "skipping the target '{}', which refers to other targets",
+                        target.name
+                    );
+                }
+                !compound
+            })
+            .collect())
+    }
+
+    /// the glob patterns of all targets below `root`
+    fn patterns(&self, root: &Path) -> Vec<(&str, String)> {
+        let root = glob::Pattern::escape(&root.to_string_lossy());
+        self.targets
+            .iter()
+            .map(|target| {
+                let mut pattern = root.trim_end_matches(['/', '\\']).to_owned();
+                for component in target_components(&target.path) {
+                    pattern.push(MAIN_SEPARATOR);
+                    pattern.push_str(&case_insensitive(&component));
+                }
+                if target.recursive {
+                    pattern.push(MAIN_SEPARATOR);
+                    pattern.push_str("**");
+                }
+                let file_mask = target.file_mask.as_deref().unwrap_or("*");
+                pattern.push(MAIN_SEPARATOR);
+                pattern.push_str(&case_insensitive(file_mask));
+                (target.name.as_str(), pattern)
+            })
+            .collect()
+    }
+
+    /// all files of the targets below `root`. Every file is returned only once, even if
+    /// it belongs to more than one target. Directories which cannot be read are logged,
+    /// and are passed to `on_error`
+    pub fn files<'a>(
+        &'a self,
+        root: &Path,
+        on_error: impl Fn(&Path, std::io::Error) + 'a,
+    ) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> + 'a {
+        let options = MatchOptions {
+            // artifacts of Windows are searched in mounted images, too
+            case_sensitive: false,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        let mut found = HashSet::new();
+        self.patterns(root)
+            .into_iter()
+            .filter_map(move |(name, pattern)| match glob::glob_with(&pattern, options) {
+                Ok(paths) => {
+                    log::info!("searching the target '{}' in '{}'", name, pattern);
+                    Some(paths)
+                }
+                Err(why) => {
+                    log::warn!("invalid path of the target '{}': {}", name, why);
+                    None
+                }
+            })
+            .flatten()
+            .filter_map(move |path| match path {
+                Ok(path) => Some(path),
+                Err(why) => {
+                    let path = why.path().to_owned();
+                    on_error(&path, why.into_error());
+                    None
+                }
+            })
+            .filter(move |path| found.insert(path.clone()))
+            .filter_map(|path| {
+                WalkDir::new(extended_length_path(&path))
+                    .max_depth(0)
+                    .into_iter()
+                    .next()
+            })
+    }
+}
+
+/// the components of the path of a target, without its drive letter. `%user%` is
+/// replaced by a wildcard, which matches every user profile
+fn target_components(path: &str) -> Vec<String> {
+    let path = match path.as_bytes() {
+        [letter, b':', ..] if letter.is_ascii_alphabetic() => &path[2..],
+        _ => path,
+    };
+    path.split(['/', '\\'])
+        .filter(|c| !c.is_empty())
+        .map(|c| match c.eq_ignore_ascii_case("%user%") {
+            true => "*".to_owned(),
+            false => c.to_owned(),
+        })
+        .collect()
+}
+
+/// converts a component of a pattern, so that it is matched case-insensitively. glob
+/// looks up components without wildcards by their name, which is case-sensitive on
+/// most filesystems, so the first letter of such a component is turned into a
+/// character class, like `[W]indows`. Those are compared case-insensitively
+fn case_insensitive(component: &str) -> String {
+    if component.contains(['*', '?', '[']) {
+        return component.to_owned();
+    }
+    match component.find(|c: char| c.is_alphabetic()) {
+        Some(index) => {
+            let letter = component[index..].chars().next().unwrap();
+            let rest = &component[index + letter.len_utf8()..];
+            format!("{}[{}]{}", &component[..index], letter, rest)
+        }
+        None => component.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{case_insensitive, target_components, Target, Targets};
+
+    #[test]
+    fn test_patterns() {
+        assert_eq!(
+            target_components("C:\\Users\\%user%\\AppData\\Local\\Google\\Chrome\\User Data\\*\\"),
+            vec!["Users", "*", "AppData", "Local", "Google", "Chrome", "User Data", "*"]
+        );
+
+        let targets = Targets {
+            targets: vec![Target {
+                name: "Event logs".to_owned(),
+                path: "C:\\Windows\\System32\\winevt\\Logs\\".to_owned(),
+                file_mask: Some("*.evtx".to_owned()),
+                recursive: true,
+            }],
+        };
+        #[cfg(unix)]
+        assert_eq!(
+            targets.patterns(Path::new("/mnt/evidence/"))[0].1,
+            "/mnt/evidence/[W]indows/[S]ystem32/[w]inevt/[L]ogs/**/*.evtx"
+        );
+        #[cfg(windows)]
+        assert_eq!(
+            targets.patterns(Path::new("D:\\"))[0].1,
+            "D:\\[W]indows\\[S]ystem32\\[w]inevt\\[L]ogs\\**\\*.evtx"
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        assert_eq!(case_insensitive("Windows"), "[W]indows");
+        assert_eq!(case_insensitive("$MFT"), "$[M]FT");
+        assert_eq!(case_insensitive("*.evtx"), "*.evtx");
+        assert_eq!(case_insensitive("1234"), "1234");
+    }
+
+    #[test]
+    fn test_files() {
+        let root = std::env::temp_dir().join(format!("dionysos-targets-{}", std::process::id()));
+        let logs = root.join("windows/SYSTEM32/winevt/logs");
+        let profile = root.join("users/alice");
+        std::fs::create_dir_all(&logs).unwrap();
+        std::fs::create_dir_all(&profile).unwrap();
+        for file in [logs.join("Security.EVTX"), profile.join("ntuser.dat")] {
+            std::fs::write(file, b"").unwrap();
+        }
+
+        let target = |path: &str, file_mask: &str| Target {
+            name: path.to_owned(),
+            path: path.to_owned(),
+            file_mask: Some(file_mask.to_owned()),
+            recursive: false,
+        };
+        let targets = Targets {
+            targets: vec![
+                target("C:\\Windows\\System32\\winevt\\Logs", "*.evtx"),
+                target("C:\\Users\\%user%", "NTUSER.DAT"),
+                target("C:\\Users\\%user%", "NTUSER.DAT.LOG1"),
+            ],
+        };
+        let mut found: Vec<_> = targets
+            .files(&root, |_, _| ())
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        found.sort();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(found, vec!["Security.EVTX", "ntuser.dat"]);
+    }
+}
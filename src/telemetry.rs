@@ -0,0 +1,114 @@
+//! OpenTelemetry tracing of the scan pipeline. Without the `otel` feature, all
+//! functions are no-ops.
+
+#[cfg(feature = "otel")]
+use anyhow::Result;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::{Span, TraceContextExt, Tracer};
+#[cfg(feature = "otel")]
+use opentelemetry::{global, Context, KeyValue};
+
+/// the context of a span, which can be passed to other threads
+#[cfg(feature = "otel")]
+pub(crate) type TraceContext = Context;
+
+#[cfg(not(feature = "otel"))]
+#[derive(Clone, Default)]
+pub(crate) struct TraceContext;
+
+/// ends the span and restores the previous context of the thread when it is dropped
+pub(crate) struct SpanGuard {
+    #[cfg(feature = "otel")]
+    _guard: opentelemetry::ContextGuard,
+}
+
+/// exports all spans to an OTLP collector, until it is dropped
+#[cfg(feature = "otel")]
+pub(crate) struct Telemetry {
+    _runtime: tokio::runtime::Runtime,
+}
+
+#[cfg(feature = "otel")]
+impl Telemetry {
+    pub fn init(endpoint: &str) -> Result<Self> {
+        use opentelemetry::sdk::{trace, Resource};
+        use opentelemetry_otlp::WithExportConfig;
+
+        // the batch exporter sends the spans from a task of this runtime
+        let runtime = tokio::runtime::Runtime::new()?;
+        let guard = runtime.enter();
+        opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                KeyValue::new("service.name", "dionysos"),
+                KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+            ])))
+            .install_batch(opentelemetry::runtime::Tokio)?;
+        log::info!("exporting traces to '{}'", endpoint);
+        drop(guard);
+        Ok(Self { _runtime: runtime })
+    }
+}
+
+#[cfg(feature = "otel")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        // exports the remaining spans
+        global::shutdown_tracer_provider();
+    }
+}
+
+/// starts a span, which is a child of the current span of this thread. Spans which
+/// are started while the guard exists are children of this span. The attributes are
+/// only computed if the span is recorded, so that spans cost nothing without `otel`
+#[cfg(feature = "otel")]
+pub(crate) fn span<F>(name: &'static str, attributes: F) -> SpanGuard
+where
+    F: FnOnce() -> Vec<(&'static str, String)>,
+{
+    let mut span = global::tracer("dionysos").start(name);
+    for (key, value) in attributes() {
+        span.set_attribute(KeyValue::new(key, value));
+    }
+    SpanGuard {
+        _guard: Context::current_with_span(span).attach(),
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn span<F>(_name: &'static str, _attributes: F) -> SpanGuard
+where
+    F: FnOnce() -> Vec<(&'static str, String)>,
+{
+    SpanGuard {}
+}
+
+/// the context of the current span of this thread
+#[cfg(feature = "otel")]
+pub(crate) fn current() -> TraceContext {
+    Context::current()
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn current() -> TraceContext {
+    TraceContext
+}
+
+/// makes `context` the current context of this thread, so that spans of another
+/// thread can be children of its span
+#[cfg(feature = "otel")]
+pub(crate) fn attach(context: TraceContext) -> SpanGuard {
+    SpanGuard {
+        _guard: context.attach(),
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn attach(_context: TraceContext) -> SpanGuard {
+    SpanGuard {}
+}
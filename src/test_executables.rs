@@ -0,0 +1,67 @@
+use goblin::elf::program_header::{PF_R, PF_X, PT_LOAD};
+use goblin::elf::section_header::{SHF_ALLOC, SHF_EXECINSTR, SHT_PROGBITS};
+
+/// the virtual address of the entry point of [`infected_elf`]
+const ENTRY_POINT: u64 = 0xc0_0000;
+
+/// the file offset and the virtual address of the code section of [`infected_elf`]
+const CODE_OFFSET: usize = 0x200;
+const CODE_ADDRESS: u64 = 0x40_0200;
+
+fn segment(offset: usize, vaddr: u64, size: usize) -> Vec<u8> {
+    let (offset, size) = (offset as u64, size as u64);
+    let mut header = Vec::new();
+    header.extend_from_slice(&PT_LOAD.to_le_bytes());
+    header.extend_from_slice(&(PF_R | PF_X).to_le_bytes());
+    for value in [offset, vaddr, vaddr, size, size, 0x1000] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    header
+}
+
+fn section(sh_type: u32, flags: u32, offset: usize, size: usize) -> Vec<u8> {
+    let mut header = vec![0; 4];
+    header.extend_from_slice(&sh_type.to_le_bytes());
+    // sh_link and sh_info are written as a single zero
+    for value in [flags as u64, 0, offset as u64, size as u64, 0, 0, 0] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    header
+}
+
+/// an x86_64 executable whose code section `code` at 0x200 is loaded by the first
+/// `PT_LOAD` segment, and which has been infected by converting its `PT_NOTE` segment
+/// to a `PT_LOAD` segment of 128 bytes after the code, which contains the entry point,
+/// but no section. Without sections, the file has no section headers at all
+pub(crate) fn infected_elf(code: &[u8], with_sections: bool) -> Vec<u8> {
+    let injected_offset = CODE_OFFSET + code.len();
+    let (section_offset, section_count) = if with_sections {
+        (injected_offset + 0x80, 2)
+    } else {
+        (0, 0)
+    };
+    let mut data = b"\x7fELF\x02\x01\x01".to_vec();
+    data.resize(16, 0);
+    for value in [2u16, 62] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&1u32.to_le_bytes());
+    for value in [ENTRY_POINT, 64, section_offset as u64] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend_from_slice(&0u32.to_le_bytes());
+    for value in [64u16, 56, 2, 64, section_count, 0] {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data.extend(segment(CODE_OFFSET, CODE_ADDRESS, code.len()));
+    data.extend(segment(injected_offset, ENTRY_POINT, 0x80));
+    data.resize(CODE_OFFSET, 0);
+    data.extend_from_slice(code);
+    data.resize(injected_offset + 0x80, 0x90);
+    if with_sections {
+        data.extend(section(0, 0, 0, 0));
+        let flags = SHF_ALLOC | SHF_EXECINSTR;
+        data.extend(section(SHT_PROGBITS, flags, CODE_OFFSET, code.len()));
+    }
+    data
+}
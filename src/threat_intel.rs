@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::hash_list::{CryptoHash, HashAlgorithms, HashList};
+use crate::scanner_result::{ScannerFinding, ScannerResult};
+
+/// an entry of a threat intelligence database. `indicator` is either a hash (in the
+/// formats of `--file-hash`) or the name of a rule, e.g. of a yara rule
+#[derive(Deserialize)]
+struct Entry {
+    indicator: String,
+    family: Option<String>,
+    actor: Option<String>,
+    campaign: Option<String>,
+}
+
+/// what is known about an indicator
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub(crate) struct Attribution {
+    pub family: Option<String>,
+    pub actor: Option<String>,
+    pub campaign: Option<String>,
+}
+
+impl Attribution {
+    fn is_empty(&self) -> bool {
+        self.family.is_none() && self.actor.is_none() && self.campaign.is_none()
+    }
+
+    /// fills the values which are missing in `self` with the values of `other`
+    fn merge(&mut self, other: &Attribution) {
+        self.family = self.family.take().or_else(|| other.family.clone());
+        self.actor = self.actor.take().or_else(|| other.actor.clone());
+        self.campaign = self.campaign.take().or_else(|| other.campaign.clone());
+    }
+
+    /// a single line, like `family Emotet, actor TA542`
+    pub fn describe(&self) -> String {
+        [
+            ("family", &self.family),
+            ("actor", &self.actor),
+            ("campaign", &self.campaign),
+        ]
+        .iter()
+        .filter_map(|(key, value)| value.as_ref().map(|v| format!("{} {}", key, v)))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "family": self.family,
+            "actor": self.actor,
+            "campaign": self.campaign
+        })
+    }
+}
+
+/// a local threat intelligence database, which attributes findings to malware
+/// families, actors and campaigns (`--threat-intel`). Findings are annotated when
+/// they are written, so that the scan itself does not depend on the database, and
+/// no information leaves the host
+#[derive(Default)]
+pub(crate) struct ThreatIntel {
+    hashes: HashList,
+    by_hash: HashMap<String, Attribution>,
+    by_rule: HashMap<String, Attribution>,
+}
+
+impl ThreatIntel {
+    /// reads a database, which is either a CSV file with a header line and the columns
+    /// `indicator`, `family`, `actor` and `campaign` (all but `indicator` are optional),
+    /// or a JSON file with objects of the same keys (an array, or one object per line)
+    pub fn add_file(&mut self, path: &Path) -> Result<usize> {
+        let is_json = path.extension().map_or(false, |e| {
+            e.eq_ignore_ascii_case("json") || e.eq_ignore_ascii_case("jsonl")
+        });
+        let entries = if is_json {
+            Self::read_json(path)?
+        } else {
+            Self::read_csv(path)?
+        };
+
+        let count = entries.len();
+        let mut hashes = Vec::new();
+        for entry in entries {
+            let attribution = Attribution {
+                family: entry.family.filter(|v| !v.is_empty()),
+                actor: entry.actor.filter(|v| !v.is_empty()),
+                campaign: entry.campaign.filter(|v| !v.is_empty()),
+            };
+            if attribution.is_empty() {
+                continue;
+            }
+            let indicator = entry.indicator.trim();
+            match CryptoHash::parse(indicator) {
+                Ok(hash) => {
+                    self.by_hash
+                        .entry(hash.to_string())
+                        .or_default()
+                        .merge(&attribution);
+                    hashes.push(hash);
+                }
+                Err(_) => self
+                    .by_rule
+                    .entry(indicator.to_owned())
+                    .or_default()
+                    .merge(&attribution),
+            }
+        }
+        self.hashes.extend(hashes);
+        Ok(count)
+    }
+
+    fn read_csv(path: &Path) -> Result<Vec<Entry>> {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+        let mut entries = Vec::new();
+        for (index, entry) in reader.deserialize().enumerate() {
+            let entry =
+                entry.map_err(|why| anyhow!("{}:{}: {}", path.display(), index + 2, why))?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn read_json(path: &Path) -> Result<Vec<Entry>> {
+        let content = std::fs::read_to_string(path)?;
+        if content.trim_start().starts_with('[') {
+            return Ok(serde_json::from_str(&content)?);
+        }
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(
+                    serde_json::from_str(&line)
+                        .map_err(|why| anyhow!("{}:{}: {}", path.display(), index + 1, why))?,
+                );
+            }
+        }
+        Ok(entries)
+    }
+
+    /// the hashes of the file of `result` which are contained in the database. The file
+    /// is only hashed if the database contains any hashes, and if the scan worker has
+    /// not already hashed it
+    pub fn hashes_of(&self, result: &ScannerResult) -> Vec<CryptoHash> {
+        if self.by_hash.is_empty() {
+            return Vec::new();
+        }
+        match self.hashes.matching_hashes_of_result(result) {
+            Ok(hashes) => hashes,
+            Err(why) => {
+                log::debug!("unable to hash '{}': {}", result.filename(), why);
+                Vec::new()
+            }
+        }
+    }
+
+    /// the hash algorithms which are needed to look up files in the database
+    pub fn hash_algorithms(&self) -> HashAlgorithms {
+        self.hashes.algorithms()
+    }
+
+    /// what is known about `finding`, whose file has the `hashes` (see
+    /// [`ThreatIntel::hashes_of`]). The hashes take precedence over the rule
+    pub fn attribution(
+        &self,
+        finding: &dyn ScannerFinding,
+        hashes: &[CryptoHash],
+    ) -> Option<Attribution> {
+        let mut attribution = Attribution::default();
+        for hash in hashes {
+            if let Some(known) = self.by_hash.get(&hash.to_string()) {
+                attribution.merge(known);
+            }
+        }
+        if let Some(known) = finding.rule().and_then(|rule| self.by_rule.get(rule)) {
+            attribution.merge(known);
+        }
+        (!attribution.is_empty()).then_some(attribution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attribution, ThreatIntel};
+
+    #[test]
+    fn test_csv_database() {
+        let path = std::env::temp_dir().join(format!("dionysos-ti-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "indicator,family,actor,campaign\n\
+             44d88612fea8a8f36de82e1278abb02f,EICAR,,\n\
+             Webshell_Generic,,APT-X,\n\
+             Webshell_Generic,,,Operation Y\n",
+        )
+        .unwrap();
+        let mut threat_intel = ThreatIntel::default();
+        assert_eq!(threat_intel.add_file(&path).unwrap(), 3);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(threat_intel.by_hash.len(), 1);
+        assert_eq!(
+            threat_intel.by_rule.get("Webshell_Generic"),
+            Some(&Attribution {
+                family: None,
+                actor: Some("APT-X".to_owned()),
+                campaign: Some("Operation Y".to_owned()),
+            })
+        );
+        assert_eq!(
+            threat_intel.by_rule["Webshell_Generic"].describe(),
+            "actor APT-X, campaign Operation Y"
+        );
+    }
+}
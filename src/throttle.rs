@@ -0,0 +1,203 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveTime};
+
+use crate::interrupt::Interrupt;
+
+/// the interval in which a paused worker checks if it may continue
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// marks that no budget has been applied yet
+const NO_BUDGET: u8 = u8::MAX;
+
+/// a daily time window, which may wrap around midnight (e.g. `22:00-06:00`)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct DailyWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl DailyWindow {
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// the share of the time which the workers may spend scanning, depending on the local
+/// time of the day
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct ThrottleSchedule {
+    windows: Vec<(DailyWindow, u8)>,
+
+    /// the budget outside of all windows
+    default_budget: u8,
+}
+
+impl ThrottleSchedule {
+    /// parses a schedule like `22:00-06:00=100%,20%`: a comma separated list of time
+    /// windows with their budget, and an optional budget for all other times (100% by
+    /// default). The first window which contains a time applies. A budget of 0% pauses
+    /// the scan
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut schedule = Self {
+            windows: Vec::new(),
+            default_budget: 100,
+        };
+        let mut has_default = false;
+        for part in value.split(',').map(str::trim) {
+            match part.split_once('=') {
+                Some((window, budget)) => {
+                    let (start, end) = window.split_once('-').ok_or_else(|| {
+                        anyhow!("invalid time window '{}', expected HH:MM-HH:MM", window)
+                    })?;
+                    let window = DailyWindow {
+                        start: parse_time(start)?,
+                        end: parse_time(end)?,
+                    };
+                    schedule.windows.push((window, parse_budget(budget)?));
+                }
+                None if has_default => {
+                    return Err(anyhow!(
+                        "the schedule contains more than one default budget"
+                    ))
+                }
+                None => {
+                    schedule.default_budget = parse_budget(part)?;
+                    has_default = true;
+                }
+            }
+        }
+        Ok(schedule)
+    }
+
+    /// the budget (in percent) at `time`
+    fn budget_at(&self, time: NaiveTime) -> u8 {
+        self.windows
+            .iter()
+            .find(|(window, _)| window.contains(time))
+            .map_or(self.default_budget, |(_, budget)| *budget)
+    }
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| anyhow!("invalid time '{}', expected HH:MM", value))
+}
+
+fn parse_budget(value: &str) -> Result<u8> {
+    let value = value.trim();
+    match value.trim_end_matches('%').parse::<u8>() {
+        Ok(budget) if budget <= 100 => Ok(budget),
+        _ => Err(anyhow!(
+            "invalid budget '{}', expected a percentage between 0% and 100%",
+            value
+        )),
+    }
+}
+
+/// slows down the workers according to a [`ThrottleSchedule`] (`--throttle`), so that
+/// long scans of production systems use the I/O and CPU mostly outside of business
+/// hours. A budget of `p` percent means that a worker pauses after every file, so that
+/// it scans only `p` percent of the time; a budget of 0% pauses the workers until
+/// the next window with a budget begins
+pub(crate) struct Throttle {
+    schedule: ThrottleSchedule,
+
+    /// the budget which has been applied most recently, to log its changes
+    current_budget: AtomicU8,
+
+    /// the time which all workers together have paused, in milliseconds
+    paused: AtomicU64,
+}
+
+impl Throttle {
+    pub fn new(schedule: ThrottleSchedule) -> Self {
+        Self {
+            schedule,
+            current_budget: AtomicU8::new(NO_BUDGET),
+            paused: AtomicU64::new(0),
+        }
+    }
+
+    fn budget(&self) -> u8 {
+        let budget = self.schedule.budget_at(Local::now().time());
+        let previous = self.current_budget.swap(budget, Ordering::Relaxed);
+        if previous != budget {
+            match budget {
+                0 => log::info!("pausing the scan, according to the throttling schedule"),
+                100 => log::info!("scanning at full speed, according to the throttling schedule"),
+                _ => log::info!("throttling the scan to {}% of the time", budget),
+            }
+        }
+        budget
+    }
+
+    /// pauses the calling worker after it has been busy for `busy` with a file
+    pub fn pause(&self, busy: Duration, interrupt: &Interrupt) {
+        let started = Instant::now();
+        let budget = self.budget();
+        if budget < 100 {
+            let until = match budget {
+                0 => None,
+                budget => Some(started + busy * (100 - budget) as u32 / budget as u32),
+            };
+            // the schedule is checked again, so that a paused worker continues as soon
+            // as a window with a higher budget begins
+            while !interrupt.is_interrupted() && until.map_or(true, |until| Instant::now() < until)
+            {
+                let remaining = until.map_or(CHECK_INTERVAL, |until| {
+                    until.saturating_duration_since(Instant::now())
+                });
+                std::thread::sleep(remaining.min(CHECK_INTERVAL));
+                if self.budget() > budget {
+                    break;
+                }
+            }
+        }
+        self.paused
+            .fetch_add(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        match self.paused.load(Ordering::Relaxed) / 1000 {
+            0 => Vec::new(),
+            secs => vec![format!(
+                "the workers have paused for {}s because of the throttling schedule",
+                secs
+            )],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::ThrottleSchedule;
+
+    #[test]
+    fn test_schedule() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let schedule = ThrottleSchedule::parse("22:00-06:00=100%, 12:00-13:00=50, 20%").unwrap();
+        assert_eq!(schedule.budget_at(time(23, 30)), 100);
+        assert_eq!(schedule.budget_at(time(5, 59)), 100);
+        assert_eq!(schedule.budget_at(time(6, 0)), 20);
+        assert_eq!(schedule.budget_at(time(12, 30)), 50);
+        assert_eq!(schedule.budget_at(time(17, 0)), 20);
+
+        let schedule = ThrottleSchedule::parse("08:00-18:00=0%").unwrap();
+        assert_eq!(schedule.budget_at(time(9, 0)), 0);
+        assert_eq!(schedule.budget_at(time(19, 0)), 100);
+
+        assert!(ThrottleSchedule::parse("22:00=100").is_err());
+        assert!(ThrottleSchedule::parse("22:00-06:00=150%").is_err());
+        assert!(ThrottleSchedule::parse("20%,30%").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+/// parses a timestamp given on the command line, either in RFC 3339 format
+/// (`2022-10-01T12:00:00Z`) or as date (`2022-10-01`, which means midnight UTC)
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap())),
+        Err(_) => Err(anyhow!(
+            "invalid timestamp '{}', use either RFC 3339 format or YYYY-MM-DD",
+            value
+        )),
+    }
+}
+
+/// a time window, which may be open at any side
+#[derive(Clone, Copy, Default)]
+pub struct TimeWindow {
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+}
+
+impl TimeWindow {
+    pub fn new(after: Option<DateTime<Utc>>, before: Option<DateTime<Utc>>) -> Self {
+        Self { after, before }
+    }
+
+    pub fn contains(&self, timestamp: &DateTime<Utc>) -> bool {
+        self.after.map_or(true, |after| *timestamp >= after)
+            && self.before.map_or(true, |before| *timestamp <= before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_timestamp, TimeWindow};
+
+    #[test]
+    fn test_time_window() {
+        let window = TimeWindow::new(
+            Some(parse_timestamp("2022-10-01").unwrap()),
+            Some(parse_timestamp("2022-10-02T12:00:00+02:00").unwrap()),
+        );
+        assert!(window.contains(&parse_timestamp("2022-10-02T09:59:59Z").unwrap()));
+        assert!(!window.contains(&parse_timestamp("2022-10-02T10:00:01Z").unwrap()));
+        assert!(!window.contains(&parse_timestamp("2022-09-30").unwrap()));
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use maplit::hashset;
+use serde_json::json;
+use tlsh2::{TlshDefault, TlshDefaultBuilder};
+use walkdir::DirEntry;
+
+use crate::csv_line::CsvLine;
+use crate::filescanner::FileScanner;
+use crate::path_string::path_to_string;
+use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
+use crate::string_options::StringOptions;
+
+/// the default maximum distance of a finding
+pub(crate) const DEFAULT_DISTANCE: u32 = 50;
+
+/// findings with at most this distance are almost identical to the known file
+const LOW_DISTANCE: u32 = 30;
+
+/// the number of hex digits of a TLSH digest with 128 buckets and a 1 byte checksum,
+/// without the version prefix `T1`
+const DIGEST_LENGTH: usize = 70;
+
+/// normalizes a TLSH digest to uppercase with the version prefix `T1`. Older digests
+/// without the prefix are accepted as well
+pub(crate) fn parse_tlsh(value: &str) -> Result<String> {
+    let digest = value.trim().to_uppercase();
+    let hex = digest.strip_prefix("T1").unwrap_or(&digest);
+    if hex.len() != DIGEST_LENGTH || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            "invalid TLSH digest '{}', expected {} hex digits with an optional 'T1' prefix",
+            value,
+            DIGEST_LENGTH
+        ));
+    }
+    Ok(format!("T1{}", hex))
+}
+
+/// a TLSH digest of a known file
+struct KnownDigest {
+    digest: String,
+    tlsh: TlshDefault,
+
+    /// the name of the known file, or of the list which contained the digest
+    source: Option<String>,
+}
+
+impl KnownDigest {
+    fn new(digest: String, source: Option<String>) -> Result<Self> {
+        let tlsh = TlshDefault::from_hash(digest.as_bytes())
+            .ok_or_else(|| anyhow!("invalid TLSH digest '{}'", digest))?;
+        Ok(Self {
+            digest,
+            tlsh,
+            source,
+        })
+    }
+}
+
+/// reports files which are similar to known files, by the distance of their TLSH
+/// digests. TLSH works better than ssdeep for large binaries, but needs at least 50
+/// bytes with some variation; other files are not compared
+pub struct TlshScanner {
+    digests: Vec<KnownDigest>,
+
+    /// the maximum distance of a finding
+    distance: u32,
+}
+
+impl Default for TlshScanner {
+    fn default() -> Self {
+        Self {
+            digests: Vec::new(),
+            distance: DEFAULT_DISTANCE,
+        }
+    }
+}
+
+impl TlshScanner {
+    pub fn with_digests(mut self, digests: &[String]) -> Result<Self> {
+        for digest in digests.iter() {
+            self.digests
+                .push(KnownDigest::new(parse_tlsh(digest)?, None)?);
+        }
+        Ok(self)
+    }
+
+    /// reads digests from a file, see [`TlshScanner::parse_digest_file`]
+    pub fn with_digest_file<P: AsRef<Path>>(mut self, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|why| anyhow!("unable to read '{}': {}", path.display(), why))?;
+        let list_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+        for (digest, name) in Self::parse_digest_file(&content)
+            .map_err(|why| anyhow!("invalid TLSH list '{}': {}", path.display(), why))?
+        {
+            let source = name.or_else(|| list_name.clone());
+            self.digests.push(KnownDigest::new(digest, source)?);
+        }
+        Ok(self)
+    }
+
+    /// only report files whose distance is at most `distance`
+    pub fn with_distance(mut self, distance: u32) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    /// parses a list of TLSH digests, with one digest per line, which may be followed
+    /// by a comma and the name of the known file. Empty lines and lines starting with
+    /// '#' are ignored
+    pub(crate) fn parse_digest_file(content: &str) -> Result<Vec<(String, Option<String>)>> {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.split_once(',') {
+                Some((digest, name)) => {
+                    let name = name.trim().trim_matches('"');
+                    Ok((parse_tlsh(digest)?, Some(name.to_owned())))
+                }
+                None => Ok((parse_tlsh(line)?, None)),
+            })
+            .collect()
+    }
+
+    /// compares the TLSH digest of a scanned file with all known digests
+    fn findings(
+        &self,
+        name: String,
+        tlsh: Option<TlshDefault>,
+    ) -> Vec<Result<Box<dyn ScannerFinding>>> {
+        let tlsh = match tlsh {
+            Some(tlsh) => tlsh,
+            None => {
+                log::debug!("'{}' is too small or too uniform for TLSH", name);
+                return Vec::new();
+            }
+        };
+        let file_digest = String::from_utf8_lossy(&tlsh.hash()).to_string();
+        self.digests
+            .iter()
+            .filter_map(|known| {
+                let distance = tlsh.diff(&known.tlsh, true).max(0) as u32;
+                (distance <= self.distance).then(|| {
+                    Ok(Box::new(TlshFinding {
+                        found_in_file: name.clone(),
+                        digest: known.digest.clone(),
+                        file_digest: file_digest.clone(),
+                        distance,
+                        source: known.source.clone(),
+                    }) as Box<dyn ScannerFinding>)
+                })
+            })
+            .collect()
+    }
+}
+
+fn tlsh_of_file(path: &Path) -> Result<Option<TlshDefault>> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 1024 * 1024];
+    let mut builder = TlshDefaultBuilder::new();
+    loop {
+        let bytes = file.read(&mut buffer)?;
+        if bytes == 0 {
+            break;
+        }
+        builder.update(&buffer[..bytes]);
+    }
+    Ok(builder.build())
+}
+
+impl Display for TlshScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "TlshScanner")
+    }
+}
+
+impl FileScanner for TlshScanner {
+    fn scan_file(&self, entry: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match tlsh_of_file(entry.path()) {
+            Ok(tlsh) => self.findings(path_to_string(entry.path()), tlsh),
+            Err(why) => vec![Err(anyhow!(
+                "unable to compute the TLSH digest of '{}': {}",
+                entry.path().display(),
+                why
+            ))],
+        }
+    }
+
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let mut builder = TlshDefaultBuilder::new();
+        builder.update(data);
+        self.findings(name.to_owned(), builder.build())
+    }
+}
+
+struct TlshFinding {
+    found_in_file: String,
+
+    /// the known digest
+    digest: String,
+
+    /// the digest of the scanned file
+    file_digest: String,
+
+    /// the distance of both digests, where 0 means (almost) identical
+    distance: u32,
+
+    /// the name of the known file, or of the list which contained the digest
+    source: Option<String>,
+}
+
+impl TlshFinding {
+    fn details(&self) -> String {
+        match &self.source {
+            Some(source) => format!("distance {} to {}", self.distance, source),
+            None => format!("distance {}", self.distance),
+        }
+    }
+}
+
+impl Display for TlshFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found_in_file = self.found_in_file();
+        let digest = &self.digest;
+        let details = self.details();
+        writeln!(
+            f,
+            "file {found_in_file} is similar to the TLSH digest {digest} ({details})"
+        )
+    }
+}
+
+impl ScannerFinding for TlshFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "TLSH",
+            &self.digest,
+            self.found_in_file(),
+            self.details()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "tlsh",
+            "02_suspicious_file": self.found_in_file(),
+            "03_hash": self.digest,
+            "04_file_hash": self.file_digest,
+            "05_distance": self.distance,
+            "06_source": self.source
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        if self.distance <= LOW_DISTANCE {
+            Severity::High
+        } else {
+            Severity::Medium
+        }
+    }
+
+    fn scanner(&self) -> String {
+        "tlsh".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_tlsh, TlshScanner};
+
+    const DIGEST: &str = "T1A2B3C4D5E6F708192A3B4C5D6E7F8091A2B3C4D5E6F708192A3B4C5D6E7F8091A2B3C4";
+
+    #[test]
+    fn test_parse_digest_file() {
+        assert_eq!(parse_tlsh(&DIGEST.to_lowercase()).unwrap(), DIGEST);
+        assert_eq!(parse_tlsh(&DIGEST[2..]).unwrap(), DIGEST);
+        assert!(parse_tlsh(&DIGEST[..40]).is_err());
+        assert!(parse_tlsh("d41d8cd98f00b204e9800998ecf8427e").is_err());
+
+        let digests = TlshScanner::parse_digest_file(&format!(
+            "# known samples\n{},\"dropper.exe\"\n\n{}\n",
+            DIGEST,
+            &DIGEST[2..]
+        ))
+        .unwrap();
+        assert_eq!(digests.len(), 2);
+        assert_eq!(digests[0].1.as_deref(), Some("dropper.exe"));
+        assert_eq!(digests[1], (DIGEST.to_owned(), None));
+    }
+}
@@ -0,0 +1,322 @@
+use std::io::Stderr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+
+use crate::interrupt::EXIT_ABORTED;
+use crate::scanner_result::ScannerResult;
+use crate::string_options::StringOptions;
+
+/// interval in which the screen is redrawn
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Default)]
+struct WorkerSlot {
+    /// the file which is currently scanned, and when the scan started
+    file: Option<(String, Instant)>,
+
+    /// the user asked to skip the current file
+    skip: bool,
+}
+
+struct TuiFinding {
+    file: String,
+    summary: String,
+
+    /// the complete finding, including matched strings
+    details: String,
+}
+
+/// state of a scan, which is shared between the workers and the TUI
+pub struct TuiState {
+    started: Instant,
+    workers: Vec<Mutex<WorkerSlot>>,
+    scanned_files: AtomicUsize,
+    skipped_files: AtomicUsize,
+    findings: Mutex<Vec<TuiFinding>>,
+    paused: AtomicBool,
+    closed: AtomicBool,
+}
+
+impl TuiState {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            workers: (0..workers).map(|_| Mutex::new(WorkerSlot::default())).collect(),
+            scanned_files: AtomicUsize::new(0),
+            skipped_files: AtomicUsize::new(0),
+            findings: Mutex::new(Vec::new()),
+            paused: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn start_file(&self, worker: usize, file: String) {
+        let mut slot = self.workers[worker].lock().unwrap();
+        slot.file = Some((file, Instant::now()));
+        slot.skip = false;
+    }
+
+    /// returns `false` if the user asked to skip the file, so that
+    /// its results must be discarded
+    pub fn finish_file(&self, worker: usize) -> bool {
+        let mut slot = self.workers[worker].lock().unwrap();
+        slot.file = None;
+        self.scanned_files.fetch_add(1, Ordering::Relaxed);
+        if slot.skip {
+            self.skipped_files.fetch_add(1, Ordering::Relaxed);
+        }
+        !std::mem::take(&mut slot.skip)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn add_findings(&self, result: &ScannerResult, options: &StringOptions) {
+        let mut findings = self.findings.lock().unwrap();
+        for finding in result.findings() {
+            let details = finding.format_readable(options);
+            findings.push(TuiFinding {
+                file: result.filename().to_owned(),
+                summary: details.lines().next().unwrap_or_default().to_owned(),
+                details,
+            });
+        }
+    }
+
+    /// stops the TUI and restores the terminal
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    /// displays the TUI on stderr until [`TuiState::close`] is called
+    /// or the user leaves the TUI
+    pub fn spawn(self: &Arc<Self>) -> Result<thread::JoinHandle<()>> {
+        let mut stderr = std::io::stderr();
+        enable_raw_mode()?;
+        execute!(stderr, EnterAlternateScreen)?;
+        let terminal = Terminal::new(CrosstermBackend::new(stderr))?;
+
+        let state = Arc::clone(self);
+        Ok(thread::spawn(move || {
+            let mut tui = Tui {
+                state,
+                focus: Focus::Findings,
+                selected_worker: 0,
+                findings: ListState::default(),
+                show_details: false,
+            };
+            if let Err(why) = tui.run(terminal) {
+                log::error!("error in the TUI: {}", why);
+            }
+        }))
+    }
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Workers,
+    Findings,
+}
+
+struct Tui {
+    state: Arc<TuiState>,
+    focus: Focus,
+    selected_worker: usize,
+    findings: ListState,
+    show_details: bool,
+}
+
+impl Tui {
+    fn run(&mut self, mut terminal: Terminal<CrosstermBackend<Stderr>>) -> Result<()> {
+        let result = self.event_loop(&mut terminal);
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut Terminal<CrosstermBackend<Stderr>>) -> Result<()> {
+        while !self.state.closed.load(Ordering::Relaxed) {
+            terminal.draw(|f| self.draw(f))?;
+
+            if !event::poll(REFRESH_INTERVAL)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        disable_raw_mode()?;
+                        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+                        std::process::exit(EXIT_ABORTED);
+                    }
+                    KeyCode::Char('q') => break,
+                    KeyCode::Char('p') => {
+                        self.state.paused.fetch_xor(true, Ordering::Relaxed);
+                    }
+                    KeyCode::Char('s') => {
+                        let mut slot = self.state.workers[self.selected_worker].lock().unwrap();
+                        if slot.file.is_some() {
+                            slot.skip = true;
+                        }
+                    }
+                    KeyCode::Tab => {
+                        self.focus = match self.focus {
+                            Focus::Workers => Focus::Findings,
+                            Focus::Findings => Focus::Workers,
+                        }
+                    }
+                    KeyCode::Enter => self.show_details = !self.show_details,
+                    KeyCode::Up => self.move_selection(-1),
+                    KeyCode::Down => self.move_selection(1),
+                    _ => (),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let (count, selected) = match self.focus {
+            Focus::Workers => (self.state.workers.len(), self.selected_worker),
+            Focus::Findings => (
+                self.state.findings.lock().unwrap().len(),
+                self.findings.selected().unwrap_or(0),
+            ),
+        };
+        if count == 0 {
+            return;
+        }
+        let selected = (selected as isize + delta).clamp(0, count as isize - 1) as usize;
+        match self.focus {
+            Focus::Workers => self.selected_worker = selected,
+            Focus::Findings => self.findings.select(Some(selected)),
+        }
+    }
+
+    fn draw(&mut self, f: &mut Frame<CrosstermBackend<Stderr>>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(self.state.workers.len() as u16 + 2),
+                Constraint::Min(5),
+                Constraint::Length(1),
+            ])
+            .split(f.size());
+
+        self.draw_header(f, rows[0]);
+        self.draw_workers(f, rows[1]);
+        if self.show_details {
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .split(rows[2]);
+            self.draw_findings(f, columns[0]);
+            self.draw_details(f, columns[1]);
+        } else {
+            self.draw_findings(f, rows[2]);
+        }
+
+        let help = "q: leave TUI  Ctrl-C: abort  p: pause/resume  s: skip file of selected worker  \
+                    Tab: switch focus  Enter: details";
+        f.render_widget(Paragraph::new(help), rows[3]);
+    }
+
+    fn draw_header(&self, f: &mut Frame<CrosstermBackend<Stderr>>, area: Rect) {
+        let elapsed = self.state.started.elapsed().as_secs_f64();
+        let scanned = self.state.scanned_files.load(Ordering::Relaxed);
+        let text = format!(
+            "{}  files: {}  skipped: {}  findings: {}  throughput: {:.1} files/s  elapsed: {:.0}s",
+            if self.state.is_paused() { "PAUSED" } else { "RUNNING" },
+            scanned,
+            self.state.skipped_files.load(Ordering::Relaxed),
+            self.state.findings.lock().unwrap().len(),
+            scanned as f64 / elapsed.max(1.0),
+            elapsed
+        );
+        let block = Block::default().borders(Borders::ALL).title("dionysos");
+        f.render_widget(Paragraph::new(text).block(block), area);
+    }
+
+    fn draw_workers(&self, f: &mut Frame<CrosstermBackend<Stderr>>, area: Rect) {
+        let items: Vec<ListItem> = self
+            .state
+            .workers
+            .iter()
+            .enumerate()
+            .map(|(id, slot)| {
+                let slot = slot.lock().unwrap();
+                let text = match &slot.file {
+                    None => format!("#{:<3} idle", id),
+                    Some((file, started)) => format!(
+                        "#{:<3} {:>6.1}s {}{}",
+                        id,
+                        started.elapsed().as_secs_f64(),
+                        file,
+                        if slot.skip { " (skipped)" } else { "" }
+                    ),
+                };
+                ListItem::new(text)
+            })
+            .collect();
+
+        let mut state = ListState::default();
+        state.select(Some(self.selected_worker));
+        let list = List::new(items)
+            .block(self.block("workers", Focus::Workers))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn draw_findings(&mut self, f: &mut Frame<CrosstermBackend<Stderr>>, area: Rect) {
+        let findings = self.state.findings.lock().unwrap();
+        let items: Vec<ListItem> = findings
+            .iter()
+            .map(|finding| ListItem::new(finding.summary.clone()))
+            .collect();
+        let list = List::new(items)
+            .block(self.block("findings", Focus::Findings))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, area, &mut self.findings);
+    }
+
+    fn draw_details(&self, f: &mut Frame<CrosstermBackend<Stderr>>, area: Rect) {
+        let findings = self.state.findings.lock().unwrap();
+        let text = match self.findings.selected().and_then(|idx| findings.get(idx)) {
+            Some(finding) => format!("{}\n\n{}", finding.file, finding.details),
+            None => "no finding selected".to_owned(),
+        };
+        let block = Block::default().borders(Borders::ALL).title("details");
+        f.render_widget(
+            Paragraph::new(text).block(block).wrap(Wrap { trim: false }),
+            area,
+        );
+    }
+
+    fn block(&self, title: &'static str, focus: Focus) -> Block<'static> {
+        let block = Block::default().borders(Borders::ALL).title(title);
+        if self.focus == focus {
+            block.border_style(Style::default().add_modifier(Modifier::BOLD))
+        } else {
+            block
+        }
+    }
+}
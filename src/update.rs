@@ -0,0 +1,261 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use minisign_verify::{PublicKey, Signature};
+use serde_json::Value;
+
+use crate::hash_list::HashList;
+use crate::threat_intel::ThreatIntel;
+use crate::yara::{YaraRuleset, YaraScanner};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/janstarke/dionysos/releases/latest";
+
+/// a file (e.g. a yara ruleset or a hash list) which is refreshed from a URL
+/// by `dionysos update`
+#[derive(Clone)]
+pub struct Feed {
+    file: PathBuf,
+    url: String,
+}
+
+impl Feed {
+    /// parses a feed in the form `FILE=URL`
+    pub fn parse(feed: &str) -> Result<Self> {
+        match feed.split_once('=') {
+            Some((file, url)) if !file.is_empty() && url.contains("://") => Ok(Self {
+                file: PathBuf::from(file),
+                url: url.to_owned(),
+            }),
+            _ => Err(anyhow!("invalid feed '{}', expected FILE=URL", feed)),
+        }
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// downloads the feed and replaces the file, if the download succeeded and
+    /// contains what dionysos expects (see [`validate`]). Rulesets in zip files are
+    /// decrypted with `zip_password`. The partial download is removed on errors
+    pub fn update(&self, zip_password: Option<&str>) -> Result<u64> {
+        let download = self.download_path();
+        let result = self.replace(&download, zip_password);
+        if result.is_err() {
+            let _ = std::fs::remove_file(&download);
+        }
+        result
+    }
+
+    /// the file which the feed is downloaded to. It keeps the extension of the feed,
+    /// because [`validate`] depends on it
+    fn download_path(&self) -> PathBuf {
+        match self.file.extension() {
+            Some(extension) => {
+                let mut download = OsString::from("download.");
+                download.push(extension);
+                self.file.with_extension(download)
+            }
+            None => self.file.with_extension("download"),
+        }
+    }
+
+    fn replace(&self, download: &Path, zip_password: Option<&str>) -> Result<u64> {
+        let bytes = io::copy(&mut get(&self.url)?, &mut File::create(download)?)?;
+        if bytes == 0 {
+            return Err(anyhow!("'{}' returned no data", self.url));
+        }
+        validate(download, zip_password)?;
+        std::fs::rename(download, &self.file)?;
+        Ok(bytes)
+    }
+}
+
+/// checks a downloaded feed: yara rulesets (also in zip files) must compile, and
+/// all other files must contain IoCs or hashes
+fn validate(path: &Path, zip_password: Option<&str>) -> Result<()> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if matches!(extension.as_str(), "yar" | "yara" | "zip") {
+        let ruleset = YaraRuleset::new(path).with_zip_password(zip_password.map(str::to_owned));
+        if ruleset.read_sources()?.is_empty() {
+            return Err(anyhow!("the downloaded ruleset contains no yara rules"));
+        }
+        return YaraScanner::compile(&ruleset).map(|_| ());
+    }
+
+    let has_iocs = ThreatIntel::default()
+        .add_file(path)
+        .map_or(false, |count| count > 0);
+    if has_iocs || HashList::default().add_file(path)? > 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("found no yara rules, IoCs or hashes"))
+    }
+}
+
+fn get(url: &str) -> Result<impl Read> {
+    let response = ureq::get(url)
+        .set("User-Agent", concat!("dionysos/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .map_err(|why| anyhow!("unable to download '{}': {}", url, why))?;
+    Ok(response.into_reader())
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    get(url)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// name of the release asset which contains the binary for this platform,
+/// e.g. `dionysos-x86_64-linux`
+fn asset_name() -> String {
+    format!(
+        "dionysos-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+fn asset_url(release: &Value, name: &str) -> Result<String> {
+    release["assets"]
+        .as_array()
+        .and_then(|assets| assets.iter().find(|a| a["name"] == name))
+        .and_then(|asset| asset["browser_download_url"].as_str())
+        .map(|url| url.to_owned())
+        .ok_or_else(|| anyhow!("the latest release contains no asset '{}'", name))
+}
+
+/// replaces the running binary by the latest release, if it is newer. The binary must
+/// be signed with minisign, and the signature (`<asset>.minisig`) is verified with
+/// `public_key` before the binary is replaced.
+///
+/// Returns the version of the new binary, or `None` if dionysos is up to date. Older
+/// releases are never installed, so that a feed cannot downgrade dionysos.
+pub fn update_binary(public_key: &str) -> Result<Option<String>> {
+    let public_key = PublicKey::from_base64(public_key)
+        .map_err(|why| anyhow!("invalid public key: {}", why))?;
+
+    let mut release = String::new();
+    get(RELEASES_URL)?.read_to_string(&mut release)?;
+    let release: Value = serde_json::from_str(&release)?;
+    let version = release["tag_name"]
+        .as_str()
+        .ok_or_else(|| anyhow!("unable to determine the latest version of dionysos"))?
+        .trim_start_matches('v')
+        .to_owned();
+    if !is_newer(&version, env!("CARGO_PKG_VERSION"))? {
+        return Ok(None);
+    }
+
+    let asset = asset_name();
+    let binary = download(&asset_url(&release, &asset)?)?;
+    let signature = download(&asset_url(&release, &format!("{}.minisig", asset))?)?;
+    let signature = Signature::decode(&String::from_utf8_lossy(&signature))
+        .map_err(|why| anyhow!("invalid signature of '{}': {}", asset, why))?;
+    public_key
+        .verify(&binary, &signature, false)
+        .map_err(|why| anyhow!("the signature of '{}' is not valid: {}", asset, why))?;
+
+    replace_current_exe(&binary)?;
+    Ok(Some(version))
+}
+
+/// checks if `version` is newer than `current`, by comparing them as semantic versions
+fn is_newer(version: &str, current: &str) -> Result<bool> {
+    let parse = |version: &str| {
+        semver::Version::parse(version)
+            .map_err(|why| anyhow!("invalid version '{}': {}", version, why))
+    };
+    Ok(parse(version)? > parse(current)?)
+}
+
+/// the running binary is renamed before it is replaced, because Windows does
+/// not allow to overwrite it
+fn replace_current_exe(binary: &[u8]) -> Result<()> {
+    let current_exe = std::env::current_exe()?;
+    let with_suffix = |suffix: &str| {
+        let mut path: OsString = current_exe.clone().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+    let new_exe = with_suffix(".new");
+    let old_exe = with_suffix(".old");
+
+    std::fs::write(&new_exe, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new_exe, std::fs::Permissions::from_mode(0o755))?;
+    }
+    std::fs::rename(&current_exe, &old_exe)?;
+    if let Err(why) = std::fs::rename(&new_exe, &current_exe) {
+        std::fs::rename(&old_exe, &current_exe)?;
+        return Err(why.into());
+    }
+    let _ = std::fs::remove_file(&old_exe);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{is_newer, validate, Feed};
+
+    #[test]
+    fn test_parse_feed() {
+        let feed = Feed::parse("rules.zip=https://example.com/rules.zip?key=value").unwrap();
+        assert_eq!(feed.file().to_str(), Some("rules.zip"));
+        assert_eq!(feed.url(), "https://example.com/rules.zip?key=value");
+
+        assert!(Feed::parse("rules.zip").is_err());
+        assert!(Feed::parse("=https://example.com/rules.zip").is_err());
+        assert!(Feed::parse("rules.zip=example.com/rules.zip").is_err());
+    }
+
+    #[test]
+    fn test_download_path() {
+        let feed = Feed::parse("rules/index.yar=https://example.com/index.yar").unwrap();
+        assert_eq!(feed.download_path(), Path::new("rules/index.download.yar"));
+        let feed = Feed::parse("hashes=https://example.com/hashes").unwrap();
+        assert_eq!(feed.download_path(), Path::new("hashes.download"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(Path::new("test/yara/sample1.yar"), None).is_ok());
+        assert!(validate(Path::new("test/data/sample1.txt"), None).is_err());
+
+        let dir = std::env::temp_dir().join(format!("dionysos-update-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let broken_rules = dir.join("broken.yar");
+        std::fs::write(&broken_rules, "rule broken { condition: }").unwrap();
+        assert!(validate(&broken_rules, None).is_err());
+
+        let hashes = dir.join("hashes.txt");
+        std::fs::write(&hashes, format!("# comment\n{}\n", "a".repeat(32))).unwrap();
+        assert!(validate(&hashes, None).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("1.10.0", "1.9.3").unwrap());
+        assert!(is_newer("2.0.0", "2.0.0-rc.1").unwrap());
+        assert!(!is_newer("1.9.3", "1.9.3").unwrap());
+        assert!(!is_newer("1.9.2", "1.10.0").unwrap());
+        assert!(!is_newer("2.0.0-rc.1", "2.0.0").unwrap());
+        assert!(is_newer("latest", "1.9.3").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::path_string::path_to_string;
+
+/// interval in which the watchdog checks the workers
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// the file which is currently scanned by a worker
+struct CurrentScan {
+    file: PathBuf,
+    scanner: String,
+    started: Instant,
+    reported: bool,
+}
+
+/// what a worker is doing, which is checked by the [`WorkerWatchdog`]
+#[derive(Default)]
+pub(crate) struct WorkerActivity {
+    current: Mutex<Option<CurrentScan>>,
+
+    /// the scan of the current file can be skipped by the watchdog
+    skippable: bool,
+    skip: AtomicBool,
+}
+
+impl WorkerActivity {
+    pub fn start_file(&self, file: &Path) {
+        self.skip.store(false, Ordering::Relaxed);
+        *self.current.lock().unwrap() = Some(CurrentScan {
+            file: file.to_owned(),
+            scanner: String::new(),
+            started: Instant::now(),
+            reported: false,
+        });
+    }
+
+    /// `scanner` starts to scan `file`. This is ignored if the worker has moved on to
+    /// another file in the meantime, which happens if the scan of `file` has been
+    /// abandoned (see [`crate::file_timeout::FileHandler`])
+    pub fn start_scanner(&self, file: &Path, scanner: &dyn Display) {
+        if let Some(current) = self.current.lock().unwrap().as_mut() {
+            if current.file == file {
+                current.scanner = scanner.to_string();
+            }
+        }
+    }
+
+    pub fn finish_file(&self) {
+        *self.current.lock().unwrap() = None;
+    }
+
+    pub fn is_skippable(&self) -> bool {
+        self.skippable
+    }
+
+    /// the watchdog asks the worker to skip the current file
+    pub fn skip_requested(&self) -> bool {
+        self.skip.load(Ordering::Relaxed)
+    }
+}
+
+/// detects workers which are stuck on a single file, so that a scan which does not
+/// make progress anymore does not go unnoticed. Stuck files are logged with the
+/// scanner which is running, and are skipped if requested
+pub(crate) struct WorkerWatchdog {
+    activities: Vec<Arc<WorkerActivity>>,
+    threshold: Duration,
+    skip: bool,
+    stuck_files: Mutex<Vec<String>>,
+    finished: AtomicBool,
+}
+
+impl WorkerWatchdog {
+    pub fn new(threshold: Duration, skip: bool) -> Self {
+        Self {
+            activities: Vec::new(),
+            threshold,
+            skip,
+            stuck_files: Mutex::new(Vec::new()),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    /// returns the activity of a new worker, which must be updated by the worker
+    pub fn add_worker(&mut self) -> Arc<WorkerActivity> {
+        let activity = Arc::new(WorkerActivity {
+            skippable: self.skip,
+            ..Default::default()
+        });
+        self.activities.push(Arc::clone(&activity));
+        activity
+    }
+
+    /// checks the workers in a separate thread, until [`WorkerWatchdog::finish`] is called
+    pub fn start(self) -> (Arc<Self>, JoinHandle<()>) {
+        let watchdog = Arc::new(self);
+        let thread_watchdog = Arc::clone(&watchdog);
+        let thread = thread::spawn(move || {
+            while !thread_watchdog.finished.load(Ordering::Relaxed) {
+                thread_watchdog.check();
+                let next = Instant::now() + CHECK_INTERVAL;
+                while !thread_watchdog.finished.load(Ordering::Relaxed) && Instant::now() < next {
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        });
+        (watchdog, thread)
+    }
+
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    fn check(&self) {
+        for (id, activity) in self.activities.iter().enumerate() {
+            let mut guard = activity.current.lock().unwrap();
+            let current = match guard.as_mut() {
+                Some(current) if !current.reported && current.started.elapsed() >= self.threshold => {
+                    current
+                }
+                _ => continue,
+            };
+            current.reported = true;
+            let file = path_to_string(&current.file);
+            log::warn!(
+                "worker #{} has been scanning '{}' with {} for {}s{}",
+                id,
+                file,
+                current.scanner,
+                current.started.elapsed().as_secs(),
+                if self.skip { ", skipping it" } else { "" }
+            );
+            if self.skip {
+                activity.skip.store(true, Ordering::Relaxed);
+            }
+            self.stuck_files
+                .lock()
+                .unwrap()
+                .push(format!("{} ({})", file, current.scanner));
+        }
+    }
+
+    /// lines which are displayed in the summary at the end of a scan
+    pub fn summary(&self) -> Vec<String> {
+        let stuck_files = self.stuck_files.lock().unwrap();
+        if stuck_files.is_empty() {
+            return Vec::new();
+        }
+        let mut lines = vec![format!(
+            "{} file(s) have been scanned for more than {}s{}:",
+            stuck_files.len(),
+            self.threshold.as_secs(),
+            if self.skip { " and have been skipped" } else { "" }
+        )];
+        lines.extend(stuck_files.iter().map(|f| format!("  {}", f)));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use super::WorkerWatchdog;
+
+    #[test]
+    fn test_stuck_worker() {
+        let mut watchdog = WorkerWatchdog::new(Duration::from_millis(0), true);
+        let idle = watchdog.add_worker();
+        let stuck = watchdog.add_worker();
+        stuck.start_file(Path::new("/tmp/huge.iso"));
+        stuck.start_scanner(Path::new("/tmp/huge.iso"), &"YaraScanner");
+        stuck.start_scanner(Path::new("/tmp/other.exe"), &"HashScanner");
+
+        watchdog.check();
+        assert!(stuck.skip_requested());
+        assert!(!idle.skip_requested());
+        assert_eq!(watchdog.summary()[1], "  /tmp/huge.iso (YaraScanner)");
+
+        // every file is reported only once
+        watchdog.check();
+        assert_eq!(watchdog.summary().len(), 2);
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use anyhow::Result;
+use libesedb::EseDb;
+use serde_json::{Map, Value};
+
+/// reads all records of all tables of an ESE database (like `WebCacheV01.dat`,
+/// `SRUDB.dat` or `Windows.edb`). Every record is serialized as JSON object,
+/// which maps the column names to their values, and passed to `handler`
+/// together with the name of the table and the index of the record.
+///
+/// Records which cannot be read are skipped; the number of skipped records
+/// is returned.
+pub fn read_records<F>(path: &Path, mut handler: F) -> Result<usize>
+where
+    F: FnMut(&str, usize, &Value) -> Result<()>,
+{
+    let db = EseDb::open(path)?;
+    let mut corrupt_records = 0;
+
+    for table in db.iter_tables()? {
+        let table = table?;
+        let table_name = table.name()?;
+        let columns = table
+            .iter_columns()?
+            .map(|column| column.and_then(|c| c.name()))
+            .collect::<std::io::Result<Vec<String>>>()?;
+
+        for (index, record) in table.iter_records()?.enumerate() {
+            match record.and_then(|record| serialize_record(&record, &columns)) {
+                Ok(record) => handler(&table_name, index, &record)?,
+                Err(why) => {
+                    log::debug!("skipping record {} of table '{}': {}", index, table_name, why);
+                    corrupt_records += 1;
+                }
+            }
+        }
+    }
+    Ok(corrupt_records)
+}
+
+/// values which cannot be read are stored as `null`
+fn serialize_record(record: &libesedb::Record, columns: &[String]) -> std::io::Result<Value> {
+    let mut object = Map::new();
+    for (column, value) in columns.iter().zip(record.iter_values()?) {
+        let value = match value {
+            Ok(value) => Value::String(value.to_string()),
+            Err(_) => Value::Null,
+        };
+        object.insert(column.clone(), value);
+    }
+    Ok(Value::Object(object))
+}
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::time_window::TimeWindow;
+
+/// selects the evtx records which are scanned by yara. All records
+/// are scanned if no criteria have been specified
+#[derive(Clone, Default)]
+pub struct EvtxFilter {
+    event_ids: Vec<u64>,
+
+    /// channel names in lowercase
+    channels: Vec<String>,
+    window: TimeWindow,
+}
+
+impl EvtxFilter {
+    pub fn new(event_ids: Vec<u64>, channels: &[String], window: TimeWindow) -> Self {
+        Self {
+            event_ids,
+            channels: channels.iter().map(|c| c.to_lowercase()).collect(),
+            window,
+        }
+    }
+
+    /// checks if the record with the JSON representation `data`, which has been
+    /// written at `timestamp`, must be scanned
+    pub fn matches(&self, data: &Value, timestamp: &DateTime<Utc>) -> bool {
+        if !self.window.contains(timestamp) {
+            return false;
+        }
+
+        let system = &data["Event"]["System"];
+        if !self.event_ids.is_empty() {
+            match event_id(system) {
+                Some(id) if self.event_ids.contains(&id) => (),
+                _ => return false,
+            }
+        }
+
+        if !self.channels.is_empty() {
+            match system["Channel"].as_str() {
+                Some(channel) if self.channels.contains(&channel.to_lowercase()) => (),
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// reads the event id from the `System` element of an evtx record
+pub fn event_id(system: &Value) -> Option<u64> {
+    match &system["EventID"] {
+        // the event id is an object if it has attributes, like `Qualifiers`
+        Value::Object(o) => o.get("#text").and_then(Value::as_u64),
+        v => v.as_u64(),
+    }
+}
@@ -0,0 +1,473 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use flate2::read::DeflateDecoder;
+
+/// containers in installers are extracted up to this depth, e.g. a CAB file in a MSI
+/// file in a self-extracting installer
+const MAX_DEPTH: usize = 3;
+
+const CFB_SIGNATURE: &[u8; 8] = &[0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1];
+const CAB_SIGNATURE: &[u8; 4] = b"MSCF";
+const ZIP_SIGNATURE: &[u8; 4] = b"PK\x03\x04";
+const NSIS_SIGNATURE: &[u8; 16] = b"\xef\xbe\xad\xdeNullsoftInst";
+
+/// NSIS searches its data at 512 byte boundaries after the stub
+const NSIS_ALIGNMENT: u64 = 512;
+const NSIS_MAX_OFFSETS: u64 = 64;
+
+/// installers, whose payloads are extracted and scanned
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum InstallerKind {
+    /// Windows Installer packages, which are compound files (CFB)
+    Msi,
+
+    /// Microsoft cabinet files
+    Cab,
+
+    /// executables with an appended ZIP or CAB file, or NSIS installers
+    SelfExtracting,
+}
+
+impl InstallerKind {
+    /// detects installers by their type, as reported by libmagic. Executables are
+    /// checked for data which has been appended to them
+    pub fn detect(magic: &str, file: &Path) -> Option<Self> {
+        if magic.contains("MSI Installer") {
+            Some(InstallerKind::Msi)
+        } else if magic.starts_with("Microsoft Cabinet archive data") {
+            Some(InstallerKind::Cab)
+        } else if magic.starts_with("PE32") {
+            let mut reader = File::open(file).ok()?;
+            Overlay::find(&mut reader).ok().flatten()?;
+            Some(InstallerKind::SelfExtracting)
+        } else {
+            None
+        }
+    }
+}
+
+/// a payload which is appended to an executable
+enum Overlay {
+    Zip(u64),
+    Cab(u64),
+    Nsis(u64),
+}
+
+impl Overlay {
+    fn find<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>> {
+        let offset = match overlay_offset(reader)? {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        // the first header of NSIS starts with its flags, followed by the signature
+        let mut header = [0u8; 20];
+        for index in 0..NSIS_MAX_OFFSETS {
+            let offset = offset + index * NSIS_ALIGNMENT;
+            reader.seek(SeekFrom::Start(offset))?;
+            if reader.read_exact(&mut header).is_err() {
+                break;
+            }
+            if index == 0 && header.starts_with(ZIP_SIGNATURE) {
+                return Ok(Some(Overlay::Zip(offset)));
+            } else if index == 0 && header.starts_with(CAB_SIGNATURE) {
+                return Ok(Some(Overlay::Cab(offset)));
+            } else if header[4..] == NSIS_SIGNATURE[..] {
+                return Ok(Some(Overlay::Nsis(offset)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// the end of the last section of a PE file, after which the data of self-extracting
+/// installers is appended. Only the headers are read, so that large installers are
+/// not loaded into memory
+fn overlay_offset<R: Read + Seek>(reader: &mut R) -> Result<Option<u64>> {
+    let mut header = vec![0u8; 4096];
+    reader.seek(SeekFrom::Start(0))?;
+    let size = reader.read(&mut header)?;
+    header.truncate(size);
+
+    let u16_at = |offset: usize| -> Option<u16> {
+        Some(u16::from_le_bytes(header.get(offset..offset + 2)?.try_into().ok()?))
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(header.get(offset..offset + 4)?.try_into().ok()?))
+    };
+    if !header.starts_with(b"MZ") {
+        return Ok(None);
+    }
+    let pe_offset = match u32_at(0x3c) {
+        Some(offset) => offset as usize,
+        None => return Ok(None),
+    };
+    if header.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0") {
+        return Ok(None);
+    }
+    let (sections, optional_header_size) = match (u16_at(pe_offset + 6), u16_at(pe_offset + 20)) {
+        (Some(sections), Some(size)) => (sections as usize, size as usize),
+        _ => return Ok(None),
+    };
+    let section_table = pe_offset + 24 + optional_header_size;
+    let mut end = 0u64;
+    for section in 0..sections {
+        let entry = section_table + section * 40;
+        match (u32_at(entry + 16), u32_at(entry + 20)) {
+            (Some(size), Some(pointer)) => end = end.max(pointer as u64 + size as u64),
+            _ => return Ok(None),
+        }
+    }
+
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    Ok(match end > 0 && end < file_size {
+        true => Some(end),
+        false => None,
+    })
+}
+
+/// extracts the payloads of an installer, and passes their path in the installer and
+/// their content to `on_payload`. Containers in the payloads (e.g. a CAB file in a MSI
+/// file) are extracted as well, and the path contains the whole chain of containers,
+/// separated by `:`, e.g. `product.cab:setup.exe`. At most `limit` bytes of each payload
+/// are extracted
+pub(crate) fn extract_payloads<R: Read + Seek>(
+    kind: InstallerKind,
+    reader: R,
+    limit: usize,
+    on_payload: &mut dyn FnMut(&str, &[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut extractor = Extractor { limit, on_payload };
+    extractor.extract(kind, reader, "", 0)
+}
+
+struct Extractor<'a> {
+    limit: usize,
+    on_payload: &'a mut dyn FnMut(&str, &[u8]) -> Result<()>,
+}
+
+impl Extractor<'_> {
+    fn extract<R: Read + Seek>(
+        &mut self,
+        kind: InstallerKind,
+        mut reader: R,
+        parent: &str,
+        depth: usize,
+    ) -> Result<()> {
+        match kind {
+            InstallerKind::Msi => self.extract_msi(reader, parent, depth),
+            InstallerKind::Cab => self.extract_cab(reader, parent, depth),
+            InstallerKind::SelfExtracting => match Overlay::find(&mut reader)? {
+                Some(Overlay::Zip(_)) => self.extract_zip(reader, parent, depth),
+                Some(Overlay::Cab(offset)) => {
+                    reader.seek(SeekFrom::Start(offset))?;
+                    let mut cab = Vec::new();
+                    reader.take(self.limit as u64).read_to_end(&mut cab)?;
+                    self.extract_cab(Cursor::new(cab), parent, depth)
+                }
+                Some(Overlay::Nsis(offset)) => self.extract_nsis(reader, offset, parent, depth),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// passes a payload to the callback, and extracts it if it is a container
+    fn payload(&mut self, parent: &str, name: &str, data: Vec<u8>, depth: usize) -> Result<()> {
+        let path = match parent.is_empty() {
+            true => name.to_owned(),
+            false => format!("{}:{}", parent, name),
+        };
+        if data.len() == self.limit {
+            log::warn!("'{}' could not be extracted completely", path);
+        }
+        (self.on_payload)(&path, &data)?;
+
+        if depth + 1 >= MAX_DEPTH {
+            return Ok(());
+        }
+        let extracted = if data.starts_with(CFB_SIGNATURE) {
+            self.extract(InstallerKind::Msi, Cursor::new(data), &path, depth + 1)
+        } else if data.starts_with(CAB_SIGNATURE) {
+            self.extract(InstallerKind::Cab, Cursor::new(data), &path, depth + 1)
+        } else if data.starts_with(b"MZ") {
+            self.extract(InstallerKind::SelfExtracting, Cursor::new(data), &path, depth + 1)
+        } else if data.starts_with(ZIP_SIGNATURE) {
+            self.extract_zip(Cursor::new(data), &path, depth + 1)
+        } else {
+            Ok(())
+        };
+        if let Err(why) = extracted {
+            log::warn!("unable to extract the payloads of '{}': {}", path, why);
+        }
+        Ok(())
+    }
+
+    fn read_limited<R: Read>(&self, reader: R) -> std::io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        reader.take(self.limit as u64).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// extracts all streams of a MSI file, except of its tables
+    fn extract_msi<R: Read + Seek>(&mut self, reader: R, parent: &str, depth: usize) -> Result<()> {
+        let mut msi = cfb::CompoundFile::open(reader)?;
+        let streams: Vec<_> = msi
+            .walk()
+            .filter(|entry| entry.is_stream())
+            .map(|entry| entry.path().to_owned())
+            .collect();
+        for stream in streams {
+            let name = decode_stream_name(&stream.file_name().unwrap_or_default().to_string_lossy());
+            if name.starts_with('!') || name.starts_with(|c: char| c.is_control()) {
+                continue;
+            }
+            let data = self.read_limited(msi.open_stream(&stream)?)?;
+            self.payload(parent, &name, data, depth)?;
+        }
+        Ok(())
+    }
+
+    fn extract_cab<R: Read + Seek>(&mut self, reader: R, parent: &str, depth: usize) -> Result<()> {
+        let mut cabinet = cab::Cabinet::new(reader)?;
+        let files: Vec<String> = cabinet
+            .folder_entries()
+            .flat_map(|folder| folder.file_entries())
+            .map(|file| file.name().to_owned())
+            .collect();
+        for file in files {
+            let data = self.read_limited(cabinet.read_file(&file)?)?;
+            self.payload(parent, &file, data, depth)?;
+        }
+        Ok(())
+    }
+
+    fn extract_zip<R: Read + Seek>(&mut self, reader: R, parent: &str, depth: usize) -> Result<()> {
+        let mut zip = zip::ZipArchive::new(reader)?;
+        for index in 0..zip.len() {
+            let file = zip.by_index(index)?;
+            if file.is_file() {
+                let name = file.name().to_owned();
+                let data = self.read_limited(file)?;
+                self.payload(parent, &name, data, depth)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// extracts the files of a NSIS installer. Their names are stored in the compiled
+    /// installation script, so they are named by their index (`nsis[0]`, ...). LZMA and
+    /// zlib compressed installers are supported, both in solid and in non-solid mode
+    fn extract_nsis<R: Read + Seek>(
+        &mut self,
+        mut reader: R,
+        offset: u64,
+        parent: &str,
+        depth: usize,
+    ) -> Result<()> {
+        // flags, signature, header size and size of all data
+        let mut first_header = [0u8; 28];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut first_header)?;
+
+        let mut start = [0u8; 4];
+        reader.read_exact(&mut start)?;
+        reader.seek(SeekFrom::Start(offset + first_header.len() as u64))?;
+
+        // solid LZMA data starts with the LZMA properties, and non-solid data with the
+        // size of the first block, whose highest bit is set if it is compressed
+        let solid_lzma = start[0] == 0x5d && start[1] == 0 && start[2] == 0;
+        let solid = solid_lzma || u32::from_le_bytes(start) & 0x8000_0000 == 0;
+        if solid {
+            let mut stream = match nsis_decoder(start[0], reader)? {
+                Some(stream) => stream,
+                None => return Err(anyhow!("unsupported compression of the NSIS installer")),
+            };
+            self.extract_solid_nsis(&mut stream, parent, depth)?;
+        } else {
+            let mut index = None;
+            while let Some(size) = read_u32(&mut reader)? {
+                let compressed = size & 0x8000_0000 != 0;
+                let mut block = Vec::new();
+                (&mut reader).take((size & 0x7fff_ffff) as u64).read_to_end(&mut block)?;
+                let data = match compressed {
+                    false => block,
+                    true => {
+                        let first_byte = block.first().copied().unwrap_or_default();
+                        match nsis_decoder(first_byte, Cursor::new(block))? {
+                            Some(decoder) => self.read_limited(decoder)?,
+                            None => {
+                                return Err(anyhow!("unsupported compression of the NSIS installer"))
+                            }
+                        }
+                    }
+                };
+                if let Some(index) = &mut index {
+                    self.payload(parent, &format!("nsis[{}]", index), data, depth)?;
+                    *index += 1;
+                } else {
+                    index = Some(0);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// extracts the blocks of the decompressed data of a solid NSIS installer. Each block
+    /// is prefixed with its size, and the first block is the installation script
+    fn extract_solid_nsis<R: Read>(
+        &mut self,
+        mut stream: R,
+        parent: &str,
+        depth: usize,
+    ) -> Result<()> {
+        let mut index = None;
+        while let Some(size) = read_u32(&mut stream)? {
+            let mut block = (&mut stream).take(size as u64);
+            let data = self.read_limited(&mut block)?;
+            // skip the rest of a truncated block, so that the next size is read correctly
+            std::io::copy(&mut block, &mut std::io::sink())?;
+            if let Some(index) = &mut index {
+                self.payload(parent, &format!("nsis[{}]", index), data, depth)?;
+                *index += 1;
+            } else {
+                index = Some(0);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the decompressor of NSIS data. LZMA data starts with its properties (usually `0x5d`),
+/// zlib data is a raw deflate stream. NSIS uses a modified bzip2 format, which is not
+/// supported
+fn nsis_decoder<'a, R: Read + 'a>(first_byte: u8, reader: R) -> Result<Option<Box<dyn Read + 'a>>> {
+    if first_byte == 0x5d {
+        // NSIS omits the uncompressed size of the .lzma format
+        let mut properties = [0u8; 5];
+        let mut reader = reader;
+        reader.read_exact(&mut properties)?;
+        let header = [&properties[..], &[0xff; 8]].concat();
+        let stream = xz::stream::Stream::new_lzma_decoder(u64::MAX)?;
+        Ok(Some(Box::new(xz::read::XzDecoder::new_stream(
+            Cursor::new(header).chain(reader),
+            stream,
+        ))))
+    } else if first_byte == 0x31 {
+        Ok(None)
+    } else {
+        Ok(Some(Box::new(DeflateDecoder::new(reader))))
+    }
+}
+
+/// reads a little-endian `u32`, or returns `None` at the end of the data
+fn read_u32<R: Read>(reader: &mut R) -> Result<Option<u32>> {
+    let mut value = [0u8; 4];
+    match reader.read_exact(&mut value) {
+        Ok(()) => Ok(Some(u32::from_le_bytes(value))),
+        Err(why) if why.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(why) => Err(why.into()),
+    }
+}
+
+/// decodes the name of a stream in a MSI file. Windows Installer packs two characters
+/// of the names of its streams into one character, to stay below the limit of 31
+/// characters. The names of tables are prefixed with `!`
+fn decode_stream_name(name: &str) -> String {
+    fn from_base64(value: u32) -> char {
+        match value {
+            0..=9 => char::from(b'0' + value as u8),
+            10..=35 => char::from(b'A' + (value - 10) as u8),
+            36..=61 => char::from(b'a' + (value - 36) as u8),
+            62 => '.',
+            _ => '_',
+        }
+    }
+
+    let mut result = String::new();
+    for c in name.chars() {
+        match c as u32 {
+            0x4840 => result.push('!'),
+            value @ 0x3800..=0x47ff => {
+                let value = value - 0x3800;
+                result.push(from_base64(value & 0x3f));
+                result.push(from_base64((value >> 6) & 0x3f));
+            }
+            value @ 0x4800..=0x483f => result.push(from_base64(value - 0x4800)),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use super::{decode_stream_name, extract_payloads, Extractor, InstallerKind};
+
+    #[test]
+    fn test_stream_names() {
+        assert_eq!(decode_stream_name("\u{4840}\u{3f7f}\u{4164}\u{422f}\u{4836}"), "!_Tables");
+        assert_eq!(decode_stream_name("\u{5}SummaryInformation"), "\u{5}SummaryInformation");
+    }
+
+    #[test]
+    fn test_zip_sfx() {
+        // a minimal PE file with a single section, followed by a zip file
+        let mut pe = vec![0u8; 0x200];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x40;
+        pe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        pe[0x46] = 1;
+        let section = 0x40 + 24;
+        pe[section + 16..section + 20].copy_from_slice(&0x10u32.to_le_bytes());
+        pe[section + 20..section + 24].copy_from_slice(&0x1f0u32.to_le_bytes());
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("payload.exe", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"evil").unwrap();
+        pe.extend(zip.finish().unwrap().into_inner());
+
+        let mut payloads = Vec::new();
+        let mut on_payload = |path: &str, data: &[u8]| {
+            payloads.push((path.to_owned(), data.to_vec()));
+            Ok(())
+        };
+        extract_payloads(InstallerKind::SelfExtracting, Cursor::new(pe), 1024, &mut on_payload)
+            .unwrap();
+        assert_eq!(payloads, vec![("payload.exe".to_owned(), b"evil".to_vec())]);
+    }
+
+    #[test]
+    fn test_solid_nsis() {
+        let mut stream = Vec::new();
+        for block in [&b"script"[..], &[b'A'; 32], b"evil"] {
+            stream.extend((block.len() as u32).to_le_bytes());
+            stream.extend(block);
+        }
+
+        let mut payloads = Vec::new();
+        let mut on_payload = |path: &str, data: &[u8]| {
+            payloads.push((path.to_owned(), data.to_vec()));
+            Ok(())
+        };
+        let mut extractor = Extractor {
+            limit: 8,
+            on_payload: &mut on_payload,
+        };
+        extractor
+            .extract_solid_nsis(Cursor::new(stream), "", 0)
+            .unwrap();
+        assert_eq!(
+            payloads,
+            vec![
+                ("nsis[0]".to_owned(), vec![b'A'; 8]),
+                ("nsis[1]".to_owned(), b"evil".to_vec()),
+            ]
+        );
+    }
+}
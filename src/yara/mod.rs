@@ -1,7 +1,32 @@
 mod yara_scanner;
 mod yara_finding;
 mod yara_string;
+mod yara_location;
+
+#[cfg(feature = "scan_evtx")]
+mod evtx_filter;
+#[cfg(feature = "scan_ese")]
+mod ese_reader;
+mod installer;
+mod split_archive;
+mod unallocated;
 mod yara_externals;
 mod yara_error;
+mod yara_lint;
+mod yara_ruleset;
+mod yara_statistics;
+
+#[cfg(feature = "yara_x")]
+mod yara_x_scanner;
+
+pub (crate) use yara_scanner::*;
+pub (crate) use yara_ruleset::YaraRuleset;
+pub (crate) use unallocated::{
+    apply_fixups, attributes, data_runs, decode_runs, ntfs_volumes, read_at, NtfsVolume,
+};
+
+#[cfg(feature = "scan_evtx")]
+pub (crate) use evtx_filter::EvtxFilter;
 
-pub (crate) use yara_scanner::*;
\ No newline at end of file
+#[cfg(feature = "yara_x")]
+pub (crate) use yara_x_scanner::*;
\ No newline at end of file
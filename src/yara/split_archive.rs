@@ -0,0 +1,287 @@
+use std::convert::TryInto;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// split archives have at most this number of volumes (`.001` to `.999`)
+const MAX_VOLUMES: usize = 999;
+
+/// every volume of a RAR archive starts with this signature
+pub(crate) const RAR_SIGNATURE: &[u8; 6] = b"Rar!\x1a\x07";
+
+/// a volume of an archive which has been split into multiple files, like
+/// `sample.zip.001`, `sample.zip.002`, ... (as created by 7-Zip or `split`), or like
+/// `sample.part1.rar`, `sample.part2.rar`, ... (as created by RAR). The volumes are only
+/// parts of the archive, so that they are scanned together
+#[derive(Debug)]
+pub(crate) enum SplitArchive {
+    /// the first volume, with the paths of all volumes in their order
+    First(Vec<PathBuf>),
+
+    /// the first volume of a RAR archive, with the paths of all volumes in their order
+    FirstRar(Vec<PathBuf>),
+
+    /// a later volume, which is scanned together with the first volume, with the paths
+    /// of all volumes and the index of this volume in them
+    Later(Vec<PathBuf>, usize),
+}
+
+impl SplitArchive {
+    /// checks if `file` is a volume of a split archive. A file is only treated as
+    /// volume if the volumes from the first one up to `file` exist, and there are at
+    /// least two of them. Otherwise, `file` is not part of the assembled archive (e.g. if
+    /// a volume is missing), and is scanned on its own
+    pub fn of(file: &Path) -> Option<Self> {
+        let (names, index) = VolumeNames::of(file)?;
+        let volumes: Vec<PathBuf> = (1..=MAX_VOLUMES)
+            .map(|index| names.path(index))
+            .take_while(|volume| volume.is_file())
+            .collect();
+        if volumes.len() < 2 || volumes.len() < index {
+            None
+        } else if index > 1 {
+            Some(SplitArchive::Later(volumes, index - 1))
+        } else if names.is_rar {
+            Some(SplitArchive::FirstRar(volumes))
+        } else {
+            Some(SplitArchive::First(volumes))
+        }
+    }
+}
+
+/// the naming scheme of the volumes of a split archive: the path of a volume consists
+/// of a prefix, the zero padded volume number and a suffix, like `sample.zip.` + `001`
+/// or `sample.part` + `01` + `.rar`
+struct VolumeNames {
+    prefix: OsString,
+    digits: usize,
+    suffix: String,
+    is_rar: bool,
+}
+
+impl VolumeNames {
+    /// the naming scheme of the volume `file`, and its volume number
+    fn of(file: &Path) -> Option<(Self, usize)> {
+        let name = file.file_name()?.to_str()?;
+        let (prefix, number, suffix) = match name.rsplit_once('.') {
+            Some((stem, extension)) if extension.eq_ignore_ascii_case("rar") => {
+                let (_, part) = stem.rsplit_once('.')?;
+                part.get(..4).filter(|p| p.eq_ignore_ascii_case("part"))?;
+                let prefix = &stem[..stem.len() - part.len() + 4];
+                (prefix, &part[4..], &name[stem.len()..])
+            }
+            Some((stem, extension)) if extension.len() == 3 => {
+                (&name[..=stem.len()], extension, "")
+            }
+            _ => return None,
+        };
+        if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let index = number.parse().ok().filter(|index| *index > 0)?;
+        let names = Self {
+            prefix: file.with_file_name(prefix).into_os_string(),
+            digits: number.len(),
+            suffix: suffix.to_owned(),
+            is_rar: !suffix.is_empty(),
+        };
+        Some((names, index))
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        let mut path = self.prefix.clone();
+        path.push(format!(
+            "{:0digits$}{}",
+            index,
+            self.suffix,
+            digits = self.digits
+        ));
+        PathBuf::from(path)
+    }
+}
+
+/// reads the volumes of a split archive as if they were a single file
+pub(crate) struct VolumeReader {
+    volumes: Vec<(PathBuf, u64)>,
+    len: u64,
+    position: u64,
+
+    /// the index of the volume which is open, and its file
+    current: Option<(usize, File)>,
+}
+
+impl VolumeReader {
+    pub fn open(volumes: &[PathBuf]) -> std::io::Result<Self> {
+        let mut sizes = Vec::with_capacity(volumes.len());
+        for volume in volumes {
+            sizes.push((volume.clone(), volume.metadata()?.len()));
+        }
+        Ok(Self {
+            len: sizes.iter().map(|(_, size)| size).sum(),
+            volumes: sizes,
+            position: 0,
+            current: None,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// the index of the volume which contains `position`, and the offset of this volume
+    fn volume_at(&self, position: u64) -> Option<(usize, u64)> {
+        let mut offset = 0;
+        for (index, (_, size)) in self.volumes.iter().enumerate() {
+            if position < offset + size {
+                return Some((index, offset));
+            }
+            offset += size;
+        }
+        None
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let (index, offset) = match self.volume_at(self.position) {
+            Some(volume) => volume,
+            None => return Ok(0),
+        };
+        if !matches!(&self.current, Some((current, _)) if *current == index) {
+            self.current = Some((index, File::open(&self.volumes[index].0)?));
+        }
+        let (_, file) = self.current.as_mut().unwrap();
+        file.seek(SeekFrom::Start(self.position - offset))?;
+
+        let remaining = offset + self.volumes[index].1 - self.position;
+        let length = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let bytes = file.read(&mut buf[..length])?;
+        self.position += bytes as u64;
+        Ok(bytes)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom};
+
+    use super::{SplitArchive, VolumeReader};
+
+    #[test]
+    fn test_volumes() {
+        let dir = std::env::temp_dir().join(format!("dionysos-split-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (index, content) in ["abc", "def", "g"].iter().enumerate() {
+            std::fs::write(dir.join(format!("sample.zip.{:03}", index + 1)), content).unwrap();
+        }
+        std::fs::write(dir.join("single.001"), "x").unwrap();
+
+        let volumes = match SplitArchive::of(&dir.join("sample.zip.001")) {
+            Some(SplitArchive::First(volumes)) => volumes,
+            other => panic!("unexpected volumes: {:?}", other),
+        };
+        assert_eq!(volumes.len(), 3);
+        assert!(matches!(
+            SplitArchive::of(&dir.join("sample.zip.003")),
+            Some(SplitArchive::Later(later, 2)) if later == volumes
+        ));
+        assert!(SplitArchive::of(&dir.join("single.001")).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_volume() {
+        let dir = std::env::temp_dir().join(format!("dionysos-gap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for index in [1, 2, 4] {
+            std::fs::write(dir.join(format!("sample.7z.{:03}", index)), "x").unwrap();
+        }
+        std::fs::write(dir.join("decoy.bin.001"), "x").unwrap();
+        std::fs::write(dir.join("decoy.bin.002"), "x").unwrap();
+        std::fs::write(dir.join("decoy.bin.005"), "x").unwrap();
+
+        assert!(matches!(
+            SplitArchive::of(&dir.join("sample.7z.001")),
+            Some(SplitArchive::First(volumes)) if volumes.len() == 2
+        ));
+        // volumes after a missing volume are not part of the archive
+        assert!(SplitArchive::of(&dir.join("sample.7z.004")).is_none());
+        assert!(SplitArchive::of(&dir.join("decoy.bin.005")).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rar_volumes() {
+        let dir = std::env::temp_dir().join(format!("dionysos-rar-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for index in 1..=3 {
+            std::fs::write(dir.join(format!("sample.part{:02}.rar", index)), "x").unwrap();
+        }
+        std::fs::write(dir.join("single.part1.rar"), "x").unwrap();
+        std::fs::write(dir.join("other.rar"), "x").unwrap();
+
+        let volumes = match SplitArchive::of(&dir.join("sample.part01.rar")) {
+            Some(SplitArchive::FirstRar(volumes)) => volumes,
+            other => panic!("unexpected volumes: {:?}", other),
+        };
+        assert_eq!(volumes.len(), 3);
+        assert_eq!(volumes[2], dir.join("sample.part03.rar"));
+        assert!(matches!(
+            SplitArchive::of(&dir.join("sample.part02.rar")),
+            Some(SplitArchive::Later(later, 1)) if later == volumes
+        ));
+        assert!(SplitArchive::of(&dir.join("single.part1.rar")).is_none());
+        assert!(SplitArchive::of(&dir.join("other.rar")).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_volume_reader() {
+        let dir = std::env::temp_dir().join(format!("dionysos-volumes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let volumes: Vec<_> = ["abc", "def", "g"]
+            .iter()
+            .enumerate()
+            .map(|(index, content)| {
+                let volume = dir.join(format!("sample.zip.{:03}", index + 1));
+                std::fs::write(&volume, content).unwrap();
+                volume
+            })
+            .collect();
+
+        let mut reader = VolumeReader::open(&volumes).unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "abcdefg");
+        reader.seek(SeekFrom::End(-5)).unwrap();
+        let mut middle = [0; 3];
+        reader.read_exact(&mut middle).unwrap();
+        assert_eq!(&middle, b"cde");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
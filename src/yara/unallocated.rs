@@ -0,0 +1,518 @@
+use std::convert::{TryFrom, TryInto};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+const SECTOR_SIZE: u64 = 512;
+const NTFS_SIGNATURE: &[u8; 8] = b"NTFS    ";
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const MBR_PROTECTIVE: u8 = 0xee;
+
+/// the number of the MFT record of `$Bitmap`, which contains the allocation bitmap
+const BITMAP_RECORD: u64 = 6;
+const ATTRIBUTE_DATA: u32 = 0x80;
+const ATTRIBUTE_END: u32 = 0xffff_ffff;
+
+/// the stride of the update sequence of MFT records
+const FIXUP_STRIDE: usize = 512;
+
+/// carved files are at most this large, if their size cannot be determined
+const MAX_CARVED_SIZE: usize = 16 * 1024 * 1024;
+
+/// NTFS supports clusters of up to 2 MiB
+const MAX_CLUSTER_SIZE: u64 = 2 * 1024 * 1024;
+
+/// consecutive chunks of a run of unallocated clusters overlap by at least this many
+/// bytes, so that matches which straddle the boundary of two chunks are found
+const CHUNK_OVERLAP: u64 = 64 * 1024;
+
+/// the offsets of the NTFS volumes in `file`, which is either a volume itself (or a raw
+/// device of a volume) or a disk with an MBR or a GPT. Extended partitions are not
+/// supported
+pub(crate) fn ntfs_volumes(file: &Path) -> Vec<u64> {
+    let mut image = match File::open(file) {
+        Ok(image) => image,
+        Err(_) => return Vec::new(),
+    };
+    let mut sector = [0u8; SECTOR_SIZE as usize];
+    if read_at(&mut image, 0, &mut sector).is_err() {
+        return Vec::new();
+    }
+    if &sector[3..11] == NTFS_SIGNATURE {
+        return vec![0];
+    }
+    if sector[510..512] != [0x55, 0xaa] {
+        return Vec::new();
+    }
+
+    let mut partitions = Vec::new();
+    for entry in sector[446..510].chunks_exact(16) {
+        let start = u32::from_le_bytes(entry[8..12].try_into().unwrap()) as u64;
+        match entry[4] {
+            0 => (),
+            MBR_PROTECTIVE => partitions.extend(gpt_partitions(&mut image).unwrap_or_default()),
+            _ => partitions.push(start * SECTOR_SIZE),
+        }
+    }
+    partitions
+        .into_iter()
+        .filter(|offset| {
+            read_at(&mut image, *offset, &mut sector).is_ok() && &sector[3..11] == NTFS_SIGNATURE
+        })
+        .collect()
+}
+
+fn gpt_partitions(image: &mut File) -> Result<Vec<u64>> {
+    let mut header = [0u8; SECTOR_SIZE as usize];
+    read_at(image, SECTOR_SIZE, &mut header)?;
+    if &header[..8] != GPT_SIGNATURE {
+        return Err(anyhow!("invalid GPT header"));
+    }
+    let entries_lba = u64::from_le_bytes(header[72..80].try_into()?);
+    let entries = u32::from_le_bytes(header[80..84].try_into()?).min(256) as usize;
+    let entry_size = u32::from_le_bytes(header[84..88].try_into()?) as usize;
+    if entry_size < 128 {
+        return Err(anyhow!("invalid size of GPT entries"));
+    }
+
+    let mut table = vec![0u8; entries * entry_size];
+    read_at(image, entries_lba * SECTOR_SIZE, &mut table)?;
+    Ok(table
+        .chunks_exact(entry_size)
+        .filter(|entry| entry[..16].iter().any(|b| *b != 0))
+        .map(|entry| u64::from_le_bytes(entry[32..40].try_into().unwrap()) * SECTOR_SIZE)
+        .collect())
+}
+
+pub(crate) fn read_at(image: &mut File, offset: u64, buffer: &mut [u8]) -> std::io::Result<()> {
+    image.seek(SeekFrom::Start(offset))?;
+    image.read_exact(buffer)
+}
+
+/// the allocation bitmap of an NTFS volume
+pub(crate) struct NtfsVolume {
+    /// the offset of the volume in the image
+    pub offset: u64,
+    pub cluster_size: u64,
+    pub clusters: u64,
+
+    /// the offset of the first record of the MFT in the image, and the size of its records
+    pub mft_offset: u64,
+    pub record_size: u64,
+    bitmap: Vec<u8>,
+}
+
+impl NtfsVolume {
+    /// reads the allocation bitmap from the `$Bitmap` file of the volume at `offset`
+    pub fn open(image: &mut File, offset: u64) -> Result<Self> {
+        let mut boot = [0u8; SECTOR_SIZE as usize];
+        read_at(image, offset, &mut boot)?;
+        if &boot[3..11] != NTFS_SIGNATURE {
+            return Err(anyhow!("no NTFS volume at offset {}", offset));
+        }
+        let bytes_per_sector = u16::from_le_bytes([boot[0x0b], boot[0x0c]]) as u64;
+        if !bytes_per_sector.is_power_of_two() || !(256..=4096).contains(&bytes_per_sector) {
+            return Err(anyhow!("invalid sector size {}", bytes_per_sector));
+        }
+        let sectors_per_cluster = match boot[0x0d] {
+            n if n >= 0xe0 => 1u64 << (256 - n as u64),
+            n if n > 0x80 => return Err(anyhow!("invalid cluster size")),
+            n => n as u64,
+        };
+        let cluster_size = bytes_per_sector * sectors_per_cluster;
+        if !cluster_size.is_power_of_two() || cluster_size > MAX_CLUSTER_SIZE {
+            return Err(anyhow!("invalid cluster size {}", cluster_size));
+        }
+        let total_sectors = u64::from_le_bytes(boot[0x28..0x30].try_into()?);
+        let clusters = total_sectors
+            .checked_mul(bytes_per_sector)
+            .ok_or_else(|| anyhow!("invalid number of sectors"))?
+            / cluster_size;
+        let mft_cluster = u64::from_le_bytes(boot[0x30..0x38].try_into()?);
+        if mft_cluster >= clusters {
+            return Err(anyhow!("the MFT is outside of the volume"));
+        }
+        let record_size = match boot[0x40] as i8 {
+            n if (-16..0).contains(&n) => 1u64 << (-(n as i64)),
+            n if n > 0 => n as u64 * cluster_size,
+            _ => return Err(anyhow!("invalid size of MFT records")),
+        };
+        if !record_size.is_power_of_two() || !(512..=64 * 1024).contains(&record_size) {
+            return Err(anyhow!("invalid size of MFT records {}", record_size));
+        }
+
+        // the first records of the MFT are stored contiguously
+        let mut record = vec![0u8; record_size as usize];
+        let record_offset = offset + mft_cluster * cluster_size + BITMAP_RECORD * record_size;
+        read_at(image, record_offset, &mut record)?;
+        apply_fixups(&mut record)?;
+
+        let runs = data_runs(&record)?;
+        let bitmap_size = ((clusters + 7) / 8) as usize;
+        let mut bitmap = Vec::with_capacity(bitmap_size);
+        for (cluster, count) in runs {
+            // the runs of a damaged record must not exhaust the memory
+            let remaining = (bitmap_size - bitmap.len()) as u64;
+            let length = count.saturating_mul(cluster_size).min(remaining) as usize;
+            let mut run = vec![0u8; length];
+            match cluster {
+                Some(cluster) if cluster < clusters => {
+                    read_at(image, offset + cluster * cluster_size, &mut run)?
+                }
+                Some(_) => return Err(anyhow!("the allocation bitmap is outside of the volume")),
+                // sparse runs of the bitmap are not allocated
+                None => (),
+            }
+            bitmap.extend(run);
+            if bitmap.len() == bitmap_size {
+                break;
+            }
+        }
+        Ok(Self {
+            offset,
+            cluster_size,
+            clusters,
+            mft_offset: offset + mft_cluster * cluster_size,
+            record_size,
+            bitmap,
+        })
+    }
+
+    /// reads `count` clusters from `cluster` on
+    pub fn read_clusters(&self, image: &mut File, cluster: u64, count: u64) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; (count * self.cluster_size) as usize];
+        read_at(
+            image,
+            self.offset + cluster * self.cluster_size,
+            &mut buffer,
+        )?;
+        Ok(buffer)
+    }
+
+    fn is_allocated(&self, cluster: u64) -> bool {
+        match self.bitmap.get((cluster / 8) as usize) {
+            Some(byte) => byte & (1 << (cluster % 8)) != 0,
+            // clusters beyond the bitmap are considered as allocated
+            None => true,
+        }
+    }
+
+    /// the chunks of at most `max_clusters` unallocated clusters, as first cluster, number
+    /// of clusters and the number of clusters at the beginning of the chunk which have
+    /// already been read with the previous chunk. Long runs of unallocated clusters are
+    /// split into chunks which overlap, so that no match is lost at their boundaries
+    pub fn unallocated_chunks(&self, max_clusters: u64) -> Vec<(u64, u64, u64)> {
+        let overlap = ((CHUNK_OVERLAP + self.cluster_size - 1) / self.cluster_size)
+            .min(max_clusters / 2);
+        let mut chunks: Vec<(u64, u64, u64)> = Vec::new();
+        let mut previous_end = None;
+        for (cluster, count) in self.unallocated_runs(max_clusters) {
+            let overlap = match previous_end {
+                Some(end) if end == cluster => overlap.min(count),
+                _ => 0,
+            };
+            previous_end = Some(cluster + count);
+            chunks.push((cluster - overlap, count + overlap, overlap));
+        }
+        chunks
+    }
+
+    /// the runs of unallocated clusters, as first cluster and number of clusters. Runs
+    /// are split after `max_clusters`
+    fn unallocated_runs(&self, max_clusters: u64) -> Vec<(u64, u64)> {
+        let mut runs = Vec::new();
+        let mut start = None;
+        for cluster in 0..self.clusters {
+            match (self.is_allocated(cluster), start) {
+                (false, None) => start = Some(cluster),
+                (false, Some(first)) if cluster - first == max_clusters => {
+                    runs.push((first, cluster - first));
+                    start = Some(cluster);
+                }
+                (true, Some(first)) => {
+                    runs.push((first, cluster - first));
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+        if let Some(first) = start {
+            runs.push((first, self.clusters - first));
+        }
+        runs
+    }
+}
+
+/// replaces the last two bytes of every sector of an MFT record, which have been
+/// replaced by the update sequence number when the record has been written
+pub(crate) fn apply_fixups(record: &mut [u8]) -> Result<()> {
+    if &record[..4] != b"FILE" {
+        return Err(anyhow!("invalid MFT record"));
+    }
+    let usa_offset = u16::from_le_bytes([record[4], record[5]]) as usize;
+    let usa_count = u16::from_le_bytes([record[6], record[7]]) as usize;
+    if usa_count == 0
+        || usa_offset + usa_count * 2 > record.len()
+        || (usa_count - 1) * FIXUP_STRIDE > record.len()
+    {
+        return Err(anyhow!("invalid update sequence of MFT record"));
+    }
+    let usn = [record[usa_offset], record[usa_offset + 1]];
+    for i in 1..usa_count {
+        let end = i * FIXUP_STRIDE;
+        if record[end - 2..end] != usn {
+            return Err(anyhow!("MFT record is damaged"));
+        }
+        let fixup = usa_offset + i * 2;
+        record[end - 2] = record[fixup];
+        record[end - 1] = record[fixup + 1];
+    }
+    Ok(())
+}
+
+/// the attributes of an MFT record, as type and the bytes of the attribute
+pub(crate) fn attributes(record: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let mut position = u16::from_le_bytes([record[0x14], record[0x15]]) as usize;
+    std::iter::from_fn(move || {
+        let attribute = record.get(position..)?.get(..0x18)?;
+        let kind = u32::from_le_bytes(attribute[..4].try_into().unwrap());
+        let length = u32::from_le_bytes(attribute[4..8].try_into().unwrap()) as usize;
+        if kind == ATTRIBUTE_END || length < 0x18 || position + length > record.len() {
+            return None;
+        }
+        let attribute = &record[position..position + length];
+        position += length;
+        Some((kind, attribute))
+    })
+}
+
+/// the data runs of a non-resident attribute, as first cluster (or `None` for sparse
+/// runs) and number of clusters
+pub(crate) fn decode_runs(attribute: &[u8]) -> Result<Vec<(Option<u64>, u64)>> {
+    let le = |data: &[u8]| -> u64 {
+        data.iter()
+            .rev()
+            .fold(0u64, |value, byte| (value << 8) | *byte as u64)
+    };
+    let invalid = || anyhow!("invalid attribute in MFT record");
+    if attribute.len() < 0x40 || attribute[8] == 0 {
+        return Err(invalid());
+    }
+
+    let mut runs = Vec::new();
+    let mut run = u16::from_le_bytes([attribute[0x20], attribute[0x21]]) as usize;
+    let mut cluster: i64 = 0;
+    while run < attribute.len() && attribute[run] != 0 {
+        let length_size = (attribute[run] & 0x0f) as usize;
+        let offset_size = (attribute[run] >> 4) as usize;
+        if length_size > 8 || offset_size > 8 {
+            return Err(invalid());
+        }
+        let data = attribute
+            .get(run + 1..run + 1 + length_size + offset_size)
+            .ok_or_else(invalid)?;
+        let count = le(&data[..length_size]);
+        if offset_size == 0 {
+            runs.push((None, count));
+        } else {
+            // the offset is signed and relative to the previous run
+            let shift = 64 - 8 * offset_size as u32;
+            let delta = ((le(&data[length_size..]) << shift) as i64) >> shift;
+            cluster = cluster.checked_add(delta).ok_or_else(invalid)?;
+            runs.push((Some(u64::try_from(cluster).map_err(|_| invalid())?), count));
+        }
+        run += 1 + length_size + offset_size;
+    }
+    Ok(runs)
+}
+
+/// the data runs of the unnamed, non-resident `$DATA` attribute of an MFT record, as
+/// first cluster (or `None` for sparse runs) and number of clusters
+pub(crate) fn data_runs(record: &[u8]) -> Result<Vec<(Option<u64>, u64)>> {
+    let non_resident = |attribute: &[u8]| attribute[8] != 0;
+    let named = |attribute: &[u8]| attribute[9] != 0;
+    match attributes(record).find(|(kind, attribute)| {
+        *kind == ATTRIBUTE_DATA && !named(attribute) && non_resident(attribute)
+    }) {
+        Some((_, attribute)) => decode_runs(attribute),
+        None => Err(anyhow!("the MFT record has no non-resident data")),
+    }
+}
+
+/// the formats of files which are carved from unallocated space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CarvedFormat {
+    Pe,
+    Zip,
+    Pdf,
+}
+
+impl CarvedFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CarvedFormat::Pe => "PE",
+            CarvedFormat::Zip => "ZIP",
+            CarvedFormat::Pdf => "PDF",
+        }
+    }
+
+    fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"MZ") {
+            let pe_offset = u32::from_le_bytes(data.get(0x3c..0x40)?.try_into().ok()?) as usize;
+            (data.get(pe_offset..pe_offset + 4)? == b"PE\0\0").then_some(CarvedFormat::Pe)
+        } else if data.starts_with(b"PK\x03\x04") {
+            Some(CarvedFormat::Zip)
+        } else if data.starts_with(b"%PDF-") {
+            Some(CarvedFormat::Pdf)
+        } else {
+            None
+        }
+    }
+
+    /// the size of the file at the beginning of `data`. If it cannot be determined,
+    /// the file is assumed to end at the end of `data`
+    fn size(&self, data: &[u8]) -> usize {
+        let data = &data[..data.len().min(MAX_CARVED_SIZE)];
+        let size = match self {
+            CarvedFormat::Pe => pe_size(data),
+            CarvedFormat::Zip => find_last(data, b"PK\x05\x06").map(|eocd| {
+                let comment = data
+                    .get(eocd + 20..eocd + 22)
+                    .map_or(0, |c| u16::from_le_bytes([c[0], c[1]]) as usize);
+                eocd + 22 + comment
+            }),
+            CarvedFormat::Pdf => find_last(data, b"%%EOF").map(|eof| eof + 5),
+        };
+        size.unwrap_or(data.len()).min(data.len())
+    }
+}
+
+/// the end of the last section of a PE file
+fn pe_size(data: &[u8]) -> Option<usize> {
+    let pe_offset = u32::from_le_bytes(data.get(0x3c..0x40)?.try_into().ok()?) as usize;
+    let sections = u16::from_le_bytes(data.get(pe_offset + 6..pe_offset + 8)?.try_into().ok()?);
+    let optional_header_size =
+        u16::from_le_bytes(data.get(pe_offset + 20..pe_offset + 22)?.try_into().ok()?) as usize;
+    let section_table = pe_offset + 24 + optional_header_size;
+    (0..sections as usize)
+        .filter_map(|index| {
+            let section = data.get(section_table + index * 40..section_table + (index + 1) * 40)?;
+            let raw_size = u32::from_le_bytes(section[16..20].try_into().ok()?) as usize;
+            let raw_offset = u32::from_le_bytes(section[20..24].try_into().ok()?) as usize;
+            Some(raw_offset + raw_size)
+        })
+        .max()
+}
+
+fn find_last(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+/// the files which start at a cluster boundary in `data`, as their offset in `data`,
+/// their format and their content. Files are only detected at cluster boundaries,
+/// because every file starts at the beginning of a cluster
+pub(crate) fn carve(data: &[u8], cluster_size: usize) -> Vec<(usize, CarvedFormat, &[u8])> {
+    (0..data.len())
+        .step_by(cluster_size.max(1))
+        .filter_map(|offset| {
+            let format = CarvedFormat::detect(&data[offset..])?;
+            let size = format.size(&data[offset..]);
+            Some((offset, format, &data[offset..offset + size]))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{carve, data_runs, CarvedFormat, NtfsVolume, NTFS_SIGNATURE};
+
+    fn boot_sector(bytes_per_sector: u16, sectors_per_cluster: u8, record_size: i8) -> Vec<u8> {
+        let mut boot = vec![0u8; 512];
+        boot[3..11].copy_from_slice(NTFS_SIGNATURE);
+        boot[0x0b..0x0d].copy_from_slice(&bytes_per_sector.to_le_bytes());
+        boot[0x0d] = sectors_per_cluster;
+        boot[0x28..0x30].copy_from_slice(&1024u64.to_le_bytes());
+        boot[0x30..0x38].copy_from_slice(&4u64.to_le_bytes());
+        boot[0x40] = record_size as u8;
+        boot
+    }
+
+    fn open(boot: &[u8]) -> anyhow::Result<NtfsVolume> {
+        let path = std::env::temp_dir().join(format!("dionysos-ntfs-{}", std::process::id()));
+        std::fs::File::create(&path).unwrap().write_all(boot).unwrap();
+        let volume = NtfsVolume::open(&mut std::fs::File::open(&path).unwrap(), 0);
+        std::fs::remove_file(&path).unwrap();
+        volume
+    }
+
+    #[test]
+    fn test_invalid_boot_sector() {
+        let error = |boot: Vec<u8>| open(&boot).err().unwrap().to_string();
+        assert!(error(boot_sector(0, 8, -10)).contains("sector size"));
+        assert!(error(boot_sector(500, 8, -10)).contains("sector size"));
+        assert!(error(boot_sector(512, 3, -10)).contains("cluster size"));
+        assert!(error(boot_sector(512, 0x90, -10)).contains("cluster size"));
+        assert!(error(boot_sector(512, 8, 0)).contains("MFT records"));
+        assert!(error(boot_sector(512, 8, -40)).contains("MFT records"));
+        assert!(error(boot_sector(512, 8, 100)).contains("MFT records"));
+    }
+
+    #[test]
+    fn test_overlapping_chunks() {
+        let volume = NtfsVolume {
+            offset: 0,
+            cluster_size: 4096,
+            clusters: 100,
+            mft_offset: 0,
+            record_size: 1024,
+            // clusters 8 to 63 and 72 to 79 are unallocated
+            bitmap: vec![0xff, 0, 0, 0, 0, 0, 0, 0, 0xff, 0, 0xff, 0xff, 0xff],
+        };
+        assert_eq!(
+            volume.unallocated_chunks(40),
+            vec![(8, 40, 0), (32, 32, 16), (72, 8, 0)]
+        );
+    }
+
+    #[test]
+    fn test_data_runs() {
+        let mut record = vec![0u8; 1024];
+        record[..4].copy_from_slice(b"FILE");
+        record[0x14] = 0x38;
+        let attribute = &mut record[0x38..];
+        attribute[..4].copy_from_slice(&0x80u32.to_le_bytes());
+        attribute[4..8].copy_from_slice(&0x60u32.to_le_bytes());
+        attribute[8] = 1;
+        attribute[0x20] = 0x40;
+        // 0x10 clusters at cluster 0x1000, 0x08 sparse clusters, 0x04 clusters at 0x0ff0
+        attribute[0x40..0x4b].copy_from_slice(&[
+            0x21, 0x10, 0x00, 0x10, 0x01, 0x08, 0x11, 0x04, 0xf0, 0x00, 0x00,
+        ]);
+        let end = 0x38 + 0x60;
+        record[end..end + 4].copy_from_slice(&0xffff_ffffu32.to_le_bytes());
+
+        assert_eq!(
+            data_runs(&record).unwrap(),
+            vec![(Some(0x1000), 0x10), (None, 0x08), (Some(0x0ff0), 0x04)]
+        );
+    }
+
+    #[test]
+    fn test_carve() {
+        let mut data = vec![0u8; 4096 * 3];
+        data[4096..4096 + 9].copy_from_slice(b"%PDF-1.7\n");
+        data[4096 + 100..4096 + 105].copy_from_slice(b"%%EOF");
+        data[8192..8196].copy_from_slice(b"PK\x03\x04");
+
+        let carved = carve(&data, 4096);
+        assert_eq!(carved.len(), 2);
+        assert_eq!((carved[0].0, carved[0].1), (4096, CarvedFormat::Pdf));
+        assert_eq!(carved[0].2.len(), 105);
+        assert_eq!((carved[1].0, carved[1].1), (8192, CarvedFormat::Zip));
+    }
+}
@@ -1,6 +1,12 @@
 use std::collections::HashMap;
+use std::path::Path;
 
+use anyhow::{anyhow, Result};
 use duplicate::duplicate_item;
+use filemagic::magic;
+
+#[cfg(target_family = "unix")]
+use file_owner::PathExt;
 
 
 #[derive(Default)]
@@ -41,6 +47,21 @@ impl YaraExternals {
         self
     }
 
+    /// the externals of `file`, which are required by some signature-base rules.
+    /// `filepath` is the path which is displayed, and `magic` the type of the file
+    pub fn of_file(file: &Path, filepath: String, magic: Option<&str>) -> Result<Self> {
+        let filename = file.file_name().unwrap_or_default().to_string_lossy();
+        Ok(Self::default()
+            .with_filepath(filepath)
+            .with_filename(filename.to_string())
+            .with_extension(match file.extension() {
+                Some(f) => f.to_string_lossy().to_string(),
+                None => "-".to_owned(),
+            })
+            .with_filetype(magic.unwrap_or("-").to_owned())
+            .with_owner(owner_of(file)?))
+    }
+
     pub fn dummy() -> Self {
         Self::default()
             .with_filename("-".to_owned())
@@ -50,4 +71,44 @@ impl YaraExternals {
             .with_md5("-".to_owned())
             .with_owner("dummy".to_owned())
     }
-}
\ No newline at end of file
+}
+
+/// the type of `file`, as determined by libmagic
+pub(crate) fn magic_of(file: &Path) -> Option<String> {
+    match magic!().unwrap().file(file) {
+        Ok(magic) => {
+            log::info!("treating '{}' as '{}'", file.display(), &magic);
+            Some(magic)
+        }
+        Err(why) => {
+            log::warn!(
+                "unable to determine file type for '{}': {}",
+                file.display(),
+                why
+            );
+            None
+        }
+    }
+}
+
+/// the name of the owner of `file`, or its id if the name is unknown
+#[cfg(target_family = "unix")]
+fn owner_of(file: &Path) -> Result<String> {
+    let owner = file
+        .display()
+        .to_string()
+        .owner()
+        .map_err(|why| anyhow!("unable to determine file owner: {:?}", why))?;
+    Ok(match owner.name() {
+        Ok(name) => name.unwrap_or_else(|| owner.id().to_string()),
+        Err(why) => {
+            log::warn!("unable to retrieve owner name: {:?}", why);
+            owner.id().to_string()
+        }
+    })
+}
+
+#[cfg(not(target_family = "unix"))]
+fn owner_of(_file: &Path) -> Result<String> {
+    Ok("-".to_owned())
+}
@@ -1,12 +1,20 @@
 use std::collections::HashSet;
 use std::fmt::Display;
+use std::fs::File;
+use std::path::Path;
 
+use maplit::hashset;
 use serde_json::{json, Value};
 
+use crate::attack::{self, ATTACK_METADATA};
 use crate::csv_line::CsvLine;
 use crate::scanner_result::ScannerFinding;
+use crate::severity::Severity;
 
-use super::yara_string::YaraString;
+use crate::string_options::{escape_vec, StringOptions};
+
+use super::yara_location::YaraLocation;
+use super::yara_string::{YaraMatch, YaraMatchContext, YaraString};
 
 pub struct YaraFinding {
     pub identifier: String,
@@ -16,11 +24,40 @@ pub struct YaraFinding {
     pub strings: Vec<YaraString>,
     pub value_data: Option<String>,
     pub contained_file: Option<String>,
+    pub location: Option<YaraLocation>,
+
+    /// additional information, e.g. about damaged files
+    pub note: Option<String>,
+
+    /// taken from the `severity` or `score` metadata of the rule
+    pub severity: Severity,
+
+    /// MITRE ATT&CK technique IDs, taken from the `attack` metadata and the tags of the rule
+    pub attack_techniques: Vec<String>,
     found_in_file: String,
 }
 
 impl YaraFinding {
     pub fn new(rule: yara::Rule, found_in_file: String) -> Self {
+        let severity = rule
+            .metadatas
+            .iter()
+            .find_map(|m| match (m.identifier, &m.value) {
+                ("severity", yara::MetadataValue::String(s)) => Severity::parse(s),
+                ("score", yara::MetadataValue::Integer(score)) => Some(Severity::from_score(*score)),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let attack_techniques = attack::parse_techniques(
+            rule.metadatas
+                .iter()
+                .filter_map(|m| match (m.identifier, &m.value) {
+                    (identifier, yara::MetadataValue::String(s))
+                        if ATTACK_METADATA.contains(&identifier) => Some(*s),
+                    _ => None,
+                })
+                .chain(rule.tags.iter().copied()),
+        );
         Self {
             identifier: rule.identifier.to_owned(),
             namespace: rule.namespace.to_owned(),
@@ -28,6 +65,45 @@ impl YaraFinding {
             strings: rule.strings.into_iter().map(|s| s.into()).collect(),
             value_data: None,
             contained_file: None,
+            location: None,
+            note: None,
+            severity,
+            attack_techniques,
+            found_in_file
+        }
+    }
+
+    #[cfg(feature = "yara_x")]
+    pub fn from_yara_x(rule: yara_x::Rule, found_in_file: String) -> Self {
+        let severity = rule
+            .metadata()
+            .find_map(|(identifier, value)| match (identifier, value) {
+                ("severity", yara_x::MetaValue::String(s)) => Severity::parse(s),
+                ("score", yara_x::MetaValue::Integer(score)) => Some(Severity::from_score(score)),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let attack_values: Vec<String> = rule
+            .metadata()
+            .filter_map(|(identifier, value)| match value {
+                yara_x::MetaValue::String(s) if ATTACK_METADATA.contains(&identifier) => {
+                    Some(s.to_owned())
+                }
+                _ => None,
+            })
+            .chain(rule.tags().map(|t| t.identifier().to_owned()))
+            .collect();
+        Self {
+            identifier: rule.identifier().to_owned(),
+            namespace: rule.namespace().to_owned(),
+            tags: rule.tags().map(|t| t.identifier().to_owned()).collect(),
+            strings: rule.patterns().map(|p| p.into()).collect(),
+            value_data: None,
+            contained_file: None,
+            location: None,
+            note: None,
+            severity,
+            attack_techniques: attack::parse_techniques(attack_values.iter().map(|v| &v[..])),
             found_in_file
         }
     }
@@ -41,47 +117,142 @@ impl YaraFinding {
         self.contained_file = Some(file.to_owned());
         self
     }
+
+    pub fn with_location(mut self, location: YaraLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.note = Some(note);
+        self
+    }
+
+    /// adds up to `context` bytes before and after every match, taken from `data`
+    pub fn with_context_from_slice(mut self, data: &[u8], context: usize) -> Self {
+        if context == 0 {
+            return self;
+        }
+        for m in self.strings.iter_mut().flat_map(|s| s.matches.iter_mut()) {
+            m.context = YaraMatchContext::from_slice(data, m, context);
+        }
+        self
+    }
+
+    /// moves the offsets of all matches by `base`, e.g. if the matches have been
+    /// found in a part of a disk image which has been read from `base`
+    pub fn with_base_offset(mut self, base: usize) -> Self {
+        for m in self.strings.iter_mut().flat_map(|s| s.matches.iter_mut()) {
+            m.offset += base;
+            if let Some(context) = m.context.as_mut() {
+                context.offset += base;
+            }
+        }
+        self
+    }
+
+    /// adds up to `context` bytes before and after every match, read from `path`
+    pub fn with_context_from_file(mut self, path: &Path, context: usize) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        for m in self.strings.iter_mut().flat_map(|s| s.matches.iter_mut()) {
+            m.context = Some(YaraMatchContext::from_reader(&mut file, m, context)?);
+        }
+        Ok(self)
+    }
+}
+
+impl YaraFinding {
+    /// returns all strings with their matches, limited to the maximum number
+    /// of matches configured in `options`, and the number of omitted matches
+    fn displayed_strings(&self, options: &StringOptions) -> (Vec<(&YaraString, &[YaraMatch])>, usize) {
+        let mut remaining = options.max_strings().unwrap_or(usize::MAX);
+        let mut omitted = 0;
+        let mut strings = Vec::new();
+        for s in self.strings.iter() {
+            if remaining == 0 {
+                omitted += s.matches.len().max(1);
+                continue;
+            }
+            let count = s.matches.len().min(remaining);
+            omitted += s.matches.len() - count;
+            remaining -= count.max(1);
+            strings.push((s, &s.matches[..count]));
+        }
+        (strings, omitted)
+    }
 }
 
 impl Display for YaraFinding {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Yara: {} {}", self.identifier, self.found_in_file())?;
+        writeln!(f, "Yara: {} {}", self.identifier, self.found_in_file())
+    }
+}
+
+impl ScannerFinding for YaraFinding {
+    fn format_readable(&self, options: &StringOptions) -> String {
+        let mut lines = vec![format!("Yara: {} {}", self.identifier, self.found_in_file())];
+        if let Some(location) = &self.location {
+            lines.push(format!("  in {}", location.describe()));
+        }
+        if let Some(note) = &self.note {
+            lines.push(format!("  note: {}", note));
+        }
 
-        if crate::display_strings() {
-            for s in self.strings.iter() {
-                if s.matches.is_empty() {
+        if options.print_strings() {
+            let (strings, omitted) = self.displayed_strings(options);
+            for (s, matches) in strings {
+                if matches.is_empty() {
                     match &self.value_data {
-                        None => writeln!(f, "  {} matches", s.identifier)?,
-                        Some(d) => writeln!(f, "  '{}' matches to {}", d,s.identifier)?,
+                        None => lines.push(format!("  {} matches", s.identifier)),
+                        Some(d) => lines.push(format!("  '{}' matches to {}", d,s.identifier)),
                     }
                 } else {
                     match &self.value_data {
-                        None => writeln!(f, "  {} has the following matches:", s.identifier)?,
-                        Some(d) => writeln!(f, "  {} has the following matches in '{}':", s.identifier, d)?
+                        None => lines.push(format!("  {} has the following matches:", s.identifier)),
+                        Some(d) => lines.push(format!("  {} has the following matches in '{}':", s.identifier, d)),
                     }
-                
-                    for m in s.matches.iter() {
-                        writeln!(f, "    0x{:08x}: {}", m.offset, escape_vec(&m.data))?;
+
+                    for m in matches.iter() {
+                        lines.push(format!("    0x{:08x}: {}", m.offset, options.render(&m.data)));
+                        if let Some(context) = &m.context {
+                            for line in hexdump(context.offset, &context.data) {
+                                lines.push(format!("      {}", line));
+                            }
+                        }
                     }
                 }
             }
+            if omitted > 0 {
+                lines.push(format!("  ... {} more matches omitted", omitted));
+            }
         }
-        Ok(())
+        lines.push(String::new());
+        lines.join("\n")
     }
-}
 
-impl ScannerFinding for YaraFinding {
-    fn format_csv(&self) -> HashSet<CsvLine> {
+    fn format_csv(&self, options: &StringOptions) -> HashSet<CsvLine> {
         let file = self.found_in_file();
         let mut lines = HashSet::new();
 
+        if let Some(location) = &self.location {
+            lines.insert(
+                CsvLine::new("Yara", &self.identifier, file, format!("in {}", location.describe()))
+            );
+        }
+        if let Some(note) = &self.note {
+            lines.insert(
+                CsvLine::new("Yara", &self.identifier, file, format!("note: {}", note))
+            );
+        }
+
         if self.strings.is_empty() {
             lines.insert(
                 CsvLine::new("Yara", &self.identifier, file, String::new())
             );
         } else {
-            for s in self.strings.iter() {
-                if s.matches.is_empty() {
+            let (strings, omitted) = self.displayed_strings(options);
+            for (s, matches) in strings {
+                if matches.is_empty() {
                     match &self.value_data {
                         None => {lines.insert(
                             CsvLine::new("Yara",&self.identifier,file,s.identifier.clone())
@@ -91,54 +262,169 @@ impl ScannerFinding for YaraFinding {
                         );}
                     }
                 } else {
-                    for m in s.matches.iter() {
+                    for m in matches.iter() {
+                        let context = match &m.context {
+                            None => String::new(),
+                            Some(c) => format!(" (context at offset {:x}: {})", c.offset, hex::encode(&c.data)),
+                        };
                         match &self.value_data {
                             None => {lines.insert(
                                 CsvLine::new("Yara",&self.identifier,file,
-                                format!("{} at offset {:x}: {}", s.identifier, m.offset, escape_vec(&m.data)))
+                                format!("{} at offset {:x}: {}{}", s.identifier, m.offset, options.render(&m.data), context))
                             );}
                             Some(d) => {lines.insert(
                                 CsvLine::new("Yara",&self.identifier,file,
-                                format!("{} at offset {:x}: {} in ({})", s.identifier, m.offset, escape_vec(&m.data), d))
+                                format!("{} at offset {:x}: {}{} in ({})", s.identifier, m.offset, options.render(&m.data), context, d))
                             );}
                         }
                     }
                 }
             }
+            if omitted > 0 {
+                lines.insert(
+                    CsvLine::new("Yara", &self.identifier, file, format!("{} more matches omitted", omitted))
+                );
+            }
         }
 
         lines
     }
-    fn to_json(&self) -> serde_json::Value {
+    fn to_json(&self, options: &StringOptions) -> serde_json::Value {
         let file = self.found_in_file();
+        let (strings, omitted) = self.displayed_strings(options);
         json!({
             "01_scanner": "yara",
             "02_suspicious_file": file,
             "03_value": self.value_data,
-            "04_strings": self.strings.iter().map(|s: &YaraString| {
+            "04_strings": strings.into_iter().map(|(s, matches)| {
                 json!({
                     "identifier": s.identifier,
-                    "matches": s.matches.iter().map(|m| json!({
+                    "matches": matches.iter().map(|m| json!({
                         "offset": m.offset,
-                        "data": escape_vec(&m.data)
+                        "data": options.render(&m.data),
+                        "context": m.context.as_ref().map(|c| json!({
+                            "offset": c.offset,
+                            "hex": hex::encode(&c.data),
+                            "ascii": escape_vec(&c.data)
+                        }))
                     })).collect::<Vec<Value>>()
                 })
             }).collect::<Vec<Value>>(),
-            "05_contained_file": self.contained_file
+            "05_contained_file": self.contained_file,
+            "06_omitted_matches": omitted,
+            "07_location": self.location.as_ref().map(|l| l.to_json()),
+            "08_note": self.note,
+            "09_rule": self.identifier
         })
     }
 
+    fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn scanner(&self) -> String {
+        "yara".to_owned()
+    }
+
     fn found_in_file(&self) -> &str {
         &self.found_in_file[..]
     }
+
+    fn rule(&self) -> Option<&str> {
+        Some(&self.identifier)
+    }
+
+    fn attack_techniques(&self) -> Vec<String> {
+        self.attack_techniques.clone()
+    }
+}
+
+/// an encrypted archive (or encrypted members of an archive) which could not be
+/// opened with any of the archive passwords, so that its content has not been scanned
+pub struct EncryptedContainerFinding {
+    found_in_file: String,
+
+    /// the number of members which could not be decrypted, or `None` if the
+    /// whole archive (including the list of its members) is encrypted
+    locked_files: Option<usize>,
+    tried_passwords: usize,
 }
 
+impl EncryptedContainerFinding {
+    pub fn new(found_in_file: String, locked_files: Option<usize>, tried_passwords: usize) -> Self {
+        Self {
+            found_in_file,
+            locked_files,
+            tried_passwords,
+        }
+    }
 
-pub fn escape_vec(v: &[u8]) -> String {
-    v.iter()
-    .map(|b| {let c = char::from(*b); if c.is_ascii_graphic() {
-        c.to_string() } else {
-            format!("\\{:02x}", b)
+    fn reason(&self) -> String {
+        match self.locked_files {
+            Some(locked_files) => format!(
+                "{} encrypted file(s) could not be opened with {} password(s)",
+                locked_files, self.tried_passwords
+            ),
+            None => format!(
+                "could not be opened with {} password(s)",
+                self.tried_passwords
+            ),
         }
-    }).collect::<Vec<String>>().join("")
-}
\ No newline at end of file
+    }
+}
+
+impl Display for EncryptedContainerFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Yara: encrypted container could not be opened ({}) {}",
+            self.reason(),
+            self.found_in_file()
+        )
+    }
+}
+
+impl ScannerFinding for EncryptedContainerFinding {
+    fn format_csv(&self, _options: &StringOptions) -> HashSet<CsvLine> {
+        hashset![CsvLine::new(
+            "Yara",
+            "encrypted container",
+            self.found_in_file(),
+            self.reason()
+        )]
+    }
+
+    fn to_json(&self, _options: &StringOptions) -> serde_json::Value {
+        json!({
+            "01_scanner": "yara",
+            "02_suspicious_file": self.found_in_file(),
+            "03_incomplete": true,
+            "04_reason": self.reason()
+        })
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Medium
+    }
+
+    fn scanner(&self) -> String {
+        "yara".to_owned()
+    }
+
+    fn found_in_file(&self) -> &str {
+        &self.found_in_file[..]
+    }
+}
+
+
+/// formats `data` like `hexdump -C`, 16 bytes per line
+pub fn hexdump(offset: usize, data: &[u8]) -> Vec<String> {
+    data.chunks(16).enumerate().map(|(idx, chunk)| {
+        let hex = chunk.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(" ");
+        let ascii: String = chunk.iter().map(|b| {
+            let c = char::from(*b);
+            if c.is_ascii_graphic() || c == ' ' { c } else { '.' }
+        }).collect();
+        format!("{:08x}  {:<47}  |{}|", offset + idx * 16, hex, ascii)
+    }).collect()
+}
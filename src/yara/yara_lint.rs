@@ -0,0 +1,116 @@
+use std::ffi::{CStr, CString};
+use std::fmt::Display;
+use std::os::raw::{c_char, c_int, c_void};
+
+use anyhow::{anyhow, Result};
+
+use super::yara_externals::YaraExternals;
+
+/// The `yara` crate discards all compiler warnings if compilation succeeds,
+/// so we use a separate `libyara` compiler to collect all messages
+/// with their line numbers.
+#[derive(PartialEq, Eq)]
+pub(crate) enum YaraCompileLevel {
+    Error,
+    Warning,
+}
+
+pub(crate) struct YaraCompileMessage {
+    pub level: YaraCompileLevel,
+    pub source: String,
+    pub line: i32,
+    pub message: String,
+}
+
+impl Display for YaraCompileMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self.level {
+            YaraCompileLevel::Error => "error",
+            YaraCompileLevel::Warning => "warning",
+        };
+        write!(f, "{}:{}: {}: {}", self.source, self.line, level, self.message)
+    }
+}
+
+struct Messages {
+    source: String,
+    messages: Vec<YaraCompileMessage>,
+}
+
+unsafe extern "C" fn collect_message(
+    error_level: c_int,
+    _file_name: *const c_char,
+    line_number: c_int,
+    _rule: *const yara_sys::YR_RULE,
+    message: *const c_char,
+    user_data: *mut c_void,
+) {
+    let messages = &mut *(user_data as *mut Messages);
+    let level = if error_level as u32 == yara_sys::YARA_ERROR_LEVEL_WARNING {
+        YaraCompileLevel::Warning
+    } else {
+        YaraCompileLevel::Error
+    };
+    let message = if message.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(message).to_string_lossy().to_string()
+    };
+    let source = messages.source.clone();
+    messages.messages.push(YaraCompileMessage {
+        level,
+        source,
+        line: line_number,
+        message,
+    });
+}
+
+/// compiles `rules` with a throw-away compiler and returns all errors and
+/// warnings reported by `libyara`. `libyara` must already be initialized,
+/// which is the case as long as a `yara::Compiler` exists.
+pub(crate) fn lint_rules(source: &str, rules: &str) -> Result<Vec<YaraCompileMessage>> {
+    let rules = CString::new(rules)?;
+    let mut messages = Messages {
+        source: source.to_owned(),
+        messages: Vec::new(),
+    };
+
+    let mut compiler: *mut yara_sys::YR_COMPILER = std::ptr::null_mut();
+    if unsafe { yara_sys::yr_compiler_create(&mut compiler) } != 0 || compiler.is_null() {
+        return Err(anyhow!("unable to create yara compiler"));
+    }
+
+    let mut result = Ok(());
+    for (identifier, value) in YaraExternals::dummy().to_hashmap() {
+        let identifier = CString::new(identifier)?;
+        let value = CString::new(value)?;
+        if unsafe {
+            yara_sys::yr_compiler_define_string_variable(
+                compiler,
+                identifier.as_ptr(),
+                value.as_ptr(),
+            )
+        } != 0
+        {
+            result = Err(anyhow!(
+                "unable to define external yara variable '{}'",
+                identifier.to_string_lossy()
+            ));
+            break;
+        }
+    }
+
+    if result.is_ok() {
+        unsafe {
+            yara_sys::yr_compiler_set_callback(
+                compiler,
+                Some(collect_message),
+                &mut messages as *mut Messages as *mut c_void,
+            );
+            yara_sys::yr_compiler_add_string(compiler, rules.as_ptr(), std::ptr::null());
+        }
+    }
+
+    unsafe { yara_sys::yr_compiler_destroy(compiler) };
+    result.map(|_| messages.messages)
+}
@@ -0,0 +1,137 @@
+use serde_json::{json, Value};
+
+/// location of a yara match inside a structured file, which
+/// is more precise than the path of the file alone
+pub enum YaraLocation {
+    /// a value inside a registry hive
+    Registry {
+        /// path of the key, relative to the root of the hive
+        key_path: String,
+        value_name: String,
+        value_type: &'static str,
+
+        /// last write time of the key (RFC 3339)
+        last_written: String,
+    },
+
+    /// a record inside an evtx file
+    EvtxRecord {
+        record_id: u64,
+        event_id: Option<u64>,
+        channel: Option<String>,
+        provider: Option<String>,
+
+        /// timestamp of the record (RFC 3339)
+        timestamp: String,
+    },
+
+    /// a record of a table inside an ESE database
+    EseRecord { table: String, record_index: usize },
+
+    /// a run of unallocated clusters of a disk image, starting at `offset` of the image
+    Unallocated { offset: u64, length: u64 },
+
+    /// a file which has been carved from unallocated space at `offset` of the image
+    Carved {
+        format: &'static str,
+        offset: u64,
+        length: u64,
+    },
+}
+
+impl YaraLocation {
+    /// a short, human readable description of the location
+    pub fn describe(&self) -> String {
+        match self {
+            YaraLocation::Registry {
+                key_path,
+                value_name,
+                value_type,
+                last_written,
+            } => format!(
+                "{}\\@{} ({}, key last written at {})",
+                key_path, value_name, value_type, last_written
+            ),
+            YaraLocation::EvtxRecord {
+                record_id,
+                event_id,
+                channel,
+                provider,
+                timestamp,
+            } => format!(
+                "record {} (event id {}, channel {}, provider {}, written at {})",
+                record_id,
+                event_id.map_or_else(|| "-".to_owned(), |id| id.to_string()),
+                channel.as_deref().unwrap_or("-"),
+                provider.as_deref().unwrap_or("-"),
+                timestamp
+            ),
+            YaraLocation::EseRecord {
+                table,
+                record_index,
+            } => format!("record {} of table '{}'", record_index, table),
+            YaraLocation::Unallocated { offset, length } => format!(
+                "unallocated space at 0x{:x}-0x{:x}",
+                offset,
+                offset + length
+            ),
+            YaraLocation::Carved {
+                format,
+                offset,
+                length,
+            } => format!(
+                "{} file ({} bytes) carved from unallocated space at 0x{:x}",
+                format, length, offset
+            ),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            YaraLocation::Registry {
+                key_path,
+                value_name,
+                value_type,
+                last_written,
+            } => json!({
+                "key_path": key_path,
+                "value_name": value_name,
+                "value_type": value_type,
+                "last_written": last_written
+            }),
+            YaraLocation::EvtxRecord {
+                record_id,
+                event_id,
+                channel,
+                provider,
+                timestamp,
+            } => json!({
+                "record_id": record_id,
+                "event_id": event_id,
+                "channel": channel,
+                "provider": provider,
+                "timestamp": timestamp
+            }),
+            YaraLocation::EseRecord {
+                table,
+                record_index,
+            } => json!({
+                "table": table,
+                "record_index": record_index
+            }),
+            YaraLocation::Unallocated { offset, length } => json!({
+                "offset": offset,
+                "length": length
+            }),
+            YaraLocation::Carved {
+                format,
+                offset,
+                length,
+            } => json!({
+                "format": format,
+                "offset": offset,
+                "length": length
+            }),
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use walkdir::WalkDir;
+
+/// describes where the yara rules are read from and how they are compiled.
+/// This can be a single file, a zip file or a directory.
+#[derive(Clone)]
+pub(crate) struct YaraRuleset {
+    path: PathBuf,
+    strict: bool,
+    zip_password: Option<String>,
+}
+
+impl YaraRuleset {
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            strict: false,
+            zip_password: None,
+        }
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn with_zip_password(mut self, zip_password: Option<String>) -> Self {
+        self.zip_password = zip_password;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// returns the most recent modification time of all files in this ruleset,
+    /// which is used to detect changes of the ruleset
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        WalkDir::new(&self.path)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.metadata().ok())
+            .filter_map(|m| m.modified().ok())
+            .max()
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// checks if the rules use one of the externals which depend on the path of a file, and
+    /// not on its content: `filename`, `filepath`, `extension` or `owner`. Rulesets which
+    /// cannot be read are assumed to use them
+    pub fn uses_path_externals(&self) -> bool {
+        let externals = regex::Regex::new(r"\b(filename|filepath|extension|owner)\b").unwrap();
+        match self.read_sources() {
+            Ok(sources) => sources.iter().any(|(_, rule)| externals.is_match(rule)),
+            Err(_) => true,
+        }
+    }
+
+    /// reads all yara rules of this ruleset. Returns pairs of source name and rule text.
+    pub fn read_sources(&self) -> Result<Vec<(String, String)>> {
+        let path = &self.path;
+        let mut rules_str = Vec::new();
+        let metadata = std::fs::metadata(path)?;
+        if metadata.is_file() {
+            if Self::points_to_zip_file(path)? {
+                self.add_rules_from_zip(&mut rules_str, path)?;
+            } else if Self::points_to_yara_file(path)? {
+                Self::add_rules_from_yara(&mut rules_str, path)?;
+            } else {
+                log::warn!(
+                    "file '{}' is neither a yara nor a zip file; I'll ignore it",
+                    path.display()
+                );
+            }
+        } else {
+            Self::add_rules_from_directory(&mut rules_str, path)?;
+        }
+        Ok(rules_str)
+    }
+
+    fn add_rules_from_yara<P>(rules: &mut Vec<(String, String)>, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        Self::add_rules_from_stream(rules, &path, &mut BufReader::new(File::open(&path)?))
+    }
+
+    fn add_rules_from_stream<P, R>(
+        rules: &mut Vec<(String, String)>,
+        path: P,
+        stream: &mut R,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+        R: std::io::Read,
+    {
+        log::trace!("parsing yara file: '{}'", path.as_ref().display());
+        let mut yara_content = String::new();
+        stream.read_to_string(&mut yara_content)?;
+
+        rules.push((path.as_ref().display().to_string(), yara_content));
+
+        Ok(())
+    }
+
+    fn add_rules_from_zip<P>(&self, rules: &mut Vec<(String, String)>, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let zip_file = BufReader::new(File::open(&path)?);
+        let mut zip = zip::ZipArchive::new(zip_file)?;
+        for i in 0..zip.len() {
+            let mut file = match &self.zip_password {
+                None => zip.by_index(i)?,
+                Some(password) => match zip.by_index_decrypt(i, password.as_bytes()) {
+                    Ok(file) => file,
+                    Err(zip::result::ZipError::InvalidPassword) => {
+                        return Err(anyhow!(
+                            "invalid password for yara ruleset '{}'",
+                            path.as_ref().display()
+                        ))
+                    }
+                    Err(why) => return Err(why.into()),
+                },
+            };
+            if file.is_file() {
+                match file.enclosed_name() {
+                    Some(file_path) => match file_path.to_str() {
+                        Some(name) => {
+                            if Self::is_yara_filename(name) {
+                                Self::add_rules_from_stream(rules, &file_path, &mut file)?;
+                            }
+                        }
+                        None => {
+                            log::warn!(
+                                "found no enclosed name for {}, ignoring that file",
+                                file.name()
+                            );
+                        }
+                    },
+                    None => {
+                        log::warn!(
+                            "found no enclosed name for {}, ignoring that file",
+                            file.name()
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_rules_from_directory<P>(rules: &mut Vec<(String, String)>, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if Self::points_to_yara_file(&path)? {
+                Self::add_rules_from_yara(rules, path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn points_to_yara_file<P>(path: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = match path.as_ref().file_name().and_then(|v| v.to_str()) {
+            Some(v) => v,
+            None => return Err(anyhow!("unable to read filename")),
+        };
+        Ok(Self::is_yara_filename(filename))
+    }
+
+    fn is_yara_filename(filename: &str) -> bool {
+        let lc_filename = filename.to_lowercase();
+        lc_filename.ends_with(".yar") || lc_filename.ends_with(".yara")
+    }
+
+    fn points_to_zip_file<P>(path: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = match path.as_ref().file_name().and_then(|v| v.to_str()) {
+            Some(v) => v,
+            None => return Err(anyhow!("unable to read filename")),
+        };
+        Ok(Self::is_zip_filename(filename))
+    }
+
+    fn is_zip_filename(filename: &str) -> bool {
+        let lc_filename = filename.to_lowercase();
+        lc_filename.ends_with(".zip")
+    }
+}
@@ -1,10 +1,9 @@
 use crate::filescanner::*;
 use crate::scanner_result;
 use crate::scanner_result::*;
-use crate::yara::yara_finding::YaraFinding;
+use crate::yara::yara_finding::{EncryptedContainerFinding, YaraFinding};
 use anyhow::{anyhow, Result};
 use bzip2::read::BzDecoder;
-use filemagic::magic;
 use flate2::read::GzDecoder;
 use nt_hive2::Hive;
 use nt_hive2::HiveParseMode;
@@ -13,27 +12,79 @@ use std::fmt::Display;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::Read;
-use std::path::Path;
+use std::io::Seek;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
 use walkdir::DirEntry;
-use walkdir::WalkDir;
 use xz::read::XzDecoder;
 
 #[cfg(feature = "scan_evtx")]
 use serde_json::Value;
 
-#[cfg(target_family = "unix")]
-use file_owner::PathExt;
+use super::installer::{self, InstallerKind};
+use super::split_archive::{SplitArchive, VolumeReader, RAR_SIGNATURE};
+use super::yara_externals::{magic_of, YaraExternals};
+use super::yara_lint::{self, YaraCompileLevel};
+use super::unallocated::{self, NtfsVolume};
+use super::yara_location::YaraLocation;
+use super::yara_ruleset::YaraRuleset;
+use super::yara_statistics::RuleStatisticsCollector;
+#[cfg(feature = "scan_evtx")]
+use super::evtx_filter::{self, EvtxFilter};
+#[cfg(feature = "scan_ese")]
+use super::ese_reader;
+use crate::path_string::{path_to_string, user_friendly_path};
+use crate::scan_job::{ScanCancelled, ScanHandle};
+use crate::time_window::TimeWindow;
+
+const MISSING_MODULE_HINT: &str = "the ruleset requires a yara module which is not available \
+in your libyara. Make sure that libyara has been built with the pe, elf, math, hash (--with-crypto) \
+and dotnet (--enable-dotnet) modules";
+
+const EVTX_SIGNATURE: &[u8; 8] = b"ElfFile\0";
+const REG_SIGNATURE: &[u8; 4] = b"regf";
+
+/// signature of ESE databases, which is stored at offset 4
+const ESE_SIGNATURE: &[u8; 4] = &[0xef, 0xcd, 0xab, 0x89];
 
-use super::yara_externals::YaraExternals;
+const ZIP_SIGNATURE: &[u8; 4] = b"PK\x03\x04";
+const SEVENZIP_SIGNATURE: &[u8; 6] = b"7z\xbc\xaf\x27\x1c";
+
+/// passwords which are tried for encrypted archives, in addition to the passwords of
+/// `--archive-passwords`. Malware samples are usually shared in archives which are
+/// encrypted with one of these
+const DEFAULT_ARCHIVE_PASSWORDS: &[&str] = &["infected", "malware", "virus"];
 
 pub struct YaraScanner {
-    rules: yara::Rules,
+    rules: RwLock<Arc<yara::Rules>>,
+    ruleset: YaraRuleset,
+
+    /// the rules use externals like `filename`, so that their matches depend on the path
+    path_externals: AtomicBool,
     scan_compressed: bool,
     timeout: u16,
     buffer_size: usize,
     scan_evtx: bool,
+    #[cfg(feature = "scan_evtx")]
+    evtx_filter: EvtxFilter,
     scan_reg: bool,
+    registry_window: TimeWindow,
+    scan_ese: bool,
+    force_raw: bool,
+
+    /// scan the unallocated space of disk images instead of the images themselves
+    scan_unallocated: bool,
+    timed_out_files: Mutex<Vec<String>>,
+    string_context: usize,
+    statistics: Option<RuleStatisticsCollector>,
+
+    /// passwords which are tried to decrypt encrypted archives
+    archive_passwords: Vec<String>,
+
+    /// aborts scans which consist of multiple parts, e.g. of archives and event logs
+    handle: Option<ScanHandle>,
 }
 
 #[derive(Debug)]
@@ -42,8 +93,15 @@ enum FileType {
     BZip2,
     XZ,
     Zip,
+    SevenZip,
+    SplitArchive(SplitArchive),
+
+    /// a disk image or a raw device, with the offsets of its NTFS volumes
+    DiskImage(Vec<u64>),
+    Installer(InstallerKind),
     Evtx,
     Reg,
+    Ese,
     Uncompressed,
 }
 
@@ -55,50 +113,25 @@ impl Display for YaraScanner {
 
 impl FileScanner for YaraScanner {
     fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        if let Err(why) = self.check_cancelled() {
+            return vec![Err(why)];
+        }
         let mut results = Vec::new();
         let file = file.path();
 
-        let magic = match magic!().unwrap().file(file) {
-            Ok(magic) => {
-                log::info!("treating '{}' as '{}'", file.display(), &magic);
-                Some(magic)
-            }
-            Err(why) => {
-                log::warn!(
-                    "unable to determine file type for '{}': {}",
-                    file.display(),
-                    why
-                );
-                None
-            }
-        };
+        let magic = magic_of(file);
 
         // prepare externals, which are required by some signature-base rules
-        let mut externals = YaraExternals::default()
-            .with_filepath(file.display().to_string())
-            .with_filename(file.file_name().unwrap().to_str().unwrap().to_string())
-            .with_extension(match file.extension() {
-                Some(f) => f.to_string_lossy().to_string(),
-                None => "-".to_owned(),
-            })
-            .with_filetype(magic.clone().unwrap_or_else(|| "-".to_owned()));
-
-        externals = if cfg!(target_family = "unix") {
-            externals.with_owner(match file.display().to_string().owner() {
-                Ok(owner) => match owner.name() {
-                    Ok(name) => name.unwrap_or_else(|| owner.id().to_string()),
-                    Err(why) => {
-                        log::warn!("unable to retrieve owner name: {:?}", why);
-                        owner.id().to_string()
-                    }
-                },
-                Err(why) => return vec![Err(anyhow!("unable to determine file owner: {:?}", why))],
-            })
-        } else {
-            externals.with_owner("-".to_owned())
+        let filepath = user_friendly_path(file).display().to_string();
+        let externals = match YaraExternals::of_file(file, filepath, magic.as_deref()) {
+            Ok(externals) => externals,
+            Err(why) => return vec![Err(why)],
         };
 
-        let mut scanner = match self.rules.scanner() {
+        // keep a reference to the current rules, so that a reload does not
+        // affect this scan
+        let rules = Arc::clone(&self.rules.read().unwrap());
+        let mut scanner = match rules.scanner() {
             Err(why) => return vec![Err(anyhow!("unable to create yara scanner: {:?}", why))],
             Ok(scanner) => scanner,
         };
@@ -117,29 +150,73 @@ impl FileScanner for YaraScanner {
         // check if the file is a compressed file and must be decompressed before scanning
         let file_type = self.get_filetype(magic, file);
 
+        // encrypted archives which could not be opened with any of the passwords
+        let mut locked = Vec::new();
+
+        // files which could only be scanned partially
+        let mut incomplete = Vec::new();
+
+        let begin = Instant::now();
         let scan_result = match file_type {
             FileType::GZip => self.scan_compressed(
                 &mut scanner,
                 GzDecoder::new(File::open(file).unwrap()),
-                &file.display().to_string(),
+                &path_to_string(file),
             ),
 
             FileType::BZip2 => self.scan_compressed(
                 &mut scanner,
                 BzDecoder::new(File::open(file).unwrap()),
-                &file.display().to_string(),
+                &path_to_string(file),
             ),
 
             FileType::XZ => self.scan_compressed(
                 &mut scanner,
                 XzDecoder::new(File::open(file).unwrap()),
-                &file.display().to_string(),
+                &path_to_string(file),
+            ),
+
+            FileType::Zip => self.scan_zip_archive(
+                &mut scanner,
+                File::open(file).unwrap(),
+                &path_to_string(file),
+                &mut locked,
+            ),
+
+            FileType::SevenZip => self.scan_7z_archive(
+                &mut scanner,
+                &[file.to_owned()],
+                &path_to_string(file),
+                &mut locked,
             ),
 
-            FileType::Zip => {
-                self.scan_zip_archive(scanner, File::open(file).unwrap(), &file.to_string_lossy())
+            FileType::SplitArchive(SplitArchive::First(volumes)) => {
+                self.scan_split_archive(&mut scanner, &volumes, &mut locked, &mut incomplete)
+            }
+
+            FileType::SplitArchive(SplitArchive::FirstRar(volumes)) => {
+                self.scan_rar_volumes(&mut scanner, file, &volumes, &mut locked)
+            }
+
+            FileType::SplitArchive(SplitArchive::Later(volumes, index)) => {
+                match self.is_scanned_with_first_volume(&volumes, index) {
+                    Ok(true) => {
+                        log::info!(
+                            "'{}' is scanned together with the first volume '{}'",
+                            file.display(),
+                            volumes[0].display()
+                        );
+                        Ok(Vec::new())
+                    }
+                    Ok(false) => self.scan_file(&mut scanner, file),
+                    Err(why) => Err(why),
+                }
             }
 
+            FileType::DiskImage(volumes) => self.scan_unallocated(&mut scanner, file, &volumes),
+
+            FileType::Installer(kind) => self.scan_installer(&mut scanner, kind, file),
+
             FileType::Evtx => {
                 #[cfg(feature = "scan_evtx")]
                 if self.scan_evtx {
@@ -149,12 +226,12 @@ impl FileScanner for YaraScanner {
                 }
 
                 #[cfg(not(feature = "scan_evtx"))]
-                scanner.scan_file(&file).or_else(|e| Err(anyhow!(e)))
+                self.scan_file(&mut scanner, file)
             }
 
             FileType::Reg => {
                 #[cfg(feature = "scan_reg")]
-                if self.scan_evtx && matches!(file_type, FileType::Evtx) {
+                if self.scan_reg {
                     let hive_file = File::open(file).unwrap();
                     let hive = match Hive::new(hive_file, HiveParseMode::NormalWithBaseBlock) {
                         Ok(hive) => hive,
@@ -167,7 +244,7 @@ impl FileScanner for YaraScanner {
                             file.display()
                         );
 
-                        self.scan_reg(&mut scanner, hive, &file.to_string_lossy())
+                        self.scan_reg(&mut scanner, hive, &path_to_string(file))
                     } else {
                         log::trace!(
                             "'{}' is no primary hive file, using the normal yara scanner",
@@ -180,12 +257,47 @@ impl FileScanner for YaraScanner {
                 }
 
                 #[cfg(not(feature = "scan_reg"))]
-                scanner.scan_file(&file).or_else(|e| Err(anyhow!(e)))
+                self.scan_file(&mut scanner, file)
+            }
+
+            FileType::Ese => {
+                #[cfg(feature = "scan_ese")]
+                if self.scan_ese {
+                    self.scan_ese(&mut scanner, file)
+                } else {
+                    self.scan_file(&mut scanner, file)
+                }
+
+                #[cfg(not(feature = "scan_ese"))]
+                self.scan_file(&mut scanner, file)
             }
             FileType::Uncompressed => self.scan_file(&mut scanner, file),
         };
 
+        results.extend(
+            locked
+                .into_iter()
+                .map(|finding| Ok(Box::new(finding) as Box<dyn ScannerFinding>)),
+        );
+        results.extend(incomplete.into_iter().map(Err));
         match scan_result {
+            Err(why) if Self::is_timeout(&why) => {
+                log::warn!(
+                    "yara scan of '{}' has been aborted after {}s",
+                    file.display(),
+                    self.timeout
+                );
+                self.timed_out_files
+                    .lock()
+                    .unwrap()
+                    .push(path_to_string(file));
+                results.push(Err(anyhow!(
+                    "the yara scan of '{}' has been aborted after {}s (--yara-timeout)",
+                    file.display(),
+                    self.timeout
+                )));
+            }
+            Err(why) if why.is::<ScanCancelled>() => results.push(Err(why)),
             Err(why) => {
                 results.push(Err(anyhow!(
                     "yara scan error with '{}': {}",
@@ -194,6 +306,9 @@ impl FileScanner for YaraScanner {
                 )));
             }
             Ok(res) => {
+                if let Some(statistics) = &self.statistics {
+                    statistics.add_findings(&res, Instant::now().duration_since(begin));
+                }
                 results.extend(res.into_iter().map(|r| {
                     log::trace!(
                         "new yara finding: {} in '{}'",
@@ -207,47 +322,140 @@ impl FileScanner for YaraScanner {
 
         results
     }
-}
 
-impl YaraScanner {
-    pub fn new<P>(path: P) -> Result<Self>
-    where
-        P: AsRef<Path>,
-    {
-        let mut rules_str = Vec::new();
-        let metadata = std::fs::metadata(&path)?;
-        if metadata.is_file() {
-            if Self::points_to_zip_file(&path)? {
-                Self::add_rules_from_zip(&mut rules_str, &path)?;
-            } else if Self::points_to_yara_file(&path)? {
-                Self::add_rules_from_yara(&mut rules_str, path)?;
-            } else {
-                log::warn!(
-                    "file '{}' is neither a yara nor a zip file; I'll ignore it",
-                    path.as_ref().display()
-                );
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let rules = Arc::clone(&self.rules.read().unwrap());
+        let mut scanner = match rules.scanner() {
+            Err(why) => return vec![Err(anyhow!("unable to create yara scanner: {:?}", why))],
+            Ok(scanner) => scanner,
+        };
+        scanner.set_timeout(self.timeout.into());
+
+        let externals = YaraExternals::dummy()
+            .with_filename(name.to_owned())
+            .with_filepath(name.to_owned());
+        for entry in externals.to_hashmap() {
+            if let Err(why) = scanner.define_variable(entry.0, entry.1) {
+                return vec![Err(anyhow!(
+                    "unable to define external yara variable '{}': {:?}",
+                    entry.0,
+                    why
+                ))];
             }
-        } else {
-            Self::add_rules_from_directory(&mut rules_str, path)?;
         }
 
-        let mut compiler = yara::Compiler::new()?;
-        for entry in YaraExternals::dummy().to_hashmap() {
-            compiler.define_variable(entry.0, entry.1)?;
+        match self.scan_mem(&mut scanner, data) {
+            Err(why) if why.is::<ScanCancelled>() => vec![Err(why)],
+            Err(why) => vec![Err(anyhow!("yara scan error with '{}': {}", name, why))],
+            Ok(results) => results
+                .into_iter()
+                .map(|r| {
+                    Ok(Box::new(
+                        YaraFinding::new(r, name.to_owned())
+                            .with_context_from_slice(data, self.string_context),
+                    ) as Box<dyn ScannerFinding>)
+                })
+                .collect(),
         }
-        for rule in rules_str.into_iter() {
-            compiler = compiler.add_rules_str(&rule)?;
+    }
+
+    fn depends_on_path(&self) -> bool {
+        self.path_externals.load(Ordering::Relaxed)
+    }
+
+    fn reload(&self) -> Result<()> {
+        let rules = Self::compile(&self.ruleset)?;
+        *self.rules.write().unwrap() = Arc::new(rules);
+        self.path_externals
+            .store(self.ruleset.uses_path_externals(), Ordering::Relaxed);
+        log::info!("reloaded yara rules from '{}'", self.ruleset.path().display());
+        Ok(())
+    }
+
+    fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(statistics) = &self.statistics {
+            lines.extend(statistics.summary());
+        }
+
+        let timed_out_files = self.timed_out_files.lock().unwrap();
+        if !timed_out_files.is_empty() {
+            lines.push(format!(
+                "yara scan incomplete for {} file(s) because of the timeout of {}s:",
+                timed_out_files.len(),
+                self.timeout
+            ));
+            lines.extend(timed_out_files.iter().map(|f| format!("  {}", f)));
         }
+        lines
+    }
+}
 
-        Ok(Self {
-            rules: compiler.compile_rules()?,
+impl YaraScanner {
+    pub fn new(ruleset: &YaraRuleset) -> Result<Self> {
+        Ok(Self::from_rules(ruleset, Arc::new(Self::compile(ruleset)?)))
+    }
+
+    /// uses `rules`, which have already been compiled from `ruleset`
+    pub(crate) fn from_rules(ruleset: &YaraRuleset, rules: Arc<yara::Rules>) -> Self {
+        Self {
+            rules: RwLock::new(rules),
+            ruleset: ruleset.clone(),
+            path_externals: AtomicBool::new(ruleset.uses_path_externals()),
             scan_compressed: false,
             timeout: 240,
             buffer_size: 128,
 
             scan_evtx: false,
+            #[cfg(feature = "scan_evtx")]
+            evtx_filter: EvtxFilter::default(),
             scan_reg: false,
-        })
+            registry_window: TimeWindow::default(),
+            scan_ese: false,
+            force_raw: false,
+            scan_unallocated: false,
+            timed_out_files: Mutex::new(Vec::new()),
+            string_context: 0,
+            statistics: None,
+            archive_passwords: DEFAULT_ARCHIVE_PASSWORDS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            handle: None,
+        }
+    }
+
+    pub(crate) fn compile(ruleset: &YaraRuleset) -> Result<yara::Rules> {
+        let rules_str = ruleset.read_sources()?;
+
+        let mut compiler = yara::Compiler::new()?;
+        for entry in YaraExternals::dummy().to_hashmap() {
+            compiler.define_variable(entry.0, entry.1)?;
+        }
+
+        Self::check_rules(&rules_str, ruleset.strict())?;
+
+        for (_source, rule) in rules_str.into_iter() {
+            compiler = compiler.add_rules_str(&rule)?;
+        }
+        Ok(compiler.compile_rules()?)
+    }
+
+    /// libyara does not tell us which rule was being evaluated when the
+    /// timeout triggered, so we can only report the affected file
+    fn is_timeout(why: &anyhow::Error) -> bool {
+        let yara_error = match why.downcast_ref::<yara::Error>() {
+            Some(yara::Error::Yara(yara_error)) => Some(yara_error),
+            Some(_) => None,
+            None => why.downcast_ref::<yara::YaraError>(),
+        };
+        matches!(
+            yara_error,
+            Some(yara::YaraError {
+                kind: yara::YaraErrorKind::ScanTimeout,
+                ..
+            })
+        )
     }
 
     pub fn with_scan_compressed(mut self, scan_compressed: bool) -> Self {
@@ -255,6 +463,17 @@ impl YaraScanner {
         self
     }
 
+    /// tries `passwords` (in addition to the default passwords, like `infected`)
+    /// to decrypt encrypted zip and 7z archives
+    pub fn with_archive_passwords(mut self, passwords: Vec<String>) -> Self {
+        for password in passwords {
+            if !self.archive_passwords.contains(&password) {
+                self.archive_passwords.push(password);
+            }
+        }
+        self
+    }
+
     pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
         self.buffer_size = buffer_size;
         self
@@ -265,119 +484,144 @@ impl YaraScanner {
         self
     }
 
-    #[cfg(feature = "scan_reg")]
-    pub fn with_scan_reg(mut self, scan_reg: bool) -> Self {
-        self.scan_reg = scan_reg;
+    /// scan evtx files and registry hives as raw files, even if
+    /// they should be parsed
+    pub fn with_force_raw(mut self, force_raw: bool) -> Self {
+        self.force_raw = force_raw;
         self
     }
 
-    #[cfg(feature = "scan_evtx")]
-    pub fn with_scan_evtx(mut self, scan_evtx: bool) -> Self {
-        self.scan_evtx = scan_evtx;
+    /// scans only the unallocated clusters of disk images and raw devices with NTFS
+    /// volumes, and the files which are carved from them
+    pub fn with_scan_unallocated(mut self, scan_unallocated: bool) -> Self {
+        self.scan_unallocated = scan_unallocated;
         self
     }
 
-    fn add_rules_from_yara<P>(rules: &mut Vec<String>, path: P) -> Result<()>
-    where
-        P: AsRef<Path>,
-    {
-        Self::add_rules_from_stream(rules, &path, &mut BufReader::new(File::open(&path)?))
+    /// number of bytes before and after every string match which will be reported
+    pub fn with_string_context(mut self, string_context: usize) -> Self {
+        self.string_context = string_context;
+        self
     }
 
-    fn add_rules_from_stream<P, R>(rules: &mut Vec<String>, path: P, stream: &mut R) -> Result<()>
-    where
-        P: AsRef<Path>,
-        R: std::io::Read,
-    {
-        log::trace!("parsing yara file: '{}'", path.as_ref().display());
-        let mut yara_content = String::new();
-        stream.read_to_string(&mut yara_content)?;
-
-        rules.push(yara_content);
+    /// collect per-rule statistics, which are displayed in the summary
+    pub fn with_rule_statistics(mut self, rule_statistics: bool) -> Self {
+        self.statistics = rule_statistics.then(RuleStatisticsCollector::default);
+        self
+    }
 
-        Ok(())
+    /// aborts the scans of this scanner when `handle` is cancelled. libyara checks for
+    /// the cancellation whenever it has evaluated a rule
+    pub fn with_scan_handle(mut self, handle: ScanHandle) -> Self {
+        self.handle = Some(handle);
+        self
     }
 
-    fn add_rules_from_zip<P>(rules: &mut Vec<String>, path: P) -> Result<()>
-    where
-        P: AsRef<Path>,
-    {
-        let zip_file = BufReader::new(File::open(&path)?);
-        let mut zip = zip::ZipArchive::new(zip_file)?;
-        for i in 0..zip.len() {
-            let mut file = zip.by_index(i)?;
-            if file.is_file() {
-                match file.enclosed_name() {
-                    Some(file_path) => match file_path.to_str() {
-                        Some(name) => {
-                            if Self::is_yara_filename(name) {
-                                // create PathBuf to let rust release all immutable borrows of `file`
-                                let file_path = file_path.to_path_buf();
-                                Self::add_rules_from_stream(rules, &file_path, &mut file)?;
-                            }
-                        }
-                        None => {
-                            log::warn!(
-                                "found no enclosed name for {}, ignoring that file",
-                                file.name()
-                            );
-                        }
-                    },
-                    None => {
-                        log::warn!(
-                            "found no enclosed name for {}, ignoring that file",
-                            file.name()
-                        );
-                    }
-                }
-            }
+    /// returns [`ScanCancelled`] if the scan has been cancelled
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.handle {
+            Some(handle) => handle.check(),
+            None => Ok(()),
         }
-        Ok(())
     }
 
-    fn add_rules_from_directory<P>(rules: &mut Vec<String>, path: P) -> Result<()>
-    where
-        P: AsRef<Path>,
-    {
-        for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-            let path = entry.path();
-            if Self::points_to_yara_file(&path)? {
-                Self::add_rules_from_yara(rules, path)?;
-            }
+    /// like [`yara::Scanner::scan_mem`], but libyara aborts the scan when it is cancelled
+    fn scan_mem<'r>(
+        &self,
+        scanner: &mut yara::Scanner<'r>,
+        data: &[u8],
+    ) -> Result<Vec<yara::Rule<'r>>> {
+        let mut rules = Vec::new();
+        scanner.scan_mem_callback(data, |message| self.collect_rule(message, &mut rules))?;
+        self.check_cancelled()?;
+        Ok(rules)
+    }
+
+    /// the callback of libyara, which collects the matching rules. libyara calls it
+    /// while evaluating the rules, so that an aborted scan ends before the timeout
+    fn collect_rule<'r>(
+        &self,
+        message: yara::CallbackMsg<'r>,
+        rules: &mut Vec<yara::Rule<'r>>,
+    ) -> yara::CallbackReturn {
+        if let yara::CallbackMsg::RuleMatching(rule) = message {
+            rules.push(rule);
+        }
+        match &self.handle {
+            Some(handle) if handle.is_cancelled() => yara::CallbackReturn::Abort,
+            _ => yara::CallbackReturn::Continue,
         }
-        Ok(())
     }
 
-    fn points_to_yara_file<P>(path: P) -> Result<bool>
-    where
-        P: AsRef<Path>,
-    {
-        let filename = match path.as_ref().file_name().and_then(|v| v.to_str()) {
-            Some(v) => v,
-            None => return Err(anyhow!("unable to read filename")),
-        };
-        Ok(Self::is_yara_filename(filename))
+    #[cfg(feature = "scan_reg")]
+    pub fn with_scan_reg(mut self, scan_reg: bool) -> Self {
+        self.scan_reg = scan_reg;
+        self
     }
 
-    fn is_yara_filename(filename: &str) -> bool {
-        let lc_filename = filename.to_lowercase();
-        lc_filename.ends_with(".yar") || lc_filename.ends_with(".yara")
+    /// only scan the values of registry keys which have been written within `window`
+    #[cfg(feature = "scan_reg")]
+    pub fn with_registry_window(mut self, window: TimeWindow) -> Self {
+        self.registry_window = window;
+        self
     }
 
-    fn points_to_zip_file<P>(path: P) -> Result<bool>
-    where
-        P: AsRef<Path>,
-    {
-        let filename = match path.as_ref().file_name().and_then(|v| v.to_str()) {
-            Some(v) => v,
-            None => return Err(anyhow!("unable to read filename")),
-        };
-        Ok(Self::is_zip_filename(filename))
+    #[cfg(feature = "scan_ese")]
+    pub fn with_scan_ese(mut self, scan_ese: bool) -> Self {
+        self.scan_ese = scan_ese;
+        self
+    }
+
+    #[cfg(feature = "scan_evtx")]
+    pub fn with_scan_evtx(mut self, scan_evtx: bool) -> Self {
+        self.scan_evtx = scan_evtx;
+        self
+    }
+
+    /// only scan the evtx records which match `filter`
+    #[cfg(feature = "scan_evtx")]
+    pub fn with_evtx_filter(mut self, filter: EvtxFilter) -> Self {
+        self.evtx_filter = filter;
+        self
     }
 
-    fn is_zip_filename(filename: &str) -> bool {
-        let lc_filename = filename.to_lowercase();
-        lc_filename.ends_with(".zip")
+    /// compiles every rule source separately and reports all errors and
+    /// warnings with their source file and line. Errors always abort, warnings
+    /// only abort in `strict` mode.
+    fn check_rules(rules: &[(String, String)], strict: bool) -> Result<()> {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        for (source, rule) in rules.iter() {
+            for message in yara_lint::lint_rules(source, rule)? {
+                match message.level {
+                    YaraCompileLevel::Error => errors.push(message),
+                    YaraCompileLevel::Warning => warnings.push(message),
+                }
+            }
+        }
+
+        for warning in warnings.iter() {
+            log::warn!("{}", warning);
+        }
+
+        if !errors.is_empty() || (strict && !warnings.is_empty()) {
+            let mut report = errors
+                .iter()
+                .chain(warnings.iter())
+                .map(|m| format!("  {}", m))
+                .collect::<Vec<String>>()
+                .join("\n");
+            if errors.iter().any(|e| e.message.starts_with("unknown module")) {
+                report.push_str(&format!("\n{}", MISSING_MODULE_HINT));
+            }
+            return Err(anyhow!(
+                "compilation of the yara ruleset produced {} error(s) and {} warning(s):\n{}",
+                errors.len(),
+                warnings.len(),
+                report
+            ));
+        }
+        Ok(())
     }
 
     fn read_into_buffer<R: Read>(&self, reader: R) -> std::io::Result<(usize, Vec<u8>)> {
@@ -411,27 +655,109 @@ impl YaraScanner {
         file: &Path,
     ) -> anyhow::Result<Vec<YaraFinding>> {
         log::trace!("scanning for IOCs inside evtx file '{}'", file.display());
-        let filename = file.display().to_string();
+        let filename = path_to_string(file);
 
         let mut results = Vec::new();
+        let mut corrupt_records = 0;
         let mut parser = evtx::EvtxParser::from_path(file)?;
+
+        // the parser continues with the next chunk if a chunk is damaged,
+        // so we can scan all records which can be recovered
         for result in parser.records_json_value() {
             match result {
-                Err(why) => return Err(why.into()),
+                Err(why) => {
+                    log::debug!("skipping corrupt evtx data in '{}': {}", filename, why);
+                    corrupt_records += 1;
+                }
                 Ok(record) => {
-                    let res = Self::scan_json(scanner, &record.data, &filename)?;
-                    results.extend(
-                        res.into_iter()
-                            .map(|yr| yr.with_value_data(record.data.to_string())),
-                    );
+                    self.check_cancelled()?;
+                    if !self.evtx_filter.matches(&record.data, &record.timestamp) {
+                        continue;
+                    }
+
+                    let res = self.scan_json(scanner, &record.data, &filename)?;
+                    if res.is_empty() {
+                        continue;
+                    }
+
+                    let system = &record.data["Event"]["System"];
+                    let event_id = evtx_filter::event_id(system);
+                    let location = || YaraLocation::EvtxRecord {
+                        record_id: record.event_record_id,
+                        event_id,
+                        channel: system["Channel"].as_str().map(str::to_owned),
+                        provider: system["Provider"]["#attributes"]["Name"]
+                            .as_str()
+                            .map(str::to_owned),
+                        timestamp: record.timestamp.to_rfc3339(),
+                    };
+                    results.extend(res.into_iter().map(|yr| {
+                        yr.with_value_data(record.data.to_string())
+                            .with_location(location())
+                    }));
                 }
             }
         }
+
+        if corrupt_records > 0 {
+            log::warn!(
+                "'{}' is damaged, {} records or chunks could not be recovered",
+                filename,
+                corrupt_records
+            );
+            let note = format!(
+                "partially recovered, {} records or chunks could not be parsed",
+                corrupt_records
+            );
+            results = results
+                .into_iter()
+                .map(|r| r.with_note(note.clone()))
+                .collect();
+        }
+        Ok(results)
+    }
+
+    #[cfg(feature = "scan_ese")]
+    fn scan_ese<'a>(
+        &self,
+        scanner: &'a mut yara::Scanner,
+        file: &Path,
+    ) -> anyhow::Result<Vec<YaraFinding>> {
+        log::trace!("scanning for IOCs inside ESE database '{}'", file.display());
+        let filename = path_to_string(file);
+
+        let mut results = Vec::new();
+        let corrupt_records = ese_reader::read_records(file, |table, index, record| {
+            self.check_cancelled()?;
+            let data = record.to_string();
+            results.extend(self.scan_string(scanner, &data, &filename)?.into_iter().map(|r| {
+                r.with_value_data(data.clone())
+                    .with_location(YaraLocation::EseRecord {
+                        table: table.to_owned(),
+                        record_index: index,
+                    })
+            }));
+            Ok(())
+        })?;
+
+        if corrupt_records > 0 {
+            log::warn!(
+                "'{}' is damaged, {} records could not be read",
+                filename,
+                corrupt_records
+            );
+            let note = format!("partially recovered, {} records could not be read", corrupt_records);
+            results = results
+                .into_iter()
+                .map(|r| r.with_note(note.clone()))
+                .collect();
+        }
         Ok(results)
     }
 
     #[cfg(feature = "scan_evtx")]
     fn scan_json<'a>(
+        &self,
         scanner: &'a mut yara::Scanner,
         val: &Value,
         filename: &str,
@@ -442,37 +768,36 @@ impl YaraScanner {
             Value::Bool(_) => Ok(vec![]),
             Value::Number(_) => Ok(vec![]),
             Value::String(s) => {
-                results.extend(Self::scan_string(scanner, s, filename)?);
+                results.extend(self.scan_string(scanner, s, filename)?);
                 Ok(results)
             }
             Value::Array(a) => {
                 for v in a.iter() {
-                    results.extend(Self::scan_json(scanner, v, filename)?);
+                    results.extend(self.scan_json(scanner, v, filename)?);
                 }
                 Ok(results)
             }
             Value::Object(o) => {
                 for (_n, v) in o.iter() {
-                    results.extend(Self::scan_json(scanner, v, filename)?);
+                    results.extend(self.scan_json(scanner, v, filename)?);
                 }
                 Ok(results)
             }
         }
     }
 
-    #[cfg(feature = "scan_evtx")]
+    #[cfg(any(feature = "scan_evtx", feature = "scan_reg", feature = "scan_ese"))]
     fn scan_string<'a>(
+        &self,
         scanner: &'a mut yara::Scanner,
-        s: &String,
+        s: &str,
         filename: &str,
-    ) -> Result<Vec<YaraFinding>, yara::YaraError> {
-        match scanner.scan_mem(s.as_bytes()) {
-            Err(why) => Err(why),
-            Ok(r) => Ok(r
-                .into_iter()
-                .map(|r| YaraFinding::new(r, filename.to_string()))
-                .collect()),
-        }
+    ) -> anyhow::Result<Vec<YaraFinding>> {
+        Ok(self
+            .scan_mem(scanner, s.as_bytes())?
+            .into_iter()
+            .map(|r| YaraFinding::new(r, filename.to_string()))
+            .collect())
     }
 
     #[cfg(feature = "scan_reg")]
@@ -484,55 +809,81 @@ impl YaraScanner {
     ) -> anyhow::Result<Vec<YaraFinding>> {
         let root_key = hive.root_key_node()?;
 
-        match Self::scan_key(scanner, &mut hive, &root_key, String::new(), filename) {
+        match self.scan_key(scanner, &mut hive, &root_key, String::new(), filename) {
             Err(why) => Err(why),
             Ok(results) => Ok(results),
         }
     }
 
+    #[cfg(feature = "scan_reg")]
     fn scan_key<'a>(
+        &self,
         scanner: &'a mut yara::Scanner,
         hive: &mut Hive<File>,
         key: &KeyNode,
         path: String,
         filename: &str,
     ) -> anyhow::Result<Vec<YaraFinding>> {
+        self.check_cancelled()?;
+        let window = &self.registry_window;
         let mut results = Vec::new();
-        for v in key.values() {
-            match v.value() {
-                nt_hive2::RegistryValue::RegSZ(s)
-                | nt_hive2::RegistryValue::RegExpandSZ(s)
-                | nt_hive2::RegistryValue::RegResourceList(s)
-                | nt_hive2::RegistryValue::RegFullResourceDescriptor(s)
-                | nt_hive2::RegistryValue::RegResourceRequirementsList(s) => {
-                    results.extend(
-                        Self::scan_string(scanner, s, filename)?
-                            .into_iter()
-                            .map(|r| r.with_value_data(Self::key_display(&path, v.name(), s))),
-                    );
+        let last_written = key.timestamp().to_rfc3339();
+
+        // the last write time of a key does not change if one of its subkeys
+        // is modified, so we must always descend into the subkeys
+        let values: &[_] = if window.contains(key.timestamp()) {
+            key.values()
+        } else {
+            log::trace!("skipping the values of '{}', which is outside of the time window", path);
+            &[]
+        };
+        let location = |value_name: &str, value_type: &'static str| YaraLocation::Registry {
+            key_path: path.clone(),
+            value_name: value_name.to_owned(),
+            value_type,
+            last_written: last_written.clone(),
+        };
+
+        for v in values {
+            let (value_type, data): (&'static str, Vec<&str>) = match v.value() {
+                nt_hive2::RegistryValue::RegSZ(s) => ("REG_SZ", vec![s.as_str()]),
+                nt_hive2::RegistryValue::RegExpandSZ(s) => ("REG_EXPAND_SZ", vec![s.as_str()]),
+                nt_hive2::RegistryValue::RegResourceList(s) => ("REG_RESOURCE_LIST", vec![s.as_str()]),
+                nt_hive2::RegistryValue::RegFullResourceDescriptor(s) => {
+                    ("REG_FULL_RESOURCE_DESCRIPTOR", vec![s.as_str()])
+                }
+                nt_hive2::RegistryValue::RegResourceRequirementsList(s) => {
+                    ("REG_RESOURCE_REQUIREMENTS_LIST", vec![s.as_str()])
+                }
+                nt_hive2::RegistryValue::RegMultiSZ(sl) => {
+                    ("REG_MULTI_SZ", sl.iter().map(String::as_str).collect())
                 }
                 nt_hive2::RegistryValue::RegBinary(b) => {
-                    results.extend(scanner.scan_mem(&b[..])?.into_iter().map(|r| {
+                    results.extend(self.scan_mem(scanner, &b[..])?.into_iter().map(|r| {
                         YaraFinding::new(r, filename.to_string())
                             .with_value_data(Self::key_display(&path, v.name(), "<binary data>"))
-                    }))
-                }
-                nt_hive2::RegistryValue::RegMultiSZ(sl) => {
-                    for s in sl {
-                        results.extend(
-                            Self::scan_string(scanner, s, filename)?
-                                .into_iter()
-                                .map(|r| r.with_value_data(Self::key_display(&path, v.name(), s))),
-                        );
-                    }
+                            .with_location(location(v.name(), "REG_BINARY"))
+                    }));
+                    continue;
                 }
-                _ => (),
+                _ => continue,
+            };
+
+            for s in data {
+                results.extend(
+                    self.scan_string(scanner, s, filename)?
+                        .into_iter()
+                        .map(|r| {
+                            r.with_value_data(Self::key_display(&path, v.name(), s))
+                                .with_location(location(v.name(), value_type))
+                        }),
+                );
             }
         }
 
         for subkey in key.subkeys(hive)?.iter() {
-            let subkey_path = format!("{}/{}", path, subkey.borrow().name());
-            results.extend(Self::scan_key(
+            let subkey_path = format!("{}\\{}", path, subkey.borrow().name());
+            results.extend(self.scan_key(
                 scanner,
                 hive,
                 &subkey.borrow(),
@@ -544,25 +895,57 @@ impl YaraScanner {
         Ok(results)
     }
 
+    #[cfg(feature = "scan_reg")]
     fn key_display(path: &str, attr_name: &str, attr_value: &str) -> String {
-        format!("{}/@{} = '{}'", path, attr_name, attr_value)
+        format!("{}\\@{} = '{}'", path, attr_name, attr_value)
+    }
+
+    /// detects evtx files, registry hives and ESE databases by their signature, so that
+    /// renamed or carved files are recognized as well
+    fn detect_signature(file: &Path) -> Option<FileType> {
+        let mut header = [0u8; 8];
+        File::open(file).ok()?.read_exact(&mut header).ok()?;
+        if &header == EVTX_SIGNATURE {
+            Some(FileType::Evtx)
+        } else if header.starts_with(REG_SIGNATURE) {
+            Some(FileType::Reg)
+        } else if &header[4..] == ESE_SIGNATURE {
+            Some(FileType::Ese)
+        } else {
+            None
+        }
     }
 
     fn get_filetype(&self, magic: Option<String>, file: &Path) -> FileType {
+        if !self.force_raw && (self.scan_evtx || self.scan_reg || self.scan_ese) {
+            if let Some(file_type) = Self::detect_signature(file) {
+                return file_type;
+            }
+        }
+
+        if self.scan_unallocated {
+            let volumes = unallocated::ntfs_volumes(file);
+            if !volumes.is_empty() {
+                return FileType::DiskImage(volumes);
+            }
+        }
+
         let file_type = if self.scan_compressed {
-            if let Some(m) = &magic {
+            if let Some(split_archive) = SplitArchive::of(file) {
+                FileType::SplitArchive(split_archive)
+            } else if let Some(m) = &magic {
                 if m == "XZ compressed data" {
                     FileType::XZ
                 } else if m.starts_with("gzip compressed data") {
                     FileType::GZip
                 } else if m.starts_with("bzip2 compressed data") {
                     FileType::BZip2
-                } else if m.starts_with("MS Windows Vista Event Log,") {
-                    FileType::Evtx
-                } else if m.starts_with("MS Windows registry file, NT/2000 or above") {
-                    FileType::Reg
                 } else if m.starts_with("Zip archive data") {
                     FileType::Zip
+                } else if m.starts_with("7-zip archive data") {
+                    FileType::SevenZip
+                } else if let Some(kind) = InstallerKind::detect(m, file) {
+                    FileType::Installer(kind)
                 } else {
                     if m.contains("compressed data") {
                         log::warn!("unknown compression format: '{}', file will be handled without decompression", m);
@@ -575,14 +958,8 @@ impl YaraScanner {
         } else if let Some(m) = &magic {
             if m.contains("compressed data") || m.contains("archive data") {
                 log::warn!("'{}' contains compressed data, but it will not be decompressed before the scan. Consider using the '-C' flag", file.display());
-                FileType::Uncompressed
-            } else if m.starts_with("MS Windows Vista Event Log,") {
-                FileType::Evtx
-            } else if m.starts_with("MS Windows registry file, NT/2000 or above") {
-                FileType::Reg
-            } else {
-                FileType::Uncompressed
             }
+            FileType::Uncompressed
         } else {
             FileType::Uncompressed
         };
@@ -594,14 +971,23 @@ impl YaraScanner {
         scanner: &mut yara::Scanner<'_>,
         file: &Path,
     ) -> anyhow::Result<Vec<YaraFinding>> {
-        let filename = file.display().to_string();
-
-        match scanner.scan_file(file) {
-            Err(why) => Err(why.into()),
-            Ok(results) => Ok(results
+        let filename = path_to_string(file);
+
+        let mut rules = Vec::new();
+        scanner.scan_file_callback(file, |message| self.collect_rule(message, &mut rules))?;
+        self.check_cancelled()?;
+        let results: Vec<YaraFinding> = rules
+            .into_iter()
+            .map(|r| YaraFinding::new(r, filename.clone()))
+            .collect();
+
+        if self.string_context == 0 {
+            Ok(results)
+        } else {
+            results
                 .into_iter()
-                .map(|r| YaraFinding::new(r, filename.clone()))
-                .collect()),
+                .map(|r| Ok(r.with_context_from_file(file, self.string_context)?))
+                .collect()
         }
     }
 
@@ -612,7 +998,16 @@ impl YaraScanner {
         file_display_name: &str,
     ) -> anyhow::Result<Vec<YaraFinding>> {
         let (bytes, buffer) = self.read_into_buffer(reader)?;
+        self.scan_decompressed(scanner, bytes, buffer, file_display_name)
+    }
 
+    fn scan_decompressed(
+        &self,
+        scanner: &mut yara::Scanner,
+        bytes: usize,
+        buffer: Vec<u8>,
+        file_display_name: &str,
+    ) -> anyhow::Result<Vec<YaraFinding>> {
         if bytes == buffer.capacity() {
             log::warn!("file '{file_display_name}' could not be decompressed completely")
         } else {
@@ -620,41 +1015,658 @@ impl YaraScanner {
             log::info!("uncompressed {bytes} bytes from '{file_display_name}'");
         }
 
-        match scanner.scan_mem(&buffer) {
-            Err(why) => Err(why.into()),
+        match self.scan_mem(scanner, &buffer) {
+            Err(why) => Err(why),
             Ok(results) => Ok(results
                 .into_iter()
-                .map(|r| YaraFinding::new(r, file_display_name.to_owned()))
+                .map(|r| {
+                    YaraFinding::new(r, file_display_name.to_owned())
+                        .with_context_from_slice(&buffer, self.string_context)
+                })
                 .collect()),
         }
     }
 
-    fn scan_zip_archive(
+    /// scans the unallocated clusters of the NTFS `volumes` of a disk image, and the PE,
+    /// ZIP and PDF files which are carved from them. The clusters are scanned in chunks
+    /// of the size of the decompression buffer, which overlap, and the offsets of all
+    /// matches are relative to the beginning of the image. Matches which lie completely
+    /// in the overlap have already been reported with the previous chunk
+    fn scan_unallocated(
+        &self,
+        scanner: &mut yara::Scanner,
+        file: &Path,
+        volumes: &[u64],
+    ) -> anyhow::Result<Vec<YaraFinding>> {
+        let filename = path_to_string(file);
+        let mut image = File::open(file)?;
+        let mut results = Vec::new();
+
+        for volume_offset in volumes {
+            let volume = NtfsVolume::open(&mut image, *volume_offset).map_err(|why| {
+                anyhow!("unable to read the NTFS volume at offset {}: {}", volume_offset, why)
+            })?;
+            let max_clusters = (1024 * 1024 * self.buffer_size as u64 / volume.cluster_size).max(1);
+            log::info!(
+                "scanning the unallocated space of the NTFS volume at offset {} of '{}'",
+                volume_offset,
+                filename
+            );
+
+            for (cluster, count, overlap) in volume.unallocated_chunks(max_clusters) {
+                self.check_cancelled()?;
+                let offset = volume.offset + cluster * volume.cluster_size;
+                let overlap = (overlap * volume.cluster_size) as usize;
+                let buffer = volume.read_clusters(&mut image, cluster, count)?;
+                let rules = self.scan_mem(scanner, &buffer)?.into_iter().filter(|r| {
+                    overlap == 0
+                        || r.strings
+                            .iter()
+                            .flat_map(|s| s.matches.iter())
+                            .any(|m| m.offset + m.length > overlap)
+                });
+                results.extend(rules.map(|r| {
+                    YaraFinding::new(r, filename.clone())
+                        .with_context_from_slice(&buffer, self.string_context)
+                        .with_base_offset(offset as usize)
+                        .with_location(YaraLocation::Unallocated {
+                            offset,
+                            length: buffer.len() as u64,
+                        })
+                }));
+
+                // rules which check the header of a file only match carved files
+                for (carved_offset, format, data) in
+                    unallocated::carve(&buffer, volume.cluster_size as usize)
+                        .into_iter()
+                        .filter(|(carved_offset, _, _)| *carved_offset >= overlap)
+                {
+                    let offset = offset + carved_offset as u64;
+                    results.extend(self.scan_mem(scanner, data)?.into_iter().map(|r| {
+                        YaraFinding::new(r, filename.clone())
+                            .with_context_from_slice(data, self.string_context)
+                            .with_base_offset(offset as usize)
+                            .with_location(YaraLocation::Carved {
+                                format: format.name(),
+                                offset,
+                                length: data.len() as u64,
+                            })
+                    }));
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// scans an installer and the payloads which are extracted from it
+    fn scan_installer(
+        &self,
+        scanner: &mut yara::Scanner,
+        kind: InstallerKind,
+        file: &Path,
+    ) -> anyhow::Result<Vec<YaraFinding>> {
+        let mut results = self.scan_file(scanner, file)?;
+        let installer_name = path_to_string(file);
+        let limit = 1024 * 1024 * self.buffer_size;
+
+        let mut scan_payload = |path: &str, data: &[u8]| -> Result<()> {
+            self.check_cancelled()?;
+            let display_name = format!("{installer_name}:{path}");
+            let filename = path.rsplit(':').next().unwrap_or(path);
+            scanner.define_variable("filename", filename)?;
+            log::info!("scanning '{display_name}'");
+            results.extend(self.scan_mem(scanner, data)?.into_iter().map(|r| {
+                YaraFinding::new(r, display_name.clone())
+                    .with_context_from_slice(data, self.string_context)
+                    .with_contained_file(path)
+            }));
+            Ok(())
+        };
+        installer::extract_payloads(kind, File::open(file)?, limit, &mut scan_payload)?;
+        Ok(results)
+    }
+
+    fn scan_zip_archive<R: Read + Seek>(
         &self,
-        mut scanner: yara::Scanner,
-        reader: File,
+        scanner: &mut yara::Scanner,
+        reader: R,
         zip_name: &str,
+        locked: &mut Vec<EncryptedContainerFinding>,
     ) -> anyhow::Result<Vec<YaraFinding>> {
         let mut results = Vec::new();
 
+        // the password which has decrypted the last encrypted file, which is tried first
+        let mut last_password = None;
+        let mut locked_files = 0;
+
         if let Ok(mut zip) = zip::ZipArchive::new(reader) {
             for i in 0..zip.len() {
-                let file = zip.by_index(i)?;
-                if file.is_file() {
-                    let filename = file.name().to_owned();
-                    let display_name = format!("{zip_name}:{filename}");
-                    scanner.define_variable("filename", &filename[..])?;
-
-                    match self.scan_compressed(&mut scanner, file, &display_name) {
-                        Ok(res) => {
-                            results
-                                .extend(res.into_iter().map(|r| r.with_contained_file(&filename)));
+                self.check_cancelled()?;
+                let (is_file, encrypted, filename) = {
+                    let file = zip.by_index_raw(i)?;
+                    (file.is_file(), file.encrypted(), file.name().to_owned())
+                };
+                if !is_file {
+                    continue;
+                }
+                let display_name = format!("{zip_name}:{filename}");
+                scanner.define_variable("filename", &filename[..])?;
+
+                let res = if encrypted {
+                    match self.decrypt_zip_file(&mut zip, i, &mut last_password)? {
+                        Some((bytes, buffer)) => {
+                            self.scan_decompressed(scanner, bytes, buffer, &display_name)?
+                        }
+                        None => {
+                            log::warn!("unable to decrypt '{display_name}'");
+                            locked_files += 1;
+                            continue;
                         }
-                        Err(why) => return Err(why),
                     }
-                }
+                } else {
+                    self.scan_compressed(scanner, zip.by_index(i)?, &display_name)?
+                };
+                results.extend(res.into_iter().map(|r| r.with_contained_file(&filename)));
             }
         }
+        if locked_files > 0 {
+            locked.push(EncryptedContainerFinding::new(
+                zip_name.to_owned(),
+                Some(locked_files),
+                self.archive_passwords.len(),
+            ));
+        }
         Ok(results)
     }
+
+    /// decrypts and decompresses the `index`th file of `zip` with the archive passwords.
+    /// Returns `None` if none of them is correct
+    fn decrypt_zip_file<R: Read + Seek>(
+        &self,
+        zip: &mut zip::ZipArchive<R>,
+        index: usize,
+        last_password: &mut Option<usize>,
+    ) -> anyhow::Result<Option<(usize, Vec<u8>)>> {
+        let first = *last_password;
+        let candidates = first
+            .into_iter()
+            .chain((0..self.archive_passwords.len()).filter(|p| Some(*p) != first));
+        for candidate in candidates {
+            let password = self.archive_passwords[candidate].as_bytes();
+            let mut file = match zip.by_index_decrypt(index, password) {
+                Ok(file) => file,
+                Err(zip::result::ZipError::InvalidPassword) => continue,
+                Err(why) => return Err(why.into()),
+            };
+
+            // ZipCrypto checks the password with a single byte, so that one of 256 wrong
+            // passwords is accepted. The CRC of the file is checked when all of it has been
+            // read, so that the rest of a file which does not fit into the buffer is read
+            // as well. Otherwise, the garbage of a stored file would be scanned
+            let decompressed = self.read_into_buffer(&mut file).and_then(|decompressed| {
+                std::io::copy(&mut file, &mut std::io::sink())?;
+                Ok(decompressed)
+            });
+            match decompressed {
+                Ok(decompressed) => {
+                    *last_password = Some(candidate);
+                    return Ok(Some(decompressed));
+                }
+                Err(why) => log::debug!("decrypting with a wrong password failed: {}", why),
+            }
+        }
+        Ok(None)
+    }
+
+    /// scans the volumes of a split archive, which are concatenated to the archive. Split
+    /// files which are no zip or 7z archives, or which cannot be opened as such, are
+    /// scanned as a single file
+    fn scan_split_archive(
+        &self,
+        scanner: &mut yara::Scanner,
+        volumes: &[PathBuf],
+        locked: &mut Vec<EncryptedContainerFinding>,
+        incomplete: &mut Vec<anyhow::Error>,
+    ) -> anyhow::Result<Vec<YaraFinding>> {
+        let archive_name = path_to_string(&volumes[0]);
+        log::info!(
+            "scanning the {} volumes of '{}' as a single file",
+            volumes.len(),
+            archive_name
+        );
+        let reader = VolumeReader::open(volumes)?;
+        match Self::split_archive_type(volumes)? {
+            Some(FileType::Zip) => self.scan_zip_archive(scanner, reader, &archive_name, locked),
+            Some(_) => self.scan_7z_archive(scanner, volumes, &archive_name, locked),
+            None => {
+                let limit = 1024 * 1024 * self.buffer_size as u64;
+                if reader.len() > limit {
+                    incomplete.push(anyhow!(
+                        "only the first {} of {} bytes of the split archive '{}' have been scanned",
+                        limit,
+                        reader.len(),
+                        archive_name
+                    ));
+                }
+                self.scan_compressed(scanner, reader, &archive_name)
+            }
+        }
+    }
+
+    /// `FileType::Zip` or `FileType::SevenZip` if the volumes form a zip or 7z archive
+    /// which can be opened, so that all volumes are read when the archive is extracted
+    fn split_archive_type(volumes: &[PathBuf]) -> anyhow::Result<Option<FileType>> {
+        let mut reader = VolumeReader::open(volumes)?;
+        let mut signature = [0u8; 6];
+        if reader.read_exact(&mut signature).is_err() {
+            return Ok(None);
+        }
+        reader.rewind()?;
+
+        if signature.starts_with(ZIP_SIGNATURE) {
+            Ok(zip::ZipArchive::new(reader).ok().map(|_| FileType::Zip))
+        } else if &signature == SEVENZIP_SIGNATURE {
+            let len = reader.len();
+            match sevenz_rust::SevenZReader::new(reader, len, "".into()) {
+                Ok(_)
+                | Err(sevenz_rust::Error::PasswordRequired)
+                | Err(sevenz_rust::Error::MaybeBadPassword(_)) => Ok(Some(FileType::SevenZip)),
+                Err(_) => Ok(None),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// checks if the `index`th volume is scanned when the first volume is scanned. This
+    /// is not the case if the volumes do not form an archive which can be extracted, and
+    /// the volume is beyond the part of the concatenated volumes which fits into the
+    /// buffer, or if RAR archives are not extracted. Such a volume is scanned on its own,
+    /// so that a decoy first volume cannot hide it
+    fn is_scanned_with_first_volume(
+        &self,
+        volumes: &[PathBuf],
+        index: usize,
+    ) -> anyhow::Result<bool> {
+        let is_rar = volumes[0]
+            .extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("rar"));
+        if is_rar {
+            return Ok(cfg!(feature = "scan_rar")
+                && Self::is_rar_volume(&volumes[0])?
+                && Self::is_rar_volume(&volumes[index])?);
+        }
+        if Self::split_archive_type(volumes)?.is_some() {
+            return Ok(true);
+        }
+        let mut end = 0;
+        for volume in &volumes[..=index] {
+            end += volume.metadata()?.len();
+        }
+        Ok(end <= 1024 * 1024 * self.buffer_size as u64)
+    }
+
+    fn is_rar_volume(volume: &Path) -> std::io::Result<bool> {
+        let mut signature = [0u8; 6];
+        match File::open(volume)?.read_exact(&mut signature) {
+            Ok(()) => Ok(&signature == RAR_SIGNATURE),
+            Err(why) if why.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(why) => Err(why),
+        }
+    }
+
+    /// scans the files of a RAR archive, which has been split into `volumes`. RAR
+    /// volumes cannot be concatenated, but the archive is extracted from all volumes
+    /// if dionysos has been built with the `scan_rar` feature. Otherwise, every volume is
+    /// scanned on its own
+    #[cfg(feature = "scan_rar")]
+    fn scan_rar_volumes(
+        &self,
+        scanner: &mut yara::Scanner,
+        file: &Path,
+        volumes: &[PathBuf],
+        locked: &mut Vec<EncryptedContainerFinding>,
+    ) -> anyhow::Result<Vec<YaraFinding>> {
+        if !Self::is_rar_volume(file)? {
+            return self.scan_file(scanner, file);
+        }
+        let archive_name = path_to_string(file);
+        log::info!(
+            "extracting the {} volumes of '{}'",
+            volumes.len(),
+            archive_name
+        );
+        let candidates = std::iter::once("").chain(self.archive_passwords.iter().map(|p| &p[..]));
+        for password in candidates {
+            if let Some(results) = self.scan_rar_with_password(scanner, file, password)? {
+                return Ok(results);
+            }
+        }
+        log::warn!("unable to decrypt '{}'", archive_name);
+        locked.push(EncryptedContainerFinding::new(
+            archive_name,
+            None,
+            self.archive_passwords.len(),
+        ));
+        Ok(Vec::new())
+    }
+
+    #[cfg(not(feature = "scan_rar"))]
+    fn scan_rar_volumes(
+        &self,
+        scanner: &mut yara::Scanner,
+        file: &Path,
+        _volumes: &[PathBuf],
+        _locked: &mut Vec<EncryptedContainerFinding>,
+    ) -> anyhow::Result<Vec<YaraFinding>> {
+        self.scan_file(scanner, file)
+    }
+
+    /// scans the files of the RAR archive whose first volume is `file`, or returns `None`
+    /// if the archive is encrypted and `password` is wrong
+    #[cfg(feature = "scan_rar")]
+    fn scan_rar_with_password(
+        &self,
+        scanner: &mut yara::Scanner,
+        file: &Path,
+        password: &str,
+    ) -> anyhow::Result<Option<Vec<YaraFinding>>> {
+        use unrar::error::Code;
+
+        // like with 7z, a wrong password may only be detected by a failing decompression
+        let is_wrong_password = |why: &unrar::error::UnrarError| match why.code {
+            Code::MissingPassword | Code::BadPassword => true,
+            _ => !password.is_empty(),
+        };
+        let archive = if password.is_empty() {
+            unrar::Archive::new(file)
+        } else {
+            unrar::Archive::with_password(file, password)
+        };
+        let mut archive = match archive.open_for_processing() {
+            Ok(archive) => archive,
+            Err(why) if is_wrong_password(&why) => return Ok(None),
+            Err(why) => return Err(anyhow!("unable to open RAR archive: {}", why)),
+        };
+
+        let archive_name = path_to_string(file);
+        let limit = 1024 * 1024 * self.buffer_size as u64;
+        let mut results = Vec::new();
+        loop {
+            self.check_cancelled()?;
+            let header = match archive.read_header() {
+                Ok(Some(header)) => header,
+                Ok(None) => break,
+                Err(why) if is_wrong_password(&why) => return Ok(None),
+                Err(why) => return Err(anyhow!("unable to read RAR archive: {}", why)),
+            };
+            let entry = header.entry();
+            let filename = entry.filename.to_string_lossy().to_string();
+            let size = entry.unpacked_size as u64;
+            if !entry.is_file() || size == 0 || size > limit {
+                if size > limit {
+                    log::warn!("'{archive_name}:{filename}' is too large to be scanned");
+                }
+                archive = header
+                    .skip()
+                    .map_err(|why| anyhow!("unable to read RAR archive: {}", why))?;
+                continue;
+            }
+            let (data, rest) = match header.read() {
+                Ok(read) => read,
+                Err(why) if is_wrong_password(&why) => return Ok(None),
+                Err(why) => return Err(anyhow!("unable to read RAR archive: {}", why)),
+            };
+            archive = rest;
+
+            let display_name = format!("{archive_name}:{filename}");
+            scanner.define_variable("filename", &filename[..])?;
+            let res = self.scan_compressed(scanner, &data[..], &display_name)?;
+            results.extend(res.into_iter().map(|r| r.with_contained_file(&filename)));
+        }
+        Ok(Some(results))
+    }
+
+    /// scans the files of a 7z archive, which is tried to be opened without a password
+    /// first, and then with each of the archive passwords
+    fn scan_7z_archive(
+        &self,
+        scanner: &mut yara::Scanner,
+        volumes: &[PathBuf],
+        archive_name: &str,
+        locked: &mut Vec<EncryptedContainerFinding>,
+    ) -> anyhow::Result<Vec<YaraFinding>> {
+        let candidates = std::iter::once("").chain(self.archive_passwords.iter().map(|p| &p[..]));
+        for password in candidates {
+            if let Some(results) =
+                self.scan_7z_with_password(scanner, volumes, archive_name, password)?
+            {
+                return Ok(results);
+            }
+        }
+        log::warn!("unable to decrypt '{}'", archive_name);
+        locked.push(EncryptedContainerFinding::new(
+            archive_name.to_owned(),
+            None,
+            self.archive_passwords.len(),
+        ));
+        Ok(Vec::new())
+    }
+
+    /// scans the files of a 7z archive, which may be split into `volumes`, or returns
+    /// `None` if the archive is encrypted and `password` is wrong
+    fn scan_7z_with_password(
+        &self,
+        scanner: &mut yara::Scanner,
+        volumes: &[PathBuf],
+        archive_name: &str,
+        password: &str,
+    ) -> anyhow::Result<Option<Vec<YaraFinding>>> {
+        // a wrong password is not detected as such, but causes the decompression to fail
+        let is_wrong_password = |why: &sevenz_rust::Error| match why {
+            sevenz_rust::Error::PasswordRequired | sevenz_rust::Error::MaybeBadPassword(_) => true,
+            _ => !password.is_empty(),
+        };
+
+        let reader = VolumeReader::open(volumes)?;
+        let len = reader.len();
+        let mut archive = match sevenz_rust::SevenZReader::new(reader, len, password.into()) {
+            Ok(archive) => archive,
+            Err(why) if is_wrong_password(&why) => return Ok(None),
+            Err(why) => return Err(anyhow!("unable to open 7z archive: {}", why)),
+        };
+
+        let mut results = Vec::new();
+        let mut read_error = None;
+        let mut scan_error = None;
+        let outcome = archive.for_each_entries(|entry, reader| {
+            if entry.is_directory() || !entry.has_stream() {
+                return Ok(true);
+            }
+            let filename = entry.name().to_owned();
+            let display_name = format!("{archive_name}:{filename}");
+            let (bytes, buffer) = match self.read_into_buffer(reader) {
+                Ok(decompressed) => decompressed,
+                Err(why) => {
+                    read_error = Some(why);
+                    return Ok(false);
+                }
+            };
+            let res = self
+                .check_cancelled()
+                .and_then(|_| Ok(scanner.define_variable("filename", &filename[..])?))
+                .and_then(|_| self.scan_decompressed(scanner, bytes, buffer, &display_name));
+            match res {
+                Ok(res) => {
+                    results.extend(res.into_iter().map(|r| r.with_contained_file(&filename)));
+                    Ok(true)
+                }
+                Err(why) => {
+                    scan_error = Some(why);
+                    Ok(false)
+                }
+            }
+        });
+
+        if let Some(why) = scan_error {
+            return Err(why);
+        }
+        match (outcome, read_error) {
+            (Err(why), _) if is_wrong_password(&why) => Ok(None),
+            (Err(why), _) => Err(anyhow!("unable to read 7z archive: {}", why)),
+            (Ok(()), Some(_)) if !password.is_empty() => Ok(None),
+            (Ok(()), Some(why)) => Err(anyhow!("unable to read 7z archive: {}", why)),
+            (Ok(()), None) => Ok(Some(results)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use walkdir::WalkDir;
+
+    use super::YaraScanner;
+    use crate::filescanner::FileScanner;
+    use crate::scan_job::{ScanCancelled, ScanHandle};
+    use crate::string_options::StringOptions;
+    use crate::yara::YaraRuleset;
+
+    fn scan_with_module(module: &str, sample: &str) -> Vec<String> {
+        let rules = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test/yara/modules")
+            .join(format!("{}.yar", module));
+        let sample = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test/data").join(sample);
+
+        let scanner = match YaraScanner::new(&YaraRuleset::new(&rules)) {
+            Ok(scanner) => scanner,
+            Err(why) => panic!("unable to compile rules using the '{}' module: {}", module, why),
+        };
+        let entry = WalkDir::new(&sample).into_iter().next().unwrap().unwrap();
+        scanner
+            .scan_file(&entry)
+            .into_iter()
+            .map(|r| r.unwrap().format_readable(&StringOptions::default()))
+            .collect()
+    }
+
+    #[test]
+    fn test_hash_module() {
+        let results = scan_with_module("hash", "sample1.txt");
+        assert_eq!(results.len(), 1);
+        assert!(results[0].starts_with("Yara: sample1_md5 "));
+        assert!(scan_with_module("hash", "sample2.txt").is_empty());
+    }
+
+    #[test]
+    fn test_math_module() {
+        let results = scan_with_module("math", "sample1.txt");
+        assert_eq!(results.len(), 1);
+        assert!(results[0].starts_with("Yara: low_entropy "));
+    }
+
+    #[test]
+    fn test_pe_module() {
+        assert!(scan_with_module("pe", "sample1.txt").is_empty());
+    }
+
+    #[test]
+    fn test_elf_module() {
+        assert!(scan_with_module("elf", "sample1.txt").is_empty());
+    }
+
+    #[test]
+    fn test_dotnet_module() {
+        assert!(scan_with_module("dotnet", "sample1.txt").is_empty());
+    }
+
+    #[test]
+    fn test_cancelled_scan() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let sample = std::fs::read(dir.join("test/data/sample1.txt")).unwrap();
+        let rules = YaraRuleset::new(dir.join("test/yara/modules/hash.yar"));
+        let handle = ScanHandle::default();
+        let scanner = YaraScanner::new(&rules)
+            .unwrap()
+            .with_scan_handle(handle.clone());
+        assert!(scanner.scan_buffer("sample1.txt", &sample)[0].is_ok());
+
+        // libyara is aborted by the callback, because the buffer is not checked before
+        handle.cancel();
+        let results = scanner.scan_buffer("sample1.txt", &sample);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].as_ref().err().unwrap().is::<ScanCancelled>());
+    }
+
+    #[test]
+    fn test_encrypted_zip() {
+        use std::io::Write;
+
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let sample = std::fs::read(dir.join("test/data/sample1.txt")).unwrap();
+        let archive =
+            std::env::temp_dir().join(format!("dionysos-encrypted-{}.zip", std::process::id()));
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        for (name, password) in [("sample1.txt", "infected"), ("locked.txt", "unknown")] {
+            let options = zip::write::SimpleFileOptions::default()
+                .with_deprecated_encryption(password.as_bytes());
+            zip.start_file(name, options).unwrap();
+            zip.write_all(&sample).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let rules = dir.join("test/yara/modules/hash.yar");
+        let scanner = YaraScanner::new(&YaraRuleset::new(&rules))
+            .unwrap()
+            .with_scan_compressed(true);
+        let entry = WalkDir::new(&archive).into_iter().next().unwrap().unwrap();
+        let results: Vec<String> = scanner
+            .scan_file(&entry)
+            .into_iter()
+            .map(|r| r.unwrap().format_readable(&StringOptions::default()))
+            .collect();
+        std::fs::remove_file(&archive).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().any(|r| r.starts_with("Yara: sample1_md5 ")));
+        assert!(results
+            .iter()
+            .any(|r| r.starts_with("Yara: encrypted container could not be opened")));
+    }
+
+    #[test]
+    fn test_wrong_zip_password() {
+        use std::io::Write;
+
+        // a stored file which does not fit into the buffer
+        let data = vec![b'A'; 2 * 1024 * 1024];
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
+            .with_deprecated_encryption(b"correct");
+        zip.start_file("stored.bin", options).unwrap();
+        zip.write_all(&data).unwrap();
+        let mut zip = zip::ZipArchive::new(zip.finish().unwrap()).unwrap();
+
+        // a wrong password which passes the check byte of ZipCrypto
+        let wrong_password = (0..)
+            .map(|i| format!("wrong{}", i))
+            .find(|password| zip.by_index_decrypt(0, password.as_bytes()).is_ok())
+            .unwrap();
+
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let rules = YaraRuleset::new(dir.join("test/yara/modules/hash.yar"));
+        let scanner = YaraScanner::new(&rules).unwrap().with_buffer_size(1);
+        let scanner = scanner.with_archive_passwords(vec![wrong_password]);
+        let decrypted = scanner.decrypt_zip_file(&mut zip, 0, &mut None).unwrap();
+        assert!(decrypted.is_none());
+
+        let scanner = scanner.with_archive_passwords(vec!["correct".to_owned()]);
+        let decrypted = scanner.decrypt_zip_file(&mut zip, 0, &mut None).unwrap();
+        let (bytes, buffer) = decrypted.unwrap();
+        assert_eq!(bytes, 1024 * 1024);
+        assert!(buffer.iter().all(|b| *b == b'A'));
+    }
 }
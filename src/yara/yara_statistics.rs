@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::yara_finding::YaraFinding;
+
+#[derive(Default)]
+struct RuleStatistics {
+    matched_files: usize,
+    total_matches: usize,
+    scan_time: Duration,
+}
+
+/// collects per-rule statistics over all scanned files.
+///
+/// libyara only measures the time needed to scan a file with the whole ruleset,
+/// so the scan time of a file is attributed to every rule which matched that file.
+#[derive(Default)]
+pub(crate) struct RuleStatisticsCollector {
+    rules: Mutex<HashMap<String, RuleStatistics>>,
+}
+
+impl RuleStatisticsCollector {
+    pub fn add_findings(&self, findings: &[YaraFinding], scan_time: Duration) {
+        let mut rules = self.rules.lock().unwrap();
+
+        // a rule can match more than once per file, e.g. in evtx records
+        let mut counted = Vec::new();
+        for finding in findings.iter() {
+            let stats = rules.entry(finding.identifier.clone()).or_default();
+            stats.total_matches += finding
+                .strings
+                .iter()
+                .map(|s| s.matches.len())
+                .sum::<usize>()
+                .max(1);
+
+            if !counted.contains(&&finding.identifier) {
+                counted.push(&finding.identifier);
+                stats.matched_files += 1;
+                stats.scan_time += scan_time;
+            }
+        }
+    }
+
+    pub fn summary(&self) -> Vec<String> {
+        let rules = self.rules.lock().unwrap();
+        if rules.is_empty() {
+            return Vec::new();
+        }
+
+        let mut rules: Vec<_> = rules.iter().collect();
+        rules.sort_by(|a, b| {
+            b.1.matched_files
+                .cmp(&a.1.matched_files)
+                .then_with(|| a.0.cmp(b.0))
+        });
+
+        let mut lines = vec![format!(
+            "{:<40} {:>10} {:>10} {:>14}",
+            "yara rule", "files", "matches", "avg. time (s)"
+        )];
+        lines.extend(rules.into_iter().map(|(name, stats)| {
+            format!(
+                "{:<40} {:>10} {:>10} {:>14.3}",
+                name,
+                stats.matched_files,
+                stats.total_matches,
+                stats.scan_time.as_secs_f64() / stats.matched_files as f64
+            )
+        }));
+        lines
+    }
+}
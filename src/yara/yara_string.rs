@@ -1,16 +1,82 @@
+use std::io::{Read, Seek, SeekFrom};
+
 use yara::{Match, YrString};
 
 
 pub struct YaraString {
     pub identifier: String,
-    pub matches: Vec<Match>,
+    pub matches: Vec<YaraMatch>,
+}
+
+/// a single match of a yara string, independent of the yara engine being used
+pub struct YaraMatch {
+    pub offset: usize,
+    pub data: Vec<u8>,
+    pub context: Option<YaraMatchContext>,
+}
+
+/// bytes surrounding a match, starting at `offset` in the scanned data
+pub struct YaraMatchContext {
+    pub offset: usize,
+    pub data: Vec<u8>,
+}
+
+impl YaraMatchContext {
+    pub fn from_slice(data: &[u8], m: &YaraMatch, context: usize) -> Option<Self> {
+        if m.offset > data.len() {
+            return None;
+        }
+        let begin = m.offset.saturating_sub(context);
+        let end = data.len().min(m.offset + m.data.len() + context);
+        Some(Self {
+            offset: begin,
+            data: data[begin..end].to_vec()
+        })
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: &mut R, m: &YaraMatch, context: usize) -> std::io::Result<Self> {
+        let begin = m.offset.saturating_sub(context);
+        let length = m.offset - begin + m.data.len() + context;
+        reader.seek(SeekFrom::Start(begin as u64))?;
+
+        let mut data = Vec::with_capacity(length);
+        reader.take(length as u64).read_to_end(&mut data)?;
+        Ok(Self {
+            offset: begin,
+            data
+        })
+    }
 }
 
 impl From<YrString<'_>> for YaraString {
     fn from(s: YrString<'_>) -> Self {
         Self {
             identifier: s.identifier.to_owned(),
-            matches: s.matches
+            matches: s.matches.into_iter().map(|m| m.into()).collect()
         }
     }
-}
\ No newline at end of file
+}
+
+impl From<Match> for YaraMatch {
+    fn from(m: Match) -> Self {
+        Self {
+            offset: m.offset,
+            data: m.data,
+            context: None
+        }
+    }
+}
+
+#[cfg(feature = "yara_x")]
+impl From<yara_x::Pattern<'_, '_>> for YaraString {
+    fn from(p: yara_x::Pattern<'_, '_>) -> Self {
+        Self {
+            identifier: p.identifier().to_owned(),
+            matches: p.matches().map(|m| YaraMatch {
+                offset: m.range().start,
+                data: m.data().to_vec(),
+                context: None
+            }).collect()
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use memmap::MmapOptions;
+use walkdir::DirEntry;
+
+use crate::filescanner::*;
+use crate::path_string::path_to_string;
+use crate::scan_job::{ScanCancelled, ScanHandle};
+use crate::scanner_result::*;
+use crate::yara::yara_finding::YaraFinding;
+
+use super::yara_externals::{magic_of, YaraExternals};
+use super::yara_statistics::RuleStatisticsCollector;
+use super::yara_ruleset::YaraRuleset;
+
+/// yara scanner which uses the pure-rust YARA-X engine instead of libyara.
+/// Decompression, evtx, registry and ESE scanning are not supported by this
+/// engine; files are always scanned as they are. A cancelled scan ends before
+/// the next file, because YARA-X cannot abort a running scan
+pub struct YaraXScanner {
+    rules: RwLock<Arc<yara_x::Rules>>,
+    ruleset: YaraRuleset,
+
+    /// the rules use externals like `filename`, so that their matches depend on the path
+    path_externals: AtomicBool,
+    timeout: u16,
+    timed_out_files: Mutex<Vec<String>>,
+    statistics: Option<RuleStatisticsCollector>,
+
+    /// number of bytes before and after every match which are included in the findings
+    string_context: usize,
+    handle: Option<ScanHandle>,
+}
+
+impl Display for YaraXScanner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "YaraXScanner")
+    }
+}
+
+impl FileScanner for YaraXScanner {
+    fn scan_file(&self, file: &DirEntry) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let begin = Instant::now();
+        let result = self.scan_path(file.path());
+        self.results(&path_to_string(file.path()), begin, result)
+    }
+
+    fn scan_buffer(&self, name: &str, data: &[u8]) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        let begin = Instant::now();
+        let externals = YaraExternals::dummy()
+            .with_filename(name.to_owned())
+            .with_filepath(name.to_owned());
+        let result = self.scan(name, &externals, data);
+        self.results(name, begin, result)
+    }
+
+    fn depends_on_path(&self) -> bool {
+        self.path_externals.load(Ordering::Relaxed)
+    }
+
+    fn reload(&self) -> Result<()> {
+        let rules = Self::compile(&self.ruleset)?;
+        *self.rules.write().unwrap() = Arc::new(rules);
+        self.path_externals
+            .store(self.ruleset.uses_path_externals(), Ordering::Relaxed);
+        log::info!("reloaded yara rules from '{}'", self.ruleset.path().display());
+        Ok(())
+    }
+
+    fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(statistics) = &self.statistics {
+            lines.extend(statistics.summary());
+        }
+
+        let timed_out_files = self.timed_out_files.lock().unwrap();
+        if !timed_out_files.is_empty() {
+            lines.push(format!(
+                "yara scan incomplete for {} file(s) because of the timeout of {}s:",
+                timed_out_files.len(),
+                self.timeout
+            ));
+            lines.extend(timed_out_files.iter().map(|f| format!("  {}", f)));
+        }
+        lines
+    }
+}
+
+impl YaraXScanner {
+    pub fn new(ruleset: &YaraRuleset) -> Result<Self> {
+        Ok(Self {
+            rules: RwLock::new(Arc::new(Self::compile(ruleset)?)),
+            ruleset: ruleset.clone(),
+            path_externals: AtomicBool::new(ruleset.uses_path_externals()),
+            timeout: 240,
+            timed_out_files: Mutex::new(Vec::new()),
+            statistics: None,
+            string_context: 0,
+            handle: None,
+        })
+    }
+
+    fn compile(ruleset: &YaraRuleset) -> Result<yara_x::Rules> {
+        let mut compiler = yara_x::Compiler::new();
+        for (identifier, value) in YaraExternals::dummy().to_hashmap() {
+            compiler.define_global(identifier, value)?;
+        }
+
+        for (source, rule) in ruleset.read_sources()?.iter() {
+            let code = yara_x::SourceCode::from(rule.as_str()).with_origin(source.as_str());
+            compiler.add_source(code)?;
+        }
+
+        let warnings = compiler.warnings();
+        for warning in warnings.iter() {
+            log::warn!("{}", warning);
+        }
+        if ruleset.strict() && !warnings.is_empty() {
+            return Err(anyhow!(
+                "compilation of the yara ruleset produced {} warning(s):\n{}",
+                warnings.len(),
+                warnings
+                    .iter()
+                    .map(|w| format!("  {}", w))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ));
+        }
+
+        Ok(compiler.build())
+    }
+
+    pub fn with_timeout(mut self, timeout: u16) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// collect per-rule statistics, which are displayed in the summary
+    pub fn with_rule_statistics(mut self, rule_statistics: bool) -> Self {
+        self.statistics = rule_statistics.then(RuleStatisticsCollector::default);
+        self
+    }
+
+    /// include up to `string_context` bytes before and after every match
+    pub fn with_string_context(mut self, string_context: usize) -> Self {
+        self.string_context = string_context;
+        self
+    }
+
+    /// stop scanning when the scan of `handle` is cancelled
+    pub fn with_scan_handle(mut self, handle: ScanHandle) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// converts the result of a scan of `name`, and records timeouts and statistics
+    fn results(
+        &self,
+        name: &str,
+        begin: Instant,
+        result: Result<Vec<YaraFinding>>,
+    ) -> Vec<anyhow::Result<Box<dyn ScannerFinding>>> {
+        match result {
+            Ok(findings) => {
+                if let Some(statistics) = &self.statistics {
+                    statistics.add_findings(&findings, Instant::now().duration_since(begin));
+                }
+                findings
+                    .into_iter()
+                    .map(|f| Ok(Box::new(f) as Box<dyn ScannerFinding>))
+                    .collect()
+            }
+            Err(why) if why.is::<ScanCancelled>() => vec![Err(why)],
+            Err(why) => match why.downcast_ref::<yara_x::ScanError>() {
+                Some(yara_x::ScanError::Timeout) => {
+                    log::warn!(
+                        "yara scan of '{}' has been aborted after {}s",
+                        name,
+                        self.timeout
+                    );
+                    self.timed_out_files.lock().unwrap().push(name.to_owned());
+                    vec![Err(anyhow!(
+                        "the yara scan of '{}' has been aborted after {}s (--yara-timeout)",
+                        name,
+                        self.timeout
+                    ))]
+                }
+                _ => vec![Err(anyhow!("yara scan error with '{}': {}", name, why))],
+            },
+        }
+    }
+
+    fn scan_path(&self, file: &Path) -> Result<Vec<YaraFinding>> {
+        let filename = path_to_string(file);
+        let magic = magic_of(file);
+        let externals = YaraExternals::of_file(file, filename.clone(), magic.as_deref())?;
+
+        let file_handle = File::open(file)?;
+        let mmap;
+        let data: &[u8] = if file_handle.metadata()?.len() == 0 {
+            &[]
+        } else {
+            mmap = unsafe { MmapOptions::new().map(&file_handle)? };
+            &mmap
+        };
+        self.scan(&filename, &externals, data)
+    }
+
+    /// scans `data` with the current rules, using `externals` as values of the globals
+    fn scan(
+        &self,
+        found_in_file: &str,
+        externals: &YaraExternals,
+        data: &[u8],
+    ) -> Result<Vec<YaraFinding>> {
+        if let Some(handle) = &self.handle {
+            handle.check()?;
+        }
+
+        // keep a reference to the current rules, so that a reload does not
+        // affect this scan
+        let rules = Arc::clone(&self.rules.read().unwrap());
+        let mut scanner = yara_x::Scanner::new(&rules);
+        scanner.set_timeout(Duration::from_secs(self.timeout.into()));
+        for (identifier, value) in externals.to_hashmap() {
+            scanner.set_global(identifier, value)?;
+        }
+
+        let results = scanner.scan(data)?;
+        Ok(results
+            .matching_rules()
+            .map(|r| {
+                YaraFinding::from_yara_x(r, found_in_file.to_owned())
+                    .with_context_from_slice(data, self.string_context)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::YaraXScanner;
+    use crate::filescanner::FileScanner;
+    use crate::scan_job::{ScanCancelled, ScanHandle};
+    use crate::string_options::StringOptions;
+    use crate::yara::YaraRuleset;
+
+    #[test]
+    fn test_scan_buffer() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let sample = std::fs::read(dir.join("test/data/sample1.txt")).unwrap();
+        let rules = YaraRuleset::new(dir.join("test/yara/modules/hash.yar"));
+        let scanner = YaraXScanner::new(&rules).unwrap();
+
+        let results = scanner.scan_buffer("sample1.txt", &sample);
+        assert_eq!(results.len(), 1);
+        let result = results[0].as_ref().unwrap();
+        assert!(result
+            .format_readable(&StringOptions::default())
+            .starts_with("Yara: sample1_md5 "));
+        assert!(scanner.scan_buffer("empty", b"").is_empty());
+    }
+
+    #[test]
+    fn test_cancelled_scan() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let sample = std::fs::read(dir.join("test/data/sample1.txt")).unwrap();
+        let rules = YaraRuleset::new(dir.join("test/yara/modules/hash.yar"));
+        let handle = ScanHandle::default();
+        let scanner = YaraXScanner::new(&rules)
+            .unwrap()
+            .with_scan_handle(handle.clone());
+        assert!(scanner.scan_buffer("sample1.txt", &sample)[0].is_ok());
+
+        handle.cancel();
+        let results = scanner.scan_buffer("sample1.txt", &sample);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].as_ref().err().unwrap().is::<ScanCancelled>());
+    }
+}